@@ -0,0 +1,32 @@
+// Build metadata for `GET /api/system/info`: git commit, rustc version and
+// target. Every value falls back to "unknown" (e.g. Docker builds without .git).
+
+use std::process::Command;
+
+fn output(cmd: &str, args: &[&str]) -> Option<String> {
+    let out = Command::new(cmd).args(args).output().ok()?;
+    out.status
+        .success()
+        .then(|| String::from_utf8_lossy(&out.stdout).trim().to_string())
+        .filter(|s| !s.is_empty())
+}
+
+fn main() {
+    let git_hash = std::env::var("CH_GIT_HASH")
+        .ok()
+        .or_else(|| output("git", &["rev-parse", "--short=12", "HEAD"]))
+        .unwrap_or_else(|| "unknown".to_string());
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let rustc_version = output(&rustc, &["--version"]).unwrap_or_else(|| "unknown".to_string());
+    let target = std::env::var("TARGET").unwrap_or_else(|_| "unknown".to_string());
+
+    println!("cargo:rustc-env=CH_GIT_HASH={}", git_hash);
+    println!("cargo:rustc-env=CH_RUSTC_VERSION={}", rustc_version);
+    println!("cargo:rustc-env=CH_BUILD_TARGET={}", target);
+    println!("cargo:rerun-if-env-changed=CH_GIT_HASH");
+    for path in ["../.git/HEAD", "../.git/index"] {
+        if std::path::Path::new(path).exists() {
+            println!("cargo:rerun-if-changed={}", path);
+        }
+    }
+}
@@ -0,0 +1,21 @@
+-- ClaudeHydra — Durable per-request usage events
+-- Migration 040: ch_usage_events (one row per completed provider call)
+-- Used by GET /api/usage/export for invoice reconciliation.
+
+CREATE TABLE IF NOT EXISTS ch_usage_events (
+    id             BIGSERIAL PRIMARY KEY,
+    created_at     TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    model          TEXT NOT NULL,
+    input_tokens   INTEGER NOT NULL DEFAULT 0,
+    output_tokens  INTEGER NOT NULL DEFAULT 0,
+    total_tokens   INTEGER NOT NULL DEFAULT 0,
+    cost_usd       DOUBLE PRECISION NOT NULL DEFAULT 0,
+    estimated      BOOLEAN NOT NULL DEFAULT FALSE,
+    source         TEXT NOT NULL,
+    session_id     UUID REFERENCES ch_sessions(id) ON DELETE SET NULL,
+    agent_id       TEXT,
+    user_id        TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_ch_usage_events_created ON ch_usage_events (created_at);
+CREATE INDEX IF NOT EXISTS idx_ch_usage_events_session ON ch_usage_events (session_id);
@@ -0,0 +1,18 @@
+-- ClaudeHydra — Configurable model list prices
+-- Migration 041: ch_model_prices used by GET /api/usage/reconciliation.
+-- `model_pattern` is matched as a substring of the model id; the longest
+-- matching pattern wins (e.g. 'claude-3-5-haiku' beats 'haiku').
+
+CREATE TABLE IF NOT EXISTS ch_model_prices (
+    model_pattern          TEXT PRIMARY KEY,
+    input_usd_per_mtok     DOUBLE PRECISION NOT NULL,
+    output_usd_per_mtok    DOUBLE PRECISION NOT NULL,
+    updated_at             TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+INSERT INTO ch_model_prices (model_pattern, input_usd_per_mtok, output_usd_per_mtok)
+VALUES
+    ('opus',   15.0, 75.0),
+    ('sonnet',  3.0, 15.0),
+    ('haiku',   0.25, 1.25)
+ON CONFLICT (model_pattern) DO NOTHING;
@@ -0,0 +1,16 @@
+-- ClaudeHydra — Usage anomaly log
+-- Migration 042: ch_usage_anomalies, written by the background usage anomaly
+-- detector. (kind, subject, window_day) is unique so a condition that keeps
+-- holding is raised once per day rather than on every check.
+
+CREATE TABLE IF NOT EXISTS ch_usage_anomalies (
+    id           BIGSERIAL PRIMARY KEY,
+    kind         TEXT NOT NULL,
+    subject      TEXT NOT NULL,
+    window_day   DATE NOT NULL,
+    details      JSONB NOT NULL DEFAULT '{}'::jsonb,
+    created_at   TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (kind, subject, window_day)
+);
+
+CREATE INDEX IF NOT EXISTS idx_ch_usage_anomalies_created ON ch_usage_anomalies (created_at DESC);
@@ -0,0 +1,16 @@
+-- ClaudeHydra — Uploaded files
+-- Migration 043: ch_uploads, metadata for files streamed to disk by
+-- POST /api/uploads. The bytes live at storage_path (under CH_UPLOAD_DIR);
+-- sha256 is computed while writing and verified against the client's checksum.
+
+CREATE TABLE IF NOT EXISTS ch_uploads (
+    id            UUID PRIMARY KEY,
+    filename      TEXT NOT NULL,
+    mime_type     TEXT NOT NULL DEFAULT 'application/octet-stream',
+    size_bytes    BIGINT NOT NULL,
+    sha256        TEXT NOT NULL,
+    storage_path  TEXT NOT NULL,
+    created_at    TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_ch_uploads_created ON ch_uploads (created_at DESC);
@@ -0,0 +1,7 @@
+-- ClaudeHydra — Per-agent stop sequences
+-- Migration 044: stop sequences an agent always generates with (e.g. the
+-- `###END###` marker the orchestrator splits on). Merged with the caller's
+-- own stop sequences by the request builder.
+
+ALTER TABLE ch_agents_config
+    ADD COLUMN IF NOT EXISTS stop_sequences TEXT[] NOT NULL DEFAULT '{}';
@@ -0,0 +1,16 @@
+-- ClaudeHydra — Session snapshots (restore points)
+-- Migration 045: ch_session_snapshots keeps a JSONB copy of a session's
+-- messages (with their tool interactions) so the session can be rolled back
+-- via POST /api/sessions/{id}/snapshots/{sid}/restore.
+
+CREATE TABLE IF NOT EXISTS ch_session_snapshots (
+    id             UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    session_id     UUID NOT NULL REFERENCES ch_sessions(id) ON DELETE CASCADE,
+    label          TEXT NOT NULL,
+    message_count  INT NOT NULL DEFAULT 0,
+    messages       JSONB NOT NULL DEFAULT '[]'::jsonb,
+    created_at     TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_ch_session_snapshots_session
+    ON ch_session_snapshots (session_id, created_at DESC);
@@ -0,0 +1,20 @@
+-- ClaudeHydra — Team users (OIDC sign-in)
+-- Migration 046: ch_users records everyone who signed in through the OIDC
+-- provider, keyed by (issuer, subject). The role is re-derived from the
+-- identity claims on every sign-in; `disabled` blocks a user regardless.
+
+CREATE TABLE IF NOT EXISTS ch_users (
+    id             UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    issuer         TEXT NOT NULL,
+    subject        TEXT NOT NULL,
+    email          TEXT,
+    name           TEXT,
+    role           TEXT NOT NULL DEFAULT 'member'
+                   CHECK (role IN ('admin', 'member', 'viewer')),
+    disabled       BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at     TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_login_at  TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (issuer, subject)
+);
+
+CREATE INDEX IF NOT EXISTS idx_ch_users_email ON ch_users (LOWER(email));
@@ -0,0 +1,19 @@
+-- ClaudeHydra — Scoped API tokens
+-- Migration 047: ch_api_tokens holds named credentials for scripts and CI.
+-- Only the SHA-256 of a token is stored; `prefix` is kept for display.
+-- Revoked tokens stay in the table for the audit trail.
+
+CREATE TABLE IF NOT EXISTS ch_api_tokens (
+    id            UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    name          TEXT NOT NULL,
+    token_hash    TEXT NOT NULL UNIQUE,
+    prefix        TEXT NOT NULL,
+    scopes        TEXT[] NOT NULL,
+    created_by    TEXT,
+    created_at    TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at    TIMESTAMPTZ,
+    last_used_at  TIMESTAMPTZ,
+    revoked_at    TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_ch_api_tokens_created ON ch_api_tokens (created_at DESC);
@@ -0,0 +1,6 @@
+-- ClaudeHydra — Message author attribution for shared sessions
+-- Migration 048: who appended a message (web session subject or a display
+-- name) when several people work in one session. NULL for older messages and
+-- model replies.
+
+ALTER TABLE ch_messages ADD COLUMN IF NOT EXISTS author TEXT;
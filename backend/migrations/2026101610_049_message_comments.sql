@@ -0,0 +1,19 @@
+-- ClaudeHydra — Comment threads on messages
+-- Migration 049: review comments attached to individual messages. They are
+-- kept out of ch_messages so they are never sent to the model. Replies point
+-- at their parent and are deleted with it.
+
+CREATE TABLE IF NOT EXISTS ch_message_comments (
+    id          UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    session_id  UUID NOT NULL REFERENCES ch_sessions(id) ON DELETE CASCADE,
+    message_id  UUID NOT NULL REFERENCES ch_messages(id) ON DELETE CASCADE,
+    parent_id   UUID REFERENCES ch_message_comments(id) ON DELETE CASCADE,
+    author      TEXT,
+    body        TEXT NOT NULL,
+    resolved    BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at  TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_ch_message_comments_message
+    ON ch_message_comments (message_id, created_at);
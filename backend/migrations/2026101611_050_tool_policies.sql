@@ -0,0 +1,10 @@
+-- ClaudeHydra — Tool confirmation policies
+-- Migration 050: per-tool "require confirmation" flag. Calls to a flagged tool
+-- pause the agent loop until approved via POST /api/tool-calls/{id}/approve.
+-- Tools without a row run without asking.
+
+CREATE TABLE IF NOT EXISTS ch_tool_policies (
+    tool_name             TEXT PRIMARY KEY,
+    require_confirmation  BOOLEAN NOT NULL DEFAULT FALSE,
+    updated_at            TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,28 @@
+-- ClaudeHydra — Agent-loop transcripts
+-- Migration 051: one row per tool-enabled run with the resolved context
+-- (model, params, system prompt, initial messages), plus its steps in order:
+-- upstream requests and tool calls with their inputs and outputs.
+-- Replays reference the transcript they re-ran via replay_of.
+
+CREATE TABLE IF NOT EXISTS ch_transcripts (
+    id           UUID PRIMARY KEY,
+    session_id   UUID REFERENCES ch_sessions(id) ON DELETE SET NULL,
+    source       TEXT NOT NULL,
+    replay_of    UUID REFERENCES ch_transcripts(id) ON DELETE SET NULL,
+    model        TEXT NOT NULL,
+    context      JSONB NOT NULL,
+    started_at   TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    finished_at  TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_ch_transcripts_started ON ch_transcripts (started_at DESC);
+CREATE INDEX IF NOT EXISTS idx_ch_transcripts_session ON ch_transcripts (session_id, started_at DESC);
+
+CREATE TABLE IF NOT EXISTS ch_transcript_steps (
+    transcript_id  UUID NOT NULL REFERENCES ch_transcripts(id) ON DELETE CASCADE,
+    seq            INTEGER NOT NULL,
+    kind           TEXT NOT NULL,
+    data           JSONB NOT NULL,
+    created_at     TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (transcript_id, seq)
+);
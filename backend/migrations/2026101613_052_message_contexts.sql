@@ -0,0 +1,11 @@
+-- ClaudeHydra — Generation context of assistant messages
+-- Migration 052: the upstream request an assistant message was generated
+-- from (model, params, system prompt, history as sent, tool names), captured
+-- at generation time. Attachment payloads are stored as size + SHA-256 only.
+
+CREATE TABLE IF NOT EXISTS ch_message_contexts (
+    message_id     UUID PRIMARY KEY REFERENCES ch_messages(id) ON DELETE CASCADE,
+    transcript_id  UUID REFERENCES ch_transcripts(id) ON DELETE SET NULL,
+    request        JSONB NOT NULL,
+    created_at     TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,7 @@
+-- ClaudeHydra — API token default priority
+-- Migration 053: priority class (high / normal / low) applied to chat requests
+-- made with the token that don't set their own `priority`.
+
+ALTER TABLE ch_api_tokens
+    ADD COLUMN IF NOT EXISTS default_priority TEXT NOT NULL DEFAULT 'normal'
+        CHECK (default_priority IN ('high', 'normal', 'low'));
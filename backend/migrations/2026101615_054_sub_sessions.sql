@@ -0,0 +1,11 @@
+-- ClaudeHydra — Nested sub-sessions
+-- Migration 054: a session spawned by an orchestration step (e.g. a debate
+-- started from a chat) links to the session that spawned it. `step_id` names
+-- the step inside the parent. Children are deleted with their parent.
+
+ALTER TABLE ch_sessions
+    ADD COLUMN IF NOT EXISTS parent_id UUID REFERENCES ch_sessions(id) ON DELETE CASCADE,
+    ADD COLUMN IF NOT EXISTS step_id TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_ch_sessions_parent
+    ON ch_sessions (parent_id, created_at) WHERE parent_id IS NOT NULL;
@@ -0,0 +1,18 @@
+-- ClaudeHydra — Health history
+-- Migration 055: one row per watchdog probe (Anthropic API, Google API,
+-- database), kept for HEALTH_HISTORY_RETENTION_DAYS. GET /api/health/history
+-- aggregates it into daily uptime per component.
+
+CREATE TABLE IF NOT EXISTS ch_health_checks (
+    id BIGSERIAL PRIMARY KEY,
+    component TEXT NOT NULL,
+    ok BOOLEAN NOT NULL,
+    latency_ms INTEGER,
+    detail TEXT,
+    checked_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_ch_health_checks_component_time
+    ON ch_health_checks (component, checked_at DESC);
+CREATE INDEX IF NOT EXISTS idx_ch_health_checks_time
+    ON ch_health_checks (checked_at);
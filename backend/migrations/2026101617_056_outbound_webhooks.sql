@@ -0,0 +1,19 @@
+-- ClaudeHydra — Outbound webhooks
+-- Migration 056: subscriptions for signed event deliveries (see webhooks.rs).
+-- `secret` is the per-webhook HMAC-SHA256 signing key, returned to the admin
+-- once at creation and never listed again.
+
+CREATE TABLE IF NOT EXISTS ch_outbound_webhooks (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    url TEXT NOT NULL,
+    events TEXT[] NOT NULL,
+    description TEXT,
+    secret TEXT NOT NULL,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_delivery_at TIMESTAMPTZ,
+    last_status TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_ch_outbound_webhooks_events
+    ON ch_outbound_webhooks USING GIN (events) WHERE enabled;
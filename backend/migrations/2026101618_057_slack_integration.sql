@@ -0,0 +1,24 @@
+-- ClaudeHydra — Slack connector
+-- Migration 057: bot credentials (single row) and channel mappings. A channel
+-- can feed a session, be answered by an agent, and follow outbound events
+-- (same names as ch_outbound_webhooks.events).
+
+CREATE TABLE IF NOT EXISTS ch_slack_config (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    bot_token TEXT NOT NULL,
+    signing_secret TEXT,
+    team TEXT,
+    bot_user_id TEXT,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS ch_slack_channels (
+    channel_id TEXT PRIMARY KEY,
+    session_id UUID REFERENCES ch_sessions(id) ON DELETE SET NULL,
+    agent_id TEXT,
+    events TEXT[] NOT NULL DEFAULT '{}',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_ch_slack_channels_session
+    ON ch_slack_channels (session_id) WHERE session_id IS NOT NULL;
@@ -0,0 +1,43 @@
+-- ClaudeHydra — GitHub issue triage
+-- Migration 058: per-repository triage settings and the issues seen through
+-- GitHub webhooks. Issues double as the index for duplicate detection.
+
+CREATE TABLE IF NOT EXISTS ch_github_triage_repos (
+    repo TEXT PRIMARY KEY,
+    -- 'suggest' keeps suggestions for the API, 'post' applies them on GitHub
+    mode TEXT NOT NULL DEFAULT 'suggest' CHECK (mode IN ('suggest', 'post')),
+    agent_id TEXT,
+    -- Labels the agent may pick from (empty = any)
+    labels TEXT[] NOT NULL DEFAULT '{}',
+    webhook_secret TEXT,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS ch_github_issues (
+    id BIGSERIAL PRIMARY KEY,
+    repo TEXT NOT NULL,
+    issue_number BIGINT NOT NULL,
+    title TEXT NOT NULL,
+    body TEXT NOT NULL DEFAULT '',
+    html_url TEXT,
+    author TEXT,
+    -- pending → suggested → posted, or failed
+    status TEXT NOT NULL DEFAULT 'pending',
+    labels TEXT[] NOT NULL DEFAULT '{}',
+    priority TEXT,
+    duplicate_of BIGINT,
+    summary TEXT,
+    agent_id TEXT,
+    error TEXT,
+    search_vector tsvector GENERATED ALWAYS AS (
+        to_tsvector('english', title || ' ' || body)
+    ) STORED,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    triaged_at TIMESTAMPTZ,
+    UNIQUE (repo, issue_number)
+);
+
+CREATE INDEX IF NOT EXISTS idx_ch_github_issues_search ON ch_github_issues USING GIN (search_vector);
+CREATE INDEX IF NOT EXISTS idx_ch_github_issues_repo_created ON ch_github_issues (repo, created_at DESC);
@@ -0,0 +1,12 @@
+-- ClaudeHydra — Encrypted provider API keys
+-- Migration 059: AES-256-GCM ciphertexts of the keys set with
+-- POST /api/settings/api-key. The master key lives outside the database;
+-- key_fingerprint identifies which master key a row was written under.
+
+CREATE TABLE IF NOT EXISTS ch_api_keys (
+    provider TEXT PRIMARY KEY,
+    nonce BYTEA NOT NULL,
+    ciphertext BYTEA NOT NULL,
+    key_fingerprint TEXT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,5 @@
+-- ClaudeHydra — API key timestamps
+-- Migration 060: when a stored provider key was first set (updated_at already
+-- tracks the latest change), for GET /api/settings/api-keys.
+
+ALTER TABLE ch_api_keys ADD COLUMN IF NOT EXISTS created_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
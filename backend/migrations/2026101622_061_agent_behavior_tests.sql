@@ -0,0 +1,25 @@
+-- ClaudeHydra — Agent behavior tests
+-- Migration 061: stored input → expectations cases per agent and the results
+-- of running them (POST /api/agents/{id}/tests/run).
+
+CREATE TABLE IF NOT EXISTS ch_agent_tests (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    agent_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    input TEXT NOT NULL,
+    -- [{"type":"regex","pattern":"…","must_match":true} | {"type":"judge","criteria":"…"}]
+    expectations JSONB NOT NULL DEFAULT '[]',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (agent_id, name)
+);
+
+CREATE TABLE IF NOT EXISTS ch_agent_test_runs (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    agent_id TEXT NOT NULL,
+    passed INTEGER NOT NULL,
+    failed INTEGER NOT NULL,
+    results JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_ch_agent_test_runs_agent ON ch_agent_test_runs (agent_id, created_at DESC);
@@ -0,0 +1,26 @@
+-- ClaudeHydra — Conversation templates
+-- Migration 062: reusable prompt templates with {{variable}} slots, and
+-- interview state for sessions walked through an interview template's
+-- questions before its prompt is rendered and sent.
+
+CREATE TABLE IF NOT EXISTS ch_conversation_templates (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    name TEXT NOT NULL UNIQUE,
+    kind TEXT NOT NULL CHECK (kind IN ('prompt', 'interview')),
+    description TEXT NOT NULL DEFAULT '',
+    prompt TEXT NOT NULL,
+    -- [{"variable":"steps","question":"How do we reproduce it?"}] (interview only)
+    questions JSONB NOT NULL DEFAULT '[]',
+    agent_id TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS ch_interviews (
+    session_id UUID PRIMARY KEY REFERENCES ch_sessions(id) ON DELETE CASCADE,
+    template_id UUID NOT NULL REFERENCES ch_conversation_templates(id) ON DELETE CASCADE,
+    answers JSONB NOT NULL DEFAULT '{}',
+    status TEXT NOT NULL DEFAULT 'active' CHECK (status IN ('active', 'completed', 'cancelled')),
+    started_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,25 @@
+-- ClaudeHydra — Model refusal logging
+-- Migration 063: one ch_refusals row per reply detected as a refusal or safety
+-- stop (GET /api/usage/refusals), and the setting that enables one
+-- reformulation retry on /api/claude/chat.
+
+CREATE TABLE IF NOT EXISTS ch_refusals (
+    id           BIGSERIAL PRIMARY KEY,
+    created_at   TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    model        TEXT NOT NULL,
+    agent_id     TEXT,
+    session_id   UUID REFERENCES ch_sessions(id) ON DELETE SET NULL,
+    message_id   UUID REFERENCES ch_messages(id) ON DELETE SET NULL,
+    source       TEXT NOT NULL,
+    -- 'stop_reason' (provider said so) | 'heuristic' (reply text)
+    signal       TEXT NOT NULL,
+    stop_reason  TEXT,
+    retried      BOOLEAN NOT NULL DEFAULT FALSE,
+    resolved     BOOLEAN NOT NULL DEFAULT FALSE,
+    preview      TEXT NOT NULL DEFAULT ''
+);
+
+CREATE INDEX IF NOT EXISTS idx_ch_refusals_created ON ch_refusals (created_at);
+CREATE INDEX IF NOT EXISTS idx_ch_refusals_message ON ch_refusals (message_id) WHERE message_id IS NOT NULL;
+
+ALTER TABLE ch_settings ADD COLUMN IF NOT EXISTS refusal_retry BOOLEAN NOT NULL DEFAULT FALSE;
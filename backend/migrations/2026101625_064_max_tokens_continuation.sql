@@ -0,0 +1,5 @@
+-- ClaudeHydra — max_tokens continuation
+-- Migration 064: how many times a reply that stops with max_tokens is
+-- re-requested with the partial output as an assistant prefill (0 = off).
+
+ALTER TABLE ch_settings ADD COLUMN IF NOT EXISTS max_continuations INTEGER NOT NULL DEFAULT 2;
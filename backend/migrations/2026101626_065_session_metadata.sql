@@ -0,0 +1,8 @@
+-- ClaudeHydra — Session metadata
+-- Migration 065: pinned and archived flags on sessions, set with
+-- PATCH /api/sessions/{id}/metadata (alongside title and tags).
+
+ALTER TABLE ch_sessions ADD COLUMN IF NOT EXISTS pinned BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE ch_sessions ADD COLUMN IF NOT EXISTS archived BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE INDEX IF NOT EXISTS idx_ch_sessions_archived_pinned ON ch_sessions (archived, pinned DESC, updated_at DESC);
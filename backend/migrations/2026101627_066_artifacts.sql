@@ -0,0 +1,19 @@
+-- ClaudeHydra — Mermaid / LaTeX artifacts
+-- Migration 066: diagrams and display math found in assistant replies, one
+-- row per block in reply order. The SVG is filled in on the first
+-- GET /api/artifacts/{id}/render and reused after that.
+
+CREATE TABLE IF NOT EXISTS ch_artifacts (
+    id          UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    session_id  UUID NOT NULL REFERENCES ch_sessions(id) ON DELETE CASCADE,
+    message_id  UUID NOT NULL REFERENCES ch_messages(id) ON DELETE CASCADE,
+    seq         INTEGER NOT NULL,
+    kind        TEXT NOT NULL CHECK (kind IN ('mermaid', 'latex')),
+    source      TEXT NOT NULL,
+    svg         TEXT,
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    rendered_at TIMESTAMPTZ,
+    UNIQUE (message_id, seq)
+);
+
+CREATE INDEX IF NOT EXISTS idx_ch_artifacts_session ON ch_artifacts (session_id);
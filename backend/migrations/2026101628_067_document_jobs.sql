@@ -0,0 +1,22 @@
+-- ClaudeHydra — Document jobs
+-- Migration 067: background jobs over uploaded documents, starting with the
+-- map-reduce summary of POST /api/documents/{id}/summarize. Progress is
+-- chunks_done / chunks_total; result holds the summary once completed.
+
+CREATE TABLE IF NOT EXISTS ch_document_jobs (
+    id            UUID PRIMARY KEY,
+    upload_id     UUID NOT NULL REFERENCES ch_uploads(id) ON DELETE CASCADE,
+    kind          TEXT NOT NULL,
+    status        TEXT NOT NULL CHECK (status IN ('running', 'completed', 'failed')),
+    phase         TEXT NOT NULL DEFAULT 'map',
+    chunks_total  INTEGER NOT NULL DEFAULT 0,
+    chunks_done   INTEGER NOT NULL DEFAULT 0,
+    pages         INTEGER NOT NULL DEFAULT 0,
+    result        JSONB,
+    error         TEXT,
+    created_at    TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at    TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    finished_at   TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_ch_document_jobs_upload ON ch_document_jobs (upload_id, created_at DESC);
@@ -0,0 +1,17 @@
+-- ClaudeHydra — Document passages for question answering
+-- Migration 068: passages of an uploaded document with their embeddings,
+-- built on the first POST /api/documents/{id}/ask and reused after that.
+-- Keyed by embedding model so changing CH_EMBEDDING_MODEL re-indexes.
+
+CREATE TABLE IF NOT EXISTS ch_document_chunks (
+    upload_id   UUID NOT NULL REFERENCES ch_uploads(id) ON DELETE CASCADE,
+    model       TEXT NOT NULL,
+    idx         INTEGER NOT NULL,
+    label       TEXT NOT NULL,
+    page_start  INTEGER,
+    page_end    INTEGER,
+    text        TEXT NOT NULL,
+    embedding   REAL[] NOT NULL,
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (upload_id, model, idx)
+);
@@ -0,0 +1,5 @@
+-- ClaudeHydra — System prompt layering
+-- Migration 069: order, separator and length limit used to merge the global,
+-- project, agent and request system prompt layers (NULL = built-in default).
+
+ALTER TABLE ch_settings ADD COLUMN IF NOT EXISTS prompt_layering JSONB;
@@ -0,0 +1,7 @@
+-- ClaudeHydra — Named key environments
+-- Migration 070: the active provider key environment (NULL = default) and
+-- an optional per-token environment. Environment keys themselves live in
+-- ch_api_keys as `<provider>@<environment>`.
+
+ALTER TABLE ch_settings ADD COLUMN IF NOT EXISTS key_environment TEXT;
+ALTER TABLE ch_api_tokens ADD COLUMN IF NOT EXISTS key_environment TEXT;
@@ -0,0 +1,21 @@
+-- ClaudeHydra — Crash recovery journal
+-- Migration 071: operations found unfinished in the on-disk journal at
+-- startup (session streams, document jobs, agent runs), with the request
+-- needed to retry them.
+
+CREATE TABLE IF NOT EXISTS ch_interrupted_operations (
+    id              UUID PRIMARY KEY,
+    kind            TEXT NOT NULL,
+    instance        TEXT NOT NULL,
+    session_id      UUID,
+    summary         TEXT NOT NULL,
+    payload         JSONB NOT NULL DEFAULT 'null'::jsonb,
+    started_at      TIMESTAMPTZ NOT NULL,
+    interrupted_at  TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    status          TEXT NOT NULL DEFAULT 'interrupted'
+                    CHECK (status IN ('interrupted', 'retried', 'dismissed')),
+    resolved_at     TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_ch_interrupted_operations_status
+    ON ch_interrupted_operations (status, started_at DESC);
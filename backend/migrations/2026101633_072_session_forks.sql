@@ -0,0 +1,7 @@
+-- ClaudeHydra — Conversation forks
+-- Migration 072: a fork is a child session (`parent_id`) holding a copy of
+-- the parent's messages up to `forked_from`. The link is cleared if that
+-- message is later deleted from the parent; the copy is unaffected.
+
+ALTER TABLE ch_sessions
+    ADD COLUMN IF NOT EXISTS forked_from UUID REFERENCES ch_messages(id) ON DELETE SET NULL;
@@ -0,0 +1,11 @@
+-- ClaudeHydra — Session compaction
+-- Migration 073: compaction summarizes older messages into one pinned
+-- `system` message. The summarized messages stay in the session with
+-- `compacted_at` set and are no longer sent upstream.
+
+ALTER TABLE ch_messages
+    ADD COLUMN IF NOT EXISTS pinned BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN IF NOT EXISTS compacted_at TIMESTAMPTZ;
+
+CREATE INDEX IF NOT EXISTS idx_ch_messages_live
+    ON ch_messages (session_id, created_at) WHERE compacted_at IS NULL;
@@ -0,0 +1,6 @@
+-- ClaudeHydra — Tier token budgets
+-- Migration 074: daily token budget per agent tier (Commander / Coordinator /
+-- Executor) and what happens when it runs out (downgrade or reject).
+-- NULL means no budgets.
+
+ALTER TABLE ch_settings ADD COLUMN IF NOT EXISTS tier_budgets JSONB;
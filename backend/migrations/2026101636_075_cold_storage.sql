@@ -0,0 +1,25 @@
+-- ClaudeHydra — Cold storage for old sessions
+-- Migration 075: messages of sessions idle for a long time keep only a
+-- preview in `content`; the full text is zstd-compressed in `content_zst`
+-- until the session is accessed again.
+
+ALTER TABLE ch_messages ADD COLUMN IF NOT EXISTS content_zst BYTEA;
+
+ALTER TABLE ch_sessions
+    ADD COLUMN IF NOT EXISTS cold_at TIMESTAMPTZ,
+    ADD COLUMN IF NOT EXISTS thawed_at TIMESTAMPTZ;
+
+-- Compressing a message replaces its content with the preview; keep the
+-- search vector of the full text so cold sessions stay searchable.
+CREATE OR REPLACE FUNCTION ch_messages_search_vector_update() RETURNS trigger AS $$
+BEGIN
+    IF TG_OP = 'UPDATE' AND NEW.content_zst IS NOT NULL THEN
+        NEW.search_vector := OLD.search_vector;
+    ELSE
+        NEW.search_vector := to_tsvector('english', COALESCE(NEW.content, ''));
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE INDEX IF NOT EXISTS idx_ch_sessions_cold ON ch_sessions (cold_at) WHERE cold_at IS NOT NULL;
@@ -0,0 +1,6 @@
+-- ClaudeHydra — Prompt-assembly decision log
+-- Migration 076: the decisions taken while building the request an assistant
+-- message was generated from (model routing, prompt layers and truncation,
+-- template, tier downgrades, retries), stored with its generation context.
+
+ALTER TABLE ch_message_contexts ADD COLUMN IF NOT EXISTS decisions JSONB NOT NULL DEFAULT '[]';
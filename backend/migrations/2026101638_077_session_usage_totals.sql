@@ -0,0 +1,23 @@
+-- ClaudeHydra — Per-session token and cost totals
+-- Migration 077: cumulative input/output tokens and estimated cost on each
+-- session, bumped by every recorded usage event, so the session list can show
+-- which conversations are eating the budget. Backfilled from ch_usage_events.
+
+ALTER TABLE ch_sessions ADD COLUMN IF NOT EXISTS input_tokens BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE ch_sessions ADD COLUMN IF NOT EXISTS output_tokens BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE ch_sessions ADD COLUMN IF NOT EXISTS cost_usd DOUBLE PRECISION NOT NULL DEFAULT 0;
+
+UPDATE ch_sessions s
+SET input_tokens = u.input_tokens,
+    output_tokens = u.output_tokens,
+    cost_usd = u.cost_usd
+FROM (
+    SELECT session_id,
+           SUM(input_tokens)::BIGINT AS input_tokens,
+           SUM(output_tokens)::BIGINT AS output_tokens,
+           SUM(cost_usd) AS cost_usd
+    FROM ch_usage_events
+    WHERE session_id IS NOT NULL
+    GROUP BY session_id
+) u
+WHERE s.id = u.session_id;
@@ -0,0 +1,6 @@
+-- ClaudeHydra — Spend caps and model token quotas
+-- Migration 078: daily / monthly estimated spend caps (USD) and per-model
+-- token budgets; chat is rejected with 429 once one is reached.
+-- NULL means no limits.
+
+ALTER TABLE ch_settings ADD COLUMN IF NOT EXISTS quotas JSONB;
@@ -0,0 +1,15 @@
+-- ClaudeHydra — Event history
+-- Migration 079: everything published on the internal event bus (replica
+-- state changes, webhook events), so clients that were offline can catch up
+-- with GET /api/events/history. Pruned by age and row count.
+
+CREATE TABLE IF NOT EXISTS ch_events (
+    id BIGSERIAL PRIMARY KEY,
+    event_type TEXT NOT NULL,
+    instance TEXT NOT NULL DEFAULT '',
+    data JSONB NOT NULL DEFAULT '{}',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_ch_events_type_id ON ch_events (event_type, id);
+CREATE INDEX IF NOT EXISTS idx_ch_events_created_at ON ch_events (created_at);
@@ -0,0 +1,26 @@
+-- ClaudeHydra — Prompt canaries
+-- Migration 080: canary rollouts of prompt edits (custom instructions, agent
+-- personas). A share of requests uses the candidate text, the rest the live
+-- one; usage events record which variant a reply was generated with, so both
+-- can be compared before the candidate is promoted.
+
+CREATE TABLE IF NOT EXISTS ch_prompt_canaries (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    target TEXT NOT NULL,
+    candidate TEXT NOT NULL,
+    baseline TEXT NOT NULL DEFAULT '',
+    percent SMALLINT NOT NULL CHECK (percent BETWEEN 1 AND 99),
+    status TEXT NOT NULL DEFAULT 'running',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    ended_at TIMESTAMPTZ
+);
+
+-- One running canary per target.
+CREATE UNIQUE INDEX IF NOT EXISTS idx_ch_prompt_canaries_running
+    ON ch_prompt_canaries (target) WHERE status = 'running';
+
+ALTER TABLE ch_usage_events ADD COLUMN IF NOT EXISTS prompt_variants JSONB;
+ALTER TABLE ch_usage_events ADD COLUMN IF NOT EXISTS refused BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE INDEX IF NOT EXISTS idx_ch_usage_events_prompt_variants
+    ON ch_usage_events USING GIN (prompt_variants) WHERE prompt_variants IS NOT NULL;
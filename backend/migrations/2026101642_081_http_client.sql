@@ -0,0 +1,6 @@
+-- ClaudeHydra — Upstream HTTP client settings
+-- Migration 081: timeouts and connection pooling of the client used for
+-- provider calls. NULL (or a field left out) falls back to the env vars and
+-- built-in defaults.
+
+ALTER TABLE ch_settings ADD COLUMN IF NOT EXISTS http_client JSONB;
@@ -0,0 +1,6 @@
+-- ClaudeHydra — Model per agent tier
+-- Migration 082: configured model of each agent tier
+-- ({"Executor": "claude-haiku-4-5"}). A tier left out follows the model
+-- registry (pin, newest of its family, built-in default).
+
+ALTER TABLE ch_settings ADD COLUMN IF NOT EXISTS tier_models JSONB;
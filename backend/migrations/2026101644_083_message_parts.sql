@@ -0,0 +1,6 @@
+-- ClaudeHydra — Structured message content
+-- Migration 083: content parts of a message (text, image, tool_call,
+-- tool_result, thinking) as sent with POST /api/sessions/{id}/messages.
+-- NULL = a plain text message; `content` keeps the text either way.
+
+ALTER TABLE ch_messages ADD COLUMN IF NOT EXISTS parts JSONB;
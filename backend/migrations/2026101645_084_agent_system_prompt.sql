@@ -0,0 +1,5 @@
+-- ClaudeHydra — Agent system prompts
+-- Migration 084: instructions of a custom (or edited) agent, added to the
+-- agent layer of the system prompt when generating as that agent.
+
+ALTER TABLE ch_agents_config ADD COLUMN IF NOT EXISTS system_prompt TEXT NOT NULL DEFAULT '';
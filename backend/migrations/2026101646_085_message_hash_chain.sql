@@ -0,0 +1,58 @@
+-- ClaudeHydra — Message hash chain
+-- Migration 085: sessions with `hash_chain` on link every new message to the
+-- previous one: chain_hash = sha256 of the previous hash and the message
+-- (id, role, model, agent, content), chain_seq counts from 1. Changing,
+-- removing or reordering a linked message breaks the chain, which
+-- GET /api/sessions/{id}/chain detects.
+
+ALTER TABLE ch_sessions ADD COLUMN IF NOT EXISTS hash_chain BOOLEAN NOT NULL DEFAULT FALSE;
+
+ALTER TABLE ch_messages
+    ADD COLUMN IF NOT EXISTS chain_seq BIGINT,
+    ADD COLUMN IF NOT EXISTS chain_hash TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_ch_messages_chain
+    ON ch_messages (session_id, chain_seq) WHERE chain_seq IS NOT NULL;
+
+-- Must match message_chain::link_hash in the backend.
+CREATE OR REPLACE FUNCTION ch_messages_chain_link() RETURNS trigger AS $$
+DECLARE
+    prev_seq BIGINT;
+    prev_hash TEXT;
+BEGIN
+    -- Row lock: concurrent inserts into the same session link one at a time.
+    PERFORM 1 FROM ch_sessions WHERE id = NEW.session_id AND hash_chain FOR UPDATE;
+    IF NOT FOUND THEN
+        RETURN NEW;
+    END IF;
+
+    SELECT chain_seq, chain_hash INTO prev_seq, prev_hash
+    FROM ch_messages
+    WHERE session_id = NEW.session_id AND chain_seq IS NOT NULL
+    ORDER BY chain_seq DESC
+    LIMIT 1;
+
+    NEW.chain_seq := COALESCE(prev_seq, 0) + 1;
+    NEW.chain_hash := encode(sha256(convert_to(
+        COALESCE(prev_hash, '') || E'\n' ||
+        NEW.id::text || E'\n' ||
+        NEW.role || E'\n' ||
+        COALESCE(NEW.model, '') || E'\n' ||
+        COALESCE(NEW.agent, '') || E'\n' ||
+        COALESCE(NEW.content, ''),
+        'UTF8')), 'hex');
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DO $$
+BEGIN
+    IF NOT EXISTS (
+        SELECT 1 FROM pg_trigger WHERE tgname = 'trg_ch_messages_chain_link'
+    ) THEN
+        CREATE TRIGGER trg_ch_messages_chain_link
+            BEFORE INSERT ON ch_messages
+            FOR EACH ROW
+            EXECUTE FUNCTION ch_messages_chain_link();
+    END IF;
+END $$;
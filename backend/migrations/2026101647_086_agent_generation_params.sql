@@ -0,0 +1,7 @@
+-- ClaudeHydra — Agent generation parameters
+-- Migration 086: temperature and reply length limit used when generating as
+-- an agent; NULL follows the global settings.
+
+ALTER TABLE ch_agents_config
+    ADD COLUMN IF NOT EXISTS temperature DOUBLE PRECISION,
+    ADD COLUMN IF NOT EXISTS max_tokens INTEGER;
@@ -0,0 +1,17 @@
+-- ClaudeHydra — Agent status lifecycle
+-- Migration 087: an agent is 'active', 'paused' (not delegated to) or
+-- 'disabled' (refused everywhere). Other values stored so far become 'active'.
+
+UPDATE ch_agents_config SET status = 'active'
+WHERE status NOT IN ('active', 'paused', 'disabled');
+
+DO $$
+BEGIN
+    IF NOT EXISTS (
+        SELECT 1 FROM pg_constraint WHERE conname = 'ch_agents_config_status_check'
+    ) THEN
+        ALTER TABLE ch_agents_config
+            ADD CONSTRAINT ch_agents_config_status_check
+            CHECK (status IN ('active', 'paused', 'disabled'));
+    END IF;
+END $$;
@@ -0,0 +1,6 @@
+-- ClaudeHydra — Endpoint policies
+-- Migration 088: timeout and retry overrides per group of provider calls
+-- (chat, stream, models, batch, tools). NULL (or a field left out) falls
+-- back to the built-in defaults.
+
+ALTER TABLE ch_settings ADD COLUMN IF NOT EXISTS endpoint_policies JSONB;
@@ -0,0 +1,20 @@
+-- ClaudeHydra — Cluster registry and shared rate limits
+-- Migration 089: replicas announce their internal URL so stream resumes can
+-- be forwarded to the owning replica, and chat rate limits are counted in
+-- one-minute windows shared by every replica (only with CLUSTER_SYNC=1).
+
+CREATE TABLE IF NOT EXISTS ch_cluster_instances (
+    instance_id  TEXT PRIMARY KEY,
+    internal_url TEXT NOT NULL,
+    heartbeat_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS ch_rate_windows (
+    endpoint_group TEXT NOT NULL,
+    client         TEXT NOT NULL,
+    window_start   TIMESTAMPTZ NOT NULL,
+    hits           INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (endpoint_group, client, window_start)
+);
+
+CREATE INDEX IF NOT EXISTS idx_ch_rate_windows_start ON ch_rate_windows (window_start);
@@ -0,0 +1,282 @@
+// ClaudeHydra v4 -- Agent catalog
+// Community agent packs listed in a static JSON index. Off unless
+// `CH_AGENT_CATALOG_URL` points at the index:
+//
+//   { "packs": [{ "id": "reviewers", "name": "Code reviewers", "version": "1.2.0",
+//                 "description": "...", "url": "packs/reviewers.json",
+//                 "sha256": "<hex>", "signature": "<hex>" }] }
+//
+// `GET /api/agents/catalog` lists the packs (the index is cached for 10
+// minutes); `POST /api/agents/catalog/{pack_id}/install` downloads a pack,
+// checks it against the index's `sha256` and installs it like
+// `POST /api/agents/import`. With `CH_AGENT_CATALOG_KEY` set, packs must also
+// carry `signature`, the hex HMAC-SHA256 of the pack file under that key.
+// Relative pack URLs are resolved against the index URL.
+
+use std::time::{Duration, Instant};
+
+use axum::Json;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use tokio::sync::Mutex;
+
+use crate::crypto::{ct_eq, hmac_sha256};
+use crate::models::AgentPack;
+use crate::state::AppState;
+
+const CACHE_TTL: Duration = Duration::from_secs(10 * 60);
+const FETCH_TIMEOUT: Duration = Duration::from_secs(15);
+/// Index and pack files larger than this are refused.
+const MAX_FILE_BYTES: usize = 1024 * 1024;
+
+type ApiError = (StatusCode, Json<Value>);
+
+/// One pack as listed in the index.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CatalogEntry {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub version: String,
+    #[serde(default)]
+    pub description: String,
+    /// Pack file, absolute or relative to the index.
+    pub url: String,
+    /// Hex SHA-256 of the pack file.
+    #[serde(default)]
+    pub sha256: String,
+    /// Hex HMAC-SHA256 of the pack file (required with CH_AGENT_CATALOG_KEY).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CatalogIndex {
+    #[serde(default)]
+    packs: Vec<CatalogEntry>,
+}
+
+/// Index as last fetched.
+static INDEX: Mutex<Option<(Instant, Vec<CatalogEntry>)>> = Mutex::const_new(None);
+
+fn index_url() -> Option<String> {
+    std::env::var("CH_AGENT_CATALOG_URL")
+        .ok()
+        .map(|u| u.trim().to_string())
+        .filter(|u| !u.is_empty())
+}
+
+fn signing_key() -> Option<String> {
+    std::env::var("CH_AGENT_CATALOG_KEY").ok().filter(|k| !k.is_empty())
+}
+
+fn error(status: StatusCode, msg: impl Into<String>) -> ApiError {
+    (status, Json(json!({ "error": msg.into() })))
+}
+
+/// `url` of a pack, resolved against the index URL.
+fn resolve_url(index: &str, url: &str) -> Result<url::Url, String> {
+    let base = url::Url::parse(index).map_err(|e| format!("invalid index URL: {}", e))?;
+    let resolved = base.join(url).map_err(|e| format!("invalid pack URL '{}': {}", url, e))?;
+    if !matches!(resolved.scheme(), "https" | "http") {
+        return Err(format!("unsupported pack URL scheme '{}'", resolved.scheme()));
+    }
+    Ok(resolved)
+}
+
+/// Check a downloaded pack file against its index entry.
+fn verify(bytes: &[u8], entry: &CatalogEntry, key: Option<&str>) -> Result<(), String> {
+    if entry.sha256.is_empty() {
+        return Err("the index lists no sha256 for this pack".to_string());
+    }
+    let digest: String = Sha256::digest(bytes).iter().map(|b| format!("{:02x}", b)).collect();
+    if !ct_eq(&entry.sha256.to_ascii_lowercase(), &digest) {
+        return Err("sha256 does not match the index".to_string());
+    }
+    if let Some(key) = key {
+        let Some(signature) = entry.signature.as_deref() else {
+            return Err("the pack is not signed".to_string());
+        };
+        if !ct_eq(&signature.to_ascii_lowercase(), &hmac_sha256(key.as_bytes(), bytes)) {
+            return Err("signature does not match".to_string());
+        }
+    }
+    Ok(())
+}
+
+async fn fetch(client: &reqwest::Client, url: &str) -> Result<Vec<u8>, String> {
+    let resp = client
+        .get(url)
+        .header(reqwest::header::USER_AGENT, "ClaudeHydra")
+        .timeout(FETCH_TIMEOUT)
+        .send()
+        .await
+        .map_err(|e| format!("request failed: {}", e))?;
+    if !resp.status().is_success() {
+        return Err(format!("{} returned {}", url, resp.status()));
+    }
+    if resp.content_length().is_some_and(|len| len as usize > MAX_FILE_BYTES) {
+        return Err(format!("{} is larger than {} bytes", url, MAX_FILE_BYTES));
+    }
+    let bytes = resp.bytes().await.map_err(|e| format!("read failed: {}", e))?;
+    if bytes.len() > MAX_FILE_BYTES {
+        return Err(format!("{} is larger than {} bytes", url, MAX_FILE_BYTES));
+    }
+    Ok(bytes.to_vec())
+}
+
+/// The catalog's packs (cached for `CACHE_TTL`; failed fetches are not cached).
+async fn packs(state: &AppState, index: &str, refresh: bool) -> Result<(Instant, Vec<CatalogEntry>), ApiError> {
+    let mut cached = INDEX.lock().await;
+    if !refresh
+        && let Some((at, packs)) = cached.as_ref()
+        && at.elapsed() < CACHE_TTL
+    {
+        return Ok((*at, packs.clone()));
+    }
+    let bytes = fetch(&state.http_client, index).await.map_err(|e| {
+        tracing::warn!("agent_catalog: index fetch failed: {}", e);
+        (
+            StatusCode::BAD_GATEWAY,
+            Json(json!({ "error": "Failed to fetch the agent catalog", "details": e })),
+        )
+    })?;
+    let parsed: CatalogIndex = serde_json::from_slice(&bytes).map_err(|e| {
+        (
+            StatusCode::BAD_GATEWAY,
+            Json(json!({ "error": "The agent catalog index is not valid JSON", "details": e.to_string() })),
+        )
+    })?;
+    *cached = Some((Instant::now(), parsed.packs.clone()));
+    Ok((Instant::now(), parsed.packs))
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct CatalogQuery {
+    /// Fetch the index again instead of using the cached copy.
+    #[serde(default)]
+    pub refresh: bool,
+}
+
+/// `GET /api/agents/catalog` — packs listed in the configured index
+#[utoipa::path(get, path = "/api/agents/catalog", tag = "agents",
+    params(("refresh" = Option<bool>, Query, description = "Bypass the 10-minute index cache")),
+    responses(
+        (status = 200, description = "Packs in the catalog (enabled: false when no index is configured)"),
+        (status = 502, description = "The index could not be fetched or parsed")
+    ))]
+pub async fn list_catalog(
+    State(state): State<AppState>,
+    Query(q): Query<CatalogQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let Some(index) = index_url() else {
+        return Ok(Json(json!({ "enabled": false, "packs": [] })));
+    };
+    let (fetched, packs) = packs(&state, &index, q.refresh).await?;
+    let fetched_at = chrono::Utc::now() - chrono::Duration::from_std(fetched.elapsed()).unwrap_or_default();
+    Ok(Json(json!({
+        "enabled": true,
+        "index_url": index,
+        "signed": signing_key().is_some(),
+        "packs": packs,
+        "fetched_at": fetched_at,
+    })))
+}
+
+/// `POST /api/agents/catalog/{pack_id}/install` — download, verify and install a pack
+#[utoipa::path(post, path = "/api/agents/catalog/{pack_id}/install", tag = "agents",
+    params(("pack_id" = String, Path, description = "Pack id from the catalog")),
+    responses(
+        (status = 201, description = "Installed agents and the names skipped as already present"),
+        (status = 400, description = "The pack contains no agents or an invalid one"),
+        (status = 404, description = "No catalog configured, or no such pack"),
+        (status = 502, description = "The pack could not be fetched, failed verification or is not a valid pack")
+    ))]
+pub async fn install_pack(
+    State(state): State<AppState>,
+    Path(pack_id): Path<String>,
+) -> Result<(StatusCode, Json<Value>), ApiError> {
+    let Some(index) = index_url() else {
+        return Err(error(StatusCode::NOT_FOUND, "No agent catalog is configured (CH_AGENT_CATALOG_URL)"));
+    };
+    let (_, packs) = packs(&state, &index, false).await?;
+    let Some(entry) = packs.into_iter().find(|p| p.id == pack_id) else {
+        return Err(error(StatusCode::NOT_FOUND, format!("Pack '{}' is not in the catalog", pack_id)));
+    };
+
+    let url = resolve_url(&index, &entry.url).map_err(|e| error(StatusCode::BAD_GATEWAY, e))?;
+    let bytes = fetch(&state.http_client, url.as_str()).await.map_err(|e| {
+        tracing::warn!("agent_catalog: pack {} fetch failed: {}", entry.id, e);
+        (
+            StatusCode::BAD_GATEWAY,
+            Json(json!({ "error": "Failed to fetch the pack", "details": e })),
+        )
+    })?;
+    if let Err(e) = verify(&bytes, &entry, signing_key().as_deref()) {
+        tracing::warn!("agent_catalog: pack {} rejected: {}", entry.id, e);
+        return Err((
+            StatusCode::BAD_GATEWAY,
+            Json(json!({ "error": "The pack failed verification", "code": "integrity_check_failed", "details": e })),
+        ));
+    }
+    let pack: AgentPack = serde_json::from_slice(&bytes).map_err(|e| {
+        (
+            StatusCode::BAD_GATEWAY,
+            Json(json!({ "error": "The pack is not a valid agent pack", "details": e.to_string() })),
+        )
+    })?;
+
+    let mut result = crate::handlers::agents::import_pack(&state, pack).await?;
+    result["catalog_id"] = json!(entry.id);
+    result["sha256"] = json!(entry.sha256);
+    crate::audit::log_audit(&state.db, "agents.catalog_install", result.clone(), None).await;
+    Ok((StatusCode::CREATED, Json(result)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(bytes: &[u8]) -> CatalogEntry {
+        CatalogEntry {
+            id: "reviewers".to_string(),
+            name: "Reviewers".to_string(),
+            version: "1.0.0".to_string(),
+            description: String::new(),
+            url: "packs/reviewers.json".to_string(),
+            sha256: Sha256::digest(bytes).iter().map(|b| format!("{:02x}", b)).collect(),
+            signature: None,
+        }
+    }
+
+    #[test]
+    fn packs_must_match_the_index_hash() {
+        let pack = br#"{"name":"Reviewers","agents":[]}"#;
+        assert!(verify(pack, &entry(pack), None).is_ok());
+        assert!(verify(b"tampered", &entry(pack), None).is_err());
+        let unhashed = CatalogEntry { sha256: String::new(), ..entry(pack) };
+        assert!(verify(pack, &unhashed, None).is_err());
+    }
+
+    #[test]
+    fn signing_key_requires_a_valid_signature() {
+        let pack = br#"{"name":"Reviewers","agents":[]}"#;
+        assert_eq!(verify(pack, &entry(pack), Some("k")), Err("the pack is not signed".to_string()));
+        let signed = CatalogEntry { signature: Some(hmac_sha256(b"k", pack)), ..entry(pack) };
+        assert!(verify(pack, &signed, Some("k")).is_ok());
+        assert!(verify(pack, &signed, Some("other")).is_err());
+    }
+
+    #[test]
+    fn pack_urls_resolve_against_the_index() {
+        let index = "https://example.com/catalog/index.json";
+        assert_eq!(
+            resolve_url(index, "packs/a.json").unwrap().as_str(),
+            "https://example.com/catalog/packs/a.json"
+        );
+        assert!(resolve_url(index, "file:///etc/passwd").is_err());
+    }
+}
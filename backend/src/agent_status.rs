@@ -0,0 +1,186 @@
+// ClaudeHydra v4 -- Agent status lifecycle
+// An agent is `active`, `paused` or `disabled` (ch_agents_config.status,
+// changed with PATCH /api/agents/{id}/status):
+//   - active   — used everywhere,
+//   - paused   — not delegated to by orchestration (`call_agent`), still
+//                usable when addressed directly,
+//   - disabled — refused everywhere: chat with its `agent_id`, agent chat,
+//                agent runs (and tests) and delegation.
+// Independently, an agent is busy while a request generated as it is in
+// flight on this replica; GET /api/agents shows `busy` and `in_flight`.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use utoipa::ToSchema;
+
+use crate::models::WitcherAgent;
+use crate::state::AppState;
+
+type ApiError = (StatusCode, Json<Value>);
+
+pub const ACTIVE: &str = "active";
+pub const PAUSED: &str = "paused";
+pub const DISABLED: &str = "disabled";
+pub const STATUSES: [&str; 3] = [ACTIVE, PAUSED, DISABLED];
+
+static IN_FLIGHT: Mutex<BTreeMap<String, usize>> = Mutex::new(BTreeMap::new());
+
+/// Marks an agent busy until dropped.
+#[derive(Debug)]
+pub struct Busy {
+    agent_id: String,
+}
+
+impl Drop for Busy {
+    fn drop(&mut self) {
+        if let Ok(mut in_flight) = IN_FLIGHT.lock()
+            && let Some(n) = in_flight.get_mut(&self.agent_id)
+        {
+            *n = n.saturating_sub(1);
+            if *n == 0 {
+                in_flight.remove(&self.agent_id);
+            }
+        }
+    }
+}
+
+/// Count a request generated as `agent_id` until the guard is dropped.
+pub fn busy(agent_id: &str) -> Busy {
+    if let Ok(mut in_flight) = IN_FLIGHT.lock() {
+        *in_flight.entry(agent_id.to_string()).or_default() += 1;
+    }
+    Busy {
+        agent_id: agent_id.to_string(),
+    }
+}
+
+/// Requests in flight for `agent_id` on this replica.
+pub fn in_flight(agent_id: &str) -> usize {
+    IN_FLIGHT
+        .lock()
+        .map(|m| m.get(agent_id).copied().unwrap_or(0))
+        .unwrap_or(0)
+}
+
+/// Error unless `status` is one of `STATUSES`.
+pub fn check(status: &str) -> Result<(), ApiError> {
+    if STATUSES.contains(&status) {
+        return Ok(());
+    }
+    Err((
+        StatusCode::BAD_REQUEST,
+        Json(json!({ "error": "status must be one of: active, paused, disabled" })),
+    ))
+}
+
+/// Why `agent` cannot be addressed directly, if it cannot.
+pub fn refusal(agent: &WitcherAgent) -> Option<String> {
+    (agent.status == DISABLED).then(|| format!("Agent '{}' is disabled", agent.name))
+}
+
+/// Why `agent` cannot be delegated to, if it cannot.
+pub fn delegation_refusal(agent: &WitcherAgent) -> Option<String> {
+    (agent.status == PAUSED || agent.status == DISABLED)
+        .then(|| format!("Agent '{}' is {}", agent.name, agent.status))
+}
+
+/// `refusal` as a 409 response.
+pub fn ensure_usable(agent: &WitcherAgent) -> Result<(), ApiError> {
+    match refusal(agent) {
+        Some(error) => Err((StatusCode::CONFLICT, Json(json!({ "error": error })))),
+        None => Ok(()),
+    }
+}
+
+/// `agent` as listed by the API, with its activity.
+pub fn with_activity(agent: &WitcherAgent) -> Value {
+    let mut value = serde_json::to_value(agent).unwrap_or_else(|_| json!({}));
+    let in_flight = in_flight(&agent.id);
+    if let Some(obj) = value.as_object_mut() {
+        obj.insert("busy".to_string(), json!(in_flight > 0));
+        obj.insert("in_flight".to_string(), json!(in_flight));
+    }
+    value
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  PATCH /api/agents/{id}/status
+// ═══════════════════════════════════════════════════════════════════════
+
+/// Request body for `PATCH /api/agents/{id}/status`.
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct SetStatusRequest {
+    /// `active`, `paused` or `disabled`.
+    pub status: String,
+}
+
+/// `PATCH /api/agents/{id}/status` — activate, pause or disable an agent
+#[utoipa::path(patch, path = "/api/agents/{id}/status", tag = "agents",
+    params(("id" = String, Path, description = "Agent ID")),
+    request_body = SetStatusRequest,
+    responses(
+        (status = 200, description = "Agent with its new status"),
+        (status = 400, description = "Unknown status"),
+        (status = 404, description = "Agent not found")
+    ))]
+pub async fn set_agent_status(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(req): Json<SetStatusRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let status = req.status.trim().to_lowercase();
+    check(&status)?;
+
+    let row: Option<crate::models::AgentConfigRow> = sqlx::query_as(
+        "UPDATE ch_agents_config SET status = $2, updated_at = now() WHERE id = $1 \
+         RETURNING id, name, role, tier, status, description, model, stop_sequences, system_prompt, \
+                   temperature, max_tokens, created_at, updated_at",
+    )
+    .bind(&id)
+    .bind(&status)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|e| {
+        tracing::error!("set_agent_status DB error: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": "Failed to update agent status" })),
+        )
+    })?;
+    let Some(row) = row else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": format!("Agent '{}' not found", id) })),
+        ));
+    };
+
+    let agent: WitcherAgent = row.into();
+    state.refresh_agents().await;
+    crate::cluster::publish(&state, crate::cluster::ClusterEvent::AgentsChanged);
+    tracing::info!("Agent {} ({}) is now {}", agent.name, agent.id, agent.status);
+    Ok(Json(with_activity(&agent)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn busy_counts_requests_in_flight() {
+        let first = busy("agent-test-busy");
+        let second = busy("agent-test-busy");
+        assert_eq!(in_flight("agent-test-busy"), 2);
+        drop(first);
+        assert_eq!(in_flight("agent-test-busy"), 1);
+        drop(second);
+        assert_eq!(in_flight("agent-test-busy"), 0);
+        assert!(check("paused").is_ok());
+        assert!(check("idle").is_err());
+    }
+}
@@ -0,0 +1,449 @@
+// ClaudeHydra v4 -- Scoped API tokens
+// Named, expiring credentials for scripts and CI (`/api/api-tokens` —
+// `/api/tokens` belongs to the shared service-token handlers), so they
+// don't have to share the main AUTH_SECRET. Tokens look like `chk_<64 hex>`;
+// only their SHA-256 is stored and the plaintext is returned once, at creation.
+//
+// Scopes (a token may hold several; any matching scope allows the request):
+//   - `chat`  — chat endpoints only (`/api/claude/*`, `/api/gemini/*`,
+//               `/api/debate`, `/api/prefetch/*`, POST
+//               `/api/sessions/{id}/chat` and `/chat/stream`, and POST
+//               `/api/agents/{id}/chat`)
+//   - `read`  — safe methods everywhere except admin-only endpoints (see
+//               `web_session::is_admin_request`) and the token endpoints
+//               (these and the shared service tokens at `/api/tokens`),
+//               and the chat WebSockets
+//   - `admin` — everything, including minting further tokens
+// The chat WebSockets (`/ws/chat`, `/api/ws`) are GET upgrades that start
+// generations, so `read` can't open them. `chat` doesn't cover them either:
+// they authenticate with `?token=` or the session cookie, and scripts use the
+// HTTP chat endpoints.
+//
+// `token_auth` runs in front of every route: a `Bearer chk_…` header is looked
+// up, checked against the scopes and swapped for the bearer credential the
+// shared `require_auth` expects. Unknown, expired or revoked tokens get 401.
+// Non-admin web sessions only see and revoke the tokens they minted; admin
+// sessions, admin-scoped tokens and AUTH_SECRET callers manage all of them.
+// A token also carries a `default_priority` (see `priority`) for chat requests
+// that don't set their own, so batch jobs can mint `low` tokens, and an
+// optional `key_environment` (see `key_environments`), so a CI token can be
+// pinned to the `dev` provider keys.
+
+use axum::extract::{Path, Request, State};
+use axum::http::{HeaderValue, Method, StatusCode, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+
+use crate::priority::{DefaultPriority, Priority};
+use crate::state::AppState;
+use crate::web_session::{Role, Session};
+
+pub const TOKEN_PREFIX: &str = "chk_";
+const DEFAULT_EXPIRY_DAYS: i64 = 90;
+const MAX_EXPIRY_DAYS: i64 = 365;
+
+const CHAT_PREFIXES: &[&str] = &["/api/claude/", "/api/gemini/", "/api/debate", "/api/prefetch/"];
+
+/// `POST {prefix}{id}/chat` or `/chat/stream` — chat bound to one entity.
+fn is_entity_chat(method: &Method, path: &str, prefix: &str) -> bool {
+    *method == Method::POST
+        && path
+            .strip_prefix(prefix)
+            .and_then(|rest| rest.strip_suffix("/chat").or_else(|| rest.strip_suffix("/chat/stream")))
+            .is_some_and(|id| !id.is_empty() && !id.contains('/'))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    Chat,
+    Read,
+    Admin,
+}
+
+impl Scope {
+    fn as_str(self) -> &'static str {
+        match self {
+            Scope::Chat => "chat",
+            Scope::Read => "read",
+            Scope::Admin => "admin",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "chat" => Some(Scope::Chat),
+            "read" => Some(Scope::Read),
+            "admin" => Some(Scope::Admin),
+            _ => None,
+        }
+    }
+
+    fn allows(self, method: &Method, path: &str) -> bool {
+        match self {
+            Scope::Admin => true,
+            Scope::Read => {
+                matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+                    && !crate::web_session::is_admin_request(method, path)
+                    && !path.starts_with("/api/api-tokens")
+                    && !path.starts_with("/api/tokens")
+                    && !crate::ws::is_chat_socket(path)
+            }
+            Scope::Chat => {
+                CHAT_PREFIXES.iter().any(|p| path.starts_with(p))
+                    || is_entity_chat(method, path, "/api/sessions/")
+                    || is_entity_chat(method, path, "/api/agents/")
+            }
+        }
+    }
+}
+
+fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+fn generate_token() -> String {
+    format!(
+        "{}{}{}",
+        TOKEN_PREFIX,
+        uuid::Uuid::new_v4().simple(),
+        uuid::Uuid::new_v4().simple()
+    )
+}
+
+fn error(status: StatusCode, msg: &str) -> Response {
+    (status, Json(json!({ "error": msg }))).into_response()
+}
+
+/// Middleware: authenticate `Bearer chk_…` API tokens and enforce their scopes.
+pub async fn token_auth(State(state): State<AppState>, mut req: Request, next: Next) -> Response {
+    let token = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .filter(|t| t.starts_with(TOKEN_PREFIX))
+        .map(str::to_string);
+    let (Some(token), Some(secret)) = (token, state.auth_secret.clone()) else {
+        return next.run(req).await;
+    };
+
+    let row = sqlx::query_as::<_, (uuid::Uuid, Vec<String>, String, Option<String>)>(
+        "SELECT id, scopes, default_priority, key_environment FROM ch_api_tokens \
+         WHERE token_hash = $1 AND revoked_at IS NULL \
+           AND (expires_at IS NULL OR expires_at > NOW())",
+    )
+    .bind(hash_token(&token))
+    .fetch_optional(&state.db)
+    .await;
+    let (id, scopes, default_priority, key_environment) = match row {
+        Ok(Some(r)) => r,
+        Ok(None) => return error(StatusCode::UNAUTHORIZED, "Invalid, expired or revoked API token"),
+        Err(e) => {
+            tracing::error!("api_tokens: lookup failed: {}", e);
+            return error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to verify API token");
+        }
+    };
+
+    let path = req.uri().path();
+    if !scopes
+        .iter()
+        .filter_map(|s| Scope::parse(s))
+        .any(|s| s.allows(req.method(), path))
+    {
+        tracing::warn!("api_tokens: token {} lacks scope for {} {}", id, req.method(), path);
+        return error(StatusCode::FORBIDDEN, "API token scope does not allow this endpoint");
+    }
+
+    // Throttled so busy tokens don't write on every request.
+    let db = state.db.clone();
+    tokio::spawn(async move {
+        let _ = sqlx::query(
+            "UPDATE ch_api_tokens SET last_used_at = NOW() WHERE id = $1 \
+             AND (last_used_at IS NULL OR last_used_at < NOW() - INTERVAL '1 minute')",
+        )
+        .bind(id)
+        .execute(&db)
+        .await;
+    });
+
+    if let Ok(v) = HeaderValue::from_str(&format!("Bearer {}", secret)) {
+        req.headers_mut().insert(header::AUTHORIZATION, v);
+    }
+    if let Some(p) = Priority::parse(&default_priority) {
+        req.extensions_mut().insert(DefaultPriority(p));
+    }
+    req.extensions_mut().insert(crate::principal::TokenPrincipal(id));
+    if let Some(environment) = key_environment {
+        req.extensions_mut()
+            .insert(crate::key_environments::TokenKeyEnvironment(environment));
+    }
+    next.run(req).await
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  /api/api-tokens
+// ═══════════════════════════════════════════════════════════════════════
+
+#[derive(Debug, Deserialize)]
+pub struct CreateTokenRequest {
+    pub name: String,
+    pub scopes: Vec<String>,
+    /// Days until expiry (default 90, max 365).
+    pub expires_in_days: Option<i64>,
+    /// Priority for chat requests that don't set one (default `normal`).
+    #[serde(default)]
+    pub default_priority: Option<Priority>,
+    /// Provider key environment for requests made with this token.
+    #[serde(default)]
+    pub key_environment: Option<String>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct ApiTokenRow {
+    pub id: uuid::Uuid,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: Vec<String>,
+    pub default_priority: String,
+    pub key_environment: Option<String>,
+    pub created_by: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub last_used_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub revoked_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+const TOKEN_COLUMNS: &str =
+    "id, name, prefix, scopes, default_priority, key_environment, created_by, created_at, expires_at, last_used_at, revoked_at";
+
+/// Validate a create request; returns the normalized name, scopes and lifetime.
+fn validate(req: &CreateTokenRequest) -> Result<(String, Vec<Scope>, i64), String> {
+    let name = req.name.trim();
+    if name.is_empty() || name.chars().count() > 100 {
+        return Err("name must be 1-100 characters".into());
+    }
+    if req.scopes.is_empty() {
+        return Err("at least one scope is required (chat, read, admin)".into());
+    }
+    let mut scopes = Vec::new();
+    for s in &req.scopes {
+        let scope = Scope::parse(s).ok_or_else(|| format!("unknown scope '{}'", s))?;
+        if !scopes.contains(&scope) {
+            scopes.push(scope);
+        }
+    }
+    let days = req.expires_in_days.unwrap_or(DEFAULT_EXPIRY_DAYS);
+    if !(1..=MAX_EXPIRY_DAYS).contains(&days) {
+        return Err(format!("expires_in_days must be 1-{}", MAX_EXPIRY_DAYS));
+    }
+    if let Some(environment) = req.key_environment.as_deref()
+        && crate::key_environments::normalize(environment).is_none()
+    {
+        return Err("key_environment must be 1-32 of [a-z0-9_-]".into());
+    }
+    Ok((name.to_string(), scopes, days))
+}
+
+/// Creator the caller is limited to: the subject of a non-admin web session,
+/// `None` for callers that may manage every token.
+fn owner_filter(session: Option<&Session>) -> Option<String> {
+    session
+        .filter(|s| s.role != Role::Admin)
+        .map(|s| s.subject.clone())
+}
+
+/// `GET /api/api-tokens` — list API tokens (never the secrets)
+pub async fn list_tokens(
+    State(state): State<AppState>,
+    session: Option<Extension<Session>>,
+) -> Result<Json<Value>, StatusCode> {
+    let owner = owner_filter(session.as_ref().map(|Extension(s)| s));
+    let rows = sqlx::query_as::<_, ApiTokenRow>(&format!(
+        "SELECT {} FROM ch_api_tokens WHERE ($1::TEXT IS NULL OR created_by = $1) \
+         ORDER BY created_at DESC",
+        TOKEN_COLUMNS
+    ))
+    .bind(&owner)
+    .fetch_all(&state.db)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to list API tokens: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    Ok(Json(json!({ "tokens": rows })))
+}
+
+/// `POST /api/api-tokens` — mint a token; the plaintext is only in this response
+pub async fn create_token(
+    State(state): State<AppState>,
+    session: Option<Extension<Session>>,
+    Json(req): Json<CreateTokenRequest>,
+) -> Response {
+    let (name, scopes, days) = match validate(&req) {
+        Ok(v) => v,
+        Err(e) => return error(StatusCode::BAD_REQUEST, &e),
+    };
+    let created_by = session.as_ref().map(|Extension(s)| s.subject.clone());
+    // Web sessions can't mint tokens more powerful than themselves.
+    if let Some(Extension(s)) = &session
+        && s.role != Role::Admin
+        && scopes.contains(&Scope::Admin)
+    {
+        return error(StatusCode::FORBIDDEN, "Only admins can mint admin-scoped tokens");
+    }
+
+    let token = generate_token();
+    let scope_names: Vec<&str> = scopes.iter().map(|s| s.as_str()).collect();
+    let row = sqlx::query_as::<_, ApiTokenRow>(&format!(
+        "INSERT INTO ch_api_tokens (name, token_hash, prefix, scopes, created_by, expires_at, default_priority, key_environment) \
+         VALUES ($1, $2, $3, $4, $5, NOW() + make_interval(days => $6), $7, $8) RETURNING {}",
+        TOKEN_COLUMNS
+    ))
+    .bind(&name)
+    .bind(hash_token(&token))
+    .bind(&token[..TOKEN_PREFIX.len() + 8])
+    .bind(&scope_names)
+    .bind(&created_by)
+    .bind(days as i32)
+    .bind(req.default_priority.unwrap_or_default().as_str())
+    .bind(
+        req.key_environment
+            .as_deref()
+            .and_then(crate::key_environments::normalize)
+            .filter(|e| e != crate::key_environments::DEFAULT_ENVIRONMENT),
+    )
+    .fetch_one(&state.db)
+    .await;
+
+    match row {
+        Ok(row) => {
+            tracing::info!("API token '{}' created ({:?})", row.name, row.scopes);
+            (
+                StatusCode::CREATED,
+                Json(json!({
+                    "token": token,
+                    "warning": "Store this token now — it cannot be shown again",
+                    "details": row,
+                })),
+            )
+                .into_response()
+        }
+        Err(e) => {
+            tracing::error!("Failed to create API token: {}", e);
+            error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to create API token")
+        }
+    }
+}
+
+/// `DELETE /api/api-tokens/{id}` — revoke a token (kept for the audit trail)
+pub async fn revoke_token(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    session: Option<Extension<Session>>,
+) -> Result<StatusCode, StatusCode> {
+    let id: uuid::Uuid = id.parse().map_err(|_| StatusCode::BAD_REQUEST)?;
+    let owner = owner_filter(session.as_ref().map(|Extension(s)| s));
+    // Someone else's token reads as unknown (404), like a revoked one.
+    let result = sqlx::query(
+        "UPDATE ch_api_tokens SET revoked_at = NOW() \
+         WHERE id = $1 AND revoked_at IS NULL AND ($2::TEXT IS NULL OR created_by = $2)",
+    )
+    .bind(id)
+    .bind(&owner)
+    .execute(&state.db)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to revoke API token: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    tracing::info!("API token {} revoked", id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(name: &str, scopes: &[&str], days: Option<i64>) -> CreateTokenRequest {
+        CreateTokenRequest {
+            name: name.into(),
+            scopes: scopes.iter().map(|s| s.to_string()).collect(),
+            expires_in_days: days,
+            default_priority: None,
+            key_environment: None,
+        }
+    }
+
+    #[test]
+    fn scopes_limit_reach() {
+        assert!(Scope::Chat.allows(&Method::POST, "/api/claude/chat/stream"));
+        assert!(Scope::Chat.allows(&Method::POST, "/api/gemini/chat"));
+        assert!(Scope::Chat.allows(&Method::POST, "/api/gemini/chat/stream"));
+        assert!(!Scope::Chat.allows(&Method::GET, "/api/sessions"));
+        assert!(Scope::Chat.allows(&Method::POST, "/api/sessions/abc/chat"));
+        assert!(Scope::Chat.allows(&Method::POST, "/api/sessions/abc/chat/stream"));
+        assert!(!Scope::Chat.allows(&Method::GET, "/api/sessions/abc/chat"));
+        assert!(!Scope::Chat.allows(&Method::POST, "/api/sessions/abc/messages"));
+        assert!(!Scope::Chat.allows(&Method::POST, "/api/sessions/abc/x/chat"));
+        assert!(Scope::Chat.allows(&Method::POST, "/api/agents/agent-001/chat"));
+        assert!(!Scope::Chat.allows(&Method::POST, "/api/agents/agent-001/run"));
+        assert!(!Scope::Chat.allows(&Method::PATCH, "/api/agents/agent-001"));
+        assert!(Scope::Read.allows(&Method::GET, "/api/sessions"));
+        assert!(!Scope::Read.allows(&Method::POST, "/api/sessions"));
+        assert!(!Scope::Read.allows(&Method::GET, "/api/api-tokens"));
+        assert!(!Scope::Read.allows(&Method::GET, "/api/tokens"));
+        assert!(!Scope::Read.allows(&Method::GET, "/api/debug/pprof/heap"));
+        assert!(Scope::Admin.allows(&Method::DELETE, "/api/api-tokens/x"));
+        // WebSocket chat starts generations through a GET upgrade
+        for socket in ["/ws/chat", "/api/ws"] {
+            assert!(!Scope::Read.allows(&Method::GET, socket));
+            assert!(!Scope::Chat.allows(&Method::GET, socket));
+            assert!(Scope::Admin.allows(&Method::GET, socket));
+        }
+        assert!(Scope::Read.allows(&Method::GET, "/ws/sessions/abc"));
+    }
+
+    #[test]
+    fn create_requests_are_validated() {
+        let (name, scopes, days) = validate(&request(" ci ", &["chat", "chat", "read"], None)).unwrap();
+        assert_eq!(name, "ci");
+        assert_eq!(scopes, vec![Scope::Chat, Scope::Read]);
+        assert_eq!(days, DEFAULT_EXPIRY_DAYS);
+
+        assert!(validate(&request("", &["chat"], None)).is_err());
+        assert!(validate(&request("ci", &[], None)).is_err());
+        assert!(validate(&request("ci", &["write"], None)).is_err());
+        assert!(validate(&request("ci", &["read"], Some(0))).is_err());
+        assert!(validate(&request("ci", &["read"], Some(400))).is_err());
+    }
+
+    #[test]
+    fn only_non_admin_sessions_are_limited_to_their_tokens() {
+        let session = |role| Session {
+            expires_at: 0,
+            subject: "user-1".into(),
+            role,
+        };
+        assert_eq!(owner_filter(Some(&session(Role::Member))), Some("user-1".into()));
+        assert_eq!(owner_filter(Some(&session(Role::Viewer))), Some("user-1".into()));
+        assert_eq!(owner_filter(Some(&session(Role::Admin))), None);
+        assert_eq!(owner_filter(None), None);
+    }
+
+    #[test]
+    fn tokens_are_prefixed_and_hashed() {
+        let token = generate_token();
+        assert!(token.starts_with(TOKEN_PREFIX));
+        assert_eq!(token.len(), TOKEN_PREFIX.len() + 64);
+        assert_eq!(hash_token(&token).len(), 64);
+        assert_ne!(hash_token(&token), hash_token(&generate_token()));
+    }
+}
@@ -0,0 +1,418 @@
+// ClaudeHydra v4 -- Mermaid / LaTeX artifacts with server-side SVG rendering
+// Assistant replies are scanned for diagrams and display math — ```mermaid
+// fences, ```latex / ```tex / ```math fences and `$$ … $$` blocks — and each
+// one is stored in ch_artifacts against its message. Replies written by the
+// WebSocket chat and linked NDJSON streams are scanned when stored; any other
+// reply is picked up the first time its session's artifacts are listed or
+// exported.
+//
+// `GET /api/artifacts/{id}/render` returns the artifact as SVG, rendered once
+// and cached in the row. Rendering shells out to external tools, so exports
+// and shared links get real diagrams without a browser:
+//   CH_MERMAID_CMD  (default `mmdc`,    @mermaid-js/mermaid-cli)  -i in.mmd -o out.svg
+//   CH_LATEX_CMD    (default `tex2svg`, mathjax-node-cli)         "<tex>" → SVG on stdout
+// A missing tool gives 503, a source the tool rejects gives 422.
+//
+// Endpoints:
+// - `GET /api/sessions/{id}/artifacts` — artifacts of a session, oldest first
+// - `GET /api/artifacts/{id}`          — one artifact with its source
+// - `GET /api/artifacts/{id}/render`   — the artifact as `image/svg+xml`
+
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use axum::Json;
+use axum::extract::{Path, Query, State};
+use axum::http::{StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::state::AppState;
+
+const RENDER_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_SOURCE_CHARS: usize = 50_000;
+const ERROR_PREVIEW_CHARS: usize = 500;
+
+type ApiError = (StatusCode, Json<Value>);
+
+fn api_error(status: StatusCode, msg: impl Into<String>) -> ApiError {
+    (status, Json(json!({ "error": msg.into() })))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArtifactKind {
+    Mermaid,
+    Latex,
+}
+
+impl ArtifactKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Mermaid => "mermaid",
+            Self::Latex => "latex",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "mermaid" => Some(Self::Mermaid),
+            "latex" => Some(Self::Latex),
+            _ => None,
+        }
+    }
+}
+
+static FENCE_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?ms)^[ \t]*```[ \t]*(mermaid|latex|tex|math)[ \t]*\r?\n(.*?)^[ \t]*```").unwrap()
+});
+static ANY_FENCE_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?ms)^[ \t]*```.*?^[ \t]*```").unwrap());
+static DISPLAY_MATH_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?s)\$\$(.+?)\$\$").unwrap());
+
+/// Diagrams and display math in a reply, in order of appearance.
+pub fn extract(content: &str) -> Vec<(ArtifactKind, String)> {
+    let mut found: Vec<(usize, ArtifactKind, String)> = FENCE_RE
+        .captures_iter(content)
+        .filter_map(|c| {
+            let kind = match &c[1] {
+                "mermaid" => ArtifactKind::Mermaid,
+                _ => ArtifactKind::Latex,
+            };
+            let source = c[2].trim();
+            (!source.is_empty()).then(|| (c.get(0).map_or(0, |m| m.start()), kind, source.to_string()))
+        })
+        .collect();
+
+    // `$$ … $$` outside code fences (a fence may show LaTeX as code).
+    let fences: Vec<(usize, usize)> = ANY_FENCE_RE
+        .find_iter(content)
+        .map(|m| (m.start(), m.end()))
+        .collect();
+    for m in DISPLAY_MATH_RE.captures_iter(content) {
+        let whole = m.get(0).expect("match");
+        if fences.iter().any(|&(s, e)| whole.start() >= s && whole.start() < e) {
+            continue;
+        }
+        let source = m[1].trim();
+        if !source.is_empty() {
+            found.push((whole.start(), ArtifactKind::Latex, source.to_string()));
+        }
+    }
+
+    found.sort_by_key(|(pos, _, _)| *pos);
+    found
+        .into_iter()
+        .filter(|(_, _, source)| source.chars().count() <= MAX_SOURCE_CHARS)
+        .map(|(_, kind, source)| (kind, source))
+        .collect()
+}
+
+/// Store the artifacts of one assistant message (idempotent per message).
+pub async fn store_from_message(
+    db: &sqlx::PgPool,
+    message_id: uuid::Uuid,
+    session_id: uuid::Uuid,
+    content: &str,
+) {
+    for (seq, (kind, source)) in extract(content).into_iter().enumerate() {
+        if let Err(e) = sqlx::query(
+            "INSERT INTO ch_artifacts (message_id, session_id, seq, kind, source) \
+             VALUES ($1, $2, $3, $4, $5) ON CONFLICT (message_id, seq) DO NOTHING",
+        )
+        .bind(message_id)
+        .bind(session_id)
+        .bind(seq as i32)
+        .bind(kind.as_str())
+        .bind(&source)
+        .execute(db)
+        .await
+        {
+            tracing::warn!("artifacts: failed to store artifact for {}: {}", message_id, e);
+            return;
+        }
+    }
+}
+
+/// Scan a stored assistant message (replies persisted by the shared handlers).
+pub async fn scan_message(db: &sqlx::PgPool, message_id: uuid::Uuid) {
+    let row = sqlx::query_as::<_, (uuid::Uuid, String)>(
+        "SELECT session_id, content FROM ch_messages WHERE id = $1 AND role = 'assistant'",
+    )
+    .bind(message_id)
+    .fetch_optional(db)
+    .await;
+    if let Ok(Some((session_id, content))) = row {
+        store_from_message(db, message_id, session_id, &content).await;
+    }
+}
+
+/// Pick up artifacts in a session's replies that were not scanned yet.
+pub async fn sync_session(db: &sqlx::PgPool, session_id: uuid::Uuid) -> Result<(), sqlx::Error> {
+    let rows = sqlx::query_as::<_, (uuid::Uuid, String)>(
+        "SELECT m.id, m.content FROM ch_messages m \
+         WHERE m.session_id = $1 AND m.role = 'assistant' \
+         AND (m.content LIKE '%```%' OR m.content LIKE '%$$%') \
+         AND NOT EXISTS (SELECT 1 FROM ch_artifacts a WHERE a.message_id = m.id)",
+    )
+    .bind(session_id)
+    .fetch_all(db)
+    .await?;
+    for (message_id, content) in rows {
+        store_from_message(db, message_id, session_id, &content).await;
+    }
+    Ok(())
+}
+
+/// `message_id → [{id, kind}]` for a session, for exports.
+pub async fn by_message(
+    db: &sqlx::PgPool,
+    session_id: uuid::Uuid,
+) -> std::collections::HashMap<String, Vec<Value>> {
+    let _ = sync_session(db, session_id).await;
+    let rows = sqlx::query_as::<_, (uuid::Uuid, uuid::Uuid, String)>(
+        "SELECT id, message_id, kind FROM ch_artifacts WHERE session_id = $1 ORDER BY message_id, seq",
+    )
+    .bind(session_id)
+    .fetch_all(db)
+    .await
+    .unwrap_or_default();
+    let mut map: std::collections::HashMap<String, Vec<Value>> = std::collections::HashMap::new();
+    for (id, message_id, kind) in rows {
+        map.entry(message_id.to_string())
+            .or_default()
+            .push(json!({ "id": id, "kind": kind }));
+    }
+    map
+}
+
+// ── Rendering ────────────────────────────────────────────────────────────────
+
+#[derive(Debug)]
+enum RenderError {
+    /// The renderer binary is not installed.
+    Unavailable(String),
+    /// The renderer rejected the source (or timed out).
+    Failed(String),
+}
+
+fn renderer(kind: ArtifactKind) -> String {
+    let (var, default) = match kind {
+        ArtifactKind::Mermaid => ("CH_MERMAID_CMD", "mmdc"),
+        ArtifactKind::Latex => ("CH_LATEX_CMD", "tex2svg"),
+    };
+    std::env::var(var)
+        .ok()
+        .filter(|v| !v.trim().is_empty())
+        .unwrap_or_else(|| default.to_string())
+}
+
+async fn run(mut cmd: tokio::process::Command, program: &str) -> Result<Vec<u8>, RenderError> {
+    cmd.kill_on_drop(true);
+    let output = match tokio::time::timeout(RENDER_TIMEOUT, cmd.output()).await {
+        Err(_) => return Err(RenderError::Failed(format!("{} timed out", program))),
+        Ok(Err(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(RenderError::Unavailable(format!("{} is not installed", program)));
+        }
+        Ok(Err(e)) => return Err(RenderError::Failed(format!("{} failed to start: {}", program, e))),
+        Ok(Ok(output)) => output,
+    };
+    if !output.status.success() {
+        let stderr: String = String::from_utf8_lossy(&output.stderr)
+            .trim()
+            .chars()
+            .take(ERROR_PREVIEW_CHARS)
+            .collect();
+        return Err(RenderError::Failed(format!("{} failed: {}", program, stderr)));
+    }
+    Ok(output.stdout)
+}
+
+async fn render_svg(kind: ArtifactKind, source: &str) -> Result<String, RenderError> {
+    let program = renderer(kind);
+    let svg = match kind {
+        ArtifactKind::Mermaid => {
+            let base = std::env::temp_dir().join(format!("ch-artifact-{}", uuid::Uuid::new_v4()));
+            let input = base.with_extension("mmd");
+            let output = base.with_extension("svg");
+            tokio::fs::write(&input, source)
+                .await
+                .map_err(|e| RenderError::Failed(format!("cannot write temp file: {}", e)))?;
+            let mut cmd = tokio::process::Command::new(&program);
+            cmd.arg("-i").arg(&input).arg("-o").arg(&output).args(["-b", "transparent", "-q"]);
+            let result = run(cmd, &program).await;
+            let svg = tokio::fs::read(&output).await;
+            let _ = tokio::fs::remove_file(&input).await;
+            let _ = tokio::fs::remove_file(&output).await;
+            result?;
+            svg.map_err(|e| RenderError::Failed(format!("{} wrote no SVG: {}", program, e)))?
+        }
+        ArtifactKind::Latex => {
+            let mut cmd = tokio::process::Command::new(&program);
+            cmd.arg(source);
+            run(cmd, &program).await?
+        }
+    };
+    let svg = String::from_utf8(svg).map_err(|_| RenderError::Failed("renderer output is not UTF-8".into()))?;
+    if !svg.contains("<svg") {
+        return Err(RenderError::Failed(format!("{} did not produce SVG", program)));
+    }
+    Ok(svg)
+}
+
+// ── Handlers ─────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+struct ArtifactRow {
+    id: uuid::Uuid,
+    message_id: uuid::Uuid,
+    session_id: uuid::Uuid,
+    seq: i32,
+    kind: String,
+    source: String,
+    #[serde(skip)]
+    svg: Option<String>,
+    created_at: DateTime<Utc>,
+    rendered_at: Option<DateTime<Utc>>,
+}
+
+impl ArtifactRow {
+    fn to_json(&self) -> Value {
+        json!({
+            "id": self.id,
+            "message_id": self.message_id,
+            "session_id": self.session_id,
+            "seq": self.seq,
+            "kind": self.kind,
+            "source": self.source,
+            "rendered": self.svg.is_some(),
+            "render_url": format!("/api/artifacts/{}/render", self.id),
+            "created_at": self.created_at.to_rfc3339(),
+            "rendered_at": self.rendered_at.map(|t| t.to_rfc3339()),
+        })
+    }
+}
+
+const ARTIFACT_COLUMNS: &str = "id, message_id, session_id, seq, kind, source, svg, created_at, rendered_at";
+
+fn parse_id(id: &str) -> Result<uuid::Uuid, ApiError> {
+    id.parse()
+        .map_err(|_| api_error(StatusCode::BAD_REQUEST, "Invalid id"))
+}
+
+fn db_error(e: sqlx::Error) -> ApiError {
+    tracing::error!("artifacts: database error: {}", e);
+    api_error(StatusCode::INTERNAL_SERVER_ERROR, "Database error")
+}
+
+async fn fetch(db: &sqlx::PgPool, id: uuid::Uuid) -> Result<ArtifactRow, ApiError> {
+    sqlx::query_as::<_, ArtifactRow>(&format!("SELECT {} FROM ch_artifacts WHERE id = $1", ARTIFACT_COLUMNS))
+        .bind(id)
+        .fetch_optional(db)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| api_error(StatusCode::NOT_FOUND, "Artifact not found"))
+}
+
+pub async fn list_session_artifacts(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let session_id = parse_id(&id)?;
+    sync_session(&state.db, session_id).await.map_err(db_error)?;
+    let rows = sqlx::query_as::<_, ArtifactRow>(&format!(
+        "SELECT a.{} FROM ch_artifacts a JOIN ch_messages m ON m.id = a.message_id \
+         WHERE a.session_id = $1 ORDER BY m.created_at ASC, a.seq ASC",
+        ARTIFACT_COLUMNS.replace(", ", ", a.")
+    ))
+    .bind(session_id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(db_error)?;
+    Ok(Json(json!({
+        "session_id": id,
+        "artifacts": rows.iter().map(ArtifactRow::to_json).collect::<Vec<_>>(),
+    })))
+}
+
+pub async fn get_artifact(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let row = fetch(&state.db, parse_id(&id)?).await?;
+    Ok(Json(row.to_json()))
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct RenderParams {
+    /// Re-render even when a cached SVG exists.
+    #[serde(default)]
+    pub refresh: bool,
+}
+
+pub async fn render_artifact(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(params): Query<RenderParams>,
+) -> Result<Response, ApiError> {
+    let row = fetch(&state.db, parse_id(&id)?).await?;
+    let svg = match row.svg {
+        Some(svg) if !params.refresh => svg,
+        _ => {
+            let kind = ArtifactKind::parse(&row.kind)
+                .ok_or_else(|| api_error(StatusCode::UNPROCESSABLE_ENTITY, "Unknown artifact kind"))?;
+            let svg = render_svg(kind, &row.source).await.map_err(|e| match e {
+                RenderError::Unavailable(msg) => api_error(StatusCode::SERVICE_UNAVAILABLE, msg),
+                RenderError::Failed(msg) => api_error(StatusCode::UNPROCESSABLE_ENTITY, msg),
+            })?;
+            sqlx::query("UPDATE ch_artifacts SET svg = $2, rendered_at = NOW() WHERE id = $1")
+                .bind(row.id)
+                .bind(&svg)
+                .execute(&state.db)
+                .await
+                .map_err(db_error)?;
+            svg
+        }
+    };
+    Ok((
+        [
+            (header::CONTENT_TYPE, "image/svg+xml"),
+            (header::CACHE_CONTROL, "private, max-age=86400"),
+            (header::X_CONTENT_TYPE_OPTIONS, "nosniff"),
+            // Opened directly, an SVG must not run script on the API origin.
+            (
+                header::CONTENT_SECURITY_POLICY,
+                "default-src 'none'; style-src 'unsafe-inline'; img-src data:; font-src data:",
+            ),
+        ],
+        svg,
+    )
+        .into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_fenced_diagrams_and_display_math_in_order() {
+        let reply = "Flow:\n```mermaid\ngraph TD\n  A --> B\n```\nEnergy: $$E = mc^2$$\n\n```tex\n\\frac{a}{b}\n```\n";
+        assert_eq!(
+            extract(reply),
+            vec![
+                (ArtifactKind::Mermaid, "graph TD\n  A --> B".to_string()),
+                (ArtifactKind::Latex, "E = mc^2".to_string()),
+                (ArtifactKind::Latex, "\\frac{a}{b}".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn math_inside_other_code_fences_is_ignored() {
+        let reply = "```rust\nlet s = \"$$not math$$\";\n```\nand ```python``` inline.";
+        assert!(extract(reply).is_empty());
+        assert!(extract("```mermaid\n\n```").is_empty());
+    }
+}
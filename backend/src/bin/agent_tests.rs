@@ -0,0 +1,111 @@
+// agent-tests — Run stored agent behavior tests against a running backend
+//
+// Calls POST /api/agents/{id}/tests/run for each agent given on the command
+// line (or every agent with tests when none is given), prints one line per
+// test and exits non-zero when any test fails — suitable as a CI step after
+// prompt changes.
+//
+// Usage:
+//   CH_TARGET=http://localhost:8082 AUTH_SECRET=<secret> \
+//     cargo run --release --bin agent-tests -- agent-001 agent-003
+//
+// Exit codes: 0 all passed, 1 a test failed, 2 the backend could not be used.
+
+use serde_json::{Value, json};
+
+fn fail(msg: impl std::fmt::Display) -> ! {
+    eprintln!("agent-tests: {}", msg);
+    std::process::exit(2);
+}
+
+async fn send(req: reqwest::RequestBuilder, token: Option<&str>) -> Value {
+    let req = match token {
+        Some(t) => req.bearer_auth(t),
+        None => req,
+    };
+    let resp = req.send().await.unwrap_or_else(|e| fail(e));
+    let status = resp.status();
+    let body: Value = resp.json().await.unwrap_or_default();
+    if !status.is_success() {
+        fail(format!(
+            "{} — {}",
+            status,
+            body["error"].as_str().unwrap_or("request failed")
+        ));
+    }
+    body
+}
+
+#[tokio::main]
+async fn main() {
+    let target = std::env::var("CH_TARGET")
+        .unwrap_or_else(|_| "http://localhost:8082".to_string())
+        .trim_end_matches('/')
+        .to_string();
+    let token = std::env::var("AUTH_SECRET").ok().filter(|t| !t.is_empty());
+    let client = reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs(600))
+        .build()
+        .unwrap_or_else(|e| fail(e));
+
+    let mut agents: Vec<String> = std::env::args().skip(1).collect();
+    let explicit = !agents.is_empty();
+    if !explicit {
+        let list = send(client.get(format!("{}/api/agents", target)), token.as_deref()).await;
+        agents = list
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|a| a["id"].as_str().map(str::to_string))
+            .collect();
+    }
+
+    let (mut passed, mut failed) = (0u64, 0u64);
+    for agent in &agents {
+        let run = send(
+            client
+                .post(format!("{}/api/agents/{}/tests/run", target, agent))
+                .json(&json!({})),
+            token.as_deref(),
+        )
+        .await;
+        let results = run["results"].as_array().cloned().unwrap_or_default();
+        if results.is_empty() {
+            if explicit {
+                println!("{}: no tests", agent);
+            }
+            continue;
+        }
+        for r in &results {
+            let ok = r["passed"] == true;
+            println!(
+                "{} {} / {}",
+                if ok { "PASS" } else { "FAIL" },
+                agent,
+                r["name"].as_str().unwrap_or("?")
+            );
+            if ok {
+                passed += 1;
+                continue;
+            }
+            failed += 1;
+            if let Some(err) = r["error"].as_str() {
+                println!("     error: {}", err);
+            }
+            for check in r["expectations"].as_array().into_iter().flatten() {
+                if check["passed"] != true {
+                    println!(
+                        "     {} — {}",
+                        check["expectation"],
+                        check["detail"].as_str().unwrap_or("")
+                    );
+                }
+            }
+        }
+    }
+
+    println!("\n{} passed, {} failed", passed, failed);
+    if failed > 0 {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,466 @@
+// loadtest — Synthetic load generator for the streaming chat pipeline
+//
+// Two modes:
+//
+//   mock — run a local mock of the Anthropic Messages API that streams
+//          synthetic SSE tokens with a fixed inter-token delay. Point the
+//          backend at it with ANTHROPIC_API_URL (and any non-empty
+//          ANTHROPIC_API_KEY) so no real provider traffic is generated.
+//
+//   run  — drive N concurrent streaming chats against a running backend
+//          (POST /api/claude/chat/stream) and report throughput, latency
+//          percentiles, time-to-first-token and memory before/after.
+//          Memory is the backend's resident set (RSS, read from
+//          /proc/<pid>/status) when LOADTEST_PID names the backend process
+//          on this machine; otherwise it is the used memory of the backend's
+//          whole host from GET /api/system/stats, which other processes move
+//          too.
+//
+// Usage:
+//   LOADTEST_MOCK_PORT=8199 cargo run --release --bin loadtest -- mock
+//
+//   ANTHROPIC_API_URL=http://localhost:8199 ANTHROPIC_API_KEY=mock cargo run --release
+//
+//   LOADTEST_TARGET=http://localhost:8082 LOADTEST_CONCURRENCY=50 \
+//   LOADTEST_REQUESTS=1000 AUTH_SECRET=<secret> LOADTEST_PID=$(pgrep -x claudehydra-backend) \
+//   cargo run --release --bin loadtest -- run
+//
+// The execute route group is rate limited; raise the `execute` limit via
+// PATCH /api/admin/rate-limits/{endpoint_group} before large runs.
+
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use axum::Router;
+use axum::body::{Body, Bytes};
+use axum::http::header;
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use futures_util::StreamExt;
+use serde_json::{Value, json};
+use tracing::{error, info, warn};
+
+fn env_or<T: std::str::FromStr>(key: &str, default: T) -> T {
+    std::env::var(key)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+// ── Mock provider ───────────────────────────────────────────────────────────
+
+#[derive(Clone)]
+struct MockConfig {
+    tokens: usize,
+    delay: Duration,
+}
+
+fn sse_event(event: &str, data: Value) -> Bytes {
+    Bytes::from(format!("event: {}\ndata: {}\n\n", event, data))
+}
+
+/// `POST /v1/messages` — Anthropic-compatible mock. Streams `tokens` text
+/// deltas when `stream: true`, otherwise returns a single JSON message.
+async fn mock_messages(
+    axum::extract::State(cfg): axum::extract::State<MockConfig>,
+    axum::Json(body): axum::Json<Value>,
+) -> Response {
+    let model = body
+        .get("model")
+        .and_then(|m| m.as_str())
+        .unwrap_or("claude-mock")
+        .to_string();
+    let id = format!("msg_mock_{}", uuid::Uuid::new_v4().simple());
+
+    if !body.get("stream").and_then(|s| s.as_bool()).unwrap_or(false) {
+        let text = "lorem ".repeat(cfg.tokens);
+        return axum::Json(json!({
+            "id": id,
+            "type": "message",
+            "role": "assistant",
+            "model": model,
+            "content": [{ "type": "text", "text": text }],
+            "stop_reason": "end_turn",
+            "usage": { "input_tokens": 32, "output_tokens": cfg.tokens },
+        }))
+        .into_response();
+    }
+
+    let stream = async_stream::stream! {
+        yield Ok::<_, Infallible>(sse_event("message_start", json!({
+            "type": "message_start",
+            "message": {
+                "id": id, "type": "message", "role": "assistant", "model": model,
+                "content": [], "usage": { "input_tokens": 32, "output_tokens": 0 }
+            }
+        })));
+        yield Ok(sse_event("content_block_start", json!({
+            "type": "content_block_start", "index": 0,
+            "content_block": { "type": "text", "text": "" }
+        })));
+        for i in 0..cfg.tokens {
+            if !cfg.delay.is_zero() {
+                tokio::time::sleep(cfg.delay).await;
+            }
+            yield Ok(sse_event("content_block_delta", json!({
+                "type": "content_block_delta", "index": 0,
+                "delta": { "type": "text_delta", "text": format!("tok{} ", i) }
+            })));
+        }
+        yield Ok(sse_event("content_block_stop", json!({ "type": "content_block_stop", "index": 0 })));
+        yield Ok(sse_event("message_delta", json!({
+            "type": "message_delta",
+            "delta": { "stop_reason": "end_turn" },
+            "usage": { "output_tokens": cfg.tokens }
+        })));
+        yield Ok(sse_event("message_stop", json!({ "type": "message_stop" })));
+    };
+
+    Response::builder()
+        .header(header::CONTENT_TYPE, "text/event-stream")
+        .header(header::CACHE_CONTROL, "no-cache")
+        .body(Body::from_stream(stream))
+        .unwrap_or_else(|_| Response::new(Body::empty()))
+}
+
+async fn run_mock() {
+    let port: u16 = env_or("LOADTEST_MOCK_PORT", 8199);
+    let cfg = MockConfig {
+        tokens: env_or("LOADTEST_MOCK_TOKENS", 200),
+        delay: Duration::from_millis(env_or("LOADTEST_MOCK_DELAY_MS", 5)),
+    };
+    info!(
+        "Mock Anthropic provider on :{} ({} tokens/response, {:?} between tokens)",
+        port, cfg.tokens, cfg.delay
+    );
+
+    let app = Router::new()
+        .route("/v1/messages", post(mock_messages))
+        .with_state(cfg);
+    let listener = match tokio::net::TcpListener::bind(("0.0.0.0", port)).await {
+        Ok(l) => l,
+        Err(e) => {
+            error!("Failed to bind mock provider port {}: {}", port, e);
+            std::process::exit(1);
+        }
+    };
+    if let Err(e) = axum::serve(listener, app).await {
+        error!("Mock provider stopped: {}", e);
+    }
+}
+
+// ── Load runner ─────────────────────────────────────────────────────────────
+
+struct Sample {
+    ok: bool,
+    latency: Duration,
+    first_token: Option<Duration>,
+    tokens: usize,
+}
+
+async fn one_stream(client: &reqwest::Client, url: &str, token: Option<&str>, n: usize) -> Sample {
+    let started = Instant::now();
+    let mut req = client.post(url).json(&json!({
+        "messages": [{ "role": "user", "content": format!("Load test prompt #{}", n) }],
+        "stream": true,
+    }));
+    if let Some(t) = token {
+        req = req.bearer_auth(t);
+    }
+
+    let failed = |started: Instant| Sample {
+        ok: false,
+        latency: started.elapsed(),
+        first_token: None,
+        tokens: 0,
+    };
+
+    let resp = match req.send().await {
+        Ok(r) if r.status().is_success() => r,
+        Ok(r) => {
+            warn!("request #{} failed with status {}", n, r.status());
+            return failed(started);
+        }
+        Err(e) => {
+            warn!("request #{} failed: {}", n, e);
+            return failed(started);
+        }
+    };
+
+    let mut stream = resp.bytes_stream();
+    let mut buf = String::new();
+    let mut first_token = None;
+    let mut tokens = 0usize;
+    let mut done = false;
+
+    while let Some(chunk) = stream.next().await {
+        let Ok(chunk) = chunk else {
+            return failed(started);
+        };
+        buf.push_str(&String::from_utf8_lossy(&chunk));
+        while let Some(nl) = buf.find('\n') {
+            let line = buf[..nl].to_string();
+            buf.drain(..=nl);
+            let Ok(frame) = serde_json::from_str::<Value>(&line) else {
+                continue;
+            };
+            if frame.get("done").and_then(|d| d.as_bool()).unwrap_or(false) {
+                done = true;
+            } else if frame
+                .get("token")
+                .and_then(|t| t.as_str())
+                .is_some_and(|t| !t.is_empty())
+            {
+                first_token.get_or_insert_with(|| started.elapsed());
+                tokens += 1;
+            }
+        }
+    }
+
+    Sample {
+        ok: done,
+        latency: started.elapsed(),
+        first_token,
+        tokens,
+    }
+}
+
+/// The value at quantile `p` (0..=1) of `sorted`, nearest rank.
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let idx = ((sorted.len() as f64 - 1.0) * p).round() as usize;
+    sorted[idx.min(sorted.len() - 1)]
+}
+
+/// p50 / p95 / p99 of `sorted`.
+fn percentiles(sorted: &[Duration]) -> [Duration; 3] {
+    [percentile(sorted, 0.50), percentile(sorted, 0.95), percentile(sorted, 0.99)]
+}
+
+/// What a run reports, computed from its samples and wall time.
+#[derive(Debug)]
+struct Summary {
+    ok: usize,
+    failed: usize,
+    requests_per_sec: f64,
+    tokens_per_sec: f64,
+    latency: [Duration; 3],
+    first_token: [Duration; 3],
+}
+
+impl Summary {
+    /// Throughput and percentiles count successful streams only.
+    fn of(samples: &[Sample], wall: Duration) -> Self {
+        let ok: Vec<&Sample> = samples.iter().filter(|s| s.ok).collect();
+        let mut latencies: Vec<Duration> = ok.iter().map(|s| s.latency).collect();
+        latencies.sort();
+        let mut ttft: Vec<Duration> = ok.iter().filter_map(|s| s.first_token).collect();
+        ttft.sort();
+        let tokens: usize = ok.iter().map(|s| s.tokens).sum();
+        let secs = wall.as_secs_f64().max(f64::EPSILON);
+        Self {
+            ok: ok.len(),
+            failed: samples.len() - ok.len(),
+            requests_per_sec: ok.len() as f64 / secs,
+            tokens_per_sec: tokens as f64 / secs,
+            latency: percentiles(&latencies),
+            first_token: percentiles(&ttft),
+        }
+    }
+}
+
+/// Where the memory figure comes from.
+#[derive(Debug, Clone, Copy)]
+enum MemorySource {
+    /// Resident set of the backend process (LOADTEST_PID).
+    ProcessRss(u32),
+    /// Used memory of the backend's host (GET /api/system/stats).
+    Host,
+}
+
+impl MemorySource {
+    fn label(self) -> &'static str {
+        match self {
+            Self::ProcessRss(_) => "Backend RSS",
+            Self::Host => "Host memory",
+        }
+    }
+}
+
+/// `VmRSS` of a `/proc/<pid>/status` file, in MB.
+fn vm_rss_mb(status: &str) -> Option<f64> {
+    let line = status.lines().find(|l| l.starts_with("VmRSS:"))?;
+    let kb: f64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb / 1024.0)
+}
+
+async fn memory_mb(
+    source: MemorySource,
+    client: &reqwest::Client,
+    base: &str,
+    token: Option<&str>,
+) -> Option<f64> {
+    match source {
+        MemorySource::ProcessRss(pid) => {
+            let status = tokio::fs::read_to_string(format!("/proc/{}/status", pid)).await.ok()?;
+            vm_rss_mb(&status)
+        }
+        MemorySource::Host => {
+            let mut req = client.get(format!("{}/api/system/stats", base));
+            if let Some(t) = token {
+                req = req.bearer_auth(t);
+            }
+            let body: Value = req.send().await.ok()?.json().await.ok()?;
+            body.get("memory_used_mb").and_then(|m| m.as_f64())
+        }
+    }
+}
+
+async fn run_load() {
+    let base = std::env::var("LOADTEST_TARGET").unwrap_or_else(|_| "http://localhost:8082".to_string());
+    let base = base.trim_end_matches('/').to_string();
+    let concurrency: usize = env_or("LOADTEST_CONCURRENCY", 20).max(1);
+    let total: usize = env_or("LOADTEST_REQUESTS", 200).max(1);
+    let token = std::env::var("AUTH_SECRET").ok().filter(|t| !t.is_empty());
+    let memory = match std::env::var("LOADTEST_PID").ok().and_then(|p| p.trim().parse().ok()) {
+        Some(pid) => MemorySource::ProcessRss(pid),
+        None => MemorySource::Host,
+    };
+
+    let client = reqwest::Client::builder()
+        .pool_max_idle_per_host(concurrency)
+        .timeout(Duration::from_secs(300))
+        .build()
+        .unwrap_or_default();
+    let url = format!("{}/api/claude/chat/stream", base);
+
+    info!("Driving {} streaming chats against {} ({} concurrent)", total, url, concurrency);
+    let mem_before = memory_mb(memory, &client, &base, token.as_deref()).await;
+
+    let next = Arc::new(AtomicUsize::new(0));
+    let started = Instant::now();
+    let mut workers = tokio::task::JoinSet::new();
+    for _ in 0..concurrency {
+        let client = client.clone();
+        let url = url.clone();
+        let token = token.clone();
+        let next = next.clone();
+        workers.spawn(async move {
+            let mut samples = Vec::new();
+            loop {
+                let n = next.fetch_add(1, Ordering::Relaxed);
+                if n >= total {
+                    break;
+                }
+                samples.push(one_stream(&client, &url, token.as_deref(), n).await);
+            }
+            samples
+        });
+    }
+
+    let mut samples = Vec::with_capacity(total);
+    while let Some(res) = workers.join_next().await {
+        match res {
+            Ok(s) => samples.extend(s),
+            Err(e) => error!("worker panicked: {}", e),
+        }
+    }
+    let wall = started.elapsed();
+    let mem_after = memory_mb(memory, &client, &base, token.as_deref()).await;
+
+    let summary = Summary::of(&samples, wall);
+    info!("");
+    info!("=== Load test results ===");
+    info!("  Requests:      {} ok / {} failed", summary.ok, summary.failed);
+    info!("  Wall time:     {:.2}s", wall.as_secs_f64());
+    info!("  Throughput:    {:.1} req/s, {:.0} tokens/s", summary.requests_per_sec, summary.tokens_per_sec);
+    let [p50, p95, p99] = summary.latency;
+    info!("  Latency:       p50 {:?}  p95 {:?}  p99 {:?}", p50, p95, p99);
+    let [p50, p95, p99] = summary.first_token;
+    info!("  First token:   p50 {:?}  p95 {:?}  p99 {:?}", p50, p95, p99);
+    match (mem_before, mem_after) {
+        (Some(b), Some(a)) => info!("  {:<14} {:.0} MB -> {:.0} MB ({:+.0} MB)", format!("{}:", memory.label()), b, a, a - b),
+        _ => warn!("  {:<14} unavailable", format!("{}:", memory.label())),
+    }
+
+    if summary.failed > 0 {
+        std::process::exit(2);
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    tracing_subscriber::fmt()
+        .with_target(false)
+        .with_env_filter(
+            tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info")),
+        )
+        .init();
+
+    match std::env::args().nth(1).as_deref() {
+        Some("mock") => run_mock().await,
+        Some("run") | None => run_load().await,
+        Some(other) => {
+            error!("Unknown mode '{}' — expected `mock` or `run`", other);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    #[test]
+    fn percentiles_use_the_nearest_rank() {
+        let sorted: Vec<Duration> = (1..=100).map(ms).collect();
+        assert_eq!(percentiles(&sorted), [ms(51), ms(95), ms(99)]);
+        assert_eq!(percentile(&sorted, 0.0), ms(1));
+        assert_eq!(percentile(&sorted, 1.0), ms(100));
+        assert_eq!(percentiles(&[ms(7)]), [ms(7); 3]);
+        assert_eq!(percentiles(&[]), [Duration::ZERO; 3]);
+    }
+
+    #[test]
+    fn summary_counts_only_successful_streams() {
+        let sample = |ok, latency, first_token: Option<u64>, tokens| Sample {
+            ok,
+            latency: ms(latency),
+            first_token: first_token.map(ms),
+            tokens,
+        };
+        let samples = [
+            sample(true, 300, Some(100), 50),
+            sample(true, 100, Some(20), 30),
+            sample(true, 200, None, 0),
+            sample(false, 5, None, 0),
+        ];
+        let summary = Summary::of(&samples, Duration::from_secs(2));
+        assert_eq!((summary.ok, summary.failed), (3, 1));
+        assert!((summary.requests_per_sec - 1.5).abs() < 1e-9);
+        assert!((summary.tokens_per_sec - 40.0).abs() < 1e-9);
+        assert_eq!(summary.latency, [ms(200), ms(300), ms(300)]);
+        // Streams without a token have no time-to-first-token.
+        assert_eq!(summary.first_token, [ms(100), ms(100), ms(100)]);
+
+        let empty = Summary::of(&[], Duration::ZERO);
+        assert_eq!((empty.ok, empty.failed), (0, 0));
+        assert_eq!(empty.requests_per_sec, 0.0);
+    }
+
+    #[test]
+    fn rss_is_read_from_proc_status() {
+        let status = "Name:\tclaudehydra\nVmPeak:\t  900000 kB\nVmRSS:\t  262144 kB\nThreads:\t24\n";
+        assert_eq!(vm_rss_mb(status), Some(256.0));
+        assert_eq!(vm_rss_mb("Name:\tkthreadd\n"), None);
+    }
+}
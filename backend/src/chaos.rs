@@ -0,0 +1,317 @@
+// ClaudeHydra v4 -- Fault injection (feature `chaos`)
+// Test builds only: `cargo build --features chaos`. Faults are injected
+// between the backend and the model providers, so the real retry, circuit
+// breaker, failover and partial-persistence code handles them:
+//   - drop_connection  — the request fails as if the connection broke,
+//   - upstream_529     — a synthetic 529 `overloaded_error` answer,
+//   - truncate_stream  — the response body ends with an error part-way,
+//   - malformed_sse    — an unparseable event is put into an SSE stream,
+//   - slow_stream      — every body chunk is delayed by `slow_stream_delay_ms`.
+// Each is a probability (0–1) rolled per upstream request. Faults are set
+// with `PUT /api/admin/chaos`, are per process and switch themselves off
+// after `ttl_secs`. `GET /api/admin/chaos` also counts what was injected.
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use axum::Json;
+use axum::extract::State;
+use axum::http::StatusCode;
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use utoipa::ToSchema;
+
+use crate::state::AppState;
+
+const DEFAULT_TTL_SECS: u64 = 600;
+const MAX_TTL_SECS: u64 = 24 * 3600;
+const MAX_SLOW_STREAM_DELAY_MS: u64 = 30_000;
+const MALFORMED_EVENT: &[u8] = b"event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"delta\":{\"te\n\n";
+
+type ApiError = (StatusCode, Json<Value>);
+
+/// Fault probabilities, set with `PUT /api/admin/chaos`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(default)]
+pub struct Faults {
+    /// Providers to inject into (`anthropic`, `google`); empty = all.
+    pub providers: Vec<String>,
+    pub drop_connection: f64,
+    pub upstream_529: f64,
+    pub truncate_stream: f64,
+    pub malformed_sse: f64,
+    pub slow_stream: f64,
+    pub slow_stream_delay_ms: u64,
+    /// Faults switch off after this long (default 600, max 86400).
+    pub ttl_secs: Option<u64>,
+}
+
+impl Faults {
+    fn normalized(mut self) -> Self {
+        for rate in [
+            &mut self.drop_connection,
+            &mut self.upstream_529,
+            &mut self.truncate_stream,
+            &mut self.malformed_sse,
+            &mut self.slow_stream,
+        ] {
+            *rate = if rate.is_finite() { rate.clamp(0.0, 1.0) } else { 0.0 };
+        }
+        self.slow_stream_delay_ms = self.slow_stream_delay_ms.min(MAX_SLOW_STREAM_DELAY_MS);
+        self.ttl_secs = Some(self.ttl_secs.unwrap_or(DEFAULT_TTL_SECS).clamp(1, MAX_TTL_SECS));
+        self.providers.retain(|p| !p.trim().is_empty());
+        self
+    }
+
+    fn applies_to(&self, provider: &str) -> bool {
+        self.providers.is_empty() || self.providers.iter().any(|p| p == provider)
+    }
+}
+
+struct Active {
+    faults: Faults,
+    until: Instant,
+}
+
+static ACTIVE: Mutex<Option<Active>> = Mutex::new(None);
+static INJECTED: Mutex<BTreeMap<&'static str, u64>> = Mutex::new(BTreeMap::new());
+
+/// Faults in effect for `provider`, if any.
+fn active(provider: &str) -> Option<Faults> {
+    let mut active = ACTIVE.lock().ok()?;
+    if active.as_ref().is_some_and(|a| Instant::now() >= a.until) {
+        tracing::warn!("chaos: fault injection expired");
+        *active = None;
+    }
+    active
+        .as_ref()
+        .map(|a| a.faults.clone())
+        .filter(|f| f.applies_to(provider))
+}
+
+fn hit(rate: f64) -> bool {
+    rate > 0.0 && rand::random::<f64>() < rate
+}
+
+fn count(fault: &'static str, provider: &str) {
+    tracing::warn!("chaos: injecting {} into a {} request", fault, provider);
+    if let Ok(mut injected) = INJECTED.lock() {
+        *injected.entry(fault).or_default() += 1;
+    }
+}
+
+/// Body faults picked for one response.
+#[derive(Debug, Default, Clone, Copy)]
+struct BodyFaults {
+    truncate: bool,
+    malformed: bool,
+    delay: Option<Duration>,
+}
+
+/// Send an upstream request (`send`) with the active faults applied.
+pub async fn around<F>(provider: &'static str, send: F) -> Result<reqwest::Response, ApiError>
+where
+    F: Future<Output = Result<reqwest::Response, ApiError>>,
+{
+    let Some(faults) = active(provider) else {
+        return send.await;
+    };
+    if hit(faults.drop_connection) {
+        count("drop_connection", provider);
+        return Err((
+            StatusCode::BAD_GATEWAY,
+            Json(json!({ "error": "AI provider request failed" })),
+        ));
+    }
+    if hit(faults.upstream_529) {
+        count("upstream_529", provider);
+        return Ok(overloaded());
+    }
+    let resp = send.await?;
+    if !resp.status().is_success() {
+        return Ok(resp);
+    }
+    let sse = resp
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/event-stream"));
+    let body = BodyFaults {
+        truncate: hit(faults.truncate_stream),
+        malformed: sse && hit(faults.malformed_sse),
+        delay: (faults.slow_stream_delay_ms > 0 && hit(faults.slow_stream))
+            .then(|| Duration::from_millis(faults.slow_stream_delay_ms)),
+    };
+    for (on, fault) in [
+        (body.truncate, "truncate_stream"),
+        (body.malformed, "malformed_sse"),
+        (body.delay.is_some(), "slow_stream"),
+    ] {
+        if on {
+            count(fault, provider);
+        }
+    }
+    if !body.truncate && !body.malformed && body.delay.is_none() {
+        return Ok(resp);
+    }
+    Ok(with_body_faults(resp, body))
+}
+
+/// A 529 answer shaped like Anthropic's.
+fn overloaded() -> reqwest::Response {
+    let body = json!({ "type": "error", "error": { "type": "overloaded_error", "message": "Overloaded (injected fault)" } });
+    let resp = http::Response::builder()
+        .status(529)
+        .header("content-type", "application/json")
+        .body(body.to_string().into_bytes())
+        .expect("static response parts are valid");
+    reqwest::Response::from(resp)
+}
+
+/// `resp` with its body slowed down, broken off or corrupted.
+fn with_body_faults(resp: reqwest::Response, faults: BodyFaults) -> reqwest::Response {
+    let mut builder = http::Response::builder().status(resp.status().as_u16());
+    for (name, value) in resp.headers() {
+        if name != reqwest::header::CONTENT_LENGTH {
+            builder = builder.header(name.as_str(), value.as_bytes());
+        }
+    }
+    let mut upstream = resp.bytes_stream();
+    let stream = async_stream::stream! {
+        let mut chunks = 0usize;
+        while let Some(chunk) = upstream.next().await {
+            if let Some(delay) = faults.delay {
+                tokio::time::sleep(delay).await;
+            }
+            chunks += 1;
+            match chunk {
+                Ok(bytes) => yield Ok::<_, Box<dyn std::error::Error + Send + Sync>>(bytes),
+                Err(e) => {
+                    yield Err(e.into());
+                    return;
+                }
+            }
+            if chunks == 1 && faults.malformed {
+                yield Ok(axum::body::Bytes::from_static(MALFORMED_EVENT));
+            }
+            if chunks == 2 && faults.truncate {
+                yield Err("connection reset by peer (injected fault)".into());
+                return;
+            }
+        }
+    };
+    let resp = builder
+        .body(reqwest::Body::wrap_stream(stream))
+        .expect("upstream response parts are valid");
+    reqwest::Response::from(resp)
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  /api/admin/chaos
+// ═══════════════════════════════════════════════════════════════════════
+
+fn status() -> Value {
+    let active = ACTIVE.lock().ok().and_then(|a| {
+        a.as_ref()
+            .filter(|a| Instant::now() < a.until)
+            .map(|a| (a.faults.clone(), a.until.saturating_duration_since(Instant::now()).as_secs()))
+    });
+    let injected = INJECTED.lock().map(|i| i.clone()).unwrap_or_default();
+    json!({
+        "enabled": active.is_some(),
+        "faults": active.as_ref().map(|(f, _)| f),
+        "expires_in_secs": active.as_ref().map(|(_, secs)| secs),
+        "injected": injected,
+    })
+}
+
+/// `GET /api/admin/chaos` — active faults and what was injected so far
+#[utoipa::path(get, path = "/api/admin/chaos", tag = "system",
+    responses((status = 200, description = "Active faults, time left and injection counts")))]
+pub async fn get_faults() -> Json<Value> {
+    Json(status())
+}
+
+/// `PUT /api/admin/chaos` — replace the active faults
+#[utoipa::path(put, path = "/api/admin/chaos", tag = "system",
+    request_body = Faults,
+    responses((status = 200, description = "Faults are active until they expire")))]
+pub async fn set_faults(State(state): State<AppState>, Json(faults): Json<Faults>) -> Json<Value> {
+    let faults = faults.normalized();
+    let ttl = Duration::from_secs(faults.ttl_secs.unwrap_or(DEFAULT_TTL_SECS));
+    tracing::warn!("chaos: fault injection enabled for {}s: {:?}", ttl.as_secs(), faults);
+    crate::audit::log_audit(&state.db, "set_chaos_faults", json!(faults), None).await;
+    if let Ok(mut active) = ACTIVE.lock() {
+        *active = Some(Active {
+            faults,
+            until: Instant::now() + ttl,
+        });
+    }
+    if let Ok(mut injected) = INJECTED.lock() {
+        injected.clear();
+    }
+    Json(status())
+}
+
+/// `DELETE /api/admin/chaos` — stop injecting faults
+#[utoipa::path(delete, path = "/api/admin/chaos", tag = "system",
+    responses((status = 200, description = "Fault injection off")))]
+pub async fn clear_faults(State(state): State<AppState>) -> Json<Value> {
+    let cleared = ACTIVE.lock().map(|mut a| a.take().is_some()).unwrap_or(false);
+    if cleared {
+        tracing::warn!("chaos: fault injection disabled");
+        crate::audit::log_audit(&state.db, "clear_chaos_faults", json!({}), None).await;
+    }
+    Json(status())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rates_and_ttl_are_clamped() {
+        let faults = Faults {
+            upstream_529: 1.5,
+            slow_stream: f64::NAN,
+            slow_stream_delay_ms: 120_000,
+            providers: vec![" ".into(), "anthropic".into()],
+            ..Default::default()
+        }
+        .normalized();
+        assert_eq!(faults.upstream_529, 1.0);
+        assert_eq!(faults.slow_stream, 0.0);
+        assert_eq!(faults.slow_stream_delay_ms, MAX_SLOW_STREAM_DELAY_MS);
+        assert_eq!(faults.ttl_secs, Some(DEFAULT_TTL_SECS));
+        assert!(faults.applies_to("anthropic"));
+        assert!(!faults.applies_to("google"));
+    }
+
+    #[tokio::test]
+    async fn truncated_bodies_end_with_an_error() {
+        let resp = reqwest::Response::from(
+            http::Response::builder()
+                .status(200)
+                .header("content-type", "text/event-stream")
+                .body(reqwest::Body::wrap_stream(futures_util::stream::iter([
+                    Ok::<_, std::io::Error>("event: a\n\n"),
+                    Ok("event: b\n\n"),
+                    Ok("event: c\n\n"),
+                ])))
+                .unwrap(),
+        );
+        let faults = BodyFaults {
+            truncate: true,
+            malformed: true,
+            delay: None,
+        };
+        let mut stream = with_body_faults(resp, faults).bytes_stream();
+        assert_eq!(stream.next().await.unwrap().unwrap(), "event: a\n\n");
+        assert_eq!(stream.next().await.unwrap().unwrap(), MALFORMED_EVENT);
+        assert_eq!(stream.next().await.unwrap().unwrap(), "event: b\n\n");
+        assert!(stream.next().await.unwrap().is_err());
+    }
+}
@@ -0,0 +1,268 @@
+// ClaudeHydra v4 -- Provider circuit breakers
+// One breaker per upstream provider (anthropic, google). After
+// CH_CIRCUIT_FAILURE_THRESHOLD consecutive failures (default 5) — 429 / 5xx
+// answers, connection errors, timeouts and stalled streams — the breaker
+// opens and requests fail fast with 503 `circuit_open` instead of waiting for
+// the upstream timeout. After CH_CIRCUIT_OPEN_SECS (default 30) it half-opens:
+// the next request goes through as a probe, the others keep failing fast.
+// A successful probe closes the breaker, a failed one opens it again.
+//
+// Breakers are per process. Their state is reported as `circuits` in
+// `GET /api/health`. The Anthropic breaker also feeds the shared
+// `state.circuit_breaker` used by the shared crates.
+
+use std::collections::BTreeMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use axum::Json;
+use axum::http::StatusCode;
+use chrono::{DateTime, Utc};
+use serde_json::{Value, json};
+
+pub const ANTHROPIC: &str = "anthropic";
+pub const GOOGLE: &str = "google";
+
+const DEFAULT_FAILURE_THRESHOLD: u32 = 5;
+const DEFAULT_OPEN_SECS: u64 = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Config {
+    failure_threshold: u32,
+    open_for: Duration,
+}
+
+fn config() -> Config {
+    static CONFIG: OnceLock<Config> = OnceLock::new();
+    *CONFIG.get_or_init(|| {
+        let env = |name: &str| std::env::var(name).ok().and_then(|v| v.trim().parse::<u64>().ok());
+        Config {
+            failure_threshold: env("CH_CIRCUIT_FAILURE_THRESHOLD")
+                .map(|n| n.clamp(1, 1_000) as u32)
+                .unwrap_or(DEFAULT_FAILURE_THRESHOLD),
+            open_for: Duration::from_secs(env("CH_CIRCUIT_OPEN_SECS").unwrap_or(DEFAULT_OPEN_SECS).max(1)),
+        }
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Phase {
+    Closed,
+    Open { until: Instant },
+    /// A probe request is in flight since `since`.
+    HalfOpen { since: Instant },
+}
+
+#[derive(Debug, Clone)]
+struct Breaker {
+    phase: Phase,
+    consecutive_failures: u32,
+    opened_at: Option<DateTime<Utc>>,
+    last_error: Option<String>,
+    /// Times the breaker opened since startup.
+    trips: u64,
+}
+
+impl Default for Breaker {
+    fn default() -> Self {
+        Breaker {
+            phase: Phase::Closed,
+            consecutive_failures: 0,
+            opened_at: None,
+            last_error: None,
+            trips: 0,
+        }
+    }
+}
+
+impl Breaker {
+    /// Whether a request may go upstream; `Err` is the time until the next probe.
+    fn admit(&mut self, config: Config, now: Instant) -> Result<(), Duration> {
+        match self.phase {
+            Phase::Closed => Ok(()),
+            Phase::Open { until } if now < until => Err(until - now),
+            Phase::Open { .. } => {
+                self.phase = Phase::HalfOpen { since: now };
+                Ok(())
+            }
+            // A probe that never reported back (cancelled) must not keep the
+            // breaker half-open forever.
+            Phase::HalfOpen { since } if now.duration_since(since) >= config.open_for => {
+                self.phase = Phase::HalfOpen { since: now };
+                Ok(())
+            }
+            Phase::HalfOpen { since } => Err(config.open_for.saturating_sub(now.duration_since(since))),
+        }
+    }
+
+    fn is_open(&self, now: Instant) -> Option<Duration> {
+        match self.phase {
+            Phase::Open { until } if now < until => Some(until - now),
+            _ => None,
+        }
+    }
+
+    /// Returns whether this success closed the breaker.
+    fn success(&mut self) -> bool {
+        let was_open = self.phase != Phase::Closed;
+        self.phase = Phase::Closed;
+        self.consecutive_failures = 0;
+        self.opened_at = None;
+        was_open
+    }
+
+    /// Returns whether this failure opened the breaker.
+    fn failure(&mut self, config: Config, now: Instant, error: String) -> bool {
+        self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+        self.last_error = Some(error);
+        let trip = match self.phase {
+            Phase::HalfOpen { .. } => true,
+            Phase::Closed => self.consecutive_failures >= config.failure_threshold,
+            Phase::Open { .. } => false,
+        };
+        if trip {
+            self.phase = Phase::Open { until: now + config.open_for };
+            self.opened_at = Some(Utc::now());
+            self.trips += 1;
+        }
+        trip
+    }
+
+    fn status(&self, now: Instant) -> Value {
+        let (state, retry_in) = match self.phase {
+            Phase::Closed => ("closed", None),
+            Phase::Open { until } if now < until => ("open", Some((until - now).as_secs_f64().ceil() as u64)),
+            Phase::Open { .. } | Phase::HalfOpen { .. } => ("half_open", None),
+        };
+        json!({
+            "state": state,
+            "consecutive_failures": self.consecutive_failures,
+            "opened_at": self.opened_at,
+            "retry_in_secs": retry_in,
+            "last_error": self.last_error,
+            "trips": self.trips,
+        })
+    }
+}
+
+static BREAKERS: Mutex<BTreeMap<&'static str, Breaker>> = Mutex::new(BTreeMap::new());
+
+fn with<T>(provider: &'static str, f: impl FnOnce(&mut Breaker) -> T) -> Option<T> {
+    BREAKERS
+        .lock()
+        .ok()
+        .map(|mut breakers| f(breakers.entry(provider).or_default()))
+}
+
+/// The 503 answer while `provider`'s breaker is open.
+fn rejection(provider: &str, retry_in: Duration) -> (StatusCode, Json<Value>) {
+    let secs = retry_in.as_secs_f64().ceil() as u64;
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(json!({
+            "error": format!(
+                "{} is failing; requests fail fast for the next {}s while the circuit breaker is open",
+                provider, secs
+            ),
+            "code": "circuit_open",
+            "provider": provider,
+            "retry_after_secs": secs,
+        })),
+    )
+}
+
+/// Gate a request to `provider`: `Err` (503) while the breaker is open.
+pub fn admit(provider: &'static str) -> Result<(), (StatusCode, Json<Value>)> {
+    match with(provider, |b| b.admit(config(), Instant::now())) {
+        Some(Err(retry_in)) => Err(rejection(provider, retry_in)),
+        _ => Ok(()),
+    }
+}
+
+/// `Err` (503) if `provider`'s breaker is open right now (no probe admitted).
+pub fn ensure_closed(provider: &'static str) -> Result<(), (StatusCode, Json<Value>)> {
+    match with(provider, |b| b.is_open(Instant::now())).flatten() {
+        Some(retry_in) => Err(rejection(provider, retry_in)),
+        None => Ok(()),
+    }
+}
+
+pub async fn record_success(state: &crate::state::AppState, provider: &'static str) {
+    if with(provider, Breaker::success) == Some(true) {
+        tracing::info!("circuit: {} breaker closed", provider);
+    }
+    if provider == ANTHROPIC {
+        state.circuit_breaker.record_success().await;
+    }
+}
+
+pub async fn record_failure(state: &crate::state::AppState, provider: &'static str, error: impl Into<String>) {
+    let error = error.into();
+    let opened = with(provider, |b| b.failure(config(), Instant::now(), error.clone()));
+    if opened == Some(true) {
+        tracing::warn!(
+            "circuit: {} breaker opened for {}s ({})",
+            provider,
+            config().open_for.as_secs(),
+            error
+        );
+    }
+    if provider == ANTHROPIC {
+        state.circuit_breaker.record_failure().await;
+    }
+}
+
+/// Breaker states for the health endpoint.
+pub fn health_status() -> Value {
+    let now = Instant::now();
+    let breakers = BREAKERS.lock().map(|b| b.clone()).unwrap_or_default();
+    let status: serde_json::Map<String, Value> = [ANTHROPIC, GOOGLE]
+        .into_iter()
+        .map(|p| (p.to_string(), breakers.get(p).cloned().unwrap_or_default().status(now)))
+        .collect();
+    Value::Object(status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: Config = Config {
+        failure_threshold: 3,
+        open_for: Duration::from_secs(30),
+    };
+
+    #[test]
+    fn opens_after_consecutive_failures() {
+        let now = Instant::now();
+        let mut b = Breaker::default();
+        assert!(!b.failure(CONFIG, now, "HTTP 529".into()));
+        assert!(!b.failure(CONFIG, now, "HTTP 529".into()));
+        b.success();
+        assert!(!b.failure(CONFIG, now, "timeout".into()));
+        assert!(!b.failure(CONFIG, now, "timeout".into()));
+        assert!(b.failure(CONFIG, now, "timeout".into()));
+        assert_eq!(b.admit(CONFIG, now + Duration::from_secs(10)), Err(Duration::from_secs(20)));
+        assert_eq!(b.status(now)["state"], "open");
+    }
+
+    #[test]
+    fn half_open_admits_one_probe() {
+        let now = Instant::now();
+        let mut b = Breaker::default();
+        for _ in 0..3 {
+            b.failure(CONFIG, now, "HTTP 503".into());
+        }
+        let later = now + Duration::from_secs(31);
+        assert_eq!(b.admit(CONFIG, later), Ok(()));
+        assert!(b.admit(CONFIG, later).is_err());
+        // A failed probe opens the breaker again right away.
+        assert!(b.failure(CONFIG, later, "HTTP 503".into()));
+        assert!(b.is_open(later).is_some());
+        let probe = later + Duration::from_secs(31);
+        assert_eq!(b.admit(CONFIG, probe), Ok(()));
+        assert!(b.success());
+        assert_eq!(b.admit(CONFIG, probe), Ok(()));
+        assert_eq!(b.status(probe)["state"], "closed");
+    }
+}
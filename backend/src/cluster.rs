@@ -0,0 +1,294 @@
+// ClaudeHydra v4 -- Multi-instance coordination
+// When several backend replicas share one PostgreSQL database, in-process
+// caches and runtime toggles drift apart. This module fans out change events
+// between replicas over Postgres LISTEN/NOTIFY (no extra infrastructure):
+//
+// - agents_changed — reload the in-memory agent roster
+// - read_only      — apply maintenance mode on every replica
+// - subsystem      — pause/resume a subsystem on every replica
+// - key_environment — switch the active provider key environment
+// - prompt_canaries — reload the running prompt canaries
+// - http_client     — reload the upstream HTTP client settings
+// - tier_models     — apply the model-per-tier mapping
+// - cancel_stream   — cancel a stream on the replica that owns it
+//
+// Enabled with CLUSTER_SYNC=1. Each replica ignores its own notifications.
+//
+// Besides the event bus, sync turns on two shared tables:
+// - ch_cluster_instances — every replica with CLUSTER_INTERNAL_URL set
+//   heartbeats its address, so a stream resume that lands on the wrong
+//   replica is forwarded to the owner (see stream_relay);
+// - ch_rate_windows — per-client counters of the chat rate limits, shared by
+//   all replicas (see shared_rate_limits).
+//
+// Postgres stands in for a Redis layer here: the database is already shared
+// by every replica. What that costs:
+// - NOTIFY is fire-and-forget. Events sent while a replica's listener is
+//   disconnected are lost; on reconnect only the agent roster is re-read, so
+//   other toggles stay stale there until they change again or it restarts.
+// - The listener holds a session connection of its own. Transaction-mode
+//   poolers (PgBouncer) do not deliver notifications.
+// - Payloads are capped at 8000 bytes by Postgres; events carry settings,
+//   never session data.
+// - Streams themselves are not shared: a stream lives and dies with the
+//   replica that started it, others only forward to it.
+
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::state::AppState;
+
+const CLUSTER_CHANNEL: &str = "ch_cluster";
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+/// A replica that missed this long of heartbeats is not forwarded to.
+const HEARTBEAT_TTL: Duration = Duration::from_secs(90);
+
+/// A change that must be applied on every replica.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ClusterEvent {
+    AgentsChanged,
+    ReadOnly { enabled: bool, message: Option<String> },
+    Subsystem { name: String, paused: bool },
+    KeyEnvironment { active: Option<String> },
+    TierBudgets { budgets: serde_json::Value },
+    Quotas { quotas: serde_json::Value },
+    PromptCanaries,
+    HttpClient,
+    EndpointPolicies,
+    TierModels { models: serde_json::Value },
+    CancelStream { stream_id: String },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Envelope {
+    origin: String,
+    #[serde(flatten)]
+    event: ClusterEvent,
+}
+
+/// Whether cross-replica sync is enabled (`CLUSTER_SYNC=1|true`).
+pub fn enabled() -> bool {
+    static ENABLED: OnceLock<bool> = OnceLock::new();
+    *ENABLED.get_or_init(|| {
+        std::env::var("CLUSTER_SYNC")
+            .map(|v| matches!(v.to_lowercase().as_str(), "1" | "true" | "yes"))
+            .unwrap_or(false)
+    })
+}
+
+/// Stable identifier of this replica — `FLY_MACHINE_ID`, `HOSTNAME`, or a random UUID.
+pub fn instance_id() -> &'static str {
+    static ID: OnceLock<String> = OnceLock::new();
+    ID.get_or_init(|| {
+        std::env::var("FLY_MACHINE_ID")
+            .or_else(|_| std::env::var("HOSTNAME"))
+            .ok()
+            .filter(|v| !v.is_empty())
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string())
+    })
+}
+
+/// Broadcast an event to the other replicas (no-op when sync is disabled).
+/// State changes are stored in the event history either way.
+pub fn publish(state: &AppState, event: ClusterEvent) {
+    if !matches!(event, ClusterEvent::CancelStream { .. })
+        && let Ok(serde_json::Value::Object(mut data)) = serde_json::to_value(&event)
+        && let Some(serde_json::Value::String(kind)) = data.remove("kind")
+    {
+        crate::event_history::record(&state.db, format!("state.{}", kind), data.into());
+    }
+    if !enabled() {
+        return;
+    }
+    let payload = match serde_json::to_string(&Envelope {
+        origin: instance_id().to_string(),
+        event,
+    }) {
+        Ok(p) => p,
+        Err(e) => {
+            tracing::warn!("cluster: failed to serialize event: {}", e);
+            return;
+        }
+    };
+    let db = state.db.clone();
+    tokio::spawn(async move {
+        if let Err(e) = sqlx::query("SELECT pg_notify($1, $2)")
+            .bind(CLUSTER_CHANNEL)
+            .bind(&payload)
+            .execute(&db)
+            .await
+        {
+            tracing::warn!("cluster: pg_notify failed: {}", e);
+        }
+    });
+}
+
+/// Decode a notification payload, dropping events that originated here.
+fn decode_foreign(payload: &str, self_id: &str) -> Option<ClusterEvent> {
+    let envelope: Envelope = serde_json::from_str(payload).ok()?;
+    (envelope.origin != self_id).then_some(envelope.event)
+}
+
+async fn apply(state: &AppState, event: ClusterEvent) {
+    tracing::info!("cluster: applying {:?}", event);
+    match event {
+        ClusterEvent::AgentsChanged => state.refresh_agents().await,
+        ClusterEvent::ReadOnly { enabled, message } => {
+            state.maintenance.set_read_only(enabled, message)
+        }
+        ClusterEvent::Subsystem { name, paused } => {
+            state.subsystems.set_paused(&name, paused);
+        }
+        ClusterEvent::KeyEnvironment { active } => crate::key_environments::set_active(active),
+        ClusterEvent::TierBudgets { budgets } => {
+            crate::tier_budgets::set(serde_json::from_value(budgets).unwrap_or_default())
+        }
+        ClusterEvent::Quotas { quotas } => {
+            crate::quotas::set(serde_json::from_value(quotas).unwrap_or_default())
+        }
+        ClusterEvent::PromptCanaries => crate::prompt_canary::load(&state.db).await,
+        ClusterEvent::HttpClient => crate::http_client::load(&state.db).await,
+        ClusterEvent::EndpointPolicies => crate::endpoint_policies::load(&state.db).await,
+        ClusterEvent::TierModels { models } => {
+            crate::tier_models::set(serde_json::from_value(models).unwrap_or_default())
+        }
+        ClusterEvent::CancelStream { stream_id } => {
+            if state.streams.cancel(&stream_id) == Some(true) {
+                tracing::info!("cluster: stream {} cancelled from another replica", stream_id);
+            }
+        }
+    }
+}
+
+/// This replica's address for the others (`CLUSTER_INTERNAL_URL`), e.g.
+/// `http://10.0.0.5:8082` or `http://<machine>.vm.<app>.internal:8082`.
+pub fn internal_url() -> Option<&'static str> {
+    static URL: OnceLock<Option<String>> = OnceLock::new();
+    URL.get_or_init(|| {
+        std::env::var("CLUSTER_INTERNAL_URL")
+            .ok()
+            .map(|v| v.trim().trim_end_matches('/').to_string())
+            .filter(|v| !v.is_empty())
+    })
+    .as_deref()
+}
+
+/// Address of replica `instance`, if it heartbeated recently.
+pub async fn instance_url(db: &sqlx::PgPool, instance: &str) -> Option<String> {
+    if !enabled() {
+        return None;
+    }
+    sqlx::query_scalar::<_, String>(
+        "SELECT internal_url FROM ch_cluster_instances \
+         WHERE instance_id = $1 AND heartbeat_at > NOW() - make_interval(secs => $2)",
+    )
+    .bind(instance)
+    .bind(HEARTBEAT_TTL.as_secs_f64())
+    .fetch_optional(db)
+    .await
+    .unwrap_or_else(|e| {
+        tracing::warn!("cluster: instance lookup failed: {}", e);
+        None
+    })
+}
+
+/// Heartbeat this replica's address and expire stale rate-limit windows.
+fn spawn_heartbeat(db: sqlx::PgPool) {
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(HEARTBEAT_INTERVAL);
+        loop {
+            tick.tick().await;
+            if let Some(url) = internal_url()
+                && let Err(e) = sqlx::query(
+                    "INSERT INTO ch_cluster_instances (instance_id, internal_url, heartbeat_at) \
+                     VALUES ($1, $2, NOW()) \
+                     ON CONFLICT (instance_id) DO UPDATE SET internal_url = $2, heartbeat_at = NOW()",
+                )
+                .bind(instance_id())
+                .bind(url)
+                .execute(&db)
+                .await
+            {
+                tracing::warn!("cluster: heartbeat failed: {}", e);
+            }
+            let _ = sqlx::query(
+                "DELETE FROM ch_rate_windows WHERE window_start < NOW() - INTERVAL '5 minutes'",
+            )
+            .execute(&db)
+            .await;
+        }
+    });
+}
+
+/// Spawn the LISTEN loop (reconnects with backoff). No-op when sync is disabled.
+pub fn spawn_listener(state: AppState) {
+    if !enabled() {
+        return;
+    }
+    spawn_heartbeat(state.db.clone());
+    tokio::spawn(async move {
+        tracing::info!("cluster: sync enabled (instance={})", instance_id());
+        let mut backoff = Duration::from_secs(1);
+        loop {
+            match sqlx::postgres::PgListener::connect_with(&state.db).await {
+                Ok(mut listener) => {
+                    if let Err(e) = listener.listen(CLUSTER_CHANNEL).await {
+                        tracing::warn!("cluster: LISTEN failed: {}", e);
+                    } else {
+                        backoff = Duration::from_secs(1);
+                        // Anything missed while disconnected is re-read from the DB.
+                        state.refresh_agents().await;
+                        loop {
+                            match listener.recv().await {
+                                Ok(n) => {
+                                    if let Some(event) = decode_foreign(n.payload(), instance_id()) {
+                                        apply(&state, event).await;
+                                    }
+                                }
+                                Err(e) => {
+                                    tracing::warn!("cluster: listener error: {}", e);
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                }
+                Err(e) => tracing::warn!("cluster: listener connect failed: {}", e),
+            }
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(Duration::from_secs(60));
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn own_events_are_ignored() {
+        let payload = serde_json::to_string(&Envelope {
+            origin: "a".into(),
+            event: ClusterEvent::Subsystem { name: "webhooks".into(), paused: true },
+        })
+        .unwrap();
+        assert_eq!(decode_foreign(&payload, "a"), None);
+        assert_eq!(
+            decode_foreign(&payload, "b"),
+            Some(ClusterEvent::Subsystem { name: "webhooks".into(), paused: true })
+        );
+    }
+
+    #[test]
+    fn envelope_is_flat() {
+        let payload = serde_json::to_value(Envelope {
+            origin: "a".into(),
+            event: ClusterEvent::AgentsChanged,
+        })
+        .unwrap();
+        assert_eq!(payload, serde_json::json!({ "origin": "a", "kind": "agents_changed" }));
+    }
+}
@@ -0,0 +1,349 @@
+// ClaudeHydra v4 -- Cold storage for old sessions
+// Sessions untouched for `CH_COLD_STORAGE_DAYS` (opt-in, default 0 = off) are
+// compressed by a background sweep: each message longer than a preview gets
+// its full text zstd-compressed into `content_zst` and `content` cut to the
+// first `PREVIEW_CHARS` characters. The full-text index keeps the vector of
+// the full text (see migration 075), so cold sessions stay searchable and
+// search results still show a preview.
+//
+// Access is transparent: any `/api/sessions/{id}…` request (middleware) and
+// the WebSocket history load thaw the session first — content is restored and
+// the session waits another N days after its last access before it is
+// compressed again. While the sweep is off and no session is cold, the
+// middleware skips the lookup (the answer is cached for a minute).
+
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::time::Duration;
+
+use axum::Json;
+use axum::extract::{Request, State};
+use axum::http::StatusCode;
+use axum::middleware::Next;
+use axum::response::Response;
+use serde_json::{Value, json};
+use uuid::Uuid;
+
+use crate::state::AppState;
+
+/// Off unless `CH_COLD_STORAGE_DAYS` is set.
+const DEFAULT_DAYS: i64 = 0;
+const SWEEP_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+/// Sessions compressed per sweep.
+const SWEEP_BATCH: i64 = 50;
+/// Characters left uncompressed in `content` (search previews, lists).
+const PREVIEW_CHARS: usize = 200;
+const ZSTD_LEVEL: i32 = 9;
+/// How long `thaw_on_access` trusts its "no cold sessions" answer.
+const COLD_CHECK_TTL_SECS: i64 = 60;
+
+/// Whether any session was cold at the last check, and when (unix seconds).
+static ANY_COLD: AtomicBool = AtomicBool::new(true);
+static ANY_COLD_CHECKED_AT: AtomicI64 = AtomicI64::new(0);
+
+/// Days without activity before a session is compressed; `None` when off.
+pub fn threshold_days() -> Option<i64> {
+    let days = std::env::var("CH_COLD_STORAGE_DAYS")
+        .ok()
+        .and_then(|v| v.trim().parse::<i64>().ok())
+        .unwrap_or(DEFAULT_DAYS);
+    (days > 0).then_some(days)
+}
+
+fn compress(content: &str) -> std::io::Result<Vec<u8>> {
+    zstd::encode_all(content.as_bytes(), ZSTD_LEVEL)
+}
+
+fn decompress(data: &[u8]) -> Result<String, String> {
+    let bytes = zstd::decode_all(data).map_err(|e| e.to_string())?;
+    String::from_utf8(bytes).map_err(|e| e.to_string())
+}
+
+fn preview(content: &str) -> String {
+    content.chars().take(PREVIEW_CHARS).collect()
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SweepStats {
+    pub sessions: u64,
+    pub messages: u64,
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+}
+
+/// Compress one session. Returns (messages, bytes before, bytes after).
+async fn compress_session(db: &sqlx::PgPool, session_id: Uuid) -> Result<(u64, u64, u64), sqlx::Error> {
+    let mut tx = db.begin().await?;
+    let rows: Vec<(Uuid, String)> = sqlx::query_as(
+        "SELECT id, content FROM ch_messages \
+         WHERE session_id = $1 AND content_zst IS NULL AND LENGTH(content) > $2 \
+         FOR UPDATE",
+    )
+    .bind(session_id)
+    .bind(PREVIEW_CHARS as i32)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let compressed = tokio::task::spawn_blocking(move || {
+        rows.into_iter()
+            .filter_map(|(id, content)| {
+                let data = compress(&content).ok()?;
+                // Not worth it for text zstd cannot shrink
+                (data.len() < content.len()).then(|| (id, preview(&content), data, content.len()))
+            })
+            .collect::<Vec<_>>()
+    })
+    .await
+    .unwrap_or_default();
+
+    let (mut before, mut after) = (0u64, 0u64);
+    for (id, preview, data, original_len) in &compressed {
+        sqlx::query("UPDATE ch_messages SET content = $2, content_zst = $3 WHERE id = $1")
+            .bind(id)
+            .bind(preview)
+            .bind(data)
+            .execute(&mut *tx)
+            .await?;
+        before += *original_len as u64;
+        after += (preview.len() + data.len()) as u64;
+    }
+    sqlx::query("UPDATE ch_sessions SET cold_at = NOW() WHERE id = $1")
+        .bind(session_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok((compressed.len() as u64, before, after))
+}
+
+/// Compress up to `SWEEP_BATCH` sessions idle for `days`.
+pub async fn sweep(db: &sqlx::PgPool, days: i64) -> Result<SweepStats, sqlx::Error> {
+    let sessions: Vec<Uuid> = sqlx::query_scalar(
+        "SELECT id FROM ch_sessions \
+         WHERE cold_at IS NULL \
+           AND GREATEST(updated_at, COALESCE(thawed_at, updated_at)) < NOW() - make_interval(days => $1::INT) \
+         ORDER BY updated_at LIMIT $2",
+    )
+    .bind(days)
+    .bind(SWEEP_BATCH)
+    .fetch_all(db)
+    .await?;
+
+    let mut stats = SweepStats::default();
+    for session_id in sessions {
+        match compress_session(db, session_id).await {
+            Ok((messages, before, after)) => {
+                stats.sessions += 1;
+                stats.messages += messages;
+                stats.bytes_before += before;
+                stats.bytes_after += after;
+            }
+            Err(e) => tracing::warn!("cold_storage: failed to compress session {}: {}", session_id, e),
+        }
+    }
+    Ok(stats)
+}
+
+/// Restore a cold session's messages. `Ok(false)` when it was not cold.
+pub async fn thaw(db: &sqlx::PgPool, session_id: Uuid) -> Result<bool, sqlx::Error> {
+    let cold: Option<bool> = sqlx::query_scalar("SELECT cold_at IS NOT NULL FROM ch_sessions WHERE id = $1")
+        .bind(session_id)
+        .fetch_optional(db)
+        .await?;
+    if cold != Some(true) {
+        return Ok(false);
+    }
+
+    let mut tx = db.begin().await?;
+    // Lock the session so concurrent requests thaw it once
+    let still_cold: Option<bool> =
+        sqlx::query_scalar("SELECT cold_at IS NOT NULL FROM ch_sessions WHERE id = $1 FOR UPDATE")
+            .bind(session_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+    if still_cold != Some(true) {
+        return Ok(false);
+    }
+    let rows: Vec<(Uuid, Vec<u8>)> = sqlx::query_as(
+        "SELECT id, content_zst FROM ch_messages WHERE session_id = $1 AND content_zst IS NOT NULL",
+    )
+    .bind(session_id)
+    .fetch_all(&mut *tx)
+    .await?;
+    let restored = tokio::task::spawn_blocking(move || {
+        rows.into_iter()
+            .map(|(id, data)| (id, decompress(&data)))
+            .collect::<Vec<_>>()
+    })
+    .await
+    .unwrap_or_default();
+
+    for (id, content) in restored {
+        match content {
+            Ok(content) => {
+                sqlx::query("UPDATE ch_messages SET content = $2, content_zst = NULL WHERE id = $1")
+                    .bind(id)
+                    .bind(content)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            // Keep the compressed copy; the preview is served meanwhile
+            Err(e) => tracing::error!("cold_storage: message {} could not be decompressed: {}", id, e),
+        }
+    }
+    sqlx::query("UPDATE ch_sessions SET cold_at = NULL, thawed_at = NOW() WHERE id = $1")
+        .bind(session_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    tracing::info!("cold_storage: thawed session {}", session_id);
+    Ok(true)
+}
+
+/// Whether a session may be cold. Always true while the sweep runs (any
+/// replica may have just compressed one); otherwise only leftovers from an
+/// earlier run count, checked once a minute through `idx_ch_sessions_cold`.
+async fn may_be_cold(db: &sqlx::PgPool) -> bool {
+    if threshold_days().is_some() {
+        return true;
+    }
+    let now = chrono::Utc::now().timestamp();
+    if now - ANY_COLD_CHECKED_AT.load(Ordering::Relaxed) < COLD_CHECK_TTL_SECS {
+        return ANY_COLD.load(Ordering::Relaxed);
+    }
+    match sqlx::query_scalar::<_, bool>("SELECT EXISTS (SELECT 1 FROM ch_sessions WHERE cold_at IS NOT NULL)")
+        .fetch_one(db)
+        .await
+    {
+        Ok(any) => {
+            ANY_COLD.store(any, Ordering::Relaxed);
+            ANY_COLD_CHECKED_AT.store(now, Ordering::Relaxed);
+            any
+        }
+        Err(e) => {
+            tracing::warn!("cold_storage: failed to check for cold sessions: {}", e);
+            true
+        }
+    }
+}
+
+/// Middleware: thaw the session addressed by `/api/sessions/{id}…`.
+pub async fn thaw_on_access(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let session_id = req
+        .uri()
+        .path()
+        .strip_prefix("/api/sessions/")
+        .and_then(|rest| rest.split('/').next())
+        .and_then(|id| Uuid::parse_str(id).ok());
+    if let Some(session_id) = session_id
+        && may_be_cold(&state.db).await
+        && let Err(e) = thaw(&state.db, session_id).await
+    {
+        tracing::warn!("cold_storage: failed to thaw session {}: {}", session_id, e);
+    }
+    next.run(req).await
+}
+
+/// Spawn the sweep loop (no-op unless `CH_COLD_STORAGE_DAYS` is positive).
+pub fn spawn(state: AppState) {
+    let Some(days) = threshold_days() else {
+        tracing::info!("cold_storage: disabled (CH_COLD_STORAGE_DAYS not set)");
+        return;
+    };
+    tokio::spawn(async move {
+        tracing::info!("cold_storage: compressing sessions idle for {} days", days);
+        let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+        loop {
+            interval.tick().await;
+            if state.subsystems.is_paused(crate::subsystems::COLD_STORAGE) {
+                tracing::debug!("cold_storage: sweep paused");
+                continue;
+            }
+            match sweep(&state.db, days).await {
+                Ok(stats) if stats.sessions > 0 => tracing::info!(
+                    "cold_storage: compressed {} sessions ({} messages, {} → {} bytes)",
+                    stats.sessions,
+                    stats.messages,
+                    stats.bytes_before,
+                    stats.bytes_after
+                ),
+                Ok(_) => {}
+                Err(e) => tracing::warn!("cold_storage: sweep failed: {}", e),
+            }
+        }
+    });
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  /api/admin/cold-storage
+// ═══════════════════════════════════════════════════════════════════════
+
+fn db_error(e: sqlx::Error) -> (StatusCode, Json<Value>) {
+    tracing::error!("cold_storage: {}", e);
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(json!({ "error": "Database error" })),
+    )
+}
+
+/// `GET /api/admin/cold-storage` — cold sessions and their compressed size
+#[utoipa::path(get, path = "/api/admin/cold-storage", tag = "system",
+    responses((status = 200, description = "Cold storage settings and totals")))]
+pub async fn cold_storage_status(
+    State(state): State<AppState>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let (sessions, messages, bytes): (i64, i64, i64) = sqlx::query_as(
+        "SELECT \
+            (SELECT COUNT(*) FROM ch_sessions WHERE cold_at IS NOT NULL), \
+            COUNT(*), \
+            COALESCE(SUM(OCTET_LENGTH(content_zst)), 0)::BIGINT \
+         FROM ch_messages WHERE content_zst IS NOT NULL",
+    )
+    .fetch_one(&state.db)
+    .await
+    .map_err(db_error)?;
+    Ok(Json(json!({
+        "enabled": threshold_days().is_some(),
+        "idle_days": threshold_days(),
+        "paused": state.subsystems.is_paused(crate::subsystems::COLD_STORAGE),
+        "cold_sessions": sessions,
+        "compressed_messages": messages,
+        "compressed_bytes": bytes,
+    })))
+}
+
+/// `POST /api/admin/cold-storage/run` — run a sweep now
+#[utoipa::path(post, path = "/api/admin/cold-storage/run", tag = "system",
+    responses(
+        (status = 200, description = "Sessions compressed by this sweep"),
+        (status = 409, description = "Cold storage is disabled")
+    ))]
+pub async fn run_cold_storage(
+    State(state): State<AppState>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let Some(days) = threshold_days() else {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(json!({ "error": "Cold storage is disabled (set CH_COLD_STORAGE_DAYS to enable it)" })),
+        ));
+    };
+    let stats = sweep(&state.db, days).await.map_err(db_error)?;
+    Ok(Json(json!({
+        "sessions": stats.sessions,
+        "messages": stats.messages,
+        "bytes_before": stats.bytes_before,
+        "bytes_after": stats.bytes_after,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compressed_content_round_trips() {
+        let content = "Kompresja działa — ".repeat(200);
+        let data = compress(&content).unwrap();
+        assert!(data.len() < content.len() / 10);
+        assert_eq!(decompress(&data).unwrap(), content);
+        assert_eq!(preview(&content).chars().count(), PREVIEW_CHARS);
+    }
+}
@@ -0,0 +1,363 @@
+// ClaudeHydra v4 -- Session compaction
+// Server-owned history (session chat, regenerate) grows without bound, and
+// long sessions end up larger than the model's context window. Compaction
+// summarizes the older messages with the executor model (Haiku) into one
+// pinned `system` message and marks the summarized messages `compacted_at`:
+// they stay readable in the session, but only the summary plus the newer
+// messages are sent upstream (the summary goes in as the request layer of
+// the system prompt).
+//
+// Runs automatically after a session chat turn once the live history is
+// estimated above `CH_COMPACTION_TOKENS` (default 120k tokens, local
+// tokenizer estimate), keeping the newest `compaction_keep` messages from settings; the
+// `auto_compaction` subsystem pauses that. `POST /api/sessions/{id}/compact`
+// runs it on demand. A previous summary is folded into the new one.
+
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use utoipa::ToSchema;
+
+use crate::models::ChatMessage;
+use crate::session_presence::{AttributedMessage, MESSAGE_COLUMNS, SessionEvent};
+use crate::state::AppState;
+
+const DEFAULT_TOKEN_THRESHOLD: i64 = 120_000;
+const MIN_KEEP: i64 = 2;
+const MAX_KEEP: i64 = 200;
+const SUMMARY_MAX_TOKENS: u32 = 2048;
+/// Transcript sent for summarizing; the oldest part is cut beyond this.
+const MAX_TRANSCRIPT_CHARS: usize = 400_000;
+/// Each message is cut to this many characters in the transcript.
+const MAX_MESSAGE_CHARS: usize = 8_000;
+
+const SUMMARY_SYSTEM: &str = "You compress the earlier part of a conversation so it can continue \
+without the full transcript. Write a dense summary in the conversation's language: the user's \
+goals and constraints, decisions made, facts and names established, code, files and commands \
+that matter, and open questions or tasks. Keep exact identifiers, numbers and paths. Do not \
+address the user and do not add anything that is not in the transcript.";
+
+type ApiError = (StatusCode, Json<Value>);
+
+fn api_error(status: StatusCode, msg: impl Into<String>) -> ApiError {
+    (status, Json(json!({ "error": msg.into() })))
+}
+
+fn db_error(e: sqlx::Error) -> ApiError {
+    tracing::error!("compaction: database error: {}", e);
+    api_error(StatusCode::INTERNAL_SERVER_ERROR, "Database error")
+}
+
+/// Estimated live-history size that triggers automatic compaction.
+pub fn token_threshold() -> i64 {
+    std::env::var("CH_COMPACTION_TOKENS")
+        .ok()
+        .and_then(|v| v.trim().parse::<i64>().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(DEFAULT_TOKEN_THRESHOLD)
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct LiveMessage {
+    id: uuid::Uuid,
+    role: String,
+    content: String,
+    pinned: bool,
+    created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Transcript of the messages to summarize, oldest part cut first.
+fn transcript(messages: &[LiveMessage]) -> String {
+    let mut parts: Vec<String> = messages
+        .iter()
+        .map(|m| {
+            let label = if m.pinned { "Earlier summary" } else { m.role.as_str() };
+            let content = crate::handlers::truncate_for_context_with_limit(m.content.trim(), MAX_MESSAGE_CHARS);
+            format!("[{}]\n{}", label, content)
+        })
+        .collect();
+    let mut total: usize = parts.iter().map(|p| p.len() + 2).sum();
+    while total > MAX_TRANSCRIPT_CHARS && parts.len() > 1 {
+        total -= parts.remove(0).len() + 2;
+    }
+    parts.join("\n\n")
+}
+
+/// Pinned summaries of `session_id` as `system` messages, for the history
+/// sent upstream.
+pub async fn summary_messages(db: &sqlx::PgPool, session_id: uuid::Uuid) -> Result<Vec<ChatMessage>, sqlx::Error> {
+    let summaries: Vec<String> = sqlx::query_scalar(
+        "SELECT content FROM ch_messages \
+         WHERE session_id = $1 AND pinned AND compacted_at IS NULL ORDER BY created_at",
+    )
+    .bind(session_id)
+    .fetch_all(db)
+    .await?;
+    Ok(summaries
+        .into_iter()
+        .map(|content| ChatMessage {
+            role: "system".to_string(),
+            content: format!("Summary of the earlier conversation:\n{}", content),
+            attachments: Vec::new(),
+            model: None,
+            timestamp: None,
+        })
+        .collect())
+}
+
+/// Outcome of one compaction.
+#[derive(Debug)]
+pub struct Compacted {
+    pub summary: AttributedMessage,
+    pub compacted_messages: usize,
+    /// Estimated tokens of the summarized messages.
+    pub compacted_tokens: u64,
+    pub model: String,
+}
+
+/// Summarize every live message of `session_id` except the newest `keep`.
+/// `Ok(None)` when there is not enough to compact.
+pub async fn compact(state: &AppState, session_id: uuid::Uuid, keep: i64) -> Result<Option<Compacted>, ApiError> {
+    let keep = keep.clamp(MIN_KEEP, MAX_KEEP);
+    let mut live = sqlx::query_as::<_, LiveMessage>(
+        "SELECT id, role, content, pinned, created_at FROM ch_messages \
+         WHERE session_id = $1 AND compacted_at IS NULL ORDER BY created_at",
+    )
+    .bind(session_id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(db_error)?;
+    let split = live.len().saturating_sub(keep as usize);
+    live.truncate(split);
+    // A lone earlier summary (or a single message) gains nothing.
+    if live.iter().filter(|m| !m.pinned).count() < 2 {
+        return Ok(None);
+    }
+
+    let model = crate::model_registry::get_model_id(state, "executor").await;
+    let prompt = format!(
+        "Summarize this conversation so far:\n\n{}",
+        transcript(&live)
+    );
+    let summary = crate::handlers::documents::complete(
+        state,
+        &model,
+        SUMMARY_SYSTEM,
+        &prompt,
+        SUMMARY_MAX_TOKENS,
+        "compaction",
+    )
+    .await
+    .map_err(|e| api_error(StatusCode::BAD_GATEWAY, format!("Summarization failed: {}", e)))?;
+    if summary.trim().is_empty() {
+        return Err(api_error(StatusCode::BAD_GATEWAY, "The model returned an empty summary"));
+    }
+
+    let ids: Vec<uuid::Uuid> = live.iter().map(|m| m.id).collect();
+    let last_at = live.last().map(|m| m.created_at).unwrap_or_else(chrono::Utc::now);
+    let compacted_tokens: u64 = live
+        .iter()
+        .filter(|m| !m.pinned)
+        .map(|m| crate::tokenizer::count(&m.content))
+        .sum();
+
+    let mut tx = state.db.begin().await.map_err(db_error)?;
+    let marked = sqlx::query(
+        "UPDATE ch_messages SET compacted_at = NOW() \
+         WHERE session_id = $1 AND id = ANY($2) AND compacted_at IS NULL",
+    )
+    .bind(session_id)
+    .bind(&ids)
+    .execute(&mut *tx)
+    .await
+    .map_err(db_error)?
+    .rows_affected();
+    if marked as usize != ids.len() {
+        // Another compaction (or a delete) got there first.
+        return Err(api_error(StatusCode::CONFLICT, "The session changed during compaction; try again"));
+    }
+    // Placed right after the last summarized message, before the kept ones.
+    let summary = sqlx::query_as::<_, AttributedMessage>(&format!(
+        "INSERT INTO ch_messages (session_id, role, content, model, pinned, created_at) \
+         VALUES ($1, 'system', $2, $3, TRUE, $4::timestamptz + INTERVAL '1 microsecond') RETURNING {}",
+        MESSAGE_COLUMNS
+    ))
+    .bind(session_id)
+    .bind(summary.trim())
+    .bind(&model)
+    .bind(last_at)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(db_error)?;
+    tx.commit().await.map_err(db_error)?;
+
+    state.presence.publish(
+        session_id,
+        SessionEvent::MessageAdded {
+            message: json!(summary),
+        },
+    );
+    tracing::info!(
+        "compaction: session {} — {} messages (~{} tokens) summarized",
+        session_id,
+        ids.len(),
+        compacted_tokens
+    );
+    Ok(Some(Compacted {
+        summary,
+        compacted_messages: ids.len(),
+        compacted_tokens,
+        model,
+    }))
+}
+
+/// Compact `session_id` in the background if its live history is over the
+/// threshold. Called after session chat turns.
+pub fn schedule(state: AppState, session_id: uuid::Uuid) {
+    if state.subsystems.is_paused(crate::subsystems::AUTO_COMPACTION) {
+        return;
+    }
+    tokio::spawn(async move {
+        let row: Result<(i64, i32), sqlx::Error> = sqlx::query_as(
+            "SELECT COALESCE((SELECT SUM(LENGTH(content))::BIGINT FROM ch_messages \
+                              WHERE session_id = $1 AND compacted_at IS NULL), 0), \
+                    COALESCE((SELECT compaction_keep FROM ch_settings WHERE id = 1), 15)",
+        )
+        .bind(session_id)
+        .fetch_one(&state.db)
+        .await;
+        let (chars, keep) = match row {
+            Ok(row) => row,
+            Err(e) => {
+                tracing::warn!("compaction: failed to size session {}: {}", session_id, e);
+                return;
+            }
+        };
+        // Under the threshold even at one token per character: skip reading
+        // the messages.
+        if chars < token_threshold() {
+            return;
+        }
+        let contents: Vec<String> = match sqlx::query_scalar(
+            "SELECT content FROM ch_messages WHERE session_id = $1 AND compacted_at IS NULL",
+        )
+        .bind(session_id)
+        .fetch_all(&state.db)
+        .await
+        {
+            Ok(contents) => contents,
+            Err(e) => {
+                tracing::warn!("compaction: failed to size session {}: {}", session_id, e);
+                return;
+            }
+        };
+        let tokens: u64 = contents.iter().map(|c| crate::tokenizer::count(c)).sum();
+        if (tokens as i64) < token_threshold() {
+            return;
+        }
+        if let Err((_, Json(err))) = compact(&state, session_id, keep as i64).await {
+            tracing::warn!("compaction: automatic compaction of {} failed: {}", session_id, err);
+        }
+    });
+}
+
+/// Optional body for `POST /api/sessions/{id}/compact`.
+#[derive(Debug, Clone, Default, Deserialize, ToSchema)]
+pub struct CompactSessionRequest {
+    /// Newest messages kept as they are (default: `compaction_keep` setting).
+    #[serde(default)]
+    pub keep: Option<i64>,
+}
+
+/// `POST /api/sessions/{id}/compact` — summarize older messages now
+#[utoipa::path(post, path = "/api/sessions/{id}/compact", tag = "sessions",
+    params(("id" = String, Path, description = "Session UUID")),
+    request_body(content = CompactSessionRequest, description = "Optional number of messages to keep"),
+    responses(
+        (status = 200, description = "Summary stored (compacted: false when there was nothing to compact)"),
+        (status = 400, description = "Invalid session id"),
+        (status = 404, description = "Session not found"),
+        (status = 409, description = "The session changed during compaction"),
+        (status = 502, description = "Summarization failed (nothing changed)")
+    ))]
+pub async fn compact_session(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    body: Option<Json<CompactSessionRequest>>,
+) -> Result<Json<Value>, ApiError> {
+    let session_id: uuid::Uuid = id
+        .parse()
+        .map_err(|_| api_error(StatusCode::BAD_REQUEST, "Invalid session id"))?;
+    let req = body.map(|Json(b)| b).unwrap_or_default();
+    let exists = sqlx::query("SELECT 1 FROM ch_sessions WHERE id = $1")
+        .bind(session_id)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(db_error)?;
+    if exists.is_none() {
+        return Err(api_error(StatusCode::NOT_FOUND, "Session not found"));
+    }
+    let keep = match req.keep {
+        Some(keep) => keep,
+        None => sqlx::query_scalar::<_, i32>("SELECT compaction_keep FROM ch_settings WHERE id = 1")
+            .fetch_optional(&state.db)
+            .await
+            .map_err(db_error)?
+            .unwrap_or(15) as i64,
+    };
+
+    match compact(&state, session_id, keep).await? {
+        Some(done) => Ok(Json(json!({
+            "compacted": true,
+            "session_id": session_id,
+            "summary": done.summary,
+            "compacted_messages": done.compacted_messages,
+            "estimated_tokens_saved": done.compacted_tokens,
+            "model": done.model,
+        }))),
+        None => Ok(Json(json!({
+            "compacted": false,
+            "session_id": session_id,
+            "reason": "Not enough messages beyond the kept ones",
+        }))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(role: &str, content: &str, pinned: bool) -> LiveMessage {
+        LiveMessage {
+            id: uuid::Uuid::new_v4(),
+            role: role.to_string(),
+            content: content.to_string(),
+            pinned,
+            created_at: chrono::Utc::now(),
+        }
+    }
+
+    #[test]
+    fn transcript_labels_the_earlier_summary() {
+        let out = transcript(&[
+            message("system", "User wants a CLI.", true),
+            message("user", "Add --verbose", false),
+            message("assistant", "Done.", false),
+        ]);
+        assert_eq!(
+            out,
+            "[Earlier summary]\nUser wants a CLI.\n\n[user]\nAdd --verbose\n\n[assistant]\nDone."
+        );
+    }
+
+    #[test]
+    fn transcript_drops_the_oldest_part_when_too_long() {
+        let big = "x".repeat(MAX_MESSAGE_CHARS);
+        let many: Vec<LiveMessage> = (0..80).map(|_| message("user", &big, false)).collect();
+        let out = transcript(&many);
+        assert!(out.len() <= MAX_TRANSCRIPT_CHARS);
+        assert!(out.ends_with('x'));
+    }
+}
@@ -0,0 +1,309 @@
+// ClaudeHydra v4 -- Ask an agent by email
+// Inbound email webhooks (Postmark's inbound JSON format) post to
+// `/api/integrations/email/inbound`. Each email from an allowed sender
+// becomes a new session with the email text as the first user message; the
+// configured agent answers it (an agent run without tools, see
+// handlers/agent_run.rs) and the answer is stored in the session and sent
+// back as a reply through Postmark when POSTMARK_SERVER_TOKEN is set.
+//
+// Configuration is environment-only:
+// - EMAIL_INBOUND_TOKEN — required; the webhook URL carries it as the HTTP
+//   Basic password (`https://inbound:<token>@host/api/integrations/email/inbound`).
+// - EMAIL_ALLOWED_SENDERS — required; comma-separated addresses or `@domain`s.
+// - EMAIL_AGENT_ID — answering agent (default agent-001).
+// - POSTMARK_SERVER_TOKEN + EMAIL_FROM — reply channel; without them the
+//   answer only lands in the session.
+//
+// Requests are rejected while the `webhooks` subsystem is paused.
+
+use axum::Json;
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode, header};
+use base64::Engine as _;
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::crypto::ct_eq;
+use crate::state::AppState;
+
+const POSTMARK_API: &str = "https://api.postmarkapp.com";
+pub const DEFAULT_EMAIL_AGENT: &str = "agent-001";
+/// Email text given to the agent.
+const MAX_BODY_CHARS: usize = 20_000;
+
+#[derive(Debug, Clone)]
+struct EmailConfig {
+    token: String,
+    allowed_senders: Vec<String>,
+    agent_id: String,
+    /// Postmark server token and From address for replies.
+    reply: Option<(String, String)>,
+}
+
+impl EmailConfig {
+    fn from_env() -> Option<Self> {
+        let env = |key: &str| std::env::var(key).ok().filter(|v| !v.trim().is_empty());
+        Some(Self {
+            token: env("EMAIL_INBOUND_TOKEN")?,
+            allowed_senders: env("EMAIL_ALLOWED_SENDERS")
+                .unwrap_or_default()
+                .split(',')
+                .map(|s| s.trim().to_lowercase())
+                .filter(|s| !s.is_empty())
+                .collect(),
+            agent_id: env("EMAIL_AGENT_ID").unwrap_or_else(|| DEFAULT_EMAIL_AGENT.to_string()),
+            reply: env("POSTMARK_SERVER_TOKEN").zip(env("EMAIL_FROM")),
+        })
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct EmailAddress {
+    #[serde(default)]
+    email: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct EmailHeader {
+    name: String,
+    value: String,
+}
+
+/// The parts of Postmark's inbound payload we use.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct InboundEmail {
+    #[serde(default)]
+    from_full: EmailAddress,
+    #[serde(default)]
+    subject: String,
+    #[serde(default)]
+    text_body: String,
+    /// The new text of a reply, without the quoted thread.
+    #[serde(default)]
+    stripped_text_reply: String,
+    #[serde(default)]
+    headers: Vec<EmailHeader>,
+}
+
+impl InboundEmail {
+    fn text(&self) -> String {
+        let text = if self.stripped_text_reply.trim().is_empty() {
+            &self.text_body
+        } else {
+            &self.stripped_text_reply
+        };
+        text.trim().chars().take(MAX_BODY_CHARS).collect()
+    }
+
+    fn message_id(&self) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|h| h.name.eq_ignore_ascii_case("Message-ID"))
+            .map(|h| h.value.as_str())
+    }
+}
+
+/// `Authorization: Basic` whose password is the inbound token.
+fn authorized(headers: &HeaderMap, token: &str) -> bool {
+    let Some(encoded) = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Basic "))
+    else {
+        return false;
+    };
+    let Ok(decoded) = base64::engine::general_purpose::STANDARD.decode(encoded.trim()) else {
+        return false;
+    };
+    let decoded = String::from_utf8_lossy(&decoded);
+    decoded
+        .split_once(':')
+        .is_some_and(|(_, password)| ct_eq(password, token))
+}
+
+/// Exact address or `@domain` match, case-insensitive.
+fn sender_allowed(allowed: &[String], sender: &str) -> bool {
+    let sender = sender.trim().to_lowercase();
+    !sender.is_empty()
+        && allowed.iter().any(|a| {
+            if a.starts_with('@') {
+                sender.ends_with(a.as_str())
+            } else {
+                *a == sender
+            }
+        })
+}
+
+fn reply_subject(subject: &str) -> String {
+    let subject = subject.trim();
+    if subject.to_lowercase().starts_with("re:") {
+        subject.to_string()
+    } else if subject.is_empty() {
+        "Re: your question".to_string()
+    } else {
+        format!("Re: {}", subject)
+    }
+}
+
+/// `POST /api/integrations/email/inbound` — Postmark inbound webhook
+pub async fn email_inbound(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(email): Json<InboundEmail>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let cfg = EmailConfig::from_env().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(json!({ "error": "Email ingestion is not configured" })),
+    ))?;
+    if !authorized(&headers, &cfg.token) {
+        return Err((StatusCode::UNAUTHORIZED, Json(json!({ "error": "Invalid inbound token" }))));
+    }
+    // 403 also tells Postmark not to retry.
+    let sender = email.from_full.email.clone();
+    if !sender_allowed(&cfg.allowed_senders, &sender) {
+        tracing::info!("email_inbound: ignored mail from '{}'", sender);
+        return Err((StatusCode::FORBIDDEN, Json(json!({ "error": "Sender is not allowed" }))));
+    }
+    let text = email.text();
+    if text.is_empty() {
+        return Ok(Json(json!({ "ignored": "empty message" })));
+    }
+
+    let title: String = format!("Email: {}", email.subject.trim()).chars().take(200).collect();
+    let session_id: uuid::Uuid = sqlx::query_scalar("INSERT INTO ch_sessions (title) VALUES ($1) RETURNING id")
+        .bind(&title)
+        .fetch_one(&state.db)
+        .await
+        .map_err(|e| {
+            tracing::error!("email_inbound: failed to create session: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Failed to create session" })),
+            )
+        })?;
+    let author = format!("email:{}", sender.to_lowercase());
+    store_message(&state, session_id, "user", &text, None, Some(&author)).await;
+
+    tokio::spawn(answer(state, cfg, email, text, session_id));
+    Ok(Json(json!({ "session_id": session_id, "queued": true })))
+}
+
+async fn answer(state: AppState, cfg: EmailConfig, email: InboundEmail, text: String, session_id: uuid::Uuid) {
+    let req = crate::handlers::AgentRunRequest {
+        prompt: format!("Subject: {}\n\n{}", email.subject.trim(), text),
+        max_iterations: Some(1),
+        tools: Some(Vec::new()),
+        working_directory: None,
+    };
+    let reply = match crate::handlers::agent_run::execute_run(&state, &cfg.agent_id, &req, Default::default()).await
+    {
+        Ok(run) => {
+            let answer = run["answer"].as_str().unwrap_or("").trim().to_string();
+            store_message(&state, session_id, "assistant", &answer, run["agent"]["name"].as_str(), None).await;
+            answer
+        }
+        Err((status, Json(err))) => {
+            let error = err["error"].as_str().unwrap_or("Agent run failed");
+            tracing::warn!("email_inbound: agent run for {} failed ({}): {}", session_id, status, error);
+            format!("Sorry, the agent could not answer: {}", error)
+        }
+    };
+
+    let Some((server_token, from)) = cfg.reply else {
+        return;
+    };
+    let mut payload = json!({
+        "From": from,
+        "To": email.from_full.email,
+        "Subject": reply_subject(&email.subject),
+        "TextBody": reply,
+        "MessageStream": "outbound",
+    });
+    if let Some(message_id) = email.message_id() {
+        payload["Headers"] = json!([
+            { "Name": "In-Reply-To", "Value": message_id },
+            { "Name": "References", "Value": message_id },
+        ]);
+    }
+    let result = state
+        .http_client
+        .post(format!("{}/email", POSTMARK_API))
+        .header("X-Postmark-Server-Token", server_token)
+        .header(header::ACCEPT, "application/json")
+        .json(&payload)
+        .timeout(std::time::Duration::from_secs(15))
+        .send()
+        .await;
+    match result {
+        Ok(resp) if resp.status().is_success() => {}
+        Ok(resp) => tracing::warn!("email_inbound: Postmark reply for {} returned {}", session_id, resp.status()),
+        Err(e) => tracing::warn!("email_inbound: Postmark reply for {} failed: {}", session_id, e),
+    }
+}
+
+async fn store_message(
+    state: &AppState,
+    session_id: uuid::Uuid,
+    role: &str,
+    content: &str,
+    agent: Option<&str>,
+    author: Option<&str>,
+) {
+    if let Err(e) =
+        sqlx::query("INSERT INTO ch_messages (session_id, role, content, agent, author) VALUES ($1, $2, $3, $4, $5)")
+            .bind(session_id)
+            .bind(role)
+            .bind(content)
+            .bind(agent)
+            .bind(author)
+            .execute(&state.db)
+            .await
+    {
+        tracing::error!("email_inbound: failed to store message in {}: {}", session_id, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn basic_auth_password_must_match_the_token() {
+        let mut headers = HeaderMap::new();
+        assert!(!authorized(&headers, "t0k"));
+        let basic = |creds: &str| format!("Basic {}", base64::engine::general_purpose::STANDARD.encode(creds));
+        headers.insert(header::AUTHORIZATION, basic("inbound:t0k").parse().unwrap());
+        assert!(authorized(&headers, "t0k"));
+        headers.insert(header::AUTHORIZATION, basic("inbound:nope").parse().unwrap());
+        assert!(!authorized(&headers, "t0k"));
+    }
+
+    #[test]
+    fn senders_match_addresses_and_domains() {
+        let allowed = vec!["ops@example.com".to_string(), "@witchers.dev".to_string()];
+        assert!(sender_allowed(&allowed, "OPS@example.com"));
+        assert!(sender_allowed(&allowed, "geralt@witchers.dev"));
+        assert!(!sender_allowed(&allowed, "geralt@evil-witchers.dev.com"));
+        assert!(!sender_allowed(&allowed, "someone@example.com"));
+        assert!(!sender_allowed(&[], "ops@example.com"));
+    }
+
+    #[test]
+    fn inbound_payload_prefers_the_stripped_reply() {
+        let email: InboundEmail = serde_json::from_value(json!({
+            "FromFull": { "Email": "ops@example.com", "Name": "Ops" },
+            "Subject": "Deploy?",
+            "TextBody": "Can we deploy?\n\n> old thread",
+            "StrippedTextReply": "  Can we deploy?  ",
+            "Headers": [{ "Name": "Message-ID", "Value": "<abc@example.com>" }]
+        }))
+        .unwrap();
+        assert_eq!(email.text(), "Can we deploy?");
+        assert_eq!(email.message_id(), Some("<abc@example.com>"));
+        assert_eq!(reply_subject(&email.subject), "Re: Deploy?");
+        assert_eq!(reply_subject("RE: Deploy?"), "RE: Deploy?");
+    }
+}
@@ -0,0 +1,246 @@
+// ClaudeHydra v4 -- Per-endpoint timeout and retry policies
+// Provider calls are grouped by what they serve, and each group has its own
+// timeout and retry policy. They are set in the `policies` field of
+// `/api/settings` (stored in `ch_settings.endpoint_policies`):
+//   - chat   — non-streaming chat, agent runs, debates and test judges
+//              (default timeout: `http.request_timeout_secs`),
+//   - stream — streamed replies and their tool-loop turns
+//              (default: `http.stream_timeout_secs`),
+//   - models — model listing and upstream token counts (15s),
+//   - batch  — document summaries and embeddings (180s),
+//   - tools  — one tool execution (60s).
+// Unset retry fields use the CH_RETRY_* env vars (see `providers::retry`).
+// Retries apply to Anthropic Messages calls (chat, stream, batch); model
+// listings, token counts, tools and Gemini calls are not retried. Changes are
+// applied on every replica; `GET /api/settings/policies` shows the policies
+// in effect.
+
+use std::sync::RwLock;
+use std::time::Duration;
+
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use utoipa::ToSchema;
+
+use crate::providers::retry::RetryPolicy;
+
+const MAX_TIMEOUT_SECS: u64 = 3600;
+const MAX_ATTEMPTS: u32 = 10;
+const MAX_DELAY_MS: u64 = 300_000;
+
+/// What a provider call serves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Group {
+    Chat,
+    Stream,
+    Models,
+    Batch,
+    Tools,
+}
+
+impl Group {
+    pub const ALL: [Group; 5] = [Group::Chat, Group::Stream, Group::Models, Group::Batch, Group::Tools];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Group::Chat => "chat",
+            Group::Stream => "stream",
+            Group::Models => "models",
+            Group::Batch => "batch",
+            Group::Tools => "tools",
+        }
+    }
+
+    /// Whether transient upstream errors of this group are retried.
+    pub fn retried(self) -> bool {
+        matches!(self, Group::Chat | Group::Stream | Group::Batch)
+    }
+}
+
+/// Overrides of one group; unset fields use the default.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(default)]
+pub struct EndpointPolicy {
+    /// Timeout of one call.
+    pub timeout_secs: Option<u64>,
+    /// Requests in total, the first one included (1 = no retries).
+    pub max_attempts: Option<u32>,
+    /// Delay before the first retry; doubles with each retry.
+    pub base_delay_ms: Option<u64>,
+    /// Longest delay; a longer `retry-after` returns the error right away.
+    pub max_delay_ms: Option<u64>,
+}
+
+impl EndpointPolicy {
+    fn normalized(self) -> Self {
+        Self {
+            timeout_secs: self.timeout_secs.map(|s| s.clamp(1, MAX_TIMEOUT_SECS)),
+            max_attempts: self.max_attempts.map(|n| n.clamp(1, MAX_ATTEMPTS)),
+            base_delay_ms: self.base_delay_ms.map(|ms| ms.min(MAX_DELAY_MS)),
+            max_delay_ms: self.max_delay_ms.map(|ms| ms.min(MAX_DELAY_MS)),
+        }
+    }
+}
+
+/// Overrides stored in `ch_settings.endpoint_policies`, edited through the
+/// `policies` field of `/api/settings`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(default)]
+pub struct EndpointPolicies {
+    pub chat: EndpointPolicy,
+    pub stream: EndpointPolicy,
+    pub models: EndpointPolicy,
+    pub batch: EndpointPolicy,
+    pub tools: EndpointPolicy,
+}
+
+impl EndpointPolicies {
+    /// Clamp the set values into their valid ranges.
+    pub fn normalized(self) -> Self {
+        Self {
+            chat: self.chat.normalized(),
+            stream: self.stream.normalized(),
+            models: self.models.normalized(),
+            batch: self.batch.normalized(),
+            tools: self.tools.normalized(),
+        }
+    }
+
+    /// Parse the stored JSON; anything unreadable falls back to the default.
+    pub fn from_stored(value: Option<Value>) -> Self {
+        value
+            .and_then(|v| serde_json::from_value::<EndpointPolicies>(v).ok())
+            .unwrap_or_default()
+            .normalized()
+    }
+
+    fn group(&self, group: Group) -> &EndpointPolicy {
+        match group {
+            Group::Chat => &self.chat,
+            Group::Stream => &self.stream,
+            Group::Models => &self.models,
+            Group::Batch => &self.batch,
+            Group::Tools => &self.tools,
+        }
+    }
+}
+
+/// A group's policy in effect.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Policy {
+    pub timeout: Duration,
+    pub retry: RetryPolicy,
+}
+
+impl Policy {
+    pub fn timeout_secs(&self) -> u64 {
+        self.timeout.as_secs()
+    }
+}
+
+/// Timeout of `group` when the policy sets none.
+fn default_timeout_secs(group: Group) -> u64 {
+    match group {
+        Group::Chat => crate::http_client::request_timeout(),
+        Group::Stream => crate::http_client::stream_timeout(),
+        Group::Models => 15,
+        Group::Batch => 180,
+        Group::Tools => 60,
+    }
+}
+
+fn resolve(policy: &EndpointPolicy, timeout_secs: u64, retry: RetryPolicy) -> Policy {
+    let policy = policy.clone().normalized();
+    Policy {
+        timeout: Duration::from_secs(policy.timeout_secs.unwrap_or(timeout_secs)),
+        retry: RetryPolicy {
+            max_attempts: policy.max_attempts.unwrap_or(retry.max_attempts),
+            base_delay: policy.base_delay_ms.map(Duration::from_millis).unwrap_or(retry.base_delay),
+            max_delay: policy.max_delay_ms.map(Duration::from_millis).unwrap_or(retry.max_delay),
+        },
+    }
+}
+
+static CURRENT: RwLock<Option<EndpointPolicies>> = RwLock::new(None);
+
+/// Apply `policies` (startup, settings changes).
+pub fn set(policies: &EndpointPolicies) {
+    if let Ok(mut current) = CURRENT.write() {
+        *current = Some(policies.clone().normalized());
+    }
+}
+
+/// Load the stored policies (startup and after changes on another replica).
+pub async fn load(db: &sqlx::PgPool) {
+    let stored =
+        match sqlx::query_scalar::<_, Option<Value>>("SELECT endpoint_policies FROM ch_settings WHERE id = 1")
+            .fetch_optional(db)
+            .await
+        {
+            Ok(row) => row.flatten(),
+            Err(e) => {
+                tracing::warn!("endpoint_policies: failed to load policies: {}", e);
+                None
+            }
+        };
+    set(&EndpointPolicies::from_stored(stored));
+}
+
+/// Policy in effect for `group` (defaults before `load`).
+pub fn get(group: Group) -> Policy {
+    let current = CURRENT.read().ok().and_then(|c| c.clone()).unwrap_or_default();
+    resolve(current.group(group), default_timeout_secs(group), RetryPolicy::get())
+}
+
+/// `GET /api/settings/policies` — timeout and retry policy in effect per endpoint group
+#[utoipa::path(get, path = "/api/settings/policies", tag = "settings",
+    responses((status = 200, description = "Policy in effect per endpoint group")))]
+pub async fn effective_policies() -> Json<Value> {
+    let groups: serde_json::Map<String, Value> = Group::ALL
+        .into_iter()
+        .map(|group| {
+            let policy = get(group);
+            let mut entry = json!({ "timeout_secs": policy.timeout_secs(), "retried": group.retried() });
+            if group.retried() {
+                entry["max_attempts"] = json!(policy.retry.max_attempts);
+                entry["base_delay_ms"] = json!(policy.retry.base_delay.as_millis() as u64);
+                entry["max_delay_ms"] = json!(policy.retry.max_delay.as_millis() as u64);
+            }
+            (group.as_str().to_string(), entry)
+        })
+        .collect();
+    Json(Value::Object(groups))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_fields_override_the_defaults() {
+        let retry = RetryPolicy {
+            max_attempts: 4,
+            base_delay: Duration::from_millis(1_000),
+            max_delay: Duration::from_millis(30_000),
+        };
+        let policy = EndpointPolicy {
+            timeout_secs: Some(99_999),
+            max_attempts: Some(0),
+            base_delay_ms: Some(250),
+            ..Default::default()
+        };
+        let p = resolve(&policy, 120, retry);
+        assert_eq!(p.timeout_secs(), MAX_TIMEOUT_SECS);
+        assert_eq!(p.retry.max_attempts, 1);
+        assert_eq!(p.retry.base_delay, Duration::from_millis(250));
+        assert_eq!(p.retry.max_delay, retry.max_delay);
+        assert_eq!(resolve(&EndpointPolicy::default(), 120, retry).timeout_secs(), 120);
+
+        let stored = EndpointPolicies::from_stored(Some(json!({ "batch": { "timeout_secs": 600 } })));
+        assert_eq!(stored.group(Group::Batch).timeout_secs, Some(600));
+        assert_eq!(stored.group(Group::Chat), &EndpointPolicy::default());
+        assert_eq!(EndpointPolicies::from_stored(Some(json!("nonsense"))), EndpointPolicies::default());
+    }
+}
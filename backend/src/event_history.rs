@@ -0,0 +1,263 @@
+// ClaudeHydra v4 -- Event history
+// Everything that goes over the internal event bus is also stored in
+// `ch_events`, so clients that were offline can catch up on what changed
+// instead of resyncing every resource:
+//   - `state.<kind>` for every runtime state change published to the other
+//     replicas (see cluster.rs — agents_changed, read_only, subsystem,
+//     key_environment, tier_budgets, quotas), recorded whether or not
+//     CLUSTER_SYNC is on,
+//   - the webhook events (`usage.anomaly`, `health.probe_failed`,
+//     `agent.run_completed`, …) as they are dispatched.
+//
+// `GET /api/events/history?type=&since=` returns them oldest first; `since`
+// is the last `id` a client has seen (or a timestamp). Retention is bounded
+// by age (EVENT_HISTORY_RETENTION_DAYS, default 7) and row count
+// (EVENT_HISTORY_MAX_ROWS, default 50 000), pruned hourly.
+
+use std::time::Duration;
+
+use axum::Json;
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::state::AppState;
+
+const DEFAULT_RETENTION_DAYS: i32 = 7;
+const DEFAULT_MAX_ROWS: i64 = 50_000;
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const DEFAULT_PAGE: i64 = 100;
+const MAX_PAGE: i64 = 1_000;
+
+fn retention_days() -> i32 {
+    std::env::var("EVENT_HISTORY_RETENTION_DAYS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|d| *d > 0)
+        .unwrap_or(DEFAULT_RETENTION_DAYS)
+}
+
+fn max_rows() -> i64 {
+    std::env::var("EVENT_HISTORY_MAX_ROWS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(DEFAULT_MAX_ROWS)
+}
+
+/// Store an event (fire-and-forget — never blocks or fails the caller).
+pub fn record(db: &sqlx::PgPool, event_type: String, data: Value) {
+    let db = db.clone();
+    tokio::spawn(async move {
+        if let Err(e) = sqlx::query("INSERT INTO ch_events (event_type, instance, data) VALUES ($1, $2, $3)")
+            .bind(&event_type)
+            .bind(crate::cluster::instance_id())
+            .bind(&data)
+            .execute(&db)
+            .await
+        {
+            tracing::warn!("event_history: failed to record {}: {}", event_type, e);
+        }
+    });
+}
+
+/// Drop events past the retention window, then all but the newest `max_rows`.
+pub async fn prune(db: &sqlx::PgPool) {
+    let result = async {
+        let old = sqlx::query("DELETE FROM ch_events WHERE created_at < NOW() - make_interval(days => $1)")
+            .bind(retention_days())
+            .execute(db)
+            .await?
+            .rows_affected();
+        let excess = sqlx::query(
+            "DELETE FROM ch_events WHERE id <= (SELECT id FROM ch_events ORDER BY id DESC OFFSET $1 LIMIT 1)",
+        )
+        .bind(max_rows())
+        .execute(db)
+        .await?
+        .rows_affected();
+        Ok::<u64, sqlx::Error>(old + excess)
+    }
+    .await;
+    match result {
+        Ok(n) if n > 0 => tracing::info!("event_history: pruned {} old events", n),
+        Ok(_) => {}
+        Err(e) => tracing::warn!("event_history: prune failed: {}", e),
+    }
+}
+
+/// Spawn the hourly prune loop.
+pub fn spawn(state: AppState) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+        loop {
+            interval.tick().await;
+            prune(&state.db).await;
+        }
+    });
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  GET /api/events/history
+// ═══════════════════════════════════════════════════════════════════════
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryQuery {
+    /// Exact event type, or a prefix ending in `*` (`state.*`).
+    #[serde(rename = "type")]
+    pub event_type: Option<String>,
+    /// Last event id seen, or an RFC 3339 timestamp.
+    pub since: Option<String>,
+    /// Page size (default 100, max 1000).
+    pub limit: Option<i64>,
+}
+
+/// Where to resume from.
+#[derive(Debug, PartialEq)]
+enum Since {
+    Start,
+    After(i64),
+    At(chrono::DateTime<chrono::Utc>),
+}
+
+fn parse_since(since: Option<&str>) -> Option<Since> {
+    let Some(since) = since.map(str::trim).filter(|s| !s.is_empty()) else {
+        return Some(Since::Start);
+    };
+    if let Ok(id) = since.parse::<i64>() {
+        return Some(Since::After(id));
+    }
+    chrono::DateTime::parse_from_rfc3339(since)
+        .ok()
+        .map(|t| Since::At(t.with_timezone(&chrono::Utc)))
+}
+
+/// `type` as a SQL `LIKE` pattern: `state.*` → `state.%`, other text literal.
+fn type_pattern(event_type: &str) -> String {
+    let (base, wildcard) = match event_type.strip_suffix('*') {
+        Some(prefix) => (prefix, true),
+        None => (event_type, false),
+    };
+    let mut pattern = base.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+    if wildcard {
+        pattern.push('%');
+    }
+    pattern
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct EventRow {
+    id: i64,
+    event_type: String,
+    instance: String,
+    data: Value,
+    created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// `GET /api/events/history` — stored events after `since`, oldest first
+#[utoipa::path(get, path = "/api/events/history", tag = "system",
+    params(
+        ("type" = Option<String>, Query, description = "Event type, or a prefix ending in `*`"),
+        ("since" = Option<String>, Query, description = "Last event id seen, or an RFC 3339 timestamp"),
+        ("limit" = Option<i64>, Query, description = "Page size (default 100, max 1000)")
+    ),
+    responses(
+        (status = 200, description = "Events, the cursor to resume from and whether more are waiting"),
+        (status = 400, description = "`since` is neither an event id nor a timestamp")
+    ))]
+pub async fn event_history(
+    State(state): State<AppState>,
+    Query(q): Query<HistoryQuery>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let Some(since) = parse_since(q.since.as_deref()) else {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "since must be an event id or an RFC 3339 timestamp" })),
+        ));
+    };
+    let limit = q.limit.unwrap_or(DEFAULT_PAGE).clamp(1, MAX_PAGE);
+    let pattern = q
+        .event_type
+        .as_deref()
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .map(type_pattern);
+    let (after_id, at) = match since {
+        Since::Start => (None, None),
+        Since::After(id) => (Some(id), None),
+        Since::At(t) => (None, Some(t)),
+    };
+
+    // One extra row tells whether another page is waiting.
+    let mut rows = sqlx::query_as::<_, EventRow>(
+        "SELECT id, event_type, instance, data, created_at FROM ch_events \
+         WHERE ($1::BIGINT IS NULL OR id > $1) \
+           AND ($2::TIMESTAMPTZ IS NULL OR created_at >= $2) \
+           AND ($3::TEXT IS NULL OR event_type LIKE $3 ESCAPE '\\') \
+         ORDER BY id ASC LIMIT $4",
+    )
+    .bind(after_id)
+    .bind(at)
+    .bind(&pattern)
+    .bind(limit + 1)
+    .fetch_all(&state.db)
+    .await
+    .map_err(|e| {
+        tracing::error!("event_history: query failed: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": "Failed to load event history" })),
+        )
+    })?;
+    let has_more = rows.len() as i64 > limit;
+    rows.truncate(limit as usize);
+
+    // Oldest retained id: a cursor below it means events were pruned and the
+    // client has to resync after all.
+    let oldest: Option<i64> = sqlx::query_scalar("SELECT MIN(id) FROM ch_events")
+        .fetch_one(&state.db)
+        .await
+        .unwrap_or(None);
+    let gap = matches!((after_id, oldest), (Some(after), Some(oldest)) if after + 1 < oldest);
+
+    let next_since = rows.last().map(|r| r.id).or(after_id);
+    let events: Vec<Value> = rows
+        .into_iter()
+        .map(|r| {
+            json!({
+                "id": r.id,
+                "type": r.event_type,
+                "instance": r.instance,
+                "data": r.data,
+                "created_at": r.created_at,
+            })
+        })
+        .collect();
+    Ok(Json(json!({
+        "events": events,
+        "next_since": next_since,
+        "has_more": has_more,
+        "gap": gap,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn since_is_an_id_or_a_timestamp() {
+        assert_eq!(parse_since(None), Some(Since::Start));
+        assert_eq!(parse_since(Some("42")), Some(Since::After(42)));
+        assert!(matches!(parse_since(Some("2026-10-16T08:00:00Z")), Some(Since::At(_))));
+        assert_eq!(parse_since(Some("yesterday")), None);
+    }
+
+    #[test]
+    fn type_wildcard_is_a_prefix_match() {
+        assert_eq!(type_pattern("state.*"), "state.%");
+        assert_eq!(type_pattern("usage.anomaly"), "usage.anomaly");
+        assert_eq!(type_pattern("state.read_only"), "state.read\\_only");
+    }
+}
@@ -0,0 +1,352 @@
+// ClaudeHydra v4 -- GitHub PR review connector
+// `POST /api/integrations/github/review` reviews a pull request with the
+// Vesemir → Geralt workflow: Vesemir (testing) reviews correctness and test
+// coverage, then Geralt (security) reviews with Vesemir's notes in hand and
+// gives the verdict. Each step is an agent run without tools (see
+// handlers/agent_run.rs) over the PR's diff.
+//
+// The run is stored as a session (request, then one message per reviewer),
+// and the combined review is posted back as a PR review (COMMENT event) or a
+// plain issue comment. The GitHub token comes from the settings API keys
+// (`POST /api/settings/api-key` with provider `github`) or GITHUB_TOKEN.
+
+use axum::Json;
+use axum::extract::State;
+use axum::http::StatusCode;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use utoipa::ToSchema;
+
+use crate::handlers::AgentRunRequest;
+use crate::state::AppState;
+
+pub(crate) const GITHUB_API: &str = "https://api.github.com";
+/// Diff characters given to the reviewers; the rest is summarized by file name.
+const MAX_DIFF_CHARS: usize = 80_000;
+/// GitHub's limit for review / comment bodies is 65536.
+const MAX_BODY_CHARS: usize = 60_000;
+/// Vesemir (testing), then Geralt (security).
+pub const DEFAULT_REVIEWERS: &[&str] = &["agent-003", "agent-001"];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum PostAs {
+    /// A pull request review with the `COMMENT` event.
+    #[default]
+    Review,
+    /// A regular comment on the PR conversation.
+    Comment,
+    /// Don't post; only return and store the review.
+    None,
+}
+
+/// Request body for `POST /api/integrations/github/review`.
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct GithubReviewRequest {
+    /// `owner/name`.
+    pub repo: String,
+    pub pr_number: u64,
+    #[serde(default)]
+    pub post_as: PostAs,
+    /// Reviewer agent ids, in order (default: Vesemir, Geralt).
+    #[serde(default)]
+    pub reviewers: Option<Vec<String>>,
+}
+
+/// `owner/name` → (owner, name).
+pub(crate) fn parse_repo(repo: &str) -> Option<(&str, &str)> {
+    let (owner, name) = repo.trim().split_once('/')?;
+    let ok = |s: &str| {
+        !s.is_empty()
+            && s.len() <= 100
+            && s.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    };
+    (ok(owner) && ok(name)).then_some((owner, name))
+}
+
+/// Cut the diff at a file boundary and list the files that didn't fit.
+fn fit_diff(diff: &str, max_chars: usize) -> String {
+    if diff.len() <= max_chars {
+        return diff.to_string();
+    }
+    let mut kept = String::new();
+    let mut skipped: Vec<&str> = Vec::new();
+    for (i, file) in diff.split("diff --git ").enumerate() {
+        if file.is_empty() {
+            continue;
+        }
+        let chunk = if i == 0 { file.to_string() } else { format!("diff --git {}", file) };
+        if skipped.is_empty() && kept.len() + chunk.len() <= max_chars {
+            kept.push_str(&chunk);
+        } else {
+            skipped.push(file.lines().next().unwrap_or("").trim());
+        }
+    }
+    format!(
+        "{}\n[{} more file(s) not shown: {}]",
+        kept,
+        skipped.len(),
+        skipped.join(", ")
+    )
+}
+
+/// The text posted to GitHub.
+fn review_body(reviews: &[(String, String)]) -> String {
+    let mut body = String::from("## ClaudeHydra review\n");
+    for (agent, review) in reviews {
+        body.push_str(&format!("\n### {}\n\n{}\n", agent, review.trim()));
+    }
+    if body.chars().count() > MAX_BODY_CHARS {
+        body = body.chars().take(MAX_BODY_CHARS).collect::<String>() + "\n\n… (truncated)";
+    }
+    body
+}
+
+pub(crate) async fn github_token(state: &AppState) -> Option<String> {
+    let stored = state.runtime.read().await.api_keys.get("github").cloned();
+    stored
+        .or_else(|| std::env::var("GITHUB_TOKEN").ok())
+        .filter(|t| !t.trim().is_empty())
+}
+
+pub(crate) fn gateway_error(msg: String) -> (StatusCode, Json<Value>) {
+    tracing::warn!("github_review: {}", msg);
+    (StatusCode::BAD_GATEWAY, Json(json!({ "error": msg })))
+}
+
+pub(crate) async fn github_get(state: &AppState, token: &str, path: &str, accept: &str) -> Result<reqwest::Response, String> {
+    let resp = state
+        .http_client
+        .get(format!("{}{}", GITHUB_API, path))
+        .bearer_auth(token)
+        .header(reqwest::header::ACCEPT, accept)
+        .header(reqwest::header::USER_AGENT, "ClaudeHydra")
+        .send()
+        .await
+        .map_err(|e| format!("GitHub request failed: {}", e))?;
+    if !resp.status().is_success() {
+        return Err(format!("GitHub GET {} returned {}", path, resp.status().as_u16()));
+    }
+    Ok(resp)
+}
+
+pub(crate) async fn github_post(state: &AppState, token: &str, path: &str, payload: &Value) -> Result<Value, String> {
+    let resp = state
+        .http_client
+        .post(format!("{}{}", GITHUB_API, path))
+        .bearer_auth(token)
+        .header(reqwest::header::ACCEPT, "application/vnd.github+json")
+        .header(reqwest::header::USER_AGENT, "ClaudeHydra")
+        .json(payload)
+        .send()
+        .await
+        .map_err(|e| format!("GitHub request failed: {}", e))?;
+    if !resp.status().is_success() {
+        return Err(format!("GitHub POST {} returned {}", path, resp.status().as_u16()));
+    }
+    Ok(resp.json().await.unwrap_or_default())
+}
+
+async fn store_message(state: &AppState, session_id: uuid::Uuid, role: &str, content: &str, agent: Option<&str>) {
+    if let Err(e) = sqlx::query("INSERT INTO ch_messages (session_id, role, content, agent) VALUES ($1, $2, $3, $4)")
+        .bind(session_id)
+        .bind(role)
+        .bind(content)
+        .bind(agent)
+        .execute(&state.db)
+        .await
+    {
+        tracing::error!("github_review: failed to store message: {}", e);
+    }
+}
+
+/// `POST /api/integrations/github/review` — review a PR with the agents and post the result
+#[utoipa::path(post, path = "/api/integrations/github/review", tag = "integrations",
+    request_body = GithubReviewRequest,
+    responses(
+        (status = 200, description = "Reviews, stored session and posted review/comment"),
+        (status = 400, description = "Invalid request or no GitHub token"),
+        (status = 502, description = "GitHub or the AI provider failed")
+    ))]
+pub async fn github_review(
+    State(state): State<AppState>,
+    Json(req): Json<GithubReviewRequest>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let bad_request = |msg: &str| (StatusCode::BAD_REQUEST, Json(json!({ "error": msg })));
+    let (owner, name) = parse_repo(&req.repo).ok_or_else(|| bad_request("repo must be 'owner/name'"))?;
+    if req.pr_number == 0 {
+        return Err(bad_request("pr_number must be positive"));
+    }
+    let reviewers: Vec<String> = req
+        .reviewers
+        .clone()
+        .unwrap_or_else(|| DEFAULT_REVIEWERS.iter().map(|s| s.to_string()).collect());
+    if reviewers.is_empty() || reviewers.len() > 5 {
+        return Err(bad_request("reviewers must list 1-5 agent ids"));
+    }
+    {
+        let agents = state.agents.read().await;
+        if let Some(unknown) = reviewers.iter().find(|id| !agents.iter().any(|a| &a.id == *id)) {
+            return Err((
+                StatusCode::NOT_FOUND,
+                Json(json!({ "error": format!("Agent '{}' not found", unknown) })),
+            ));
+        }
+    }
+    let token = github_token(&state).await.ok_or_else(|| {
+        bad_request("No GitHub token — set one with POST /api/settings/api-key (provider: github) or GITHUB_TOKEN")
+    })?;
+
+    // ── Fetch the PR ────────────────────────────────────────────────────
+    let pr_path = format!("/repos/{}/{}/pulls/{}", owner, name, req.pr_number);
+    let pr: Value = github_get(&state, &token, &pr_path, "application/vnd.github+json")
+        .await
+        .map_err(gateway_error)?
+        .json()
+        .await
+        .map_err(|e| gateway_error(format!("Invalid PR response: {}", e)))?;
+    let diff = github_get(&state, &token, &pr_path, "application/vnd.github.v3.diff")
+        .await
+        .map_err(gateway_error)?
+        .text()
+        .await
+        .map_err(|e| gateway_error(format!("Failed to read diff: {}", e)))?;
+    let title = pr["title"].as_str().unwrap_or("").to_string();
+    let pr_url = pr["html_url"].as_str().unwrap_or("").to_string();
+    let head_sha = pr["head"]["sha"].as_str().map(str::to_string);
+
+    // ── Store the run as a session ──────────────────────────────────────
+    let session_title: String = format!("PR review: {}/{}#{} {}", owner, name, req.pr_number, title)
+        .chars()
+        .take(200)
+        .collect();
+    let session_id: uuid::Uuid = sqlx::query_scalar("INSERT INTO ch_sessions (title) VALUES ($1) RETURNING id")
+        .bind(&session_title)
+        .fetch_one(&state.db)
+        .await
+        .map_err(|e| {
+            tracing::error!("github_review: failed to create session: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Failed to create review session" })),
+            )
+        })?;
+    let context = format!(
+        "Pull request {}/{}#{}: {}\n{}\n\n{}\n\n```diff\n{}\n```",
+        owner,
+        name,
+        req.pr_number,
+        title,
+        pr_url,
+        pr["body"].as_str().unwrap_or("").trim(),
+        fit_diff(&diff, MAX_DIFF_CHARS)
+    );
+    store_message(&state, session_id, "user", &context, None).await;
+
+    // ── Vesemir → Geralt ────────────────────────────────────────────────
+    let mut reviews: Vec<(String, String)> = Vec::new();
+    let last = reviewers.len() - 1;
+    for (i, agent_id) in reviewers.iter().enumerate() {
+        let earlier: String = reviews
+            .iter()
+            .map(|(agent, review)| format!("\n\n### Review by {}\n{}", agent, review))
+            .collect();
+        let ask = if i == last {
+            "Review this pull request from your specialty. Consider the earlier reviews, \
+             then end with a verdict: APPROVE, REQUEST CHANGES, or COMMENT, and the reasons."
+        } else {
+            "Review this pull request from your specialty. List concrete issues with file and \
+             line references, and missing tests. Be specific and brief."
+        };
+        let run_req = AgentRunRequest {
+            prompt: format!("{}\n\n{}{}", ask, context, earlier),
+            max_iterations: Some(1),
+            tools: Some(Vec::new()),
+            working_directory: None,
+        };
+        let run = crate::handlers::agent_run::execute_run(&state, agent_id, &run_req, Default::default()).await?;
+        let agent = run["agent"]["name"].as_str().unwrap_or(agent_id).to_string();
+        let review = run["answer"].as_str().unwrap_or("").to_string();
+        store_message(&state, session_id, "assistant", &review, Some(&agent)).await;
+        reviews.push((agent, review));
+    }
+
+    // ── Post back ───────────────────────────────────────────────────────
+    let body = review_body(&reviews);
+    let posted = match req.post_as {
+        PostAs::None => None,
+        post_as => {
+            let (path, payload) = match post_as {
+                PostAs::Review => (
+                    format!("{}/reviews", pr_path),
+                    match &head_sha {
+                        Some(sha) => json!({ "body": body, "event": "COMMENT", "commit_id": sha }),
+                        None => json!({ "body": body, "event": "COMMENT" }),
+                    },
+                ),
+                _ => (
+                    format!("/repos/{}/{}/issues/{}/comments", owner, name, req.pr_number),
+                    json!({ "body": body }),
+                ),
+            };
+            let created = github_post(&state, &token, &path, &payload)
+                .await
+                .map_err(|e| gateway_error(format!("{}; the review is stored in session {}", e, session_id)))?;
+            Some(json!({
+                "kind": if post_as == PostAs::Review { "review" } else { "comment" },
+                "url": created["html_url"],
+            }))
+        }
+    };
+
+    crate::audit::log_audit(
+        &state.db,
+        "github_review",
+        json!({ "repo": req.repo, "pr_number": req.pr_number, "session_id": session_id }),
+        None,
+    )
+    .await;
+    Ok(Json(json!({
+        "session_id": session_id,
+        "pr": { "title": title, "url": pr_url },
+        "reviews": reviews
+            .iter()
+            .map(|(agent, review)| json!({ "agent": agent, "review": review }))
+            .collect::<Vec<_>>(),
+        "posted": posted,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repos_must_be_owner_slash_name() {
+        assert_eq!(parse_repo("octo-org/hello.rs"), Some(("octo-org", "hello.rs")));
+        assert_eq!(parse_repo("nope"), None);
+        assert_eq!(parse_repo("a/b/c"), None);
+        assert_eq!(parse_repo("/b"), None);
+    }
+
+    #[test]
+    fn long_diffs_are_cut_at_file_boundaries() {
+        let diff = "diff --git a/x.rs b/x.rs\n+aaaa\ndiff --git a/y.rs b/y.rs\n+bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb\n";
+        let fitted = fit_diff(diff, 40);
+        assert!(fitted.starts_with("diff --git a/x.rs b/x.rs\n+aaaa\n"));
+        assert!(fitted.ends_with("[1 more file(s) not shown: a/y.rs b/y.rs]"));
+        assert_eq!(fit_diff(diff, 10_000), diff);
+    }
+
+    #[test]
+    fn review_body_has_a_section_per_reviewer() {
+        let body = review_body(&[
+            ("Vesemir".into(), "Missing tests.".into()),
+            ("Geralt".into(), " Verdict: COMMENT ".into()),
+        ]);
+        assert_eq!(
+            body,
+            "## ClaudeHydra review\n\n### Vesemir\n\nMissing tests.\n\n### Geralt\n\nVerdict: COMMENT\n"
+        );
+    }
+}
@@ -0,0 +1,652 @@
+// ClaudeHydra v4 -- GitHub issue triage
+// GitHub `issues` webhooks post to `/api/integrations/github/events`. For a
+// repository configured with `PUT /api/integrations/github/repos/{owner}/{name}`
+// each opened or reopened issue is classified by an agent (Triss by default):
+// labels (from the repository's allow-list when one is set), a priority, and a
+// possible duplicate among earlier issues of the same repository.
+//
+// Every issue seen is kept in ch_github_issues, which is also the index the
+// duplicate search runs on (Postgres full-text search over title + body).
+// In `suggest` mode the result waits for `GET /api/integrations/github/triage`
+// and can be applied with `POST /api/integrations/github/triage/{id}/apply`;
+// in `post` mode labels and a triage comment go to GitHub right away (same
+// token as the PR review connector, see github_review.rs).
+//
+// Deliveries are verified with `X-Hub-Signature-256` against the repository's
+// webhook secret or GITHUB_WEBHOOK_SECRET, and rejected while the `webhooks`
+// subsystem is paused.
+
+use axum::Json;
+use axum::body::Bytes;
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::crypto::{ct_eq, hmac_sha256};
+use crate::github_review::{github_post, github_token, parse_repo};
+use crate::state::AppState;
+
+/// Triss (data) sorts the incoming issues.
+pub const DEFAULT_TRIAGE_AGENT: &str = "agent-004";
+pub const PRIORITIES: &[&str] = &["critical", "high", "medium", "low"];
+/// Earlier issues shown to the agent as duplicate candidates.
+const MAX_CANDIDATES: i64 = 5;
+/// Issue body characters given to the agent.
+const MAX_ISSUE_CHARS: usize = 12_000;
+const MAX_LABELS: usize = 5;
+
+fn bad_request(msg: impl Into<String>) -> (StatusCode, Json<Value>) {
+    (StatusCode::BAD_REQUEST, Json(json!({ "error": msg.into() })))
+}
+
+fn db_error(e: sqlx::Error) -> (StatusCode, Json<Value>) {
+    tracing::error!("github_triage: query failed: {}", e);
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(json!({ "error": "GitHub triage storage unavailable" })),
+    )
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  Storage
+// ═══════════════════════════════════════════════════════════════════════
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+struct RepoConfig {
+    repo: String,
+    mode: String,
+    agent_id: Option<String>,
+    labels: Vec<String>,
+    webhook_secret: Option<String>,
+    enabled: bool,
+    created_at: chrono::DateTime<chrono::Utc>,
+    updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+const REPO_COLUMNS: &str = "repo, mode, agent_id, labels, webhook_secret, enabled, created_at, updated_at";
+
+impl RepoConfig {
+    /// API view — the webhook secret is never returned.
+    fn to_json(&self) -> Value {
+        json!({
+            "repo": self.repo,
+            "mode": self.mode,
+            "agent_id": self.agent_id,
+            "labels": self.labels,
+            "webhook_secret_configured": self.webhook_secret.is_some(),
+            "enabled": self.enabled,
+            "created_at": self.created_at,
+            "updated_at": self.updated_at,
+        })
+    }
+}
+
+async fn load_repo(db: &sqlx::PgPool, repo: &str) -> Result<Option<RepoConfig>, sqlx::Error> {
+    sqlx::query_as::<_, RepoConfig>(&format!(
+        "SELECT {} FROM ch_github_triage_repos WHERE repo = $1",
+        REPO_COLUMNS
+    ))
+    .bind(repo)
+    .fetch_optional(db)
+    .await
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct TriagedIssue {
+    pub id: i64,
+    pub repo: String,
+    pub issue_number: i64,
+    pub title: String,
+    pub html_url: Option<String>,
+    pub author: Option<String>,
+    /// `pending`, `suggested`, `posted` or `failed`.
+    pub status: String,
+    pub labels: Vec<String>,
+    pub priority: Option<String>,
+    pub duplicate_of: Option<i64>,
+    pub summary: Option<String>,
+    pub agent_id: Option<String>,
+    pub error: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub triaged_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+const ISSUE_COLUMNS: &str = "id, repo, issue_number, title, html_url, author, status, labels, priority, \
+     duplicate_of, summary, agent_id, error, created_at, triaged_at";
+
+// ═══════════════════════════════════════════════════════════════════════
+//  Classification
+// ═══════════════════════════════════════════════════════════════════════
+
+/// What the agent suggests for an issue.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Suggestion {
+    labels: Vec<String>,
+    priority: Option<String>,
+    duplicate_of: Option<i64>,
+    summary: String,
+}
+
+/// `sha256=<hex>` over the raw body, as GitHub sends it.
+fn verify_signature(secret: &str, body: &[u8], signature: &str) -> bool {
+    ct_eq(signature, &format!("sha256={}", hmac_sha256(secret.as_bytes(), body)))
+}
+
+/// OR-query over the title's words, so a duplicate doesn't need every word.
+/// Only alphanumeric words are kept, which keeps `to_tsquery` syntax-safe.
+fn duplicate_query(title: &str) -> Option<String> {
+    let mut words: Vec<String> = Vec::new();
+    for word in title.split(|c: char| !c.is_alphanumeric()) {
+        let word = word.to_lowercase();
+        if word.chars().count() >= 3 && !words.contains(&word) {
+            words.push(word);
+        }
+    }
+    (!words.is_empty()).then(|| words.join(" | "))
+}
+
+/// Read the agent's JSON answer, keeping only allowed labels, known priorities
+/// and duplicates among the candidates it was shown.
+fn parse_suggestion(answer: &str, allowed_labels: &[String], candidates: &[i64]) -> Option<Suggestion> {
+    let start = answer.find('{')?;
+    let end = answer.rfind('}')?;
+    let raw: Value = serde_json::from_str(answer.get(start..=end)?).ok()?;
+    let mut labels: Vec<String> = Vec::new();
+    for label in raw["labels"].as_array().into_iter().flatten().filter_map(Value::as_str) {
+        let label = label.trim();
+        let label = if allowed_labels.is_empty() {
+            (!label.is_empty() && label.len() <= 50).then(|| label.to_string())
+        } else {
+            allowed_labels.iter().find(|l| l.eq_ignore_ascii_case(label)).cloned()
+        };
+        if let Some(label) = label
+            && !labels.iter().any(|l| l.eq_ignore_ascii_case(&label))
+            && labels.len() < MAX_LABELS
+        {
+            labels.push(label);
+        }
+    }
+    let priority = raw["priority"]
+        .as_str()
+        .map(|p| p.trim().to_lowercase())
+        .filter(|p| PRIORITIES.contains(&p.as_str()));
+    let duplicate_of = raw["duplicate_of"].as_i64().filter(|n| candidates.contains(n));
+    Some(Suggestion {
+        labels,
+        priority,
+        duplicate_of,
+        summary: raw["summary"].as_str().unwrap_or("").trim().to_string(),
+    })
+}
+
+/// The triage comment posted on the issue.
+fn comment_body(issue: &TriagedIssue) -> String {
+    let mut body = String::from("### ClaudeHydra triage\n");
+    if let Some(ref priority) = issue.priority {
+        body.push_str(&format!("\n**Priority:** {}", priority));
+    }
+    if !issue.labels.is_empty() {
+        body.push_str(&format!("\n**Labels:** {}", issue.labels.join(", ")));
+    }
+    if let Some(dup) = issue.duplicate_of {
+        body.push_str(&format!("\n**Possible duplicate of** #{}", dup));
+    }
+    if let Some(ref summary) = issue.summary
+        && !summary.is_empty()
+    {
+        body.push_str(&format!("\n\n{}", summary));
+    }
+    body.push('\n');
+    body
+}
+
+async fn triage_issue(state: AppState, cfg: RepoConfig, id: i64) {
+    if let Err(e) = run_triage(&state, &cfg, id).await {
+        tracing::warn!("github_triage: {} issue {} failed: {}", cfg.repo, id, e);
+        let _ = sqlx::query("UPDATE ch_github_issues SET status = 'failed', error = $2 WHERE id = $1")
+            .bind(id)
+            .bind(&e)
+            .execute(&state.db)
+            .await;
+    }
+}
+
+async fn run_triage(state: &AppState, cfg: &RepoConfig, id: i64) -> Result<(), String> {
+    let (number, title, body): (i64, String, String) =
+        sqlx::query_as("SELECT issue_number, title, body FROM ch_github_issues WHERE id = $1")
+            .bind(id)
+            .fetch_one(&state.db)
+            .await
+            .map_err(|e| e.to_string())?;
+
+    let candidates: Vec<(i64, String)> = match duplicate_query(&title) {
+        Some(query) => sqlx::query_as(
+            "SELECT issue_number, title FROM ch_github_issues \
+             WHERE repo = $1 AND issue_number <> $2 AND search_vector @@ to_tsquery('english', $3) \
+             ORDER BY ts_rank(search_vector, to_tsquery('english', $3)) DESC LIMIT $4",
+        )
+        .bind(&cfg.repo)
+        .bind(number)
+        .bind(&query)
+        .bind(MAX_CANDIDATES)
+        .fetch_all(&state.db)
+        .await
+        .map_err(|e| e.to_string())?,
+        None => Vec::new(),
+    };
+
+    let labels_rule = if cfg.labels.is_empty() {
+        "Suggest up to 5 short labels.".to_string()
+    } else {
+        format!("Pick up to 5 labels from this list only: {}.", cfg.labels.join(", "))
+    };
+    let candidates_text = if candidates.is_empty() {
+        "(none)".to_string()
+    } else {
+        candidates
+            .iter()
+            .map(|(n, t)| format!("#{}: {}", n, t))
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    let body: String = body.chars().take(MAX_ISSUE_CHARS).collect();
+    let prompt = format!(
+        "Triage this GitHub issue for {repo}. {labels_rule} Rate the priority as one of \
+         critical, high, medium, low. If it duplicates one of the earlier issues listed, give \
+         its number. Answer with JSON only: \
+         {{\"labels\": [], \"priority\": \"\", \"duplicate_of\": null, \"summary\": \"one or two sentences\"}}\n\n\
+         Issue #{number}: {title}\n\n{body}\n\nEarlier issues:\n{candidates_text}",
+        repo = cfg.repo,
+    );
+    let agent_id = cfg.agent_id.as_deref().unwrap_or(DEFAULT_TRIAGE_AGENT);
+    let req = crate::handlers::AgentRunRequest {
+        prompt,
+        max_iterations: Some(1),
+        tools: Some(Vec::new()),
+        working_directory: None,
+    };
+    let run = crate::handlers::agent_run::execute_run(state, agent_id, &req, Default::default())
+        .await
+        .map_err(|(_, Json(err))| err["error"].as_str().unwrap_or("Agent run failed").to_string())?;
+    let answer = run["answer"].as_str().unwrap_or("");
+    let numbers: Vec<i64> = candidates.iter().map(|(n, _)| *n).collect();
+    let suggestion =
+        parse_suggestion(answer, &cfg.labels, &numbers).ok_or("The agent did not answer with triage JSON")?;
+
+    let issue = sqlx::query_as::<_, TriagedIssue>(&format!(
+        "UPDATE ch_github_issues SET status = 'suggested', labels = $2, priority = $3, duplicate_of = $4, \
+             summary = $5, agent_id = $6, error = NULL, triaged_at = NOW() \
+         WHERE id = $1 RETURNING {}",
+        ISSUE_COLUMNS
+    ))
+    .bind(id)
+    .bind(&suggestion.labels)
+    .bind(&suggestion.priority)
+    .bind(suggestion.duplicate_of)
+    .bind(&suggestion.summary)
+    .bind(agent_id)
+    .fetch_one(&state.db)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    if cfg.mode == "post" {
+        apply(state, &issue).await?;
+    }
+    Ok(())
+}
+
+/// Add the suggested labels and the triage comment on GitHub.
+async fn apply(state: &AppState, issue: &TriagedIssue) -> Result<(), String> {
+    let token = github_token(state)
+        .await
+        .ok_or("No GitHub token — set one with POST /api/settings/api-key (provider: github) or GITHUB_TOKEN")?;
+    let issue_path = format!("/repos/{}/issues/{}", issue.repo, issue.issue_number);
+    if !issue.labels.is_empty() {
+        github_post(
+            state,
+            &token,
+            &format!("{}/labels", issue_path),
+            &json!({ "labels": issue.labels }),
+        )
+        .await?;
+    }
+    github_post(
+        state,
+        &token,
+        &format!("{}/comments", issue_path),
+        &json!({ "body": comment_body(issue) }),
+    )
+    .await?;
+    sqlx::query("UPDATE ch_github_issues SET status = 'posted', error = NULL WHERE id = $1")
+        .bind(issue.id)
+        .execute(&state.db)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  GitHub → ClaudeHydra
+// ═══════════════════════════════════════════════════════════════════════
+
+/// `POST /api/integrations/github/events` — GitHub webhook receiver (`issues`, `ping`)
+pub async fn github_events(State(state): State<AppState>, headers: HeaderMap, body: Bytes) -> Response {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).unwrap_or("");
+    let Ok(payload) = serde_json::from_slice::<Value>(&body) else {
+        return bad_request("Invalid JSON").into_response();
+    };
+    let repo = payload["repository"]["full_name"].as_str().unwrap_or("");
+    let cfg = match load_repo(&state.db, repo).await {
+        Ok(Some(cfg)) if cfg.enabled => cfg,
+        Ok(_) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({ "error": "Repository is not configured for triage" })),
+            )
+                .into_response();
+        }
+        Err(e) => return db_error(e).into_response(),
+    };
+    let Some(secret) = cfg
+        .webhook_secret
+        .clone()
+        .or_else(|| std::env::var("GITHUB_WEBHOOK_SECRET").ok())
+        .filter(|s| !s.trim().is_empty())
+    else {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({ "error": "GitHub webhook secret is not configured" })),
+        )
+            .into_response();
+    };
+    if !verify_signature(&secret, &body, header("x-hub-signature-256")) {
+        return (StatusCode::UNAUTHORIZED, Json(json!({ "error": "Invalid GitHub signature" }))).into_response();
+    }
+
+    match header("x-github-event") {
+        "ping" => return Json(json!({ "ok": true })).into_response(),
+        "issues" => {}
+        _ => return StatusCode::OK.into_response(),
+    }
+    let action = payload["action"].as_str().unwrap_or("");
+    let issue = &payload["issue"];
+    let Some(number) = issue["number"].as_i64() else {
+        return bad_request("Missing issue number").into_response();
+    };
+    let title = issue["title"].as_str().unwrap_or("");
+    let issue_body = issue["body"].as_str().unwrap_or("");
+    match action {
+        "opened" | "reopened" => {}
+        // Edits keep the duplicate index current without a new triage.
+        "edited" => {
+            let result = sqlx::query(
+                "UPDATE ch_github_issues SET title = $3, body = $4 WHERE repo = $1 AND issue_number = $2",
+            )
+            .bind(&cfg.repo)
+            .bind(number)
+            .bind(title)
+            .bind(issue_body)
+            .execute(&state.db)
+            .await;
+            return match result {
+                Ok(_) => StatusCode::OK.into_response(),
+                Err(e) => db_error(e).into_response(),
+            };
+        }
+        _ => return StatusCode::OK.into_response(),
+    }
+    let result = sqlx::query_scalar::<_, i64>(
+        "INSERT INTO ch_github_issues (repo, issue_number, title, body, html_url, author) \
+         VALUES ($1, $2, $3, $4, $5, $6) \
+         ON CONFLICT (repo, issue_number) DO UPDATE SET title = $3, body = $4, html_url = $5, \
+             status = 'pending', error = NULL \
+         RETURNING id",
+    )
+    .bind(&cfg.repo)
+    .bind(number)
+    .bind(title)
+    .bind(issue_body)
+    .bind(issue["html_url"].as_str())
+    .bind(issue["user"]["login"].as_str())
+    .fetch_one(&state.db)
+    .await;
+    let id = match result {
+        Ok(id) => id,
+        Err(e) => return db_error(e).into_response(),
+    };
+    tokio::spawn(triage_issue(state, cfg, id));
+    (StatusCode::ACCEPTED, Json(json!({ "queued": true, "id": id }))).into_response()
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  /api/integrations/github/repos, /api/integrations/github/triage
+// ═══════════════════════════════════════════════════════════════════════
+
+#[derive(Debug, Deserialize)]
+pub struct TriageRepoRequest {
+    /// `suggest` (default) or `post`.
+    #[serde(default)]
+    pub mode: Option<String>,
+    #[serde(default)]
+    pub agent_id: Option<String>,
+    #[serde(default)]
+    pub labels: Vec<String>,
+    /// Secret set on the GitHub webhook; kept when omitted.
+    #[serde(default)]
+    pub webhook_secret: Option<String>,
+    #[serde(default)]
+    pub enabled: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TriageListQuery {
+    #[serde(default)]
+    pub repo: Option<String>,
+    #[serde(default)]
+    pub status: Option<String>,
+    #[serde(default)]
+    pub limit: Option<i64>,
+}
+
+/// `GET /api/integrations/github/repos` — repositories configured for triage
+pub async fn list_triage_repos(State(state): State<AppState>) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let repos = sqlx::query_as::<_, RepoConfig>(&format!(
+        "SELECT {} FROM ch_github_triage_repos ORDER BY repo",
+        REPO_COLUMNS
+    ))
+    .fetch_all(&state.db)
+    .await
+    .map_err(db_error)?;
+    Ok(Json(json!({ "repos": repos.iter().map(RepoConfig::to_json).collect::<Vec<_>>() })))
+}
+
+/// `PUT /api/integrations/github/repos/{owner}/{name}` — enable or update triage for a repository
+pub async fn configure_triage_repo(
+    State(state): State<AppState>,
+    Path((owner, name)): Path<(String, String)>,
+    Json(req): Json<TriageRepoRequest>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let repo = format!("{}/{}", owner, name);
+    if parse_repo(&repo).is_none() {
+        return Err(bad_request("repo must be 'owner/name'"));
+    }
+    let mode = req.mode.as_deref().unwrap_or("suggest");
+    if !matches!(mode, "suggest" | "post") {
+        return Err(bad_request("mode must be 'suggest' or 'post'"));
+    }
+    let mut labels: Vec<String> = Vec::new();
+    for label in &req.labels {
+        let label = label.trim();
+        if label.is_empty() || label.len() > 50 {
+            return Err(bad_request("labels must be 1-50 characters"));
+        }
+        if !labels.iter().any(|l| l == label) {
+            labels.push(label.to_string());
+        }
+    }
+    let agent_id = req.agent_id.as_deref().map(str::trim).filter(|a| !a.is_empty());
+    let check_agent = agent_id.unwrap_or(DEFAULT_TRIAGE_AGENT);
+    if !state.agents.read().await.iter().any(|a| a.id == check_agent) {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": format!("Agent '{}' not found", check_agent) })),
+        ));
+    }
+    let secret = req.webhook_secret.as_deref().map(str::trim).filter(|s| !s.is_empty());
+
+    let cfg = sqlx::query_as::<_, RepoConfig>(&format!(
+        "INSERT INTO ch_github_triage_repos (repo, mode, agent_id, labels, webhook_secret, enabled) \
+         VALUES ($1, $2, $3, $4, $5, COALESCE($6, TRUE)) \
+         ON CONFLICT (repo) DO UPDATE SET mode = $2, agent_id = $3, labels = $4, \
+             webhook_secret = COALESCE($5, ch_github_triage_repos.webhook_secret), \
+             enabled = COALESCE($6, ch_github_triage_repos.enabled), updated_at = NOW() \
+         RETURNING {}",
+        REPO_COLUMNS
+    ))
+    .bind(&repo)
+    .bind(mode)
+    .bind(agent_id)
+    .bind(&labels)
+    .bind(secret)
+    .bind(req.enabled)
+    .fetch_one(&state.db)
+    .await
+    .map_err(db_error)?;
+
+    crate::audit::log_audit(
+        &state.db,
+        "github_triage_configured",
+        json!({ "repo": repo, "mode": mode }),
+        None,
+    )
+    .await;
+    Ok(Json(cfg.to_json()))
+}
+
+/// `DELETE /api/integrations/github/repos/{owner}/{name}` — stop triaging a repository (its issues are kept)
+pub async fn remove_triage_repo(
+    State(state): State<AppState>,
+    Path((owner, name)): Path<(String, String)>,
+) -> Result<StatusCode, (StatusCode, Json<Value>)> {
+    let result = sqlx::query("DELETE FROM ch_github_triage_repos WHERE repo = $1")
+        .bind(format!("{}/{}", owner, name))
+        .execute(&state.db)
+        .await
+        .map_err(db_error)?;
+    if result.rows_affected() == 0 {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": "Repository is not configured for triage" })),
+        ));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// `GET /api/integrations/github/triage` — triaged issues, newest first
+pub async fn list_triaged_issues(
+    State(state): State<AppState>,
+    Query(q): Query<TriageListQuery>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    if let Some(ref status) = q.status
+        && !matches!(status.as_str(), "pending" | "suggested" | "posted" | "failed")
+    {
+        return Err(bad_request("status must be pending, suggested, posted or failed"));
+    }
+    let limit = q.limit.unwrap_or(50).clamp(1, 200);
+    let issues = sqlx::query_as::<_, TriagedIssue>(&format!(
+        "SELECT {} FROM ch_github_issues \
+         WHERE ($1::TEXT IS NULL OR repo = $1) AND ($2::TEXT IS NULL OR status = $2) \
+         ORDER BY created_at DESC LIMIT $3",
+        ISSUE_COLUMNS
+    ))
+    .bind(&q.repo)
+    .bind(&q.status)
+    .bind(limit)
+    .fetch_all(&state.db)
+    .await
+    .map_err(db_error)?;
+    Ok(Json(json!({ "issues": issues })))
+}
+
+/// `POST /api/integrations/github/triage/{id}/apply` — post a stored suggestion to GitHub
+pub async fn apply_triage(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let issue = sqlx::query_as::<_, TriagedIssue>(&format!(
+        "SELECT {} FROM ch_github_issues WHERE id = $1",
+        ISSUE_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(db_error)?
+    .ok_or_else(|| (StatusCode::NOT_FOUND, Json(json!({ "error": "Issue not found" }))))?;
+    if !matches!(issue.status.as_str(), "suggested" | "failed") || issue.triaged_at.is_none() {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(json!({ "error": format!("Issue is {}; nothing to apply", issue.status) })),
+        ));
+    }
+    apply(&state, &issue)
+        .await
+        .map_err(crate::github_review::gateway_error)?;
+    crate::audit::log_audit(
+        &state.db,
+        "github_triage_applied",
+        json!({ "repo": issue.repo, "issue_number": issue.issue_number }),
+        None,
+    )
+    .await;
+    Ok(Json(json!({ "posted": true, "id": id })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn github_signatures_cover_the_raw_body() {
+        let body = br#"{"action":"opened"}"#;
+        let sig = format!("sha256={}", hmac_sha256(b"s3cret", body));
+        assert!(verify_signature("s3cret", body, &sig));
+        assert!(!verify_signature("other", body, &sig));
+        assert!(!verify_signature("s3cret", br#"{"action":"closed"}"#, &sig));
+        assert!(!verify_signature("s3cret", body, ""));
+    }
+
+    #[test]
+    fn duplicate_query_ors_distinct_words() {
+        assert_eq!(
+            duplicate_query("Crash on login: login page (v2) crashes!").as_deref(),
+            Some("crash | login | page | crashes")
+        );
+        assert_eq!(duplicate_query("a b ?!"), None);
+        assert_eq!(duplicate_query("x' & !y) | zzz:*").as_deref(), Some("zzz"));
+    }
+
+    #[test]
+    fn suggestions_are_limited_to_allowed_values() {
+        let answer = r#"Here you go:
+        {"labels": ["BUG", "ui", "bug"], "priority": "High", "duplicate_of": 12, "summary": " Login crash. "}"#;
+        let allowed = vec!["bug".to_string(), "docs".to_string()];
+        let s = parse_suggestion(answer, &allowed, &[7, 12]).unwrap();
+        assert_eq!(
+            s,
+            Suggestion {
+                labels: vec!["bug".into()],
+                priority: Some("high".into()),
+                duplicate_of: Some(12),
+                summary: "Login crash.".into(),
+            }
+        );
+
+        let s = parse_suggestion(answer, &[], &[7]).unwrap();
+        assert_eq!(s.labels, vec!["BUG", "ui"]);
+        assert_eq!(s.duplicate_of, None);
+
+        assert!(parse_suggestion("no json here", &[], &[]).is_none());
+    }
+}
@@ -0,0 +1,459 @@
+//! Agent runs — a server-side tool loop driven by one agent.
+//!
+//! Endpoint:
+//! - `POST /api/agents/{id}/run` — run a task to a final answer
+//!
+//! Claude gets the agent's identity and a set of server-side tools. Every
+//! `tool_use` it returns is executed here (behind the usual tool confirmation
+//! policies), answered with a `tool_result`, and the conversation continues
+//! until the model ends its turn or `max_iterations` requests have been made.
+//! Unlike chat, nothing is passed through to the client in between: the
+//! response carries the final answer and a log of the tool calls. Runs
+//! started as background tasks (see `tasks`) report each model turn and tool
+//! call as they happen, through the scope's side channel.
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::{Extension, Json};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use utoipa::ToSchema;
+
+use jaskier_core::handlers::anthropic_streaming::{
+    sanitize_api_error, trim_conversation, truncate_for_context_with_limit as truncate_tool_output,
+};
+
+use crate::models::WitcherAgent;
+use crate::priority::{DefaultPriority, Priority};
+use crate::request_scope::{RequestScope, StreamEvent};
+use crate::state::AppState;
+
+use super::{send_to_anthropic, tool_timeout_secs};
+
+/// Tools offered when the request doesn't choose: web fetch, read-only
+/// filesystem access, and code execution in the sandbox.
+pub const DEFAULT_RUN_TOOLS: &[&str] = &[
+    "fetch_webpage",
+    "read_file",
+    "list_directory",
+    "search_in_files",
+    "sandbox_execute_code",
+];
+/// Hard cap on `max_iterations`.
+pub const MAX_RUN_ITERATIONS: u32 = 25;
+const TOOL_OUTPUT_CHARS: usize = 15_000;
+const LOG_PREVIEW_CHARS: usize = 500;
+const MAX_PROMPT_CHARS: usize = 100_000;
+
+// ── Request / Response types ────────────────────────────────────────────────
+
+/// Request body for `POST /api/agents/{id}/run`.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AgentRunRequest {
+    /// The task.
+    pub prompt: String,
+    /// Model requests allowed, tool rounds included (default: the
+    /// `agent_max_iterations` setting, at most 25).
+    #[serde(default)]
+    pub max_iterations: Option<u32>,
+    /// Tool names to offer (default: web fetch, file read, sandbox).
+    /// `call_agent` is not available in runs.
+    #[serde(default)]
+    pub tools: Option<Vec<String>>,
+    /// Base directory for the filesystem tools.
+    #[serde(default)]
+    pub working_directory: Option<String>,
+}
+
+/// One executed tool call.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ToolCallLog {
+    pub iteration: u32,
+    pub name: String,
+    #[schema(value_type = Object)]
+    pub input: Value,
+    pub is_error: bool,
+    pub duration_ms: u64,
+    /// First 500 characters of the result.
+    pub output_preview: String,
+}
+
+/// Validate the requested tool names against the registered definitions.
+fn select_tools(requested: Option<&[String]>, available: &[String]) -> Result<Vec<String>, String> {
+    let Some(requested) = requested else {
+        return Ok(DEFAULT_RUN_TOOLS
+            .iter()
+            .filter(|t| available.iter().any(|a| a == *t))
+            .map(|t| t.to_string())
+            .collect());
+    };
+    let mut selected: Vec<String> = Vec::new();
+    for name in requested {
+        if name == "call_agent" {
+            return Err("call_agent is not available in agent runs".into());
+        }
+        if !available.contains(name) {
+            return Err(format!("Unknown tool '{}'", name));
+        }
+        if !selected.contains(name) {
+            selected.push(name.clone());
+        }
+    }
+    Ok(selected)
+}
+
+fn run_system_prompt(agent: &WitcherAgent, model: &str, working_directory: &str) -> String {
+    let mut prompt = format!(
+        "## Identity\n\
+         **{}** | {} | {} | `{}` | ClaudeHydra v4 (autonomous run)\n\
+         {}\n\n\
+         ## Rules\n\
+         - Work the task through to a final answer; nobody can reply to questions mid-run.\n\
+         - Use tools when they help. Request independent tool calls in parallel.\n\
+         - Finish with a complete, self-contained answer.",
+        agent.name, agent.role, agent.tier, model, agent.description
+    );
+    if !working_directory.is_empty() {
+        prompt.push_str(&format!(
+            "\n\n## Working Directory\n**Current working directory**: `{}`",
+            working_directory
+        ));
+    }
+    prompt
+}
+
+// ── POST /api/agents/{id}/run ───────────────────────────────────────────────
+
+#[utoipa::path(post, path = "/api/agents/{id}/run", tag = "agents",
+    params(("id" = String, Path, description = "Agent ID")),
+    request_body = AgentRunRequest,
+    responses(
+        (status = 200, description = "Final answer and tool call log"),
+        (status = 400, description = "Invalid request"),
+        (status = 404, description = "Agent not found")
+    ))]
+pub async fn run_agent(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    token_priority: Option<Extension<DefaultPriority>>,
+    Json(req): Json<AgentRunRequest>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let priority = token_priority
+        .map(|Extension(DefaultPriority(p))| p)
+        .unwrap_or_default();
+    let _journal = crate::recovery::begin(
+        crate::recovery::OperationKind::AgentRun,
+        None,
+        format!("agent run as {}", id),
+        json!({ "agent_id": id, "request": req }),
+    );
+    execute_run(&state, &id, &req, priority).await.map(Json)
+}
+
+/// Validate `req` and run it as agent `id` (also used by the Slack connector).
+pub(crate) async fn execute_run(
+    state: &AppState,
+    id: &str,
+    req: &AgentRunRequest,
+    priority: Priority,
+) -> Result<Value, (StatusCode, Json<Value>)> {
+    let bad_request = |msg: String| (StatusCode::BAD_REQUEST, Json(json!({ "error": msg })));
+    let prompt = req.prompt.trim();
+    if prompt.is_empty() {
+        return Err(bad_request("prompt must not be empty".into()));
+    }
+    if prompt.chars().count() > MAX_PROMPT_CHARS {
+        return Err(bad_request(format!("prompt must be at most {} characters", MAX_PROMPT_CHARS)));
+    }
+    if let Some(n) = req.max_iterations
+        && !(1..=MAX_RUN_ITERATIONS).contains(&n)
+    {
+        return Err(bad_request(format!("max_iterations must be between 1 and {}", MAX_RUN_ITERATIONS)));
+    }
+
+    let agent = state
+        .agents
+        .read()
+        .await
+        .iter()
+        .find(|a| a.id == id)
+        .cloned()
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                Json(json!({ "error": format!("Agent '{}' not found", id) })),
+            )
+        })?;
+    crate::agent_status::ensure_usable(&agent)?;
+
+    let working_directory = req.working_directory.as_deref().unwrap_or("").trim().to_string();
+    let model = crate::model_registry::get_model_id(state, &agent.tier.to_lowercase()).await;
+    let max_tokens = super::prompt::tier_token_budget(&model);
+
+    let definitions = state
+        .tool_executor
+        .tool_definitions_with_mcp(state, Some(&model))
+        .await;
+    let available: Vec<String> = definitions.iter().map(|d| d.name.clone()).collect();
+    let selected = select_tools(req.tools.as_deref(), &available).map_err(bad_request)?;
+    let tool_defs: Vec<Value> = definitions
+        .into_iter()
+        .filter(|d| selected.contains(&d.name))
+        .map(|d| json!({ "name": d.name, "description": d.description, "input_schema": d.input_schema }))
+        .collect();
+
+    let max_iterations = match req.max_iterations {
+        Some(n) => n,
+        None => sqlx::query_scalar::<_, i32>(
+            "SELECT COALESCE(agent_max_iterations, 8) FROM ch_settings WHERE id = 1",
+        )
+        .fetch_optional(&state.db)
+        .await
+        .ok()
+        .flatten()
+        .unwrap_or(8)
+        .clamp(1, MAX_RUN_ITERATIONS as i32) as u32,
+    };
+
+    // The agent's stop sequences are applied by `send_to_anthropic`. Progress
+    // goes to the caller's side channel, if it has one (background tasks).
+    let scope = Arc::new(RequestScope {
+        stop_sequences: agent.stop_sequences.clone(),
+        events: crate::request_scope::current().and_then(|s| s.events.clone()),
+        priority,
+        busy: Some(Arc::new(crate::agent_status::busy(&agent.id))),
+        ..Default::default()
+    });
+    tracing::info!(
+        "agent_run: {} ({}) — model={}, tools={:?}, max_iterations={}",
+        agent.name,
+        agent.id,
+        model,
+        selected,
+        max_iterations
+    );
+
+    let system_prompt = run_system_prompt(&agent, &model, &working_directory);
+    crate::request_scope::run(
+        scope,
+        run_loop(
+            state,
+            &agent,
+            &model,
+            max_tokens,
+            &system_prompt,
+            prompt,
+            tool_defs,
+            &working_directory,
+            max_iterations,
+        ),
+    )
+    .await
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn run_loop(
+    state: &AppState,
+    agent: &WitcherAgent,
+    model: &str,
+    max_tokens: u32,
+    system_prompt: &str,
+    prompt: &str,
+    tool_defs: Vec<Value>,
+    working_directory: &str,
+    max_iterations: u32,
+) -> Result<Value, (StatusCode, Json<Value>)> {
+    let started = Instant::now();
+    let executor = state.tool_executor.with_working_directory(working_directory);
+    let mut conversation: Vec<Value> = vec![json!({ "role": "user", "content": prompt })];
+    let mut tool_calls: Vec<ToolCallLog> = Vec::new();
+    let (mut input_tokens, mut output_tokens) = (0u64, 0u64);
+    let mut answer = String::new();
+    let mut stop_reason = String::from("max_iterations");
+    let mut iterations = 0u32;
+
+    while iterations < max_iterations {
+        iterations += 1;
+        let mut body = json!({
+            "model": model,
+            "max_tokens": max_tokens,
+            "system": system_prompt,
+            "messages": &conversation,
+        });
+        if !tool_defs.is_empty() {
+            body["tools"] = json!(tool_defs);
+        }
+
+        let resp = send_to_anthropic(state, &body, crate::endpoint_policies::Group::Chat).await?;
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let err = resp.text().await.unwrap_or_default();
+            tracing::error!("agent_run: {} API error (status={}): {}", agent.name, status, err);
+            return Err((
+                StatusCode::BAD_GATEWAY,
+                Json(json!({ "error": sanitize_api_error(&err), "iterations": iterations })),
+            ));
+        }
+        let resp_json: Value = resp.json().await.map_err(|e| {
+            tracing::error!("agent_run: {} response parse error: {}", agent.name, e);
+            (
+                StatusCode::BAD_GATEWAY,
+                Json(json!({ "error": "Failed to parse AI response" })),
+            )
+        })?;
+
+        let usage = &resp_json["usage"];
+        input_tokens += usage["input_tokens"].as_u64().unwrap_or(0);
+        output_tokens += usage["output_tokens"].as_u64().unwrap_or(0);
+
+        let blocks = resp_json["content"].as_array().cloned().unwrap_or_default();
+        let text: String = blocks
+            .iter()
+            .filter(|b| b["type"] == "text")
+            .filter_map(|b| b["text"].as_str())
+            .collect::<Vec<_>>()
+            .join("");
+        let tool_uses: Vec<&Value> = blocks.iter().filter(|b| b["type"] == "tool_use").collect();
+        let reason = resp_json["stop_reason"].as_str().unwrap_or("end_turn");
+        if !text.is_empty() {
+            crate::request_scope::emit(StreamEvent::Frame(
+                json!({ "type": "text", "iteration": iterations, "text": text }).to_string(),
+            ));
+        }
+
+        if reason != "tool_use" || tool_uses.is_empty() {
+            answer = text;
+            stop_reason = reason.to_string();
+            break;
+        }
+        // Keep whatever was said alongside the last tool round, in case the
+        // iteration budget runs out before a final answer.
+        answer = text;
+
+        conversation.push(json!({ "role": "assistant", "content": blocks }));
+        let mut results: Vec<Value> = Vec::new();
+        for tu in tool_uses {
+            let name = tu["name"].as_str().unwrap_or("");
+            let tool_id = tu["id"].as_str().unwrap_or("");
+            let input = tu.get("input").cloned().unwrap_or_else(|| json!({}));
+            let call_started = Instant::now();
+
+            // The model may only call what it was offered.
+            let offered = tool_defs.iter().any(|d| d["name"] == name);
+            let (output, is_error) = if !offered {
+                (format!("Tool '{}' is not available in this run", name), true)
+            } else if let Err(refusal) = crate::tool_confirmation::gate(state, name, &input).await {
+                (refusal, true)
+            } else {
+                let timeout_secs = tool_timeout_secs();
+                match tokio::time::timeout(
+                    std::time::Duration::from_secs(timeout_secs),
+                    executor.execute_with_state(name, &input, state),
+                )
+                .await
+                {
+                    Ok(res) => res,
+                    Err(_) => (format!("Tool '{}' timed out after {}s", name, timeout_secs), true),
+                }
+            };
+
+            let log = ToolCallLog {
+                iteration: iterations,
+                name: name.to_string(),
+                input,
+                is_error,
+                duration_ms: call_started.elapsed().as_millis() as u64,
+                output_preview: output.chars().take(LOG_PREVIEW_CHARS).collect(),
+            };
+            let mut frame = json!({ "type": "tool_call" });
+            if let (Some(frame), Ok(Value::Object(detail))) = (frame.as_object_mut(), serde_json::to_value(&log)) {
+                frame.extend(detail);
+            }
+            crate::request_scope::emit(StreamEvent::Frame(frame.to_string()));
+            tool_calls.push(log);
+            results.push(json!({
+                "type": "tool_result",
+                "tool_use_id": tool_id,
+                "content": truncate_tool_output(&output, TOOL_OUTPUT_CHARS),
+                "is_error": is_error,
+            }));
+        }
+        conversation.push(json!({ "role": "user", "content": results }));
+        trim_conversation(&mut conversation);
+    }
+
+    tracing::info!(
+        "agent_run: {} finished — stop_reason={}, iterations={}, tool_calls={}",
+        agent.name,
+        stop_reason,
+        iterations,
+        tool_calls.len()
+    );
+    if let Some(refusal) = crate::refusals::detect(Some(&stop_reason), &answer) {
+        crate::refusals::record(
+            &state.db,
+            &refusal,
+            crate::refusals::RefusalContext {
+                model: model.clone(),
+                agent_id: Some(agent.id.clone()),
+                source: "agent_run",
+                ..Default::default()
+            },
+        );
+    }
+    let duration_ms = started.elapsed().as_millis() as u64;
+    crate::webhooks::dispatch(
+        state,
+        "agent.run_completed",
+        json!({
+            "agent": { "id": agent.id, "name": agent.name },
+            "stop_reason": stop_reason,
+            "iterations": iterations,
+            "tool_calls": tool_calls.len(),
+            "answer_preview": answer.chars().take(LOG_PREVIEW_CHARS).collect::<String>(),
+            "duration_ms": duration_ms,
+        }),
+    );
+    Ok(json!({
+        "agent": { "id": agent.id, "name": agent.name, "tier": agent.tier },
+        "model": model,
+        "answer": answer,
+        "stop_reason": stop_reason,
+        "iterations": iterations,
+        "max_iterations": max_iterations,
+        "tool_calls": tool_calls,
+        "usage": { "input_tokens": input_tokens, "output_tokens": output_tokens },
+        "duration_ms": duration_ms,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn default_tools_are_limited_to_registered_ones() {
+        let available = names(&["read_file", "fetch_webpage", "write_file", "call_agent"]);
+        assert_eq!(select_tools(None, &available).unwrap(), names(&["fetch_webpage", "read_file"]));
+    }
+
+    #[test]
+    fn requested_tools_are_validated() {
+        let available = names(&["read_file", "write_file", "call_agent"]);
+        let requested = names(&["write_file", "read_file", "write_file"]);
+        assert_eq!(
+            select_tools(Some(&requested), &available).unwrap(),
+            names(&["write_file", "read_file"])
+        );
+        assert!(select_tools(Some(&names(&["call_agent"])), &available).is_err());
+        assert!(select_tools(Some(&names(&["rm_rf"])), &available).is_err());
+        assert!(select_tools(Some(&[]), &available).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,407 @@
+//! Declarative agent behavior tests.
+//!
+//! Endpoints:
+//! - `GET /api/agents/{id}/tests` — stored tests of an agent
+//! - `POST /api/agents/{id}/tests` — add a test (input → expectations)
+//! - `DELETE /api/agents/{id}/tests/{test_id}` — remove a test
+//! - `POST /api/agents/{id}/tests/run` — run the tests and store the results
+//!
+//! A test sends its input to the agent as a run without tools (see
+//! `agent_run`) and checks the answer against each expectation: a regex that
+//! must (or must not) match, or criteria graded by the executor-tier model.
+//! The `agent-tests` binary runs them against a live backend for CI, so a
+//! prompt edit that breaks an agent's output format fails the build.
+
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use utoipa::ToSchema;
+
+use crate::state::AppState;
+
+use super::AgentRunRequest;
+use super::send_to_anthropic;
+
+const MAX_EXPECTATIONS: usize = 20;
+const MAX_INPUT_CHARS: usize = 20_000;
+const ANSWER_PREVIEW_CHARS: usize = 2_000;
+const JUDGE_MAX_TOKENS: u32 = 300;
+
+type ApiError = (StatusCode, Json<Value>);
+
+fn default_true() -> bool {
+    true
+}
+
+/// One check on the agent's answer.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Expectation {
+    /// The answer must match `pattern` (or must not, with `must_match: false`).
+    Regex {
+        pattern: String,
+        #[serde(default = "default_true")]
+        must_match: bool,
+    },
+    /// A judge model decides whether the answer meets `criteria`.
+    Judge { criteria: String },
+}
+
+/// Request body for `POST /api/agents/{id}/tests`.
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct CreateAgentTestRequest {
+    pub name: String,
+    /// Prompt sent to the agent.
+    pub input: String,
+    pub expectations: Vec<Expectation>,
+}
+
+/// Request body for `POST /api/agents/{id}/tests/run`.
+#[derive(Debug, Clone, Default, Deserialize, ToSchema)]
+pub struct RunAgentTestsRequest {
+    /// Run only these tests (default: all of the agent's tests).
+    #[serde(default)]
+    pub test_ids: Option<Vec<uuid::Uuid>>,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct AgentTest {
+    pub id: uuid::Uuid,
+    pub agent_id: String,
+    pub name: String,
+    pub input: String,
+    pub expectations: sqlx::types::Json<Vec<Expectation>>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+const TEST_COLUMNS: &str = "id, agent_id, name, input, expectations, created_at";
+
+fn db_error(e: sqlx::Error) -> ApiError {
+    tracing::error!("agent_tests: query failed: {}", e);
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(json!({ "error": "Agent test storage unavailable" })),
+    )
+}
+
+async fn require_agent(state: &AppState, id: &str) -> Result<(), ApiError> {
+    if state.agents.read().await.iter().any(|a| a.id == id) {
+        Ok(())
+    } else {
+        Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": format!("Agent '{}' not found", id) })),
+        ))
+    }
+}
+
+fn validate_test(req: &CreateAgentTestRequest) -> Result<(), String> {
+    if req.name.trim().is_empty() || req.name.chars().count() > 100 {
+        return Err("name must be 1-100 characters".into());
+    }
+    if req.input.trim().is_empty() || req.input.chars().count() > MAX_INPUT_CHARS {
+        return Err(format!("input must be 1-{} characters", MAX_INPUT_CHARS));
+    }
+    if req.expectations.is_empty() || req.expectations.len() > MAX_EXPECTATIONS {
+        return Err(format!("expectations must list 1-{} checks", MAX_EXPECTATIONS));
+    }
+    for e in &req.expectations {
+        match e {
+            Expectation::Regex { pattern, .. } => {
+                regex::Regex::new(pattern).map_err(|err| format!("invalid regex '{}': {}", pattern, err))?;
+            }
+            Expectation::Judge { criteria } if criteria.trim().is_empty() => {
+                return Err("judge criteria must not be empty".into());
+            }
+            Expectation::Judge { .. } => {}
+        }
+    }
+    Ok(())
+}
+
+/// `(passed, detail)` for a regex expectation.
+fn check_regex(pattern: &str, must_match: bool, answer: &str) -> (bool, String) {
+    match regex::Regex::new(pattern) {
+        Ok(re) => {
+            let found = re.find(answer);
+            let passed = found.is_some() == must_match;
+            let detail = match (found, must_match) {
+                (Some(m), true) => format!("matched \"{}\"", m.as_str().chars().take(80).collect::<String>()),
+                (None, true) => "no match".to_string(),
+                (Some(m), false) => format!(
+                    "unexpected match \"{}\"",
+                    m.as_str().chars().take(80).collect::<String>()
+                ),
+                (None, false) => "no match, as expected".to_string(),
+            };
+            (passed, detail)
+        }
+        Err(e) => (false, format!("invalid regex: {}", e)),
+    }
+}
+
+/// Read `{"pass": bool, "reason": "…"}` out of the judge's reply.
+fn parse_verdict(text: &str) -> Option<(bool, String)> {
+    let start = text.find('{')?;
+    let end = text.rfind('}')?;
+    let v: Value = serde_json::from_str(text.get(start..=end)?).ok()?;
+    Some((
+        v["pass"].as_bool()?,
+        v["reason"].as_str().unwrap_or("").trim().to_string(),
+    ))
+}
+
+async fn check_judge(state: &AppState, criteria: &str, input: &str, answer: &str) -> (bool, String) {
+    let model = crate::model_registry::get_model_id(state, "executor").await;
+    let body = json!({
+        "model": model,
+        "max_tokens": JUDGE_MAX_TOKENS,
+        "system": "You grade an AI agent's answer against criteria. Be strict and literal. \
+                   Reply with JSON only: {\"pass\": true|false, \"reason\": \"one sentence\"}.",
+        "messages": [{
+            "role": "user",
+            "content": format!(
+                "## Criteria\n{}\n\n## Prompt given to the agent\n{}\n\n## Agent's answer\n{}",
+                criteria, input, answer
+            ),
+        }],
+    });
+    let resp = match send_to_anthropic(state, &body, crate::endpoint_policies::Group::Chat).await {
+        Ok(r) if r.status().is_success() => r,
+        Ok(r) => return (false, format!("judge call failed ({})", r.status().as_u16())),
+        Err((_, Json(err))) => {
+            return (false, format!("judge call failed: {}", err["error"].as_str().unwrap_or("unknown")));
+        }
+    };
+    let reply: Value = resp.json().await.unwrap_or_default();
+    let text = reply["content"][0]["text"].as_str().unwrap_or("");
+    parse_verdict(text).unwrap_or_else(|| (false, "judge reply was not a verdict".to_string()))
+}
+
+async fn run_test(state: &AppState, test: &AgentTest) -> Value {
+    let req = AgentRunRequest {
+        prompt: test.input.clone(),
+        max_iterations: Some(1),
+        tools: Some(Vec::new()),
+        working_directory: None,
+    };
+    let run = match super::agent_run::execute_run(state, &test.agent_id, &req, Default::default()).await {
+        Ok(run) => run,
+        Err((_, Json(err))) => {
+            return json!({
+                "test_id": test.id,
+                "name": test.name,
+                "passed": false,
+                "error": err["error"].as_str().unwrap_or("Agent run failed"),
+                "expectations": [],
+            });
+        }
+    };
+    let answer = run["answer"].as_str().unwrap_or("");
+    let mut checks: Vec<Value> = Vec::new();
+    for e in test.expectations.iter() {
+        let (passed, detail) = match e {
+            Expectation::Regex { pattern, must_match } => check_regex(pattern, *must_match, answer),
+            Expectation::Judge { criteria } => check_judge(state, criteria, &test.input, answer).await,
+        };
+        checks.push(json!({ "expectation": e, "passed": passed, "detail": detail }));
+    }
+    json!({
+        "test_id": test.id,
+        "name": test.name,
+        "passed": checks.iter().all(|c| c["passed"] == true),
+        "answer": answer.chars().take(ANSWER_PREVIEW_CHARS).collect::<String>(),
+        "model": run["model"],
+        "expectations": checks,
+    })
+}
+
+// ── GET /api/agents/{id}/tests ──────────────────────────────────────────────
+
+#[utoipa::path(get, path = "/api/agents/{id}/tests", tag = "agents",
+    params(("id" = String, Path, description = "Agent ID")),
+    responses((status = 200, description = "Stored behavior tests"), (status = 404, description = "Agent not found")))]
+pub async fn list_agent_tests(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    require_agent(&state, &id).await?;
+    let tests = sqlx::query_as::<_, AgentTest>(&format!(
+        "SELECT {} FROM ch_agent_tests WHERE agent_id = $1 ORDER BY name",
+        TEST_COLUMNS
+    ))
+    .bind(&id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(db_error)?;
+    Ok(Json(json!({ "tests": tests })))
+}
+
+// ── POST /api/agents/{id}/tests ─────────────────────────────────────────────
+
+#[utoipa::path(post, path = "/api/agents/{id}/tests", tag = "agents",
+    params(("id" = String, Path, description = "Agent ID")),
+    request_body = CreateAgentTestRequest,
+    responses(
+        (status = 201, description = "Test created"),
+        (status = 400, description = "Invalid test"),
+        (status = 404, description = "Agent not found"),
+        (status = 409, description = "A test with this name exists")
+    ))]
+pub async fn create_agent_test(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(req): Json<CreateAgentTestRequest>,
+) -> Result<(StatusCode, Json<Value>), ApiError> {
+    validate_test(&req).map_err(|e| (StatusCode::BAD_REQUEST, Json(json!({ "error": e }))))?;
+    require_agent(&state, &id).await?;
+    let test = sqlx::query_as::<_, AgentTest>(&format!(
+        "INSERT INTO ch_agent_tests (agent_id, name, input, expectations) VALUES ($1, $2, $3, $4) \
+         ON CONFLICT (agent_id, name) DO NOTHING RETURNING {}",
+        TEST_COLUMNS
+    ))
+    .bind(&id)
+    .bind(req.name.trim())
+    .bind(&req.input)
+    .bind(sqlx::types::Json(&req.expectations))
+    .fetch_optional(&state.db)
+    .await
+    .map_err(db_error)?
+    .ok_or_else(|| {
+        (
+            StatusCode::CONFLICT,
+            Json(json!({ "error": format!("Test '{}' already exists", req.name.trim()) })),
+        )
+    })?;
+    Ok((StatusCode::CREATED, Json(json!(test))))
+}
+
+// ── DELETE /api/agents/{id}/tests/{test_id} ─────────────────────────────────
+
+#[utoipa::path(delete, path = "/api/agents/{id}/tests/{test_id}", tag = "agents",
+    params(("id" = String, Path, description = "Agent ID"), ("test_id" = String, Path, description = "Test ID")),
+    responses((status = 204, description = "Test deleted"), (status = 404, description = "Test not found")))]
+pub async fn delete_agent_test(
+    State(state): State<AppState>,
+    Path((id, test_id)): Path<(String, uuid::Uuid)>,
+) -> Result<StatusCode, ApiError> {
+    let result = sqlx::query("DELETE FROM ch_agent_tests WHERE agent_id = $1 AND id = $2")
+        .bind(&id)
+        .bind(test_id)
+        .execute(&state.db)
+        .await
+        .map_err(db_error)?;
+    if result.rows_affected() == 0 {
+        return Err((StatusCode::NOT_FOUND, Json(json!({ "error": "Test not found" }))));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// ── POST /api/agents/{id}/tests/run ─────────────────────────────────────────
+
+#[utoipa::path(post, path = "/api/agents/{id}/tests/run", tag = "agents",
+    params(("id" = String, Path, description = "Agent ID")),
+    request_body = RunAgentTestsRequest,
+    responses(
+        (status = 200, description = "Per-test results with pass/fail counts"),
+        (status = 404, description = "Agent not found")
+    ))]
+pub async fn run_agent_tests(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(req): Json<RunAgentTestsRequest>,
+) -> Result<Json<Value>, ApiError> {
+    require_agent(&state, &id).await?;
+    let tests = sqlx::query_as::<_, AgentTest>(&format!(
+        "SELECT {} FROM ch_agent_tests WHERE agent_id = $1 AND ($2::UUID[] IS NULL OR id = ANY($2)) \
+         ORDER BY name",
+        TEST_COLUMNS
+    ))
+    .bind(&id)
+    .bind(&req.test_ids)
+    .fetch_all(&state.db)
+    .await
+    .map_err(db_error)?;
+
+    // Sequential: keeps provider load (and cost) predictable.
+    let mut results: Vec<Value> = Vec::with_capacity(tests.len());
+    for test in &tests {
+        results.push(run_test(&state, test).await);
+    }
+    let passed = results.iter().filter(|r| r["passed"] == true).count();
+    let failed = results.len() - passed;
+
+    let run_id: Option<uuid::Uuid> = sqlx::query_scalar(
+        "INSERT INTO ch_agent_test_runs (agent_id, passed, failed, results) VALUES ($1, $2, $3, $4) RETURNING id",
+    )
+    .bind(&id)
+    .bind(passed as i32)
+    .bind(failed as i32)
+    .bind(json!(results))
+    .fetch_one(&state.db)
+    .await
+    .map_err(|e| tracing::warn!("agent_tests: failed to store run for {}: {}", id, e))
+    .ok();
+    Ok(Json(json!({
+        "run_id": run_id,
+        "agent_id": id,
+        "passed": passed,
+        "failed": failed,
+        "results": results,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regex_expectations_can_require_or_forbid_a_match() {
+        let answer = "## Verdict\nAPPROVE — no issues found.";
+        assert!(check_regex(r"(?m)^## Verdict$", true, answer).0);
+        assert!(!check_regex(r"REQUEST CHANGES", true, answer).0);
+        assert!(check_regex(r"(?i)as an ai", false, answer).0);
+        let (passed, detail) = check_regex("APPROVE", false, answer);
+        assert!(!passed);
+        assert_eq!(detail, "unexpected match \"APPROVE\"");
+    }
+
+    #[test]
+    fn expectations_deserialize_with_defaults() {
+        let parsed: Vec<Expectation> = serde_json::from_value(json!([
+            { "type": "regex", "pattern": "^##" },
+            { "type": "judge", "criteria": "Answers in Polish" }
+        ]))
+        .unwrap();
+        assert_eq!(
+            parsed[0],
+            Expectation::Regex { pattern: "^##".into(), must_match: true }
+        );
+        assert!(matches!(parsed[1], Expectation::Judge { .. }));
+    }
+
+    #[test]
+    fn invalid_tests_are_rejected() {
+        let test = |expectations: Vec<Expectation>| CreateAgentTestRequest {
+            name: "format".into(),
+            input: "Review this".into(),
+            expectations,
+        };
+        assert!(validate_test(&test(vec![Expectation::Regex { pattern: "(".into(), must_match: true }])).is_err());
+        assert!(validate_test(&test(vec![])).is_err());
+        assert!(validate_test(&test(vec![Expectation::Judge { criteria: " ".into() }])).is_err());
+        assert!(validate_test(&test(vec![Expectation::Judge { criteria: "Brief".into() }])).is_ok());
+    }
+
+    #[test]
+    fn judge_verdicts_are_read_from_json() {
+        assert_eq!(
+            parse_verdict("```json\n{\"pass\": true, \"reason\": \"Uses headings.\"}\n```"),
+            Some((true, "Uses headings.".to_string()))
+        );
+        assert_eq!(parse_verdict("{\"reason\": \"missing pass\"}"), None);
+        assert_eq!(parse_verdict("PASS"), None);
+    }
+}
@@ -8,9 +8,14 @@ use futures_util::stream::Stream;
 use serde_json::{Value, json};
 use std::convert::Infallible;
 
-use crate::models::{AgentConfigRow, CreateAgentRequest, UpdateAgentRequest, WitcherAgent};
+use crate::models::{AgentConfigRow, AgentPack, CreateAgentRequest, UpdateAgentRequest, WitcherAgent};
 use crate::state::AppState;
 
+/// Longest accepted agent system prompt.
+const MAX_SYSTEM_PROMPT_CHARS: usize = 20_000;
+/// Largest accepted agent `max_tokens` (the model's tier budget still caps it).
+const MAX_AGENT_MAX_TOKENS: u32 = 64_000;
+
 // ═══════════════════════════════════════════════════════════════════════
 //  GET /api/agents — list all agents (from in-memory cache)
 // ═══════════════════════════════════════════════════════════════════════
@@ -23,9 +28,9 @@ use crate::state::AppState;
 )]
 pub async fn list_agents(State(state): State<AppState>) -> Json<Value> {
     let agents = state.agents.read().await;
-    Json(
-        serde_json::to_value(&*agents).unwrap_or_else(|_| json!({"error": "serialization failed"})),
-    )
+    Json(Value::Array(
+        agents.iter().map(crate::agent_status::with_activity).collect(),
+    ))
 }
 
 // ═══════════════════════════════════════════════════════════════════════
@@ -47,7 +52,7 @@ pub async fn get_agent(
     Path(id): Path<String>,
 ) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
     let row: Option<AgentConfigRow> = sqlx::query_as(
-        "SELECT id, name, role, tier, status, description, model, created_at, updated_at \
+        "SELECT id, name, role, tier, status, description, model, stop_sequences, system_prompt, temperature, max_tokens, created_at, updated_at \
          FROM ch_agents_config WHERE id = $1",
     )
     .bind(&id)
@@ -64,7 +69,7 @@ pub async fn get_agent(
     match row {
         Some(agent) => {
             let wa: WitcherAgent = agent.into();
-            Ok(Json(serde_json::to_value(wa).unwrap_or_else(|_| json!({}))))
+            Ok(Json(crate::agent_status::with_activity(&wa)))
         }
         None => Err((
             StatusCode::NOT_FOUND,
@@ -92,6 +97,58 @@ pub async fn create_agent(
     State(state): State<AppState>,
     Json(req): Json<CreateAgentRequest>,
 ) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)> {
+    let wa = insert_agent(&state, req).await?;
+    // Refresh in-memory cache (and on other replicas)
+    state.refresh_agents().await;
+    crate::cluster::publish(&state, crate::cluster::ClusterEvent::AgentsChanged);
+    tracing::info!("Agent created: {} ({})", wa.name, wa.id);
+    Ok((
+        StatusCode::CREATED,
+        Json(serde_json::to_value(wa).unwrap_or_else(|_| json!({}))),
+    ))
+}
+
+/// Reject agent system prompts over `MAX_SYSTEM_PROMPT_CHARS`.
+fn check_system_prompt(system_prompt: &str) -> Result<(), (StatusCode, Json<Value>)> {
+    if system_prompt.chars().count() > MAX_SYSTEM_PROMPT_CHARS {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": format!("system_prompt must be at most {} characters", MAX_SYSTEM_PROMPT_CHARS) })),
+        ));
+    }
+    Ok(())
+}
+
+/// Reject agent generation parameters the API would refuse.
+fn check_generation_params(
+    temperature: Option<f64>,
+    max_tokens: Option<u32>,
+) -> Result<(), (StatusCode, Json<Value>)> {
+    if let Some(t) = temperature
+        && !(0.0..=1.0).contains(&t)
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "temperature must be between 0 and 1" })),
+        ));
+    }
+    if let Some(n) = max_tokens
+        && !(1..=MAX_AGENT_MAX_TOKENS).contains(&n)
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": format!("max_tokens must be between 1 and {}", MAX_AGENT_MAX_TOKENS) })),
+        ));
+    }
+    Ok(())
+}
+
+/// Validate and store a new agent with the next sequential ID. The caller
+/// refreshes the agents cache.
+pub(crate) async fn insert_agent(
+    state: &AppState,
+    req: CreateAgentRequest,
+) -> Result<WitcherAgent, (StatusCode, Json<Value>)> {
     // Validate tier
     if !["Commander", "Coordinator", "Executor"].contains(&req.tier.as_str()) {
         return Err((
@@ -108,6 +165,9 @@ pub async fn create_agent(
             Json(json!({ "error": "name must not be empty" })),
         ));
     }
+    check_system_prompt(&req.system_prompt)?;
+    check_generation_params(req.temperature, req.max_tokens)?;
+    crate::agent_status::check(&req.status)?;
 
     // Generate next sequential ID
     let next_id = {
@@ -131,23 +191,18 @@ pub async fn create_agent(
         }
     };
 
-    // Resolve model — use provided or derive from tier
+    // Resolve model — use provided or derive from tier (see tier_models)
     let model = if req.model.is_empty() {
-        match req.tier.as_str() {
-            "Commander" => "claude-opus-4-6",
-            "Coordinator" => "claude-sonnet-4-6",
-            "Executor" => "claude-haiku-4-5-20251001",
-            _ => "claude-sonnet-4-6",
-        }
-        .to_string()
+        crate::tier_models::agent_model(&req.tier)
     } else {
         req.model
     };
 
     let row: Result<AgentConfigRow, _> = sqlx::query_as(
-        "INSERT INTO ch_agents_config (id, name, role, tier, status, description, model) \
-         VALUES ($1, $2, $3, $4, $5, $6, $7) \
-         RETURNING id, name, role, tier, status, description, model, created_at, updated_at",
+        "INSERT INTO ch_agents_config \
+         (id, name, role, tier, status, description, model, stop_sequences, system_prompt, temperature, max_tokens) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11) \
+         RETURNING id, name, role, tier, status, description, model, stop_sequences, system_prompt, temperature, max_tokens, created_at, updated_at",
     )
     .bind(&next_id)
     .bind(&name)
@@ -156,20 +211,15 @@ pub async fn create_agent(
     .bind(&req.status)
     .bind(&req.description)
     .bind(&model)
+    .bind(crate::request_scope::merge_stop_sequences([req.stop_sequences.as_slice()]))
+    .bind(req.system_prompt.trim())
+    .bind(req.temperature)
+    .bind(req.max_tokens.map(|n| n as i32))
     .fetch_one(&state.db)
     .await;
 
     match row {
-        Ok(agent) => {
-            let wa: WitcherAgent = agent.into();
-            // Refresh in-memory cache
-            state.refresh_agents().await;
-            tracing::info!("Agent created: {} ({})", wa.name, wa.id);
-            Ok((
-                StatusCode::CREATED,
-                Json(serde_json::to_value(wa).unwrap_or_else(|_| json!({}))),
-            ))
-        }
+        Ok(agent) => Ok(agent.into()),
         Err(e) => {
             let msg = e.to_string();
             if msg.contains("duplicate key") || msg.contains("unique constraint") {
@@ -189,7 +239,76 @@ pub async fn create_agent(
 }
 
 // ═══════════════════════════════════════════════════════════════════════
-//  PUT /api/agents/{id} — update an existing agent
+//  POST /api/agents/import — install an agent pack
+// ═══════════════════════════════════════════════════════════════════════
+
+/// Store every agent of `pack`. Agents whose name is taken are skipped, so
+/// installing a pack twice is harmless; any other failure stops the import
+/// (agents stored so far are kept).
+pub(crate) async fn import_pack(
+    state: &AppState,
+    pack: AgentPack,
+) -> Result<Value, (StatusCode, Json<Value>)> {
+    if pack.agents.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "The pack contains no agents" })),
+        ));
+    }
+    let mut installed = Vec::new();
+    let mut skipped = Vec::new();
+    let mut result = Ok(());
+    for agent in pack.agents {
+        let name = agent.name.clone();
+        match insert_agent(state, agent).await {
+            Ok(wa) => installed.push(wa),
+            Err((StatusCode::CONFLICT, _)) => skipped.push(name),
+            Err(e) => {
+                result = Err(e);
+                break;
+            }
+        }
+    }
+    if !installed.is_empty() {
+        state.refresh_agents().await;
+        crate::cluster::publish(state, crate::cluster::ClusterEvent::AgentsChanged);
+    }
+    result?;
+    tracing::info!(
+        "Agent pack imported: {} ({} installed, {} skipped)",
+        pack.name,
+        installed.len(),
+        skipped.len()
+    );
+    Ok(json!({
+        "pack": pack.name,
+        "version": pack.version,
+        "installed": installed,
+        "skipped": skipped,
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/agents/import",
+    tag = "agents",
+    request_body = AgentPack,
+    responses(
+        (status = 201, description = "Installed agents and the names skipped as already present"),
+        (status = 400, description = "Empty pack or an invalid agent")
+    )
+)]
+pub async fn import_agent_pack(
+    State(state): State<AppState>,
+    Json(pack): Json<AgentPack>,
+) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)> {
+    let result = import_pack(&state, pack).await?;
+    crate::audit::log_audit(&state.db, "agents.import", result.clone(), None).await;
+    Ok((StatusCode::CREATED, Json(result)))
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  PUT|PATCH /api/agents/{id} — update an existing agent (partial)
 // ═══════════════════════════════════════════════════════════════════════
 
 #[utoipa::path(
@@ -226,6 +345,13 @@ pub async fn update_agent(
                 Json(json!({ "error": "name must not be empty" })),
             ));
         }
+    if let Some(ref system_prompt) = req.system_prompt {
+        check_system_prompt(system_prompt)?;
+    }
+    check_generation_params(req.temperature, req.max_tokens)?;
+    if let Some(ref status) = req.status {
+        crate::agent_status::check(status)?;
+    }
 
     // Use COALESCE pattern: only update fields that are provided (non-null)
     let row: Option<AgentConfigRow> = sqlx::query_as(
@@ -236,9 +362,13 @@ pub async fn update_agent(
             status = COALESCE($5, status), \
             description = COALESCE($6, description), \
             model = COALESCE($7, model), \
+            stop_sequences = COALESCE($8, stop_sequences), \
+            system_prompt = COALESCE($9, system_prompt), \
+            temperature = COALESCE($10, temperature), \
+            max_tokens = COALESCE($11, max_tokens), \
             updated_at = now() \
          WHERE id = $1 \
-         RETURNING id, name, role, tier, status, description, model, created_at, updated_at",
+         RETURNING id, name, role, tier, status, description, model, stop_sequences, system_prompt, temperature, max_tokens, created_at, updated_at",
     )
     .bind(&id)
     .bind(&req.name)
@@ -247,6 +377,14 @@ pub async fn update_agent(
     .bind(&req.status)
     .bind(&req.description)
     .bind(&req.model)
+    .bind(
+        req.stop_sequences
+            .as_deref()
+            .map(|s| crate::request_scope::merge_stop_sequences([s])),
+    )
+    .bind(req.system_prompt.as_deref().map(str::trim))
+    .bind(req.temperature)
+    .bind(req.max_tokens.map(|n| n as i32))
     .fetch_optional(&state.db)
     .await
     .map_err(|e| {
@@ -273,8 +411,9 @@ pub async fn update_agent(
     match row {
         Some(agent) => {
             let wa: WitcherAgent = agent.into();
-            // Refresh in-memory cache
+            // Refresh in-memory cache (and on other replicas)
             state.refresh_agents().await;
+            crate::cluster::publish(&state, crate::cluster::ClusterEvent::AgentsChanged);
             tracing::info!("Agent updated: {} ({})", wa.name, wa.id);
             Ok(Json(serde_json::to_value(wa).unwrap_or_else(|_| json!({}))))
         }
@@ -322,8 +461,9 @@ pub async fn delete_agent(
         ));
     }
 
-    // Refresh in-memory cache
+    // Refresh in-memory cache (and on other replicas)
     state.refresh_agents().await;
+    crate::cluster::publish(&state, crate::cluster::ClusterEvent::AgentsChanged);
     tracing::info!("Agent deleted: {}", id);
 
     Ok(Json(json!({
@@ -459,3 +599,64 @@ pub async fn delegations_stream(
 
     Sse::new(stream).keep_alive(KeepAlive::new())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn system_prompt_limit_counts_characters() {
+        assert!(check_system_prompt("").is_ok());
+        assert!(check_system_prompt(&"x".repeat(MAX_SYSTEM_PROMPT_CHARS)).is_ok());
+        // Multi-byte characters count once, not per UTF-8 byte.
+        assert!(check_system_prompt(&"ż".repeat(MAX_SYSTEM_PROMPT_CHARS)).is_ok());
+
+        let (status, Json(body)) = check_system_prompt(&"x".repeat(MAX_SYSTEM_PROMPT_CHARS + 1)).unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], "system_prompt must be at most 20000 characters");
+    }
+
+    #[test]
+    fn generation_params_are_bounded() {
+        assert!(check_generation_params(None, None).is_ok());
+        assert!(check_generation_params(Some(0.0), Some(1)).is_ok());
+        assert!(check_generation_params(Some(1.0), Some(MAX_AGENT_MAX_TOKENS)).is_ok());
+        assert!(check_generation_params(Some(-0.1), None).is_err());
+        assert!(check_generation_params(Some(1.5), None).is_err());
+        assert!(check_generation_params(None, Some(0)).is_err());
+        assert!(check_generation_params(None, Some(MAX_AGENT_MAX_TOKENS + 1)).is_err());
+    }
+
+    #[test]
+    fn partial_update_leaves_omitted_fields_unset() {
+        // Omitted fields bind as NULL, so the COALESCE update keeps them.
+        let req: UpdateAgentRequest =
+            serde_json::from_value(json!({ "system_prompt": "Answer in Polish." })).unwrap();
+        assert_eq!(req.system_prompt.as_deref(), Some("Answer in Polish."));
+        assert!(req.name.is_none() && req.role.is_none() && req.tier.is_none());
+        assert!(req.status.is_none() && req.description.is_none() && req.model.is_none());
+        assert!(req.stop_sequences.is_none() && req.temperature.is_none() && req.max_tokens.is_none());
+    }
+
+    #[test]
+    fn system_prompt_survives_the_row_round_trip() {
+        let row = AgentConfigRow {
+            id: "agent-001".into(),
+            name: "Geralt".into(),
+            role: "Security".into(),
+            tier: "Commander".into(),
+            status: "active".into(),
+            description: String::new(),
+            model: String::new(),
+            stop_sequences: Vec::new(),
+            system_prompt: "Answer in Polish.".into(),
+            temperature: Some(0.2),
+            max_tokens: Some(2048),
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+        };
+        let agent = serde_json::to_value(WitcherAgent::from(row)).unwrap();
+        assert_eq!(agent["system_prompt"], "Answer in Polish.");
+        assert_eq!(agent["max_tokens"], 2048);
+    }
+}
@@ -163,7 +163,7 @@ fn clamp_days(days: Option<i32>) -> i32 {
 }
 
 /// Determine pricing tier from model name.
-fn model_tier(model: &str) -> &'static str {
+pub(crate) fn model_tier(model: &str) -> &'static str {
     let m = model.to_lowercase();
     if m.contains("opus") {
         "opus"
@@ -177,7 +177,7 @@ fn model_tier(model: &str) -> &'static str {
 }
 
 /// Per-million-token pricing: (input, output).
-fn tier_pricing(tier: &str) -> (f64, f64) {
+pub(crate) fn tier_pricing(tier: &str) -> (f64, f64) {
     match tier {
         "opus" => (15.0, 75.0),
         "sonnet" => (3.0, 15.0),
@@ -211,7 +211,7 @@ pub async fn analytics_tokens(
         "#,
     )
     .bind(days)
-    .fetch_all(&state.db)
+    .fetch_all(crate::read_replica::pool(&state))
     .await
     .map_err(|e| {
         tracing::error!("analytics/tokens query failed: {e}");
@@ -263,7 +263,7 @@ pub async fn analytics_latency(
         "#,
     )
     .bind(days)
-    .fetch_all(&state.db)
+    .fetch_all(crate::read_replica::pool(&state))
     .await
     .map_err(|e| {
         tracing::error!("analytics/latency query failed: {e}");
@@ -312,7 +312,7 @@ pub async fn analytics_success_rate(
         "#,
     )
     .bind(days)
-    .fetch_all(&state.db)
+    .fetch_all(crate::read_replica::pool(&state))
     .await
     .map_err(|e| {
         tracing::error!("analytics/success-rate query failed: {e}");
@@ -369,7 +369,7 @@ pub async fn analytics_top_tools(
     )
     .bind(days)
     .bind(limit as i64)
-    .fetch_all(&state.db)
+    .fetch_all(crate::read_replica::pool(&state))
     .await
     .map_err(|e| {
         tracing::error!("analytics/top-tools query failed: {e}");
@@ -413,7 +413,7 @@ pub async fn analytics_cost(
         "#,
     )
     .bind(days)
-    .fetch_all(&state.db)
+    .fetch_all(crate::read_replica::pool(&state))
     .await
     .map_err(|e| {
         tracing::error!("analytics/cost query failed: {e}");
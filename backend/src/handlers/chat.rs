@@ -1,75 +1,48 @@
 //! Non-streaming Claude chat endpoints.
 //!
-//! - `claude_models` — list resolved Claude models per tier
+//! - `claude_models` — Claude models per tier, checked against the live model list
 //! - `claude_chat` — non-streaming chat completion
+//! - `agent_chat` — non-streaming chat as one agent (its prompt, model and parameters)
 
-use axum::Json;
-use axum::extract::State;
+use axum::extract::{Path, State};
+use axum::{Extension, Json};
 use axum::http::StatusCode;
 use serde_json::{Value, json};
 
 use crate::models::*;
+use crate::priority::DefaultPriority;
+use crate::providers::{Anthropic, Gemini, Provider, ProviderRequest};
 use crate::state::AppState;
 
-use super::{sanitize_json_strings, send_to_anthropic};
-
 // ═══════════════════════════════════════════════════════════════════════
 //  Claude models endpoint
 // ═══════════════════════════════════════════════════════════════════════
 
-/// GET /api/claude/models — dynamically resolved Claude models per tier
+/// GET /api/claude/models — Claude models per tier plus the other models the
+/// Anthropic credential can use (live `GET /v1/models` list)
 #[utoipa::path(get, path = "/api/claude/models", tag = "chat",
     responses((status = 200, description = "List Claude models per tier")))]
 pub async fn claude_models(State(state): State<AppState>) -> Json<Value> {
     let resolved = crate::model_registry::resolve_models(&state).await;
-
-    let models = vec![
+    let tier = |model: &Option<crate::model_registry::ModelInfo>, tier: &str, fallback_id: &str, fallback_name: &str| {
         ClaudeModelInfo {
-            id: resolved
-                .commander
-                .as_ref()
-                .map(|m| m.id.clone())
-                .unwrap_or_else(|| "claude-opus-4-6".to_string()),
-            name: resolved
-                .commander
-                .as_ref()
-                .and_then(|m| m.display_name.clone())
-                .unwrap_or_else(|| "Claude Opus".to_string()),
-            tier: "Commander".to_string(),
-            provider: "anthropic".to_string(),
-            available: true,
-        },
-        ClaudeModelInfo {
-            id: resolved
-                .coordinator
-                .as_ref()
-                .map(|m| m.id.clone())
-                .unwrap_or_else(|| "claude-sonnet-4-6".to_string()),
-            name: resolved
-                .coordinator
+            id: model.as_ref().map(|m| m.id.clone()).unwrap_or_else(|| fallback_id.to_string()),
+            name: model
                 .as_ref()
                 .and_then(|m| m.display_name.clone())
-                .unwrap_or_else(|| "Claude Sonnet".to_string()),
-            tier: "Coordinator".to_string(),
+                .unwrap_or_else(|| fallback_name.to_string()),
+            tier: tier.to_string(),
             provider: "anthropic".to_string(),
             available: true,
-        },
-        ClaudeModelInfo {
-            id: resolved
-                .executor
-                .as_ref()
-                .map(|m| m.id.clone())
-                .unwrap_or_else(|| "claude-haiku-4-5-20251001".to_string()),
-            name: resolved
-                .executor
-                .as_ref()
-                .and_then(|m| m.display_name.clone())
-                .unwrap_or_else(|| "Claude Haiku".to_string()),
-            tier: "Executor".to_string(),
-            provider: "anthropic".to_string(),
-            available: true,
-        },
+        }
+    };
+    let tiers = vec![
+        tier(&resolved.commander, "Commander", "claude-opus-4-6", "Claude Opus"),
+        tier(&resolved.coordinator, "Coordinator", "claude-sonnet-4-6", "Claude Sonnet"),
+        tier(&resolved.executor, "Executor", "claude-haiku-4-5-20251001", "Claude Haiku"),
     ];
+    let access = crate::model_registry::anthropic_access(&state).await;
+    let models = crate::model_registry::merge_live_models(tiers, &access);
 
     Json(serde_json::to_value(models).unwrap_or_else(|_| json!({"error": "serialization failed"})))
 }
@@ -84,93 +57,73 @@ pub async fn claude_models(State(state): State<AppState>) -> Json<Value> {
     responses((status = 200, description = "Chat completion response")))]
 pub async fn claude_chat(
     State(state): State<AppState>,
+    token_priority: Option<Extension<DefaultPriority>>,
     Json(req): Json<ChatRequest>,
 ) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let scope = super::prompt::resolve_request_scope(
+        &state,
+        &req,
+        token_priority.map(|Extension(DefaultPriority(p))| p),
+    )
+    .await?;
     let default_model = crate::model_registry::get_model_id(&state, "coordinator").await;
-    let model = req.model.unwrap_or(default_model);
-    let max_tokens = req.max_tokens.unwrap_or(4096);
-
-    let messages: Vec<Value> = req
-        .messages
-        .iter()
-        .map(|m| json!({ "role": m.role, "content": m.content }))
-        .collect();
-
-    let mut body = json!({
-        "model": model,
-        "max_tokens": max_tokens,
-        "messages": messages,
-    });
-
-    if let Some(temp) = req.temperature {
-        body["temperature"] = json!(temp);
-    }
-
-    sanitize_json_strings(&mut body);
-
-    let resp = send_to_anthropic(&state, &body, 120).await?;
-
-    if !resp.status().is_success() {
-        let status = resp.status();
-        let err_body: Value = resp.json().await.unwrap_or_default();
-        tracing::error!("anthropic chat: status={}, body={}", status, err_body);
-        return Err((
-            StatusCode::from_u16(status.as_u16()).unwrap_or(StatusCode::BAD_GATEWAY),
-            Json(json!({ "error": "AI provider request failed" })),
-        ));
-    }
+    let session_id = req.session_id.as_deref().and_then(|id| uuid::Uuid::parse_str(id).ok());
+    let agent_id = req.agent_id.clone();
+    let provider_req = ProviderRequest {
+        model: req.model.unwrap_or(default_model),
+        messages: req.messages,
+        temperature: req.temperature,
+        max_tokens: req.max_tokens.unwrap_or(4096),
+        ..Default::default()
+    };
+    let completion = crate::request_scope::run(scope, Anthropic.chat(&state, &provider_req)).await?;
 
-    let resp_body: Value = resp.json().await.map_err(|e| {
-        tracing::error!("anthropic chat: invalid JSON response: {}", e);
+    Ok(Json(serde_json::to_value(completion.into_attributed_response(&state, "chat", session_id, agent_id)).map_err(|_| {
         (
-            StatusCode::BAD_GATEWAY,
-            Json(json!({ "error": "AI provider returned invalid response" })),
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "serialization failed"})),
         )
-    })?;
-
-    let content = resp_body
-        .get("content")
-        .and_then(|c| c.as_array())
-        .map(|blocks| {
-            blocks
-                .iter()
-                .filter_map(|b| b.get("text").and_then(|t| t.as_str()))
-                .collect::<Vec<&str>>()
-                .join("")
-        })
-        .unwrap_or_default();
-
-    let response_model = resp_body
-        .get("model")
-        .and_then(|m| m.as_str())
-        .unwrap_or(&model)
-        .to_string();
+    })?))
+}
 
-    let usage = resp_body.get("usage").map(|u| UsageInfo {
-        prompt_tokens: u.get("input_tokens").and_then(|v| v.as_u64()).unwrap_or(0) as u32,
-        completion_tokens: u.get("output_tokens").and_then(|v| v.as_u64()).unwrap_or(0) as u32,
-        total_tokens: (u.get("input_tokens").and_then(|v| v.as_u64()).unwrap_or(0)
-            + u.get("output_tokens").and_then(|v| v.as_u64()).unwrap_or(0))
-            as u32,
-    });
+// ═══════════════════════════════════════════════════════════════════════
+//  Agent chat
+// ═══════════════════════════════════════════════════════════════════════
 
-    let chat_resp = ChatResponse {
-        id: resp_body
-            .get("id")
-            .and_then(|v| v.as_str())
-            .unwrap_or("unknown")
-            .to_string(),
-        message: ChatMessage {
-            role: "assistant".to_string(),
-            content,
-            model: Some(response_model.clone()),
-            timestamp: Some(chrono::Utc::now().to_rfc3339()),
-        },
-        model: response_model,
-        usage,
+/// POST /api/agents/{id}/chat — non-streaming chat generated as an agent:
+/// its system prompt, stop sequences, model, temperature and max_tokens
+/// apply unless the request sets them
+#[utoipa::path(post, path = "/api/agents/{id}/chat", tag = "agents",
+    params(("id" = String, Path, description = "Agent ID")),
+    request_body = ChatRequest,
+    responses(
+        (status = 200, description = "Chat completion generated as the agent"),
+        (status = 404, description = "Agent not found")
+    ))]
+pub async fn agent_chat(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    token_priority: Option<Extension<DefaultPriority>>,
+    Json(mut req): Json<ChatRequest>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    req.agent_id = Some(id);
+    let scope = super::prompt::resolve_request_scope(
+        &state,
+        &req,
+        token_priority.map(|Extension(DefaultPriority(p))| p),
+    )
+    .await?;
+    let ctx = crate::request_scope::run(scope.clone(), super::prompt::resolve_chat_context(&state, &req)).await;
+    let session_id = ctx.session_id;
+    let agent_id = req.agent_id.clone();
+    let provider_req = ProviderRequest::from_context(ctx, req.messages);
+    let completion = if provider_req.model.starts_with("gemini-") {
+        crate::request_scope::run(scope, Gemini.chat(&state, &provider_req)).await?
+    } else {
+        crate::request_scope::run(scope, Anthropic.chat(&state, &provider_req)).await?
     };
 
-    Ok(Json(serde_json::to_value(chat_resp).map_err(|_| {
+    Ok(Json(serde_json::to_value(completion.into_attributed_response(&state, "agent_chat", session_id, agent_id)).map_err(|_| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(json!({"error": "serialization failed"})),
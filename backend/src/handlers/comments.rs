@@ -0,0 +1,354 @@
+//! Comment threads on individual messages — review notes that are never sent
+//! to the model (they live outside `ch_messages`).
+//!
+//! Endpoints:
+//! - `GET    /api/sessions/{id}/messages/{mid}/comments`        — threads for a message
+//! - `POST   /api/sessions/{id}/messages/{mid}/comments`        — comment or reply (`parent_id`)
+//! - `PATCH  /api/sessions/{id}/messages/{mid}/comments/{cid}`  — edit body / (un)resolve
+//! - `DELETE /api/sessions/{id}/messages/{mid}/comments/{cid}`  — delete (with replies)
+//!
+//! Changes are pushed to everyone viewing the session as `comments_changed`.
+
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::{Extension, Json};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use utoipa::ToSchema;
+
+use crate::session_presence::SessionEvent;
+use crate::state::AppState;
+use crate::web_session::Session;
+
+const MAX_COMMENT_LENGTH: usize = 10_000;
+
+// ── Request / Response types ────────────────────────────────────────────────
+
+/// Request body for adding a comment.
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct CreateCommentRequest {
+    pub body: String,
+    /// Comment being replied to (must belong to the same message).
+    #[serde(default)]
+    pub parent_id: Option<uuid::Uuid>,
+    /// Display name for callers without a web session.
+    #[serde(default)]
+    pub author: Option<String>,
+}
+
+/// Request body for editing a comment.
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct UpdateCommentRequest {
+    #[serde(default)]
+    pub body: Option<String>,
+    #[serde(default)]
+    pub resolved: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct CommentRow {
+    pub id: uuid::Uuid,
+    pub parent_id: Option<uuid::Uuid>,
+    pub author: Option<String>,
+    pub body: String,
+    pub resolved: bool,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// A top-level comment with its replies (oldest first).
+#[derive(Debug, Serialize)]
+pub struct CommentThread {
+    #[serde(flatten)]
+    pub comment: CommentRow,
+    pub replies: Vec<CommentRow>,
+}
+
+const COMMENT_COLUMNS: &str = "id, parent_id, author, body, resolved, created_at, updated_at";
+
+type ApiError = (StatusCode, Json<Value>);
+
+fn api_error(status: StatusCode, msg: &str) -> ApiError {
+    (status, Json(json!({ "error": msg })))
+}
+
+fn db_error(e: sqlx::Error) -> ApiError {
+    tracing::error!("Comment query failed: {}", e);
+    api_error(StatusCode::INTERNAL_SERVER_ERROR, "Database error")
+}
+
+fn parse_ids(id: &str, mid: &str) -> Result<(uuid::Uuid, uuid::Uuid), ApiError> {
+    let session_id = id
+        .parse()
+        .map_err(|_| api_error(StatusCode::BAD_REQUEST, "Invalid session id"))?;
+    let message_id = mid
+        .parse()
+        .map_err(|_| api_error(StatusCode::BAD_REQUEST, "Invalid message id"))?;
+    Ok((session_id, message_id))
+}
+
+fn validate_body(body: &str) -> Result<String, ApiError> {
+    let body = body.trim();
+    if body.is_empty() || body.len() > MAX_COMMENT_LENGTH {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            "Comment must be 1-10000 characters",
+        ));
+    }
+    Ok(body.to_string())
+}
+
+/// Group a flat, chronologically ordered list into threads. Replies to
+/// replies are attached to the thread of their root comment.
+fn build_threads(rows: Vec<CommentRow>) -> Vec<CommentThread> {
+    let root_of = |mut id: uuid::Uuid, rows: &[CommentRow]| {
+        while let Some(parent) = rows.iter().find(|r| r.id == id).and_then(|r| r.parent_id) {
+            id = parent;
+        }
+        id
+    };
+    let roots: Vec<uuid::Uuid> = rows.iter().map(|r| root_of(r.id, &rows)).collect();
+
+    let mut threads: Vec<CommentThread> = Vec::new();
+    for (row, root) in rows.into_iter().zip(roots) {
+        if row.id == root {
+            threads.push(CommentThread {
+                comment: row,
+                replies: Vec::new(),
+            });
+        } else if let Some(thread) = threads.iter_mut().find(|t| t.comment.id == root) {
+            thread.replies.push(row);
+        }
+    }
+    threads
+}
+
+async fn message_in_session(
+    state: &AppState,
+    session_id: uuid::Uuid,
+    message_id: uuid::Uuid,
+) -> Result<(), ApiError> {
+    sqlx::query("SELECT 1 FROM ch_messages WHERE id = $1 AND session_id = $2")
+        .bind(message_id)
+        .bind(session_id)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(db_error)?
+        .map(|_| ())
+        .ok_or_else(|| api_error(StatusCode::NOT_FOUND, "Message not found in session"))
+}
+
+fn notify(state: &AppState, session_id: uuid::Uuid, message_id: uuid::Uuid) {
+    state.presence.publish(
+        session_id,
+        SessionEvent::CommentsChanged { message_id },
+    );
+}
+
+// ── GET /api/sessions/{id}/messages/{mid}/comments ──────────────────────────
+
+#[utoipa::path(get, path = "/api/sessions/{id}/messages/{mid}/comments", tag = "sessions",
+    params(
+        ("id" = String, Path, description = "Session UUID"),
+        ("mid" = String, Path, description = "Message UUID")
+    ),
+    responses((status = 200, description = "Comment threads for the message")))]
+pub async fn list_comments(
+    State(state): State<AppState>,
+    Path((id, mid)): Path<(String, String)>,
+) -> Result<Json<Value>, ApiError> {
+    let (session_id, message_id) = parse_ids(&id, &mid)?;
+
+    let rows = sqlx::query_as::<_, CommentRow>(&format!(
+        "SELECT {} FROM ch_message_comments \
+         WHERE session_id = $1 AND message_id = $2 ORDER BY created_at",
+        COMMENT_COLUMNS
+    ))
+    .bind(session_id)
+    .bind(message_id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(db_error)?;
+
+    let total = rows.len();
+    let unresolved = rows.iter().filter(|r| r.parent_id.is_none() && !r.resolved).count();
+    Ok(Json(json!({
+        "message_id": message_id,
+        "total": total,
+        "unresolved_threads": unresolved,
+        "threads": build_threads(rows),
+    })))
+}
+
+// ── POST /api/sessions/{id}/messages/{mid}/comments ─────────────────────────
+
+#[utoipa::path(post, path = "/api/sessions/{id}/messages/{mid}/comments", tag = "sessions",
+    params(
+        ("id" = String, Path, description = "Session UUID"),
+        ("mid" = String, Path, description = "Message UUID")
+    ),
+    request_body = CreateCommentRequest,
+    responses(
+        (status = 201, description = "Comment added"),
+        (status = 404, description = "Message or parent comment not found")
+    ))]
+pub async fn create_comment(
+    State(state): State<AppState>,
+    Path((id, mid)): Path<(String, String)>,
+    session: Option<Extension<Session>>,
+    Json(req): Json<CreateCommentRequest>,
+) -> Result<(StatusCode, Json<Value>), ApiError> {
+    let (session_id, message_id) = parse_ids(&id, &mid)?;
+    let body = validate_body(&req.body)?;
+    // A signed-in web session identifies the author; the body can't override it.
+    let author = match session {
+        Some(Extension(s)) => Some(s.subject),
+        None => req
+            .author
+            .map(|a| a.trim().chars().take(60).collect::<String>())
+            .filter(|a| !a.is_empty()),
+    };
+
+    message_in_session(&state, session_id, message_id).await?;
+    if let Some(parent) = req.parent_id {
+        let found = sqlx::query("SELECT 1 FROM ch_message_comments WHERE id = $1 AND message_id = $2")
+            .bind(parent)
+            .bind(message_id)
+            .fetch_optional(&state.db)
+            .await
+            .map_err(db_error)?;
+        if found.is_none() {
+            return Err(api_error(StatusCode::NOT_FOUND, "Parent comment not found"));
+        }
+    }
+
+    let row = sqlx::query_as::<_, CommentRow>(&format!(
+        "INSERT INTO ch_message_comments (session_id, message_id, parent_id, author, body) \
+         VALUES ($1, $2, $3, $4, $5) RETURNING {}",
+        COMMENT_COLUMNS
+    ))
+    .bind(session_id)
+    .bind(message_id)
+    .bind(req.parent_id)
+    .bind(&author)
+    .bind(&body)
+    .fetch_one(&state.db)
+    .await
+    .map_err(db_error)?;
+
+    notify(&state, session_id, message_id);
+    Ok((StatusCode::CREATED, Json(json!(row))))
+}
+
+// ── PATCH /api/sessions/{id}/messages/{mid}/comments/{cid} ──────────────────
+
+#[utoipa::path(patch, path = "/api/sessions/{id}/messages/{mid}/comments/{cid}", tag = "sessions",
+    params(
+        ("id" = String, Path, description = "Session UUID"),
+        ("mid" = String, Path, description = "Message UUID"),
+        ("cid" = String, Path, description = "Comment UUID")
+    ),
+    request_body = UpdateCommentRequest,
+    responses((status = 200, description = "Comment updated")))]
+pub async fn update_comment(
+    State(state): State<AppState>,
+    Path((id, mid, cid)): Path<(String, String, String)>,
+    Json(req): Json<UpdateCommentRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let (session_id, message_id) = parse_ids(&id, &mid)?;
+    let comment_id: uuid::Uuid = cid
+        .parse()
+        .map_err(|_| api_error(StatusCode::BAD_REQUEST, "Invalid comment id"))?;
+    let body = req.body.as_deref().map(validate_body).transpose()?;
+    if body.is_none() && req.resolved.is_none() {
+        return Err(api_error(StatusCode::BAD_REQUEST, "Nothing to update"));
+    }
+
+    let row = sqlx::query_as::<_, CommentRow>(&format!(
+        "UPDATE ch_message_comments SET \
+             body = COALESCE($4, body), resolved = COALESCE($5, resolved), updated_at = NOW() \
+         WHERE id = $1 AND session_id = $2 AND message_id = $3 RETURNING {}",
+        COMMENT_COLUMNS
+    ))
+    .bind(comment_id)
+    .bind(session_id)
+    .bind(message_id)
+    .bind(&body)
+    .bind(req.resolved)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(db_error)?
+    .ok_or_else(|| api_error(StatusCode::NOT_FOUND, "Comment not found"))?;
+
+    notify(&state, session_id, message_id);
+    Ok(Json(json!(row)))
+}
+
+// ── DELETE /api/sessions/{id}/messages/{mid}/comments/{cid} ─────────────────
+
+#[utoipa::path(delete, path = "/api/sessions/{id}/messages/{mid}/comments/{cid}", tag = "sessions",
+    params(
+        ("id" = String, Path, description = "Session UUID"),
+        ("mid" = String, Path, description = "Message UUID"),
+        ("cid" = String, Path, description = "Comment UUID")
+    ),
+    responses((status = 204, description = "Comment and its replies deleted")))]
+pub async fn delete_comment(
+    State(state): State<AppState>,
+    Path((id, mid, cid)): Path<(String, String, String)>,
+) -> Result<StatusCode, ApiError> {
+    let (session_id, message_id) = parse_ids(&id, &mid)?;
+    let comment_id: uuid::Uuid = cid
+        .parse()
+        .map_err(|_| api_error(StatusCode::BAD_REQUEST, "Invalid comment id"))?;
+
+    // Replies go with their parent (ON DELETE CASCADE).
+    let result = sqlx::query(
+        "DELETE FROM ch_message_comments WHERE id = $1 AND session_id = $2 AND message_id = $3",
+    )
+    .bind(comment_id)
+    .bind(session_id)
+    .bind(message_id)
+    .execute(&state.db)
+    .await
+    .map_err(db_error)?;
+    if result.rows_affected() == 0 {
+        return Err(api_error(StatusCode::NOT_FOUND, "Comment not found"));
+    }
+
+    notify(&state, session_id, message_id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(id: u128, parent: Option<u128>) -> CommentRow {
+        let now = chrono::Utc::now();
+        CommentRow {
+            id: uuid::Uuid::from_u128(id),
+            parent_id: parent.map(uuid::Uuid::from_u128),
+            author: None,
+            body: format!("c{id}"),
+            resolved: false,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    #[test]
+    fn replies_are_grouped_under_their_root() {
+        let threads = build_threads(vec![
+            row(1, None),
+            row(2, None),
+            row(3, Some(1)),
+            row(4, Some(3)),
+            row(5, Some(2)),
+        ]);
+        assert_eq!(threads.len(), 2);
+        let ids = |t: &CommentThread| t.replies.iter().map(|r| r.id.as_u128()).collect::<Vec<_>>();
+        assert_eq!(ids(&threads[0]), vec![3, 4]);
+        assert_eq!(ids(&threads[1]), vec![5]);
+    }
+}
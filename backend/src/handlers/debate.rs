@@ -0,0 +1,459 @@
+//! Turn-based agent debate mode.
+//!
+//! - `POST /api/debate` — two agents argue opposing positions on a topic for
+//!   N rounds, then a judge agent delivers a verdict. Every turn is streamed
+//!   as an NDJSON line and persisted to a new session (`ch_sessions` +
+//!   `ch_messages`, with the speaking agent recorded in `ch_messages.agent`).
+//!   With `parent_session_id` the debate session is stored as a sub-session of
+//!   the conversation that started it (see `sub_sessions`).
+
+use axum::Json;
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::Response;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use utoipa::ToSchema;
+
+use jaskier_core::handlers::anthropic_streaming::build_ndjson_response;
+
+use crate::models::WitcherAgent;
+use crate::state::AppState;
+
+use super::{sanitize_json_strings, send_to_anthropic};
+
+const DEFAULT_DEBATE_ROUNDS: u32 = 3;
+const MAX_DEBATE_ROUNDS: u32 = 6;
+const DEBATE_TURN_MAX_TOKENS: u32 = 1024;
+const DEBATE_VERDICT_MAX_TOKENS: u32 = 2048;
+
+// ── Request types ───────────────────────────────────────────────────────────
+
+/// Request body for `POST /api/debate`.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct DebateRequest {
+    /// The question or design decision under debate.
+    pub topic: String,
+    /// Agent ID (or name) arguing the first position.
+    pub agent_a: String,
+    /// Agent ID (or name) arguing the opposing position.
+    pub agent_b: String,
+    /// Agent ID (or name) delivering the verdict. Defaults to the first
+    /// Commander-tier agent that is not already debating.
+    pub judge: Option<String>,
+    /// Position for `agent_a` (default: "in favour").
+    pub position_a: Option<String>,
+    /// Position for `agent_b` (default: "against").
+    pub position_b: Option<String>,
+    /// Number of rounds (default 3, max 6). One round = one turn per debater.
+    pub rounds: Option<u32>,
+    /// Store the debate as a child of this session.
+    #[serde(default)]
+    pub parent_session_id: Option<String>,
+    /// Step of the parent that started the debate (default: "debate").
+    #[serde(default)]
+    pub step_id: Option<String>,
+}
+
+/// One side of the debate, resolved against the agent roster.
+struct Debater {
+    agent: WitcherAgent,
+    position: String,
+}
+
+fn find_agent(agents: &[WitcherAgent], key: &str) -> Option<WitcherAgent> {
+    agents
+        .iter()
+        .find(|a| a.id == key || a.name.eq_ignore_ascii_case(key))
+        .cloned()
+}
+
+/// Render the exchange so far as a plain-text transcript for the next prompt.
+fn render_transcript(turns: &[(String, String, String)]) -> String {
+    turns
+        .iter()
+        .map(|(name, position, content)| format!("[{} — {}]\n{}", name, position, content))
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+fn debater_system_prompt(d: &Debater, opponent: &Debater, topic: &str) -> String {
+    format!(
+        "You are {name}, {role}. {description}\n\n\
+         You are taking part in a structured debate on the topic:\n\"{topic}\"\n\n\
+         Your position: {position}. Your opponent, {opp}, argues: {opp_position}.\n\
+         Argue your position rigorously. Address your opponent's latest points directly, \
+         concede minor points where honest, and keep each turn under 300 words. \
+         Do not break character or summarise the whole debate.",
+        name = d.agent.name,
+        role = d.agent.role,
+        description = d.agent.description,
+        topic = topic,
+        position = d.position,
+        opp = opponent.agent.name,
+        opp_position = opponent.position,
+    )
+}
+
+fn judge_system_prompt(judge: &WitcherAgent, topic: &str) -> String {
+    format!(
+        "You are {name}, {role}, acting as the impartial judge of a debate on:\n\"{topic}\"\n\n\
+         Weigh the arguments on their merits, not on rhetoric. Structure your answer as:\n\
+         1. Strongest points from each side\n\
+         2. Weaknesses or unaddressed objections\n\
+         3. Verdict — which position is better supported and under what conditions the \
+         other would win\n\
+         4. A concrete recommendation",
+        name = judge.name,
+        role = judge.role,
+        topic = topic,
+    )
+}
+
+/// Single non-streaming completion — returns the concatenated text blocks.
+async fn complete_turn(
+    state: &AppState,
+    agent: &WitcherAgent,
+    session_id: uuid::Uuid,
+    system: &str,
+    prompt: &str,
+    max_tokens: u32,
+) -> Result<String, String> {
+    let model = agent.model.as_str();
+    let mut body = json!({
+        "model": model,
+        "max_tokens": max_tokens,
+        "system": system,
+        "messages": [{ "role": "user", "content": prompt }],
+    });
+    sanitize_json_strings(&mut body);
+
+    let scope = std::sync::Arc::new(crate::request_scope::RequestScope {
+        stop_sequences: agent.stop_sequences.clone(),
+        busy: Some(std::sync::Arc::new(crate::agent_status::busy(&agent.id))),
+        ..Default::default()
+    });
+    let resp = crate::request_scope::run(scope, send_to_anthropic(state, &body, crate::endpoint_policies::Group::Chat))
+        .await
+        .map_err(|(_, Json(err))| {
+            err.get("error")
+                .and_then(|e| e.as_str())
+                .unwrap_or("AI provider request failed")
+                .to_string()
+        })?;
+
+    if !resp.status().is_success() {
+        let status = resp.status();
+        let err_body: Value = resp.json().await.unwrap_or_default();
+        tracing::error!("debate turn: status={}, body={}", status, err_body);
+        return Err(format!("AI provider returned {}", status.as_u16()));
+    }
+
+    let resp_body: Value = resp
+        .json()
+        .await
+        .map_err(|_| "AI provider returned invalid response".to_string())?;
+
+    if let Some(u) = resp_body.get("usage") {
+        crate::usage::record_usage(
+            &state.db,
+            crate::usage::UsageEvent {
+                model: model.to_string(),
+                input_tokens: u.get("input_tokens").and_then(|v| v.as_u64()).unwrap_or(0) as u32,
+                output_tokens: u.get("output_tokens").and_then(|v| v.as_u64()).unwrap_or(0) as u32,
+                source: "debate",
+                session_id: Some(session_id),
+                agent_id: Some(agent.id.clone()),
+                ..Default::default()
+            },
+        );
+    }
+
+    Ok(resp_body
+        .get("content")
+        .and_then(|c| c.as_array())
+        .map(|blocks| {
+            blocks
+                .iter()
+                .filter_map(|b| b.get("text").and_then(|t| t.as_str()))
+                .collect::<Vec<&str>>()
+                .join("")
+        })
+        .unwrap_or_default())
+}
+
+async fn store_debate_message(
+    db: &sqlx::PgPool,
+    session_id: uuid::Uuid,
+    role: &str,
+    content: &str,
+    model: Option<&str>,
+    agent: Option<&str>,
+) {
+    if let Err(e) = sqlx::query(
+        "INSERT INTO ch_messages (session_id, role, content, model, agent) VALUES ($1, $2, $3, $4, $5)",
+    )
+    .bind(session_id)
+    .bind(role)
+    .bind(content)
+    .bind(model)
+    .bind(agent)
+    .execute(db)
+    .await
+    {
+        tracing::error!("Failed to store debate message: {}", e);
+    }
+}
+
+fn ndjson_line(value: Value) -> Result<axum::body::Bytes, std::io::Error> {
+    Ok(axum::body::Bytes::from(format!(
+        "{}\n",
+        serde_json::to_string(&value).unwrap_or_default()
+    )))
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  POST /api/debate
+// ═══════════════════════════════════════════════════════════════════════
+
+/// `POST /api/debate` — run a turn-based debate between two agents with a judge.
+///
+/// Streams NDJSON lines:
+/// - `{"type":"start","session_id",...}` — participants and round count
+/// - `{"type":"turn","round","agent_id","agent","position","content"}` — one per debater turn
+/// - `{"type":"verdict","agent_id","agent","content"}` — the judge's ruling
+/// - `{"type":"error","error"}` — upstream failure (stream ends)
+/// - `{"done":true,"session_id"}` — final line
+#[utoipa::path(post, path = "/api/debate", tag = "chat",
+    request_body = DebateRequest,
+    responses(
+        (status = 200, description = "Streaming NDJSON debate transcript"),
+        (status = 400, description = "Invalid debate configuration"),
+        (status = 404, description = "Unknown agent")
+    ))]
+pub async fn start_debate(
+    State(state): State<AppState>,
+    Json(req): Json<DebateRequest>,
+) -> Result<Response, (StatusCode, Json<Value>)> {
+    let topic = req.topic.trim().to_string();
+    if topic.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "topic must not be empty" })),
+        ));
+    }
+    if topic.len() > super::MAX_MESSAGE_LENGTH {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "topic too long" })),
+        ));
+    }
+    let rounds = req.rounds.unwrap_or(DEFAULT_DEBATE_ROUNDS);
+    if rounds == 0 || rounds > MAX_DEBATE_ROUNDS {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": format!("rounds must be between 1 and {}", MAX_DEBATE_ROUNDS) })),
+        ));
+    }
+    let parent_id = match req.parent_session_id.as_deref() {
+        Some(id) => Some(id.parse::<uuid::Uuid>().map_err(|_| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": "parent_session_id must be a session UUID" })),
+            )
+        })?),
+        None => None,
+    };
+    let step_id = super::sub_sessions::normalize_step_id(req.step_id.as_deref())
+        .map_err(|e| (StatusCode::BAD_REQUEST, Json(json!({ "error": e }))))?
+        .unwrap_or_else(|| "debate".to_string());
+
+    let (a, b, judge) = {
+        let agents = state.agents.read().await;
+        let not_found = |key: &str| {
+            (
+                StatusCode::NOT_FOUND,
+                Json(json!({ "error": format!("Agent not found: {}", key) })),
+            )
+        };
+        let a = find_agent(&agents, &req.agent_a).ok_or_else(|| not_found(&req.agent_a))?;
+        let b = find_agent(&agents, &req.agent_b).ok_or_else(|| not_found(&req.agent_b))?;
+        let judge = match req.judge.as_deref() {
+            Some(key) => find_agent(&agents, key).ok_or_else(|| not_found(key))?,
+            None => {
+                // Paused and disabled agents are not picked as judge.
+                let candidates = || {
+                    agents.iter().filter(|j| {
+                        j.id != a.id && j.id != b.id && crate::agent_status::delegation_refusal(j).is_none()
+                    })
+                };
+                candidates()
+                    .find(|j| j.tier == "Commander")
+                    .or_else(|| candidates().next())
+                    .cloned()
+                    .ok_or_else(|| not_found("judge"))?
+            }
+        };
+        (a, b, judge)
+    };
+    for agent in [&a, &b, &judge] {
+        crate::agent_status::ensure_usable(agent)?;
+    }
+
+    if a.id == b.id {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "agent_a and agent_b must be different agents" })),
+        ));
+    }
+
+    let side_a = Debater {
+        agent: a,
+        position: req.position_a.unwrap_or_else(|| "in favour".to_string()),
+    };
+    let side_b = Debater {
+        agent: b,
+        position: req.position_b.unwrap_or_else(|| "against".to_string()),
+    };
+
+    let title: String = format!("Debate: {}", topic).chars().take(120).collect();
+    let created = match parent_id {
+        Some(parent) => {
+            super::sub_sessions::insert_child_session(&state.db, parent, Some(&step_id), &title).await
+        }
+        None => sqlx::query_scalar("INSERT INTO ch_sessions (title) VALUES ($1) RETURNING id")
+            .bind(&title)
+            .fetch_one(&state.db)
+            .await
+            .map(Some),
+    };
+    let session_id: uuid::Uuid = created
+        .map_err(|e| {
+            tracing::error!("Failed to create debate session: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Failed to create debate session" })),
+            )
+        })?
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                Json(json!({ "error": "Parent session not found" })),
+            )
+        })?;
+
+    store_debate_message(&state.db, session_id, "user", &topic, None, None).await;
+
+    tracing::info!(
+        %session_id,
+        agent_a = %side_a.agent.name,
+        agent_b = %side_b.agent.name,
+        judge = %judge.name,
+        rounds,
+        "debate started"
+    );
+
+    let ndjson_stream = async_stream::stream! {
+        yield ndjson_line(json!({
+            "type": "start",
+            "session_id": session_id,
+            "parent_session_id": parent_id,
+            "topic": &topic,
+            "rounds": rounds,
+            "agent_a": { "id": &side_a.agent.id, "name": &side_a.agent.name, "position": &side_a.position },
+            "agent_b": { "id": &side_b.agent.id, "name": &side_b.agent.name, "position": &side_b.position },
+            "judge": { "id": &judge.id, "name": &judge.name },
+            "done": false,
+        }));
+
+        let mut turns: Vec<(String, String, String)> = Vec::new();
+        let mut failed = false;
+
+        'rounds: for round in 1..=rounds {
+            for (speaker, opponent) in [(&side_a, &side_b), (&side_b, &side_a)] {
+                let system = debater_system_prompt(speaker, opponent, &topic);
+                let prompt = if turns.is_empty() {
+                    format!("Open the debate on: {}", topic)
+                } else {
+                    format!(
+                        "Debate transcript so far:\n\n{}\n\nRound {} of {} — your turn.",
+                        render_transcript(&turns),
+                        round,
+                        rounds
+                    )
+                };
+
+                match complete_turn(&state, &speaker.agent, session_id, &system, &prompt, DEBATE_TURN_MAX_TOKENS).await {
+                    Ok(content) => {
+                        store_debate_message(
+                            &state.db,
+                            session_id,
+                            "assistant",
+                            &content,
+                            Some(&speaker.agent.model),
+                            Some(&speaker.agent.name),
+                        )
+                        .await;
+                        yield ndjson_line(json!({
+                            "type": "turn",
+                            "round": round,
+                            "agent_id": &speaker.agent.id,
+                            "agent": &speaker.agent.name,
+                            "position": &speaker.position,
+                            "content": &content,
+                            "done": false,
+                        }));
+                        turns.push((speaker.agent.name.clone(), speaker.position.clone(), content));
+                    }
+                    Err(e) => {
+                        tracing::warn!(%session_id, agent = %speaker.agent.name, "debate turn failed: {}", e);
+                        yield ndjson_line(json!({ "type": "error", "error": e, "done": false }));
+                        failed = true;
+                        break 'rounds;
+                    }
+                }
+            }
+        }
+
+        if !failed {
+            let prompt = format!(
+                "Full debate transcript:\n\n{}\n\nDeliver your verdict.",
+                render_transcript(&turns)
+            );
+            match complete_turn(&state, &judge, session_id, &judge_system_prompt(&judge, &topic), &prompt, DEBATE_VERDICT_MAX_TOKENS).await {
+                Ok(content) => {
+                    store_debate_message(
+                        &state.db,
+                        session_id,
+                        "assistant",
+                        &content,
+                        Some(&judge.model),
+                        Some(&judge.name),
+                    )
+                    .await;
+                    yield ndjson_line(json!({
+                        "type": "verdict",
+                        "agent_id": &judge.id,
+                        "agent": &judge.name,
+                        "content": &content,
+                        "done": false,
+                    }));
+                }
+                Err(e) => {
+                    tracing::warn!(%session_id, judge = %judge.name, "debate verdict failed: {}", e);
+                    yield ndjson_line(json!({ "type": "error", "error": e, "done": false }));
+                }
+            }
+        }
+
+        let _ = sqlx::query("UPDATE ch_sessions SET updated_at = NOW() WHERE id = $1")
+            .bind(session_id)
+            .execute(&state.db)
+            .await;
+
+        yield ndjson_line(json!({ "done": true, "session_id": session_id }));
+    };
+
+    Ok(build_ndjson_response(Body::from_stream(ndjson_stream)))
+}
@@ -0,0 +1,865 @@
+//! Long-document summarization and question answering over uploaded files.
+//!
+//! A "document" is an upload (`POST /api/uploads`) holding a PDF or text file.
+//!
+//! - `POST /api/documents/{id}/summarize` — starts a map-reduce summary job and
+//!   returns 202 with its id. The text is split into page-aligned chunks, each
+//!   chunk is summarized in parallel by the executor model (Haiku), and the
+//!   chunk summaries are synthesized into one summary by the coordinator model
+//!   (Sonnet). When the chunk summaries are themselves too long for one call
+//!   they are condensed in batches first, so a 300-page PDF never has to fit
+//!   in a single context window.
+//! - `GET /api/documents/jobs/{id}` — job status and progress
+//!   (`phase`, `chunks_done` / `chunks_total`), and the summary once done.
+//!
+//! - `POST /api/documents/{id}/ask` — answers a question from the passages most
+//!   relevant to it, with numbered citations. The document is split into small
+//!   passages and embedded (Gemini embeddings) on the first question; the
+//!   vectors are kept in `ch_document_chunks`, so later questions only embed the
+//!   question. Without a Google credential retrieval falls back to keyword
+//!   overlap.
+//!
+//! Jobs live in `ch_document_jobs`; a job still running when the server stops
+//! is marked failed on the next start (`fail_interrupted_jobs`).
+
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use futures_util::StreamExt;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use utoipa::ToSchema;
+
+use crate::state::AppState;
+
+use super::{sanitize_json_strings, send_to_anthropic};
+
+/// Largest upload read as a document.
+const MAX_DOCUMENT_BYTES: usize = 64 * 1024 * 1024;
+/// Target chunk size (~6k tokens) — small enough for fast parallel Haiku calls.
+pub(crate) const CHUNK_CHARS: usize = 24_000;
+/// Chunk summaries summarized at once; more are condensed in batches first.
+const MAX_REDUCE_INPUT_CHARS: usize = 120_000;
+const MAP_CONCURRENCY: usize = 4;
+const MAP_MAX_TOKENS: u32 = 1024;
+const REDUCE_MAX_TOKENS: u32 = 4096;
+const MAX_INSTRUCTIONS_CHARS: usize = 2_000;
+
+type ApiError = (StatusCode, Json<Value>);
+
+fn api_error(status: StatusCode, msg: impl Into<String>) -> ApiError {
+    (status, Json(json!({ "error": msg.into() })))
+}
+
+fn db_error(e: sqlx::Error) -> ApiError {
+    tracing::error!("documents: database error: {}", e);
+    api_error(StatusCode::INTERNAL_SERVER_ERROR, "Database error")
+}
+
+// ── Document text ───────────────────────────────────────────────────────────
+
+/// Extracted text of an upload, one entry per page (a single entry for
+/// unpaginated text files).
+pub(crate) struct DocumentText {
+    pub filename: String,
+    pub pages: Vec<String>,
+}
+
+fn is_text_document(mime: &str, filename: &str) -> bool {
+    let ext = filename.rsplit_once('.').map(|(_, e)| e.to_ascii_lowercase());
+    mime.starts_with("text/")
+        || matches!(mime, "application/json" | "application/xml" | "application/x-yaml")
+        || matches!(
+            ext.as_deref(),
+            Some("txt" | "md" | "markdown" | "csv" | "json" | "xml" | "yaml" | "yml" | "html" | "log" | "rst")
+        )
+}
+
+/// Load an upload and extract its text (PDF via `pdf-extract`, text files as UTF-8).
+pub(crate) async fn load_document(state: &AppState, id: uuid::Uuid) -> Result<DocumentText, ApiError> {
+    let filename: String = sqlx::query_scalar("SELECT filename FROM ch_uploads WHERE id = $1")
+        .bind(id)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| api_error(StatusCode::NOT_FOUND, "Document not found"))?;
+    let (mime, bytes) = super::uploads::read_upload(state, id, MAX_DOCUMENT_BYTES).await?;
+
+    let is_pdf = mime == "application/pdf" || filename.to_ascii_lowercase().ends_with(".pdf");
+    let pages: Vec<String> = if is_pdf {
+        let text = tokio::task::spawn_blocking(move || pdf_extract::extract_text_from_mem(&bytes))
+            .await
+            .map_err(|_| api_error(StatusCode::INTERNAL_SERVER_ERROR, "PDF extraction failed"))?
+            .map_err(|e| api_error(StatusCode::UNPROCESSABLE_ENTITY, format!("PDF extraction failed: {}", e)))?;
+        text.split('\x0c').map(|p| p.trim().to_string()).collect()
+    } else if is_text_document(&mime, &filename) {
+        vec![String::from_utf8_lossy(&bytes).into_owned()]
+    } else {
+        return Err(api_error(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            format!("Cannot read text from {} ({})", filename, mime),
+        ));
+    };
+
+    if pages.iter().all(|p| p.trim().is_empty()) {
+        return Err(api_error(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Document has no extractable text (scanned PDF? run it through /api/ocr first)",
+        ));
+    }
+    Ok(DocumentText { filename, pages })
+}
+
+/// A slice of a document sent to the model in one call.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Chunk {
+    pub index: usize,
+    /// 1-based page range, when the document is paginated.
+    pub pages: Option<(usize, usize)>,
+    pub text: String,
+}
+
+impl Chunk {
+    pub fn label(&self) -> String {
+        match self.pages {
+            Some((a, b)) if a == b => format!("page {}", a),
+            Some((a, b)) => format!("pages {}-{}", a, b),
+            None => format!("part {}", self.index + 1),
+        }
+    }
+}
+
+/// Split text longer than `max_chars` at paragraph, line or word boundaries.
+fn split_long(text: &str, max_chars: usize) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut rest = text.trim();
+    while rest.chars().count() > max_chars {
+        let limit = rest.char_indices().nth(max_chars).map_or(rest.len(), |(i, _)| i);
+        let window = &rest[..limit];
+        let cut = ["\n\n", "\n", ". ", " "]
+            .iter()
+            .filter_map(|sep| window.rfind(sep).map(|i| i + sep.len()))
+            .find(|&i| i > limit / 2)
+            .unwrap_or(limit);
+        parts.push(rest[..cut].trim().to_string());
+        rest = rest[cut..].trim_start();
+    }
+    if !rest.is_empty() {
+        parts.push(rest.to_string());
+    }
+    parts
+}
+
+/// Group pages into chunks of at most `max_chars`, keeping page boundaries
+/// where possible; a page longer than that is split on its own.
+pub(crate) fn chunk_pages(pages: &[String], max_chars: usize) -> Vec<Chunk> {
+    let paginated = pages.len() > 1;
+    let mut chunks: Vec<Chunk> = Vec::new();
+    let mut current = String::new();
+    let mut first_page = 0;
+    let mut last_page = 0;
+
+    let flush = |text: &mut String, first: usize, last: usize, chunks: &mut Vec<Chunk>| {
+        if !text.trim().is_empty() {
+            chunks.push(Chunk {
+                index: chunks.len(),
+                pages: paginated.then_some((first, last)),
+                text: std::mem::take(text).trim().to_string(),
+            });
+        }
+    };
+
+    for (i, page) in pages.iter().enumerate() {
+        let page_no = i + 1;
+        let page = page.trim();
+        if page.is_empty() {
+            continue;
+        }
+        let len = page.chars().count();
+        if len > max_chars {
+            flush(&mut current, first_page, last_page, &mut chunks);
+            for mut part in split_long(page, max_chars) {
+                flush(&mut part, page_no, page_no, &mut chunks);
+            }
+            continue;
+        }
+        if !current.is_empty() && current.chars().count() + len + 2 > max_chars {
+            flush(&mut current, first_page, last_page, &mut chunks);
+        }
+        if current.is_empty() {
+            first_page = page_no;
+        } else {
+            current.push_str("\n\n");
+        }
+        current.push_str(page);
+        last_page = page_no;
+    }
+    flush(&mut current, first_page, last_page, &mut chunks);
+    chunks
+}
+
+/// One non-streaming completion; usage is recorded under `source`.
+pub(crate) async fn complete(
+    state: &AppState,
+    model: &str,
+    system: &str,
+    prompt: &str,
+    max_tokens: u32,
+    source: &'static str,
+) -> Result<String, String> {
+    let mut body = json!({
+        "model": model,
+        "max_tokens": max_tokens,
+        "system": system,
+        "messages": [{ "role": "user", "content": prompt }],
+    });
+    sanitize_json_strings(&mut body);
+
+    let resp = send_to_anthropic(state, &body, crate::endpoint_policies::Group::Batch).await.map_err(|(_, Json(err))| {
+        err.get("error")
+            .and_then(|e| e.as_str())
+            .unwrap_or("AI provider request failed")
+            .to_string()
+    })?;
+    if !resp.status().is_success() {
+        let status = resp.status();
+        let err_body: Value = resp.json().await.unwrap_or_default();
+        tracing::error!("documents: status={}, body={}", status, err_body);
+        return Err(format!("AI provider returned {}", status.as_u16()));
+    }
+    let resp_body: Value = resp
+        .json()
+        .await
+        .map_err(|_| "AI provider returned invalid response".to_string())?;
+
+    if let Some(u) = resp_body.get("usage") {
+        crate::usage::record_usage(
+            &state.db,
+            crate::usage::UsageEvent {
+                model: model.to_string(),
+                input_tokens: u.get("input_tokens").and_then(|v| v.as_u64()).unwrap_or(0) as u32,
+                output_tokens: u.get("output_tokens").and_then(|v| v.as_u64()).unwrap_or(0) as u32,
+                source,
+                ..Default::default()
+            },
+        );
+    }
+
+    Ok(resp_body
+        .get("content")
+        .and_then(|c| c.as_array())
+        .map(|blocks| {
+            blocks
+                .iter()
+                .filter_map(|b| b.get("text").and_then(|t| t.as_str()))
+                .collect::<Vec<&str>>()
+                .join("")
+        })
+        .unwrap_or_default())
+}
+
+// ── Summary jobs ────────────────────────────────────────────────────────────
+
+/// Request body for `POST /api/documents/{id}/summarize` (optional).
+#[derive(Debug, Clone, Default, Deserialize, ToSchema)]
+pub struct SummarizeDocumentRequest {
+    /// Extra guidance for the final summary ("focus on the risks", "in Polish").
+    pub instructions: Option<String>,
+}
+
+const MAP_SYSTEM: &str = "You summarize one section of a longer document. Keep every key fact, figure, \
+     name, decision and conclusion in the section; drop filler. Write dense prose or bullets, no preamble.";
+const CONDENSE_SYSTEM: &str = "You merge consecutive section summaries of one document into a single, \
+     shorter summary of that stretch. Keep the key facts, figures and conclusions and their order.";
+const REDUCE_SYSTEM: &str = "You write the final summary of a long document from summaries of its sections, \
+     given in document order. Open with a short overview, then cover the main points in order. \
+     Refer to page numbers where they help. Do not invent anything not in the summaries.";
+
+async fn set_progress(db: &sqlx::PgPool, job_id: uuid::Uuid, phase: &str, chunks_done: usize) {
+    if let Err(e) = sqlx::query(
+        "UPDATE ch_document_jobs SET phase = $2, chunks_done = $3, updated_at = NOW() WHERE id = $1",
+    )
+    .bind(job_id)
+    .bind(phase)
+    .bind(chunks_done as i32)
+    .execute(db)
+    .await
+    {
+        tracing::warn!("documents: failed to update job {}: {}", job_id, e);
+    }
+}
+
+/// Batches of consecutive summaries whose joined length stays under `max_chars`.
+fn reduce_batches(summaries: &[String], max_chars: usize) -> Vec<Vec<String>> {
+    let mut batches: Vec<Vec<String>> = Vec::new();
+    let mut size = 0;
+    for s in summaries {
+        let len = s.chars().count() + 2;
+        if batches.last().is_none_or(|b| !b.is_empty() && size + len > max_chars) {
+            batches.push(Vec::new());
+            size = 0;
+        }
+        if let Some(batch) = batches.last_mut() {
+            batch.push(s.clone());
+        }
+        size += len;
+    }
+    batches
+}
+
+async fn run_summary(
+    state: &AppState,
+    job_id: uuid::Uuid,
+    doc: DocumentText,
+    instructions: Option<String>,
+) -> Result<Value, String> {
+    let chunks = chunk_pages(&doc.pages, CHUNK_CHARS);
+    let map_model = crate::model_registry::get_model_id(state, "executor").await;
+    let reduce_model = crate::model_registry::get_model_id(state, "coordinator").await;
+
+    // Map: summarize chunks in parallel, keeping document order.
+    let total = chunks.len();
+    let mut done = 0;
+    let mut mapped: Vec<(usize, String)> = Vec::with_capacity(total);
+    let mut results = futures_util::stream::iter(chunks.iter().map(|chunk| {
+        let model = map_model.clone();
+        let prompt = format!(
+            "Document: {}\nSection: {} (of {} sections)\n\n{}",
+            doc.filename,
+            chunk.label(),
+            total,
+            chunk.text
+        );
+        async move {
+            let summary = complete(state, &model, MAP_SYSTEM, &prompt, MAP_MAX_TOKENS, "document").await;
+            (chunk.index, summary)
+        }
+    }))
+    .buffer_unordered(MAP_CONCURRENCY);
+    while let Some((index, summary)) = results.next().await {
+        let summary = summary.map_err(|e| format!("summarizing {} failed: {}", chunks[index].label(), e))?;
+        mapped.push((index, summary));
+        done += 1;
+        set_progress(&state.db, job_id, "map", done).await;
+    }
+    drop(results);
+    mapped.sort_by_key(|(i, _)| *i);
+
+    let chunk_summaries: Vec<Value> = mapped
+        .iter()
+        .map(|(i, s)| json!({ "index": i, "label": chunks[*i].label(), "summary": s }))
+        .collect();
+    let mut summaries: Vec<String> = mapped
+        .into_iter()
+        .map(|(i, s)| format!("[{}]\n{}", chunks[i].label(), s.trim()))
+        .collect();
+
+    // Condense until the summaries fit one synthesis call.
+    let mut condense_rounds = 0;
+    let joined_len = |s: &[String]| s.iter().map(|s| s.chars().count() + 2).sum::<usize>();
+    while summaries.len() > 1 && joined_len(&summaries) > MAX_REDUCE_INPUT_CHARS {
+        set_progress(&state.db, job_id, "condense", total).await;
+        let batches = reduce_batches(&summaries, MAX_REDUCE_INPUT_CHARS / 4);
+        let condensed: Vec<Result<String, String>> =
+            futures_util::stream::iter(batches.into_iter().map(|batch| {
+                let model = map_model.clone();
+                async move {
+                    let prompt = batch.join("\n\n");
+                    // Keep the page label of the first summary in the batch.
+                    let first = batch.first().and_then(|s| s.lines().next()).unwrap_or("").to_string();
+                    complete(state, &model, CONDENSE_SYSTEM, &prompt, MAP_MAX_TOKENS * 2, "document")
+                        .await
+                        .map(|s| format!("{} onwards\n{}", first, s.trim()))
+                }
+            }))
+            .buffered(MAP_CONCURRENCY)
+            .collect()
+            .await;
+        let condensed = condensed.into_iter().collect::<Result<Vec<_>, _>>()?;
+        if condensed.len() >= summaries.len() {
+            break;
+        }
+        summaries = condensed;
+        condense_rounds += 1;
+    }
+
+    // Reduce: one synthesis call with the stronger model.
+    set_progress(&state.db, job_id, "reduce", total).await;
+    let mut prompt = format!(
+        "Document: {} ({} pages, {} sections)\n\nSection summaries in order:\n\n{}",
+        doc.filename,
+        doc.pages.len(),
+        total,
+        summaries.join("\n\n")
+    );
+    if let Some(instructions) = instructions.as_deref() {
+        prompt.push_str(&format!("\n\nAdditional instructions for the summary: {}", instructions));
+    }
+    let summary = complete(state, &reduce_model, REDUCE_SYSTEM, &prompt, REDUCE_MAX_TOKENS, "document")
+        .await
+        .map_err(|e| format!("final summary failed: {}", e))?;
+
+    Ok(json!({
+        "summary": summary,
+        "chunks": chunk_summaries,
+        "condense_rounds": condense_rounds,
+        "models": { "map": map_model, "reduce": reduce_model },
+    }))
+}
+
+#[utoipa::path(post, path = "/api/documents/{id}/summarize", tag = "documents",
+    params(("id" = String, Path, description = "Upload UUID")),
+    request_body(content = SummarizeDocumentRequest, description = "Optional summary instructions"),
+    responses(
+        (status = 202, description = "Summary job started"),
+        (status = 400, description = "Invalid id or over-long instructions"),
+        (status = 404, description = "Document not found"),
+        (status = 415, description = "Not a PDF or text document"),
+        (status = 422, description = "No extractable text")
+    ))]
+pub async fn summarize_document(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    body: Option<Json<SummarizeDocumentRequest>>,
+) -> Result<Response, ApiError> {
+    let upload_id: uuid::Uuid = id
+        .parse()
+        .map_err(|_| api_error(StatusCode::BAD_REQUEST, "Invalid document id"))?;
+    let instructions = body
+        .and_then(|Json(b)| b.instructions)
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty());
+    if instructions.as_ref().is_some_and(|s| s.chars().count() > MAX_INSTRUCTIONS_CHARS) {
+        return Err(api_error(StatusCode::BAD_REQUEST, "instructions too long"));
+    }
+
+    let doc = load_document(&state, upload_id).await?;
+    let chunks_total = chunk_pages(&doc.pages, CHUNK_CHARS).len();
+    let job_id = uuid::Uuid::new_v4();
+    sqlx::query(
+        "INSERT INTO ch_document_jobs (id, upload_id, kind, status, phase, chunks_total, pages) \
+         VALUES ($1, $2, 'summarize', 'running', 'map', $3, $4)",
+    )
+    .bind(job_id)
+    .bind(upload_id)
+    .bind(chunks_total as i32)
+    .bind(doc.pages.len() as i32)
+    .execute(&state.db)
+    .await
+    .map_err(db_error)?;
+
+    let journal = crate::recovery::begin(
+        crate::recovery::OperationKind::DocumentJob,
+        None,
+        format!("summary of document {} (job {})", upload_id, job_id),
+        json!({ "upload_id": upload_id, "instructions": instructions, "job_id": job_id }),
+    );
+    let job_state = state.clone();
+    tokio::spawn(async move {
+        let _journal = journal;
+        let outcome = run_summary(&job_state, job_id, doc, instructions).await;
+        let (status, result, error) = match outcome {
+            Ok(result) => ("completed", Some(result), None),
+            Err(e) => {
+                tracing::warn!("documents: summary job {} failed: {}", job_id, e);
+                ("failed", None, Some(e))
+            }
+        };
+        if let Err(e) = sqlx::query(
+            "UPDATE ch_document_jobs SET status = $2, phase = 'done', result = $3, error = $4, \
+             updated_at = NOW(), finished_at = NOW() WHERE id = $1",
+        )
+        .bind(job_id)
+        .bind(status)
+        .bind(result)
+        .bind(error)
+        .execute(&job_state.db)
+        .await
+        {
+            tracing::error!("documents: failed to finish job {}: {}", job_id, e);
+        }
+    });
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(json!({
+            "job_id": job_id,
+            "document_id": upload_id,
+            "status": "running",
+            "chunks_total": chunks_total,
+            "status_url": format!("/api/documents/jobs/{}", job_id),
+        })),
+    )
+        .into_response())
+}
+
+#[derive(sqlx::FromRow)]
+struct DocumentJobRow {
+    upload_id: uuid::Uuid,
+    kind: String,
+    status: String,
+    phase: String,
+    chunks_total: i32,
+    chunks_done: i32,
+    pages: i32,
+    result: Option<Value>,
+    error: Option<String>,
+    created_at: chrono::DateTime<chrono::Utc>,
+    finished_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[utoipa::path(get, path = "/api/documents/jobs/{id}", tag = "documents",
+    params(("id" = String, Path, description = "Job UUID")),
+    responses(
+        (status = 200, description = "Job status, progress and result"),
+        (status = 400, description = "Invalid id"),
+        (status = 404, description = "Job not found")
+    ))]
+pub async fn get_document_job(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let job_id: uuid::Uuid = id
+        .parse()
+        .map_err(|_| api_error(StatusCode::BAD_REQUEST, "Invalid job id"))?;
+    let job = sqlx::query_as::<_, DocumentJobRow>(
+        "SELECT upload_id, kind, status, phase, chunks_total, chunks_done, pages, result, error, \
+         created_at, finished_at FROM ch_document_jobs WHERE id = $1",
+    )
+    .bind(job_id)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(db_error)?
+    .ok_or_else(|| api_error(StatusCode::NOT_FOUND, "Job not found"))?;
+    let progress = if job.chunks_total > 0 {
+        (job.chunks_done as f64 / job.chunks_total as f64).min(1.0)
+    } else {
+        0.0
+    };
+    Ok(Json(json!({
+        "job_id": job_id,
+        "document_id": job.upload_id,
+        "kind": job.kind,
+        "status": job.status,
+        "phase": job.phase,
+        "chunks_total": job.chunks_total,
+        "chunks_done": job.chunks_done,
+        "pages": job.pages,
+        "progress": progress,
+        "result": job.result,
+        "error": job.error,
+        "created_at": job.created_at.to_rfc3339(),
+        "finished_at": job.finished_at.map(|t| t.to_rfc3339()),
+    })))
+}
+
+// ── Question answering ──────────────────────────────────────────────────────
+
+/// Passage size for `ask` — small, so a citation points at a specific place.
+const ASK_CHUNK_CHARS: usize = 2_000;
+const DEFAULT_TOP_K: usize = 6;
+const MAX_TOP_K: usize = 12;
+const MAX_QUESTION_CHARS: usize = 4_000;
+const ASK_MAX_TOKENS: u32 = 2048;
+const CITATION_EXCERPT_CHARS: usize = 300;
+
+const ASK_SYSTEM: &str = "You answer questions about one document using only the numbered excerpts \
+     provided. Cite the excerpts you rely on as [1], [2] right after each claim. If the excerpts do not \
+     contain the answer, say so plainly instead of guessing.";
+
+/// Request body for `POST /api/documents/{id}/ask`.
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct AskDocumentRequest {
+    pub question: String,
+    /// Passages to retrieve (default 6, max 12).
+    pub top_k: Option<usize>,
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+struct PassageRow {
+    label: String,
+    page_start: Option<i32>,
+    page_end: Option<i32>,
+    text: String,
+    embedding: Vec<f32>,
+}
+
+/// Cosine similarity; 0 for empty or mismatched vectors.
+fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() || a.is_empty() {
+        return 0.0;
+    }
+    let (mut dot, mut na, mut nb) = (0.0f32, 0.0f32, 0.0f32);
+    for (x, y) in a.iter().zip(b) {
+        dot += x * y;
+        na += x * x;
+        nb += y * y;
+    }
+    if na == 0.0 || nb == 0.0 { 0.0 } else { dot / (na.sqrt() * nb.sqrt()) }
+}
+
+fn terms(text: &str) -> std::collections::HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|t| t.chars().count() >= 3)
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Share of the question's terms found in the passage (retrieval without embeddings).
+fn keyword_score(question: &std::collections::HashSet<String>, passage: &str) -> f32 {
+    if question.is_empty() {
+        return 0.0;
+    }
+    let passage = terms(passage);
+    question.iter().filter(|t| passage.contains(*t)).count() as f32 / question.len() as f32
+}
+
+/// Excerpt numbers cited as `[n]` in an answer, in order, without repeats.
+fn cited_numbers(answer: &str, max: usize) -> Vec<usize> {
+    let mut seen = Vec::new();
+    for part in answer.split('[').skip(1) {
+        if let Some((n, _)) = part.split_once(']')
+            && let Ok(n) = n.trim().parse::<usize>()
+            && (1..=max).contains(&n)
+            && !seen.contains(&n)
+        {
+            seen.push(n);
+        }
+    }
+    seen
+}
+
+/// The document's passages, embedding and storing them on first use. The
+/// flag is false when embeddings are unavailable (vectors are then empty).
+async fn passages(state: &AppState, upload_id: uuid::Uuid) -> Result<(Vec<PassageRow>, bool), ApiError> {
+    let model = crate::providers::gemini::embedding_model();
+    let stored = sqlx::query_as::<_, PassageRow>(
+        "SELECT label, page_start, page_end, text, embedding FROM ch_document_chunks \
+         WHERE upload_id = $1 AND model = $2 ORDER BY idx ASC",
+    )
+    .bind(upload_id)
+    .bind(&model)
+    .fetch_all(&state.db)
+    .await
+    .map_err(db_error)?;
+    if !stored.is_empty() {
+        return Ok((stored, true));
+    }
+
+    let doc = load_document(state, upload_id).await?;
+    let chunks = chunk_pages(&doc.pages, ASK_CHUNK_CHARS);
+    let texts: Vec<String> = chunks.iter().map(|c| c.text.clone()).collect();
+    let vectors = match crate::providers::gemini::embed(state, &texts, "RETRIEVAL_DOCUMENT").await {
+        Ok(v) => Some(v),
+        Err((status, Json(err))) => {
+            tracing::warn!(
+                "documents: embeddings unavailable ({}: {}), using keyword retrieval",
+                status,
+                err["error"]
+            );
+            None
+        }
+    };
+    let embedded = vectors.is_some();
+    let mut vectors = vectors.unwrap_or_default().into_iter();
+    let rows: Vec<PassageRow> = chunks
+        .into_iter()
+        .map(|c| PassageRow {
+            label: c.label(),
+            page_start: c.pages.map(|(a, _)| a as i32),
+            page_end: c.pages.map(|(_, b)| b as i32),
+            text: c.text,
+            embedding: vectors.next().unwrap_or_default(),
+        })
+        .collect();
+
+    if embedded {
+        let mut tx = state.db.begin().await.map_err(db_error)?;
+        for (idx, row) in rows.iter().enumerate() {
+            sqlx::query(
+                "INSERT INTO ch_document_chunks \
+                 (upload_id, model, idx, label, page_start, page_end, text, embedding) \
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8) ON CONFLICT DO NOTHING",
+            )
+            .bind(upload_id)
+            .bind(&model)
+            .bind(idx as i32)
+            .bind(&row.label)
+            .bind(row.page_start)
+            .bind(row.page_end)
+            .bind(&row.text)
+            .bind(&row.embedding)
+            .execute(&mut *tx)
+            .await
+            .map_err(db_error)?;
+        }
+        tx.commit().await.map_err(db_error)?;
+    }
+    Ok((rows, embedded))
+}
+
+#[utoipa::path(post, path = "/api/documents/{id}/ask", tag = "documents",
+    params(("id" = String, Path, description = "Upload UUID")),
+    request_body = AskDocumentRequest,
+    responses(
+        (status = 200, description = "Answer with numbered citations"),
+        (status = 400, description = "Invalid id, empty or over-long question"),
+        (status = 404, description = "Document not found"),
+        (status = 415, description = "Not a PDF or text document"),
+        (status = 422, description = "No extractable text")
+    ))]
+pub async fn ask_document(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(req): Json<AskDocumentRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let upload_id: uuid::Uuid = id
+        .parse()
+        .map_err(|_| api_error(StatusCode::BAD_REQUEST, "Invalid document id"))?;
+    let question = req.question.trim();
+    if question.is_empty() || question.chars().count() > MAX_QUESTION_CHARS {
+        return Err(api_error(StatusCode::BAD_REQUEST, "question must be 1-4000 characters"));
+    }
+    let top_k = req.top_k.unwrap_or(DEFAULT_TOP_K).clamp(1, MAX_TOP_K);
+    let filename: String = sqlx::query_scalar("SELECT filename FROM ch_uploads WHERE id = $1")
+        .bind(upload_id)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| api_error(StatusCode::NOT_FOUND, "Document not found"))?;
+
+    let (rows, embedded) = passages(&state, upload_id).await?;
+    let query_vector = if embedded {
+        crate::providers::gemini::embed(&state, &[question.to_string()], "RETRIEVAL_QUERY")
+            .await
+            .ok()
+            .and_then(|mut v| v.pop())
+    } else {
+        None
+    };
+    let retrieval = if query_vector.is_some() { "embeddings" } else { "keyword" };
+    let question_terms = terms(question);
+    let mut scored: Vec<(usize, f32)> = rows
+        .iter()
+        .enumerate()
+        .map(|(i, row)| {
+            let score = match &query_vector {
+                Some(q) => cosine(q, &row.embedding),
+                None => keyword_score(&question_terms, &row.text),
+            };
+            (i, score)
+        })
+        .collect();
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+    scored.truncate(top_k);
+    // Present the excerpts in document order — easier to reason over.
+    scored.sort_by_key(|(i, _)| *i);
+
+    let excerpts = scored
+        .iter()
+        .enumerate()
+        .map(|(n, (i, _))| format!("[{}] ({})\n{}", n + 1, rows[*i].label, rows[*i].text))
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    let prompt = format!(
+        "Document: {}\n\nExcerpts:\n\n{}\n\nQuestion: {}",
+        filename, excerpts, question
+    );
+    let model = crate::model_registry::get_model_id(&state, "coordinator").await;
+    let answer = complete(&state, &model, ASK_SYSTEM, &prompt, ASK_MAX_TOKENS, "document")
+        .await
+        .map_err(|e| api_error(StatusCode::BAD_GATEWAY, e))?;
+
+    let citations: Vec<Value> = scored
+        .iter()
+        .enumerate()
+        .map(|(n, (i, score))| {
+            let row = &rows[*i];
+            json!({
+                "n": n + 1,
+                "label": row.label,
+                "page_start": row.page_start,
+                "page_end": row.page_end,
+                "excerpt": row.text.chars().take(CITATION_EXCERPT_CHARS).collect::<String>(),
+                "score": score,
+            })
+        })
+        .collect();
+    Ok(Json(json!({
+        "document_id": upload_id,
+        "question": question,
+        "answer": answer,
+        "cited": cited_numbers(&answer, citations.len()),
+        "citations": citations,
+        "retrieval": retrieval,
+        "model": model,
+    })))
+}
+
+/// Mark jobs left running by a previous process as failed (called at startup).
+pub async fn fail_interrupted_jobs(db: &sqlx::PgPool) {
+    match sqlx::query(
+        "UPDATE ch_document_jobs SET status = 'failed', error = 'interrupted by server restart', \
+         updated_at = NOW(), finished_at = NOW() WHERE status = 'running'",
+    )
+    .execute(db)
+    .await
+    {
+        Ok(r) if r.rows_affected() > 0 => {
+            tracing::warn!("documents: {} interrupted job(s) marked failed", r.rows_affected());
+        }
+        Ok(_) => {}
+        Err(e) => tracing::warn!("documents: failed to clean up interrupted jobs: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pages_are_grouped_into_page_aligned_chunks() {
+        let pages: Vec<String> = (1..=5).map(|i| format!("page {} {}", i, "x".repeat(40))).collect();
+        let chunks = chunk_pages(&pages, 100);
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0].pages, Some((1, 2)));
+        assert_eq!(chunks[2].pages, Some((5, 5)));
+        assert_eq!(chunks[2].label(), "page 5");
+        assert!(chunks.iter().all(|c| c.text.chars().count() <= 100));
+    }
+
+    #[test]
+    fn long_text_is_split_on_boundaries() {
+        let text = format!("{}\n\n{}", "a ".repeat(30).trim(), "b ".repeat(30).trim());
+        let chunks = chunk_pages(&[text], 80);
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].pages, None);
+        assert_eq!(chunks[1].label(), "part 2");
+        assert!(chunks[1].text.starts_with('b'));
+    }
+
+    #[test]
+    fn reduce_batches_keep_order_and_size() {
+        let summaries: Vec<String> = (0..6).map(|i| format!("{}{}", i, "s".repeat(29))).collect();
+        let batches = reduce_batches(&summaries, 70);
+        assert_eq!(batches.len(), 3);
+        assert!(batches.iter().all(|b| b.len() == 2));
+        assert!(batches[1][0].starts_with('2'));
+    }
+
+    #[test]
+    fn retrieval_scores() {
+        assert!((cosine(&[1.0, 0.0], &[2.0, 0.0]) - 1.0).abs() < 1e-6);
+        assert_eq!(cosine(&[1.0, 0.0], &[0.0, 1.0]), 0.0);
+        assert_eq!(cosine(&[], &[]), 0.0);
+        let q = terms("What is the notice period for termination?");
+        assert!(keyword_score(&q, "The notice period is 30 days before termination.") > 0.5);
+        assert_eq!(keyword_score(&q, "Payment is due monthly."), 0.0);
+    }
+
+    #[test]
+    fn citations_are_read_from_the_answer() {
+        assert_eq!(cited_numbers("Yes [2], see also [1] and [2]; not [9] or [x].", 3), vec![2, 1]);
+    }
+}
@@ -0,0 +1,134 @@
+//! Google Gemini chat endpoints.
+//!
+//! - `gemini_chat` — non-streaming chat completion (`ChatResponse`)
+//! - `gemini_chat_stream` — NDJSON streaming (same frames as the Claude stream)
+//!
+//! Requests use the same `ChatRequest` as the Claude endpoints; `model` must
+//! be a `gemini-*` id and defaults to the resolved `flash` tier. Tools and
+//! assistant prefill are Anthropic-only and rejected here. The upstream API is
+//! handled by `providers::Gemini`.
+
+use axum::extract::State;
+use axum::{Extension, Json};
+use axum::http::StatusCode;
+use axum::response::Response;
+use serde_json::{Value, json};
+
+use crate::models::*;
+use crate::priority::DefaultPriority;
+use crate::providers::{Gemini, Provider, ProviderRequest};
+use crate::state::AppState;
+
+use super::prompt::{resolve_chat_context, resolve_request_scope};
+
+/// Pick the Gemini model for a request, rejecting non-Gemini ids.
+async fn gemini_model(
+    state: &AppState,
+    req: &ChatRequest,
+) -> Result<String, (StatusCode, Json<Value>)> {
+    match req.model.as_deref() {
+        Some(m) if m.starts_with("gemini-") => Ok(m.to_string()),
+        Some(m) => Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "error": format!("'{}' is not a Gemini model — use /api/claude/chat for Claude", m)
+            })),
+        )),
+        None => Ok(crate::model_registry::get_model_id(state, "flash").await),
+    }
+}
+
+fn validate_gemini_request(req: &ChatRequest) -> Result<(), (StatusCode, Json<Value>)> {
+    if req.messages.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "messages must not be empty" })),
+        ));
+    }
+    if req.tools_enabled.unwrap_or(false) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "tools_enabled is not supported for Gemini models" })),
+        ));
+    }
+    if super::has_assistant_prefill(&req.messages) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "Assistant prefill is only supported for Claude models" })),
+        ));
+    }
+    Ok(())
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  Non-streaming chat
+// ═══════════════════════════════════════════════════════════════════════
+
+/// POST /api/gemini/chat — non-streaming Gemini request
+#[utoipa::path(post, path = "/api/gemini/chat", tag = "chat",
+    request_body = ChatRequest,
+    responses((status = 200, description = "Chat completion response")))]
+pub async fn gemini_chat(
+    State(state): State<AppState>,
+    token_priority: Option<Extension<DefaultPriority>>,
+    Json(req): Json<ChatRequest>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    validate_gemini_request(&req)?;
+    let model = gemini_model(&state, &req).await?;
+    let scope = resolve_request_scope(
+        &state,
+        &req,
+        token_priority.map(|Extension(DefaultPriority(p))| p),
+    )
+    .await?;
+    let session_id = req.session_id.as_deref().and_then(|id| uuid::Uuid::parse_str(id).ok());
+    let agent_id = req.agent_id.clone();
+    let provider_req = ProviderRequest {
+        model,
+        messages: req.messages,
+        temperature: req.temperature,
+        max_tokens: req.max_tokens.unwrap_or(4096),
+        ..Default::default()
+    };
+    let completion = crate::request_scope::run(scope, Gemini.chat(&state, &provider_req)).await?;
+
+    Ok(Json(serde_json::to_value(completion.into_attributed_response(&state, "chat", session_id, agent_id)).map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "serialization failed"})),
+        )
+    })?))
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  Streaming chat
+// ═══════════════════════════════════════════════════════════════════════
+
+/// POST /api/gemini/chat/stream — Gemini NDJSON stream (resumable like the Claude stream)
+#[utoipa::path(post, path = "/api/gemini/chat/stream", tag = "chat",
+    request_body = ChatRequest,
+    responses((status = 200, description = "Streaming NDJSON response")))]
+pub async fn gemini_chat_stream(
+    State(state): State<AppState>,
+    token_priority: Option<Extension<DefaultPriority>>,
+    Json(mut req): Json<ChatRequest>,
+) -> Result<Response, (StatusCode, Json<Value>)> {
+    validate_gemini_request(&req)?;
+    let model = gemini_model(&state, &req).await?;
+    req.model = Some(model.clone());
+    let scope = resolve_request_scope(
+        &state,
+        &req,
+        token_priority.map(|Extension(DefaultPriority(p))| p),
+    )
+    .await?;
+    crate::request_scope::run(scope, async move {
+        let mut ctx = resolve_chat_context(&state, &req).await;
+        // A/B routing in the context resolver may pick a Claude model
+        ctx.model = model;
+        let provider_req = ProviderRequest::from_context(ctx, req.messages);
+        let response = Gemini.chat_stream(&state, provider_req).await?;
+        Ok(crate::stream_relay::detach(&state, response))
+    })
+    .await
+}
@@ -4,42 +4,79 @@
 //! - `prompt` — system prompt construction, chat context resolution, auto-tier routing
 //! - `streaming` — NDJSON streaming handlers (Anthropic SSE + Gemini hybrid)
 //! - `chat` — non-streaming Claude chat endpoints
+//! - `gemini` — Google Gemini chat endpoints (non-streaming + NDJSON)
 //! - `health` — health, readiness, system stats, auth mode, admin
 //! - `sessions` — session CRUD, messages, AI title generation
+//! - `session_chat` — session-bound chat that stores the user turn and the reply together
+//! - `snapshots` — session restore points (snapshot / restore message lists)
+//! - `comments` — review comment threads on individual messages
 //! - `settings` — application settings endpoints
 //! - `agents` — agent listing and refresh
+//! - `agent_run` — server-side tool loop (`POST /api/agents/{id}/run`)
+//! - `agent_tests` — stored behavior tests per agent (regex / judge expectations)
 //! - `files` — file listing and native folder browser
 //! - `prompt_history` — bash-like prompt recall
 //! - `analytics` — agent performance dashboard aggregation endpoints
-
+//! - `debate` — turn-based agent debate mode with a judge verdict
+//! - `documents` — map-reduce summaries of uploaded PDFs / text files
+//! - `templates` — conversation templates and slot-filling interview mode
+//! - `usage` — usage event export (CSV / JSONL)
+//! - `uploads` — streaming multipart uploads to disk with checksum verification
+//! - `usage_upstream` — Anthropic Admin API org usage reconciled with local accounting
+
+pub mod agent_run;
+pub mod agent_tests;
 pub mod agents;
 pub mod analytics;
 pub mod chat;
+pub mod comments;
+pub mod debate;
+pub mod documents;
 pub mod files;
+pub mod gemini;
 pub mod health;
 pub mod prompt;
 pub mod prompt_history;
+pub mod session_chat;
 pub mod sessions;
 pub mod settings;
+pub mod snapshots;
 pub mod streaming;
+pub mod sub_sessions;
 pub mod tags;
+pub mod templates;
+pub mod uploads;
+pub mod usage;
+pub mod usage_upstream;
 
 // Re-export everything (including utoipa __path_* types needed by OpenApi derive)
+pub use agent_run::*;
+pub use agent_tests::*;
 pub use agents::*;
 pub use analytics::*;
 pub use chat::*;
+pub use comments::*;
+pub use debate::*;
+pub use documents::*;
 pub use files::*;
+pub use gemini::*;
 pub use health::*;
 pub use prompt::warm_prompt_cache;
 pub use prompt_history::*;
+pub use session_chat::*;
 pub use sessions::*;
 pub use settings::*;
+pub use snapshots::*;
 pub use streaming::*;
+pub use sub_sessions::*;
 pub use tags::*;
+pub use templates::*;
+pub use uploads::*;
+pub use usage::*;
+pub use usage_upstream::*;
 
 // ── Shared constants ──────────────────────────────────────────────────────
 
-pub(crate) const TOOL_TIMEOUT_SECS: u64 = 60;
 pub(crate) const MAX_MESSAGE_LENGTH: usize = 100_000;
 
 // ── Shared helpers ────────────────────────────────────────────────────────
@@ -51,6 +88,11 @@ use serde_json::{Value, json};
 use crate::ai_gateway::vault_bridge::HasVaultBridge;
 use crate::state::AppState;
 
+/// Timeout of one tool execution (the `tools` endpoint policy).
+pub(crate) fn tool_timeout_secs() -> u64 {
+    crate::endpoint_policies::get(crate::endpoint_policies::Group::Tools).timeout_secs()
+}
+
 /// Check if an HTTP status code is retryable (429 Too Many Requests or 5xx).
 pub(crate) fn is_retryable_status(status: u16) -> bool {
     status == 429 || (500..=599).contains(&status)
@@ -94,6 +136,80 @@ pub(crate) fn sanitize_json_strings(value: &mut Value) {
     }
 }
 
+/// Assistant prefill: a trailing `assistant` message is sent as-is so the model
+/// continues it, and the response contains only the continuation. Anthropic
+/// rejects prefills ending in whitespace, so the content is trimmed (an empty
+/// prefill is dropped). Returns whether a prefill is present.
+pub(crate) fn prepare_assistant_prefill(
+    messages: &mut Vec<Value>,
+) -> Result<bool, (StatusCode, Json<Value>)> {
+    let Some(last) = messages.last_mut() else {
+        return Ok(false);
+    };
+    if last.get("role").and_then(|r| r.as_str()) != Some("assistant") {
+        return Ok(false);
+    }
+    let trimmed = last
+        .get("content")
+        .and_then(|c| c.as_str())
+        .map(|c| c.trim_end().to_string())
+        .unwrap_or_default();
+    if trimmed.is_empty() {
+        messages.pop();
+        return Ok(false);
+    }
+    last["content"] = json!(trimmed);
+    if messages.len() < 2 {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "An assistant prefill must follow a user message" })),
+        ));
+    }
+    Ok(true)
+}
+
+/// Ceiling for the `max_continuations` setting.
+pub(crate) const MAX_CONTINUATIONS_LIMIT: i32 = 5;
+
+/// How many times a reply cut off at `max_tokens` is continued
+/// (`max_continuations` setting; 0 turns continuation off).
+pub(crate) async fn max_continuations(db: &sqlx::PgPool) -> u32 {
+    sqlx::query_scalar::<_, i32>("SELECT max_continuations FROM ch_settings WHERE id = 1")
+        .fetch_optional(db)
+        .await
+        .ok()
+        .flatten()
+        .unwrap_or(2)
+        .clamp(0, MAX_CONTINUATIONS_LIMIT) as u32
+}
+
+/// Set up a `max_tokens` continuation: the reply so far becomes (or extends)
+/// the trailing assistant prefill, trimmed as `prepare_assistant_prefill` does.
+pub(crate) fn continue_from(messages: &mut Vec<Value>, partial: &str) {
+    if let Some(last) = messages.last_mut()
+        && last.get("role").and_then(|r| r.as_str()) == Some("assistant")
+        && let Some(prefill) = last.get("content").and_then(|c| c.as_str()).map(str::to_string)
+    {
+        last["content"] = json!(format!("{}{}", prefill, partial).trim_end());
+        return;
+    }
+    messages.push(json!({ "role": "assistant", "content": partial.trim_end() }));
+}
+
+/// Append a continuation to the reply so far. The prefill it continues was
+/// trimmed, so the model supplies the whitespace at the seam itself.
+pub(crate) fn stitch_continuation(text: &mut String, piece: &str) {
+    text.truncate(text.trim_end().len());
+    text.push_str(piece);
+}
+
+/// Whether the request ends with an assistant prefill (see `prepare_assistant_prefill`).
+pub(crate) fn has_assistant_prefill(messages: &[crate::models::ChatMessage]) -> bool {
+    messages
+        .last()
+        .is_some_and(|m| m.role == "assistant" && !m.content.trim().is_empty())
+}
+
 // ── Anthropic API helpers ─────────────────────────────────────────────────
 
 /// Get the Anthropic credential with dual resolution strategy:
@@ -101,8 +217,12 @@ pub(crate) fn sanitize_json_strings(value: &mut Value) {
 /// 2. Fallback: Old DB path (`jaskier_oauth::anthropic::get_valid_anthropic_access_token`)
 /// 3. Last resort: Runtime API keys / `ANTHROPIC_API_KEY` env var
 ///
+/// Outside the default key environment only that environment's key is used.
 /// Returns `(token_or_key, is_oauth)`.
-async fn get_anthropic_credential(state: &AppState) -> Option<(String, bool)> {
+pub(crate) async fn get_anthropic_credential(state: &AppState) -> Option<(String, bool)> {
+    if let Some(key) = crate::key_environments::environment_key(state, "anthropic").await {
+        return key.map(|k| (k, false));
+    }
     // 1. Try Vault first (ai_providers/anthropic_max)
     match state.vault_client().get("ai_providers", "anthropic_max").await {
         Ok(cred) if cred.is_connected => {
@@ -162,6 +282,16 @@ async fn get_anthropic_credential(state: &AppState) -> Option<(String, bool)> {
     None
 }
 
+/// Base URL of the Anthropic API. Overridable via `ANTHROPIC_API_URL` so the
+/// streaming pipeline can be pointed at a local mock provider (see `loadtest` bin).
+pub(crate) fn anthropic_api_url() -> String {
+    std::env::var("ANTHROPIC_API_URL")
+        .ok()
+        .filter(|u| !u.is_empty())
+        .map(|u| u.trim_end_matches('/').to_string())
+        .unwrap_or_else(|| "https://api.anthropic.com".to_string())
+}
+
 /// Build a request to the Anthropic Messages API.
 fn build_anthropic_request(
     state: &AppState,
@@ -170,9 +300,8 @@ fn build_anthropic_request(
     timeout_secs: u64,
     is_oauth: bool,
 ) -> reqwest::RequestBuilder {
-    let mut req = state
-        .http_client
-        .post("https://api.anthropic.com/v1/messages")
+    let mut req = crate::http_client::client(state)
+        .post(format!("{}/v1/messages", anthropic_api_url()))
         .timeout(std::time::Duration::from_secs(timeout_secs))
         .header("content-type", "application/json")
         .header("anthropic-version", "2023-06-01");
@@ -344,6 +473,9 @@ async fn send_to_anthropic_via_vault(
 
 /// Get Anthropic API key only (skip OAuth). Used as fallback.
 async fn get_anthropic_api_key_only(state: &AppState) -> Option<(String, bool)> {
+    if let Some(key) = crate::key_environments::environment_key(state, "anthropic").await {
+        return key.map(|k| (k, false));
+    }
     {
         let rt = state.runtime.read().await;
         if let Some(key) = rt.api_keys.get("ANTHROPIC_API_KEY")
@@ -358,39 +490,134 @@ async fn get_anthropic_api_key_only(state: &AppState) -> Option<(String, bool)>
         .map(|k| (k, false))
 }
 
-/// Send to Anthropic with circuit breaker + retry on 429/5xx.
+/// Send to Anthropic with circuit breaker (see `circuit`) + retry on 429/5xx,
+/// with the timeout and retries of the `group` policy (see `endpoint_policies`).
 pub(crate) async fn send_to_anthropic(
     state: &AppState,
     body: &Value,
-    timeout_secs: u64,
+    group: crate::endpoint_policies::Group,
 ) -> Result<reqwest::Response, (StatusCode, Json<Value>)> {
-    // Circuit breaker gate
-    if let Err(msg) = state.circuit_breaker.check().await {
-        return Err((
-            StatusCode::SERVICE_UNAVAILABLE,
-            Json(json!({
-                "error": msg
-            })),
-        ));
+    // Agent / caller stop sequences from the active request scope
+    let scoped = crate::request_scope::apply(body);
+    let body = scoped.as_ref().unwrap_or(body);
+    // Daily tier budgets: downgrade to a lower tier or reject (429)
+    let budgeted = crate::tier_budgets::apply(state, body).await?;
+    let body = budgeted.as_ref().unwrap_or(body);
+    // Spend caps and the (final) model's token quota: reject (429), or warn
+    // when close (soft_limits)
+    crate::quotas::check(state, body.get("model").and_then(|m| m.as_str())).await?;
+    crate::soft_limits::check_context(body);
+    if let Some(transcript) = crate::transcripts::current() {
+        transcript.record_request(body);
+    }
+    if let Some(scope) = crate::request_scope::current() {
+        scope.decisions.set_request(body);
     }
 
-    let resp = send_to_anthropic_once(state, body, timeout_secs).await?;
-
-    if resp.status().is_success() {
-        state.circuit_breaker.record_success().await;
-        Ok(resp)
-    } else if is_retryable_status(resp.status().as_u16()) {
-        state.circuit_breaker.record_failure().await;
-        // Retry once with 2s backoff
-        tokio::time::sleep(std::time::Duration::from_secs(2)).await;
-        let retry_resp = send_to_anthropic_once(state, body, timeout_secs).await?;
-        if retry_resp.status().is_success() {
-            state.circuit_breaker.record_success().await;
+    // Upstream concurrency slot, queued by priority (held by the request scope
+    // when there is one, otherwise until this call returns)
+    let _permit = crate::priority::admit(state).await?;
+
+    // Circuit breaker gate: fail fast while Anthropic keeps failing
+    crate::circuit::admit(crate::circuit::ANTHROPIC)?;
+
+    // Transient answers (429 / 529 / 5xx) are retried with backoff before
+    // anything reaches the caller — see `providers::retry`.
+    let endpoint = crate::endpoint_policies::get(group);
+    let (policy, timeout_secs) = (endpoint.retry, endpoint.timeout_secs());
+    let mut retries = Vec::new();
+    let mut attempt = 1;
+    loop {
+        // Test builds can inject upstream faults here (see `chaos`).
+        #[cfg(feature = "chaos")]
+        let sent = crate::chaos::around(
+            crate::circuit::ANTHROPIC,
+            send_to_anthropic_once(state, body, timeout_secs),
+        )
+        .await;
+        #[cfg(not(feature = "chaos"))]
+        let sent = send_to_anthropic_once(state, body, timeout_secs).await;
+        let mut resp = match sent {
+            Ok(resp) => resp,
+            Err(e) => {
+                // Connection errors and timeouts count against the breaker too.
+                if e.0 == StatusCode::BAD_GATEWAY || e.0 == StatusCode::GATEWAY_TIMEOUT {
+                    crate::circuit::record_failure(state, crate::circuit::ANTHROPIC, "request failed").await;
+                }
+                return Err(e);
+            }
+        };
+        // Any other answer (4xx included) shows the upstream is reachable.
+        let status = resp.status().as_u16();
+        if is_retryable_status(status) {
+            crate::circuit::record_failure(state, crate::circuit::ANTHROPIC, format!("HTTP {}", status)).await;
         } else {
-            state.circuit_breaker.record_failure().await;
+            crate::circuit::record_success(state, crate::circuit::ANTHROPIC).await;
         }
-        Ok(retry_resp)
-    } else {
-        Ok(resp)
+        let retry_after = crate::providers::retry::retry_after(resp.headers());
+        let delay = is_retryable_status(status)
+            .then(|| policy.delay(attempt + 1, retry_after, rand::random::<f64>()))
+            .flatten();
+        let Some(delay) = delay else {
+            if !retries.is_empty() {
+                resp.extensions_mut()
+                    .insert(crate::providers::retry::Retries(retries));
+            }
+            return Ok(resp);
+        };
+        tracing::warn!(
+            "anthropic: HTTP {} on attempt {}/{}, retrying in {} ms",
+            status,
+            attempt,
+            policy.max_attempts,
+            delay.as_millis()
+        );
+        let retry = crate::providers::retry::RetryAttempt {
+            attempt,
+            status,
+            delay_ms: delay.as_millis() as u64,
+            retry_after: retry_after.is_some(),
+        };
+        crate::request_scope::decide(
+            "retry",
+            json!({ "status": status, "attempt": attempt + 1, "backoff_ms": retry.delay_ms, "retry_after": retry.retry_after }),
+        );
+        let mut frame = json!({ "type": "retry" });
+        if let (Some(frame), Ok(Value::Object(detail))) = (frame.as_object_mut(), serde_json::to_value(&retry)) {
+            frame.extend(detail);
+        }
+        crate::request_scope::emit(crate::request_scope::StreamEvent::Frame(frame.to_string()));
+        retries.push(retry);
+        drop(resp);
+        tokio::time::sleep(delay).await;
+        // Another request (or this one) may have tripped the breaker while we waited.
+        if let Err((status, Json(mut err))) = crate::circuit::ensure_closed(crate::circuit::ANTHROPIC) {
+            err["retries"] = json!(retries);
+            return Err((status, Json(err)));
+        }
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn continuation_extends_the_prefill_or_adds_one() {
+        let mut messages = vec![json!({ "role": "user", "content": "Write a long essay" })];
+        continue_from(&mut messages, "The first part \n");
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[1], json!({ "role": "assistant", "content": "The first part" }));
+        continue_from(&mut messages, " and the second. ");
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[1]["content"], "The first part and the second.");
+    }
+
+    #[test]
+    fn stitched_replies_drop_whitespace_at_the_seam() {
+        let mut text = "Line one\n\n".to_string();
+        stitch_continuation(&mut text, "\n\nLine two");
+        assert_eq!(text, "Line one\n\nLine two");
     }
 }
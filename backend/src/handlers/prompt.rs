@@ -1,11 +1,18 @@
 //! System prompt construction, chat context resolution, and auto-tier routing.
 //!
-//! - `build_system_prompt` — server-side system prompt (single source of truth)
+//! - `build_system_prompt` — global layer of the system prompt (layering in `prompt_layers`)
 //! - `resolve_chat_context` — model selection, session WD, generation params
+//! - `resolve_request_scope` — agent + caller stop sequences and priority for the request builder
 //! - `warm_prompt_cache` — pre-warm system prompt cache at startup
 //! - `tier_token_budget` — per-model max_tokens budget
 //! - `classify_complexity` — auto-tier routing (re-exported from model_registry)
 
+use axum::Json;
+use axum::http::StatusCode;
+use serde_json::{Value, json};
+
+use crate::prompt_layers::PromptLayer;
+use crate::request_scope::RequestScope;
 use crate::state::AppState;
 
 // ═══════════════════════════════════════════════════════════════════════
@@ -39,14 +46,61 @@ pub(crate) struct ChatContext {
     pub working_directory: String,
     pub session_id: Option<uuid::Uuid>,
     pub system_prompt: String,
+    /// Policy and per-layer sizes behind `system_prompt`.
+    pub prompt_layering: crate::prompt_layers::PromptLayering,
+    pub prompt_layers: Vec<crate::prompt_layers::LayerReport>,
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  Request scope — agent / caller generation constraints
+// ═══════════════════════════════════════════════════════════════════════
+
+/// Resolve the `RequestScope` for a chat request: the selected agent's stop
+/// sequences first, then the caller's. Unknown `agent_id` → 404, disabled
+/// agent → 409; the agent stays busy while the scope lives.
+pub(crate) async fn resolve_request_scope(
+    state: &AppState,
+    req: &crate::models::ChatRequest,
+    token_priority: Option<crate::priority::Priority>,
+) -> Result<std::sync::Arc<RequestScope>, (StatusCode, Json<Value>)> {
+    let agent = match req.agent_id.as_deref().filter(|id| !id.is_empty()) {
+        Some(id) => {
+            let agents = state.agents.read().await;
+            let agent = agents.iter().find(|a| a.id == id).ok_or_else(|| {
+                (
+                    StatusCode::NOT_FOUND,
+                    Json(json!({ "error": format!("Agent '{}' not found", id) })),
+                )
+            })?;
+            crate::agent_status::ensure_usable(agent)?;
+            Some(agent.clone())
+        }
+        None => None,
+    };
+    let agent_stops = agent.as_ref().map(|a| a.stop_sequences.clone()).unwrap_or_default();
+    let caller_stops = req.stop_sequences.clone().unwrap_or_default();
+
+    Ok(std::sync::Arc::new(RequestScope {
+        stop_sequences: crate::request_scope::merge_stop_sequences([
+            agent_stops.as_slice(),
+            caller_stops.as_slice(),
+        ]),
+        received_at: Some(std::time::Instant::now()),
+        priority: req.priority.or(token_priority).unwrap_or_default(),
+        key_environment: crate::key_environments::requested(),
+        principal: crate::principal::requested(),
+        busy: agent.map(|a| std::sync::Arc::new(crate::agent_status::busy(&a.id))),
+        ..Default::default()
+    }))
 }
 
 // ═══════════════════════════════════════════════════════════════════════
 //  System prompt builder (server-side, single source of truth)
 // ═══════════════════════════════════════════════════════════════════════
 
-/// Build system prompt server-side (single source of truth).
-fn build_system_prompt(working_directory: &str, language: &str, custom_instructions: &str) -> String {
+/// Build the global system prompt layer: the built-in swarm prompt plus the
+/// custom instructions from settings.
+fn build_system_prompt(language: &str, custom_instructions: &str) -> String {
     let lang_name = if language == "pl" {
         "Polish"
     } else {
@@ -99,15 +153,6 @@ fn build_system_prompt(working_directory: &str, language: &str, custom_instructi
         "## Task Completion".to_string(),
         "At the END of every completed task, add a section '## Co dalej?' with exactly 5 numbered follow-up tasks the user could ask you to do next. Make them specific, actionable, and relevant to the work just completed. Format each as a one-line imperative sentence.".to_string(),
     ];
-    if !working_directory.is_empty() {
-        lines.extend([
-            String::new(),
-            "## Working Directory".to_string(),
-            format!("**Current working directory**: `{}`", working_directory),
-            "You can use relative paths (e.g. `src/main.rs`) — they resolve against this directory.".to_string(),
-            "You do NOT need to specify absolute paths unless referencing files outside this folder.".to_string(),
-        ]);
-    }
     if !custom_instructions.is_empty() {
         lines.extend([
             String::new(),
@@ -123,12 +168,23 @@ fn build_system_prompt(working_directory: &str, language: &str, custom_instructi
 // ═══════════════════════════════════════════════════════════════════════
 
 /// Resolves model, max_tokens, session WD (session → global fallback).
+/// With an agent selected, its model, temperature and max_tokens apply
+/// where the request leaves them out.
 pub(crate) async fn resolve_chat_context(
     state: &AppState,
     req: &crate::models::ChatRequest,
 ) -> ChatContext {
+    let agent = match req.agent_id.as_deref().filter(|id| !id.is_empty()) {
+        Some(id) => state.agents.read().await.iter().find(|a| a.id == id).cloned(),
+        None => None,
+    };
+
     let model = if let Some(ref m) = req.model {
+        crate::request_scope::decide("model", json!({ "rule": "caller", "model": m }));
         m.clone()
+    } else if let Some(a) = agent.as_ref().filter(|a| !a.model.is_empty()) {
+        crate::request_scope::decide("model", json!({ "rule": "agent", "agent_id": a.id, "model": a.model }));
+        a.model.clone()
     } else {
         let prompt_text: String = req
             .messages
@@ -138,11 +194,16 @@ pub(crate) async fn resolve_chat_context(
             .map(|m| m.content.as_str())
             .collect();
         let complexity = crate::model_registry::classify_complexity(&prompt_text);
-        match complexity {
+        let model = match complexity {
             "simple" => crate::model_registry::get_model_id(state, "coordinator").await,
             "complex" => crate::model_registry::get_model_id(state, "commander").await,
             _ => crate::model_registry::get_model_id(state, "commander").await,
-        }
+        };
+        crate::request_scope::decide(
+            "model",
+            json!({ "rule": "complexity", "complexity": complexity, "model": model }),
+        );
+        model
     };
 
     // A/B testing: read ab_model_b + ab_split from settings
@@ -160,6 +221,10 @@ pub(crate) async fn resolve_chat_context(
                     model_b,
                     split * 100.0
                 );
+                crate::request_scope::decide(
+                    "model",
+                    json!({ "rule": "ab_test", "replaced": model, "model": model_b, "split": split }),
+                );
                 model_b
             } else {
                 model
@@ -174,17 +239,19 @@ pub(crate) async fn resolve_chat_context(
         .as_deref()
         .and_then(|s| uuid::Uuid::parse_str(s).ok());
 
-    // Single query: fetch session WD, global WD, language, generation params, and custom instructions
-    let (working_directory, language, db_temperature, db_max_tokens, db_max_iterations, custom_instructions) =
+    // Single query: fetch session WD, global WD, language, generation params, custom instructions
+    // and the prompt layering policy
+    let (working_directory, language, db_temperature, db_max_tokens, db_max_iterations, custom_instructions, layering) =
         if let Some(ref sid) = session_uuid {
-            let row: Option<(String, String, String, f64, i32, i32, String)> = sqlx::query_as(
+            let row: Option<(String, String, String, f64, i32, i32, String, Option<Value>)> = sqlx::query_as(
                 "SELECT COALESCE(s.working_directory, '') AS session_wd, \
              COALESCE(g.working_directory, '') AS global_wd, \
              COALESCE(g.language, 'en') AS language, \
              COALESCE(g.temperature, 0.7) AS temperature, \
              COALESCE(g.max_tokens, 4096) AS max_tokens, \
              COALESCE(g.max_iterations, 10) AS max_iterations, \
-             COALESCE(g.custom_instructions, '') AS custom_instructions \
+             COALESCE(g.custom_instructions, '') AS custom_instructions, \
+             g.prompt_layering \
              FROM ch_sessions s \
              CROSS JOIN ch_settings g \
              WHERE s.id = $1 AND g.id = 1",
@@ -195,48 +262,65 @@ pub(crate) async fn resolve_chat_context(
             .ok()
             .flatten();
             match row {
-                Some((session_wd, global_wd, lang, temp, mtok, miter, ci)) => {
+                Some((session_wd, global_wd, lang, temp, mtok, miter, ci, layering)) => {
                     let wd = if !session_wd.is_empty() {
                         session_wd
                     } else {
                         global_wd
                     };
-                    (wd, lang, temp, mtok, miter, ci)
+                    (wd, lang, temp, mtok, miter, ci, layering)
                 }
-                None => (String::new(), "en".to_string(), 0.7, 4096, 10, String::new()),
+                None => (String::new(), "en".to_string(), 0.7, 4096, 10, String::new(), None),
             }
         } else {
-            let row: Option<(String, String, f64, i32, i32, String)> = sqlx::query_as(
+            let row: Option<(String, String, f64, i32, i32, String, Option<Value>)> = sqlx::query_as(
                 "SELECT COALESCE(working_directory, ''), COALESCE(language, 'en'), \
              COALESCE(temperature, 0.7), COALESCE(max_tokens, 4096), COALESCE(max_iterations, 10), \
-             COALESCE(custom_instructions, '') \
+             COALESCE(custom_instructions, ''), prompt_layering \
              FROM ch_settings WHERE id = 1",
             )
             .fetch_optional(&state.db)
             .await
             .ok()
             .flatten();
-            row.unwrap_or(("".to_string(), "en".to_string(), 0.7, 4096, 10, String::new()))
+            row.unwrap_or(("".to_string(), "en".to_string(), 0.7, 4096, 10, String::new(), None))
         };
 
+    // Prompt canary: a share of requests gets the edited instructions
+    let custom_instructions =
+        crate::prompt_canary::pick(crate::prompt_canary::INSTRUCTIONS).unwrap_or(custom_instructions);
+
     let budget = tier_token_budget(&model);
-    let max_tokens = req.max_tokens.unwrap_or(db_max_tokens as u32).min(budget);
-    let temperature = req.temperature.unwrap_or(db_temperature);
+    let requested_max_tokens = req
+        .max_tokens
+        .or(agent.as_ref().and_then(|a| a.max_tokens))
+        .unwrap_or(db_max_tokens as u32);
+    let max_tokens = requested_max_tokens.min(budget);
+    if max_tokens < requested_max_tokens {
+        crate::request_scope::decide(
+            "max_tokens",
+            json!({ "requested": requested_max_tokens, "capped_to": max_tokens, "rule": "tier_budget" }),
+        );
+    }
+    let temperature = req
+        .temperature
+        .or(agent.as_ref().and_then(|a| a.temperature))
+        .unwrap_or(db_temperature);
 
-    // Use cached system prompt if available (cache key includes custom_instructions hash)
+    // Use cached global layer if available (cache key includes custom_instructions hash)
     let ci_hash = {
         use std::hash::{Hash, Hasher};
         let mut h = std::collections::hash_map::DefaultHasher::new();
         custom_instructions.hash(&mut h);
         h.finish()
     };
-    let cache_key = format!("{}:{}:{}", working_directory, language, ci_hash);
-    let system_prompt = {
+    let cache_key = format!("{}:{}", language, ci_hash);
+    let global_prompt = {
         let cache = state.prompt_cache.read().await;
         cache.get(&cache_key).cloned()
     }
     .unwrap_or_else(|| {
-        let prompt = build_system_prompt(&working_directory, &language, &custom_instructions);
+        let prompt = build_system_prompt(&language, &custom_instructions);
         let prompt_clone = prompt.clone();
         let state_clone = state.prompt_cache.clone();
         let key_clone = cache_key;
@@ -246,6 +330,46 @@ pub(crate) async fn resolve_chat_context(
         prompt
     });
 
+    let agent_prompt = agent
+        .as_ref()
+        .map(|a| match crate::prompt_canary::pick(&crate::prompt_canary::agent_target(&a.id)) {
+            Some(description) => crate::prompt_layers::agent_layer(&crate::models::WitcherAgent {
+                description,
+                ..a.clone()
+            }),
+            None => crate::prompt_layers::agent_layer(a),
+        })
+        .unwrap_or_default();
+    let agent_prompt_empty = agent_prompt.is_empty();
+    let prompt_layering = crate::prompt_layers::PromptLayering::from_stored(layering);
+    let assembled = crate::prompt_layers::assemble(
+        &prompt_layering,
+        &[
+            (PromptLayer::Global, global_prompt),
+            (
+                PromptLayer::Project,
+                crate::prompt_layers::project_layer(&working_directory).await,
+            ),
+            (PromptLayer::Agent, agent_prompt),
+            (
+                PromptLayer::Request,
+                crate::prompt_layers::request_layer(&req.messages),
+            ),
+        ],
+    );
+
+    crate::request_scope::decide(
+        "prompt",
+        json!({
+            "agent_id": req.agent_id.as_deref().filter(|id| !id.is_empty()),
+            "agent_prompt": !agent_prompt_empty,
+            "order": prompt_layering.order,
+            "max_chars": prompt_layering.max_chars,
+            "layers": assembled.layers,
+            "truncated": assembled.layers.iter().any(|l| l.truncated_chars > 0),
+        }),
+    );
+
     ChatContext {
         model,
         max_tokens,
@@ -253,7 +377,9 @@ pub(crate) async fn resolve_chat_context(
         max_iterations: db_max_iterations,
         working_directory,
         session_id: session_uuid,
-        system_prompt,
+        system_prompt: assembled.prompt,
+        prompt_layering,
+        prompt_layers: assembled.layers,
     }
 }
 
@@ -283,12 +409,12 @@ pub async fn warm_prompt_cache(state: &AppState) {
     let languages = ["en", "pl"];
     let mut count = 0;
     for lang in &languages {
-        let prompt = build_system_prompt("", lang, &custom_instructions);
+        let prompt = build_system_prompt(lang, &custom_instructions);
         state
             .prompt_cache
             .write()
             .await
-            .insert(format!(":{}:{}", lang, ci_hash), prompt);
+            .insert(format!("{}:{}", lang, ci_hash), prompt);
         count += 1;
     }
     tracing::info!("prompt_cache: pre-warmed {} system prompt variants", count);
@@ -0,0 +1,649 @@
+//! Session-bound chat — the server owns the conversation history.
+//!
+//! - `POST /api/sessions/{id}/chat` — one user turn. The message array is the
+//!   session's stored history plus `content`; after the provider answers, the
+//!   user message and the reply are inserted in one transaction. Nothing is
+//!   stored when the call fails, so a retry never leaves a dangling user turn.
+//! - `POST /api/sessions/{id}/chat/stream` — the same over NDJSON, through the
+//!   regular streaming pipeline (tools, Gemini, resumable `X-Stream-Id`). The
+//!   stream is drained server-side, so both messages are stored when it ends
+//!   even if the client went away; a last `{"persisted": {…}}` frame follows
+//!   the `done` frame with the stored message ids.
+//!
+//! - `POST /api/sessions/{id}/messages/{mid}/regenerate` — the "retry"
+//!   button: answer the turn before assistant message `mid` again (optionally
+//!   with another model / temperature), then replace `mid` and everything
+//!   after it with the new reply. The removed messages are kept in a
+//!   `Before regenerate` snapshot.
+//!
+//! Messages summarized by compaction are not sent; their pinned summary is.
+//! Before sending, the context is checked against the model's window (see
+//! `token_count`): 413 when it cannot fit, a warning when it is close.
+//! Each stored turn may trigger automatic compaction (see `compaction`).
+//!
+//! This replaces calling `/api/claude/chat` and then
+//! `POST /api/sessions/{id}/messages` twice from the client.
+
+use axum::body::{Body, Bytes};
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::Response;
+use axum::{Extension, Json};
+use futures_util::StreamExt;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use utoipa::ToSchema;
+
+use crate::models::{ChatMessage, ChatRequest, ChatResponse};
+use crate::priority::{DefaultPriority, Priority};
+use crate::request_scope::DecisionLog;
+use crate::providers::{Anthropic, Gemini, Provider, ProviderRequest};
+use crate::session_presence::{AttributedMessage, MESSAGE_COLUMNS, SessionEvent};
+use crate::state::AppState;
+
+use super::MAX_MESSAGE_LENGTH;
+use super::prompt::{resolve_chat_context, resolve_request_scope};
+
+/// Most recent stored messages sent as context.
+const MAX_HISTORY_MESSAGES: i64 = 200;
+
+type ApiError = (StatusCode, Json<Value>);
+
+fn api_error(status: StatusCode, msg: impl Into<String>) -> ApiError {
+    (status, Json(json!({ "error": msg.into() })))
+}
+
+fn db_error(e: sqlx::Error) -> ApiError {
+    tracing::error!("session_chat: database error: {}", e);
+    api_error(StatusCode::INTERNAL_SERVER_ERROR, "Database error")
+}
+
+/// Request body for `POST /api/sessions/{id}/chat` and `/chat/stream`.
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct SessionChatRequest {
+    /// The new user message.
+    pub content: String,
+    pub model: Option<String>,
+    pub temperature: Option<f64>,
+    pub max_tokens: Option<u32>,
+    /// Run the tool loop (streaming endpoint only).
+    #[serde(default)]
+    pub tools_enabled: Option<bool>,
+    /// Generate as this agent; stored on the reply.
+    #[serde(default)]
+    pub agent_id: Option<String>,
+    #[serde(default)]
+    pub stop_sequences: Option<Vec<String>>,
+    #[serde(default)]
+    pub priority: Option<crate::priority::Priority>,
+}
+
+fn chat_message(role: &str, content: String) -> ChatMessage {
+    ChatMessage {
+        role: role.to_string(),
+        content,
+        attachments: Vec::new(),
+        model: None,
+        timestamp: None,
+    }
+}
+
+/// Stored history as provider messages: user / assistant turns only, starting
+/// at the first user message (a leading assistant greeting is dropped).
+fn history_messages(rows: Vec<(String, String)>) -> Vec<ChatMessage> {
+    rows.into_iter()
+        .filter(|(role, content)| (role == "user" || role == "assistant") && !content.trim().is_empty())
+        .skip_while(|(role, _)| role != "user")
+        .map(|(role, content)| chat_message(&role, content))
+        .collect()
+}
+
+/// Decision-log entry for the history loaded from the session.
+fn history_decision(rows: usize, summaries: usize) -> Value {
+    json!({
+        "source": "session",
+        "messages": rows,
+        "summaries": summaries,
+        "limit": MAX_HISTORY_MESSAGES,
+        "truncated": rows as i64 >= MAX_HISTORY_MESSAGES,
+    })
+}
+
+/// Validate the turn and build the `ChatRequest` from the stored history,
+/// with the history decision for the request's log.
+async fn build_request(
+    state: &AppState,
+    session_id: uuid::Uuid,
+    req: &SessionChatRequest,
+) -> Result<(ChatRequest, Value), ApiError> {
+    let content = req.content.trim();
+    if content.is_empty() {
+        return Err(api_error(StatusCode::BAD_REQUEST, "content is required"));
+    }
+    if req.content.len() > MAX_MESSAGE_LENGTH {
+        return Err(api_error(StatusCode::BAD_REQUEST, "content is too long"));
+    }
+    let exists = sqlx::query("SELECT 1 FROM ch_sessions WHERE id = $1")
+        .bind(session_id)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(db_error)?;
+    if exists.is_none() {
+        return Err(api_error(StatusCode::NOT_FOUND, "Session not found"));
+    }
+    let rows: Vec<(String, String)> = sqlx::query_as(
+        "SELECT role, content FROM ( \
+             SELECT role, content, created_at FROM ch_messages \
+             WHERE session_id = $1 AND compacted_at IS NULL \
+             ORDER BY created_at DESC LIMIT $2 \
+         ) recent ORDER BY created_at ASC",
+    )
+    .bind(session_id)
+    .bind(MAX_HISTORY_MESSAGES)
+    .fetch_all(&state.db)
+    .await
+    .map_err(db_error)?;
+
+    let mut messages = crate::compaction::summary_messages(&state.db, session_id)
+        .await
+        .map_err(db_error)?;
+    let history = history_decision(rows.len(), messages.len());
+    messages.extend(history_messages(rows));
+    messages.push(chat_message("user", req.content.clone()));
+    let chat_req = ChatRequest {
+        messages,
+        model: req.model.clone(),
+        temperature: req.temperature,
+        max_tokens: req.max_tokens,
+        stream: None,
+        tools_enabled: req.tools_enabled,
+        session_id: Some(session_id.to_string()),
+        agent_id: req.agent_id.clone(),
+        stop_sequences: req.stop_sequences.clone(),
+        priority: req.priority,
+    };
+    Ok((chat_req, history))
+}
+
+/// Insert the user message and the reply in one transaction, then announce
+/// them like any other appended message.
+async fn persist_turn(
+    state: &AppState,
+    session_id: uuid::Uuid,
+    user_content: &str,
+    reply: &str,
+    model: &str,
+    agent: Option<&str>,
+    decisions: Option<&DecisionLog>,
+) -> Result<(AttributedMessage, AttributedMessage), sqlx::Error> {
+    let mut tx = state.db.begin().await?;
+    // clock_timestamp(), not NOW(): both rows share one transaction and must
+    // still sort user → assistant.
+    let user = sqlx::query_as::<_, AttributedMessage>(&format!(
+        "INSERT INTO ch_messages (session_id, role, content, created_at) \
+         VALUES ($1, 'user', $2, clock_timestamp()) RETURNING {}",
+        MESSAGE_COLUMNS
+    ))
+    .bind(session_id)
+    .bind(user_content)
+    .fetch_one(&mut *tx)
+    .await?;
+    let assistant = sqlx::query_as::<_, AttributedMessage>(&format!(
+        "INSERT INTO ch_messages (session_id, role, content, model, agent, created_at) \
+         VALUES ($1, 'assistant', $2, $3, $4, clock_timestamp()) RETURNING {}",
+        MESSAGE_COLUMNS
+    ))
+    .bind(session_id)
+    .bind(reply)
+    .bind(model)
+    .bind(agent)
+    .fetch_one(&mut *tx)
+    .await?;
+    sqlx::query("UPDATE ch_sessions SET updated_at = NOW() WHERE id = $1")
+        .bind(session_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    for message in [&user, &assistant] {
+        crate::slack::mirror(state, session_id, &message.role, &message.content, None);
+        state.presence.publish(
+            session_id,
+            SessionEvent::MessageAdded {
+                message: json!(message),
+            },
+        );
+    }
+    if let Some(decisions) = decisions {
+        store_context(state, assistant.id, decisions).await;
+    }
+    crate::artifacts::store_from_message(&state.db, assistant.id, session_id, reply).await;
+    crate::compaction::schedule(state.clone(), session_id);
+    Ok((user, assistant))
+}
+
+/// Store the request the reply was generated from, with its decision log.
+async fn store_context(state: &AppState, message_id: uuid::Uuid, decisions: &DecisionLog) {
+    if let Some(request) = decisions.request() {
+        let entries = Value::Array(decisions.entries());
+        crate::message_context::store(&state.db, message_id, None, &request, &entries).await;
+    }
+}
+
+/// One non-streaming provider call for `chat_req`; an empty reply is an
+/// error. Returns the reply with the request's decision log.
+async fn complete(
+    state: &AppState,
+    chat_req: &ChatRequest,
+    history: Value,
+    token_priority: Option<Priority>,
+    source: &'static str,
+) -> Result<(ChatResponse, DecisionLog), ApiError> {
+    let scope = resolve_request_scope(state, chat_req, token_priority).await?;
+    let decisions = scope.decisions.clone();
+    decisions.record("history", history);
+    let ctx = crate::request_scope::run(scope.clone(), resolve_chat_context(state, chat_req)).await;
+    let provider_req = ProviderRequest::from_context(ctx, chat_req.messages.clone());
+    let completion = if provider_req.model.starts_with("gemini-") {
+        crate::request_scope::run(scope, Gemini.chat(state, &provider_req)).await?
+    } else {
+        crate::request_scope::run(scope, Anthropic.chat(state, &provider_req)).await?
+    };
+
+    let session_id = chat_req.session_id.as_deref().and_then(|id| uuid::Uuid::parse_str(id).ok());
+    let response = completion.into_attributed_response(state, source, session_id, chat_req.agent_id.clone());
+    if response.message.content.trim().is_empty() {
+        return Err(api_error(StatusCode::BAD_GATEWAY, "The model returned an empty reply"));
+    }
+    Ok((response, decisions))
+}
+
+#[utoipa::path(post, path = "/api/sessions/{id}/chat", tag = "sessions",
+    params(("id" = String, Path, description = "Session UUID")),
+    request_body = SessionChatRequest,
+    responses(
+        (status = 200, description = "Stored user message and reply"),
+        (status = 400, description = "Invalid id, empty or over-long content, or tools_enabled"),
+        (status = 404, description = "Session or agent not found"),
+        (status = 413, description = "Context does not fit the model's window"),
+        (status = 502, description = "Provider call failed (nothing stored)")
+    ))]
+pub async fn session_chat(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    token_priority: Option<Extension<DefaultPriority>>,
+    Json(req): Json<SessionChatRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let session_id: uuid::Uuid = id
+        .parse()
+        .map_err(|_| api_error(StatusCode::BAD_REQUEST, "Invalid session id"))?;
+    if req.tools_enabled.unwrap_or(false) {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            "tools_enabled is only supported on /chat/stream",
+        ));
+    }
+    let (chat_req, history) = build_request(&state, session_id, &req).await?;
+    let context = crate::token_count::preflight(&state, &chat_req).await?;
+    let (response, decisions) = complete(
+        &state,
+        &chat_req,
+        history,
+        token_priority.map(|Extension(DefaultPriority(p))| p),
+        "session_chat",
+    )
+    .await?;
+    let (user, assistant) = persist_turn(
+        &state,
+        session_id,
+        &req.content,
+        &response.message.content,
+        &response.model,
+        req.agent_id.as_deref(),
+        Some(&decisions),
+    )
+    .await
+    .map_err(db_error)?;
+
+    Ok(Json(json!({
+        "session_id": session_id,
+        "user_message": user,
+        "message": assistant,
+        "model": response.model,
+        "usage": response.usage,
+        "refusal": response.refusal,
+        "context_warning": context.warning(),
+        "warnings": response.warnings,
+        "decisions": decisions.entries(),
+    })))
+}
+
+/// Request body for `POST /api/sessions/{id}/messages/{mid}/regenerate`;
+/// every field is optional.
+#[derive(Debug, Clone, Default, Deserialize, ToSchema)]
+#[serde(default)]
+pub struct RegenerateRequest {
+    /// Defaults to the model of the reply being replaced.
+    pub model: Option<String>,
+    pub temperature: Option<f64>,
+    pub max_tokens: Option<u32>,
+    /// Defaults to the agent of the reply being replaced.
+    pub agent_id: Option<String>,
+    pub stop_sequences: Option<Vec<String>>,
+    pub priority: Option<Priority>,
+}
+
+/// The reply being regenerated.
+#[derive(sqlx::FromRow)]
+struct RegenerateTarget {
+    role: String,
+    model: Option<String>,
+    agent: Option<String>,
+    compacted: bool,
+    created_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[utoipa::path(post, path = "/api/sessions/{id}/messages/{mid}/regenerate", tag = "sessions",
+    params(
+        ("id" = String, Path, description = "Session UUID"),
+        ("mid" = String, Path, description = "Assistant message UUID")
+    ),
+    request_body(content = RegenerateRequest, description = "Optional overrides"),
+    responses(
+        (status = 200, description = "New reply; the old one and everything after it were removed"),
+        (status = 400, description = "Invalid ids, not an assistant message, or no user turn before it"),
+        (status = 404, description = "Message or agent not found"),
+        (status = 413, description = "Context does not fit the model's window"),
+        (status = 502, description = "Provider call failed (nothing changed)")
+    ))]
+pub async fn regenerate_message(
+    State(state): State<AppState>,
+    Path((id, mid)): Path<(String, String)>,
+    token_priority: Option<Extension<DefaultPriority>>,
+    body: Option<Json<RegenerateRequest>>,
+) -> Result<Json<Value>, ApiError> {
+    let session_id: uuid::Uuid = id
+        .parse()
+        .map_err(|_| api_error(StatusCode::BAD_REQUEST, "Invalid session id"))?;
+    let message_id: uuid::Uuid = mid
+        .parse()
+        .map_err(|_| api_error(StatusCode::BAD_REQUEST, "Invalid message id"))?;
+    let req = body.map(|Json(b)| b).unwrap_or_default();
+
+    let target = sqlx::query_as::<_, RegenerateTarget>(
+        "SELECT role, model, agent, compacted_at IS NOT NULL AS compacted, created_at \
+         FROM ch_messages WHERE id = $1 AND session_id = $2",
+    )
+    .bind(message_id)
+    .bind(session_id)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(db_error)?
+    .ok_or_else(|| api_error(StatusCode::NOT_FOUND, "Message not found"))?;
+    if target.role != "assistant" {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            "Only assistant messages can be regenerated",
+        ));
+    }
+    if target.compacted {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            "This message has been compacted into a summary",
+        ));
+    }
+
+    let rows: Vec<(String, String)> = sqlx::query_as(
+        "SELECT role, content FROM ( \
+             SELECT role, content, created_at FROM ch_messages \
+             WHERE session_id = $1 AND created_at < $2 AND compacted_at IS NULL \
+             ORDER BY created_at DESC LIMIT $3 \
+         ) recent ORDER BY created_at ASC",
+    )
+    .bind(session_id)
+    .bind(target.created_at)
+    .bind(MAX_HISTORY_MESSAGES)
+    .fetch_all(&state.db)
+    .await
+    .map_err(db_error)?;
+    let mut messages = crate::compaction::summary_messages(&state.db, session_id)
+        .await
+        .map_err(db_error)?;
+    let history = history_decision(rows.len(), messages.len());
+    messages.extend(history_messages(rows));
+    if messages.last().map(|m| m.role.as_str()) != Some("user") {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            "No user message precedes this reply",
+        ));
+    }
+
+    let agent_id = req.agent_id.or(target.agent);
+    let chat_req = ChatRequest {
+        messages,
+        model: req.model.or(target.model).filter(|m| !m.is_empty()),
+        temperature: req.temperature,
+        max_tokens: req.max_tokens,
+        stream: None,
+        tools_enabled: None,
+        session_id: Some(session_id.to_string()),
+        agent_id: agent_id.clone(),
+        stop_sequences: req.stop_sequences,
+        priority: req.priority,
+    };
+    let context = crate::token_count::preflight(&state, &chat_req).await?;
+    let (response, decisions) = complete(
+        &state,
+        &chat_req,
+        history,
+        token_priority.map(|Extension(DefaultPriority(p))| p),
+        "session_regenerate",
+    )
+    .await?;
+
+    // Truncate and insert in one transaction, so a failure leaves the old
+    // reply in place; the snapshot keeps what was removed.
+    let mut tx = state.db.begin().await.map_err(db_error)?;
+    let snapshot = super::snapshots::take_snapshot(&mut tx, session_id, "Before regenerate")
+        .await
+        .map_err(db_error)?;
+    let removed = sqlx::query("DELETE FROM ch_messages WHERE session_id = $1 AND created_at >= $2")
+        .bind(session_id)
+        .bind(target.created_at)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?
+        .rows_affected();
+    let message = sqlx::query_as::<_, AttributedMessage>(&format!(
+        "INSERT INTO ch_messages (session_id, role, content, model, agent) \
+         VALUES ($1, 'assistant', $2, $3, $4) RETURNING {}",
+        MESSAGE_COLUMNS
+    ))
+    .bind(session_id)
+    .bind(&response.message.content)
+    .bind(&response.model)
+    .bind(agent_id.as_deref())
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(db_error)?;
+    sqlx::query("UPDATE ch_sessions SET updated_at = NOW() WHERE id = $1")
+        .bind(session_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+    tx.commit().await.map_err(db_error)?;
+
+    state.presence.publish(
+        session_id,
+        SessionEvent::HistoryTruncated {
+            from_message_id: message_id,
+        },
+    );
+    state.presence.publish(
+        session_id,
+        SessionEvent::MessageAdded {
+            message: json!(message),
+        },
+    );
+    store_context(&state, message.id, &decisions).await;
+    crate::artifacts::store_from_message(&state.db, message.id, session_id, &message.content).await;
+
+    Ok(Json(json!({
+        "session_id": session_id,
+        "replaced_message_id": message_id,
+        "removed_messages": removed,
+        "snapshot_id": snapshot.id,
+        "message": message,
+        "model": response.model,
+        "usage": response.usage,
+        "refusal": response.refusal,
+        "context_warning": context.warning(),
+        "warnings": response.warnings,
+        "decisions": decisions.entries(),
+    })))
+}
+
+/// What a finished NDJSON stream produced.
+#[derive(Debug, Default, PartialEq)]
+struct StreamedReply {
+    text: String,
+    model: Option<String>,
+    done: bool,
+}
+
+impl StreamedReply {
+    /// Fold one NDJSON frame into the reply.
+    fn push_frame(&mut self, frame: &Value) {
+        if let Some(token) = frame.get("token").and_then(|t| t.as_str()) {
+            self.text.push_str(token);
+        }
+        if frame.get("done").and_then(|d| d.as_bool()) == Some(true) {
+            self.done = true;
+            if let Some(model) = frame.get("model").and_then(|m| m.as_str()) {
+                self.model = Some(model.to_string());
+            }
+        }
+    }
+}
+
+#[utoipa::path(post, path = "/api/sessions/{id}/chat/stream", tag = "sessions",
+    params(("id" = String, Path, description = "Session UUID")),
+    request_body = SessionChatRequest,
+    responses(
+        (status = 200, description = "NDJSON stream; both messages are stored when it ends"),
+        (status = 400, description = "Invalid id, empty or over-long content"),
+        (status = 404, description = "Session or agent not found"),
+        (status = 413, description = "Context does not fit the model's window")
+    ))]
+pub async fn session_chat_stream(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    token_priority: Option<Extension<DefaultPriority>>,
+    Json(req): Json<SessionChatRequest>,
+) -> Result<Response, ApiError> {
+    let session_id: uuid::Uuid = id
+        .parse()
+        .map_err(|_| api_error(StatusCode::BAD_REQUEST, "Invalid session id"))?;
+    let (chat_req, _history) = build_request(&state, session_id, &req).await?;
+    let context = crate::token_count::preflight(&state, &chat_req).await?;
+    let mut response = super::claude_chat_stream(State(state.clone()), token_priority, Json(chat_req)).await?;
+    if !response.status().is_success() {
+        return Ok(response);
+    }
+    if let Some(warning) = context.warning()
+        && let Ok(value) = axum::http::HeaderValue::from_str(&warning)
+    {
+        response.headers_mut().insert(crate::token_count::WARNING_HEADER, value);
+    }
+
+    // Drain the stream in a task of its own: the client gets every chunk as
+    // it arrives, and the turn is stored even if the client disconnects.
+    let (parts, body) = response.into_parts();
+    let (tx, rx) = tokio::sync::mpsc::channel::<Result<Bytes, std::io::Error>>(64);
+    tokio::spawn(async move {
+        let mut body = body.into_data_stream();
+        let mut reply = StreamedReply::default();
+        let mut partial: Vec<u8> = Vec::new();
+        let mut client_gone = false;
+        while let Some(chunk) = body.next().await {
+            let Ok(chunk) = chunk else { break };
+            partial.extend_from_slice(&chunk);
+            while let Some(nl) = partial.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = partial.drain(..=nl).collect();
+                if let Ok(frame) = serde_json::from_slice::<Value>(line.trim_ascii()) {
+                    reply.push_frame(&frame);
+                }
+            }
+            if !client_gone && tx.send(Ok(chunk)).await.is_err() {
+                client_gone = true;
+            }
+        }
+
+        if reply.text.trim().is_empty() {
+            tracing::warn!("session_chat: stream for {} produced no reply; nothing stored", session_id);
+            return;
+        }
+        let model = reply.model.clone().unwrap_or_default();
+        let persisted = match persist_turn(
+            &state,
+            session_id,
+            &req.content,
+            &reply.text,
+            &model,
+            req.agent_id.as_deref(),
+            None,
+        )
+        .await
+        {
+            Ok((user, assistant)) => json!({
+                "persisted": { "user_message_id": user.id, "message_id": assistant.id }
+            }),
+            Err(e) => {
+                tracing::error!("session_chat: failed to store turn for {}: {}", session_id, e);
+                json!({ "persisted": null, "error": "Failed to store the messages" })
+            }
+        };
+        if !client_gone {
+            let _ = tx.send(Ok(Bytes::from(format!("{}\n", persisted)))).await;
+        }
+    });
+
+    let stream = futures_util::stream::unfold(rx, |mut rx| async move { rx.recv().await.map(|item| (item, rx)) });
+    Ok(Response::from_parts(parts, Body::from_stream(stream)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn history_starts_at_the_first_user_turn() {
+        let rows = vec![
+            ("assistant".to_string(), "Welcome!".to_string()),
+            ("user".to_string(), "Hi".to_string()),
+            ("system".to_string(), "note".to_string()),
+            ("assistant".to_string(), "  ".to_string()),
+            ("assistant".to_string(), "Hello".to_string()),
+        ];
+        let messages = history_messages(rows);
+        let turns: Vec<(&str, &str)> = messages.iter().map(|m| (m.role.as_str(), m.content.as_str())).collect();
+        assert_eq!(turns, vec![("user", "Hi"), ("assistant", "Hello")]);
+    }
+
+    #[test]
+    fn streamed_frames_fold_into_the_reply() {
+        let mut reply = StreamedReply::default();
+        reply.push_frame(&json!({ "token": "Hel", "done": false }));
+        reply.push_frame(&json!({ "type": "tool_call", "name": "read_file" }));
+        reply.push_frame(&json!({ "token": "lo", "done": false }));
+        reply.push_frame(&json!({ "token": "", "done": true, "model": "claude-sonnet-4-6" }));
+        assert_eq!(
+            reply,
+            StreamedReply {
+                text: "Hello".to_string(),
+                model: Some("claude-sonnet-4-6".to_string()),
+                done: true,
+            }
+        );
+    }
+}
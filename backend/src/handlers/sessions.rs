@@ -4,21 +4,35 @@
 //! ClaudeHydra keeps local overrides for `get_session` and `add_session_message`
 //! because they include `ch_tool_interactions` joins and inserts — a feature
 //! specific to Claude's tool-use protocol that other Hydras don't have.
+//! `GET /api/sessions/list` is a paginated, searchable alternative to the
+//! shared `GET /api/sessions`, which always returns every session.
+//! `PATCH /api/sessions/{id}/metadata` updates title, tags, pinned and archived
+//! together (the shared `PATCH /api/sessions/{id}` only renames).
+//! `GET /api/sessions/{id}/export` downloads the full transcript (JSON / Markdown),
+//! streamed in batches of messages.
+//! `POST` / `DELETE /api/sessions/bulk-delete` removes many sessions by id or
+//! filter (the shared router owns `/api/sessions` itself).
 
 use axum::Json;
+use axum::body::{Body, Bytes};
 use axum::extract::{Path, Query, State};
-use axum::http::StatusCode;
+use axum::http::{StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use serde::Deserialize;
 use serde_json::{Value, json};
 
 use crate::models::*;
+use crate::session_transcript::{self, TranscriptFormat};
 use crate::state::AppState;
 
 use super::MAX_MESSAGE_LENGTH;
 
+/// Size limit of a message's serialized `parts` (inline images included).
+const MAX_PARTS_BYTES: usize = 8 * 1024 * 1024;
+
 // ── Re-export shared session types ───────────────────────────────────────────
 // These are used by lib.rs OpenAPI derive and route registration.
 pub use jaskier_core::sessions::{
-    PaginationParams,
     // Shared handlers — wired via turbofish in lib.rs routes.
     list_sessions, create_session, update_session, delete_session,
     update_session_working_directory, generate_session_title,
@@ -29,29 +43,55 @@ pub use jaskier_core::sessions::{
 //  LOCAL OVERRIDE — shared version lacks tool_interactions join
 // ═══════════════════════════════════════════════════════════════════════
 
+/// Query parameters for `GET /api/sessions/{id}`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SessionDetailParams {
+    /// Messages per page (default 200, max 500).
+    pub limit: Option<i64>,
+    /// Newest messages to skip (default 0). Ignored with `before`.
+    pub offset: Option<i64>,
+    /// Message id: page through the messages older than it (the
+    /// `next_before` of the previous page). Unlike `offset`, stays stable
+    /// while new messages arrive and is cheap deep into long sessions.
+    pub before: Option<uuid::Uuid>,
+}
+
 #[utoipa::path(get, path = "/api/sessions/{id}", tag = "sessions",
-    params(("id" = String, Path, description = "Session UUID")),
-    responses((status = 200, description = "Session with messages")))]
+    params(
+        ("id" = String, Path, description = "Session UUID"),
+        ("limit" = Option<i64>, Query, description = "Messages per page (default 200, max 500)"),
+        ("offset" = Option<i64>, Query, description = "Newest messages to skip"),
+        ("before" = Option<String>, Query, description = "Message id: return the messages older than it")
+    ),
+    responses(
+        (status = 200, description = "Session with one page of messages"),
+        (status = 400, description = "Invalid session or message id"),
+        (status = 404, description = "Session not found")
+    ))]
 pub async fn get_session(
     State(state): State<AppState>,
     Path(id): Path<String>,
-    Query(params): Query<PaginationParams>,
+    Query(params): Query<SessionDetailParams>,
 ) -> Result<Json<Value>, StatusCode> {
     let session_id: uuid::Uuid = id.parse().map_err(|_| StatusCode::BAD_REQUEST)?;
-    let msg_limit = params.limit.unwrap_or(200).clamp(1, 500);
-    let msg_offset = params.offset.unwrap_or(0).max(0);
+    session_detail(&state, session_id, &params).await.map(Json)
+}
 
-    let session_row = sqlx::query_as::<_, SessionRow>(
-        "SELECT id, title, created_at, updated_at, working_directory FROM ch_sessions WHERE id = $1",
-    )
-    .bind(session_id)
-    .fetch_optional(&state.db)
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to get session: {}", e);
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?
-    .ok_or(StatusCode::NOT_FOUND)?;
+/// Session JSON with one page of messages (the newest `limit` after skipping
+/// `offset`, or before the `before` message; returned oldest first) and
+/// their tool interactions.
+async fn session_detail(
+    state: &AppState,
+    session_id: uuid::Uuid,
+    params: &SessionDetailParams,
+) -> Result<Value, StatusCode> {
+    let msg_limit = params.limit.unwrap_or(200).clamp(1, 500);
+    let msg_offset = if params.before.is_some() {
+        0
+    } else {
+        params.offset.unwrap_or(0).max(0)
+    };
+    let session_row = session_row(state, session_id).await?;
 
     let total_messages: i64 =
         sqlx::query_scalar("SELECT COUNT(*) FROM ch_messages WHERE session_id = $1")
@@ -63,24 +103,109 @@ pub async fn get_session(
                 StatusCode::INTERNAL_SERVER_ERROR
             })?;
 
+    let cursor = match params.before {
+        Some(before) => {
+            let found = sqlx::query_as::<_, (uuid::Uuid, chrono::DateTime<chrono::Utc>)>(
+                "SELECT session_id, created_at FROM ch_messages WHERE id = $1",
+            )
+            .bind(before)
+            .fetch_optional(&state.db)
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to look up message cursor: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+            Some(message_cursor(session_id, before, found)?)
+        }
+        None => None,
+    };
+
+    // One row more than the page, to tell whether older messages remain.
     let message_rows = sqlx::query_as::<_, MessageRow>(
-        "SELECT * FROM (\
-            SELECT id, session_id, role, content, model, agent, created_at \
-            FROM ch_messages WHERE session_id = $1 \
-            ORDER BY created_at DESC LIMIT $2 OFFSET $3\
-        ) sub ORDER BY created_at ASC",
+        "SELECT id, session_id, role, content, model, agent, created_at, parts \
+         FROM ch_messages WHERE session_id = $1 \
+           AND ($4::timestamptz IS NULL OR (created_at, id) < ($4, $5)) \
+         ORDER BY created_at DESC, id DESC LIMIT $2 OFFSET $3",
     )
     .bind(session_id)
-    .bind(msg_limit)
+    .bind(msg_limit + 1)
     .bind(msg_offset)
+    .bind(cursor.map(|c| c.0))
+    .bind(cursor.map(|c| c.1))
     .fetch_all(&state.db)
     .await
     .map_err(|e| {
         tracing::error!("Failed to get session messages: {}", e);
         StatusCode::INTERNAL_SERVER_ERROR
     })?;
+    let (message_rows, next_before) = message_page(message_rows, msg_limit);
+
+    let messages = history_entries(&state.db, message_rows).await;
+    let truncated = (messages.len() as i64) < total_messages;
 
-    let message_ids: Vec<uuid::Uuid> = message_rows.iter().map(|m| m.id).collect();
+    Ok(json!({
+        "id": session_row.id.to_string(),
+        "title": session_row.title,
+        "created_at": session_row.created_at.to_rfc3339(),
+        "working_directory": session_row.working_directory,
+        "input_tokens": session_row.input_tokens,
+        "output_tokens": session_row.output_tokens,
+        "cost_usd": session_row.cost_usd,
+        "messages": serde_json::to_value(&messages).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+        "messages_truncated": truncated,
+        "pagination": {
+            "total": total_messages,
+            "limit": msg_limit,
+            "offset": msg_offset,
+            "before": params.before,
+            "next_before": next_before,
+        }
+    }))
+}
+
+/// The `before` cursor as `(created_at, id)`, given the cursor message's
+/// session and timestamp if it exists. A message of another session, or one
+/// deleted since, is a bad request.
+fn message_cursor(
+    session_id: uuid::Uuid,
+    before: uuid::Uuid,
+    found: Option<(uuid::Uuid, chrono::DateTime<chrono::Utc>)>,
+) -> Result<(chrono::DateTime<chrono::Utc>, uuid::Uuid), StatusCode> {
+    match found {
+        Some((owner, created_at)) if owner == session_id => Ok((created_at, before)),
+        _ => Err(StatusCode::BAD_REQUEST),
+    }
+}
+
+/// One page out of `rows`, fetched newest first with one row past `limit`:
+/// the page oldest first, and the `next_before` cursor if older messages
+/// remain.
+fn message_page(mut rows: Vec<MessageRow>, limit: i64) -> (Vec<MessageRow>, Option<uuid::Uuid>) {
+    let has_older = rows.len() as i64 > limit;
+    rows.truncate(limit as usize);
+    rows.reverse();
+    let next_before = if has_older { rows.first().map(|m| m.id) } else { None };
+    (rows, next_before)
+}
+
+async fn session_row(state: &AppState, session_id: uuid::Uuid) -> Result<SessionRow, StatusCode> {
+    sqlx::query_as::<_, SessionRow>(
+        "SELECT id, title, created_at, updated_at, working_directory, input_tokens, output_tokens, cost_usd \
+         FROM ch_sessions WHERE id = $1",
+    )
+    .bind(session_id)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to get session: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?
+    .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// `rows` as history entries, with their tool interactions.
+async fn history_entries(db: &sqlx::PgPool, rows: Vec<MessageRow>) -> Vec<HistoryEntry> {
+    let message_ids: Vec<uuid::Uuid> = rows.iter().map(|m| m.id).collect();
     let ti_rows = if message_ids.is_empty() {
         Vec::new()
     } else {
@@ -92,7 +217,7 @@ pub async fn get_session(
              ORDER BY ti.executed_at ASC",
         )
         .bind(&message_ids)
-        .fetch_all(&state.db)
+        .fetch_all(db)
         .await
         .unwrap_or_default()
     };
@@ -112,33 +237,659 @@ pub async fn get_session(
             });
     }
 
-    let messages: Vec<HistoryEntry> = message_rows
-        .into_iter()
+    rows.into_iter()
         .map(|m| {
             let interactions = ti_map.remove(&m.id);
             HistoryEntry {
                 id: m.id.to_string(),
                 role: m.role,
                 content: m.content,
+                parts: MessagePart::from_stored(m.parts),
                 model: m.model,
                 agent: m.agent,
                 timestamp: m.created_at.to_rfc3339(),
                 tool_interactions: interactions,
             }
         })
-        .collect();
+        .collect()
+}
 
-    Ok(Json(json!({
+// ═══════════════════════════════════════════════════════════════════════
+//  Export session (downloadable JSON / Markdown transcript)
+// ═══════════════════════════════════════════════════════════════════════
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    #[default]
+    Json,
+    Markdown,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ExportParams {
+    #[serde(default)]
+    pub format: ExportFormat,
+}
+
+/// Download filename: a slug of the title plus the session's creation date.
+fn export_filename(title: &str, created_at: &str, ext: &str) -> String {
+    let mut slug = String::new();
+    for c in title.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+        if slug.len() >= 60 {
+            break;
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    let slug = if slug.is_empty() { "session" } else { slug };
+    let date = created_at.get(..10).unwrap_or("");
+    if date.is_empty() {
+        format!("{}.{}", slug, ext)
+    } else {
+        format!("{}-{}.{}", slug, date, ext)
+    }
+}
+
+#[utoipa::path(get, path = "/api/sessions/{id}/export", tag = "sessions",
+    params(
+        ("id" = String, Path, description = "Session UUID"),
+        ("format" = Option<ExportFormat>, Query, description = "json (default) | markdown")
+    ),
+    responses(
+        (status = 200, description = "Full transcript as a file download"),
+        (status = 400, description = "Invalid session id or format"),
+        (status = 404, description = "Session not found")
+    ))]
+pub async fn export_session(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(params): Query<ExportParams>,
+) -> Result<Response, StatusCode> {
+    let session_id: uuid::Uuid = id.parse().map_err(|_| StatusCode::BAD_REQUEST)?;
+    let session_row = session_row(&state, session_id).await?;
+    let tags: Vec<String> =
+        sqlx::query_scalar("SELECT tag FROM ch_session_tags WHERE session_id = $1 ORDER BY tag ASC")
+            .bind(session_id)
+            .fetch_all(&state.db)
+            .await
+            .unwrap_or_default();
+    // Diagrams / display math in replies, linked to their SVG renders.
+    let artifacts = crate::artifacts::by_message(&state.db, session_id).await;
+    // Hash chain links, so the transcript can be verified later.
+    let links = crate::message_chain::links_by_message(&state.db, session_id).await;
+
+    let session = json!({
         "id": session_row.id.to_string(),
         "title": session_row.title,
         "created_at": session_row.created_at.to_rfc3339(),
         "working_directory": session_row.working_directory,
-        "messages": serde_json::to_value(&messages).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
-        "pagination": {
-            "total": total_messages,
-            "limit": msg_limit,
-            "offset": msg_offset,
+        "input_tokens": session_row.input_tokens,
+        "output_tokens": session_row.output_tokens,
+        "cost_usd": session_row.cost_usd,
+        "tags": tags,
+        "exported_at": chrono::Utc::now().to_rfc3339(),
+    });
+    let title = session["title"].as_str().unwrap_or("");
+    let created_at = session["created_at"].as_str().unwrap_or("");
+    let (content_type, filename) = match params.format {
+        ExportFormat::Json => ("application/json", export_filename(title, created_at, "json")),
+        ExportFormat::Markdown => ("text/markdown; charset=utf-8", export_filename(title, created_at, "md")),
+    };
+    let body = export_stream(state.db.clone(), session_id, session, artifacts, links, params.format);
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", filename)),
+        ],
+        Body::from_stream(body),
+    )
+        .into_response())
+}
+
+/// Messages read (and written out) at a time by the export.
+const EXPORT_BATCH: i64 = 250;
+
+/// Writes the JSON export piecewise: the session fields one per line, then
+/// one message per line inside `messages` (the last field).
+#[derive(Debug, Default)]
+struct JsonExportWriter {
+    wrote_message: bool,
+}
+
+impl JsonExportWriter {
+    /// Opening of the export, up to and including the `messages` bracket.
+    fn head(session: &Value) -> String {
+        let mut head = String::from("{");
+        if let Some(fields) = session.as_object() {
+            for (key, value) in fields {
+                if key != "messages" {
+                    head.push_str(&format!("\n  {}: {},", Value::from(key.as_str()), value));
+                }
+            }
+        }
+        head.push_str("\n  \"messages\": [");
+        head
+    }
+
+    fn message(&mut self, out: &mut String, msg: &Value) {
+        out.push_str(if self.wrote_message { ",\n    " } else { "\n    " });
+        out.push_str(&msg.to_string());
+        self.wrote_message = true;
+    }
+
+    /// Closes `messages` and the session object.
+    fn tail(&self) -> &'static str {
+        if self.wrote_message { "\n  ]\n}\n" } else { "]\n}\n" }
+    }
+}
+
+/// The export, written out batch by batch so that a session of any length
+/// never sits in memory as a whole. JSON keeps one message per line, inside
+/// the session object's `messages` (last field). A database error cuts the
+/// download short.
+fn export_stream(
+    db: sqlx::PgPool,
+    session_id: uuid::Uuid,
+    session: Value,
+    mut artifacts: std::collections::HashMap<String, Vec<Value>>,
+    mut links: std::collections::HashMap<String, (i64, String)>,
+    format: ExportFormat,
+) -> impl futures_util::Stream<Item = Result<Bytes, std::io::Error>> {
+    async_stream::stream! {
+        let head = match format {
+            ExportFormat::Json => JsonExportWriter::head(&session),
+            ExportFormat::Markdown => session_transcript::render_header(&session, TranscriptFormat::Markdown),
+        };
+        yield Ok(Bytes::from(head));
+
+        let mut cursor: Option<(chrono::DateTime<chrono::Utc>, uuid::Uuid)> = None;
+        let mut json_out = JsonExportWriter::default();
+        loop {
+            let rows = sqlx::query_as::<_, MessageRow>(
+                "SELECT id, session_id, role, content, model, agent, created_at, parts \
+                 FROM ch_messages WHERE session_id = $1 \
+                   AND ($3::timestamptz IS NULL OR (created_at, id) > ($3, $4)) \
+                 ORDER BY created_at ASC, id ASC LIMIT $2",
+            )
+            .bind(session_id)
+            .bind(EXPORT_BATCH)
+            .bind(cursor.map(|c| c.0))
+            .bind(cursor.map(|c| c.1))
+            .fetch_all(&db)
+            .await;
+            let rows = match rows {
+                Ok(rows) => rows,
+                Err(e) => {
+                    tracing::error!("Session export query failed: {}", e);
+                    yield Err(std::io::Error::other("session export failed"));
+                    return;
+                }
+            };
+            let Some(last) = rows.last() else {
+                break;
+            };
+            cursor = Some((last.created_at, last.id));
+            let done = (rows.len() as i64) < EXPORT_BATCH;
+
+            let mut chunk = String::new();
+            for entry in history_entries(&db, rows).await {
+                let mut msg = serde_json::to_value(&entry).unwrap_or_default();
+                if let Some(obj) = msg.as_object_mut() {
+                    if let Some(found) = artifacts.remove(&entry.id) {
+                        obj.insert("artifacts".to_string(), json!(found));
+                    }
+                    if let Some((seq, hash)) = links.remove(&entry.id) {
+                        obj.insert("chain_seq".to_string(), json!(seq));
+                        obj.insert("chain_hash".to_string(), json!(hash));
+                    }
+                }
+                match format {
+                    ExportFormat::Json => json_out.message(&mut chunk, &msg),
+                    ExportFormat::Markdown => {
+                        chunk.push_str(&session_transcript::render_message(&msg, TranscriptFormat::Markdown));
+                    }
+                }
+            }
+            yield Ok(Bytes::from(chunk));
+            if done {
+                break;
+            }
+        }
+
+        if let ExportFormat::Json = format {
+            yield Ok(Bytes::from(json_out.tail()));
         }
+    }
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  List sessions (paginated, title search, sortable)
+// ═══════════════════════════════════════════════════════════════════════
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionSort {
+    CreatedAt,
+    #[default]
+    UpdatedAt,
+    /// Most (or least) expensive conversations first.
+    CostUsd,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+/// Query parameters for `GET /api/sessions/list`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SessionListParams {
+    /// Page size (default 50, max 200).
+    pub limit: Option<i64>,
+    /// Rows to skip (default 0).
+    pub offset: Option<i64>,
+    /// Case-insensitive substring of the title.
+    pub q: Option<String>,
+    #[serde(default)]
+    pub sort: SessionSort,
+    #[serde(default)]
+    pub order: SortOrder,
+    /// `true` lists only archived sessions; archived ones are hidden by default.
+    #[serde(default)]
+    pub archived: bool,
+}
+
+/// Escape `LIKE` wildcards so `q` matches literally.
+fn like_pattern(q: &str) -> String {
+    let escaped = q.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+#[utoipa::path(get, path = "/api/sessions/list", tag = "sessions",
+    params(
+        ("limit" = Option<i64>, Query, description = "Page size (default 50, max 200)"),
+        ("offset" = Option<i64>, Query, description = "Rows to skip"),
+        ("q" = Option<String>, Query, description = "Title substring (case-insensitive)"),
+        ("sort" = Option<SessionSort>, Query, description = "created_at | updated_at (default)"),
+        ("order" = Option<SortOrder>, Query, description = "asc | desc (default)"),
+        ("archived" = Option<bool>, Query, description = "List archived sessions instead (default false)")
+    ),
+    responses(
+        (status = 200, description = "Page of session summaries with the total match count"),
+        (status = 400, description = "Invalid query parameters")
+    ))]
+pub async fn list_sessions_page(
+    State(state): State<AppState>,
+    Query(params): Query<SessionListParams>,
+) -> Result<Json<Value>, StatusCode> {
+    let limit = params.limit.unwrap_or(50).clamp(1, 200);
+    let offset = params.offset.unwrap_or(0).max(0);
+    let pattern = params
+        .q
+        .as_deref()
+        .map(str::trim)
+        .filter(|q| !q.is_empty())
+        .map(like_pattern);
+    // Both come from closed enums — never from the raw query string.
+    let column = match params.sort {
+        SessionSort::CreatedAt => "created_at",
+        SessionSort::UpdatedAt => "updated_at",
+        SessionSort::CostUsd => "cost_usd",
+    };
+    let direction = match params.order {
+        SortOrder::Asc => "ASC",
+        SortOrder::Desc => "DESC",
+    };
+    let filter = "($1::TEXT IS NULL OR s.title ILIKE $1 ESCAPE '\\') AND s.archived = $2";
+
+    let total: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM ch_sessions s WHERE {}", filter))
+        .bind(&pattern)
+        .bind(params.archived)
+        .fetch_one(&state.db)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to count sessions: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    type Row = (
+        uuid::Uuid,
+        String,
+        chrono::DateTime<chrono::Utc>,
+        chrono::DateTime<chrono::Utc>,
+        String,
+        bool,
+        bool,
+        i64,
+        i64,
+        f64,
+        i64,
+    );
+    // Pinned sessions lead every page.
+    let rows = sqlx::query_as::<_, Row>(
+        &format!(
+            "SELECT s.id, s.title, s.created_at, s.updated_at, s.working_directory, s.pinned, s.archived, \
+                 s.input_tokens, s.output_tokens, s.cost_usd, \
+                 (SELECT COUNT(*) FROM ch_messages m WHERE m.session_id = s.id) AS message_count \
+             FROM ch_sessions s WHERE {} \
+             ORDER BY s.pinned DESC, s.{} {}, s.id {} LIMIT $3 OFFSET $4",
+            filter, column, direction, direction
+        ),
+    )
+    .bind(&pattern)
+    .bind(params.archived)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(&state.db)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to list sessions: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let sessions: Vec<Value> = rows
+        .into_iter()
+        .map(|(id, title, created_at, updated_at, working_directory, pinned, archived, input_tokens, output_tokens, cost_usd, message_count)| {
+            json!({
+                "id": id.to_string(),
+                "title": title,
+                "created_at": created_at.to_rfc3339(),
+                "updated_at": updated_at.to_rfc3339(),
+                "message_count": message_count,
+                "working_directory": working_directory,
+                "pinned": pinned,
+                "archived": archived,
+                "input_tokens": input_tokens,
+                "output_tokens": output_tokens,
+                "cost_usd": cost_usd,
+            })
+        })
+        .collect();
+
+    Ok(Json(json!({
+        "sessions": sessions,
+        "total": total,
+        "limit": limit,
+        "offset": offset,
+    })))
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  Update session metadata (title, tags, pinned, archived)
+// ═══════════════════════════════════════════════════════════════════════
+
+const MAX_TITLE_CHARS: usize = 200;
+
+/// Body of `PATCH /api/sessions/{id}/metadata`. Omitted fields are unchanged;
+/// `tags` replaces the session's whole tag set.
+#[derive(Debug, Clone, Default, Deserialize, utoipa::ToSchema)]
+pub struct UpdateSessionMetadataRequest {
+    pub title: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub pinned: Option<bool>,
+    pub archived: Option<bool>,
+}
+
+impl UpdateSessionMetadataRequest {
+    fn is_empty(&self) -> bool {
+        self.title.is_none() && self.tags.is_none() && self.pinned.is_none() && self.archived.is_none()
+    }
+}
+
+/// Validated title: trimmed, non-empty, at most `MAX_TITLE_CHARS`.
+fn clean_title(title: &str) -> Option<String> {
+    let title = title.trim();
+    (!title.is_empty() && title.chars().count() <= MAX_TITLE_CHARS).then(|| title.to_string())
+}
+
+#[utoipa::path(patch, path = "/api/sessions/{id}/metadata", tag = "sessions",
+    params(("id" = String, Path, description = "Session UUID")),
+    request_body = UpdateSessionMetadataRequest,
+    responses(
+        (status = 200, description = "Updated session metadata"),
+        (status = 400, description = "Empty update, blank or over-long title"),
+        (status = 404, description = "Session not found")
+    ))]
+pub async fn update_session_metadata(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(req): Json<UpdateSessionMetadataRequest>,
+) -> Result<Json<Value>, StatusCode> {
+    let session_id: uuid::Uuid = id.parse().map_err(|_| StatusCode::BAD_REQUEST)?;
+    if req.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let title = match req.title.as_deref() {
+        Some(t) => Some(clean_title(t).ok_or(StatusCode::BAD_REQUEST)?),
+        None => None,
+    };
+    let db_err = |e: sqlx::Error| {
+        tracing::error!("Failed to update session metadata: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    let mut tx = state.db.begin().await.map_err(db_err)?;
+    let row = sqlx::query_as::<_, (String, bool, bool, chrono::DateTime<chrono::Utc>)>(
+        "UPDATE ch_sessions SET title = COALESCE($2, title), pinned = COALESCE($3, pinned), \
+             archived = COALESCE($4, archived), updated_at = NOW() \
+         WHERE id = $1 RETURNING title, pinned, archived, updated_at",
+    )
+    .bind(session_id)
+    .bind(&title)
+    .bind(req.pinned)
+    .bind(req.archived)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(db_err)?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    if let Some(tags) = req.tags {
+        sqlx::query("DELETE FROM ch_session_tags WHERE session_id = $1")
+            .bind(session_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(db_err)?;
+        for tag in super::tags::normalize_tags(tags) {
+            sqlx::query("INSERT INTO ch_session_tags (session_id, tag) VALUES ($1, $2)")
+                .bind(session_id)
+                .bind(&tag)
+                .execute(&mut *tx)
+                .await
+                .map_err(db_err)?;
+        }
+    }
+    let tags: Vec<String> =
+        sqlx::query_scalar("SELECT tag FROM ch_session_tags WHERE session_id = $1 ORDER BY tag ASC")
+            .bind(session_id)
+            .fetch_all(&mut *tx)
+            .await
+            .map_err(db_err)?;
+    tx.commit().await.map_err(db_err)?;
+
+    let (title, pinned, archived, updated_at) = row;
+    Ok(Json(json!({
+        "id": id,
+        "title": title,
+        "tags": tags,
+        "pinned": pinned,
+        "archived": archived,
+        "updated_at": updated_at.to_rfc3339(),
+    })))
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  Bulk delete (ids and / or filters, "clear all")
+// ═══════════════════════════════════════════════════════════════════════
+
+const MAX_BULK_DELETE_IDS: usize = 1_000;
+
+/// Which sessions a bulk delete removes. Every given filter must match; with
+/// no filter at all nothing is deleted unless `all` is set. Sub-sessions go
+/// with their parent.
+#[derive(Debug, Clone, Default, Deserialize, utoipa::ToSchema)]
+pub struct BulkDeleteFilter {
+    pub archived: Option<bool>,
+    pub pinned: Option<bool>,
+    /// Sessions carrying this tag.
+    pub tag: Option<String>,
+    /// Sessions not updated for at least this many days.
+    pub older_than_days: Option<u32>,
+    /// Required to delete every session when no other filter is given.
+    #[serde(default)]
+    pub all: bool,
+    /// Count what would be deleted without deleting it.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+impl BulkDeleteFilter {
+    fn is_empty(&self) -> bool {
+        self.archived.is_none() && self.pinned.is_none() && self.tag.is_none() && self.older_than_days.is_none()
+    }
+}
+
+/// Body of `POST /api/sessions/bulk-delete`: explicit ids, filters, or both.
+#[derive(Debug, Clone, Default, Deserialize, utoipa::ToSchema)]
+pub struct BulkDeleteRequest {
+    pub ids: Option<Vec<String>>,
+    #[serde(flatten)]
+    pub filter: BulkDeleteFilter,
+}
+
+#[utoipa::path(post, path = "/api/sessions/bulk-delete", tag = "sessions",
+    request_body = BulkDeleteRequest,
+    responses(
+        (status = 200, description = "Counts of matched and deleted sessions"),
+        (status = 400, description = "No ids or filters, invalid id, or too many ids")
+    ))]
+pub async fn bulk_delete_sessions(
+    State(state): State<AppState>,
+    Json(req): Json<BulkDeleteRequest>,
+) -> Result<Json<Value>, StatusCode> {
+    let ids = match req.ids {
+        Some(ids) if ids.len() > MAX_BULK_DELETE_IDS => return Err(StatusCode::BAD_REQUEST),
+        Some(ids) => Some(
+            ids.iter()
+                .map(|id| id.parse::<uuid::Uuid>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| StatusCode::BAD_REQUEST)?,
+        ),
+        None => None,
+    };
+    delete_matching(&state, ids, req.filter).await
+}
+
+#[utoipa::path(delete, path = "/api/sessions/bulk-delete", tag = "sessions",
+    params(
+        ("archived" = Option<bool>, Query, description = "Only archived (true) or unarchived (false) sessions"),
+        ("pinned" = Option<bool>, Query, description = "Only pinned (true) or unpinned (false) sessions"),
+        ("tag" = Option<String>, Query, description = "Only sessions with this tag"),
+        ("older_than_days" = Option<u32>, Query, description = "Only sessions not updated for this many days"),
+        ("all" = Option<bool>, Query, description = "Delete every session (when no other filter is given)"),
+        ("dry_run" = Option<bool>, Query, description = "Count without deleting")
+    ),
+    responses(
+        (status = 200, description = "Counts of matched and deleted sessions"),
+        (status = 400, description = "No filter and no `all=true`")
+    ))]
+pub async fn bulk_delete_sessions_by_filter(
+    State(state): State<AppState>,
+    Query(filter): Query<BulkDeleteFilter>,
+) -> Result<Json<Value>, StatusCode> {
+    delete_matching(&state, None, filter).await
+}
+
+async fn delete_matching(
+    state: &AppState,
+    ids: Option<Vec<uuid::Uuid>>,
+    filter: BulkDeleteFilter,
+) -> Result<Json<Value>, StatusCode> {
+    if ids.is_none() && filter.is_empty() && !filter.all {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let tag = match filter.tag.as_deref() {
+        Some(t) => Some(
+            super::tags::normalize_tags(vec![t.to_string()])
+                .pop()
+                .ok_or(StatusCode::BAD_REQUEST)?,
+        ),
+        None => None,
+    };
+    let days = filter.older_than_days.map(|d| d.min(36_500) as i32);
+
+    // `matched` are the sessions selected; `tree` adds their sub-sessions,
+    // which the parent_id cascade removes with them.
+    let action = if filter.dry_run {
+        "SELECT (SELECT COUNT(*) FROM matched), (SELECT COUNT(*) FROM tree)"
+    } else {
+        "gone AS (DELETE FROM ch_sessions WHERE id IN (SELECT id FROM tree) RETURNING id) \
+         SELECT (SELECT COUNT(*) FROM matched), (SELECT COUNT(*) FROM gone)"
+    };
+    let sql = format!(
+        "WITH RECURSIVE matched AS ( \
+             SELECT s.id FROM ch_sessions s \
+             WHERE ($1::UUID[] IS NULL OR s.id = ANY($1)) \
+             AND ($2::BOOLEAN IS NULL OR s.archived = $2) \
+             AND ($3::BOOLEAN IS NULL OR s.pinned = $3) \
+             AND ($4::TEXT IS NULL OR EXISTS \
+                 (SELECT 1 FROM ch_session_tags t WHERE t.session_id = s.id AND t.tag = $4)) \
+             AND ($5::INT IS NULL OR s.updated_at < NOW() - make_interval(days => $5)) \
+         ), tree AS ( \
+             SELECT id FROM matched \
+             UNION SELECT c.id FROM ch_sessions c JOIN tree ON c.parent_id = tree.id \
+         ){} {}",
+        if filter.dry_run { "" } else { "," },
+        action
+    );
+    let (matched, deleted) = sqlx::query_as::<_, (i64, i64)>(&sql)
+        .bind(&ids)
+        .bind(filter.archived)
+        .bind(filter.pinned)
+        .bind(&tag)
+        .bind(days)
+        .fetch_one(&state.db)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to bulk delete sessions: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let not_found = ids.as_ref().map_or(0, |ids| {
+        let unique: std::collections::HashSet<_> = ids.iter().collect();
+        (unique.len() as i64 - matched).max(0)
+    });
+    if !filter.dry_run && deleted > 0 {
+        crate::audit::log_audit(
+            &state.db,
+            "bulk_delete_sessions",
+            json!({
+                "ids": ids.as_ref().map(Vec::len),
+                "archived": filter.archived,
+                "pinned": filter.pinned,
+                "tag": tag,
+                "older_than_days": days,
+                "all": filter.all,
+                "deleted": deleted,
+            }),
+            None,
+        )
+        .await;
+    }
+    Ok(Json(json!({
+        "matched": matched,
+        "deleted": if filter.dry_run { 0 } else { deleted },
+        "sub_sessions": (deleted - matched).max(0),
+        "not_found": not_found,
+        "dry_run": filter.dry_run,
     })))
 }
 
@@ -154,8 +905,11 @@ pub async fn get_session(
 pub async fn add_session_message(
     State(state): State<AppState>,
     Path(id): Path<String>,
-    Json(req): Json<AddMessageRequest>,
+    Json(mut req): Json<AddMessageRequest>,
 ) -> Result<(StatusCode, Json<Value>), StatusCode> {
+    if req.content.is_empty() {
+        req.content = MessagePart::joined_text(&req.parts);
+    }
     if req.content.len() > MAX_MESSAGE_LENGTH {
         tracing::warn!(
             "add_session_message: content exceeds {} chars (got {})",
@@ -164,6 +918,14 @@ pub async fn add_session_message(
         );
         return Err(StatusCode::BAD_REQUEST);
     }
+    let parts = (!req.parts.is_empty())
+        .then(|| serde_json::to_value(&req.parts))
+        .transpose()
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    if parts.as_ref().is_some_and(|p| p.to_string().len() > MAX_PARTS_BYTES) {
+        tracing::warn!("add_session_message: parts exceed {} bytes", MAX_PARTS_BYTES);
+        return Err(StatusCode::BAD_REQUEST);
+    }
 
     let session_id: uuid::Uuid = id.parse().map_err(|_| StatusCode::BAD_REQUEST)?;
 
@@ -181,15 +943,16 @@ pub async fn add_session_message(
     }
 
     let row = sqlx::query_as::<_, MessageRow>(
-        "INSERT INTO ch_messages (session_id, role, content, model, agent) \
-         VALUES ($1, $2, $3, $4, $5) \
-         RETURNING id, session_id, role, content, model, agent, created_at",
+        "INSERT INTO ch_messages (session_id, role, content, model, agent, parts) \
+         VALUES ($1, $2, $3, $4, $5, $6) \
+         RETURNING id, session_id, role, content, model, agent, created_at, parts",
     )
     .bind(session_id)
     .bind(&req.role)
     .bind(&req.content)
     .bind(&req.model)
     .bind(&req.agent)
+    .bind(&parts)
     .fetch_one(&state.db)
     .await
     .map_err(|e| {
@@ -229,6 +992,7 @@ pub async fn add_session_message(
         id: row.id.to_string(),
         role: row.role,
         content: row.content,
+        parts: req.parts,
         model: row.model,
         agent: row.agent,
         timestamp: row.created_at.to_rfc3339(),
@@ -240,3 +1004,172 @@ pub async fn add_session_message(
         Json(serde_json::to_value(entry).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?),
     ))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn title_search_escapes_like_wildcards() {
+        assert_eq!(like_pattern("rust"), "%rust%");
+        assert_eq!(like_pattern("100%_done"), "%100\\%\\_done%");
+        assert_eq!(like_pattern("a\\b"), "%a\\\\b%");
+    }
+
+    #[test]
+    fn metadata_titles_are_trimmed_and_bounded() {
+        assert_eq!(clean_title("  Rust help \n").as_deref(), Some("Rust help"));
+        assert_eq!(clean_title("   "), None);
+        assert_eq!(clean_title(&"x".repeat(MAX_TITLE_CHARS + 1)), None);
+        assert!(UpdateSessionMetadataRequest::default().is_empty());
+    }
+
+    #[test]
+    fn message_parts_round_trip_and_skip_unknown_kinds() {
+        let stored = json!([
+            { "type": "thinking", "thinking": "The user wants…" },
+            { "type": "text", "text": "Here is the file." },
+            { "type": "tool_call", "id": "toolu_1", "name": "read_file", "input": { "path": "a.rs" } },
+            { "type": "tool_result", "tool_use_id": "toolu_1", "content": "fn main() {}" },
+            { "type": "image", "source": { "type": "url", "url": "https://example.com/a.png" } },
+            { "type": "hologram", "data": "…" },
+            { "type": "text", "text": "Done." }
+        ]);
+        let parts = MessagePart::from_stored(Some(stored));
+        assert_eq!(parts.len(), 6);
+        assert_eq!(MessagePart::joined_text(&parts), "Here is the file.\n\nDone.");
+        let again = MessagePart::from_stored(Some(serde_json::to_value(&parts).unwrap()));
+        assert_eq!(again, parts);
+        assert!(MessagePart::from_stored(None).is_empty());
+    }
+
+    #[test]
+    fn bulk_delete_needs_a_filter_or_all() {
+        assert!(BulkDeleteFilter::default().is_empty());
+        let req: BulkDeleteRequest =
+            serde_json::from_value(json!({ "archived": true, "dry_run": true })).unwrap();
+        assert!(req.ids.is_none());
+        assert!(!req.filter.is_empty());
+        assert!(req.filter.dry_run);
+        let all: BulkDeleteRequest = serde_json::from_value(json!({ "all": true })).unwrap();
+        assert!(all.filter.is_empty() && all.filter.all);
+    }
+
+    #[test]
+    fn export_filenames_are_slugged_and_dated() {
+        assert_eq!(
+            export_filename("Rust: async / await?", "2026-10-01T10:00:00+00:00", "md"),
+            "rust-async-await-2026-10-01.md"
+        );
+        assert_eq!(export_filename("Żółw", "", "json"), "session.json");
+    }
+
+    fn message_row(session_id: uuid::Uuid, n: i64) -> MessageRow {
+        MessageRow {
+            id: uuid::Uuid::from_u128(n as u128),
+            session_id,
+            role: if n % 2 == 0 { "user" } else { "assistant" }.to_string(),
+            content: format!("message {} with \"quotes\" and }} braces", n),
+            model: None,
+            agent: None,
+            created_at: chrono::DateTime::from_timestamp(1_700_000_000 + n, 0).unwrap(),
+            parts: None,
+        }
+    }
+
+    #[test]
+    fn before_cursor_must_belong_to_the_session() {
+        let session = uuid::Uuid::from_u128(1);
+        let before = uuid::Uuid::from_u128(7);
+        let at = chrono::Utc::now();
+        assert_eq!(message_cursor(session, before, Some((session, at))), Ok((at, before)));
+        let other = uuid::Uuid::from_u128(2);
+        assert_eq!(message_cursor(session, before, Some((other, at))), Err(StatusCode::BAD_REQUEST));
+        assert_eq!(message_cursor(session, before, None), Err(StatusCode::BAD_REQUEST));
+    }
+
+    #[test]
+    fn message_pages_walk_back_with_next_before() {
+        let session = uuid::Uuid::from_u128(1);
+        // Newest first, as fetched: messages 10..=1, limit 4 (+1 row).
+        let fetch = |older_than: i64| -> Vec<MessageRow> {
+            (1..older_than).rev().take(5).map(|n| message_row(session, n)).collect()
+        };
+
+        let (page, next) = message_page(fetch(11), 4);
+        let ids: Vec<u128> = page.iter().map(|m| m.id.as_u128()).collect();
+        assert_eq!(ids, [7, 8, 9, 10]);
+        assert_eq!(next, Some(uuid::Uuid::from_u128(7)));
+
+        let (page, next) = message_page(fetch(7), 4);
+        let ids: Vec<u128> = page.iter().map(|m| m.id.as_u128()).collect();
+        assert_eq!(ids, [3, 4, 5, 6]);
+        assert_eq!(next, Some(uuid::Uuid::from_u128(3)));
+
+        // Last page: fewer rows than the limit, nothing older.
+        let (page, next) = message_page(fetch(3), 4);
+        let ids: Vec<u128> = page.iter().map(|m| m.id.as_u128()).collect();
+        assert_eq!(ids, [1, 2]);
+        assert_eq!(next, None);
+
+        // Exactly `limit` rows left: no extra row, so no cursor either.
+        let (page, next) = message_page(fetch(5), 4);
+        assert_eq!(page.len(), 4);
+        assert_eq!(next, None);
+
+        let (page, next) = message_page(Vec::new(), 4);
+        assert!(page.is_empty() && next.is_none());
+    }
+
+    /// The JSON export as `export_stream` writes it, in `EXPORT_BATCH` chunks.
+    fn json_export(session: &Value, messages: usize) -> String {
+        let mut writer = JsonExportWriter::default();
+        let mut out = JsonExportWriter::head(session);
+        let rows: Vec<Value> = (0..messages as u128)
+            .map(|n| {
+                json!({
+                    "id": uuid::Uuid::from_u128(n).to_string(),
+                    "role": "user",
+                    "content": format!("message {} with \"quotes\", ] and }} braces\n", n),
+                    "timestamp": "2026-10-01T10:00:00+00:00",
+                })
+            })
+            .collect();
+        for batch in rows.chunks(EXPORT_BATCH as usize) {
+            let mut chunk = String::new();
+            for msg in batch {
+                writer.message(&mut chunk, msg);
+            }
+            out.push_str(&chunk);
+        }
+        out.push_str(writer.tail());
+        out
+    }
+
+    #[test]
+    fn json_export_is_valid_for_any_message_count() {
+        let session = json!({
+            "id": "00000000-0000-0000-0000-000000000001",
+            "title": "Braces } and \"quotes\"\n",
+            "created_at": "2026-10-01T10:00:00+00:00",
+            "tags": ["rust", "}"],
+            "cost_usd": 0.25,
+            "working_directory": null,
+        });
+        for count in [0, 1, 2, EXPORT_BATCH as usize, EXPORT_BATCH as usize + 1, 600] {
+            let parsed: Value = serde_json::from_str(&json_export(&session, count))
+                .unwrap_or_else(|e| panic!("{} messages: {}", count, e));
+            assert_eq!(parsed["title"], session["title"]);
+            assert_eq!(parsed["tags"], session["tags"]);
+            assert!(parsed["working_directory"].is_null());
+            let messages = parsed["messages"].as_array().unwrap();
+            assert_eq!(messages.len(), count);
+            if count > 0 {
+                assert_eq!(messages[count - 1]["id"], uuid::Uuid::from_u128(count as u128 - 1).to_string());
+            }
+        }
+        // Not an object (never happens): still a well-formed document.
+        let parsed: Value = serde_json::from_str(&json_export(&Value::Null, 1)).unwrap();
+        assert_eq!(parsed["messages"].as_array().unwrap().len(), 1);
+    }
+}
@@ -1,7 +1,7 @@
 //! Application settings endpoints (DB-backed).
 
 use axum::Json;
-use axum::extract::State;
+use axum::extract::{Path, Query, State};
 use axum::http::StatusCode;
 use serde_json::{Value, json};
 
@@ -24,7 +24,10 @@ pub async fn get_settings(State(state): State<AppState>) -> Result<Json<Value>,
          COALESCE(auto_updater, TRUE) AS auto_updater, \
          COALESCE(telemetry, FALSE) AS telemetry, \
          COALESCE(compaction_threshold, 25) AS compaction_threshold, \
-         COALESCE(compaction_keep, 15) AS compaction_keep \
+         COALESCE(compaction_keep, 15) AS compaction_keep, \
+         COALESCE(refusal_retry, FALSE) AS refusal_retry, \
+         COALESCE(max_continuations, 2) AS max_continuations, \
+         prompt_layering, http_client, endpoint_policies \
          FROM ch_settings WHERE id = 1",
     )
     .fetch_one(&state.db)
@@ -49,6 +52,17 @@ pub async fn get_settings(State(state): State<AppState>) -> Result<Json<Value>,
         telemetry: row.telemetry,
         compaction_threshold: row.compaction_threshold,
         compaction_keep: row.compaction_keep,
+        refusal_retry: row.refusal_retry,
+        max_continuations: row.max_continuations,
+        prompt_layering: crate::prompt_layers::PromptLayering::from_stored(row.prompt_layering),
+        http: {
+            // The proxy password is never sent back (see update_settings).
+            let mut http = crate::http_client::HttpSettings::from_stored(row.http_client);
+            http.proxy_url = http.proxy_url.as_deref().map(crate::http_client::redact_proxy_url);
+            http
+        },
+        policies: crate::endpoint_policies::EndpointPolicies::from_stored(row.endpoint_policies),
+        tier_models: crate::tier_models::all(),
     };
 
     Ok(Json(
@@ -65,7 +79,7 @@ pub async fn get_settings(State(state): State<AppState>) -> Result<Json<Value>,
     responses((status = 200, description = "Updated settings")))]
 pub async fn update_settings(
     State(state): State<AppState>,
-    Json(new_settings): Json<AppSettings>,
+    Json(mut new_settings): Json<AppSettings>,
 ) -> Result<Json<Value>, StatusCode> {
     // Validate working_directory if non-empty
     if !new_settings.working_directory.is_empty()
@@ -73,6 +87,26 @@ pub async fn update_settings(
     {
         return Err(StatusCode::BAD_REQUEST);
     }
+    new_settings.prompt_layering = new_settings.prompt_layering.normalized();
+    new_settings.http = new_settings.http.normalized();
+    new_settings.policies = new_settings.policies.normalized();
+    // Changed only with PUT /api/agents/tiers (which also moves the agents).
+    new_settings.tier_models = crate::tier_models::all();
+    if let Some(ref proxy_url) = new_settings.http.proxy_url {
+        if crate::http_client::validate_proxy_url(proxy_url).is_err() {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        // Settings read back from GET carry the password as `***`.
+        let stored = sqlx::query_scalar::<_, Option<Value>>("SELECT http_client FROM ch_settings WHERE id = 1")
+            .fetch_optional(&state.db)
+            .await
+            .ok()
+            .flatten()
+            .flatten();
+        let stored = crate::http_client::HttpSettings::from_stored(stored).proxy_url;
+        new_settings.http.proxy_url =
+            Some(crate::http_client::restore_proxy_password(proxy_url, stored.as_deref()));
+    }
 
     sqlx::query(
         "UPDATE ch_settings SET theme = $1, language = $2, default_model = $3, \
@@ -80,7 +114,8 @@ pub async fn update_settings(
          temperature = $8, max_tokens = $9, custom_instructions = $10, \
          auto_updater = $11, telemetry = $12, \
          compaction_threshold = $13, compaction_keep = $14, \
-         updated_at = NOW() WHERE id = 1",
+         refusal_retry = $15, max_continuations = $16, prompt_layering = $17, \
+         http_client = $18, endpoint_policies = $19, updated_at = NOW() WHERE id = 1",
     )
     .bind(&new_settings.theme)
     .bind(&new_settings.language)
@@ -96,6 +131,11 @@ pub async fn update_settings(
     .bind(new_settings.telemetry)
     .bind(new_settings.compaction_threshold.clamp(10, 100))
     .bind(new_settings.compaction_keep.clamp(5, 50))
+    .bind(new_settings.refusal_retry)
+    .bind(new_settings.max_continuations.clamp(0, super::MAX_CONTINUATIONS_LIMIT))
+    .bind(serde_json::to_value(&new_settings.prompt_layering).unwrap_or_default())
+    .bind(serde_json::to_value(&new_settings.http).unwrap_or_default())
+    .bind(serde_json::to_value(&new_settings.policies).unwrap_or_default())
     .execute(&state.db)
     .await
     .map_err(|e| {
@@ -103,6 +143,15 @@ pub async fn update_settings(
         StatusCode::INTERNAL_SERVER_ERROR
     })?;
 
+    // Start with the machine when running under systemd / as a Windows service.
+    crate::service::apply_auto_start(new_settings.auto_start);
+    // Rebuild the upstream client here and on the other replicas.
+    crate::http_client::set(&new_settings.http);
+    crate::cluster::publish(&state, crate::cluster::ClusterEvent::HttpClient);
+    crate::endpoint_policies::set(&new_settings.policies);
+    crate::cluster::publish(&state, crate::cluster::ClusterEvent::EndpointPolicies);
+
+    new_settings.http.proxy_url = new_settings.http.proxy_url.as_deref().map(crate::http_client::redact_proxy_url);
     crate::audit::log_audit(
         &state.db,
         "update_settings",
@@ -120,14 +169,270 @@ pub async fn update_settings(
 //  POST /api/settings/api-key
 // ═══════════════════════════════════════════════════════════════════════
 
+fn valid_provider(provider: &str) -> bool {
+    (1..=64).contains(&provider.len())
+        && provider.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-'))
+}
+
+/// Normalized key environment (`default` when absent).
+fn environment_param(environment: Option<&str>) -> Result<String, (StatusCode, Json<Value>)> {
+    match environment {
+        None => Ok(crate::key_environments::DEFAULT_ENVIRONMENT.to_string()),
+        Some(name) => crate::key_environments::normalize(name).ok_or_else(|| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": "environment must be 1-32 of [a-z0-9_-]" })),
+            )
+        }),
+    }
+}
+
+/// Names the startup code also registers a provider's key under.
+fn legacy_key_name(provider: &str) -> Option<&'static str> {
+    match provider {
+        "anthropic" => Some("ANTHROPIC_API_KEY"),
+        "google" => Some("GOOGLE_API_KEY"),
+        _ => None,
+    }
+}
+
+/// Keys are encrypted at rest (see `secrets.rs`); `persisted` is false when
+/// no master key is available and the key only lives until restart. With an
+/// `environment` the key is stored next to the default one (see `key_environments`).
 #[utoipa::path(post, path = "/api/settings/api-key", tag = "auth",
     request_body = ApiKeyRequest,
-    responses((status = 200, description = "API key saved")))]
+    responses(
+        (status = 200, description = "API key saved"),
+        (status = 400, description = "Invalid provider or environment, or empty key")
+    ))]
 pub async fn set_api_key(
     State(state): State<AppState>,
     Json(req): Json<ApiKeyRequest>,
-) -> Json<Value> {
-    let mut rt = state.runtime.write().await;
-    rt.api_keys.insert(req.provider.clone(), req.key);
-    Json(json!({ "status": "ok", "provider": req.provider }))
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    if !valid_provider(&req.provider) || req.key.trim().is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "provider must be 1-64 of [A-Za-z0-9_-] and key non-empty" })),
+        ));
+    }
+    let environment = environment_param(req.environment.as_deref())?;
+    let name = crate::key_environments::key_name(&req.provider, &environment);
+    let persisted = match crate::secrets::store(&state.db, &name, &req.key).await {
+        Ok(()) => true,
+        Err(e) => {
+            tracing::warn!("API key for '{}' kept in memory only: {}", name, e);
+            false
+        }
+    };
+    state.runtime.write().await.api_keys.insert(name, req.key);
+    crate::audit::log_audit(
+        &state.db,
+        "set_api_key",
+        json!({ "provider": req.provider, "environment": environment, "persisted": persisted }),
+        None,
+    )
+    .await;
+    Ok(Json(json!({
+        "status": "ok",
+        "provider": req.provider,
+        "environment": environment,
+        "persisted": persisted,
+    })))
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  GET /api/settings/api-keys
+// ═══════════════════════════════════════════════════════════════════════
+
+#[derive(Debug, serde::Deserialize)]
+pub struct ApiKeysQuery {
+    /// Check each key with a lightweight provider call (default true).
+    #[serde(default)]
+    pub validate: Option<bool>,
+}
+
+/// `sk-ant-api03-…wxyz` → `sk-...wxyz`; short keys are fully hidden.
+fn mask_key(key: &str) -> String {
+    let chars: Vec<char> = key.trim().chars().collect();
+    if chars.len() < 12 {
+        return "****".to_string();
+    }
+    let prefix: String = chars
+        .iter()
+        .take(6)
+        .take_while(|c| **c != '-')
+        .collect();
+    let suffix: String = chars[chars.len() - 4..].iter().collect();
+    if prefix.is_empty() || prefix.len() == 6 {
+        format!("...{}", suffix)
+    } else {
+        format!("{}-...{}", prefix, suffix)
+    }
+}
+
+/// Lightweight authenticated call per provider: `Some(valid)`, or `None` when
+/// the provider is unknown or unreachable.
+async fn validate_key(state: &AppState, provider: &str, key: &str) -> Option<bool> {
+    let client = &state.http_client;
+    let req = match provider {
+        "anthropic" => {
+            let req = client
+                .get(format!("{}/v1/models?limit=1", super::anthropic_api_url()))
+                .header("anthropic-version", "2023-06-01");
+            if key.starts_with("sk-ant-oat") {
+                req.bearer_auth(key)
+            } else {
+                req.header("x-api-key", key)
+            }
+        }
+        "google" => client
+            .get("https://generativelanguage.googleapis.com/v1beta/models?pageSize=1")
+            .header("x-goog-api-key", key),
+        "github" => client
+            .get("https://api.github.com/user")
+            .bearer_auth(key)
+            .header(reqwest::header::USER_AGENT, "ClaudeHydra"),
+        "openai" => client.get("https://api.openai.com/v1/models").bearer_auth(key),
+        "deepseek" => client.get("https://api.deepseek.com/models").bearer_auth(key),
+        "grok" => client.get("https://api.x.ai/v1/models").bearer_auth(key),
+        _ => return None,
+    };
+    let status = req
+        .timeout(std::time::Duration::from_secs(5))
+        .send()
+        .await
+        .ok()?
+        .status();
+    match status.as_u16() {
+        200..=299 => Some(true),
+        401 | 403 => Some(false),
+        _ => None,
+    }
+}
+
+/// Lists configured provider keys, masked, with stored timestamps and an
+/// optional validity check (`valid` is null when unknown).
+#[utoipa::path(get, path = "/api/settings/api-keys", tag = "auth",
+    params(("validate" = Option<bool>, Query, description = "Check each key with the provider (default true)")),
+    responses((status = 200, description = "Configured provider keys, masked")))]
+pub async fn list_api_keys(State(state): State<AppState>, Query(q): Query<ApiKeysQuery>) -> Json<Value> {
+    // Legacy aliases point at the same key as their provider.
+    let keys: Vec<(String, String, String)> = {
+        let rt = state.runtime.read().await;
+        let mut keys: Vec<(String, String, String)> = Vec::new();
+        for (name, key) in rt.api_keys.iter() {
+            let (provider, environment) = crate::key_environments::split_name(name);
+            if !keys.iter().any(|(p, e, _)| p == provider && e == environment) && !key.trim().is_empty() {
+                keys.push((provider.to_string(), environment.to_string(), key.clone()));
+            }
+        }
+        keys.sort();
+        keys
+    };
+    let stored = crate::secrets::timestamps(&state.db).await.unwrap_or_else(|e| {
+        tracing::warn!("Failed to read API key timestamps: {}", e);
+        Default::default()
+    });
+    let validity: Vec<Option<bool>> = if q.validate.unwrap_or(true) {
+        futures_util::future::join_all(keys.iter().map(|(p, _, k)| validate_key(&state, p, k))).await
+    } else {
+        vec![None; keys.len()]
+    };
+
+    let providers: Vec<Value> = keys
+        .iter()
+        .zip(validity)
+        .map(|((provider, environment, key), valid)| {
+            let times = stored.get(&crate::key_environments::key_name(provider, environment));
+            json!({
+                "provider": provider,
+                "environment": environment,
+                "masked_key": mask_key(key),
+                "stored": times.is_some(),
+                "created_at": times.map(|t| t.0),
+                "updated_at": times.map(|t| t.1),
+                "valid": valid,
+            })
+        })
+        .collect();
+    Json(json!({
+        "providers": providers,
+        "persistence": crate::secrets::available(),
+    }))
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  DELETE /api/settings/api-key/{provider}
+// ═══════════════════════════════════════════════════════════════════════
+
+#[derive(Debug, serde::Deserialize)]
+pub struct ApiKeyEnvironmentQuery {
+    pub environment: Option<String>,
+}
+
+/// Removes the stored and in-memory key. A key from an environment variable
+/// comes back on the next restart.
+#[utoipa::path(delete, path = "/api/settings/api-key/{provider}", tag = "auth",
+    params(
+        ("provider" = String, Path, description = "Provider name"),
+        ("environment" = Option<String>, Query, description = "Key environment (default `default`)")
+    ),
+    responses(
+        (status = 204, description = "API key removed"),
+        (status = 400, description = "Invalid provider"),
+        (status = 404, description = "No key for this provider")
+    ))]
+pub async fn delete_api_key(
+    State(state): State<AppState>,
+    Path(provider): Path<String>,
+    Query(q): Query<ApiKeyEnvironmentQuery>,
+) -> Result<StatusCode, (StatusCode, Json<Value>)> {
+    if !valid_provider(&provider) {
+        return Err((StatusCode::BAD_REQUEST, Json(json!({ "error": "Invalid provider" }))));
+    }
+    let environment = environment_param(q.environment.as_deref())?;
+    let name = crate::key_environments::key_name(&provider, &environment);
+    let stored = crate::secrets::delete(&state.db, &name).await.map_err(|e| {
+        tracing::error!("Failed to delete API key for '{}': {}", provider, e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": "Failed to delete API key" })),
+        )
+    })?;
+    let in_memory = {
+        let mut rt = state.runtime.write().await;
+        if name == provider
+            && let Some(legacy) = legacy_key_name(&provider)
+        {
+            rt.api_keys.remove(legacy);
+        }
+        rt.api_keys.remove(&name).is_some()
+    };
+    if !stored && !in_memory {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": format!("No API key for '{}' in environment '{}'", provider, environment) })),
+        ));
+    }
+    crate::audit::log_audit(
+        &state.db,
+        "delete_api_key",
+        json!({ "provider": provider, "environment": environment }),
+        None,
+    )
+    .await;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_are_masked_to_prefix_and_last_four() {
+        assert_eq!(mask_key("sk-ant-REDACTED"), "sk-...wxyz");
+        assert_eq!(mask_key("ghp_1234567890abcdEFGH"), "...EFGH");
+        assert_eq!(mask_key("AIzaSyA-1234567890"), "...7890");
+        assert_eq!(mask_key("short-key"), "****");
+    }
 }
@@ -0,0 +1,232 @@
+//! Session snapshots — restore points for the message list.
+//!
+//! Endpoints:
+//! - `GET  /api/sessions/{id}/snapshots`                 — list snapshots (no payload)
+//! - `POST /api/sessions/{id}/snapshots`                 — capture messages + tool interactions
+//! - `POST /api/sessions/{id}/snapshots/{sid}/restore`   — roll the session back
+//!
+//! Restoring first snapshots the current state ("Before restore: …"), so a
+//! restore can itself be undone.
+
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use utoipa::ToSchema;
+
+use crate::state::AppState;
+
+// ── Request / Response types ────────────────────────────────────────────────
+
+/// Request body for creating a snapshot.
+#[derive(Debug, Clone, Default, Deserialize, ToSchema)]
+pub struct CreateSnapshotRequest {
+    /// Optional human-readable label (default: timestamp).
+    #[serde(default)]
+    pub label: Option<String>,
+}
+
+/// Snapshot metadata as listed by the API.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct SnapshotRow {
+    pub id: uuid::Uuid,
+    pub label: String,
+    pub message_count: i32,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Capture the session's messages (with tool interactions) into a new snapshot.
+pub(crate) async fn take_snapshot(
+    conn: &mut sqlx::PgConnection,
+    session_id: uuid::Uuid,
+    label: &str,
+) -> Result<SnapshotRow, sqlx::Error> {
+    sqlx::query_as::<_, SnapshotRow>(
+        "INSERT INTO ch_session_snapshots (session_id, label, message_count, messages) \
+         SELECT $1, $2, COUNT(*)::INT, COALESCE(jsonb_agg(jsonb_build_object( \
+                    'id', m.id, 'role', m.role, 'content', m.content, \
+                    'model', m.model, 'agent', m.agent, 'created_at', m.created_at, \
+                    'parts', m.parts, \
+                    'tool_interactions', COALESCE(( \
+                        SELECT jsonb_agg(to_jsonb(ti) - 'message_id' ORDER BY ti.executed_at) \
+                        FROM ch_tool_interactions ti WHERE ti.message_id = m.id \
+                    ), '[]'::jsonb) \
+                ) ORDER BY m.created_at), '[]'::jsonb) \
+         FROM ch_messages m WHERE m.session_id = $1 \
+         RETURNING id, label, message_count, created_at",
+    )
+    .bind(session_id)
+    .bind(label)
+    .fetch_one(conn)
+    .await
+}
+
+async fn session_exists(state: &AppState, session_id: uuid::Uuid) -> Result<bool, StatusCode> {
+    sqlx::query("SELECT 1 FROM ch_sessions WHERE id = $1")
+        .bind(session_id)
+        .fetch_optional(&state.db)
+        .await
+        .map(|r| r.is_some())
+        .map_err(|e| {
+            tracing::error!("Failed to check session: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+// ── GET /api/sessions/{id}/snapshots ────────────────────────────────────────
+
+#[utoipa::path(get, path = "/api/sessions/{id}/snapshots", tag = "sessions",
+    params(("id" = String, Path, description = "Session UUID")),
+    responses((status = 200, description = "Snapshots for session")))]
+pub async fn list_snapshots(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, StatusCode> {
+    let session_id: uuid::Uuid = id.parse().map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let rows = sqlx::query_as::<_, SnapshotRow>(
+        "SELECT id, label, message_count, created_at FROM ch_session_snapshots \
+         WHERE session_id = $1 ORDER BY created_at DESC",
+    )
+    .bind(session_id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to list snapshots: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(json!({ "session_id": session_id, "snapshots": rows })))
+}
+
+// ── POST /api/sessions/{id}/snapshots ───────────────────────────────────────
+
+#[utoipa::path(post, path = "/api/sessions/{id}/snapshots", tag = "sessions",
+    params(("id" = String, Path, description = "Session UUID")),
+    request_body = CreateSnapshotRequest,
+    responses((status = 201, description = "Snapshot created")))]
+pub async fn create_snapshot(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    body: Option<Json<CreateSnapshotRequest>>,
+) -> Result<(StatusCode, Json<Value>), StatusCode> {
+    let session_id: uuid::Uuid = id.parse().map_err(|_| StatusCode::BAD_REQUEST)?;
+    let req = body.map(|Json(b)| b).unwrap_or_default();
+    let label = req
+        .label
+        .map(|l| l.trim().chars().take(200).collect::<String>())
+        .filter(|l| !l.is_empty())
+        .unwrap_or_else(|| chrono::Utc::now().format("Snapshot %Y-%m-%d %H:%M:%S").to_string());
+
+    if !session_exists(&state, session_id).await? {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let mut conn = state.db.acquire().await.map_err(|e| {
+        tracing::error!("Failed to acquire connection: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let snapshot = take_snapshot(&mut conn, session_id, &label).await.map_err(|e| {
+        tracing::error!("Failed to create snapshot: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok((StatusCode::CREATED, Json(json!(snapshot))))
+}
+
+// ── POST /api/sessions/{id}/snapshots/{sid}/restore ─────────────────────────
+
+#[utoipa::path(post, path = "/api/sessions/{id}/snapshots/{sid}/restore", tag = "sessions",
+    params(
+        ("id" = String, Path, description = "Session UUID"),
+        ("sid" = String, Path, description = "Snapshot UUID")
+    ),
+    responses(
+        (status = 200, description = "Session restored"),
+        (status = 404, description = "Snapshot not found")
+    ))]
+pub async fn restore_snapshot(
+    State(state): State<AppState>,
+    Path((id, sid)): Path<(String, String)>,
+) -> Result<Json<Value>, StatusCode> {
+    let session_id: uuid::Uuid = id.parse().map_err(|_| StatusCode::BAD_REQUEST)?;
+    let snapshot_id: uuid::Uuid = sid.parse().map_err(|_| StatusCode::BAD_REQUEST)?;
+    let db_err = |e: sqlx::Error| {
+        tracing::error!("Failed to restore snapshot: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    let mut tx = state.db.begin().await.map_err(db_err)?;
+
+    let label: String = sqlx::query_scalar(
+        "SELECT label FROM ch_session_snapshots WHERE id = $1 AND session_id = $2 FOR UPDATE",
+    )
+    .bind(snapshot_id)
+    .bind(session_id)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(db_err)?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    let backup = take_snapshot(&mut tx, session_id, &format!("Before restore: {}", label))
+        .await
+        .map_err(db_err)?;
+
+    // Tool interactions go with their messages (ON DELETE CASCADE).
+    sqlx::query("DELETE FROM ch_messages WHERE session_id = $1")
+        .bind(session_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_err)?;
+
+    let restored = sqlx::query(
+        "INSERT INTO ch_messages (id, session_id, role, content, model, agent, created_at, parts) \
+         SELECT (e->>'id')::uuid, $2, e->>'role', e->>'content', e->>'model', e->>'agent', \
+                (e->>'created_at')::timestamptz, NULLIF(e->'parts', 'null'::jsonb) \
+         FROM ch_session_snapshots s, jsonb_array_elements(s.messages) e \
+         WHERE s.id = $1",
+    )
+    .bind(snapshot_id)
+    .bind(session_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(db_err)?
+    .rows_affected();
+
+    sqlx::query(
+        "INSERT INTO ch_tool_interactions \
+         (id, message_id, tool_use_id, tool_name, tool_input, result, is_error, executed_at) \
+         SELECT (t->>'id')::uuid, (e->>'id')::uuid, t->>'tool_use_id', t->>'tool_name', \
+                COALESCE(t->'tool_input', '{}'::jsonb), t->>'result', \
+                COALESCE((t->>'is_error')::boolean, FALSE), (t->>'executed_at')::timestamptz \
+         FROM ch_session_snapshots s, jsonb_array_elements(s.messages) e, \
+              jsonb_array_elements(e->'tool_interactions') t \
+         WHERE s.id = $1",
+    )
+    .bind(snapshot_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(db_err)?;
+
+    sqlx::query("UPDATE ch_sessions SET updated_at = NOW() WHERE id = $1")
+        .bind(session_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_err)?;
+
+    tx.commit().await.map_err(db_err)?;
+    tracing::info!(
+        "Session {} restored to snapshot {} ({} messages)",
+        session_id,
+        snapshot_id,
+        restored
+    );
+
+    Ok(Json(json!({
+        "session_id": session_id,
+        "restored_snapshot": snapshot_id,
+        "messages": restored,
+        "backup_snapshot": backup,
+    })))
+}
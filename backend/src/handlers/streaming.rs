@@ -1,9 +1,8 @@
 //! Streaming chat endpoints — NDJSON output from Anthropic SSE and Gemini SSE,
 //! plus WebSocket streaming transport.
 //!
-//! - `claude_chat_stream` — streaming NDJSON with fallback chain (no-tools path)
+//! - `claude_chat_stream` — streaming NDJSON (no-tools path; Claude or Gemini via `providers`)
 //! - `claude_chat_stream_with_tools` — agentic tool_use loop with auto-fix
-//! - `google_chat_stream` — Gemini hybrid routing for streaming
 //! - `ws_chat` — WebSocket streaming with rich protocol (Start/Token/Iteration/ToolCall/ToolResult/Complete)
 //!
 //! BE-CH-003: NDJSON streaming now uses `jaskier_core::handlers::anthropic_streaming`
@@ -12,9 +11,8 @@
 
 use std::collections::HashMap;
 
-use axum::Json;
-use axum::body::Body;
 use axum::extract::State;
+use axum::{Extension, Json};
 use axum::extract::ws::{Message as WsMessage, WebSocket, WebSocketUpgrade};
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
@@ -26,18 +24,21 @@ use tokio_util::sync::CancellationToken;
 use jaskier_core::auth::validate_ws_token;
 use jaskier_core::handlers::anthropic_streaming::{
     self, AnthropicChatContext, AnthropicSseEvent, AnthropicSseParser, AnthropicToolDef,
-    HasAnthropicStreamingState, build_iteration_nudge, build_ndjson_response,
-    dynamic_max_iterations, parse_sse_lines, sanitize_api_error, tool_result_context_limit,
-    trim_conversation, truncate_for_context_with_limit as truncate_tool_output,
+    HasAnthropicStreamingState, build_iteration_nudge, dynamic_max_iterations,
+    parse_sse_lines, sanitize_api_error, tool_result_context_limit, trim_conversation,
+    truncate_for_context_with_limit as truncate_tool_output,
 };
 
+use crate::endpoint_policies::Group;
 use crate::models::*;
+use crate::providers::{Anthropic, Gemini, Provider, ProviderRequest};
 use crate::state::AppState;
 
-use super::prompt::{ChatContext, resolve_chat_context};
+use super::prompt::{resolve_chat_context, resolve_request_scope};
+use crate::priority::DefaultPriority;
 use super::{
-    TOOL_TIMEOUT_SECS, is_retryable_status, sanitize_json_strings, send_to_anthropic,
-    truncate_for_context_with_limit,
+    continue_from, has_assistant_prefill, is_retryable_status, max_continuations, sanitize_json_strings,
+    send_to_anthropic, tool_timeout_secs, truncate_for_context_with_limit,
 };
 
 // ═══════════════════════════════════════════════════════════════════════
@@ -60,13 +61,15 @@ impl HasAnthropicStreamingState for AppState {
     fn send_to_anthropic(
         &self,
         body: &Value,
-        timeout_secs: u64,
+        _timeout_secs: u64,
     ) -> impl std::future::Future<Output = Result<reqwest::Response, (StatusCode, String)>> + Send
     {
         let state = self.clone();
         let body = body.clone();
         async move {
-            send_to_anthropic(&state, &body, timeout_secs)
+            // The shared streaming handlers pass their built-in timeout; the
+            // `stream` endpoint policy applies instead (see endpoint_policies).
+            send_to_anthropic(&state, &body, Group::Stream)
                 .await
                 .map_err(|(status, Json(err_val))| {
                     let msg = err_val
@@ -132,49 +135,58 @@ impl HasAnthropicStreamingState for AppState {
         let name = name.to_string();
         let input = input.clone();
         let wd = working_directory.to_string();
+        let transcript = crate::transcripts::current();
         async move {
-            if name == "call_agent" {
-                // Acquire A2A concurrency permit (max 5 concurrent delegations)
-                match state.a2a_semaphore.clone().acquire_owned().await {
-                    Err(_) => (
-                        "A2A delegation limit reached — semaphore closed".to_string(),
-                        true,
-                    ),
-                    Ok(_permit) => {
-                        match tokio::time::timeout(
-                            std::time::Duration::from_secs(120),
-                            execute_agent_call(&state, &input, &wd, 0),
-                        )
-                        .await
-                        {
-                            Ok(res) => res,
-                            Err(_) => {
-                                ("Agent delegation timed out after 120s".to_string(), true)
+            let live = async {
+                // Tools flagged "require confirmation" pause here until approved
+                if let Err(refusal) = crate::tool_confirmation::gate(&state, &name, &input).await {
+                    return (refusal, true);
+                }
+                if name == "call_agent" {
+                    // Acquire A2A concurrency permit (max 5 concurrent delegations)
+                    match state.a2a_semaphore.clone().acquire_owned().await {
+                        Err(_) => (
+                            "A2A delegation limit reached — semaphore closed".to_string(),
+                            true,
+                        ),
+                        Ok(_permit) => {
+                            match tokio::time::timeout(
+                                std::time::Duration::from_secs(120),
+                                execute_agent_call(&state, &input, &wd, 0),
+                            )
+                            .await
+                            {
+                                Ok(res) => res,
+                                Err(_) => {
+                                    ("Agent delegation timed out after 120s".to_string(), true)
+                                }
                             }
                         }
                     }
+                } else {
+                    let timeout_secs = tool_timeout_secs();
+                    let executor = state.tool_executor.with_working_directory(&wd);
+                    match tokio::time::timeout(
+                        std::time::Duration::from_secs(timeout_secs),
+                        executor.execute_with_state(&name, &input, &state),
+                    )
+                    .await
+                    {
+                        Ok(res) => res,
+                        Err(_) => (
+                            format!("Tool '{}' timed out after {}s", name, timeout_secs),
+                            true,
+                        ),
+                    }
                 }
-            } else {
-                let timeout = std::time::Duration::from_secs(TOOL_TIMEOUT_SECS);
-                let executor = state.tool_executor.with_working_directory(&wd);
-                match tokio::time::timeout(
-                    timeout,
-                    executor.execute_with_state(&name, &input, &state),
-                )
-                .await
-                {
-                    Ok(res) => res,
-                    Err(_) => (
-                        format!("Tool '{}' timed out after {}s", name, TOOL_TIMEOUT_SECS),
-                        true,
-                    ),
-                }
-            }
+            };
+            // Recorded in the run's transcript; a replay answers from the recording
+            crate::transcripts::run_tool(transcript, &name, &input, live).await
         }
     }
 
     fn tool_timeout_secs(&self) -> u64 {
-        TOOL_TIMEOUT_SECS
+        tool_timeout_secs()
     }
 
     fn load_session_history(
@@ -262,7 +274,7 @@ impl HasAnthropicStreamingState for AppState {
                 "max_tokens": max_tokens
             });
 
-            state.http_client.post(base_url)
+            crate::http_client::client(&state).post(base_url)
                 .header("Authorization", format!("Bearer {}", api_key))
                 .json(&body)
                 .send()
@@ -302,6 +314,17 @@ impl HasAnthropicStreamingState for AppState {
             } else {
                 "coordinator"
             };
+            crate::usage::record_usage(
+                &db,
+                crate::usage::UsageEvent {
+                    model: model.clone(),
+                    input_tokens: input_est as u32,
+                    output_tokens: output_est as u32,
+                    estimated: true,
+                    source: "stream",
+                    ..Default::default()
+                },
+            );
             let m = model.clone();
             let db_clone = db.clone();
             tokio::spawn(async move {
@@ -388,128 +411,15 @@ async fn send_task_complete_notification(state: &AppState, model: &str) {
     }
 }
 
-// ═══════════════════════════════════════════════════════════════════════
-//  Gemini hybrid streaming
-// ═══════════════════════════════════════════════════════════════════════
-
-async fn google_chat_stream(
-    state: AppState,
-    req: ChatRequest,
-    ctx: ChatContext,
-) -> Result<Response, (StatusCode, Json<Value>)> {
-    let credential = jaskier_oauth::google::get_google_credential(&state).await;
-    let (api_key, is_oauth) = match credential {
-        Some(c) => c,
-        None => {
-            return Err((
-                StatusCode::UNAUTHORIZED,
-                Json(json!({ "error": "No Google API credential configured" })),
-            ));
-        }
-    };
-
-    let model = &ctx.model;
-    let url = format!(
-        "https://generativelanguage.googleapis.com/v1beta/models/{}:streamGenerateContent?alt=sse",
-        model
-    );
-
-    let contents: Vec<Value> = req
-        .messages
-        .iter()
-        .map(|m| {
-            let role = if m.role == "assistant" {
-                "model"
-            } else {
-                "user"
-            };
-            json!({ "role": role, "parts": [{ "text": m.content }] })
-        })
-        .collect();
-
-    let body = json!({
-        "systemInstruction": { "parts": [{ "text": ctx.system_prompt }] },
-        "contents": contents,
-        "generationConfig": {
-            "temperature": req.temperature.unwrap_or(1.0),
-            "maxOutputTokens": ctx.max_tokens,
-        }
-    });
-
-    let request =
-        jaskier_oauth::google::apply_google_auth(state.http_client.post(&url), &api_key, is_oauth)
-            .json(&body)
-            .timeout(std::time::Duration::from_secs(300));
-
-    let resp = request.send().await.map_err(|e| {
-        tracing::error!("Google API request failed: {}", e);
-        (
-            StatusCode::BAD_GATEWAY,
-            Json(json!({ "error": "AI provider request failed" })),
-        )
-    })?;
-
-    if !resp.status().is_success() {
-        let status = resp.status();
-        let err = resp.text().await.unwrap_or_default();
-        tracing::error!("Google API error (status={}): {}", status, err);
-        let safe_error = sanitize_api_error(&err);
-        return Err((
-            StatusCode::from_u16(status.as_u16()).unwrap_or(StatusCode::BAD_GATEWAY),
-            Json(json!({ "error": safe_error })),
-        ));
-    }
-
-    let model_for_done = ctx.model.clone();
-    let byte_stream = resp.bytes_stream();
-
-    let ndjson_stream = async_stream::stream! {
-        let mut sse_buffer = String::new();
-        let mut total_tokens: u32 = 0;
-        let mut stream = byte_stream;
-
-        while let Some(chunk_result) = futures_util::StreamExt::next(&mut stream).await {
-            let chunk = match chunk_result {
-                Ok(b) => b,
-                Err(e) => {
-                    tracing::error!("Google SSE stream error: {}", e);
-                    let err_line = serde_json::to_string(&json!({ "token": "\n[Stream interrupted]", "done": true, "model": &model_for_done })).unwrap_or_default();
-                    yield Ok::<_, std::io::Error>(axum::body::Bytes::from(format!("{}\n", err_line)));
-                    break;
-                }
-            };
-            sse_buffer.push_str(&String::from_utf8_lossy(&chunk));
-
-            while let Some(nl) = sse_buffer.find('\n') {
-                // Safety: '\n' is ASCII — find() returns byte pos at char boundary
-                let line = sse_buffer[..nl].trim().to_string();
-                sse_buffer = sse_buffer[nl + 1..].to_string();
-                if line.is_empty() || line.starts_with(':') { continue; }
-                if let Some(data) = line.strip_prefix("data: ")
-                    && let Ok(event) = serde_json::from_str::<Value>(data) {
-                        if let Some(text) = event.pointer("/candidates/0/content/parts/0/text").and_then(|t| t.as_str())
-                            && !text.is_empty() {
-                                let ndjson_line = serde_json::to_string(&json!({ "token": text, "done": false })).unwrap_or_default();
-                                yield Ok::<_, std::io::Error>(axum::body::Bytes::from(format!("{}\n", ndjson_line)));
-                            }
-                        if let Some(usage) = event.get("usageMetadata") {
-                            total_tokens = usage.get("totalTokenCount").and_then(|v| v.as_u64()).unwrap_or(0) as u32;
-                        }
-                    }
-            }
-        }
-        let done_line = serde_json::to_string(&json!({ "token": "", "done": true, "model": &model_for_done, "total_tokens": total_tokens })).unwrap_or_default();
-        yield Ok::<_, std::io::Error>(axum::body::Bytes::from(format!("{}\n", done_line)));
-    };
-
-    Ok(build_ndjson_response(Body::from_stream(ndjson_stream)))
-}
-
 // ═══════════════════════════════════════════════════════════════════════
 //  Session history helpers
 // ═══════════════════════════════════════════════════════════════════════
 
 async fn load_session_history(db: &sqlx::PgPool, sid: &uuid::Uuid) -> Vec<Value> {
+    // Sessions in cold storage keep only previews until thawed
+    if let Err(e) = crate::cold_storage::thaw(db, *sid).await {
+        tracing::warn!("Failed to thaw session {}: {}", sid, e);
+    }
     let mut messages: Vec<Value> = sqlx::query_as::<_, (String, String)>(
         "SELECT role, content FROM ch_messages WHERE session_id = $1 ORDER BY created_at DESC LIMIT 20",
     )
@@ -523,6 +433,7 @@ async fn load_session_history(db: &sqlx::PgPool, sid: &uuid::Uuid) -> Vec<Value>
     .collect();
 
     // Compress old messages: truncate everything except the last 6
+    let mut shortened = 0;
     for i in 0..messages.len() {
         if i < messages.len().saturating_sub(6)
             && let Some(content) = messages[i].get_mut("content")
@@ -539,13 +450,18 @@ async fn load_session_history(db: &sqlx::PgPool, sid: &uuid::Uuid) -> Vec<Value>
                 "{}... [message truncated for context efficiency]",
                 &s[..boundary]
             ));
+            shortened += 1;
         }
     }
+    crate::request_scope::decide(
+        "history",
+        json!({ "source": "session", "messages": messages.len(), "limit": 20, "shortened_messages": shortened }),
+    );
 
     messages
 }
 
-fn filter_client_system_prompt(messages: &[ChatMessage]) -> Vec<Value> {
+pub(crate) fn filter_client_system_prompt(messages: &[ChatMessage]) -> Vec<Value> {
     let mut result = Vec::new();
     let mut skip_count = 0;
 
@@ -558,8 +474,12 @@ fn filter_client_system_prompt(messages: &[ChatMessage]) -> Vec<Value> {
         skip_count = 2;
     }
 
-    for msg in messages.iter().skip(skip_count) {
-        result.push(json!({ "role": msg.role, "content": msg.content }));
+    // `system` messages are already part of the system prompt (request layer)
+    for msg in messages.iter().skip(skip_count).filter(|m| m.role != "system") {
+        result.push(json!({
+            "role": msg.role,
+            "content": crate::providers::anthropic::message_content(msg),
+        }));
     }
     result
 }
@@ -587,13 +507,75 @@ pub async fn prefetch_hints(
 // ═══════════════════════════════════════════════════════════════════════
 
 /// POST /api/claude/chat/stream
+///
+/// The response carries `X-Stream-Id`; reconnect with
+/// `GET /api/claude/chat/stream/{stream_id}?offset=<bytes>`.
 #[utoipa::path(post, path = "/api/claude/chat/stream", tag = "chat",
     request_body = ChatRequest,
     responses((status = 200, description = "Streaming NDJSON response")))]
 pub async fn claude_chat_stream(
     State(state): State<AppState>,
-    Json(req): Json<ChatRequest>,
+    token_priority: Option<Extension<DefaultPriority>>,
+    Json(mut req): Json<ChatRequest>,
+) -> Result<Response, (StatusCode, Json<Value>)> {
+    // Interview mode: the backend asks the next question itself, or swaps in
+    // the rendered prompt after the last answer (may set agent_id).
+    // Its decisions (template applied) go into the request's log.
+    let decisions = crate::request_scope::DecisionLog::default();
+    let intercepted = crate::request_scope::run(
+        std::sync::Arc::new(crate::request_scope::RequestScope {
+            decisions: decisions.clone(),
+            ..Default::default()
+        }),
+        super::templates::intercept_interview(&state, &mut req),
+    )
+    .await?;
+    if let Some(question) = intercepted {
+        return Ok(question);
+    }
+    // Agent / caller stop sequences apply to every upstream call of this stream.
+    let scope = resolve_request_scope(
+        &state,
+        &req,
+        token_priority.map(|Extension(DefaultPriority(p))| p),
+    )
+    .await?;
+    // Session-bound streams are journaled until the detached body finishes
+    // (see recovery), so a crash mid-reply is not silently lost.
+    let session_id = req.session_id.as_deref().and_then(|s| uuid::Uuid::parse_str(s).ok());
+    let scope = std::sync::Arc::new(crate::request_scope::RequestScope {
+        journal: session_id.and_then(|sid| {
+            crate::recovery::begin(
+                crate::recovery::OperationKind::Stream,
+                Some(sid),
+                format!("chat stream in session {}", sid),
+                serde_json::to_value(&req).unwrap_or_default(),
+            )
+            .map(std::sync::Arc::new)
+        }),
+        decisions,
+        ..(*scope).clone()
+    });
+    crate::request_scope::run(scope, async move {
+        // Detached + buffered so a dropped client can resume (see stream_relay)
+        let response = claude_chat_stream_inner(state.clone(), req).await?;
+        Ok(crate::stream_relay::detach(&state, response))
+    })
+    .await
+}
+
+async fn claude_chat_stream_inner(
+    state: AppState,
+    req: ChatRequest,
 ) -> Result<Response, (StatusCode, Json<Value>)> {
+    let prefill = has_assistant_prefill(&req.messages);
+    if prefill && req.tools_enabled.unwrap_or(false) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "Assistant prefill is not supported with tools_enabled" })),
+        ));
+    }
+
     // Gate: if tools_enabled, route to agentic handler
     if req.tools_enabled.unwrap_or(false) {
         return claude_chat_stream_with_tools(state, req).await;
@@ -609,24 +591,17 @@ pub async fn claude_chat_stream(
 
     // Hybrid routing: Gemini models → Google API
     if ctx.model.starts_with("gemini-") {
-        return google_chat_stream(state, req, ctx).await;
+        if prefill {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": "Assistant prefill is only supported for Claude models" })),
+            ));
+        }
+        return Gemini.chat_stream(&state, ProviderRequest::from_context(ctx, req.messages)).await;
     }
 
-    // ── Delegate to shared handler ──────────────────────────────────────
-    let prompt_len = req.messages.iter().map(|m| m.content.len()).sum::<usize>();
-    let messages = filter_client_system_prompt(&req.messages);
-
-    let shared_ctx = AnthropicChatContext {
-        model: ctx.model,
-        max_tokens: ctx.max_tokens,
-        temperature: ctx.temperature,
-        max_iterations: ctx.max_iterations.max(1) as usize,
-        working_directory: ctx.working_directory,
-        session_id: ctx.session_id,
-        system_prompt: ctx.system_prompt,
-    };
-
-    anthropic_streaming::anthropic_ndjson_stream_no_tools(&state, &shared_ctx, messages, prompt_len)
+    Anthropic
+        .chat_stream(&state, ProviderRequest::from_context(ctx, req.messages))
         .await
 }
 
@@ -637,8 +612,9 @@ pub async fn claude_chat_stream(
 
 async fn claude_chat_stream_with_tools(
     state: AppState,
-    req: ChatRequest,
+    mut req: ChatRequest,
 ) -> Result<Response, (StatusCode, Json<Value>)> {
+    crate::providers::anthropic::prepare_attachments(&state, &mut req.messages).await?;
     let ctx = resolve_chat_context(&state, &req).await;
 
     // Dynamic iteration cap based on prompt complexity
@@ -650,7 +626,10 @@ async fn claude_chat_stream_with_tools(
     let initial_messages: Vec<Value> = if let Some(ref sid) = ctx.session_id {
         let mut history = load_session_history(&state.db, sid).await;
         if let Some(last) = req.messages.last() {
-            history.push(json!({ "role": "user", "content": &last.content }));
+            history.push(json!({
+                "role": "user",
+                "content": crate::providers::anthropic::message_content(last),
+            }));
         }
         history
     } else {
@@ -667,9 +646,18 @@ async fn claude_chat_stream_with_tools(
         system_prompt: ctx.system_prompt,
     };
 
+    // Record the run (see transcripts); replays reuse the recorded context
+    let transcript =
+        crate::transcripts::start(&state, "stream", &shared_ctx, &initial_messages, true).await;
+    if let Some(ref t) = transcript {
+        crate::transcripts::attach(t);
+    }
+
     // ── Delegate to shared handler ──────────────────────────────────────
-    anthropic_streaming::anthropic_ndjson_stream_with_tools(&state, shared_ctx, initial_messages)
-        .await
+    let response =
+        anthropic_streaming::anthropic_ndjson_stream_with_tools(&state, shared_ctx, initial_messages)
+            .await?;
+    Ok(crate::transcripts::tag_response(response, transcript.as_ref()))
 }
 
 // ═══════════════════════════════════════════════════════════════════════
@@ -691,6 +679,35 @@ async fn ws_send(sender: &mut SplitSink<WebSocket, WsMessage>, msg: &WsServerMes
     }
 }
 
+/// Ask the socket's user to confirm a tool flagged "require confirmation".
+/// Returns `None` when the tool runs without asking; otherwise await the
+/// decision with `state.tool_confirmations.wait`.
+async fn request_ws_confirmation(
+    state: &AppState,
+    sender: &mut SplitSink<WebSocket, WsMessage>,
+    name: &str,
+    input: &Value,
+) -> Option<(
+    uuid::Uuid,
+    tokio::sync::oneshot::Receiver<crate::tool_confirmation::Decision>,
+)> {
+    if !state.tool_confirmations.requires(&state.db, name).await {
+        return None;
+    }
+    let (call, rx) = state.tool_confirmations.open(name, input);
+    ws_send(
+        sender,
+        &WsServerMessage::ToolConfirmation {
+            id: call.id.to_string(),
+            name: name.to_string(),
+            args: input.clone(),
+            expires_at: call.expires_at.to_rfc3339(),
+        },
+    )
+    .await;
+    Some((call.id, rx))
+}
+
 // ═══════════════════════════════════════════════════════════════════════
 //  Predictive UI Pre-fetching — view hint detection from prompt text
 // ═══════════════════════════════════════════════════════════════════════
@@ -724,10 +741,12 @@ fn detect_view_hints(prompt: &str) -> Vec<String> {
 }
 
 /// WebSocket upgrade handler for `/ws/chat`.
-/// Auth via `?token=<secret>` query parameter (WS doesn't support custom headers).
+/// Auth via `?token=<secret>` query parameter (WS doesn't support custom headers)
+/// or the web UI's session cookie.
 pub async fn ws_chat(
     ws: WebSocketUpgrade,
     State(state): State<AppState>,
+    headers: axum::http::HeaderMap,
     axum::extract::Query(params): axum::extract::Query<HashMap<String, String>>,
 ) -> impl IntoResponse {
     // Build query string from params for validate_ws_token
@@ -737,11 +756,14 @@ pub async fn ws_chat(
         .collect::<Vec<_>>()
         .join("&");
 
-    if !validate_ws_token(&query_string, state.auth_secret.as_deref()) {
+    if !validate_ws_token(&query_string, state.auth_secret.as_deref())
+        && !crate::web_session::has_valid_session(&headers, state.auth_secret.as_deref())
+    {
         return (StatusCode::UNAUTHORIZED, "Invalid or missing auth token").into_response();
     }
 
-    ws.on_upgrade(|socket| handle_ws(socket, state))
+    let principal = crate::principal::requested();
+    ws.on_upgrade(move |socket| crate::principal::scope(principal, handle_ws(socket, state)))
 }
 
 /// Main WebSocket message loop.
@@ -794,14 +816,18 @@ async fn handle_ws(socket: WebSocket, state: AppState) {
                         session_id,
                     } => {
                         let child_cancel = cancel.child_token();
-                        execute_streaming_ws(
-                            &mut sender,
-                            &state,
-                            prompt,
-                            model,
-                            tools_enabled.unwrap_or(false),
-                            session_id,
-                            child_cancel,
+                        // Scoped so the run keeps one upstream slot (see priority)
+                        crate::request_scope::run(
+                            std::sync::Arc::new(crate::request_scope::RequestScope::default()),
+                            execute_streaming_ws(
+                                &mut sender,
+                                &state,
+                                prompt,
+                                model,
+                                tools_enabled.unwrap_or(false),
+                                session_id,
+                                child_cancel,
+                            ),
                         )
                         .await;
                     }
@@ -845,6 +871,7 @@ async fn execute_streaming_ws(
         messages: vec![ChatMessage {
             role: "user".to_string(),
             content: prompt.clone(),
+            attachments: Vec::new(),
             model: None,
             timestamp: None,
         }],
@@ -854,6 +881,9 @@ async fn execute_streaming_ws(
         stream: Some(true),
         tools_enabled: Some(tools_enabled),
         session_id: session_id.clone(),
+        agent_id: None,
+        stop_sequences: None,
+        priority: None,
     };
 
     let ctx = resolve_chat_context(state, &chat_req).await;
@@ -908,7 +938,7 @@ async fn execute_streaming_ws(
         }
         sanitize_json_strings(&mut body);
 
-        let resp = match send_to_anthropic(state, &body, 300).await {
+        let resp = match send_to_anthropic(state, &body, Group::Stream).await {
             Ok(r) => r,
             Err((_, Json(err_val))) => {
                 let raw_msg = err_val
@@ -946,7 +976,7 @@ async fn execute_streaming_ws(
                     fb_model
                 );
                 body["model"] = json!(fb_model);
-                if let Ok(fb) = send_to_anthropic(state, &body, 300).await
+                if let Ok(fb) = send_to_anthropic(state, &body, Group::Stream).await
                     && fb.status().is_success()
                 {
                     let reason = if original_status.as_u16() == 429 {
@@ -992,61 +1022,121 @@ async fn execute_streaming_ws(
             return;
         }
 
-        // Parse SSE → Token messages (using shared parser)
-        let mut byte_stream = resp.bytes_stream();
-        let mut raw_buf: Vec<u8> = Vec::new();
-        let mut full_text = String::new();
-
-        while let Some(chunk_result) = byte_stream.next().await {
-            if cancel.is_cancelled() {
-                ws_send(
-                    sender,
-                    &WsServerMessage::Error {
-                        message: "Cancelled by user".to_string(),
-                        code: Some("CANCELLED".to_string()),
-                    },
-                )
-                .await;
-                return;
-            }
-            let chunk = match chunk_result {
-                Ok(bytes) => bytes,
-                Err(_) => break,
-            };
-            raw_buf.extend_from_slice(&chunk);
+        // Context as sent, before any continuation prefill is added
+        let context = crate::message_context::snapshot(&body);
 
-            let events = parse_sse_lines(&mut raw_buf);
-            for event in events {
-                let event_type = event.get("type").and_then(|t| t.as_str()).unwrap_or("");
-                if event_type == "content_block_delta" {
-                    let text = event
-                        .get("delta")
-                        .and_then(|d| d.get("text"))
-                        .and_then(|t| t.as_str())
-                        .unwrap_or("");
-                    if !text.is_empty() {
-                        full_text.push_str(text);
+        // Parse SSE → Token messages (using shared parser). A reply cut off at
+        // max_tokens is continued with the text so far as an assistant prefill;
+        // the continuation streams into the same reply.
+        let mut resp = resp;
+        let mut full_text = String::new();
+        let mut continuations = 0;
+        loop {
+            let mut byte_stream = resp.bytes_stream();
+            let mut raw_buf: Vec<u8> = Vec::new();
+            let mut stop_reason: Option<String> = None;
+
+            loop {
+                let chunk_result = match crate::stream_watchdog::next_chunk(&mut byte_stream).await {
+                    crate::stream_watchdog::Next::Item(r) => r,
+                    crate::stream_watchdog::Next::End => break,
+                    crate::stream_watchdog::Next::Stalled => {
+                        crate::stream_watchdog::record_stall(state, "ws", full_text.len()).await;
                         ws_send(
                             sender,
-                            &WsServerMessage::Token {
-                                content: text.to_string(),
+                            &WsServerMessage::Error {
+                                message: "Upstream stalled — generation aborted".to_string(),
+                                code: Some(crate::stream_watchdog::STALLED_CODE.to_string()),
                             },
                         )
                         .await;
+                        return;
+                    }
+                };
+                if cancel.is_cancelled() {
+                    ws_send(
+                        sender,
+                        &WsServerMessage::Error {
+                            message: "Cancelled by user".to_string(),
+                            code: Some("CANCELLED".to_string()),
+                        },
+                    )
+                    .await;
+                    return;
+                }
+                let chunk = match chunk_result {
+                    Ok(bytes) => bytes,
+                    Err(_) => break,
+                };
+                raw_buf.extend_from_slice(&chunk);
+
+                let events = parse_sse_lines(&mut raw_buf);
+                for event in events {
+                    let event_type = event.get("type").and_then(|t| t.as_str()).unwrap_or("");
+                    if event_type == "content_block_delta" {
+                        let text = event
+                            .get("delta")
+                            .and_then(|d| d.get("text"))
+                            .and_then(|t| t.as_str())
+                            .unwrap_or("");
+                        if !text.is_empty() {
+                            full_text.push_str(text);
+                            ws_send(
+                                sender,
+                                &WsServerMessage::Token {
+                                    content: text.to_string(),
+                                },
+                            )
+                            .await;
+                        }
+                    } else if event_type == "message_delta"
+                        && let Some(reason) = event["delta"]["stop_reason"].as_str()
+                    {
+                        stop_reason = Some(reason.to_string());
                     }
                 }
             }
+
+            if stop_reason.as_deref() != Some("max_tokens")
+                || full_text.trim().is_empty()
+                || continuations >= max_continuations(&state.db).await
+            {
+                break;
+            }
+            continuations += 1;
+            crate::request_scope::decide(
+                "continuation",
+                json!({ "attempt": continuations, "reason": "max_tokens" }),
+            );
+            // The prefill is sent trimmed; the continuation supplies the seam.
+            full_text.truncate(full_text.trim_end().len());
+            let mut messages = initial_messages.clone();
+            continue_from(&mut messages, &full_text);
+            body["messages"] = json!(messages);
+            resp = match send_to_anthropic(state, &body, Group::Stream).await {
+                Ok(r) if r.status().is_success() => r,
+                Ok(r) => {
+                    tracing::warn!("ws: max_tokens continuation failed ({})", r.status());
+                    break;
+                }
+                Err((status, _)) => {
+                    tracing::warn!("ws: max_tokens continuation failed ({})", status);
+                    break;
+                }
+            };
         }
 
         // Store message to DB if session present
         if let Some(ref sid) = ctx.session_id {
-            let _ = store_ws_messages(state, sid, &prompt, &full_text).await;
+            let _ = store_ws_messages(state, sid, &model, &prompt, &full_text, Some((None, context))).await;
         }
+        record_ws_usage(state, &model, prompt_len, full_text.len(), ctx.session_id);
 
         ws_send(
             sender,
             &WsServerMessage::Complete {
                 duration_ms: execution_start.elapsed().as_millis() as u64,
+                warnings: crate::request_scope::warnings(),
             },
         )
         .await;
@@ -1070,6 +1160,23 @@ async fn execute_streaming_ws(
         })
         .collect();
 
+    let transcript = crate::transcripts::start(
+        state,
+        "ws",
+        &AnthropicChatContext {
+            model: model.clone(),
+            max_tokens,
+            temperature: effective_temperature,
+            max_iterations: max_tool_iterations,
+            working_directory: wd.clone(),
+            session_id: ctx.session_id,
+            system_prompt: system_prompt.clone(),
+        },
+        &initial_messages,
+        true,
+    )
+    .await;
+
     let mut conversation: Vec<Value> = initial_messages;
     let mut iteration: u32 = 0;
     let mut has_written_file = false;
@@ -1140,8 +1247,11 @@ async fn execute_streaming_ws(
             "temperature": effective_temperature,
         });
         sanitize_json_strings(&mut body);
+        if let Some(ref t) = transcript {
+            t.record_request(&body);
+        }
 
-        let resp = match send_to_anthropic(state, &body, 300).await {
+        let resp = match send_to_anthropic(state, &body, Group::Stream).await {
             Ok(r) => r,
             Err((_, Json(err_val))) => {
                 let raw_msg = err_val
@@ -1196,7 +1306,23 @@ async fn execute_streaming_ws(
         let mut byte_stream = resp.bytes_stream();
         let mut raw_buf: Vec<u8> = Vec::new();
 
-        while let Some(chunk_result) = byte_stream.next().await {
+        loop {
+            let chunk_result = match crate::stream_watchdog::next_chunk(&mut byte_stream).await {
+                crate::stream_watchdog::Next::Item(r) => r,
+                crate::stream_watchdog::Next::End => break,
+                crate::stream_watchdog::Next::Stalled => {
+                    crate::stream_watchdog::record_stall(state, "ws_tools", full_text.len()).await;
+                    ws_send(
+                        sender,
+                        &WsServerMessage::Error {
+                            message: "Upstream stalled — generation aborted".to_string(),
+                            code: Some(crate::stream_watchdog::STALLED_CODE.to_string()),
+                        },
+                    )
+                    .await;
+                    return;
+                }
+            };
             if cancel.is_cancelled() {
                 break;
             }
@@ -1298,48 +1424,65 @@ async fn execute_streaming_ws(
                 let state_ref = state.clone();
                 let wd_ref = wd.clone();
 
+                // Tools flagged "require confirmation" wait for the user's
+                // decision inside the task; the collector below keeps heartbeating.
+                let confirmation =
+                    request_ws_confirmation(state, sender, &tool_name, &tool_input).await;
+
                 let semaphore = state.a2a_semaphore.clone();
+                let transcript = transcript.clone();
                 let handle = tokio::spawn(async move {
-                    let (result, is_error) = if tool_name == "call_agent" {
-                        // Acquire A2A concurrency permit
-                        match semaphore.acquire_owned().await {
-                            Err(_) => (
-                                "A2A delegation limit reached — semaphore closed".to_string(),
-                                true,
-                            ),
-                            Ok(_permit) => {
-                                match tokio::time::timeout(
-                                    std::time::Duration::from_secs(120),
-                                    execute_agent_call(&state_ref, &tool_input, &wd_ref, 0),
-                                )
-                                .await
-                                {
-                                    Ok(res) => res,
-                                    Err(_) => (
-                                        "Agent delegation timed out after 120s".to_string(),
-                                        true,
-                                    ),
-                                }
+                    let live = async {
+                        if let Some((call_id, rx)) = confirmation {
+                            let decision = state_ref.tool_confirmations.wait(call_id, rx, || {}).await;
+                            if decision != crate::tool_confirmation::Decision::Approved {
+                                let refusal = crate::tool_confirmation::refusal(&tool_name, &decision);
+                                return (refusal, true);
                             }
                         }
-                    } else {
-                        let timeout = std::time::Duration::from_secs(TOOL_TIMEOUT_SECS);
-                        match tokio::time::timeout(
-                            timeout,
-                            executor.execute_with_state(&tool_name, &tool_input, &state_ref),
-                        )
-                        .await
-                        {
-                            Ok(res) => res,
-                            Err(_) => (
-                                format!(
-                                    "Tool '{}' timed out after {}s",
-                                    tool_name, TOOL_TIMEOUT_SECS
+                        if tool_name == "call_agent" {
+                            // Acquire A2A concurrency permit
+                            match semaphore.acquire_owned().await {
+                                Err(_) => (
+                                    "A2A delegation limit reached — semaphore closed".to_string(),
+                                    true,
                                 ),
-                                true,
-                            ),
+                                Ok(_permit) => {
+                                    match tokio::time::timeout(
+                                        std::time::Duration::from_secs(120),
+                                        execute_agent_call(&state_ref, &tool_input, &wd_ref, 0),
+                                    )
+                                    .await
+                                    {
+                                        Ok(res) => res,
+                                        Err(_) => (
+                                            "Agent delegation timed out after 120s".to_string(),
+                                            true,
+                                        ),
+                                    }
+                                }
+                            }
+                        } else {
+                            let timeout_secs = tool_timeout_secs();
+                            match tokio::time::timeout(
+                                std::time::Duration::from_secs(timeout_secs),
+                                executor.execute_with_state(&tool_name, &tool_input, &state_ref),
+                            )
+                            .await
+                            {
+                                Ok(res) => res,
+                                Err(_) => (
+                                    format!(
+                                        "Tool '{}' timed out after {}s",
+                                        tool_name, timeout_secs
+                                    ),
+                                    true,
+                                ),
+                            }
                         }
                     };
+                    let (result, is_error) =
+                        crate::transcripts::run_tool(transcript, &tool_name, &tool_input, live).await;
                     (tool_name, tool_id, result, is_error)
                 });
                 handles.push(handle);
@@ -1469,8 +1612,11 @@ async fn execute_streaming_ws(
                         "tools": &edit_tools,
                         "stream": false,
                     });
+                    if let Some(ref t) = transcript {
+                        t.record_request(&fix_body);
+                    }
 
-                    if let Ok(fix_resp) = send_to_anthropic(state, &fix_body, 60).await
+                    if let Ok(fix_resp) = send_to_anthropic(state, &fix_body, Group::Chat).await
                         && fix_resp.status().is_success()
                         && let Ok(fix_json) = fix_resp.json::<Value>().await
                         && let Some(content) = fix_json.get("content").and_then(|c| c.as_array())
@@ -1484,18 +1630,46 @@ async fn execute_streaming_ws(
                                 let empty_input = json!({});
                                 let fix_tool_input = block.get("input").unwrap_or(&empty_input);
                                 let executor = state.tool_executor.with_working_directory(&wd);
-                                let timeout = std::time::Duration::from_secs(TOOL_TIMEOUT_SECS);
-                                let (result, is_error) = match tokio::time::timeout(
-                                    timeout,
-                                    executor.execute_with_state(fix_tool_name, fix_tool_input, state),
+                                let timeout = std::time::Duration::from_secs(tool_timeout_secs());
+                                let decision = match request_ws_confirmation(
+                                    state,
+                                    sender,
+                                    fix_tool_name,
+                                    fix_tool_input,
                                 )
                                 .await
                                 {
-                                    Ok(res) => res,
-                                    Err(_) => {
-                                        (format!("Tool '{}' timed out", fix_tool_name), true)
+                                    Some((call_id, rx)) => {
+                                        state.tool_confirmations.wait(call_id, rx, || {}).await
+                                    }
+                                    None => crate::tool_confirmation::Decision::Approved,
+                                };
+                                let live = async {
+                                    match decision {
+                                        crate::tool_confirmation::Decision::Approved => match tokio::time::timeout(
+                                            timeout,
+                                            executor.execute_with_state(fix_tool_name, fix_tool_input, state),
+                                        )
+                                        .await
+                                        {
+                                            Ok(res) => res,
+                                            Err(_) => {
+                                                (format!("Tool '{}' timed out", fix_tool_name), true)
+                                            }
+                                        },
+                                        other => (
+                                            crate::tool_confirmation::refusal(fix_tool_name, &other),
+                                            true,
+                                        ),
                                     }
                                 };
+                                let (result, is_error) = crate::transcripts::run_tool(
+                                    transcript.clone(),
+                                    fix_tool_name,
+                                    fix_tool_input,
+                                    live,
+                                )
+                                .await;
 
                                 ws_send(
                                     sender,
@@ -1537,14 +1711,19 @@ async fn execute_streaming_ws(
 
         // Store messages if session present
         if let Some(ref sid) = ctx.session_id {
-            let _ = store_ws_messages(state, sid, &prompt, &full_text).await;
+            let context = transcript
+                .as_ref()
+                .and_then(|t| t.last_request().map(|r| (Some(t.id), r)));
+            let _ = store_ws_messages(state, sid, &model, &prompt, &full_text, context).await;
         }
+        record_ws_usage(state, &model, prompt_len, full_text.len(), ctx.session_id);
 
         // Complete
         ws_send(
             sender,
             &WsServerMessage::Complete {
                 duration_ms: execution_start.elapsed().as_millis() as u64,
+                warnings: crate::request_scope::warnings(),
             },
         )
         .await;
@@ -1597,18 +1776,34 @@ pub(crate) async fn execute_agent_call(
         None => return ("Missing required argument: task".to_string(), true),
     };
 
-    // Find agent by name (case-insensitive)
-    let (agent_display_name, agent_role, agent_tier, agent_desc) = {
+    // Find agent by name (case-insensitive); paused and disabled agents are skipped
+    let (agent_id, agent_display_name, agent_role, agent_tier, agent_desc) = {
         let agents = state.agents.read().await;
+        let available = || -> Vec<String> {
+            agents
+                .iter()
+                .filter(|a| crate::agent_status::delegation_refusal(a).is_none())
+                .map(|a| a.name.to_lowercase())
+                .collect()
+        };
         match agents.iter().find(|a| a.name.to_lowercase() == agent_name) {
-            Some(a) => (
-                a.name.clone(),
-                a.role.clone(),
-                a.tier.clone(),
-                a.description.clone(),
-            ),
+            Some(a) => {
+                if let Some(refusal) = crate::agent_status::delegation_refusal(a) {
+                    return (
+                        format!("{} — delegate to another agent. Available: {}", refusal, available().join(", ")),
+                        true,
+                    );
+                }
+                (
+                    a.id.clone(),
+                    a.name.clone(),
+                    a.role.clone(),
+                    a.tier.clone(),
+                    a.description.clone(),
+                )
+            }
             None => {
-                let available: Vec<String> = agents.iter().map(|a| a.name.to_lowercase()).collect();
+                let available = available();
                 return (
                     format!(
                         "Unknown agent '{}'. Available: {}",
@@ -1635,6 +1830,7 @@ pub(crate) async fn execute_agent_call(
     );
 
     let task_start = std::time::Instant::now();
+    let _busy = crate::agent_status::busy(&agent_id);
 
     // Log delegation to DB (fire-and-forget)
     let task_id = uuid::Uuid::new_v4();
@@ -1733,7 +1929,7 @@ pub(crate) async fn execute_agent_call(
             "tools": &tool_defs,
         });
 
-        let resp = match send_to_anthropic(state, &body, 120).await {
+        let resp = match send_to_anthropic(state, &body, Group::Chat).await {
             Ok(r) => r,
             Err((_, Json(err_val))) => {
                 let raw_msg = err_val
@@ -1836,11 +2032,15 @@ pub(crate) async fn execute_agent_call(
                         depth,
                     ))
                     .await
+                } else if let Err(refusal) =
+                    crate::tool_confirmation::gate(state, tool_name, tool_input).await
+                {
+                    (refusal, true)
                 } else {
                     let executor = state
                         .tool_executor
                         .with_working_directory(working_directory);
-                    let timeout = std::time::Duration::from_secs(TOOL_TIMEOUT_SECS);
+                    let timeout = std::time::Duration::from_secs(tool_timeout_secs());
                     match tokio::time::timeout(
                         timeout,
                         executor.execute_with_state(tool_name, tool_input, state),
@@ -1920,32 +2120,96 @@ pub(crate) async fn execute_agent_call(
     )
 }
 
-/// Store user prompt + assistant response to DB for a WebSocket session.
+/// Store user prompt + assistant response to DB for a WebSocket session,
+/// and show both to anyone else viewing the session. A reply that reads like
+/// a refusal is recorded against its message (see `refusals`), and diagrams
+/// in the reply are stored as artifacts (see `artifacts`).
 async fn store_ws_messages(
     state: &AppState,
     session_id: &uuid::Uuid,
+    model: &str,
     user_prompt: &str,
     assistant_text: &str,
+    // (transcript, request snapshot) the reply was generated from
+    context: Option<(Option<uuid::Uuid>, Value)>,
 ) -> Result<(), sqlx::Error> {
-    sqlx::query(
-        "INSERT INTO ch_messages (id, session_id, role, content, created_at) VALUES ($1, $2, 'user', $3, NOW())",
-    )
-    .bind(uuid::Uuid::new_v4())
-    .bind(session_id)
-    .bind(user_prompt)
-    .execute(&state.db)
-    .await?;
-
+    let mut stored = vec![("user", user_prompt)];
     if !assistant_text.is_empty() {
+        stored.push(("assistant", assistant_text));
+    }
+
+    for (role, content) in stored {
+        let id = uuid::Uuid::new_v4();
         sqlx::query(
-            "INSERT INTO ch_messages (id, session_id, role, content, created_at) VALUES ($1, $2, 'assistant', $3, NOW())",
+            "INSERT INTO ch_messages (id, session_id, role, content, created_at) VALUES ($1, $2, $3, $4, NOW())",
         )
-        .bind(uuid::Uuid::new_v4())
+        .bind(id)
         .bind(session_id)
-        .bind(assistant_text)
+        .bind(role)
+        .bind(content)
         .execute(&state.db)
         .await?;
+
+        if role == "assistant"
+            && let Some((transcript_id, ref request)) = context
+        {
+            let decisions = crate::request_scope::decisions();
+            crate::message_context::store(&state.db, id, transcript_id, request, &decisions).await;
+        }
+        if role == "assistant"
+            && let Some(refusal) = crate::refusals::detect(None, content)
+        {
+            crate::refusals::record(
+                &state.db,
+                &refusal,
+                crate::refusals::RefusalContext {
+                    model: model.to_string(),
+                    session_id: Some(*session_id),
+                    message_id: Some(id),
+                    source: "ws",
+                    ..Default::default()
+                },
+            );
+        }
+        if role == "assistant" {
+            crate::artifacts::store_from_message(&state.db, id, *session_id, content).await;
+        }
+
+        state.presence.publish(
+            *session_id,
+            crate::session_presence::SessionEvent::MessageAdded {
+                message: json!({
+                    "id": id,
+                    "role": role,
+                    "content": content,
+                    "author": null,
+                    "created_at": chrono::Utc::now(),
+                }),
+            },
+        );
     }
 
     Ok(())
 }
+
+/// Record an estimated usage event for a completed WebSocket execution.
+fn record_ws_usage(
+    state: &AppState,
+    model: &str,
+    prompt_chars: usize,
+    output_chars: usize,
+    session_id: Option<uuid::Uuid>,
+) {
+    crate::usage::record_usage(
+        &state.db,
+        crate::usage::UsageEvent {
+            model: model.to_string(),
+            input_tokens: (prompt_chars / 4) as u32,
+            output_tokens: (output_chars / 4) as u32,
+            estimated: true,
+            source: "ws",
+            session_id,
+            ..Default::default()
+        },
+    );
+}
@@ -0,0 +1,445 @@
+//! Sub-sessions — conversations spawned by an orchestration step.
+//!
+//! Endpoints:
+//! - `GET  /api/sessions/{id}/children`  — direct child sessions
+//! - `POST /api/sessions/{id}/children`  — create a child session for a step
+//! - `GET  /api/sessions/{id}/tree`      — ancestors + nested descendants
+//! - `POST /api/sessions/{id}/fork`      — copy the conversation up to a message
+//!
+//! A child is an ordinary session linked to its parent (`ch_sessions.parent_id`)
+//! and to the step that spawned it (`step_id`), so the shared session endpoints
+//! read and append to it as usual. Deleting a parent deletes its children.
+//!
+//! A fork is a child holding its own copy of the parent's messages (with tool
+//! interactions) up to `forked_from`, so it can take another direction while
+//! the original thread stays as it was.
+
+use std::collections::HashMap;
+
+use axum::Json;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use utoipa::ToSchema;
+
+use crate::state::AppState;
+
+/// Deepest nesting walked by the tree endpoint.
+const MAX_TREE_DEPTH: i32 = 16;
+const MAX_STEP_ID_LEN: usize = 200;
+
+// ── Request / Response types ────────────────────────────────────────────────
+
+/// Request body for creating a child session.
+#[derive(Debug, Clone, Default, Deserialize, ToSchema)]
+pub struct CreateChildSessionRequest {
+    /// Title (default: "Sub-session: <step_id>").
+    #[serde(default)]
+    pub title: Option<String>,
+    /// The orchestration step that spawned the conversation.
+    #[serde(default)]
+    pub step_id: Option<String>,
+}
+
+/// A session as listed in the hierarchy.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct SubSessionRow {
+    pub id: uuid::Uuid,
+    pub parent_id: Option<uuid::Uuid>,
+    pub step_id: Option<String>,
+    /// Last copied message, for forks.
+    pub forked_from: Option<uuid::Uuid>,
+    pub title: String,
+    pub message_count: i64,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Trimmed step id; `Err` when it is too long.
+pub(crate) fn normalize_step_id(step_id: Option<&str>) -> Result<Option<String>, String> {
+    match step_id.map(str::trim).filter(|s| !s.is_empty()) {
+        Some(s) if s.chars().count() > MAX_STEP_ID_LEN => {
+            Err(format!("step_id must be at most {} characters", MAX_STEP_ID_LEN))
+        }
+        other => Ok(other.map(str::to_string)),
+    }
+}
+
+/// Create a session under `parent_id`. `None` when the parent doesn't exist.
+pub(crate) async fn insert_child_session(
+    db: &sqlx::PgPool,
+    parent_id: uuid::Uuid,
+    step_id: Option<&str>,
+    title: &str,
+) -> Result<Option<uuid::Uuid>, sqlx::Error> {
+    sqlx::query_scalar(
+        "INSERT INTO ch_sessions (title, parent_id, step_id) \
+         SELECT $1, id, $3 FROM ch_sessions WHERE id = $2 \
+         RETURNING id",
+    )
+    .bind(title)
+    .bind(parent_id)
+    .bind(step_id)
+    .fetch_optional(db)
+    .await
+}
+
+/// Nest `rows` (the root's descendants, any order) under `root`.
+fn nest(root: uuid::Uuid, rows: Vec<SubSessionRow>) -> Vec<Value> {
+    let mut by_parent: HashMap<uuid::Uuid, Vec<SubSessionRow>> = HashMap::new();
+    for row in rows {
+        if let Some(parent) = row.parent_id {
+            by_parent.entry(parent).or_default().push(row);
+        }
+    }
+    fn build(id: uuid::Uuid, by_parent: &mut HashMap<uuid::Uuid, Vec<SubSessionRow>>) -> Vec<Value> {
+        let mut children = by_parent.remove(&id).unwrap_or_default();
+        children.sort_by_key(|c| c.created_at);
+        children
+            .into_iter()
+            .map(|c| {
+                let id = c.id;
+                let mut node = serde_json::to_value(&c).unwrap_or_default();
+                node["children"] = json!(build(id, by_parent));
+                node
+            })
+            .collect()
+    }
+    build(root, &mut by_parent)
+}
+
+const ROW_COLUMNS: &str = "s.id, s.parent_id, s.step_id, s.forked_from, s.title, \
+     (SELECT COUNT(*) FROM ch_messages m WHERE m.session_id = s.id) AS message_count, \
+     s.created_at, s.updated_at";
+
+async fn fetch_session(state: &AppState, id: uuid::Uuid) -> Result<SubSessionRow, StatusCode> {
+    sqlx::query_as::<_, SubSessionRow>(&format!(
+        "SELECT {} FROM ch_sessions s WHERE s.id = $1",
+        ROW_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to get session: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?
+    .ok_or(StatusCode::NOT_FOUND)
+}
+
+// ── GET /api/sessions/{id}/children ─────────────────────────────────────────
+
+#[utoipa::path(get, path = "/api/sessions/{id}/children", tag = "sessions",
+    params(("id" = String, Path, description = "Session UUID")),
+    responses((status = 200, description = "Direct child sessions")))]
+pub async fn list_child_sessions(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, StatusCode> {
+    let session_id: uuid::Uuid = id.parse().map_err(|_| StatusCode::BAD_REQUEST)?;
+    fetch_session(&state, session_id).await?;
+
+    let rows = sqlx::query_as::<_, SubSessionRow>(&format!(
+        "SELECT {} FROM ch_sessions s WHERE s.parent_id = $1 ORDER BY s.created_at",
+        ROW_COLUMNS
+    ))
+    .bind(session_id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to list child sessions: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(json!({ "session_id": session_id, "children": rows })))
+}
+
+// ── POST /api/sessions/{id}/children ────────────────────────────────────────
+
+#[utoipa::path(post, path = "/api/sessions/{id}/children", tag = "sessions",
+    params(("id" = String, Path, description = "Parent session UUID")),
+    request_body = CreateChildSessionRequest,
+    responses((status = 201, description = "Child session created")))]
+pub async fn create_child_session(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    body: Option<Json<CreateChildSessionRequest>>,
+) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)> {
+    let bad_request = |msg: String| (StatusCode::BAD_REQUEST, Json(json!({ "error": msg })));
+    let parent_id: uuid::Uuid = id
+        .parse()
+        .map_err(|_| bad_request("Invalid session id".into()))?;
+    let req = body.map(|Json(b)| b).unwrap_or_default();
+    let step_id = normalize_step_id(req.step_id.as_deref()).map_err(bad_request)?;
+    let title: String = req
+        .title
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+        .unwrap_or_else(|| match &step_id {
+            Some(step) => format!("Sub-session: {}", step),
+            None => "Sub-session".to_string(),
+        })
+        .chars()
+        .take(200)
+        .collect();
+
+    let child = insert_child_session(&state.db, parent_id, step_id.as_deref(), &title)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to create child session: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Failed to create child session" })),
+            )
+        })?
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                Json(json!({ "error": "Parent session not found" })),
+            )
+        })?;
+
+    let row = fetch_session(&state, child)
+        .await
+        .map_err(|s| (s, Json(json!({ "error": "Failed to load child session" }))))?;
+    Ok((StatusCode::CREATED, Json(json!(row))))
+}
+
+// ── POST /api/sessions/{id}/fork ────────────────────────────────────────────
+
+#[derive(Debug, Clone, Default, Deserialize, ToSchema)]
+pub struct ForkSessionQuery {
+    /// Last message to copy (UUID); the whole conversation when omitted.
+    #[serde(default)]
+    pub at_message: Option<String>,
+}
+
+/// Optional body for forking a session.
+#[derive(Debug, Clone, Default, Deserialize, ToSchema)]
+pub struct ForkSessionRequest {
+    /// Title (default: "Fork: <parent title>").
+    #[serde(default)]
+    pub title: Option<String>,
+}
+
+#[utoipa::path(post, path = "/api/sessions/{id}/fork", tag = "sessions",
+    params(
+        ("id" = String, Path, description = "Session UUID"),
+        ("at_message" = Option<String>, Query, description = "Last message to copy")
+    ),
+    request_body(content = ForkSessionRequest, description = "Optional title"),
+    responses(
+        (status = 201, description = "Fork created"),
+        (status = 400, description = "Invalid id"),
+        (status = 404, description = "Session or message not found")
+    ))]
+pub async fn fork_session(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<ForkSessionQuery>,
+    body: Option<Json<ForkSessionRequest>>,
+) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)> {
+    let error = |status: StatusCode, msg: &str| (status, Json(json!({ "error": msg })));
+    let db_error = |e: sqlx::Error| {
+        tracing::error!("Failed to fork session: {}", e);
+        error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to fork session")
+    };
+    let session_id: uuid::Uuid = id
+        .parse()
+        .map_err(|_| error(StatusCode::BAD_REQUEST, "Invalid session id"))?;
+    let at_message: Option<uuid::Uuid> = match query.at_message.as_deref().filter(|m| !m.is_empty()) {
+        Some(m) => Some(
+            m.parse()
+                .map_err(|_| error(StatusCode::BAD_REQUEST, "Invalid at_message"))?,
+        ),
+        None => None,
+    };
+    let req = body.map(|Json(b)| b).unwrap_or_default();
+
+    let mut tx = state.db.begin().await.map_err(db_error)?;
+    let parent_title: String = sqlx::query_scalar("SELECT title FROM ch_sessions WHERE id = $1")
+        .bind(session_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| error(StatusCode::NOT_FOUND, "Session not found"))?;
+    // Cut-off: the chosen message, or the newest one.
+    let until: Option<(uuid::Uuid, chrono::DateTime<chrono::Utc>)> = match at_message {
+        Some(mid) => Some(
+            sqlx::query_as("SELECT id, created_at FROM ch_messages WHERE id = $1 AND session_id = $2")
+                .bind(mid)
+                .bind(session_id)
+                .fetch_optional(&mut *tx)
+                .await
+                .map_err(db_error)?
+                .ok_or_else(|| error(StatusCode::NOT_FOUND, "Message not found in this session"))?,
+        ),
+        None => sqlx::query_as(
+            "SELECT id, created_at FROM ch_messages WHERE session_id = $1 \
+             ORDER BY created_at DESC LIMIT 1",
+        )
+        .bind(session_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(db_error)?,
+    };
+
+    let title: String = req
+        .title
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+        .unwrap_or_else(|| format!("Fork: {}", parent_title))
+        .chars()
+        .take(200)
+        .collect();
+    let fork_id: uuid::Uuid = sqlx::query_scalar(
+        "INSERT INTO ch_sessions (title, parent_id, agent_id, working_directory, forked_from) \
+         SELECT $1, id, agent_id, working_directory, $3 FROM ch_sessions WHERE id = $2 \
+         RETURNING id",
+    )
+    .bind(&title)
+    .bind(session_id)
+    .bind(until.map(|(mid, _)| mid))
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(db_error)?;
+
+    let mut copied = 0;
+    if let Some((_, until)) = until {
+        // Fresh ids, original timestamps (keeps the order); tool interactions
+        // follow their message.
+        copied = sqlx::query_scalar::<_, i64>(
+            "WITH src AS ( \
+                 SELECT m.*, gen_random_uuid() AS new_id FROM ch_messages m \
+                 WHERE m.session_id = $1 AND m.created_at <= $3 \
+             ), copied AS ( \
+                 INSERT INTO ch_messages \
+                 (id, session_id, role, content, model, agent, author, pinned, compacted_at, created_at, parts) \
+                 SELECT new_id, $2, role, content, model, agent, author, pinned, compacted_at, created_at, parts \
+                 FROM src \
+                 RETURNING id \
+             ), tools AS ( \
+                 INSERT INTO ch_tool_interactions \
+                 (message_id, tool_use_id, tool_name, tool_input, result, is_error, executed_at) \
+                 SELECT src.new_id, t.tool_use_id, t.tool_name, t.tool_input, t.result, t.is_error, t.executed_at \
+                 FROM ch_tool_interactions t JOIN src ON t.message_id = src.id \
+             ) \
+             SELECT COUNT(*) FROM copied",
+        )
+        .bind(session_id)
+        .bind(fork_id)
+        .bind(until)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(db_error)?;
+    }
+    tx.commit().await.map_err(db_error)?;
+
+    let row = fetch_session(&state, fork_id)
+        .await
+        .map_err(|s| (s, Json(json!({ "error": "Failed to load forked session" }))))?;
+    let mut out = json!(row);
+    out["copied_messages"] = json!(copied);
+    Ok((StatusCode::CREATED, Json(out)))
+}
+
+// ── GET /api/sessions/{id}/tree ─────────────────────────────────────────────
+
+#[utoipa::path(get, path = "/api/sessions/{id}/tree", tag = "sessions",
+    params(("id" = String, Path, description = "Session UUID")),
+    responses((status = 200, description = "Ancestors and nested descendants")))]
+pub async fn get_session_tree(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, StatusCode> {
+    let session_id: uuid::Uuid = id.parse().map_err(|_| StatusCode::BAD_REQUEST)?;
+    let root = fetch_session(&state, session_id).await?;
+    let db_error = |e: sqlx::Error| {
+        tracing::error!("Failed to load session tree: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    // Root first, so the client can show a breadcrumb.
+    let ancestors = sqlx::query_as::<_, SubSessionRow>(&format!(
+        "WITH RECURSIVE up AS ( \
+             SELECT parent_id AS id, 1 AS depth FROM ch_sessions WHERE id = $1 \
+             UNION ALL \
+             SELECT p.parent_id, up.depth + 1 FROM ch_sessions p JOIN up ON p.id = up.id \
+             WHERE up.depth < $2 \
+         ) \
+         SELECT {} FROM up JOIN ch_sessions s ON s.id = up.id ORDER BY up.depth DESC",
+        ROW_COLUMNS
+    ))
+    .bind(session_id)
+    .bind(MAX_TREE_DEPTH)
+    .fetch_all(&state.db)
+    .await
+    .map_err(db_error)?;
+
+    let descendants = sqlx::query_as::<_, SubSessionRow>(&format!(
+        "WITH RECURSIVE down AS ( \
+             SELECT id, 1 AS depth FROM ch_sessions WHERE parent_id = $1 \
+             UNION ALL \
+             SELECT c.id, down.depth + 1 FROM ch_sessions c JOIN down ON c.parent_id = down.id \
+             WHERE down.depth < $2 \
+         ) \
+         SELECT {} FROM down JOIN ch_sessions s ON s.id = down.id",
+        ROW_COLUMNS
+    ))
+    .bind(session_id)
+    .bind(MAX_TREE_DEPTH)
+    .fetch_all(&state.db)
+    .await
+    .map_err(db_error)?;
+
+    let total = descendants.len();
+    let mut node = json!(root);
+    node["children"] = json!(nest(session_id, descendants));
+    Ok(Json(json!({
+        "ancestors": ancestors,
+        "session": node,
+        "descendant_count": total,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(id: u128, parent: Option<u128>, minute: u32) -> SubSessionRow {
+        let at = chrono::DateTime::parse_from_rfc3339(&format!("2026-10-16T10:{:02}:00Z", minute))
+            .unwrap()
+            .with_timezone(&chrono::Utc);
+        SubSessionRow {
+            id: uuid::Uuid::from_u128(id),
+            parent_id: parent.map(uuid::Uuid::from_u128),
+            step_id: None,
+            forked_from: None,
+            title: format!("s{}", id),
+            message_count: 0,
+            created_at: at,
+            updated_at: at,
+        }
+    }
+
+    #[test]
+    fn descendants_are_nested_in_creation_order() {
+        let tree = nest(
+            uuid::Uuid::from_u128(1),
+            vec![row(4, Some(2), 3), row(3, Some(1), 2), row(2, Some(1), 1)],
+        );
+        assert_eq!(tree.len(), 2);
+        assert_eq!(tree[0]["title"], "s2");
+        assert_eq!(tree[0]["children"][0]["title"], "s4");
+        assert_eq!(tree[1]["title"], "s3");
+        assert!(tree[1]["children"].as_array().unwrap().is_empty());
+    }
+
+    #[test]
+    fn step_ids_are_trimmed_and_bounded() {
+        assert_eq!(normalize_step_id(Some("  plan-1 ")).unwrap().as_deref(), Some("plan-1"));
+        assert_eq!(normalize_step_id(Some("   ")).unwrap(), None);
+        assert!(normalize_step_id(Some(&"x".repeat(MAX_STEP_ID_LEN + 1))).is_err());
+    }
+}
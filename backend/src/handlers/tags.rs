@@ -68,6 +68,18 @@ struct SearchRow {
     rank: Option<f32>,
 }
 
+/// Trim and lowercase tags, dropping empty and over-long ones (and duplicates).
+pub(crate) fn normalize_tags(tags: Vec<String>) -> Vec<String> {
+    let mut tags: Vec<String> = tags
+        .into_iter()
+        .map(|t| t.trim().to_lowercase())
+        .filter(|t| !t.is_empty() && t.len() <= 50)
+        .collect();
+    tags.sort();
+    tags.dedup();
+    tags
+}
+
 // ── GET /api/sessions/{id}/tags ─────────────────────────────────────────────
 
 #[utoipa::path(get, path = "/api/sessions/{id}/tags", tag = "tags",
@@ -131,14 +143,7 @@ pub async fn add_session_tags(
         })?
         .ok_or(StatusCode::NOT_FOUND)?;
 
-    // Validate tags
-    let tags: Vec<String> = req
-        .tags
-        .into_iter()
-        .map(|t| t.trim().to_lowercase())
-        .filter(|t| !t.is_empty() && t.len() <= 50)
-        .collect();
-
+    let tags = normalize_tags(req.tags);
     if tags.is_empty() {
         return Err(StatusCode::BAD_REQUEST);
     }
@@ -268,7 +273,7 @@ pub async fn search_sessions(
             .bind(&tag_filter)
             .bind(limit)
             .bind(offset)
-            .fetch_all(&state.db)
+            .fetch_all(crate::read_replica::pool(&state))
             .await
         } else {
             // Full-text search only (no tag filter)
@@ -289,7 +294,7 @@ pub async fn search_sessions(
             .bind(query_text)
             .bind(limit)
             .bind(offset)
-            .fetch_all(&state.db)
+            .fetch_all(crate::read_replica::pool(&state))
             .await
         }
     } else if has_tags {
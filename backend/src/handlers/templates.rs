@@ -0,0 +1,719 @@
+//! Conversation templates and interview mode.
+//!
+//! Endpoints:
+//! - `GET /api/templates` — list templates
+//! - `POST /api/templates` — create a template
+//! - `GET /api/templates/{id}` — one template
+//! - `PUT /api/templates/{id}` — replace a template
+//! - `DELETE /api/templates/{id}` — delete a template
+//! - `POST /api/templates/{id}/render` — fill a template's `{{variable}}` slots
+//! - `GET /api/sessions/{id}/interview` — interview state of a session
+//! - `POST /api/sessions/{id}/interview` — start an interview template in a session
+//! - `DELETE /api/sessions/{id}/interview` — cancel the session's interview
+//!
+//! A `prompt` template is rendered from variables the caller supplies. An
+//! `interview` template also lists questions, one per variable: starting it
+//! stores the first question as an assistant message, and while it is active
+//! every `POST /api/claude/chat/stream` for the session is answered by the
+//! backend — the last user message is recorded as the answer and the next
+//! question is streamed back without a model call. After the last answer the
+//! prompt is rendered, replaces that user message and the chat runs normally
+//! (as the template's agent, when it names one).
+
+use axum::Json;
+use axum::body::Body;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::Response;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+use utoipa::ToSchema;
+
+use jaskier_core::handlers::anthropic_streaming::build_ndjson_response;
+
+use crate::models::ChatRequest;
+use crate::state::AppState;
+
+const MAX_QUESTIONS: usize = 20;
+const MAX_PROMPT_CHARS: usize = 50_000;
+const MAX_ANSWER_CHARS: usize = 20_000;
+/// `model` reported in the done frame of a backend-asked question.
+const INTERVIEW_MODEL: &str = "interview";
+
+type ApiError = (StatusCode, Json<Value>);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TemplateKind {
+    /// Rendered from caller-supplied variables.
+    Prompt,
+    /// Variables are collected by asking `questions` in the chat first.
+    Interview,
+}
+
+impl TemplateKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Prompt => "prompt",
+            Self::Interview => "interview",
+        }
+    }
+}
+
+/// One interview step: the answer is stored in `variable`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TemplateQuestion {
+    pub variable: String,
+    pub question: String,
+}
+
+/// Request body for `POST /api/templates` and `PUT /api/templates/{id}`.
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct TemplateRequest {
+    pub name: String,
+    pub kind: TemplateKind,
+    #[serde(default)]
+    pub description: String,
+    /// Prompt text with `{{variable}}` slots.
+    pub prompt: String,
+    /// Interview questions (interview templates only).
+    #[serde(default)]
+    pub questions: Vec<TemplateQuestion>,
+    /// Agent the rendered prompt is sent as.
+    #[serde(default)]
+    pub agent_id: Option<String>,
+}
+
+/// Request body for `POST /api/templates/{id}/render`.
+#[derive(Debug, Clone, Default, Deserialize, ToSchema)]
+pub struct RenderTemplateRequest {
+    #[serde(default)]
+    pub variables: Map<String, Value>,
+}
+
+/// Request body for `POST /api/sessions/{id}/interview`.
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct StartInterviewRequest {
+    pub template_id: uuid::Uuid,
+    /// Answers known up front; their questions are skipped.
+    #[serde(default)]
+    pub variables: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct ConversationTemplate {
+    pub id: uuid::Uuid,
+    pub name: String,
+    pub kind: String,
+    pub description: String,
+    pub prompt: String,
+    pub questions: sqlx::types::Json<Vec<TemplateQuestion>>,
+    pub agent_id: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+const TEMPLATE_COLUMNS: &str = "id, name, kind, description, prompt, questions, agent_id, created_at, updated_at";
+
+fn db_error(e: sqlx::Error) -> ApiError {
+    tracing::error!("templates: query failed: {}", e);
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(json!({ "error": "Template storage unavailable" })),
+    )
+}
+
+fn bad_request(msg: impl Into<String>) -> ApiError {
+    (StatusCode::BAD_REQUEST, Json(json!({ "error": msg.into() })))
+}
+
+fn template_not_found() -> ApiError {
+    (StatusCode::NOT_FOUND, Json(json!({ "error": "Template not found" })))
+}
+
+fn placeholder_re() -> &'static regex::Regex {
+    static RE: std::sync::OnceLock<regex::Regex> = std::sync::OnceLock::new();
+    RE.get_or_init(|| regex::Regex::new(r"\{\{\s*([A-Za-z_][A-Za-z0-9_]*)\s*\}\}").expect("valid regex"))
+}
+
+fn valid_variable(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && name.len() <= 64
+}
+
+/// Distinct slot names used by `prompt`, in order of first use.
+fn placeholders(prompt: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for cap in placeholder_re().captures_iter(prompt) {
+        if !names.iter().any(|n| n == &cap[1]) {
+            names.push(cap[1].to_string());
+        }
+    }
+    names
+}
+
+fn variable_text(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::String(s) => Some(s.clone()),
+        other => Some(other.to_string()),
+    }
+}
+
+/// Fill every `{{variable}}` slot, or list the variables that are missing.
+fn render(prompt: &str, variables: &Map<String, Value>) -> Result<String, Vec<String>> {
+    let missing: Vec<String> = placeholders(prompt)
+        .into_iter()
+        .filter(|name| variables.get(name).and_then(variable_text).is_none())
+        .collect();
+    if !missing.is_empty() {
+        return Err(missing);
+    }
+    Ok(placeholder_re()
+        .replace_all(prompt, |cap: &regex::Captures| {
+            variables.get(&cap[1]).and_then(variable_text).unwrap_or_default()
+        })
+        .into_owned())
+}
+
+/// First question whose variable has no answer yet.
+fn next_question<'a>(questions: &'a [TemplateQuestion], answers: &Map<String, Value>) -> Option<&'a TemplateQuestion> {
+    questions.iter().find(|q| !answers.contains_key(&q.variable))
+}
+
+fn validate_template(req: &TemplateRequest) -> Result<(), String> {
+    if req.name.trim().is_empty() || req.name.chars().count() > 100 {
+        return Err("name must be 1-100 characters".into());
+    }
+    if req.prompt.trim().is_empty() || req.prompt.chars().count() > MAX_PROMPT_CHARS {
+        return Err(format!("prompt must be 1-{} characters", MAX_PROMPT_CHARS));
+    }
+    match req.kind {
+        TemplateKind::Prompt if !req.questions.is_empty() => {
+            Err("questions are only allowed on interview templates".into())
+        }
+        TemplateKind::Prompt => Ok(()),
+        TemplateKind::Interview => {
+            if req.questions.is_empty() || req.questions.len() > MAX_QUESTIONS {
+                return Err(format!("interview templates need 1-{} questions", MAX_QUESTIONS));
+            }
+            for (i, q) in req.questions.iter().enumerate() {
+                if !valid_variable(&q.variable) {
+                    return Err(format!("invalid variable name '{}'", q.variable));
+                }
+                if q.question.trim().is_empty() {
+                    return Err(format!("question for '{}' must not be empty", q.variable));
+                }
+                if req.questions[..i].iter().any(|p| p.variable == q.variable) {
+                    return Err(format!("variable '{}' is asked twice", q.variable));
+                }
+            }
+            let unasked: Vec<String> = placeholders(&req.prompt)
+                .into_iter()
+                .filter(|name| !req.questions.iter().any(|q| &q.variable == name))
+                .collect();
+            if unasked.is_empty() {
+                Ok(())
+            } else {
+                Err(format!("no question fills {}", unasked.join(", ")))
+            }
+        }
+    }
+}
+
+async fn validate_agent(state: &AppState, agent_id: Option<&str>) -> Result<(), ApiError> {
+    match agent_id {
+        Some(id) if !state.agents.read().await.iter().any(|a| a.id == id) => Err(bad_request(format!(
+            "Agent '{}' not found",
+            id
+        ))),
+        _ => Ok(()),
+    }
+}
+
+async fn load_template(state: &AppState, id: uuid::Uuid) -> Result<ConversationTemplate, ApiError> {
+    sqlx::query_as::<_, ConversationTemplate>(&format!(
+        "SELECT {} FROM ch_conversation_templates WHERE id = $1",
+        TEMPLATE_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(db_error)?
+    .ok_or_else(template_not_found)
+}
+
+fn name_conflict(name: &str) -> impl FnOnce(sqlx::Error) -> ApiError + '_ {
+    move |e| match e {
+        sqlx::Error::Database(ref db) if db.is_unique_violation() => (
+            StatusCode::CONFLICT,
+            Json(json!({ "error": format!("Template '{}' already exists", name) })),
+        ),
+        e => db_error(e),
+    }
+}
+
+// ── GET /api/templates ──────────────────────────────────────────────────────
+
+#[utoipa::path(get, path = "/api/templates", tag = "templates",
+    responses((status = 200, description = "Conversation templates")))]
+pub async fn list_templates(State(state): State<AppState>) -> Result<Json<Value>, ApiError> {
+    let templates = sqlx::query_as::<_, ConversationTemplate>(&format!(
+        "SELECT {} FROM ch_conversation_templates ORDER BY name",
+        TEMPLATE_COLUMNS
+    ))
+    .fetch_all(&state.db)
+    .await
+    .map_err(db_error)?;
+    Ok(Json(json!(templates)))
+}
+
+// ── POST /api/templates ─────────────────────────────────────────────────────
+
+#[utoipa::path(post, path = "/api/templates", tag = "templates",
+    request_body = TemplateRequest,
+    responses(
+        (status = 201, description = "Template created"),
+        (status = 400, description = "Invalid template"),
+        (status = 409, description = "A template with this name exists")
+    ))]
+pub async fn create_template(
+    State(state): State<AppState>,
+    Json(req): Json<TemplateRequest>,
+) -> Result<(StatusCode, Json<Value>), ApiError> {
+    validate_template(&req).map_err(bad_request)?;
+    validate_agent(&state, req.agent_id.as_deref()).await?;
+    let name = req.name.trim();
+    let template = sqlx::query_as::<_, ConversationTemplate>(&format!(
+        "INSERT INTO ch_conversation_templates (name, kind, description, prompt, questions, agent_id) \
+         VALUES ($1, $2, $3, $4, $5, $6) RETURNING {}",
+        TEMPLATE_COLUMNS
+    ))
+    .bind(name)
+    .bind(req.kind.as_str())
+    .bind(req.description.trim())
+    .bind(&req.prompt)
+    .bind(sqlx::types::Json(&req.questions))
+    .bind(&req.agent_id)
+    .fetch_one(&state.db)
+    .await
+    .map_err(name_conflict(name))?;
+    Ok((StatusCode::CREATED, Json(json!(template))))
+}
+
+// ── GET /api/templates/{id} ─────────────────────────────────────────────────
+
+#[utoipa::path(get, path = "/api/templates/{id}", tag = "templates",
+    params(("id" = String, Path, description = "Template ID")),
+    responses((status = 200, description = "Template"), (status = 404, description = "Template not found")))]
+pub async fn get_template(
+    State(state): State<AppState>,
+    Path(id): Path<uuid::Uuid>,
+) -> Result<Json<Value>, ApiError> {
+    Ok(Json(json!(load_template(&state, id).await?)))
+}
+
+// ── PUT /api/templates/{id} ─────────────────────────────────────────────────
+
+#[utoipa::path(put, path = "/api/templates/{id}", tag = "templates",
+    params(("id" = String, Path, description = "Template ID")),
+    request_body = TemplateRequest,
+    responses(
+        (status = 200, description = "Template updated"),
+        (status = 400, description = "Invalid template"),
+        (status = 404, description = "Template not found"),
+        (status = 409, description = "A template with this name exists")
+    ))]
+pub async fn update_template(
+    State(state): State<AppState>,
+    Path(id): Path<uuid::Uuid>,
+    Json(req): Json<TemplateRequest>,
+) -> Result<Json<Value>, ApiError> {
+    validate_template(&req).map_err(bad_request)?;
+    validate_agent(&state, req.agent_id.as_deref()).await?;
+    let name = req.name.trim();
+    let template = sqlx::query_as::<_, ConversationTemplate>(&format!(
+        "UPDATE ch_conversation_templates SET name = $2, kind = $3, description = $4, prompt = $5, \
+             questions = $6, agent_id = $7, updated_at = NOW() \
+         WHERE id = $1 RETURNING {}",
+        TEMPLATE_COLUMNS
+    ))
+    .bind(id)
+    .bind(name)
+    .bind(req.kind.as_str())
+    .bind(req.description.trim())
+    .bind(&req.prompt)
+    .bind(sqlx::types::Json(&req.questions))
+    .bind(&req.agent_id)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(name_conflict(name))?
+    .ok_or_else(template_not_found)?;
+    Ok(Json(json!(template)))
+}
+
+// ── DELETE /api/templates/{id} ──────────────────────────────────────────────
+
+#[utoipa::path(delete, path = "/api/templates/{id}", tag = "templates",
+    params(("id" = String, Path, description = "Template ID")),
+    responses((status = 204, description = "Template deleted"), (status = 404, description = "Template not found")))]
+pub async fn delete_template(
+    State(state): State<AppState>,
+    Path(id): Path<uuid::Uuid>,
+) -> Result<StatusCode, ApiError> {
+    let result = sqlx::query("DELETE FROM ch_conversation_templates WHERE id = $1")
+        .bind(id)
+        .execute(&state.db)
+        .await
+        .map_err(db_error)?;
+    if result.rows_affected() == 0 {
+        return Err(template_not_found());
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// ── POST /api/templates/{id}/render ─────────────────────────────────────────
+
+#[utoipa::path(post, path = "/api/templates/{id}/render", tag = "templates",
+    params(("id" = String, Path, description = "Template ID")),
+    request_body = RenderTemplateRequest,
+    responses(
+        (status = 200, description = "Rendered prompt"),
+        (status = 404, description = "Template not found"),
+        (status = 422, description = "Variables are missing")
+    ))]
+pub async fn render_template(
+    State(state): State<AppState>,
+    Path(id): Path<uuid::Uuid>,
+    Json(req): Json<RenderTemplateRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let template = load_template(&state, id).await?;
+    let prompt = render(&template.prompt, &req.variables).map_err(|missing| {
+        (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(json!({ "error": "Missing template variables", "missing": missing })),
+        )
+    })?;
+    Ok(Json(json!({ "prompt": prompt, "agent_id": template.agent_id })))
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  Interviews
+// ═══════════════════════════════════════════════════════════════════════
+
+#[derive(sqlx::FromRow)]
+struct ActiveInterview {
+    template_id: uuid::Uuid,
+    answers: sqlx::types::Json<Map<String, Value>>,
+    questions: sqlx::types::Json<Vec<TemplateQuestion>>,
+    prompt: String,
+    agent_id: Option<String>,
+}
+
+fn interview_progress(template_id: uuid::Uuid, questions: &[TemplateQuestion], answers: &Map<String, Value>) -> Value {
+    json!({
+        "template_id": template_id,
+        "answered": questions.iter().filter(|q| answers.contains_key(&q.variable)).count(),
+        "total": questions.len(),
+    })
+}
+
+fn parse_session_id(id: &str) -> Result<uuid::Uuid, ApiError> {
+    uuid::Uuid::parse_str(id).map_err(|_| bad_request("Invalid session ID"))
+}
+
+// ── GET /api/sessions/{id}/interview ────────────────────────────────────────
+
+#[utoipa::path(get, path = "/api/sessions/{id}/interview", tag = "templates",
+    params(("id" = String, Path, description = "Session ID")),
+    responses((status = 200, description = "Interview state"), (status = 404, description = "No interview in this session")))]
+pub async fn get_interview(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let session_id = parse_session_id(&id)?;
+    type Row = (
+        uuid::Uuid,
+        String,
+        sqlx::types::Json<Map<String, Value>>,
+        sqlx::types::Json<Vec<TemplateQuestion>>,
+    );
+    let row = sqlx::query_as::<_, Row>(
+        "SELECT i.template_id, i.status, i.answers, t.questions \
+         FROM ch_interviews i JOIN ch_conversation_templates t ON t.id = i.template_id \
+         WHERE i.session_id = $1",
+    )
+    .bind(session_id)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(db_error)?
+    .ok_or((
+        StatusCode::NOT_FOUND,
+        Json(json!({ "error": "No interview in this session" })),
+    ))?;
+    let (template_id, status, answers, questions) = row;
+    let mut body = interview_progress(template_id, &questions, &answers);
+    body["status"] = json!(status);
+    body["answers"] = json!(answers.0);
+    if status == "active" {
+        body["question"] = json!(next_question(&questions, &answers).map(|q| &q.question));
+    }
+    Ok(Json(body))
+}
+
+// ── POST /api/sessions/{id}/interview ───────────────────────────────────────
+
+#[utoipa::path(post, path = "/api/sessions/{id}/interview", tag = "templates",
+    params(("id" = String, Path, description = "Session ID")),
+    request_body = StartInterviewRequest,
+    responses(
+        (status = 201, description = "Interview started; the first question was added to the session"),
+        (status = 400, description = "Not an interview template"),
+        (status = 404, description = "Session or template not found")
+    ))]
+pub async fn start_interview(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(req): Json<StartInterviewRequest>,
+) -> Result<(StatusCode, Json<Value>), ApiError> {
+    let session_id = parse_session_id(&id)?;
+    let template = load_template(&state, req.template_id).await?;
+    if template.kind != TemplateKind::Interview.as_str() {
+        return Err(bad_request("Template is not an interview template"));
+    }
+    let answers: Map<String, Value> = req
+        .variables
+        .into_iter()
+        .filter(|(_, v)| variable_text(v).is_some())
+        .collect();
+    let Some(first) = next_question(&template.questions, &answers) else {
+        return Err(bad_request("Every question is already answered; use /render instead"));
+    };
+
+    let mut tx = state.db.begin().await.map_err(db_error)?;
+    sqlx::query(
+        "INSERT INTO ch_interviews (session_id, template_id, answers) VALUES ($1, $2, $3) \
+         ON CONFLICT (session_id) DO UPDATE SET template_id = $2, answers = $3, status = 'active', \
+             started_at = NOW(), updated_at = NOW()",
+    )
+    .bind(session_id)
+    .bind(template.id)
+    .bind(sqlx::types::Json(&answers))
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(ref db) if db.is_foreign_key_violation() => {
+            (StatusCode::NOT_FOUND, Json(json!({ "error": "Session not found" })))
+        }
+        e => db_error(e),
+    })?;
+    let message_id: uuid::Uuid = sqlx::query_scalar(
+        "INSERT INTO ch_messages (session_id, role, content, model) VALUES ($1, 'assistant', $2, $3) RETURNING id",
+    )
+    .bind(session_id)
+    .bind(&first.question)
+    .bind(INTERVIEW_MODEL)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(db_error)?;
+    tx.commit().await.map_err(db_error)?;
+
+    let mut body = interview_progress(template.id, &template.questions, &answers);
+    body["status"] = json!("active");
+    body["question"] = json!(first.question);
+    body["message_id"] = json!(message_id);
+    Ok((StatusCode::CREATED, Json(body)))
+}
+
+// ── DELETE /api/sessions/{id}/interview ─────────────────────────────────────
+
+#[utoipa::path(delete, path = "/api/sessions/{id}/interview", tag = "templates",
+    params(("id" = String, Path, description = "Session ID")),
+    responses((status = 204, description = "Interview cancelled"), (status = 404, description = "No active interview")))]
+pub async fn cancel_interview(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    let session_id = parse_session_id(&id)?;
+    let result = sqlx::query(
+        "UPDATE ch_interviews SET status = 'cancelled', updated_at = NOW() \
+         WHERE session_id = $1 AND status = 'active'",
+    )
+    .bind(session_id)
+    .execute(&state.db)
+    .await
+    .map_err(db_error)?;
+    if result.rows_affected() == 0 {
+        return Err((StatusCode::NOT_FOUND, Json(json!({ "error": "No active interview" }))));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Chat-stream hook: while the request's session has an active interview,
+/// record the last user message as the pending answer. Returns the next
+/// question as a finished NDJSON stream, or — after the last answer — replaces
+/// that message with the rendered prompt (and sets the template's agent) and
+/// returns `None` so the chat proceeds.
+pub(crate) async fn intercept_interview(state: &AppState, req: &mut ChatRequest) -> Result<Option<Response>, ApiError> {
+    let Some(session_id) = req
+        .session_id
+        .as_deref()
+        .and_then(|s| uuid::Uuid::parse_str(s).ok())
+    else {
+        return Ok(None);
+    };
+    let interview = match sqlx::query_as::<_, ActiveInterview>(
+        "SELECT i.template_id, i.answers, t.questions, t.prompt, t.agent_id \
+         FROM ch_interviews i JOIN ch_conversation_templates t ON t.id = i.template_id \
+         WHERE i.session_id = $1 AND i.status = 'active'",
+    )
+    .bind(session_id)
+    .fetch_optional(&state.db)
+    .await
+    {
+        Ok(Some(interview)) => interview,
+        Ok(None) => return Ok(None),
+        Err(e) => {
+            tracing::warn!("templates: interview lookup for {} failed: {}", session_id, e);
+            return Ok(None);
+        }
+    };
+    let Some(last) = req.messages.last_mut().filter(|m| m.role == "user") else {
+        return Ok(None);
+    };
+
+    let mut answers = interview.answers.0.clone();
+    let Some(pending) = next_question(&interview.questions, &answers) else {
+        return Ok(None);
+    };
+    let answer = last.content.trim();
+    if answer.is_empty() {
+        return Ok(Some(question_stream(pending, &interview, &answers)));
+    }
+    answers.insert(
+        pending.variable.clone(),
+        json!(answer.chars().take(MAX_ANSWER_CHARS).collect::<String>()),
+    );
+    let next = next_question(&interview.questions, &answers);
+    let status = if next.is_some() { "active" } else { "completed" };
+
+    // Only advance from the state we read: a concurrent answer wins, we retry.
+    let result = sqlx::query(
+        "UPDATE ch_interviews SET answers = $2, status = $3, updated_at = NOW() \
+         WHERE session_id = $1 AND status = 'active' AND answers = $4",
+    )
+    .bind(session_id)
+    .bind(sqlx::types::Json(&answers))
+    .bind(status)
+    .bind(&interview.answers)
+    .execute(&state.db)
+    .await
+    .map_err(db_error)?;
+    if result.rows_affected() == 0 {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(json!({ "error": "The interview changed; resend the answer" })),
+        ));
+    }
+
+    if let Some(next) = next {
+        return Ok(Some(question_stream(next, &interview, &answers)));
+    }
+    // Every slot is backed by a question (checked on save), so this renders.
+    last.content = render(&interview.prompt, &answers).map_err(|missing| {
+        bad_request(format!("Interview is missing {}", missing.join(", ")))
+    })?;
+    if req.agent_id.is_none() {
+        req.agent_id = interview.agent_id.clone();
+    }
+    tracing::info!(session_id = %session_id, template_id = %interview.template_id, "interview completed");
+    crate::request_scope::decide(
+        "template",
+        json!({ "source": "interview", "template_id": interview.template_id, "agent_id": req.agent_id }),
+    );
+    Ok(None)
+}
+
+fn question_stream(question: &TemplateQuestion, interview: &ActiveInterview, answers: &Map<String, Value>) -> Response {
+    let frames = [
+        json!({ "token": question.question, "done": false }),
+        json!({
+            "token": "",
+            "done": true,
+            "model": INTERVIEW_MODEL,
+            "interview": interview_progress(interview.template_id, &interview.questions, answers),
+        }),
+    ];
+    let body: String = frames
+        .iter()
+        .map(|f| format!("{}\n", serde_json::to_string(f).unwrap_or_default()))
+        .collect();
+    build_ndjson_response(Body::from(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn question(variable: &str) -> TemplateQuestion {
+        TemplateQuestion {
+            variable: variable.into(),
+            question: format!("What is the {}?", variable),
+        }
+    }
+
+    fn interview(prompt: &str, questions: Vec<TemplateQuestion>) -> TemplateRequest {
+        TemplateRequest {
+            name: "Bug report intake".into(),
+            kind: TemplateKind::Interview,
+            description: String::new(),
+            prompt: prompt.into(),
+            questions,
+            agent_id: None,
+        }
+    }
+
+    #[test]
+    fn render_fills_slots_and_reports_missing_ones() {
+        let vars: Map<String, Value> = serde_json::from_value(json!({ "title": "Crash", "count": 3 })).unwrap();
+        assert_eq!(
+            render("Bug: {{title}} ({{ count }}x) — {{title}}", &vars).unwrap(),
+            "Bug: Crash (3x) — Crash"
+        );
+        assert_eq!(
+            render("{{title}} on {{os}} / {{version}}", &vars),
+            Err(vec!["os".to_string(), "version".to_string()])
+        );
+        assert_eq!(render("No slots, {not one}", &Map::new()).unwrap(), "No slots, {not one}");
+    }
+
+    #[test]
+    fn questions_are_asked_in_order_skipping_answered_ones() {
+        let questions = vec![question("title"), question("steps"), question("expected")];
+        let mut answers = Map::new();
+        assert_eq!(next_question(&questions, &answers), Some(&questions[0]));
+        answers.insert("title".into(), json!("Crash"));
+        answers.insert("expected".into(), json!("No crash"));
+        assert_eq!(next_question(&questions, &answers), Some(&questions[1]));
+        answers.insert("steps".into(), json!("Click"));
+        assert_eq!(next_question(&questions, &answers), None);
+    }
+
+    #[test]
+    fn interview_templates_must_ask_for_every_slot() {
+        let questions = vec![question("title"), question("steps")];
+        assert!(validate_template(&interview("{{title}}: {{steps}}", questions.clone())).is_ok());
+        assert!(validate_template(&interview("{{title}} on {{os}}", questions.clone())).is_err());
+        assert!(validate_template(&interview("{{title}}", vec![])).is_err());
+        assert!(validate_template(&interview("{{title}}", vec![question("title"), question("title")])).is_err());
+        assert!(validate_template(&interview("x", vec![question("1st")])).is_err());
+
+        let mut prompt = interview("Summarize {{text}}", questions);
+        prompt.kind = TemplateKind::Prompt;
+        assert!(validate_template(&prompt).is_err());
+        prompt.questions.clear();
+        assert!(validate_template(&prompt).is_ok());
+    }
+}
@@ -0,0 +1,746 @@
+//! Usage event export for finance reconciliation.
+//!
+//! - `GET /api/usage?from=&to=&group_by=model|agent|day` — tokens and list
+//!   cost per model, agent or UTC day.
+//! - `GET /api/usage/export?format=csv|jsonl&from=&to=` — raw `ch_usage_events`
+//!   rows, streamed straight from the database cursor.
+//! - `GET /api/usage/reconciliation?month=YYYY-MM` — monthly report by model,
+//!   re-priced against `ch_model_prices`, with anomalous-spend days flagged.
+//! - `GET /api/usage/prices` / `PUT /api/usage/prices/{pattern}` — list price table.
+//! - `GET /api/usage/anomalies?days=` — anomalies raised by `usage_anomaly`.
+//! - `GET /api/usage/refusals?days=` — model refusals per model / agent (see `refusals`).
+
+use axum::Json;
+use axum::body::Body;
+use axum::extract::{Path, Query, State};
+use axum::http::{StatusCode, header};
+use axum::response::Response;
+use chrono::{DateTime, NaiveDate, Utc};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::state::AppState;
+
+// ── Query params / row types ────────────────────────────────────────────
+
+#[derive(Debug, Deserialize)]
+pub struct UsageExportQuery {
+    /// `csv` (default) or `jsonl`
+    pub format: Option<String>,
+    /// Inclusive lower bound — RFC 3339 timestamp or `YYYY-MM-DD`
+    pub from: Option<String>,
+    /// Exclusive upper bound — RFC 3339 timestamp or `YYYY-MM-DD`
+    pub to: Option<String>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct UsageEventRow {
+    pub id: i64,
+    pub created_at: DateTime<Utc>,
+    pub model: String,
+    pub input_tokens: i32,
+    pub output_tokens: i32,
+    pub total_tokens: i32,
+    pub cost_usd: f64,
+    pub estimated: bool,
+    pub source: String,
+    pub session_id: Option<uuid::Uuid>,
+    pub agent_id: Option<String>,
+    pub user_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UsageSummaryQuery {
+    /// Inclusive lower bound — RFC 3339 timestamp or `YYYY-MM-DD`
+    pub from: Option<String>,
+    /// Exclusive upper bound — RFC 3339 timestamp or `YYYY-MM-DD`
+    pub to: Option<String>,
+    /// `model` (default), `agent` or `day`
+    pub group_by: Option<String>,
+}
+
+#[derive(sqlx::FromRow)]
+struct GroupModelRow {
+    key: Option<String>,
+    model: String,
+    requests: i64,
+    input_tokens: i64,
+    output_tokens: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReconciliationQuery {
+    /// Billing month as `YYYY-MM` (default: current month)
+    pub month: Option<String>,
+}
+
+/// A configured list price, matched as a substring of the model id.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ModelPrice {
+    pub model_pattern: String,
+    pub input_usd_per_mtok: f64,
+    pub output_usd_per_mtok: f64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdatePriceRequest {
+    pub input_usd_per_mtok: f64,
+    pub output_usd_per_mtok: f64,
+}
+
+#[derive(sqlx::FromRow)]
+struct ModelMonthRow {
+    model: String,
+    events: i64,
+    input_tokens: i64,
+    output_tokens: i64,
+    recorded_cost_usd: f64,
+    estimated_events: i64,
+}
+
+#[derive(sqlx::FromRow)]
+struct DayRow {
+    day: NaiveDate,
+    model: String,
+    input_tokens: i64,
+    output_tokens: i64,
+}
+
+const CSV_HEADER: &str = "id,created_at,model,input_tokens,output_tokens,total_tokens,cost_usd,estimated,source,session_id,agent_id,user_id\n";
+
+// ── Helpers ─────────────────────────────────────────────────────────────
+
+/// Parse a range bound given either as RFC 3339 or as a bare `YYYY-MM-DD`
+/// date (interpreted as midnight UTC).
+pub(crate) fn parse_time_bound(raw: &str) -> Option<DateTime<Utc>> {
+    let raw = raw.trim();
+    if let Ok(ts) = DateTime::parse_from_rfc3339(raw) {
+        return Some(ts.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(raw, "%Y-%m-%d")
+        .ok()
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .map(|dt| dt.and_utc())
+}
+
+/// Quote a CSV field when it contains a delimiter, quote, or newline.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn csv_line(r: &UsageEventRow) -> String {
+    format!(
+        "{},{},{},{},{},{},{:.6},{},{},{},{},{}\n",
+        r.id,
+        r.created_at.to_rfc3339(),
+        csv_field(&r.model),
+        r.input_tokens,
+        r.output_tokens,
+        r.total_tokens,
+        r.cost_usd,
+        r.estimated,
+        csv_field(&r.source),
+        r.session_id.map(|s| s.to_string()).unwrap_or_default(),
+        csv_field(r.agent_id.as_deref().unwrap_or("")),
+        csv_field(r.user_id.as_deref().unwrap_or("")),
+    )
+}
+
+/// Resolve the list price for a model — the longest matching pattern wins.
+pub(crate) fn price_for(model: &str, prices: &[ModelPrice]) -> Option<(f64, f64)> {
+    let m = model.to_lowercase();
+    prices
+        .iter()
+        .filter(|p| m.contains(&p.model_pattern.to_lowercase()))
+        .max_by_key(|p| p.model_pattern.len())
+        .map(|p| (p.input_usd_per_mtok, p.output_usd_per_mtok))
+}
+
+pub(crate) fn list_cost(model: &str, input_tokens: i64, output_tokens: i64, prices: &[ModelPrice]) -> f64 {
+    let (input_price, output_price) = price_for(model, prices)
+        .unwrap_or_else(|| super::analytics::tier_pricing(super::analytics::model_tier(model)));
+    (input_tokens as f64 / 1_000_000.0) * input_price
+        + (output_tokens as f64 / 1_000_000.0) * output_price
+}
+
+/// Flag days whose spend is anomalously high relative to the month.
+///
+/// Uses the modified z-score (median / MAD), which is robust to the very
+/// outliers it is trying to find. When the MAD is zero (most days identical)
+/// any day above twice the median is flagged.
+pub(crate) fn flag_anomalous_days(costs: &[f64]) -> Vec<bool> {
+    fn median(values: &mut [f64]) -> f64 {
+        values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        let n = values.len();
+        if n == 0 {
+            0.0
+        } else if n % 2 == 1 {
+            values[n / 2]
+        } else {
+            (values[n / 2 - 1] + values[n / 2]) / 2.0
+        }
+    }
+
+    if costs.len() < 3 {
+        return vec![false; costs.len()];
+    }
+    let med = median(&mut costs.to_vec());
+    let mad = median(&mut costs.iter().map(|c| (c - med).abs()).collect::<Vec<_>>());
+
+    costs
+        .iter()
+        .map(|&c| {
+            if c <= med {
+                false
+            } else if mad > 0.0 {
+                0.6745 * (c - med) / mad > 3.5
+            } else {
+                c > med * 2.0 && c > 0.0
+            }
+        })
+        .collect()
+}
+
+/// Time range bound parser for query handlers — 400 on a malformed value.
+fn query_bound(raw: Option<&str>, name: &str) -> Result<Option<DateTime<Utc>>, (StatusCode, Json<Value>)> {
+    match raw {
+        None => Ok(None),
+        Some(s) => parse_time_bound(s).map(Some).ok_or_else(|| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": format!("Invalid '{}' — expected RFC 3339 or YYYY-MM-DD", name) })),
+            )
+        }),
+    }
+}
+
+pub(crate) async fn load_prices(db: &sqlx::PgPool) -> Result<Vec<ModelPrice>, sqlx::Error> {
+    sqlx::query_as::<_, ModelPrice>(
+        "SELECT model_pattern, input_usd_per_mtok, output_usd_per_mtok FROM ch_model_prices ORDER BY model_pattern",
+    )
+    .fetch_all(db)
+    .await
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  GET /api/usage
+// ═══════════════════════════════════════════════════════════════════════
+
+/// SQL expression for a `group_by` value (`None` when unknown).
+fn group_expr(group_by: &str) -> Option<&'static str> {
+    match group_by {
+        "model" => Some("model"),
+        "agent" => Some("agent_id"),
+        "day" => Some("to_char((created_at AT TIME ZONE 'UTC')::date, 'YYYY-MM-DD')"),
+        _ => None,
+    }
+}
+
+/// `GET /api/usage?from=&to=&group_by=model|agent|day` — usage and cost per group
+pub async fn usage_summary(
+    State(state): State<AppState>,
+    Query(q): Query<UsageSummaryQuery>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let group_by = q.group_by.as_deref().unwrap_or("model").trim().to_lowercase();
+    let expr = group_expr(&group_by).ok_or_else(|| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "group_by must be 'model', 'agent' or 'day'" })),
+        )
+    })?;
+    let from = query_bound(q.from.as_deref(), "from")?;
+    let to = query_bound(q.to.as_deref(), "to")?;
+
+    let db_err = |e: sqlx::Error| {
+        tracing::error!("usage summary query failed: {e}");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": "Failed to load usage" })),
+        )
+    };
+    let prices = load_prices(crate::read_replica::pool(&state)).await.map_err(db_err)?;
+
+    // Grouped by model as well, so every row can be priced
+    let rows = sqlx::query_as::<_, GroupModelRow>(&format!(
+        "SELECT {expr} AS key, model, COUNT(*) AS requests, \
+                COALESCE(SUM(input_tokens), 0)::BIGINT AS input_tokens, \
+                COALESCE(SUM(output_tokens), 0)::BIGINT AS output_tokens \
+         FROM ch_usage_events \
+         WHERE ($1::timestamptz IS NULL OR created_at >= $1) \
+           AND ($2::timestamptz IS NULL OR created_at < $2) \
+         GROUP BY 1, 2 ORDER BY 1 NULLS LAST, 2"
+    ))
+    .bind(from)
+    .bind(to)
+    .fetch_all(crate::read_replica::pool(&state))
+    .await
+    .map_err(db_err)?;
+
+    let mut groups: Vec<(Option<String>, i64, i64, i64, f64)> = Vec::new();
+    for r in &rows {
+        let cost = list_cost(&r.model, r.input_tokens, r.output_tokens, &prices);
+        match groups.last_mut() {
+            Some(last) if last.0 == r.key => {
+                last.1 += r.requests;
+                last.2 += r.input_tokens;
+                last.3 += r.output_tokens;
+                last.4 += cost;
+            }
+            _ => groups.push((r.key.clone(), r.requests, r.input_tokens, r.output_tokens, cost)),
+        }
+    }
+    let total_cost: f64 = groups.iter().map(|g| g.4).sum();
+    let data: Vec<Value> = groups
+        .iter()
+        .map(|(key, requests, input, output, cost)| {
+            json!({
+                group_by.as_str(): key,
+                "requests": requests,
+                "input_tokens": input,
+                "output_tokens": output,
+                "total_tokens": input + output,
+                "cost_usd": (cost * 10_000.0).round() / 10_000.0,
+            })
+        })
+        .collect();
+
+    Ok(Json(json!({
+        "group_by": group_by,
+        "from": from,
+        "to": to,
+        "data": data,
+        "total_requests": groups.iter().map(|g| g.1).sum::<i64>(),
+        "total_input_tokens": groups.iter().map(|g| g.2).sum::<i64>(),
+        "total_output_tokens": groups.iter().map(|g| g.3).sum::<i64>(),
+        "total_cost_usd": (total_cost * 10_000.0).round() / 10_000.0,
+    })))
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  GET /api/usage/export
+// ═══════════════════════════════════════════════════════════════════════
+
+/// `GET /api/usage/export?format=csv|jsonl&from=&to=` — export raw usage events
+pub async fn usage_export(
+    State(state): State<AppState>,
+    Query(q): Query<UsageExportQuery>,
+) -> Result<Response, (StatusCode, Json<Value>)> {
+    let format = q.format.as_deref().unwrap_or("csv").to_lowercase();
+    if format != "csv" && format != "jsonl" {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "format must be 'csv' or 'jsonl'" })),
+        ));
+    }
+
+    let from = query_bound(q.from.as_deref(), "from")?;
+    let to = query_bound(q.to.as_deref(), "to")?;
+
+    let filename = format!(
+        "usage_{}_{}.{}",
+        from.map(|d| d.format("%Y%m%d").to_string()).unwrap_or_else(|| "start".into()),
+        to.map(|d| d.format("%Y%m%d").to_string()).unwrap_or_else(|| "now".into()),
+        format
+    );
+    let content_type = if format == "csv" {
+        "text/csv; charset=utf-8"
+    } else {
+        "application/x-ndjson"
+    };
+
+    let db = crate::read_replica::pool(&state).clone();
+    let body_stream = async_stream::stream! {
+        if format == "csv" {
+            yield Ok::<_, std::io::Error>(axum::body::Bytes::from(CSV_HEADER));
+        }
+        let mut rows = sqlx::query_as::<_, UsageEventRow>(
+            "SELECT id, created_at, model, input_tokens, output_tokens, total_tokens, cost_usd, \
+                    estimated, source, session_id, agent_id, user_id \
+             FROM ch_usage_events \
+             WHERE ($1::timestamptz IS NULL OR created_at >= $1) \
+               AND ($2::timestamptz IS NULL OR created_at < $2) \
+             ORDER BY created_at ASC, id ASC",
+        )
+        .bind(from)
+        .bind(to)
+        .fetch(&db);
+
+        while let Some(row) = rows.next().await {
+            match row {
+                Ok(r) => {
+                    let line = if format == "csv" {
+                        csv_line(&r)
+                    } else {
+                        format!("{}\n", serde_json::to_string(&r).unwrap_or_default())
+                    };
+                    yield Ok(axum::body::Bytes::from(line));
+                }
+                Err(e) => {
+                    tracing::error!("usage export query failed: {}", e);
+                    yield Err(std::io::Error::other("usage export failed"));
+                    break;
+                }
+            }
+        }
+    };
+
+    Response::builder()
+        .header(header::CONTENT_TYPE, content_type)
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", filename),
+        )
+        .body(Body::from_stream(body_stream))
+        .map_err(|_| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Failed to build export response" })),
+            )
+        })
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  GET /api/usage/reconciliation
+// ═══════════════════════════════════════════════════════════════════════
+
+/// `GET /api/usage/reconciliation?month=2025-06` — monthly invoice reconciliation report
+pub async fn usage_reconciliation(
+    State(state): State<AppState>,
+    Query(q): Query<ReconciliationQuery>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let month = q
+        .month
+        .unwrap_or_else(|| Utc::now().format("%Y-%m").to_string());
+    let start = NaiveDate::parse_from_str(&format!("{}-01", month.trim()), "%Y-%m-%d").map_err(|_| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "month must be formatted as YYYY-MM" })),
+        )
+    })?;
+    let end = start
+        .checked_add_months(chrono::Months::new(1))
+        .unwrap_or(start);
+    let (from, to) = (
+        start.and_hms_opt(0, 0, 0).map(|d| d.and_utc()),
+        end.and_hms_opt(0, 0, 0).map(|d| d.and_utc()),
+    );
+
+    let db_err = |e: sqlx::Error| {
+        tracing::error!("usage reconciliation query failed: {e}");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": "Failed to build reconciliation report" })),
+        )
+    };
+
+    let prices = load_prices(crate::read_replica::pool(&state)).await.map_err(db_err)?;
+
+    let models = sqlx::query_as::<_, ModelMonthRow>(
+        "SELECT model, COUNT(*) AS events, \
+                COALESCE(SUM(input_tokens), 0)::BIGINT AS input_tokens, \
+                COALESCE(SUM(output_tokens), 0)::BIGINT AS output_tokens, \
+                COALESCE(SUM(cost_usd), 0)::DOUBLE PRECISION AS recorded_cost_usd, \
+                COUNT(*) FILTER (WHERE estimated) AS estimated_events \
+         FROM ch_usage_events WHERE created_at >= $1 AND created_at < $2 \
+         GROUP BY model ORDER BY model",
+    )
+    .bind(from)
+    .bind(to)
+    .fetch_all(crate::read_replica::pool(&state))
+    .await
+    .map_err(db_err)?;
+
+    let days = sqlx::query_as::<_, DayRow>(
+        "SELECT (created_at AT TIME ZONE 'UTC')::date AS day, model, \
+                COALESCE(SUM(input_tokens), 0)::BIGINT AS input_tokens, \
+                COALESCE(SUM(output_tokens), 0)::BIGINT AS output_tokens \
+         FROM ch_usage_events WHERE created_at >= $1 AND created_at < $2 \
+         GROUP BY 1, 2 ORDER BY 1",
+    )
+    .bind(from)
+    .bind(to)
+    .fetch_all(crate::read_replica::pool(&state))
+    .await
+    .map_err(db_err)?;
+
+    let by_model: Vec<Value> = models
+        .iter()
+        .map(|m| {
+            let list = list_cost(&m.model, m.input_tokens, m.output_tokens, &prices);
+            json!({
+                "model": m.model,
+                "events": m.events,
+                "estimated_events": m.estimated_events,
+                "input_tokens": m.input_tokens,
+                "output_tokens": m.output_tokens,
+                "recorded_cost_usd": (m.recorded_cost_usd * 100.0).round() / 100.0,
+                "list_cost_usd": (list * 100.0).round() / 100.0,
+                "difference_usd": ((list - m.recorded_cost_usd) * 100.0).round() / 100.0,
+            })
+        })
+        .collect();
+
+    // Collapse per-(day, model) rows into per-day totals at list price
+    let mut daily: Vec<(NaiveDate, i64, i64, f64)> = Vec::new();
+    for d in &days {
+        let cost = list_cost(&d.model, d.input_tokens, d.output_tokens, &prices);
+        match daily.last_mut() {
+            Some(last) if last.0 == d.day => {
+                last.1 += d.input_tokens;
+                last.2 += d.output_tokens;
+                last.3 += cost;
+            }
+            _ => daily.push((d.day, d.input_tokens, d.output_tokens, cost)),
+        }
+    }
+    let flags = flag_anomalous_days(&daily.iter().map(|d| d.3).collect::<Vec<_>>());
+    let daily_json: Vec<Value> = daily
+        .iter()
+        .zip(flags.iter())
+        .map(|((day, input, output, cost), anomalous)| {
+            json!({
+                "day": day.to_string(),
+                "input_tokens": input,
+                "output_tokens": output,
+                "list_cost_usd": (cost * 100.0).round() / 100.0,
+                "anomalous": anomalous,
+            })
+        })
+        .collect();
+
+    let total_list: f64 = daily.iter().map(|d| d.3).sum();
+    let total_recorded: f64 = models.iter().map(|m| m.recorded_cost_usd).sum();
+
+    Ok(Json(json!({
+        "month": month,
+        "from": from,
+        "to": to,
+        "by_model": by_model,
+        "daily": daily_json,
+        "anomalous_days": daily
+            .iter()
+            .zip(flags.iter())
+            .filter(|(_, a)| **a)
+            .map(|(d, _)| d.0.to_string())
+            .collect::<Vec<_>>(),
+        "total_recorded_cost_usd": (total_recorded * 100.0).round() / 100.0,
+        "total_list_cost_usd": (total_list * 100.0).round() / 100.0,
+        "prices": prices,
+    })))
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  Price table
+// ═══════════════════════════════════════════════════════════════════════
+
+/// `GET /api/usage/prices` — configured list prices
+pub async fn list_model_prices(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<ModelPrice>>, (StatusCode, Json<Value>)> {
+    load_prices(&state.db).await.map(Json).map_err(|e| {
+        tracing::error!("Failed to load model prices: {e}");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": "Failed to load model prices" })),
+        )
+    })
+}
+
+/// `PUT /api/usage/prices/{pattern}` — create or update a list price
+pub async fn upsert_model_price(
+    State(state): State<AppState>,
+    Path(pattern): Path<String>,
+    Json(req): Json<UpdatePriceRequest>,
+) -> Result<Json<ModelPrice>, (StatusCode, Json<Value>)> {
+    let pattern = pattern.trim().to_lowercase();
+    if pattern.is_empty() || req.input_usd_per_mtok < 0.0 || req.output_usd_per_mtok < 0.0 {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "pattern must be non-empty and prices non-negative" })),
+        ));
+    }
+
+    let row = sqlx::query_as::<_, ModelPrice>(
+        "INSERT INTO ch_model_prices (model_pattern, input_usd_per_mtok, output_usd_per_mtok) \
+         VALUES ($1, $2, $3) \
+         ON CONFLICT (model_pattern) DO UPDATE SET \
+             input_usd_per_mtok = EXCLUDED.input_usd_per_mtok, \
+             output_usd_per_mtok = EXCLUDED.output_usd_per_mtok, \
+             updated_at = NOW() \
+         RETURNING model_pattern, input_usd_per_mtok, output_usd_per_mtok",
+    )
+    .bind(&pattern)
+    .bind(req.input_usd_per_mtok)
+    .bind(req.output_usd_per_mtok)
+    .fetch_one(&state.db)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to upsert model price: {e}");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": "Failed to update model price" })),
+        )
+    })?;
+
+    crate::audit::log_audit(
+        &state.db,
+        "update_model_price",
+        json!({ "pattern": row.model_pattern, "input": row.input_usd_per_mtok, "output": row.output_usd_per_mtok }),
+        None,
+    )
+    .await;
+
+    Ok(Json(row))
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  GET /api/usage/anomalies
+// ═══════════════════════════════════════════════════════════════════════
+
+#[derive(Debug, Deserialize)]
+pub struct AnomaliesQuery {
+    /// Number of days to look back (default: 7, max 90)
+    pub days: Option<i32>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct UsageAnomalyRow {
+    pub id: i64,
+    pub kind: String,
+    pub subject: String,
+    pub window_day: NaiveDate,
+    pub details: Value,
+    pub created_at: DateTime<Utc>,
+}
+
+/// `GET /api/usage/anomalies?days=7` — recently detected usage anomalies
+pub async fn list_usage_anomalies(
+    State(state): State<AppState>,
+    Query(q): Query<AnomaliesQuery>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let days = q.days.unwrap_or(7).clamp(1, 90);
+    let rows = sqlx::query_as::<_, UsageAnomalyRow>(
+        "SELECT id, kind, subject, window_day, details, created_at FROM ch_usage_anomalies \
+         WHERE created_at >= NOW() - make_interval(days => $1) ORDER BY created_at DESC",
+    )
+    .bind(days)
+    .fetch_all(crate::read_replica::pool(&state))
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to list usage anomalies: {e}");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": "Failed to list usage anomalies" })),
+        )
+    })?;
+
+    Ok(Json(json!({ "data": rows, "days": days })))
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  Refusals
+// ═══════════════════════════════════════════════════════════════════════
+
+#[derive(Debug, Deserialize)]
+pub struct RefusalsQuery {
+    /// Number of days to look back (default: 30, max 365)
+    pub days: Option<i32>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct RefusalCountRow {
+    pub model: String,
+    pub agent_id: Option<String>,
+    pub refusals: i64,
+    pub stop_reason: i64,
+    pub heuristic: i64,
+    pub retried: i64,
+    pub resolved: i64,
+}
+
+/// `GET /api/usage/refusals?days=30` — refusal counts per model and agent
+pub async fn usage_refusals(
+    State(state): State<AppState>,
+    Query(q): Query<RefusalsQuery>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let days = q.days.unwrap_or(30).clamp(1, 365);
+    let rows = sqlx::query_as::<_, RefusalCountRow>(
+        "SELECT model, agent_id, COUNT(*) AS refusals, \
+                COUNT(*) FILTER (WHERE signal = 'stop_reason') AS stop_reason, \
+                COUNT(*) FILTER (WHERE signal = 'heuristic') AS heuristic, \
+                COUNT(*) FILTER (WHERE retried) AS retried, \
+                COUNT(*) FILTER (WHERE resolved) AS resolved \
+         FROM ch_refusals WHERE created_at >= NOW() - make_interval(days => $1) \
+         GROUP BY model, agent_id ORDER BY refusals DESC, model",
+    )
+    .bind(days)
+    .fetch_all(crate::read_replica::pool(&state))
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to count refusals: {e}");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": "Failed to count refusals" })),
+        )
+    })?;
+
+    let total: i64 = rows.iter().map(|r| r.refusals).sum();
+    Ok(Json(json!({ "data": rows, "total": total, "days": days })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_date_and_rfc3339_bounds() {
+        let d = parse_time_bound("2025-06-01").unwrap();
+        assert_eq!(d.to_rfc3339(), "2025-06-01T00:00:00+00:00");
+        let ts = parse_time_bound("2025-06-01T12:30:00+02:00").unwrap();
+        assert_eq!(ts.to_rfc3339(), "2025-06-01T10:30:00+00:00");
+        assert!(parse_time_bound("June 1st").is_none());
+    }
+
+    #[test]
+    fn only_known_groupings_are_accepted() {
+        assert_eq!(group_expr("agent"), Some("agent_id"));
+        assert!(group_expr("day").is_some());
+        assert!(group_expr("model; DROP TABLE ch_usage_events").is_none());
+    }
+
+    #[test]
+    fn csv_fields_are_quoted_when_needed() {
+        assert_eq!(csv_field("claude-sonnet-4-6"), "claude-sonnet-4-6");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn longest_price_pattern_wins() {
+        let prices = vec![
+            ModelPrice { model_pattern: "haiku".into(), input_usd_per_mtok: 0.25, output_usd_per_mtok: 1.25 },
+            ModelPrice { model_pattern: "haiku-4-5".into(), input_usd_per_mtok: 1.0, output_usd_per_mtok: 5.0 },
+        ];
+        assert_eq!(price_for("claude-haiku-4-5-20251001", &prices), Some((1.0, 5.0)));
+        assert_eq!(price_for("claude-3-haiku", &prices), Some((0.25, 1.25)));
+        assert_eq!(price_for("gpt-4o", &prices), None);
+    }
+
+    #[test]
+    fn spend_spike_is_flagged() {
+        let costs = [10.0, 11.0, 9.5, 10.5, 80.0, 10.2];
+        let flags = flag_anomalous_days(&costs);
+        assert_eq!(flags, vec![false, false, false, false, true, false]);
+    }
+
+    #[test]
+    fn flat_spend_flags_nothing() {
+        assert!(flag_anomalous_days(&[5.0; 10]).iter().all(|f| !f));
+        assert!(flag_anomalous_days(&[1.0, 50.0]).iter().all(|f| !f));
+    }
+}
@@ -0,0 +1,341 @@
+//! Organization-level usage from the Anthropic Admin API.
+//!
+//! - `GET /api/usage/upstream?from=&to=` — daily per-model token usage from
+//!   `/v1/organizations/usage_report/messages` plus cost from
+//!   `/v1/organizations/cost_report`, reconciled against `ch_usage_events`.
+//!   Days where Anthropic saw noticeably more tokens than ClaudeHydra recorded
+//!   are flagged as `outside_claudehydra` (another app or a leaked key).
+//!
+//! Requires `ANTHROPIC_ADMIN_KEY` (an `sk-ant-admin…` key); without it the
+//! endpoint answers 503.
+
+use std::collections::BTreeMap;
+
+use axum::Json;
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use chrono::{Duration, NaiveDate, Utc};
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::state::AppState;
+
+/// Relative slack before upstream usage counts as "outside ClaudeHydra" —
+/// local streaming usage is partly estimated.
+const DEFAULT_TOLERANCE: f64 = 0.10;
+/// Absolute slack so tiny days don't flag on rounding.
+const MIN_UNEXPLAINED_TOKENS: i64 = 10_000;
+const MAX_PAGES: usize = 20;
+
+type ApiError = (StatusCode, Json<Value>);
+
+#[derive(Debug, Deserialize)]
+pub struct UpstreamUsageQuery {
+    /// Inclusive start date `YYYY-MM-DD` (default: 7 days ago)
+    pub from: Option<String>,
+    /// Exclusive end date `YYYY-MM-DD` (default: tomorrow)
+    pub to: Option<String>,
+}
+
+/// Tokens for one (day, model) bucket.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Tokens {
+    pub input: i64,
+    pub output: i64,
+}
+
+impl Tokens {
+    fn total(self) -> i64 {
+        self.input + self.output
+    }
+}
+
+fn admin_key() -> Option<String> {
+    std::env::var("ANTHROPIC_ADMIN_KEY")
+        .ok()
+        .filter(|k| !k.trim().is_empty())
+}
+
+/// Strip a trailing `-YYYYMMDD` snapshot date so upstream and local ids match.
+pub fn normalize_model(model: &str) -> &str {
+    match model.rsplit_once('-') {
+        Some((base, date)) if date.len() == 8 && date.bytes().all(|b| b.is_ascii_digit()) => base,
+        _ => model,
+    }
+}
+
+/// Sum one usage-report result row into input/output tokens (cache reads and
+/// writes count as input, as they do on the invoice).
+fn result_tokens(r: &Value) -> Tokens {
+    let n = |p: &str| r.pointer(p).and_then(|v| v.as_i64()).unwrap_or(0);
+    Tokens {
+        input: n("/uncached_input_tokens")
+            + n("/cache_read_input_tokens")
+            + n("/cache_creation/ephemeral_5m_input_tokens")
+            + n("/cache_creation/ephemeral_1h_input_tokens"),
+        output: n("/output_tokens"),
+    }
+}
+
+/// Compare upstream and local buckets. Returns one row per (day, model).
+pub fn reconcile(
+    upstream: &BTreeMap<(NaiveDate, String), Tokens>,
+    local: &BTreeMap<(NaiveDate, String), Tokens>,
+    tolerance: f64,
+) -> Vec<Value> {
+    let mut keys: Vec<&(NaiveDate, String)> = upstream.keys().chain(local.keys()).collect();
+    keys.sort();
+    keys.dedup();
+    keys.into_iter()
+        .map(|key| {
+            let up = upstream.get(key).copied().unwrap_or_default();
+            let lo = local.get(key).copied().unwrap_or_default();
+            let unexplained = up.total() - lo.total();
+            let outside = unexplained > MIN_UNEXPLAINED_TOKENS
+                && unexplained as f64 > lo.total() as f64 * tolerance;
+            json!({
+                "day": key.0,
+                "model": key.1,
+                "upstream_input_tokens": up.input,
+                "upstream_output_tokens": up.output,
+                "local_input_tokens": lo.input,
+                "local_output_tokens": lo.output,
+                "unexplained_tokens": unexplained.max(0),
+                "outside_claudehydra": outside,
+            })
+        })
+        .collect()
+}
+
+/// Fetch every page of an Admin API report.
+async fn fetch_report(
+    state: &AppState,
+    key: &str,
+    path: &str,
+    params: &[(&str, String)],
+) -> Result<Vec<Value>, ApiError> {
+    let url = format!("{}{}", super::anthropic_api_url(), path);
+    let mut buckets = Vec::new();
+    let mut page: Option<String> = None;
+
+    for _ in 0..MAX_PAGES {
+        let mut req = state
+            .http_client
+            .get(&url)
+            .header("x-api-key", key)
+            .header("anthropic-version", "2023-06-01")
+            .query(params)
+            .timeout(std::time::Duration::from_secs(30));
+        if let Some(ref p) = page {
+            req = req.query(&[("page", p)]);
+        }
+        let resp = req.send().await.map_err(|e| {
+            tracing::error!("Anthropic admin API request failed: {}", e);
+            (
+                StatusCode::BAD_GATEWAY,
+                Json(json!({ "error": "Anthropic admin API unreachable" })),
+            )
+        })?;
+        let status = resp.status();
+        let body: Value = resp.json().await.unwrap_or_default();
+        if !status.is_success() {
+            tracing::error!("Anthropic admin API {} returned {}: {}", path, status, body);
+            return Err((
+                StatusCode::BAD_GATEWAY,
+                Json(json!({
+                    "error": format!("Anthropic admin API returned {}", status.as_u16()),
+                })),
+            ));
+        }
+        if let Some(data) = body.get("data").and_then(|d| d.as_array()) {
+            buckets.extend(data.iter().cloned());
+        }
+        page = body
+            .get("next_page")
+            .and_then(|p| p.as_str())
+            .map(str::to_string);
+        if !body.get("has_more").and_then(|v| v.as_bool()).unwrap_or(false) || page.is_none() {
+            break;
+        }
+    }
+    Ok(buckets)
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  GET /api/usage/upstream
+// ═══════════════════════════════════════════════════════════════════════
+
+/// `GET /api/usage/upstream?from=2026-10-01&to=2026-10-08` — org usage from Anthropic, reconciled
+pub async fn usage_upstream(
+    State(state): State<AppState>,
+    Query(q): Query<UpstreamUsageQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let Some(key) = admin_key() else {
+        return Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({
+                "error": "ANTHROPIC_ADMIN_KEY is not configured",
+                "configured": false,
+            })),
+        ));
+    };
+
+    let parse = |raw: &Option<String>, default: NaiveDate| match raw {
+        Some(s) => NaiveDate::parse_from_str(s.trim(), "%Y-%m-%d").map_err(|_| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": "from/to must be formatted as YYYY-MM-DD" })),
+            )
+        }),
+        None => Ok(default),
+    };
+    let today = Utc::now().date_naive();
+    let from = parse(&q.from, today - Duration::days(7))?;
+    let to = parse(&q.to, today + Duration::days(1))?;
+    if from >= to || (to - from).num_days() > 31 {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "Range must be 1–31 days" })),
+        ));
+    }
+    let starting_at = format!("{}T00:00:00Z", from);
+    let ending_at = format!("{}T00:00:00Z", to);
+
+    // ── Upstream usage ───────────────────────────────────────────────────
+    let usage = fetch_report(
+        &state,
+        &key,
+        "/v1/organizations/usage_report/messages",
+        &[
+            ("starting_at", starting_at.clone()),
+            ("ending_at", ending_at.clone()),
+            ("bucket_width", "1d".to_string()),
+            ("group_by[]", "model".to_string()),
+            ("limit", "31".to_string()),
+        ],
+    )
+    .await?;
+
+    let mut upstream: BTreeMap<(NaiveDate, String), Tokens> = BTreeMap::new();
+    for bucket in &usage {
+        let Some(day) = bucket
+            .get("starting_at")
+            .and_then(|v| v.as_str())
+            .and_then(|s| s.get(..10))
+            .and_then(|s| NaiveDate::parse_from_str(s, "%Y-%m-%d").ok())
+        else {
+            continue;
+        };
+        for r in bucket.get("results").and_then(|r| r.as_array()).into_iter().flatten() {
+            let model = r.get("model").and_then(|m| m.as_str()).unwrap_or("unknown");
+            let t = result_tokens(r);
+            let entry = upstream
+                .entry((day, normalize_model(model).to_string()))
+                .or_default();
+            entry.input += t.input;
+            entry.output += t.output;
+        }
+    }
+
+    // ── Upstream cost (amounts are decimal strings in cents) ─────────────
+    let cost = fetch_report(
+        &state,
+        &key,
+        "/v1/organizations/cost_report",
+        &[("starting_at", starting_at), ("ending_at", ending_at)],
+    )
+    .await?;
+    let upstream_cost_usd: f64 = cost
+        .iter()
+        .flat_map(|b| b.get("results").and_then(|r| r.as_array()).cloned().unwrap_or_default())
+        .filter_map(|r| {
+            r.get("amount")
+                .and_then(|a| a.as_str().and_then(|s| s.parse::<f64>().ok()).or_else(|| a.as_f64()))
+        })
+        .sum::<f64>()
+        / 100.0;
+
+    // ── Local accounting ─────────────────────────────────────────────────
+    let rows: Vec<(NaiveDate, String, i64, i64)> = sqlx::query_as(
+        "SELECT (created_at AT TIME ZONE 'UTC')::date AS day, model, \
+                COALESCE(SUM(input_tokens), 0)::BIGINT, COALESCE(SUM(output_tokens), 0)::BIGINT \
+         FROM ch_usage_events WHERE created_at >= $1::date AND created_at < $2::date \
+         GROUP BY 1, 2",
+    )
+    .bind(from)
+    .bind(to)
+    .fetch_all(&state.db)
+    .await
+    .map_err(|e| {
+        tracing::error!("upstream usage: local query failed: {e}");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": "Failed to load local usage" })),
+        )
+    })?;
+    let local_cost_usd: f64 = sqlx::query_scalar(
+        "SELECT COALESCE(SUM(cost_usd), 0)::DOUBLE PRECISION FROM ch_usage_events \
+         WHERE created_at >= $1::date AND created_at < $2::date",
+    )
+    .bind(from)
+    .bind(to)
+    .fetch_one(&state.db)
+    .await
+    .unwrap_or(0.0);
+
+    let mut local: BTreeMap<(NaiveDate, String), Tokens> = BTreeMap::new();
+    for (day, model, input, output) in rows {
+        let entry = local
+            .entry((day, normalize_model(&model).to_string()))
+            .or_default();
+        entry.input += input;
+        entry.output += output;
+    }
+
+    let tolerance = std::env::var("UPSTREAM_USAGE_TOLERANCE")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_TOLERANCE);
+    let days = reconcile(&upstream, &local, tolerance);
+    let flagged = days
+        .iter()
+        .filter(|d| d["outside_claudehydra"].as_bool().unwrap_or(false))
+        .count();
+
+    Ok(Json(json!({
+        "from": from,
+        "to": to,
+        "upstream_cost_usd": (upstream_cost_usd * 100.0).round() / 100.0,
+        "local_cost_usd": (local_cost_usd * 100.0).round() / 100.0,
+        "days": days,
+        "flagged": flagged,
+        "tolerance": tolerance,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_dates_are_stripped() {
+        assert_eq!(normalize_model("claude-sonnet-4-5-20250929"), "claude-sonnet-4-5");
+        assert_eq!(normalize_model("claude-opus-4-6"), "claude-opus-4-6");
+    }
+
+    #[test]
+    fn unexplained_upstream_usage_is_flagged() {
+        let day = NaiveDate::from_ymd_opt(2026, 10, 1).unwrap();
+        let key = (day, "claude-sonnet-4-6".to_string());
+        let mut upstream = BTreeMap::new();
+        let mut local = BTreeMap::new();
+        upstream.insert(key.clone(), Tokens { input: 200_000, output: 50_000 });
+        local.insert(key.clone(), Tokens { input: 195_000, output: 50_000 });
+        assert_eq!(reconcile(&upstream, &local, 0.1)[0]["outside_claudehydra"], false);
+
+        local.insert(key, Tokens { input: 20_000, output: 5_000 });
+        let rows = reconcile(&upstream, &local, 0.1);
+        assert_eq!(rows[0]["outside_claudehydra"], true);
+        assert_eq!(rows[0]["unexplained_tokens"], 225_000);
+    }
+}
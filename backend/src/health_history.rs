@@ -0,0 +1,245 @@
+// ClaudeHydra v4 -- Health history and uptime
+// Every watchdog probe (see watchdog.rs) is stored in `ch_health_checks`:
+// `anthropic` and `google` (API reachability, only while a credential is
+// configured) and `database`. `GET /api/health/history` turns the rows into
+// daily uptime percentages per component, plus the most recent failures.
+// Rows older than HEALTH_HISTORY_RETENTION_DAYS (default 90) are pruned.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use axum::Json;
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::state::AppState;
+
+const DEFAULT_RETENTION_DAYS: i32 = 90;
+const DEFAULT_HISTORY_DAYS: i32 = 30;
+const MAX_DETAIL_CHARS: usize = 500;
+const RECENT_FAILURES: i64 = 20;
+
+/// Outcome of one probe.
+#[derive(Debug, Clone)]
+pub struct Probe {
+    pub component: &'static str,
+    pub ok: bool,
+    pub latency: Duration,
+    /// Why it failed (status, error).
+    pub detail: Option<String>,
+}
+
+fn retention_days() -> i32 {
+    std::env::var("HEALTH_HISTORY_RETENTION_DAYS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|d| *d > 0)
+        .unwrap_or(DEFAULT_RETENTION_DAYS)
+}
+
+/// Store a probe result.
+pub async fn record(db: &sqlx::PgPool, probe: &Probe) {
+    let detail = probe
+        .detail
+        .as_deref()
+        .map(|d| d.chars().take(MAX_DETAIL_CHARS).collect::<String>());
+    if let Err(e) = sqlx::query(
+        "INSERT INTO ch_health_checks (component, ok, latency_ms, detail) VALUES ($1, $2, $3, $4)",
+    )
+    .bind(probe.component)
+    .bind(probe.ok)
+    .bind(probe.latency.as_millis().min(i32::MAX as u128) as i32)
+    .bind(detail)
+    .execute(db)
+    .await
+    {
+        tracing::warn!("health_history: failed to record {} probe: {}", probe.component, e);
+    }
+}
+
+/// Drop rows past the retention window.
+pub async fn prune(db: &sqlx::PgPool) {
+    match sqlx::query("DELETE FROM ch_health_checks WHERE checked_at < NOW() - make_interval(days => $1)")
+        .bind(retention_days())
+        .execute(db)
+        .await
+    {
+        Ok(r) if r.rows_affected() > 0 => {
+            tracing::info!("health_history: pruned {} old health checks", r.rows_affected())
+        }
+        Ok(_) => {}
+        Err(e) => tracing::warn!("health_history: prune failed: {}", e),
+    }
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  GET /api/health/history
+// ═══════════════════════════════════════════════════════════════════════
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryQuery {
+    /// Days to cover, today included (default 30, max the retention window).
+    pub days: Option<i32>,
+    /// Limit to one component (`anthropic`, `google`, `database`).
+    pub component: Option<String>,
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+struct DayRow {
+    component: String,
+    day: chrono::NaiveDate,
+    checks: i64,
+    ok_checks: i64,
+    avg_latency_ms: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct DayUptime {
+    day: chrono::NaiveDate,
+    checks: i64,
+    failed: i64,
+    uptime_pct: f64,
+    avg_latency_ms: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ComponentUptime {
+    component: String,
+    checks: i64,
+    failed: i64,
+    uptime_pct: Option<f64>,
+    days: Vec<DayUptime>,
+}
+
+fn pct(ok: i64, total: i64) -> f64 {
+    if total == 0 {
+        return 100.0;
+    }
+    (ok as f64 * 10_000.0 / total as f64).round() / 100.0
+}
+
+/// Group daily rows (sorted by component, day) into per-component uptime.
+fn summarize(rows: Vec<DayRow>) -> Vec<ComponentUptime> {
+    let mut by_component: BTreeMap<String, ComponentUptime> = BTreeMap::new();
+    for row in rows {
+        let entry = by_component
+            .entry(row.component.clone())
+            .or_insert_with(|| ComponentUptime {
+                component: row.component.clone(),
+                checks: 0,
+                failed: 0,
+                uptime_pct: None,
+                days: Vec::new(),
+            });
+        entry.checks += row.checks;
+        entry.failed += row.checks - row.ok_checks;
+        entry.days.push(DayUptime {
+            day: row.day,
+            checks: row.checks,
+            failed: row.checks - row.ok_checks,
+            uptime_pct: pct(row.ok_checks, row.checks),
+            avg_latency_ms: row.avg_latency_ms.map(|l| l.round()),
+        });
+    }
+    by_component
+        .into_values()
+        .map(|mut c| {
+            c.uptime_pct = (c.checks > 0).then(|| pct(c.checks - c.failed, c.checks));
+            c
+        })
+        .collect()
+}
+
+/// `GET /api/health/history` — daily uptime per provider / component and recent failures
+pub async fn health_history(
+    State(state): State<AppState>,
+    Query(q): Query<HistoryQuery>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let days = q.days.unwrap_or(DEFAULT_HISTORY_DAYS).clamp(1, retention_days());
+    let component = q.component.as_deref().map(str::trim).filter(|c| !c.is_empty());
+    let db_error = |e: sqlx::Error| {
+        tracing::error!("health_history: query failed: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": "Failed to load health history" })),
+        )
+    };
+
+    // Day boundaries in UTC; `days = 1` is today only.
+    let rows = sqlx::query_as::<_, DayRow>(
+        "SELECT component, (checked_at AT TIME ZONE 'UTC')::date AS day, \
+                COUNT(*) AS checks, COUNT(*) FILTER (WHERE ok) AS ok_checks, \
+                (AVG(latency_ms) FILTER (WHERE ok))::FLOAT8 AS avg_latency_ms \
+         FROM ch_health_checks \
+         WHERE checked_at >= ((NOW() AT TIME ZONE 'UTC')::date - ($1 - 1))::timestamp AT TIME ZONE 'UTC' \
+           AND ($2::TEXT IS NULL OR component = $2) \
+         GROUP BY 1, 2 ORDER BY 1, 2",
+    )
+    .bind(days)
+    .bind(component)
+    .fetch_all(&state.db)
+    .await
+    .map_err(db_error)?;
+
+    let failures = sqlx::query_as::<_, (String, chrono::DateTime<chrono::Utc>, Option<i32>, Option<String>)>(
+        "SELECT component, checked_at, latency_ms, detail FROM ch_health_checks \
+         WHERE NOT ok AND checked_at >= ((NOW() AT TIME ZONE 'UTC')::date - ($1 - 1))::timestamp AT TIME ZONE 'UTC' \
+           AND ($2::TEXT IS NULL OR component = $2) \
+         ORDER BY checked_at DESC LIMIT $3",
+    )
+    .bind(days)
+    .bind(component)
+    .bind(RECENT_FAILURES)
+    .fetch_all(&state.db)
+    .await
+    .map_err(db_error)?;
+
+    Ok(Json(json!({
+        "days": days,
+        "retention_days": retention_days(),
+        "components": summarize(rows),
+        "recent_failures": failures
+            .into_iter()
+            .map(|(component, at, latency_ms, detail)| json!({
+                "component": component,
+                "checked_at": at,
+                "latency_ms": latency_ms,
+                "detail": detail,
+            }))
+            .collect::<Vec<_>>(),
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(component: &str, day: u32, checks: i64, ok: i64) -> DayRow {
+        DayRow {
+            component: component.into(),
+            day: chrono::NaiveDate::from_ymd_opt(2026, 10, day).unwrap(),
+            checks,
+            ok_checks: ok,
+            avg_latency_ms: Some(120.4),
+        }
+    }
+
+    #[test]
+    fn uptime_is_computed_per_day_and_overall() {
+        let summary = summarize(vec![
+            row("anthropic", 15, 1440, 1440),
+            row("anthropic", 16, 1440, 1368),
+            row("database", 16, 1440, 1439),
+        ]);
+        assert_eq!(summary.len(), 2);
+        let anthropic = &summary[0];
+        assert_eq!(anthropic.component, "anthropic");
+        assert_eq!(anthropic.failed, 72);
+        assert_eq!(anthropic.days[1].uptime_pct, 95.0);
+        assert_eq!(anthropic.uptime_pct, Some(97.5));
+        assert_eq!(anthropic.days[0].avg_latency_ms, Some(120.0));
+        assert_eq!(summary[1].uptime_pct, Some(99.93));
+    }
+}
@@ -0,0 +1,289 @@
+// ClaudeHydra v4 -- Named key environments
+// A provider can hold several keys, one per environment (e.g. a low-limit
+// `dev` key next to the `prod` one). `POST /api/settings/api-key` with an
+// `environment` stores the key as `<provider>@<environment>`; keys without
+// one form the `default` environment (the pre-existing behaviour).
+//
+// The environment used for upstream calls is picked per request:
+//   1. `X-Key-Environment: <name>` header,
+//   2. the `key_environment` of the `chk_…` API token (a per-client profile),
+//   3. the active environment (`PUT /api/settings/key-environments`, kept in
+//      ch_settings and synced across replicas).
+// Outside `default`, only that environment's key is used: no Vault / OAuth
+// and no fallback to the default key, so a test run can never spend the
+// production quota. A missing key fails the call like a missing credential.
+
+use std::sync::RwLock;
+
+use axum::Json;
+use axum::extract::{Request, State};
+use axum::http::StatusCode;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use utoipa::ToSchema;
+
+use crate::state::AppState;
+
+/// Request header selecting the environment for one request.
+pub const HEADER: &str = "x-key-environment";
+/// Keys stored without an environment.
+pub const DEFAULT_ENVIRONMENT: &str = "default";
+const MAX_NAME_LEN: usize = 32;
+
+/// Environment chosen by the API token that authenticated the request.
+#[derive(Debug, Clone)]
+pub struct TokenKeyEnvironment(pub String);
+
+tokio::task_local! {
+    static REQUESTED: String;
+}
+
+/// Process-wide active environment (`None` = default).
+static ACTIVE: RwLock<Option<String>> = RwLock::new(None);
+
+/// Lower-case and check an environment name (`[a-z0-9_-]`, 1-32 chars).
+pub fn normalize(name: &str) -> Option<String> {
+    let name = name.trim().to_ascii_lowercase();
+    ((1..=MAX_NAME_LEN).contains(&name.len())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-')))
+    .then_some(name)
+}
+
+/// Legacy env-var style names map onto their provider.
+fn canonical_provider(provider: &str) -> &str {
+    match provider {
+        "ANTHROPIC_API_KEY" => "anthropic",
+        "GOOGLE_API_KEY" => "google",
+        other => other,
+    }
+}
+
+/// Name a key is stored under: `provider` for default, else `provider@environment`.
+pub fn key_name(provider: &str, environment: &str) -> String {
+    if environment == DEFAULT_ENVIRONMENT {
+        provider.to_string()
+    } else {
+        format!("{}@{}", canonical_provider(provider), environment)
+    }
+}
+
+/// Inverse of `key_name`: `(provider, environment)`.
+pub fn split_name(name: &str) -> (&str, &str) {
+    match name.split_once('@') {
+        Some((provider, environment)) => (provider, environment),
+        None => (canonical_provider(name), DEFAULT_ENVIRONMENT),
+    }
+}
+
+pub fn set_active(environment: Option<String>) {
+    let environment = environment.filter(|e| e != DEFAULT_ENVIRONMENT);
+    if let Ok(mut active) = ACTIVE.write() {
+        *active = environment;
+    }
+}
+
+fn global_active() -> String {
+    ACTIVE
+        .read()
+        .ok()
+        .and_then(|a| a.clone())
+        .unwrap_or_else(|| DEFAULT_ENVIRONMENT.to_string())
+}
+
+/// Environment asked for by the current request (header or token), if any.
+pub fn requested() -> Option<String> {
+    REQUESTED.try_with(Clone::clone).ok()
+}
+
+/// Environment upstream calls made now should use.
+pub fn active() -> String {
+    crate::request_scope::current()
+        .and_then(|s| s.key_environment.clone())
+        .or_else(requested)
+        .unwrap_or_else(global_active)
+}
+
+/// Key for `provider` in the active environment. `None` in the default
+/// environment (callers keep their usual credential chain); `Some(None)` when
+/// the environment has no key for the provider.
+pub async fn environment_key(state: &AppState, provider: &str) -> Option<Option<String>> {
+    let environment = active();
+    if environment == DEFAULT_ENVIRONMENT {
+        return None;
+    }
+    let key = state
+        .runtime
+        .read()
+        .await
+        .api_keys
+        .get(&key_name(provider, &environment))
+        .filter(|k| !k.trim().is_empty())
+        .cloned();
+    if key.is_none() {
+        tracing::warn!(
+            "key_environments: no {} key in environment '{}'",
+            provider,
+            environment
+        );
+    }
+    Some(key)
+}
+
+/// Google credential honouring the active environment (`(key, is_oauth)`).
+pub async fn google_credential(state: &AppState) -> Option<(String, bool)> {
+    match environment_key(state, "google").await {
+        Some(key) => key.map(|k| (k, false)),
+        None => jaskier_oauth::google::get_google_credential(state).await,
+    }
+}
+
+/// Load the active environment at startup.
+pub async fn load(db: &sqlx::PgPool) {
+    match sqlx::query_scalar::<_, Option<String>>("SELECT key_environment FROM ch_settings WHERE id = 1")
+        .fetch_optional(db)
+        .await
+    {
+        Ok(active) => set_active(active.flatten()),
+        Err(e) => tracing::warn!("key_environments: failed to load active environment: {}", e),
+    }
+}
+
+fn bad_request(msg: &str) -> Response {
+    (StatusCode::BAD_REQUEST, Json(json!({ "error": msg }))).into_response()
+}
+
+/// Middleware: run the request with the environment from the header or token.
+pub async fn select(req: Request, next: Next) -> Response {
+    let header = req
+        .headers()
+        .get(HEADER)
+        .map(|v| v.to_str().ok().and_then(normalize));
+    let requested = match header {
+        Some(Some(name)) => Some(name),
+        Some(None) => return bad_request("Invalid X-Key-Environment (use 1-32 of [a-z0-9_-])"),
+        None => req
+            .extensions()
+            .get::<TokenKeyEnvironment>()
+            .map(|e| e.0.clone()),
+    };
+    match requested {
+        Some(environment) => REQUESTED.scope(environment, next.run(req)).await,
+        None => next.run(req).await,
+    }
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  /api/settings/key-environments
+// ═══════════════════════════════════════════════════════════════════════
+
+/// `GET /api/settings/key-environments` — environments with their providers
+#[utoipa::path(get, path = "/api/settings/key-environments", tag = "auth",
+    responses((status = 200, description = "Key environments and the active one")))]
+pub async fn list_environments(State(state): State<AppState>) -> Json<Value> {
+    let mut environments: Vec<(String, Vec<String>)> = vec![(DEFAULT_ENVIRONMENT.to_string(), Vec::new())];
+    for (name, key) in state.runtime.read().await.api_keys.iter() {
+        if key.trim().is_empty() {
+            continue;
+        }
+        let (provider, environment) = split_name(name);
+        let idx = match environments.iter().position(|(e, _)| e == environment) {
+            Some(idx) => idx,
+            None => {
+                environments.push((environment.to_string(), Vec::new()));
+                environments.len() - 1
+            }
+        };
+        if !environments[idx].1.iter().any(|p| p == provider) {
+            environments[idx].1.push(provider.to_string());
+        }
+    }
+    environments.sort_by(|a, b| (a.0 != DEFAULT_ENVIRONMENT, &a.0).cmp(&(b.0 != DEFAULT_ENVIRONMENT, &b.0)));
+    let environments: Vec<Value> = environments
+        .into_iter()
+        .map(|(name, mut providers)| {
+            providers.sort();
+            json!({ "name": name, "providers": providers })
+        })
+        .collect();
+    Json(json!({
+        "active": global_active(),
+        "header": "X-Key-Environment",
+        "environments": environments,
+    }))
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SetActiveEnvironmentRequest {
+    /// Environment name; `null` or `default` goes back to the unsuffixed keys.
+    pub active: Option<String>,
+}
+
+/// `PUT /api/settings/key-environments` — switch the process-wide environment
+#[utoipa::path(put, path = "/api/settings/key-environments", tag = "auth",
+    request_body = SetActiveEnvironmentRequest,
+    responses(
+        (status = 200, description = "Active environment updated"),
+        (status = 400, description = "Invalid environment name")
+    ))]
+pub async fn set_active_environment(
+    State(state): State<AppState>,
+    Json(req): Json<SetActiveEnvironmentRequest>,
+) -> Response {
+    let active = match req.active.as_deref().map(normalize) {
+        None => None,
+        Some(Some(name)) if name == DEFAULT_ENVIRONMENT => None,
+        Some(Some(name)) => Some(name),
+        Some(None) => return bad_request("environment must be 1-32 of [a-z0-9_-]"),
+    };
+    if let Err(e) = sqlx::query("UPDATE ch_settings SET key_environment = $1, updated_at = NOW() WHERE id = 1")
+        .bind(&active)
+        .execute(&state.db)
+        .await
+    {
+        tracing::error!("key_environments: failed to store active environment: {}", e);
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": "Failed to update key environment" })),
+        )
+            .into_response();
+    }
+    set_active(active.clone());
+    crate::cluster::publish(
+        &state,
+        crate::cluster::ClusterEvent::KeyEnvironment {
+            active: active.clone(),
+        },
+    );
+    crate::audit::log_audit(
+        &state.db,
+        "set_key_environment",
+        json!({ "active": active }),
+        None,
+    )
+    .await;
+    Json(json!({ "active": active.unwrap_or_else(|| DEFAULT_ENVIRONMENT.to_string()) })).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_round_trip() {
+        assert_eq!(key_name("anthropic", "default"), "anthropic");
+        assert_eq!(key_name("ANTHROPIC_API_KEY", "dev"), "anthropic@dev");
+        assert_eq!(split_name("anthropic@dev"), ("anthropic", "dev"));
+        assert_eq!(split_name("GOOGLE_API_KEY"), ("google", "default"));
+    }
+
+    #[test]
+    fn environment_names_are_validated() {
+        assert_eq!(normalize(" Staging "), Some("staging".to_string()));
+        assert_eq!(normalize("prod-eu_1"), Some("prod-eu_1".to_string()));
+        assert_eq!(normalize(""), None);
+        assert_eq!(normalize("dev@x"), None);
+        assert_eq!(normalize(&"a".repeat(33)), None);
+    }
+}
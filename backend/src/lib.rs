@@ -1,26 +1,87 @@
+pub mod agent_catalog;
+pub mod agent_status;
 pub mod ai_gateway;
+pub mod api_tokens;
+pub mod artifacts;
 pub mod audit;
 pub mod auth;
 pub mod auto_qa;
 pub mod browser_proxy;
+#[cfg(feature = "chaos")]
+pub mod chaos;
+pub mod circuit;
+pub mod cluster;
+pub mod cold_storage;
+pub mod email_inbound;
+pub mod endpoint_policies;
+pub mod event_history;
 pub mod collab;
+pub mod compaction;
+pub mod crypto;
+pub mod github_review;
+pub mod github_triage;
 pub mod handlers;
+pub mod health_history;
+pub mod http_client;
+pub mod key_environments;
+pub mod maintenance;
 pub mod mcp;
 pub mod memory_pruning;
+pub mod message_chain;
+pub mod message_context;
 pub mod model_registry;
 pub mod models;
+pub mod object_store;
 pub mod ocr;
+pub mod oidc;
+pub mod principal;
+pub mod priority;
+#[cfg(all(feature = "pprof", unix))]
+pub mod profiling;
+pub mod prompt_canary;
+pub mod prompt_layers;
+pub mod providers;
+pub mod quotas;
 pub mod rate_limits;
+pub mod read_replica;
+pub mod recovery;
+pub mod refusals;
+pub mod request_scope;
 pub mod sandbox;
+pub mod secrets;
 pub mod semantic_cache;
+pub mod service;
+pub mod session_presence;
+pub mod session_transcript;
+pub mod shared_rate_limits;
+pub mod slack;
+pub mod soft_limits;
 pub mod state;
+pub mod statsd;
+pub mod stream_relay;
+pub mod stream_throughput;
+pub mod stream_watchdog;
+pub mod subsystems;
 pub mod swarm;
 pub mod system_monitor;
+pub mod tasks;
+pub mod tier_budgets;
+pub mod tier_models;
+pub mod token_count;
+pub mod tokenizer;
+pub mod tool_confirmation;
 pub mod tools;
+pub mod transcripts;
+pub mod update_check;
+pub mod usage;
+pub mod usage_anomaly;
 pub mod watchdog;
+pub mod web_session;
+pub mod webhooks;
+pub mod ws;
 
 use axum::Router;
-use axum::routing::{delete, get, patch, post};
+use axum::routing::{delete, get, patch, post, put};
 use jaskier_core::router_builder::{HydraRouterConfig, build_hydra_router, build_hydra_test_router};
 use utoipa::OpenApi;
 
@@ -32,7 +93,6 @@ use state::AppState;
 #[openapi(
     info(
         title = "ClaudeHydra v4 API",
-        version = "4.0.0",
         description = "AI Swarm Control Center — Backend API",
         license(name = "MIT")
     ),
@@ -43,26 +103,111 @@ use state::AppState;
         handlers::auth_mode,
         handlers::system_stats,
         handlers::system_metrics,
+        handlers::system_info,
         handlers::system_audit,
+        update_check::update_check,
+        service::service_status,
+        cold_storage::cold_storage_status,
+        cold_storage::run_cold_storage,
+        prompt_layers::preview_prompt,
+        token_count::count_tokens,
         // Agents
         handlers::list_agents,
         handlers::get_agent,
         handlers::create_agent,
+        handlers::import_agent_pack,
+        agent_catalog::list_catalog,
+        agent_catalog::install_pack,
         handlers::update_agent,
         handlers::delete_agent,
+        agent_status::set_agent_status,
+        handlers::run_agent,
+        tasks::create_task,
+        tasks::list_tasks,
+        tasks::get_task,
+        tasks::cancel_task,
+        handlers::agent_chat,
+        handlers::list_agent_tests,
+        handlers::create_agent_test,
+        handlers::delete_agent_test,
+        handlers::run_agent_tests,
         handlers::list_delegations,
         handlers::delegations_stream,
         // Chat
         handlers::claude_models,
         handlers::claude_chat,
         handlers::claude_chat_stream,
+        handlers::gemini_chat,
+        handlers::gemini_chat_stream,
+        handlers::start_debate,
+        // Conversation templates & interviews
+        handlers::list_templates,
+        handlers::create_template,
+        handlers::get_template,
+        handlers::update_template,
+        handlers::delete_template,
+        handlers::render_template,
+        handlers::get_interview,
+        handlers::start_interview,
+        handlers::cancel_interview,
+        // Integrations
+        github_review::github_review,
+        // Documents
+        handlers::summarize_document,
+        handlers::get_document_job,
+        handlers::ask_document,
         // Settings
         handlers::get_settings,
         handlers::update_settings,
         handlers::set_api_key,
+        handlers::delete_api_key,
+        handlers::list_api_keys,
+        key_environments::list_environments,
+        key_environments::set_active_environment,
+        http_client::test_proxy,
+        endpoint_policies::effective_policies,
+        tier_budgets::get_tier_budgets,
+        tier_budgets::set_tier_budgets,
+        tier_models::get_tier_models,
+        tier_models::set_tier_models,
+        quotas::get_quotas,
+        quotas::set_quotas,
+        prompt_canary::list_canaries,
+        prompt_canary::create_canary,
+        prompt_canary::get_canary,
+        prompt_canary::promote_canary,
+        prompt_canary::rollback_canary,
+        event_history::event_history,
         // Sessions (local overrides with utoipa annotations)
         handlers::get_session,
+        handlers::list_sessions_page,
+        handlers::update_session_metadata,
+        handlers::export_session,
+        handlers::bulk_delete_sessions,
+        handlers::bulk_delete_sessions_by_filter,
         handlers::add_session_message,
+        handlers::session_chat,
+        handlers::session_chat_stream,
+        handlers::regenerate_message,
+        // Snapshots
+        handlers::list_snapshots,
+        handlers::create_snapshot,
+        handlers::restore_snapshot,
+        // Hash chain
+        message_chain::get_chain,
+        message_chain::set_chain,
+        message_chain::verify_transcript,
+        // Sub-sessions
+        handlers::list_child_sessions,
+        handlers::create_child_session,
+        handlers::get_session_tree,
+        handlers::fork_session,
+        compaction::compact_session,
+        // Message comments
+        handlers::list_comments,
+        handlers::create_comment,
+        handlers::update_comment,
+        handlers::delete_comment,
         // Tags & search
         handlers::get_session_tags,
         handlers::add_session_tags,
@@ -88,23 +233,72 @@ use state::AppState;
         models::WitcherAgent,
         models::CreateAgentRequest,
         models::UpdateAgentRequest,
+        agent_status::SetStatusRequest,
+        models::AgentPack,
+        handlers::agent_run::AgentRunRequest,
+        handlers::agent_run::ToolCallLog,
+        tasks::CreateTaskRequest,
+        tasks::TaskKind,
+        handlers::agent_tests::Expectation,
+        handlers::agent_tests::CreateAgentTestRequest,
+        handlers::agent_tests::RunAgentTestsRequest,
         // Chat
         models::ChatRequest,
         models::ChatMessage,
+        models::ImageSource,
         models::ChatResponse,
+        providers::retry::RetryAttempt,
         models::UsageInfo,
         models::ClaudeModelInfo,
+        handlers::debate::DebateRequest,
+        handlers::documents::SummarizeDocumentRequest,
+        handlers::documents::AskDocumentRequest,
+        // Conversation templates
+        handlers::templates::TemplateKind,
+        handlers::templates::TemplateQuestion,
+        handlers::templates::TemplateRequest,
+        handlers::templates::RenderTemplateRequest,
+        handlers::templates::StartInterviewRequest,
+        // Integrations
+        github_review::GithubReviewRequest,
+        github_review::PostAs,
         // Settings
         models::AppSettings,
         models::ApiKeyRequest,
+        key_environments::SetActiveEnvironmentRequest,
+        tier_budgets::Tier,
+        tier_budgets::OnExhausted,
+        tier_budgets::TierBudget,
+        quotas::Quotas,
+        quotas::ModelQuota,
+        quotas::Period,
+        prompt_canary::Variant,
+        prompt_canary::CreateCanaryRequest,
+        prompt_layers::PromptLayer,
+        prompt_layers::PromptLayering,
+        http_client::HttpSettings,
+        endpoint_policies::EndpointPolicies,
+        endpoint_policies::EndpointPolicy,
+        http_client::ProxyTestRequest,
+        prompt_layers::LayerReport,
+        prompt_layers::PromptPreviewRequest,
         // Sessions
         models::Session,
         models::SessionSummary,
         models::HistoryEntry,
+        models::MessagePart,
         models::ToolInteractionInfo,
         models::CreateSessionRequest,
         models::UpdateSessionRequest,
         models::AddMessageRequest,
+        handlers::sessions::SessionSort,
+        handlers::sessions::SortOrder,
+        handlers::sessions::UpdateSessionMetadataRequest,
+        handlers::sessions::ExportFormat,
+        handlers::sessions::BulkDeleteFilter,
+        handlers::sessions::BulkDeleteRequest,
+        handlers::session_chat::SessionChatRequest,
+        handlers::session_chat::RegenerateRequest,
         // Model registry
         model_registry::ModelInfo,
         model_registry::ResolvedModels,
@@ -114,6 +308,16 @@ use state::AppState;
         // Tags
         handlers::tags::AddTagsRequest,
         handlers::tags::SearchResult,
+        // Snapshots
+        handlers::snapshots::CreateSnapshotRequest,
+        message_chain::SetChainRequest,
+        handlers::sub_sessions::CreateChildSessionRequest,
+        handlers::sub_sessions::ForkSessionRequest,
+        compaction::CompactSessionRequest,
+        token_count::TokenCountRequest,
+        // Message comments
+        handlers::comments::CreateCommentRequest,
+        handlers::comments::UpdateCommentRequest,
     )),
     tags(
         (name = "health", description = "Health & readiness endpoints"),
@@ -125,6 +329,9 @@ use state::AppState;
         (name = "models", description = "Dynamic model registry & pinning"),
         (name = "system", description = "System monitoring"),
         (name = "tags", description = "Session tagging & full-text search"),
+        (name = "templates", description = "Conversation templates & interview mode"),
+        (name = "documents", description = "Summaries of and questions over uploaded documents"),
+        (name = "integrations", description = "Slack & GitHub connectors"),
     )
 )]
 pub struct ApiDoc;
@@ -151,7 +358,11 @@ fn ch_primary_auth_routes() -> Router<AppState> {
 
 /// CH WebSocket chat route (maps to `ws_route` config slot).
 fn ch_ws_route() -> Router<AppState> {
-    Router::new().route("/ws/chat", get(handlers::ws_chat))
+    Router::new()
+        .route("/ws/chat", get(handlers::ws_chat))
+        // Multiplexed chat streams with per-request correlation ids
+        .route("/api/ws", get(ws::ws_handler))
+        .route("/ws/sessions/{id}", get(session_presence::ws_session_events))
 }
 
 /// CH streaming + non-streaming chat routes (maps to `execute_routes` config slot).
@@ -159,11 +370,24 @@ fn ch_ws_route() -> Router<AppState> {
 fn ch_chat_routes() -> Router<AppState> {
     Router::new()
         .route("/api/claude/chat/stream", post(handlers::claude_chat_stream))
+        .route(
+            "/api/claude/chat/stream/{stream_id}",
+            get(stream_relay::resume_stream),
+        )
+        .route(
+            "/api/claude/chat/stream/{stream_id}/cancel",
+            post(stream_relay::cancel_stream),
+        )
         .route("/api/claude/chat", post(handlers::claude_chat))
+        .route("/api/gemini/chat/stream", post(handlers::gemini_chat_stream))
+        .route("/api/gemini/chat", post(handlers::gemini_chat))
         .route("/api/prefetch/hints", post(handlers::prefetch_hints))
+        // Input tokens of a message list (`/api/tokens*` is the shared token API)
+        .route("/api/token-count", post(token_count::count_tokens))
+        .route("/api/debate", post(handlers::start_debate))
 }
 
-/// CH agents router — full agents CRUD + delegation monitoring (with auth).
+/// CH agents router — full agents CRUD, tool-loop runs, and delegation monitoring (with auth).
 /// Passed as `agents_router` (auth is applied by the caller via `route_layer`).
 fn ch_agents_router(state: AppState) -> Router<AppState> {
     Router::new()
@@ -175,9 +399,34 @@ fn ch_agents_router(state: AppState) -> Router<AppState> {
             "/api/agents/{id}",
             get(handlers::get_agent)
                 .put(handlers::update_agent)
+                .patch(handlers::update_agent)
                 .delete(handlers::delete_agent),
         )
+        .route("/api/agents/{id}/status", patch(agent_status::set_agent_status))
+        .route("/api/agents/{id}/run", post(handlers::run_agent))
+        .route("/api/agents/{id}/chat", post(handlers::agent_chat))
+        .route("/api/tasks", get(tasks::list_tasks).post(tasks::create_task))
+        .route("/api/tasks/{id}", get(tasks::get_task).delete(tasks::cancel_task))
+        .route(
+            "/api/agents/{id}/tests",
+            get(handlers::list_agent_tests).post(handlers::create_agent_test),
+        )
+        .route("/api/agents/{id}/tests/run", post(handlers::run_agent_tests))
+        .route(
+            "/api/agents/{id}/tests/{test_id}",
+            delete(handlers::delete_agent_test),
+        )
+        .route("/api/agents/import", post(handlers::import_agent_pack))
+        .route("/api/agents/catalog", get(agent_catalog::list_catalog))
+        .route(
+            "/api/agents/catalog/{pack_id}/install",
+            post(agent_catalog::install_pack),
+        )
         .route("/api/agents/refresh", post(handlers::refresh_agents))
+        .route(
+            "/api/agents/tiers",
+            get(tier_models::get_tier_models).put(tier_models::set_tier_models),
+        )
         .route("/api/agents/delegations", get(handlers::list_delegations))
         .route(
             "/api/agents/delegations/stream",
@@ -194,6 +443,22 @@ fn ch_files_router(state: AppState) -> Router<AppState> {
     Router::new()
         .route("/api/files/list", post(handlers::list_files))
         .route("/api/files/browse", post(handlers::browse_directory))
+        .route(
+            "/api/uploads",
+            post(handlers::upload_file).layer(axum::extract::DefaultBodyLimit::max(
+                // Multipart framing on top of the per-file limit
+                handlers::upload_max_bytes().saturating_add(64 * 1024),
+            )),
+        )
+        .route("/api/uploads/{id}", get(handlers::download_upload))
+        // Map-reduce summary of an uploaded PDF / text file, run as a job
+        .route(
+            "/api/documents/{id}/summarize",
+            post(handlers::summarize_document),
+        )
+        .route("/api/documents/jobs/{id}", get(handlers::get_document_job))
+        // One-shot question answering with citations (no knowledge base needed)
+        .route("/api/documents/{id}/ask", post(handlers::ask_document))
         .route_layer(axum::middleware::from_fn_with_state(
             state,
             auth::require_auth::<AppState>,
@@ -205,10 +470,30 @@ fn ch_files_router(state: AppState) -> Router<AppState> {
 /// Note: `/api/health`, `/api/health/ready`, `/api/health/detailed`, and
 /// `/api/auth/mode` are provided by `build_hydra_router` via `HasHealthState`
 /// handlers, so they are NOT registered here to avoid duplicate-route panics.
+/// `/api/health/history` (CH uptime history) is not one of them.
 fn ch_system_router(state: AppState) -> Router<AppState> {
     // Protected system endpoints (require auth)
     let protected = Router::new()
         .route("/api/system/stats", get(handlers::system_stats))
+        .route("/api/system/info", get(handlers::system_info))
+        .route("/api/system/update-check", get(update_check::update_check))
+        .route("/api/system/service", get(service::service_status))
+        .route(
+            "/api/system/stream-incidents",
+            get(stream_watchdog::stream_incidents),
+        )
+        .route(
+            "/api/system/stream-buffers",
+            get(stream_relay::stream_buffers),
+        )
+        .route(
+            "/api/system/concurrency",
+            get(priority::concurrency_status),
+        )
+        .route("/api/health/history", get(health_history::health_history))
+        // Stored event bus output, for clients catching up after being offline
+        .route("/api/events/history", get(event_history::event_history))
+        .route("/api/streams/recent", get(stream_throughput::recent_streams))
         .route("/api/admin/rotate-key", post(handlers::rotate_key))
         .route(
             "/api/admin/rate-limits",
@@ -218,10 +503,81 @@ fn ch_system_router(state: AppState) -> Router<AppState> {
             "/api/admin/rate-limits/{endpoint_group}",
             patch(rate_limits::update_rate_limit::<AppState>),
         )
-        .route_layer(axum::middleware::from_fn_with_state(
-            state.clone(),
-            auth::require_auth::<AppState>,
-        ));
+        .route(
+            "/api/admin/read-only",
+            get(maintenance::read_only_status).post(maintenance::set_read_only),
+        )
+        .route(
+            "/api/admin/tier-budgets",
+            get(tier_budgets::get_tier_budgets).put(tier_budgets::set_tier_budgets),
+        )
+        .route(
+            "/api/admin/quotas",
+            get(quotas::get_quotas).put(quotas::set_quotas),
+        )
+        .route(
+            "/api/admin/prompt-canaries",
+            get(prompt_canary::list_canaries).post(prompt_canary::create_canary),
+        )
+        .route("/api/admin/prompt-canaries/{id}", get(prompt_canary::get_canary))
+        .route(
+            "/api/admin/prompt-canaries/{id}/promote",
+            post(prompt_canary::promote_canary),
+        )
+        .route(
+            "/api/admin/prompt-canaries/{id}/rollback",
+            post(prompt_canary::rollback_canary),
+        )
+        .route("/api/admin/cold-storage", get(cold_storage::cold_storage_status))
+        .route(
+            "/api/admin/cold-storage/run",
+            post(cold_storage::run_cold_storage),
+        )
+        .route("/api/admin/subsystems", get(subsystems::list_subsystems))
+        .route(
+            "/api/admin/subsystems/{name}/pause",
+            post(subsystems::pause_subsystem),
+        )
+        .route(
+            "/api/admin/subsystems/{name}/resume",
+            post(subsystems::resume_subsystem),
+        )
+        .route(
+            "/api/admin/webhooks",
+            get(webhooks::list_webhooks).post(webhooks::create_webhook),
+        )
+        .route(
+            "/api/admin/webhooks/{id}",
+            delete(webhooks::delete_webhook),
+        )
+        .route("/api/admin/webhooks/{id}/test", post(webhooks::test_webhook))
+        .route(
+            "/api/admin/tool-policies",
+            get(tool_confirmation::list_policies),
+        )
+        .route(
+            "/api/admin/tool-policies/{tool}",
+            put(tool_confirmation::set_policy),
+        )
+        // Final system prompt for a session / agent / request, layer by layer
+        .route("/api/admin/prompt-preview", post(prompt_layers::preview_prompt))
+        // Operations cut short by a crash (see recovery): list, retry, dismiss
+        .route("/api/recovery", get(recovery::list_interrupted))
+        .route("/api/recovery/{id}", delete(recovery::dismiss_interrupted))
+        .route("/api/recovery/{id}/retry", post(recovery::retry_interrupted));
+    // Upstream fault injection, test builds only (see chaos)
+    #[cfg(feature = "chaos")]
+    let protected = protected.route(
+        "/api/admin/chaos",
+        get(chaos::get_faults).put(chaos::set_faults).delete(chaos::clear_faults),
+    );
+    // Whole-process CPU / heap profiles, opt-in builds only (see profiling)
+    #[cfg(all(feature = "pprof", unix))]
+    let protected = protected.route("/api/debug/pprof/{kind}", get(profiling::profile));
+    let protected = protected.route_layer(axum::middleware::from_fn_with_state(
+        state.clone(),
+        auth::require_auth::<AppState>,
+    ));
 
     // API key auth required for metrics/audit
     let api_key_auth = Router::new()
@@ -288,14 +644,55 @@ fn ch_ocr_routes() -> Router<AppState> {
 ///
 /// CH-specific session extensions that ARE safe to add here (not in `session_routes`):
 /// - `/api/sessions/search`         — CH full-text search (not in shared session_routes)
+/// - `/api/sessions/list`           — CH paginated listing with title search + sort
+/// - `/api/sessions/bulk-delete`    — CH delete by ids / filters (DELETE /api/sessions is not ours)
+/// - `/api/sessions/{id}/metadata`  — CH title / tags / pinned / archived update
+/// - `/api/sessions/{id}/export`    — CH JSON / Markdown transcript download
+/// - `/api/sessions/{id}/chat*`     — CH chat against stored history, both turns persisted
+/// - `/api/sessions/{id}/artifacts` — CH Mermaid / LaTeX artifacts of a session
 /// - `/api/sessions/{id}/tags*`     — CH session tagging (not in shared session_routes)
+/// - `/api/sessions/{id}/snapshots*` — CH session restore points
+/// - `/api/sessions/{id}/chain`, `/api/sessions/chain/verify` — CH message hash chain
+/// - `/api/sessions/{id}/children`, `/tree` — CH sub-session hierarchy
+/// - `/api/sessions/{id}/fork`      — CH conversation fork up to a message
+/// - `/api/sessions/{id}/compact`   — CH summary of older messages (context compaction)
+/// - `/api/sessions/{id}/presence`, `/append` — CH shared-session collaboration
+/// - `/api/sessions/{id}/messages/{mid}/comments*` — CH message comment threads
+/// - `/api/sessions/{id}/messages/{mid}/context`   — CH generation context of a reply
+/// - `/api/sessions/{id}/messages/{mid}/regenerate` — CH retry of an assistant reply
+/// - `/api/api-tokens*`             — CH scoped API tokens (`/api/tokens` is taken)
 /// - `/api/tags`                    — CH global tag listing
+/// - `/api/templates*`, `/api/sessions/{id}/interview` — CH conversation templates
 fn ch_app_protected_routes() -> Router<AppState> {
     Router::new()
         // Claude model list (CH-specific — Anthropic models, not Google)
         .route("/api/claude/models", get(handlers::claude_models))
         // Session search (literal path, NOT in shared session_routes)
         .route("/api/sessions/search", get(handlers::search_sessions))
+        // Paginated session listing (shared GET /api/sessions returns everything)
+        .route("/api/sessions/list", get(handlers::list_sessions_page))
+        // Delete many sessions: POST with ids and/or filters, DELETE with query filters
+        .route(
+            "/api/sessions/bulk-delete",
+            post(handlers::bulk_delete_sessions).delete(handlers::bulk_delete_sessions_by_filter),
+        )
+        // Title, tags, pinned and archived in one call (shared PATCH /api/sessions/{id} only renames)
+        .route(
+            "/api/sessions/{id}/metadata",
+            patch(handlers::update_session_metadata),
+        )
+        // Full transcript download (JSON / Markdown)
+        .route("/api/sessions/{id}/export", get(handlers::export_session))
+        // Chat against the stored history; user turn + reply are stored together
+        .route("/api/sessions/{id}/chat", post(handlers::session_chat))
+        .route("/api/sessions/{id}/chat/stream", post(handlers::session_chat_stream))
+        // Mermaid / LaTeX blocks from replies, rendered to SVG on demand
+        .route(
+            "/api/sessions/{id}/artifacts",
+            get(artifacts::list_session_artifacts),
+        )
+        .route("/api/artifacts/{id}", get(artifacts::get_artifact))
+        .route("/api/artifacts/{id}/render", get(artifacts::render_artifact))
         // Session tags (NOT in shared session_routes)
         .route(
             "/api/sessions/{id}/tags",
@@ -305,11 +702,152 @@ fn ch_app_protected_routes() -> Router<AppState> {
             "/api/sessions/{id}/tags/{tag}",
             delete(handlers::delete_session_tag),
         )
+        // Session snapshots / restore points (NOT in shared session_routes)
+        .route(
+            "/api/sessions/{id}/snapshots",
+            get(handlers::list_snapshots).post(handlers::create_snapshot),
+        )
+        .route(
+            "/api/sessions/{id}/snapshots/{sid}/restore",
+            post(handlers::restore_snapshot),
+        )
+        // Message hash chain (tamper evidence for exported transcripts)
+        .route(
+            "/api/sessions/{id}/chain",
+            get(message_chain::get_chain).put(message_chain::set_chain),
+        )
+        .route("/api/sessions/chain/verify", post(message_chain::verify_transcript))
+        // Sub-sessions spawned by orchestration steps (NOT in shared session_routes)
+        .route(
+            "/api/sessions/{id}/children",
+            get(handlers::list_child_sessions).post(handlers::create_child_session),
+        )
+        .route("/api/sessions/{id}/tree", get(handlers::get_session_tree))
+        .route("/api/sessions/{id}/fork", post(handlers::fork_session))
+        .route("/api/sessions/{id}/compact", post(compaction::compact_session))
+        // Conversation templates + interview mode (answers collected via chat stream)
+        .route(
+            "/api/templates",
+            get(handlers::list_templates).post(handlers::create_template),
+        )
+        .route(
+            "/api/templates/{id}",
+            get(handlers::get_template)
+                .put(handlers::update_template)
+                .delete(handlers::delete_template),
+        )
+        .route(
+            "/api/templates/{id}/render",
+            post(handlers::render_template),
+        )
+        .route(
+            "/api/sessions/{id}/interview",
+            get(handlers::get_interview)
+                .post(handlers::start_interview)
+                .delete(handlers::cancel_interview),
+        )
+        // Review comment threads on messages (never sent to the model)
+        .route(
+            "/api/sessions/{id}/messages/{mid}/comments",
+            get(handlers::list_comments).post(handlers::create_comment),
+        )
+        .route(
+            "/api/sessions/{id}/messages/{mid}/comments/{cid}",
+            patch(handlers::update_comment).delete(handlers::delete_comment),
+        )
+        // What was sent upstream when an assistant message was generated
+        .route(
+            "/api/sessions/{id}/messages/{mid}/context",
+            get(message_context::get_message_context),
+        )
+        // Retry button: answer again, replacing the reply and what followed
+        .route(
+            "/api/sessions/{id}/messages/{mid}/regenerate",
+            post(handlers::regenerate_message),
+        )
+        // Shared sessions: presence + attributed, conflict-safe appends
+        .route(
+            "/api/sessions/{id}/presence",
+            get(session_presence::get_presence),
+        )
+        .route(
+            "/api/sessions/{id}/append",
+            post(session_presence::append_message),
+        )
+        // Scoped API tokens for scripts / CI
+        .route(
+            "/api/api-tokens",
+            get(api_tokens::list_tokens).post(api_tokens::create_token),
+        )
+        .route("/api/api-tokens/{id}", delete(api_tokens::revoke_token))
+        // Agent-loop transcripts + replay with recorded tool results
+        .route("/api/transcripts", get(transcripts::list_transcripts))
+        .route("/api/transcripts/{id}", get(transcripts::get_transcript))
+        .route(
+            "/api/transcripts/{id}/replay",
+            post(transcripts::replay_transcript),
+        )
+        // Human-in-the-loop confirmation of flagged tool calls
+        .route("/api/tool-calls/pending", get(tool_confirmation::list_pending))
+        .route(
+            "/api/tool-calls/{id}/approve",
+            post(tool_confirmation::approve_tool_call),
+        )
+        .route(
+            "/api/tool-calls/{id}/reject",
+            post(tool_confirmation::reject_tool_call),
+        )
+        // Slack connector: bot token + channel ↔ session/agent mappings
+        .route(
+            "/api/integrations/slack",
+            get(slack::slack_status)
+                .put(slack::configure_slack)
+                .delete(slack::disconnect_slack),
+        )
+        .route(
+            "/api/integrations/slack/channels/{channel}",
+            put(slack::map_channel).delete(slack::unmap_channel),
+        )
+        // GitHub PR review by the Vesemir → Geralt workflow
+        .route(
+            "/api/integrations/github/review",
+            post(github_review::github_review),
+        )
+        // GitHub issue triage: per-repository settings and suggestions
+        .route(
+            "/api/integrations/github/repos",
+            get(github_triage::list_triage_repos),
+        )
+        .route(
+            "/api/integrations/github/repos/{owner}/{name}",
+            put(github_triage::configure_triage_repo).delete(github_triage::remove_triage_repo),
+        )
+        .route(
+            "/api/integrations/github/triage",
+            get(github_triage::list_triaged_issues),
+        )
+        .route(
+            "/api/integrations/github/triage/{id}/apply",
+            post(github_triage::apply_triage),
+        )
         // Global tags listing (NOT in shared session_routes)
         .route("/api/tags", get(handlers::list_all_tags))
         // Settings API key endpoint (CH-specific Anthropic key storage,
         // not in shared session_routes which only has /api/settings GET+PATCH)
         .route("/api/settings/api-key", post(handlers::set_api_key))
+        .route(
+            "/api/settings/api-key/{provider}",
+            delete(handlers::delete_api_key),
+        )
+        .route("/api/settings/api-keys", get(handlers::list_api_keys))
+        // Provider key environments (dev / staging / prod) and the active one
+        .route(
+            "/api/settings/key-environments",
+            get(key_environments::list_environments).put(key_environments::set_active_environment),
+        )
+        // Reachability of the Anthropic API through the configured proxy
+        .route("/api/settings/proxy/test", post(http_client::test_proxy))
+        .route("/api/settings/policies", get(endpoint_policies::effective_policies))
         // Analytics — agent performance dashboard (CH-specific)
         .route("/api/analytics/tokens", get(handlers::analytics_tokens))
         .route("/api/analytics/latency", get(handlers::analytics_latency))
@@ -319,6 +857,15 @@ fn ch_app_protected_routes() -> Router<AppState> {
         )
         .route("/api/analytics/top-tools", get(handlers::analytics_top_tools))
         .route("/api/analytics/cost", get(handlers::analytics_cost))
+        // Usage events — raw export for invoice reconciliation
+        .route("/api/usage", get(handlers::usage_summary))
+        .route("/api/usage/export", get(handlers::usage_export))
+        .route("/api/usage/reconciliation", get(handlers::usage_reconciliation))
+        .route("/api/usage/prices", get(handlers::list_model_prices))
+        .route("/api/usage/prices/{pattern}", put(handlers::upsert_model_price))
+        .route("/api/usage/anomalies", get(handlers::list_usage_anomalies))
+        .route("/api/usage/refusals", get(handlers::usage_refusals))
+        .route("/api/usage/upstream", get(handlers::usage_upstream))
 }
 
 /// Prometheus metrics endpoint (public, no auth).
@@ -342,6 +889,20 @@ fn ch_profiling_routes() -> Router<AppState> {
 //  Vault proxy routes — forward to Jaskier Vault MCP for the frontend
 // ═══════════════════════════════════════════════════════════════════════
 
+/// Cookie sessions for the served web UI (public — this is the sign-in),
+/// either with AUTH_SECRET or through the OIDC provider.
+fn ch_web_session_routes() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/api/auth/session",
+            get(web_session::session_status)
+                .post(web_session::create_session)
+                .delete(web_session::delete_session),
+        )
+        .route("/api/auth/oidc/login", get(oidc::oidc_login))
+        .route("/api/auth/oidc/callback", get(oidc::oidc_callback))
+}
+
 /// Vault proxy: public health endpoint (no auth).
 fn ch_vault_public_routes() -> Router<AppState> {
     Router::new()
@@ -501,10 +1062,19 @@ fn build_ch_config(state: AppState) -> HydraRouterConfig<AppState> {
 //  Public API
 // ═══════════════════════════════════════════════════════════════════════
 
-/// Webhook routes for Grafana alerts
-fn ch_auto_qa_routes() -> Router<AppState> {
+/// Inbound webhook routes — Grafana alerts, Slack events, GitHub issue events
+/// and inbound email (rejected while the `webhooks` subsystem is paused).
+/// Slack and GitHub requests carry their own signatures, email a Basic token.
+fn ch_auto_qa_routes(state: AppState) -> Router<AppState> {
     Router::new()
         .route("/api/webhooks/grafana", post(auto_qa::grafana_webhook::<AppState>))
+        .route("/api/integrations/slack/events", post(slack::slack_events))
+        .route("/api/integrations/github/events", post(github_triage::github_events))
+        .route("/api/integrations/email/inbound", post(email_inbound::email_inbound))
+        .route_layer(axum::middleware::from_fn_with_state(
+            state,
+            subsystems::webhook_guard,
+        ))
 }
 
 /// Build the application router with the given shared state.
@@ -530,8 +1100,9 @@ pub fn create_router(state: AppState) -> Router {
     let gateway_routes = ai_gateway::handlers::ai_gateway_router::<AppState>()
         .merge(ch_vault_public_routes())
         .merge(ch_vault_protected_routes(state.clone()))
+        .merge(ch_web_session_routes())
         // Webhooks: Grafana incidents
-        .merge(ch_auto_qa_routes())
+        .merge(ch_auto_qa_routes(state.clone()))
         // Profiling: Web Vitals collection endpoint (/api/vitals)
         .merge(ch_profiling_routes())
         // Swarm IPC: Cross-Agent Communication Protocol endpoints
@@ -549,9 +1120,49 @@ pub fn create_router(state: AppState) -> Router {
     // PERF: HTTP latency tracking middleware — records every request duration
     gateway_routes.merge(hydra_router)
         .layer(axum::middleware::from_fn_with_state(
-            state,
+            state.clone(),
             jaskier_core::profiling::latency_middleware::<AppState>,
         ))
+        // `Accept: text/markdown` / `text/plain` on GET /api/sessions/{id}
+        .layer(axum::middleware::from_fn(session_transcript::negotiate))
+        // Cold storage: restore a compressed session before it is served
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            cold_storage::thaw_on_access,
+        ))
+        // Provider key environment from `X-Key-Environment` / the API token
+        .layer(axum::middleware::from_fn(key_environments::select))
+        // Usage attribution: the session or API token behind the request
+        .layer(axum::middleware::from_fn(principal::attach))
+        // Chat rate limits counted across replicas (CLUSTER_SYNC)
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            shared_rate_limits::enforce,
+        ))
+        // Quotas: 429 on chat once a spend cap is reached; status on /api/health
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            quotas::enforce,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            handlers::report_on_health,
+        ))
+        // Maintenance: reject mutations with 503 while read-only mode is on
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            maintenance::read_only_guard,
+        ))
+        // Web UI: session cookie (+ CSRF) as an alternative to bearer tokens
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            web_session::cookie_auth,
+        ))
+        // Scoped `chk_…` API tokens for scripts / CI
+        .layer(axum::middleware::from_fn_with_state(
+            state,
+            api_tokens::token_auth,
+        ))
 }
 
 /// Test-only router — identical routes but **without** `GovernorLayer` rate
@@ -567,8 +1178,9 @@ pub fn create_test_router(state: AppState) -> Router {
     let gateway_routes = ai_gateway::handlers::ai_gateway_router::<AppState>()
         .merge(ch_vault_public_routes())
         .merge(ch_vault_protected_routes(state.clone()))
+        .merge(ch_web_session_routes())
         // Webhooks: Grafana incidents
-        .merge(ch_auto_qa_routes())
+        .merge(ch_auto_qa_routes(state.clone()))
         .merge(ch_profiling_routes())
         // CRDT Real-time Collaboration
         .merge(jaskier_collab::collab_router::<AppState>())
@@ -582,7 +1194,35 @@ pub fn create_test_router(state: AppState) -> Router {
 
     gateway_routes.merge(hydra_router)
         .layer(axum::middleware::from_fn_with_state(
-            state,
+            state.clone(),
             jaskier_core::profiling::latency_middleware::<AppState>,
         ))
+        .layer(axum::middleware::from_fn(session_transcript::negotiate))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            cold_storage::thaw_on_access,
+        ))
+        .layer(axum::middleware::from_fn(key_environments::select))
+        // Usage attribution: the session or API token behind the request
+        .layer(axum::middleware::from_fn(principal::attach))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            quotas::enforce,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            handlers::report_on_health,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            maintenance::read_only_guard,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            web_session::cookie_auth,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state,
+            api_tokens::token_auth,
+        ))
 }
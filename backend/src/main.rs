@@ -1,4 +1,4 @@
-use http::{Method, header};
+use http::{HeaderName, Method, header};
 use tower_http::compression::CompressionLayer;
 use tower_http::cors::CorsLayer;
 use tower_http::set_header::SetResponseHeaderLayer;
@@ -13,6 +13,17 @@ use claudehydra_backend::watchdog;
 
 use jaskier_core::app_builder;
 
+// jemalloc with heap sampling compiled in; it stays off until activated at
+// startup with CH_PPROF=1 (see profiling).
+#[cfg(all(feature = "pprof", target_os = "linux"))]
+#[global_allocator]
+static GLOBAL: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
+
+#[cfg(all(feature = "pprof", target_os = "linux"))]
+#[allow(non_upper_case_globals)]
+#[unsafe(export_name = "malloc_conf")]
+pub static malloc_conf: &[u8] = b"prof:true,prof_active:false,lg_prof_sample:19\0";
+
 fn build_app(state: AppState) -> axum::Router {
     // CORS — allow Vite dev server + Vercel production
     let cors = CorsLayer::new()
@@ -45,11 +56,22 @@ fn build_app(state: AppState) -> axum::Router {
         .allow_methods([
             Method::GET,
             Method::POST,
+            Method::PUT,
             Method::PATCH,
             Method::DELETE,
             Method::OPTIONS,
         ])
-        .allow_headers([header::CONTENT_TYPE, header::AUTHORIZATION])
+        .allow_headers([
+            header::CONTENT_TYPE,
+            header::AUTHORIZATION,
+            HeaderName::from_static(claudehydra_backend::key_environments::HEADER),
+        ])
+        // Stream id for resume / cancel (see stream_relay)
+        .expose_headers([
+            HeaderName::from_static(claudehydra_backend::stream_relay::STREAM_ID_HEADER),
+            HeaderName::from_static(claudehydra_backend::stream_relay::STREAM_INSTANCE_HEADER),
+            HeaderName::from_static(claudehydra_backend::token_count::WARNING_HEADER),
+        ])
         .max_age(std::time::Duration::from_secs(86_400));
 
     // Rate limiting: per-endpoint governors configured in lib.rs (#21)
@@ -136,6 +158,7 @@ async fn main() -> shuttle_axum::ShuttleAxum {
         .expect("Migrations failed");
 
     let log_buffer = std::sync::Arc::new(LogRingBuffer::new(1000));
+    claudehydra_backend::read_replica::init().await;
     let state = AppState::new(pool, log_buffer).await;
 
     // ── Spawn system monitor (CPU/memory stats, refreshed every 5s) ──
@@ -143,6 +166,16 @@ async fn main() -> shuttle_axum::ShuttleAxum {
 
     model_registry::startup_sync(&state).await;
     handlers::warm_prompt_cache(&state).await;
+    claudehydra_backend::key_environments::load(&state.db).await;
+    claudehydra_backend::tier_budgets::load(&state.db).await;
+    claudehydra_backend::quotas::load(&state.db).await;
+    claudehydra_backend::prompt_canary::load(&state.db).await;
+    claudehydra_backend::http_client::load(&state.db).await;
+    claudehydra_backend::endpoint_policies::load(&state.db).await;
+    #[cfg(all(feature = "pprof", unix))]
+    claudehydra_backend::profiling::init().await;
+    claudehydra_backend::tier_models::load(&state.db).await;
+    claudehydra_backend::recovery::recover(&state.db).await;
     state.mark_ready();
     Ok(build_app(state).into())
 }
@@ -151,6 +184,8 @@ async fn main() -> shuttle_axum::ShuttleAxum {
 #[cfg(not(feature = "shuttle"))]
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    // Windows service control manager hand-shake (`--service`) comes first.
+    claudehydra_backend::service::init();
     app_builder::enable_ansi();
     let log_buffer = app_builder::init_tracing(1000);
 
@@ -165,6 +200,7 @@ async fn main() -> anyhow::Result<()> {
         tracing::warn!("Migration skipped (schema likely exists): {}", e);
     }
 
+    claudehydra_backend::read_replica::init().await;
     let state = AppState::new(pool, log_buffer).await;
 
     // ── Spawn system monitor (CPU/memory stats, refreshed every 5s) ──
@@ -230,6 +266,39 @@ async fn main() -> anyhow::Result<()> {
     // ── Spawn background watchdog ──
     let _watchdog = watchdog::spawn(state.clone());
 
+    // ── Multi-instance sync over Postgres LISTEN/NOTIFY (CLUSTER_SYNC=1) ──
+    claudehydra_backend::cluster::spawn_listener(state.clone());
+
+    // ── Spawn usage anomaly detector (token spikes, heavy sessions, odd hours) ──
+    let _usage_anomaly = claudehydra_backend::usage_anomaly::spawn(state.clone());
+
+    // ── Cold storage: compress sessions idle for CH_COLD_STORAGE_DAYS ──
+    claudehydra_backend::cold_storage::spawn(state.clone());
+
+    // ── Event history: prune past EVENT_HISTORY_RETENTION_DAYS / _MAX_ROWS ──
+    claudehydra_backend::event_history::spawn(state.clone());
+
+    // ── Active provider key environment ──
+    claudehydra_backend::key_environments::load(&state.db).await;
+    claudehydra_backend::tier_budgets::load(&state.db).await;
+    claudehydra_backend::quotas::load(&state.db).await;
+    claudehydra_backend::prompt_canary::load(&state.db).await;
+    claudehydra_backend::http_client::load(&state.db).await;
+    claudehydra_backend::endpoint_policies::load(&state.db).await;
+    #[cfg(all(feature = "pprof", unix))]
+    claudehydra_backend::profiling::init().await;
+    claudehydra_backend::tier_models::load(&state.db).await;
+
+    // ── Operations cut short by the previous shutdown ──
+    claudehydra_backend::recovery::recover(&state.db).await;
+    handlers::fail_interrupted_jobs(&state.db).await;
+
+    // ── systemd / Windows service readiness + watchdog (no-op otherwise) ──
+    claudehydra_backend::service::spawn(state.clone());
+
+    // ── StatsD / Datadog push exporter (STATSD_ADDR; off by default) ──
+    let _statsd = claudehydra_backend::statsd::spawn(state.clone());
+
     // ── Spawn MCP client startup (connect to enabled MCP servers) ──
     let mcp_state = state.clone();
     tokio::spawn(async move {
@@ -239,7 +308,7 @@ async fn main() -> anyhow::Result<()> {
     });
 
     // ── Spawn Swarm IPC discovery loop (probes peers every 30s) ──
-    state.swarm.start_discovery();
+    state.swarm.start_discovery(state.subsystems.clone());
 
     // ── Sandbox: check Docker availability + spawn cleanup loop ──
     state.sandbox.check_docker().await;
@@ -276,8 +345,17 @@ async fn main() -> anyhow::Result<()> {
         listener,
         app.into_make_service_with_connect_info::<std::net::SocketAddr>(),
     )
-    .with_graceful_shutdown(app_builder::shutdown_signal())
+    .with_graceful_shutdown(async {
+        tokio::select! {
+            _ = app_builder::shutdown_signal() => {}
+            _ = claudehydra_backend::service::stop_requested() => {
+                tracing::info!("Stop requested by the service manager");
+            }
+        }
+        claudehydra_backend::service::stopping();
+    })
     .await?;
+    claudehydra_backend::service::stopped();
 
     Ok(())
 }
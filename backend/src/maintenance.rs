@@ -0,0 +1,160 @@
+// ClaudeHydra v4 -- Maintenance / read-only mode
+// While read-only mode is on, safe methods (GET/HEAD/OPTIONS) keep working but
+// every mutation, chat call and WebSocket chat upgrade is rejected with 503,
+// so backups and migrations can run without stopping the server.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use axum::Json;
+use axum::extract::{Request, State};
+use axum::http::{Method, StatusCode, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::state::AppState;
+
+/// Paths that stay writable in read-only mode (so the mode can be turned off
+/// and operators can still log in).
+const READ_ONLY_EXEMPT_PREFIXES: &[&str] = &["/api/admin/read-only", "/api/auth/"];
+
+const DEFAULT_MAINTENANCE_MESSAGE: &str =
+    "ClaudeHydra is in read-only maintenance mode — changes and chat are temporarily disabled";
+
+/// Runtime maintenance flag shared across all clones of `AppState`.
+#[derive(Default)]
+pub struct MaintenanceState {
+    read_only: AtomicBool,
+    details: std::sync::RwLock<Option<(String, DateTime<Utc>)>>,
+}
+
+impl MaintenanceState {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only.load(Ordering::Relaxed)
+    }
+
+    /// Enable or disable read-only mode. `message` is shown to rejected clients.
+    pub fn set_read_only(&self, enabled: bool, message: Option<String>) {
+        if let Ok(mut d) = self.details.write() {
+            *d = enabled.then(|| {
+                (
+                    message.unwrap_or_else(|| DEFAULT_MAINTENANCE_MESSAGE.to_string()),
+                    Utc::now(),
+                )
+            });
+        }
+        self.read_only.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn status(&self) -> Value {
+        let details = self.details.read().ok().and_then(|d| d.clone());
+        json!({
+            "read_only": self.is_read_only(),
+            "message": details.as_ref().map(|(m, _)| m),
+            "since": details.as_ref().map(|(_, s)| s.to_rfc3339()),
+        })
+    }
+
+    fn message(&self) -> String {
+        self.details
+            .read()
+            .ok()
+            .and_then(|d| d.as_ref().map(|(m, _)| m.clone()))
+            .unwrap_or_else(|| DEFAULT_MAINTENANCE_MESSAGE.to_string())
+    }
+}
+
+/// Whether a request would be blocked while read-only mode is active.
+pub fn is_blocked_in_read_only(method: &Method, path: &str) -> bool {
+    if READ_ONLY_EXEMPT_PREFIXES.iter().any(|p| path.starts_with(p)) {
+        return false;
+    }
+    // WebSocket chat is a GET upgrade but starts generations.
+    if path.starts_with("/ws/") || crate::ws::is_chat_socket(path) {
+        return true;
+    }
+    !matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+/// Middleware: reject mutations with 503 while read-only mode is on.
+pub async fn read_only_guard(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    if state.maintenance.is_read_only()
+        && is_blocked_in_read_only(req.method(), req.uri().path())
+    {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            [(header::RETRY_AFTER, "120")],
+            Json(json!({
+                "error": state.maintenance.message(),
+                "read_only": true,
+            })),
+        )
+            .into_response();
+    }
+    next.run(req).await
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  GET/POST /api/admin/read-only
+// ═══════════════════════════════════════════════════════════════════════
+
+#[derive(Debug, Deserialize)]
+pub struct ReadOnlyRequest {
+    pub enabled: bool,
+    pub message: Option<String>,
+}
+
+/// `GET /api/admin/read-only` — current maintenance mode status
+pub async fn read_only_status(State(state): State<AppState>) -> Json<Value> {
+    Json(state.maintenance.status())
+}
+
+/// `POST /api/admin/read-only` — toggle read-only maintenance mode
+pub async fn set_read_only(
+    State(state): State<AppState>,
+    Json(req): Json<ReadOnlyRequest>,
+) -> Json<Value> {
+    state.maintenance.set_read_only(req.enabled, req.message.clone());
+    crate::cluster::publish(
+        &state,
+        crate::cluster::ClusterEvent::ReadOnly { enabled: req.enabled, message: req.message },
+    );
+    tracing::warn!("Read-only maintenance mode {}", if req.enabled { "ENABLED" } else { "disabled" });
+
+    let db = state.db.clone();
+    let enabled = req.enabled;
+    tokio::spawn(async move {
+        crate::audit::log_audit(&db, "set_read_only", json!({ "enabled": enabled }), None).await;
+    });
+
+    Json(state.maintenance.status())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_mutations_are_blocked() {
+        assert!(!is_blocked_in_read_only(&Method::GET, "/api/sessions"));
+        assert!(!is_blocked_in_read_only(&Method::OPTIONS, "/api/claude/chat"));
+        assert!(is_blocked_in_read_only(&Method::POST, "/api/claude/chat/stream"));
+        assert!(is_blocked_in_read_only(&Method::DELETE, "/api/sessions/abc"));
+        assert!(is_blocked_in_read_only(&Method::GET, "/ws/chat"));
+        assert!(is_blocked_in_read_only(&Method::GET, "/api/ws"));
+        assert!(!is_blocked_in_read_only(&Method::GET, "/api/ws-status"));
+    }
+
+    #[test]
+    fn toggle_and_login_stay_writable() {
+        assert!(!is_blocked_in_read_only(&Method::POST, "/api/admin/read-only"));
+        assert!(!is_blocked_in_read_only(&Method::POST, "/api/auth/login"));
+    }
+}
@@ -0,0 +1,378 @@
+// ClaudeHydra v4 -- Message hash chain
+// Optional tamper evidence for a session transcript. With the chain on
+// (PUT /api/sessions/{id}/chain) every message stores a chain_seq (from 1)
+// and
+//   chain_hash = hex(sha256(prev_hash \n id \n role \n model \n agent \n content))
+// where the first message has an empty prev_hash and a missing model/agent
+// is empty. New messages are linked by a trigger (migration 085); turning
+// the chain on links the messages already there, oldest first. The text
+// content is covered; structured parts and tool interactions are not.
+//
+// GET /api/sessions/{id}/chain re-computes the stored chain. Exports carry
+// chain_seq / chain_hash per message, and POST /api/sessions/chain/verify
+// checks such a transcript on its own and against the hashes stored for
+// its session — a transcript re-hashed after editing is consistent but no
+// longer matches the server.
+
+use std::collections::HashMap;
+
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use utoipa::ToSchema;
+
+use crate::state::AppState;
+
+/// A linked message, as stored or as exported.
+#[derive(Debug, Clone, Deserialize, sqlx::FromRow)]
+pub struct Link {
+    pub id: String,
+    pub role: String,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub agent: Option<String>,
+    #[serde(default)]
+    pub content: String,
+    pub chain_seq: i64,
+    pub chain_hash: String,
+}
+
+/// Where a chain stops verifying.
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum Break {
+    /// A link is missing (message removed) or out of order.
+    Gap {
+        expected_seq: i64,
+        found_seq: i64,
+        message_id: String,
+    },
+    /// The message, or the hash it stores, was changed.
+    Mismatch { seq: i64, message_id: String },
+}
+
+/// Hash of a message linked after `prev` (must match migration 085).
+pub fn link_hash(
+    prev: &str,
+    id: &str,
+    role: &str,
+    model: Option<&str>,
+    agent: Option<&str>,
+    content: &str,
+) -> String {
+    let mut hasher = Sha256::new();
+    let fields = [prev, id, role, model.unwrap_or(""), agent.unwrap_or(""), content];
+    for (i, field) in fields.iter().enumerate() {
+        if i > 0 {
+            hasher.update(b"\n");
+        }
+        hasher.update(field.as_bytes());
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// Re-compute `links` (in chain_seq order); the head hash when intact.
+pub fn verify(links: &[Link]) -> Result<Option<String>, Break> {
+    let mut prev = "";
+    for (i, link) in links.iter().enumerate() {
+        let expected_seq = i as i64 + 1;
+        if link.chain_seq != expected_seq {
+            return Err(Break::Gap {
+                expected_seq,
+                found_seq: link.chain_seq,
+                message_id: link.id.clone(),
+            });
+        }
+        let hash = link_hash(
+            prev,
+            &link.id,
+            &link.role,
+            link.model.as_deref(),
+            link.agent.as_deref(),
+            &link.content,
+        );
+        if hash != link.chain_hash {
+            return Err(Break::Mismatch {
+                seq: link.chain_seq,
+                message_id: link.id.clone(),
+            });
+        }
+        prev = &link.chain_hash;
+    }
+    Ok(links.last().map(|l| l.chain_hash.clone()))
+}
+
+fn report(links: &[Link]) -> serde_json::Map<String, Value> {
+    let (head, broken_at) = match verify(links) {
+        Ok(head) => (head, None),
+        Err(b) => (None, Some(b)),
+    };
+    let mut report = serde_json::Map::new();
+    report.insert("valid".into(), json!(broken_at.is_none()));
+    report.insert("length".into(), json!(links.len()));
+    report.insert("head".into(), json!(head));
+    report.insert("broken_at".into(), json!(broken_at));
+    report
+}
+
+async fn stored_links(db: &sqlx::PgPool, session_id: uuid::Uuid) -> Result<Vec<Link>, sqlx::Error> {
+    sqlx::query_as::<_, Link>(
+        "SELECT id::text AS id, role, model, agent, content, chain_seq, \
+                COALESCE(chain_hash, '') AS chain_hash \
+         FROM ch_messages WHERE session_id = $1 AND chain_seq IS NOT NULL \
+         ORDER BY chain_seq ASC",
+    )
+    .bind(session_id)
+    .fetch_all(db)
+    .await
+}
+
+/// `(chain_seq, chain_hash)` of every linked message of a session, by message id.
+pub async fn links_by_message(db: &sqlx::PgPool, session_id: uuid::Uuid) -> HashMap<String, (i64, String)> {
+    sqlx::query_as::<_, (String, i64, String)>(
+        "SELECT id::text, chain_seq, COALESCE(chain_hash, '') FROM ch_messages \
+         WHERE session_id = $1 AND chain_seq IS NOT NULL",
+    )
+    .bind(session_id)
+    .fetch_all(db)
+    .await
+    .unwrap_or_else(|e| {
+        tracing::warn!("message_chain: failed to read links of {}: {}", session_id, e);
+        Vec::new()
+    })
+    .into_iter()
+    .map(|(id, seq, hash)| (id, (seq, hash)))
+    .collect()
+}
+
+fn db_error(e: sqlx::Error) -> StatusCode {
+    tracing::error!("message_chain: {}", e);
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  /api/sessions/{id}/chain
+// ═══════════════════════════════════════════════════════════════════════
+
+/// Request body for `PUT /api/sessions/{id}/chain`.
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct SetChainRequest {
+    /// Link new messages (and, when turned on, the unlinked ones already there).
+    pub enabled: bool,
+}
+
+/// `GET /api/sessions/{id}/chain` — re-compute the session's hash chain
+#[utoipa::path(get, path = "/api/sessions/{id}/chain", tag = "sessions",
+    params(("id" = String, Path, description = "Session UUID")),
+    responses(
+        (status = 200, description = "Chain length, head hash and the first break, if any"),
+        (status = 404, description = "Session not found")
+    ))]
+pub async fn get_chain(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, StatusCode> {
+    let session_id: uuid::Uuid = id.parse().map_err(|_| StatusCode::BAD_REQUEST)?;
+    let enabled: bool = sqlx::query_scalar("SELECT hash_chain FROM ch_sessions WHERE id = $1")
+        .bind(session_id)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(db_error)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let links = stored_links(&state.db, session_id).await.map_err(db_error)?;
+    let unlinked: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM ch_messages WHERE session_id = $1 AND chain_seq IS NULL")
+            .bind(session_id)
+            .fetch_one(&state.db)
+            .await
+            .map_err(db_error)?;
+
+    let mut report = report(&links);
+    report.insert("session_id".into(), json!(session_id));
+    report.insert("enabled".into(), json!(enabled));
+    report.insert("unlinked_messages".into(), json!(unlinked));
+    Ok(Json(Value::Object(report)))
+}
+
+/// `PUT /api/sessions/{id}/chain` — turn the hash chain on or off
+#[utoipa::path(put, path = "/api/sessions/{id}/chain", tag = "sessions",
+    params(("id" = String, Path, description = "Session UUID")),
+    request_body = SetChainRequest,
+    responses(
+        (status = 200, description = "Chain switched; `linked` messages were added to it"),
+        (status = 404, description = "Session not found")
+    ))]
+pub async fn set_chain(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(req): Json<SetChainRequest>,
+) -> Result<Json<Value>, StatusCode> {
+    let session_id: uuid::Uuid = id.parse().map_err(|_| StatusCode::BAD_REQUEST)?;
+    let mut tx = state.db.begin().await.map_err(db_error)?;
+
+    // Locks the session row, so the trigger cannot link messages meanwhile.
+    let updated = sqlx::query("UPDATE ch_sessions SET hash_chain = $2 WHERE id = $1")
+        .bind(session_id)
+        .bind(req.enabled)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?
+        .rows_affected();
+    if updated == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let mut linked = 0u64;
+    if req.enabled {
+        let head: Option<(i64, String)> = sqlx::query_as(
+            "SELECT chain_seq, COALESCE(chain_hash, '') FROM ch_messages \
+             WHERE session_id = $1 AND chain_seq IS NOT NULL \
+             ORDER BY chain_seq DESC LIMIT 1",
+        )
+        .bind(session_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(db_error)?;
+        let (mut seq, mut prev) = head.unwrap_or_default();
+
+        let unlinked: Vec<(uuid::Uuid, String, Option<String>, Option<String>, String)> = sqlx::query_as(
+            "SELECT id, role, model, agent, content FROM ch_messages \
+             WHERE session_id = $1 AND chain_seq IS NULL ORDER BY created_at ASC, id ASC",
+        )
+        .bind(session_id)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(db_error)?;
+        for (message_id, role, model, agent, content) in unlinked {
+            seq += 1;
+            prev = link_hash(
+                &prev,
+                &message_id.to_string(),
+                &role,
+                model.as_deref(),
+                agent.as_deref(),
+                &content,
+            );
+            sqlx::query("UPDATE ch_messages SET chain_seq = $2, chain_hash = $3 WHERE id = $1")
+                .bind(message_id)
+                .bind(seq)
+                .bind(&prev)
+                .execute(&mut *tx)
+                .await
+                .map_err(db_error)?;
+            linked += 1;
+        }
+    }
+    tx.commit().await.map_err(db_error)?;
+
+    crate::audit::log_audit(
+        &state.db,
+        "set_session_hash_chain",
+        json!({ "session_id": session_id, "enabled": req.enabled, "linked": linked }),
+        None,
+    )
+    .await;
+    Ok(Json(json!({
+        "session_id": session_id,
+        "enabled": req.enabled,
+        "linked": linked,
+    })))
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  POST /api/sessions/chain/verify
+// ═══════════════════════════════════════════════════════════════════════
+
+/// `POST /api/sessions/chain/verify` — check an exported JSON transcript
+#[utoipa::path(post, path = "/api/sessions/chain/verify", tag = "sessions",
+    request_body = Value,
+    responses(
+        (status = 200, description = "Whether the transcript's chain holds and matches the stored one"),
+        (status = 400, description = "Not an exported transcript")
+    ))]
+pub async fn verify_transcript(
+    State(state): State<AppState>,
+    Json(transcript): Json<Value>,
+) -> Result<Json<Value>, StatusCode> {
+    let messages = transcript["messages"].as_array().ok_or(StatusCode::BAD_REQUEST)?;
+    let mut links: Vec<Link> = messages
+        .iter()
+        .filter(|m| !m["chain_seq"].is_null())
+        .map(|m| serde_json::from_value(m.clone()))
+        .collect::<Result<_, _>>()
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    links.sort_by_key(|l| l.chain_seq);
+
+    let mut report = report(&links);
+    report.insert("unlinked_messages".into(), json!(messages.len() - links.len()));
+
+    // A transcript edited and re-hashed is consistent on its own; only the
+    // stored hashes give it away.
+    let session_id = transcript["id"].as_str().and_then(|id| id.parse::<uuid::Uuid>().ok());
+    let stored = match session_id {
+        Some(session_id) => links_by_message(&state.db, session_id).await,
+        None => HashMap::new(),
+    };
+    let matches_server = (!stored.is_empty()).then(|| {
+        links
+            .iter()
+            .all(|l| stored.get(&l.id).is_some_and(|(seq, hash)| *seq == l.chain_seq && *hash == l.chain_hash))
+    });
+    report.insert("matches_server".into(), json!(matches_server));
+    report.insert("server_length".into(), json!(stored.len()));
+    Ok(Json(Value::Object(report)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chain(messages: &[(&str, &str, &str)]) -> Vec<Link> {
+        let mut prev = String::new();
+        messages
+            .iter()
+            .enumerate()
+            .map(|(i, (id, role, content))| {
+                prev = link_hash(&prev, id, role, None, None, content);
+                Link {
+                    id: id.to_string(),
+                    role: role.to_string(),
+                    model: None,
+                    agent: None,
+                    content: content.to_string(),
+                    chain_seq: i as i64 + 1,
+                    chain_hash: prev.clone(),
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn link_hash_joins_fields_with_newlines() {
+        let expected = format!("{:x}", Sha256::digest(b"\nm1\nuser\n\n\nhello"));
+        assert_eq!(link_hash("", "m1", "user", None, None, "hello"), expected);
+    }
+
+    #[test]
+    fn edits_and_removals_break_the_chain() {
+        let links = chain(&[("m1", "user", "hi"), ("m2", "assistant", "hello"), ("m3", "user", "bye")]);
+        assert_eq!(verify(&links), Ok(Some(links[2].chain_hash.clone())));
+
+        let mut edited = links.clone();
+        edited[1].content = "goodbye".into();
+        assert_eq!(
+            verify(&edited),
+            Err(Break::Mismatch { seq: 2, message_id: "m2".into() })
+        );
+
+        let removed = [links[0].clone(), links[2].clone()];
+        assert_eq!(
+            verify(&removed),
+            Err(Break::Gap { expected_seq: 2, found_seq: 3, message_id: "m3".into() })
+        );
+    }
+}
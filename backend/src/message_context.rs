@@ -0,0 +1,258 @@
+// ClaudeHydra v4 -- Generation context of stored messages
+// When an assistant message is generated, the upstream request that produced
+// it (the final one of the tool loop) is stored next to it in
+// ch_message_contexts: model and params, resolved system prompt, the history
+// exactly as sent (after truncation), tool names and attachments.
+// `GET /api/sessions/{id}/messages/{mid}/context` returns it, to answer
+// "why did it say that" without guessing what the model saw.
+//
+// Attachment payloads are not stored again: base64 data is replaced with its
+// size and SHA-256, and listed under `attachments`.
+//
+// WebSocket runs store the context together with the message. NDJSON runs are
+// persisted by the shared streaming handler, so the context is linked to the
+// newest assistant message of the session once the run's transcript finishes
+// (that is also when the reply is checked for a refusal, see refusals).
+//
+// Next to the request, the request's decision log is stored (see
+// `request_scope::decide`): how the model was routed, which agent prompt and
+// prompt layers went in and what was truncated, tier downgrades and retries.
+
+use std::time::Duration;
+
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+
+use crate::state::AppState;
+
+/// How long to wait for the shared handler to persist an NDJSON reply.
+const LINK_ATTEMPTS: u32 = 5;
+const LINK_RETRY: Duration = Duration::from_millis(500);
+
+/// The request as stored: tool definitions reduced to names, attachment
+/// payloads replaced by a digest, transport-only fields dropped.
+pub fn snapshot(body: &Value) -> Value {
+    let mut request = body.clone();
+    let Some(obj) = request.as_object_mut() else {
+        return request;
+    };
+    obj.remove("stream");
+    if let Some(tools) = obj.get("tools").and_then(|t| t.as_array()) {
+        let names: Vec<Value> = tools.iter().filter_map(|t| t.get("name").cloned()).collect();
+        obj.insert("tools".to_string(), Value::Array(names));
+    }
+
+    let mut attachments = Vec::new();
+    if let Some(messages) = obj.get_mut("messages").and_then(|m| m.as_array_mut()) {
+        for (index, message) in messages.iter_mut().enumerate() {
+            let Some(blocks) = message.get_mut("content").and_then(|c| c.as_array_mut()) else {
+                continue;
+            };
+            for block in blocks {
+                let kind = block
+                    .get("type")
+                    .and_then(|t| t.as_str())
+                    .unwrap_or_default()
+                    .to_string();
+                let Some(source) = block.get_mut("source").and_then(|s| s.as_object_mut()) else {
+                    continue;
+                };
+                let mut attachment = json!({
+                    "message_index": index,
+                    "type": kind,
+                    "media_type": source.get("media_type"),
+                });
+                if let Some(data) = source.get("data").and_then(|d| d.as_str()) {
+                    let bytes = base64::engine::general_purpose::STANDARD
+                        .decode(data)
+                        .map(|d| d.len())
+                        .unwrap_or(data.len() * 3 / 4);
+                    let digest = format!("{:x}", Sha256::digest(data.as_bytes()));
+                    attachment["bytes"] = json!(bytes);
+                    attachment["sha256"] = json!(digest);
+                    source.insert(
+                        "data".to_string(),
+                        json!(format!("<{} bytes, sha256 {}>", bytes, &digest[..16])),
+                    );
+                }
+                if let Some(url) = source.get("url") {
+                    attachment["url"] = url.clone();
+                }
+                attachments.push(attachment);
+            }
+        }
+    }
+    obj.insert("attachments".to_string(), Value::Array(attachments));
+    request
+}
+
+/// Store the context of `message_id`.
+pub async fn store(
+    db: &sqlx::PgPool,
+    message_id: uuid::Uuid,
+    transcript_id: Option<uuid::Uuid>,
+    request: &Value,
+    decisions: &Value,
+) {
+    if let Err(e) = sqlx::query(
+        "INSERT INTO ch_message_contexts (message_id, transcript_id, request, decisions) \
+         VALUES ($1, $2, $3, $4) ON CONFLICT (message_id) DO NOTHING",
+    )
+    .bind(message_id)
+    .bind(transcript_id)
+    .bind(request)
+    .bind(decisions)
+    .execute(db)
+    .await
+    {
+        tracing::warn!("message_context: failed to store context for {}: {}", message_id, e);
+    }
+}
+
+/// Attach `request` to the newest assistant message of `session_id` written
+/// since `since` that has no context yet. Runs in the background and retries
+/// briefly, because the reply may be persisted after the stream ends.
+pub fn link_latest(
+    db: sqlx::PgPool,
+    session_id: uuid::Uuid,
+    since: DateTime<Utc>,
+    transcript_id: Option<uuid::Uuid>,
+    request: Value,
+    decisions: Value,
+) {
+    tokio::spawn(async move {
+        for _ in 0..LINK_ATTEMPTS {
+            let found = sqlx::query_scalar::<_, uuid::Uuid>(
+                "SELECT m.id FROM ch_messages m \
+                 WHERE m.session_id = $1 AND m.role = 'assistant' AND m.created_at >= $2 \
+                 AND NOT EXISTS (SELECT 1 FROM ch_message_contexts c WHERE c.message_id = m.id) \
+                 ORDER BY m.created_at DESC LIMIT 1",
+            )
+            .bind(session_id)
+            .bind(since)
+            .fetch_optional(&db)
+            .await;
+            match found {
+                Ok(Some(message_id)) => {
+                    store(&db, message_id, transcript_id, &request, &decisions).await;
+                    // The stop reason isn't visible here; check the text.
+                    crate::refusals::scan_message(&db, message_id, "stream").await;
+                    crate::artifacts::scan_message(&db, message_id).await;
+                    return;
+                }
+                Ok(None) => tokio::time::sleep(LINK_RETRY).await,
+                Err(e) => {
+                    tracing::warn!("message_context: failed to find reply in {}: {}", session_id, e);
+                    return;
+                }
+            }
+        }
+        tracing::debug!("message_context: no reply stored in {} to link", session_id);
+    });
+}
+
+/// `GET /api/sessions/{id}/messages/{mid}/context` — what was sent upstream when the message was generated
+pub async fn get_message_context(
+    State(state): State<AppState>,
+    Path((id, mid)): Path<(String, String)>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let (Ok(session_id), Ok(message_id)) =
+        (uuid::Uuid::parse_str(&id), uuid::Uuid::parse_str(&mid))
+    else {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "Invalid session or message id" })),
+        ));
+    };
+
+    type ContextRow = (String, Option<uuid::Uuid>, Option<Value>, Option<Value>, Option<DateTime<Utc>>);
+    let row = sqlx::query_as::<_, ContextRow>(
+        "SELECT m.role, c.transcript_id, c.request, c.decisions, c.created_at \
+         FROM ch_messages m \
+         LEFT JOIN ch_message_contexts c ON c.message_id = m.id \
+         WHERE m.id = $1 AND m.session_id = $2",
+    )
+    .bind(message_id)
+    .bind(session_id)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|e| {
+        tracing::error!("message_context: query failed: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": "Failed to load message context" })),
+        )
+    })?;
+
+    let Some((role, transcript_id, request, decisions, captured_at)) = row else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": "Message not found" })),
+        ));
+    };
+    let Some(mut request) = request else {
+        let error = if role == "assistant" {
+            "No generation context was recorded for this message"
+        } else {
+            "Only assistant messages have a generation context"
+        };
+        return Err((StatusCode::NOT_FOUND, Json(json!({ "error": error }))));
+    };
+
+    let attachments = request
+        .as_object_mut()
+        .and_then(|o| o.remove("attachments"))
+        .unwrap_or_else(|| json!([]));
+    let field = |name: &str| request.get(name).cloned().unwrap_or(Value::Null);
+    Ok(Json(json!({
+        "message_id": message_id,
+        "session_id": session_id,
+        "transcript_id": transcript_id,
+        "captured_at": captured_at,
+        "model": field("model"),
+        "system": field("system"),
+        "messages": field("messages"),
+        "params": {
+            "max_tokens": field("max_tokens"),
+            "temperature": field("temperature"),
+            "stop_sequences": field("stop_sequences"),
+            "tools": field("tools"),
+        },
+        "attachments": attachments,
+        "decisions": decisions.unwrap_or_else(|| json!([])),
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_replaces_attachment_payloads_with_digests() {
+        let body = json!({
+            "model": "claude-sonnet-4-6",
+            "stream": true,
+            "tools": [{ "name": "read_file", "input_schema": {} }],
+            "messages": [{
+                "role": "user",
+                "content": [
+                    { "type": "image", "source": { "type": "base64", "media_type": "image/png", "data": "aGVsbG8=" } },
+                    { "type": "text", "text": "what is this?" },
+                ],
+            }],
+        });
+        let snap = snapshot(&body);
+        assert!(snap.get("stream").is_none());
+        assert_eq!(snap["tools"], json!(["read_file"]));
+        let data = snap["messages"][0]["content"][0]["source"]["data"].as_str().unwrap();
+        assert!(data.starts_with("<5 bytes, sha256 "));
+        assert_eq!(snap["messages"][0]["content"][1]["text"], "what is this?");
+        assert_eq!(snap["attachments"][0]["media_type"], "image/png");
+        assert_eq!(snap["attachments"][0]["bytes"], 5);
+    }
+}
@@ -10,6 +10,8 @@
 // shared defaults (chat/thinking/image/flash).
 
 use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
 
 use axum::Json;
 use axum::extract::{Path, State};
@@ -19,6 +21,7 @@ use serde::Serialize;
 use serde_json::{Value, json};
 use utoipa::ToSchema;
 
+use crate::models::ClaudeModelInfo;
 use crate::state::AppState;
 
 // ── Re-export shared types from jaskier-core ──────────────────────────────────
@@ -142,9 +145,19 @@ pub async fn resolve_models(state: &AppState) -> ResolvedModels {
 }
 
 /// Get the model ID for a given tier/use case.
-/// Priority: 1) DB pin  2) dynamic auto-selection  3) hardcoded fallback.
+/// Priority: 1) tier mapping  2) DB pin  3) dynamic auto-selection  4) hardcoded fallback.
 pub async fn get_model_id(state: &AppState, use_case: &str) -> String {
-    // 1) Check for a pinned model in DB
+    // 1) Model configured for the agent tier (PUT /api/agents/tiers)
+    if let Some(model) = crate::tier_models::parse_tier(use_case).and_then(crate::tier_models::configured) {
+        tracing::info!(
+            "model_registry: use_case={} → model={} (tier mapping)",
+            use_case,
+            model
+        );
+        return model;
+    }
+
+    // 2) Check for a pinned model in DB
     let pinned: Option<String> =
         sqlx::query_scalar("SELECT model_id FROM ch_model_pins WHERE use_case = $1")
             .bind(use_case)
@@ -162,7 +175,7 @@ pub async fn get_model_id(state: &AppState, use_case: &str) -> String {
         return pin.clone();
     }
 
-    // 2) Dynamic auto-selection
+    // 3) Dynamic auto-selection
     let resolved = resolve_models(state).await;
 
     let (model, fallback) = match use_case {
@@ -194,6 +207,154 @@ pub async fn model_for_tier(state: &AppState, tier: &str) -> String {
     get_model_id(state, tier).await
 }
 
+// ── Live Anthropic model list ────────────────────────────────────────────────
+
+/// How long a fetched `GET /v1/models` list is used (`CH_MODEL_LIST_TTL_SECS`).
+const DEFAULT_LIVE_TTL_SECS: u64 = 3600;
+/// Rejected keys and failed fetches are retried after this long.
+const LIVE_RETRY_SECS: u64 = 60;
+const LIVE_MAX_PAGES: usize = 10;
+
+/// What the Anthropic credential can use, per `GET /v1/models`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AnthropicAccess {
+    /// Not known: a Vault-managed credential, or the API could not be reached.
+    Unknown,
+    /// No credential, or the credential was rejected (401/403).
+    Denied,
+    /// Model ids with their display names, newest first.
+    Models(Vec<(String, Option<String>)>),
+}
+
+impl AnthropicAccess {
+    fn allows(&self, model_id: &str) -> bool {
+        match self {
+            Self::Unknown => true,
+            Self::Denied => false,
+            Self::Models(models) => models.iter().any(|(id, _)| id == model_id),
+        }
+    }
+}
+
+static LIVE: RwLock<Option<(Instant, Duration, AnthropicAccess)>> = RwLock::new(None);
+
+fn live_ttl() -> Duration {
+    let secs = std::env::var("CH_MODEL_LIST_TTL_SECS")
+        .ok()
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .filter(|s| *s > 0)
+        .unwrap_or(DEFAULT_LIVE_TTL_SECS);
+    Duration::from_secs(secs)
+}
+
+async fn fetch_anthropic_models(state: &AppState) -> AnthropicAccess {
+    let Some((credential, is_oauth)) = crate::handlers::get_anthropic_credential(state).await else {
+        return AnthropicAccess::Denied;
+    };
+    if credential == "__vault_managed__" {
+        return AnthropicAccess::Unknown;
+    }
+    let base = crate::handlers::anthropic_api_url();
+    let mut models = Vec::new();
+    let mut after: Option<String> = None;
+    for _ in 0..LIVE_MAX_PAGES {
+        let mut url = format!("{}/v1/models?limit=1000", base);
+        if let Some(ref id) = after {
+            url.push_str(&format!("&after_id={}", id));
+        }
+        let mut req = crate::http_client::client(state)
+            .get(url)
+            .timeout(crate::endpoint_policies::get(crate::endpoint_policies::Group::Models).timeout)
+            .header("anthropic-version", "2023-06-01");
+        req = if is_oauth {
+            req.header("authorization", format!("Bearer {}", credential))
+        } else {
+            req.header("x-api-key", &credential)
+        };
+        let resp = match req.send().await {
+            Ok(resp) => resp,
+            Err(e) => {
+                tracing::warn!("model_registry: GET /v1/models failed: {}", e);
+                return AnthropicAccess::Unknown;
+            }
+        };
+        let status = resp.status();
+        if status == reqwest::StatusCode::UNAUTHORIZED || status == reqwest::StatusCode::FORBIDDEN {
+            tracing::warn!("model_registry: Anthropic credential rejected by /v1/models (HTTP {})", status);
+            return AnthropicAccess::Denied;
+        }
+        let body: Value = match resp.error_for_status() {
+            Ok(resp) => resp.json().await.unwrap_or_default(),
+            Err(e) => {
+                tracing::warn!("model_registry: GET /v1/models failed: {}", e);
+                return AnthropicAccess::Unknown;
+            }
+        };
+        let page = body["data"].as_array().map(Vec::as_slice).unwrap_or(&[]);
+        models.extend(page.iter().filter_map(|m| {
+            let id = m["id"].as_str()?.to_string();
+            Some((id, m["display_name"].as_str().map(str::to_string)))
+        }));
+        match body["last_id"].as_str() {
+            Some(last) if body["has_more"].as_bool() == Some(true) => after = Some(last.to_string()),
+            _ => break,
+        }
+    }
+    AnthropicAccess::Models(models)
+}
+
+/// Anthropic models the credential can use, cached for `CH_MODEL_LIST_TTL_SECS`.
+/// A failed fetch keeps the last list for another minute.
+pub async fn anthropic_access(state: &AppState) -> AnthropicAccess {
+    let cached = LIVE.read().ok().and_then(|l| l.clone());
+    if let Some((at, ttl, ref access)) = cached
+        && at.elapsed() < ttl
+    {
+        return access.clone();
+    }
+    let (access, ttl) = match fetch_anthropic_models(state).await {
+        access @ AnthropicAccess::Models(_) => (access, live_ttl()),
+        AnthropicAccess::Unknown => match cached {
+            Some((_, _, previous @ AnthropicAccess::Models(_))) => (previous, Duration::from_secs(LIVE_RETRY_SECS)),
+            _ => (AnthropicAccess::Unknown, Duration::from_secs(LIVE_RETRY_SECS)),
+        },
+        AnthropicAccess::Denied => (AnthropicAccess::Denied, Duration::from_secs(LIVE_RETRY_SECS)),
+    };
+    if let Ok(mut live) = LIVE.write() {
+        *live = Some((Instant::now(), ttl, access.clone()));
+    }
+    access
+}
+
+/// Drop the cached list (next `anthropic_access` fetches again).
+fn forget_anthropic_access() {
+    if let Ok(mut live) = LIVE.write() {
+        *live = None;
+    }
+}
+
+/// Tier models marked by whether the credential can use them, followed by the
+/// other models it can use (tier `Other`).
+pub fn merge_live_models(mut models: Vec<ClaudeModelInfo>, access: &AnthropicAccess) -> Vec<ClaudeModelInfo> {
+    for model in &mut models {
+        model.available = access.allows(&model.id);
+    }
+    if let AnthropicAccess::Models(live) = access {
+        for (id, name) in live {
+            if !models.iter().any(|m| &m.id == id) {
+                models.push(ClaudeModelInfo {
+                    id: id.clone(),
+                    name: name.clone().unwrap_or_else(|| id.clone()),
+                    tier: "Other".to_string(),
+                    provider: "anthropic".to_string(),
+                    available: true,
+                });
+            }
+        }
+    }
+    models
+}
+
 // ── HTTP handlers ────────────────────────────────────────────────────────────
 
 /// Read all pins from DB as a HashMap.
@@ -314,6 +475,7 @@ pub async fn list_models(State(state): State<AppState>) -> impl IntoResponse {
     responses((status = 200, description = "Refreshed model cache", body = Value))
 )]
 pub async fn refresh_models(State(state): State<AppState>) -> Json<Value> {
+    forget_anthropic_access();
     let (models, errors) = refresh_cache(&state).await;
     let resolved = resolve_models(&state).await;
     let pins = get_pins_map(&state).await;
@@ -619,4 +781,41 @@ mod tests {
         let cache = ModelCache::new();
         assert!(cache.models.is_empty());
     }
+
+    // ── merge_live_models ────────────────────────────────────────────────
+
+    fn tier_model(id: &str, tier: &str) -> ClaudeModelInfo {
+        ClaudeModelInfo {
+            id: id.into(),
+            name: id.into(),
+            tier: tier.into(),
+            provider: "anthropic".into(),
+            available: true,
+        }
+    }
+
+    #[test]
+    fn merge_live_models_marks_tiers_and_appends_the_rest() {
+        let tiers = vec![
+            tier_model("claude-opus-4-6", "Commander"),
+            tier_model("claude-haiku-4-5-20251001", "Executor"),
+        ];
+        let access = AnthropicAccess::Models(vec![
+            ("claude-opus-4-6".into(), Some("Claude Opus 4.6".into())),
+            ("claude-opus-4-1-20250805".into(), None),
+        ]);
+        let merged = merge_live_models(tiers, &access);
+        assert_eq!(merged.len(), 3);
+        assert!(merged[0].available);
+        assert!(!merged[1].available);
+        assert_eq!(merged[2].id, "claude-opus-4-1-20250805");
+        assert_eq!(merged[2].tier, "Other");
+    }
+
+    #[test]
+    fn merge_live_models_without_a_list() {
+        let tiers = vec![tier_model("claude-opus-4-6", "Commander")];
+        assert!(merge_live_models(tiers.clone(), &AnthropicAccess::Unknown)[0].available);
+        assert!(!merge_live_models(tiers, &AnthropicAccess::Denied)[0].available);
+    }
 }
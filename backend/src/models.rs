@@ -38,6 +38,21 @@ pub struct SettingsRow {
     /// Message compaction keep — keep this many recent messages after compaction (default 15)
     #[sqlx(default)]
     pub compaction_keep: i32,
+    /// Retry heuristic refusals once with a reformulation note
+    #[sqlx(default)]
+    pub refusal_retry: bool,
+    /// Continuations for replies cut off at max_tokens (default 2, 0 = off)
+    #[sqlx(default)]
+    pub max_continuations: i32,
+    /// System prompt layering policy (NULL / unreadable = default)
+    #[sqlx(default)]
+    pub prompt_layering: Option<Value>,
+    /// Upstream HTTP client overrides (NULL = env vars / defaults)
+    #[sqlx(default)]
+    pub http_client: Option<Value>,
+    /// Timeout / retry overrides per endpoint group (NULL = defaults)
+    #[sqlx(default)]
+    pub endpoint_policies: Option<Value>,
 }
 
 #[derive(sqlx::FromRow)]
@@ -48,6 +63,12 @@ pub struct SessionRow {
     pub updated_at: chrono::DateTime<chrono::Utc>,
     #[sqlx(default)]
     pub working_directory: String,
+    #[sqlx(default)]
+    pub input_tokens: i64,
+    #[sqlx(default)]
+    pub output_tokens: i64,
+    #[sqlx(default)]
+    pub cost_usd: f64,
 }
 
 #[derive(sqlx::FromRow)]
@@ -58,6 +79,12 @@ pub struct SessionSummaryRow {
     pub message_count: i64,
     #[sqlx(default)]
     pub working_directory: String,
+    #[sqlx(default)]
+    pub input_tokens: i64,
+    #[sqlx(default)]
+    pub output_tokens: i64,
+    #[sqlx(default)]
+    pub cost_usd: f64,
 }
 
 #[derive(sqlx::FromRow)]
@@ -69,6 +96,9 @@ pub struct MessageRow {
     pub model: Option<String>,
     pub agent: Option<String>,
     pub created_at: chrono::DateTime<chrono::Utc>,
+    /// Structured content (NULL = plain text message)
+    #[sqlx(default)]
+    pub parts: Option<Value>,
 }
 
 // ── Agent ───────────────────────────────────────────────────────────────
@@ -82,6 +112,18 @@ pub struct WitcherAgent {
     pub status: String,
     pub description: String,
     pub model: String,
+    /// Always applied when generating as this agent (merged with request ones).
+    #[serde(default)]
+    pub stop_sequences: Vec<String>,
+    /// Instructions added to the agent layer of the system prompt.
+    #[serde(default)]
+    pub system_prompt: String,
+    /// Sampling temperature when generating as this agent (unset = settings).
+    #[serde(default)]
+    pub temperature: Option<f64>,
+    /// Reply length limit when generating as this agent (unset = settings).
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
 }
 
 // ── Health ──────────────────────────────────────────────────────────────
@@ -95,6 +137,17 @@ pub struct HealthResponse {
     pub providers: Vec<ProviderInfo>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub browser_proxy: Option<crate::browser_proxy::BrowserProxyStatus>,
+    /// Spend cap / model budget status (`configured`, `status`, `exceeded`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quota: Option<Value>,
+    /// Provider circuit breaker states (`closed`, `open`, `half_open`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub circuits: Option<Value>,
+    /// Read replica state (`ok`, `fallback`) when DATABASE_READ_URL is set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub read_replica: Option<Value>,
+    /// Resumable streams still running on this replica
+    pub active_streams: usize,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
@@ -107,6 +160,7 @@ pub struct ProviderInfo {
 
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct ChatRequest {
+    /// A trailing `assistant` message is treated as a prefill the model continues.
     pub messages: Vec<ChatMessage>,
     pub model: Option<String>,
     pub temperature: Option<f64>,
@@ -115,24 +169,113 @@ pub struct ChatRequest {
     pub tools_enabled: Option<bool>,
     #[serde(default)]
     pub session_id: Option<String>,
+    /// Generate as this agent (applies its stop sequences).
+    #[serde(default)]
+    pub agent_id: Option<String>,
+    /// Extra stop sequences, merged after the agent's own.
+    #[serde(default)]
+    pub stop_sequences: Option<Vec<String>>,
+    /// Queueing class when upstream concurrency is saturated (default: the
+    /// API token's default, else `normal`).
+    #[serde(default)]
+    pub priority: Option<crate::priority::Priority>,
 }
 
+/// A chat message. `content` also accepts Anthropic-style content blocks
+/// (`[{"type":"text","text":…},{"type":"image","source":{…}}]`): text blocks
+/// are joined into `content`, image blocks become `attachments`.
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(from = "RawChatMessage")]
 pub struct ChatMessage {
     pub role: String,
     pub content: String,
+    /// Images sent with the message (Anthropic models only).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<ImageSource>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub model: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub timestamp: Option<String>,
 }
 
+/// Where an image attachment comes from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ImageSource {
+    Base64 { media_type: String, data: String },
+    Url { url: String },
+    /// A file stored with `POST /api/uploads`, inlined when the request is sent.
+    Upload { upload_id: uuid::Uuid },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ContentBlock {
+    Text { text: String },
+    Image { source: ImageSource },
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MessageContent {
+    Text(String),
+    Blocks(Vec<ContentBlock>),
+}
+
+#[derive(Deserialize)]
+struct RawChatMessage {
+    role: String,
+    content: MessageContent,
+    #[serde(default)]
+    attachments: Vec<ImageSource>,
+    #[serde(default)]
+    model: Option<String>,
+    #[serde(default)]
+    timestamp: Option<String>,
+}
+
+impl From<RawChatMessage> for ChatMessage {
+    fn from(raw: RawChatMessage) -> Self {
+        let mut attachments = raw.attachments;
+        let content = match raw.content {
+            MessageContent::Text(text) => text,
+            MessageContent::Blocks(blocks) => {
+                let mut texts: Vec<String> = Vec::new();
+                for block in blocks {
+                    match block {
+                        ContentBlock::Text { text } => texts.push(text),
+                        ContentBlock::Image { source } => attachments.push(source),
+                    }
+                }
+                texts.join("\n\n")
+            }
+        };
+        Self {
+            role: raw.role,
+            content,
+            attachments,
+            model: raw.model,
+            timestamp: raw.timestamp,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct ChatResponse {
     pub id: String,
     pub message: ChatMessage,
     pub model: String,
     pub usage: Option<UsageInfo>,
+    /// `stop_reason` / `heuristic` when the returned reply is a refusal.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refusal: Option<String>,
+    /// Transient upstream errors (429 / 529 / 5xx) retried before this reply.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retries: Option<Vec<crate::providers::retry::RetryAttempt>>,
+    /// Soft limits (budget, context window, quota) the request came close to.
+    #[serde(default)]
+    #[schema(value_type = Vec<Object>)]
+    pub warnings: Vec<serde_json::Value>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
@@ -189,6 +332,30 @@ pub struct AppSettings {
     /// Message compaction keep — keep this many recent messages after compaction (default 15)
     #[serde(default = "default_compaction_keep")]
     pub compaction_keep: i32,
+    /// Retry a reply that reads like a refusal once, asking for the answerable
+    /// part (`/api/claude/chat` only; provider safety stops are never retried)
+    #[serde(default)]
+    pub refusal_retry: bool,
+    /// How many times a reply cut off at `max_tokens` is continued with the
+    /// partial output as prefill (0–5, default 2; 0 returns truncated replies)
+    #[serde(default = "default_max_continuations")]
+    pub max_continuations: i32,
+    /// Order, separator and length limit of the system prompt layers
+    /// (global, project, agent, request)
+    #[serde(default)]
+    pub prompt_layering: crate::prompt_layers::PromptLayering,
+    /// Timeouts and connection pooling of provider calls; unset fields use
+    /// the CH_HTTP_* env vars or the defaults
+    #[serde(default)]
+    pub http: crate::http_client::HttpSettings,
+    /// Timeout and retries per endpoint group (chat, stream, models, batch,
+    /// tools); unset fields use the defaults
+    #[serde(default)]
+    pub policies: crate::endpoint_policies::EndpointPolicies,
+    /// Model per agent tier; read-only here, set with PUT /api/agents/tiers
+    #[serde(default)]
+    #[schema(value_type = Object)]
+    pub tier_models: crate::tier_models::TierModels,
 }
 
 fn default_true() -> bool {
@@ -215,19 +382,87 @@ fn default_compaction_keep() -> i32 {
     15
 }
 
+fn default_max_continuations() -> i32 {
+    2
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct ApiKeyRequest {
     pub provider: String,
     pub key: String,
+    /// Key environment (e.g. `dev`); omitted = `default`
+    #[serde(default)]
+    pub environment: Option<String>,
 }
 
 // ── History ─────────────────────────────────────────────────────────────
 
+/// One piece of a stored message. Messages without parts are plain text;
+/// with parts, `content` still carries their text for older clients.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MessagePart {
+    Text {
+        text: String,
+    },
+    /// An image by reference (an upload or URL; base64 data is kept as sent).
+    Image {
+        source: ImageSource,
+    },
+    ToolCall {
+        id: String,
+        name: String,
+        #[serde(default)]
+        #[schema(value_type = Object)]
+        input: Value,
+    },
+    ToolResult {
+        tool_use_id: String,
+        #[serde(default)]
+        content: String,
+        #[serde(default)]
+        is_error: bool,
+    },
+    Thinking {
+        thinking: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        signature: Option<String>,
+    },
+}
+
+impl MessagePart {
+    /// Text of the `text` parts, separated by blank lines.
+    pub fn joined_text(parts: &[MessagePart]) -> String {
+        parts
+            .iter()
+            .filter_map(|p| match p {
+                MessagePart::Text { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+
+    /// Parts stored in `ch_messages.parts`; kinds this version doesn't know are skipped.
+    pub fn from_stored(stored: Option<Value>) -> Vec<MessagePart> {
+        match stored {
+            Some(Value::Array(parts)) => parts
+                .into_iter()
+                .filter_map(|p| serde_json::from_value(p).ok())
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct HistoryEntry {
     pub id: String,
     pub role: String,
     pub content: String,
+    /// Structured content; absent for plain text messages
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub parts: Vec<MessagePart>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub model: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -245,6 +480,23 @@ pub struct Session {
     pub title: String,
     pub created_at: String,
     pub messages: Vec<HistoryEntry>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Pinned sessions are listed first
+    #[serde(default)]
+    pub pinned: bool,
+    /// Archived sessions are hidden from `GET /api/sessions/list` by default
+    #[serde(default)]
+    pub archived: bool,
+    /// Cumulative prompt tokens across every completed chat call
+    #[serde(default)]
+    pub input_tokens: i64,
+    /// Cumulative completion tokens across every completed chat call
+    #[serde(default)]
+    pub output_tokens: i64,
+    /// Estimated spend in USD at the list prices of the time of each call
+    #[serde(default)]
+    pub cost_usd: f64,
 }
 
 /// Lightweight view returned in session listing (no messages body).
@@ -256,6 +508,17 @@ pub struct SessionSummary {
     pub message_count: usize,
     #[serde(default)]
     pub working_directory: String,
+    #[serde(default)]
+    pub pinned: bool,
+    #[serde(default)]
+    pub archived: bool,
+    #[serde(default)]
+    pub input_tokens: i64,
+    #[serde(default)]
+    pub output_tokens: i64,
+    /// Estimated spend in USD
+    #[serde(default)]
+    pub cost_usd: f64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
@@ -290,7 +553,11 @@ pub struct AddPromptRequest {
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct AddMessageRequest {
     pub role: String,
+    /// May be left out when `parts` has text parts
+    #[serde(default)]
     pub content: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub parts: Vec<MessagePart>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub model: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -409,13 +676,25 @@ pub enum WsServerMessage {
     /// A streamed text token.
     Token { content: String },
     /// Execution completed successfully.
-    Complete { duration_ms: u64 },
+    Complete {
+        duration_ms: u64,
+        /// Soft limits the run came close to (see soft_limits).
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        warnings: Vec<Value>,
+    },
     /// A tool call has been initiated.
     ToolCall {
         name: String,
         args: Value,
         iteration: u32,
     },
+    /// A tool call is paused until `POST /api/tool-calls/{id}/approve` (or `/reject`).
+    ToolConfirmation {
+        id: String,
+        name: String,
+        args: Value,
+        expires_at: String,
+    },
     /// A tool call has completed.
     ToolResult {
         name: String,
@@ -466,6 +745,13 @@ pub struct AgentConfigRow {
     pub status: String,
     pub description: String,
     pub model: String,
+    pub stop_sequences: Vec<String>,
+    #[sqlx(default)]
+    pub system_prompt: String,
+    #[sqlx(default)]
+    pub temperature: Option<f64>,
+    #[sqlx(default)]
+    pub max_tokens: Option<i32>,
     pub created_at: chrono::DateTime<chrono::Utc>,
     pub updated_at: chrono::DateTime<chrono::Utc>,
 }
@@ -480,6 +766,10 @@ impl From<AgentConfigRow> for WitcherAgent {
             status: row.status,
             description: row.description,
             model: row.model,
+            stop_sequences: row.stop_sequences,
+            system_prompt: row.system_prompt,
+            temperature: row.temperature,
+            max_tokens: row.max_tokens.and_then(|n| u32::try_from(n).ok()),
         }
     }
 }
@@ -496,6 +786,14 @@ pub struct CreateAgentRequest {
     pub description: String,
     #[serde(default)]
     pub model: String,
+    #[serde(default)]
+    pub stop_sequences: Vec<String>,
+    #[serde(default)]
+    pub system_prompt: String,
+    #[serde(default)]
+    pub temperature: Option<f64>,
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
 }
 
 fn default_agent_status() -> String {
@@ -511,4 +809,19 @@ pub struct UpdateAgentRequest {
     pub status: Option<String>,
     pub description: Option<String>,
     pub model: Option<String>,
+    pub stop_sequences: Option<Vec<String>>,
+    pub system_prompt: Option<String>,
+    pub temperature: Option<f64>,
+    pub max_tokens: Option<u32>,
+}
+
+/// A set of agents installed together (`POST /api/agents/import`, catalog).
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AgentPack {
+    pub name: String,
+    #[serde(default)]
+    pub version: String,
+    #[serde(default)]
+    pub description: String,
+    pub agents: Vec<CreateAgentRequest>,
 }
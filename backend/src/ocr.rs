@@ -61,9 +61,12 @@ impl HasOcrProvider for AppState {
         prompt: &str,
     ) -> Result<(String, String, Option<f64>), String> {
         // Try Claude Vision API first
-        let api_key = {
-            let rt = self.runtime.read().await;
-            rt.api_keys.get("ANTHROPIC_API_KEY").cloned()
+        let api_key = match crate::key_environments::environment_key(self, "anthropic").await {
+            Some(key) => key,
+            None => {
+                let rt = self.runtime.read().await;
+                rt.api_keys.get("ANTHROPIC_API_KEY").cloned()
+            }
         };
 
         if let Some(key) = api_key {
@@ -77,7 +80,7 @@ impl HasOcrProvider for AppState {
 
         // Fallback: Gemini Vision API via Google OAuth
         if let Some((credential, is_oauth)) =
-            jaskier_oauth::google::get_google_credential(self).await
+            crate::key_environments::google_credential(self).await
         {
             let (text, confidence) = ocr_with_gemini(
                 &self.http_client,
@@ -346,7 +349,7 @@ async fn ocr_with_gemini(
 /// Extract structured data from OCR text. Uses Gemini (text-only, simpler) since
 /// this is a second-pass analysis that doesn't need vision capabilities.
 async fn extract_structured_data(state: &AppState, ocr_text: &str) -> Result<Value, String> {
-    let (credential, is_oauth) = jaskier_oauth::google::get_google_credential(state)
+    let (credential, is_oauth) = crate::key_environments::google_credential(state)
         .await
         .ok_or_else(|| {
             "No Google API credential configured for structured extraction".to_string()
@@ -0,0 +1,599 @@
+// ClaudeHydra v4 -- OIDC sign-in for team deployments
+// Authorization-code flow (with PKCE) against any OpenID Connect provider —
+// Google Workspace, Microsoft Entra ID, Keycloak, ... — configured through
+// OIDC_ISSUER / OIDC_CLIENT_ID / OIDC_CLIENT_SECRET / OIDC_REDIRECT_URL.
+//
+// A successful sign-in upserts the user into `ch_users`, maps the identity
+// claims to a role and starts a regular web session (see `web_session.rs`), so
+// every other route keeps working unchanged. Role mapping, first match wins:
+//   1. email listed in OIDC_ADMIN_EMAILS            → admin
+//   2. OIDC_ROLE_CLAIM (default `roles`, Entra app roles; `groups` also works)
+//      containing a value listed in OIDC_ADMIN_ROLES / OIDC_MEMBER_ROLES /
+//      OIDC_VIEWER_ROLES (default `admin` / `member` / `viewer`). Values must
+//      match exactly (case-insensitive); the most privileged match wins
+//   3. email in OIDC_ALLOWED_EMAILS or an OIDC_ALLOWED_DOMAINS domain
+//      → OIDC_DEFAULT_ROLE (default member)
+//   4. anyone else is refused — an issuer like Google would otherwise admit
+//      every account it knows
+// OIDC_ALLOWED_DOMAINS also restricts sign-in to the listed email domains.
+//
+// The ID token comes straight from the token endpoint over TLS, so per OIDC
+// Core 3.1.3.7 its issuer is trusted without checking the JWS signature; the
+// audience, expiry and nonce are still verified.
+
+use std::sync::OnceLock;
+
+use axum::Json;
+use axum::extract::{Query, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::{IntoResponse, Redirect, Response};
+use base64::Engine as _;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+
+use crate::crypto;
+use crate::state::AppState;
+use crate::web_session::{self, Role};
+
+const FLOW_COOKIE: &str = "ch_oidc";
+const FLOW_COOKIE_PATH: &str = "/api/auth/oidc";
+const FLOW_MAX_AGE_SECS: i64 = 600;
+
+#[derive(Debug, Clone)]
+pub struct OidcConfig {
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: String,
+    pub redirect_url: String,
+    pub allowed_domains: Vec<String>,
+    /// Individual users admitted with the default role.
+    pub allowed_emails: Vec<String>,
+    pub admin_emails: Vec<String>,
+    pub role_claim: String,
+    /// Role claim values granting each role (lowercase, exact match).
+    pub admin_roles: Vec<String>,
+    pub member_roles: Vec<String>,
+    pub viewer_roles: Vec<String>,
+    pub default_role: Role,
+    pub post_login_redirect: String,
+}
+
+/// `key` as a list, or `default` when unset.
+fn env_list_or(key: &str, default: &str) -> Vec<String> {
+    let list = env_list(key);
+    if list.is_empty() { vec![default.to_string()] } else { list }
+}
+
+fn env_list(key: &str) -> Vec<String> {
+    std::env::var(key)
+        .unwrap_or_default()
+        .split(',')
+        .map(|s| s.trim().to_ascii_lowercase())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// OIDC settings, or `None` when the provider isn't fully configured.
+pub fn config() -> Option<&'static OidcConfig> {
+    static CONFIG: OnceLock<Option<OidcConfig>> = OnceLock::new();
+    CONFIG
+        .get_or_init(|| {
+            let var = |k: &str| std::env::var(k).ok().filter(|v| !v.trim().is_empty());
+            Some(OidcConfig {
+                issuer: var("OIDC_ISSUER")?.trim_end_matches('/').to_string(),
+                client_id: var("OIDC_CLIENT_ID")?,
+                client_secret: var("OIDC_CLIENT_SECRET")?,
+                redirect_url: var("OIDC_REDIRECT_URL")?,
+                allowed_domains: env_list("OIDC_ALLOWED_DOMAINS"),
+                allowed_emails: env_list("OIDC_ALLOWED_EMAILS"),
+                admin_emails: env_list("OIDC_ADMIN_EMAILS"),
+                role_claim: var("OIDC_ROLE_CLAIM").unwrap_or_else(|| "roles".to_string()),
+                admin_roles: env_list_or("OIDC_ADMIN_ROLES", "admin"),
+                member_roles: env_list_or("OIDC_MEMBER_ROLES", "member"),
+                viewer_roles: env_list_or("OIDC_VIEWER_ROLES", "viewer"),
+                default_role: var("OIDC_DEFAULT_ROLE")
+                    .and_then(|r| Role::parse(&r))
+                    .unwrap_or(Role::Member),
+                post_login_redirect: var("OIDC_POST_LOGIN_REDIRECT")
+                    .unwrap_or_else(|| "/".to_string()),
+            })
+        })
+        .as_ref()
+}
+
+pub fn enabled() -> bool {
+    config().is_some()
+}
+
+#[derive(Debug, Deserialize)]
+struct Discovery {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+}
+
+async fn discovery(state: &AppState, cfg: &OidcConfig) -> Result<&'static Discovery, String> {
+    static DISCOVERY: tokio::sync::OnceCell<Discovery> = tokio::sync::OnceCell::const_new();
+    DISCOVERY
+        .get_or_try_init(|| async {
+            let url = format!("{}/.well-known/openid-configuration", cfg.issuer);
+            let resp = state
+                .http_client
+                .get(&url)
+                .timeout(std::time::Duration::from_secs(10))
+                .send()
+                .await
+                .map_err(|e| format!("discovery request failed: {}", e))?;
+            if !resp.status().is_success() {
+                return Err(format!("discovery returned {}", resp.status()));
+            }
+            resp.json::<Discovery>()
+                .await
+                .map_err(|e| format!("invalid discovery document: {}", e))
+        })
+        .await
+}
+
+fn error(status: StatusCode, msg: impl Into<String>) -> Response {
+    (status, Json(json!({ "error": msg.into() }))).into_response()
+}
+
+/// Config and signing secret, or the error response explaining what's missing.
+fn prerequisites(state: &AppState) -> Result<(&'static OidcConfig, String), Response> {
+    let cfg = config().ok_or_else(|| error(StatusCode::NOT_FOUND, "OIDC sign-in is not configured"))?;
+    let secret = state.auth_secret.clone().ok_or_else(|| {
+        error(
+            StatusCode::BAD_REQUEST,
+            "OIDC sign-in needs AUTH_SECRET to sign sessions",
+        )
+    })?;
+    Ok((cfg, secret))
+}
+
+fn b64url(bytes: &[u8]) -> String {
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn flow_cookie(value: &str, max_age: i64) -> (header::HeaderName, String) {
+    // Lax, not Strict: the provider's redirect back is a cross-site navigation.
+    let mut cookie = format!(
+        "{}={}; Path={}; Max-Age={}; SameSite=Lax; HttpOnly",
+        FLOW_COOKIE, value, FLOW_COOKIE_PATH, max_age
+    );
+    if web_session::secure_cookies() {
+        cookie.push_str("; Secure");
+    }
+    (header::SET_COOKIE, cookie)
+}
+
+/// Signed `<state>.<nonce>.<pkce verifier>` kept between login and callback.
+fn sign_flow(secret: &str, state: &str, nonce: &str, verifier: &str) -> String {
+    let payload = format!("{}.{}.{}", state, nonce, verifier);
+    let sig = crypto::hmac_sha256(secret.as_bytes(), format!("oidc|{}", payload).as_bytes());
+    format!("{}.{}", payload, sig)
+}
+
+fn verify_flow(secret: &str, value: &str) -> Option<(String, String, String)> {
+    let (payload, sig) = value.rsplit_once('.')?;
+    let expected = crypto::hmac_sha256(secret.as_bytes(), format!("oidc|{}", payload).as_bytes());
+    if !crypto::ct_eq(sig, &expected) {
+        return None;
+    }
+    let mut parts = payload.splitn(3, '.');
+    Some((
+        parts.next()?.to_string(),
+        parts.next()?.to_string(),
+        parts.next()?.to_string(),
+    ))
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  GET /api/auth/oidc/login
+// ═══════════════════════════════════════════════════════════════════════
+
+/// `GET /api/auth/oidc/login` — redirect to the identity provider
+pub async fn oidc_login(State(state): State<AppState>) -> Response {
+    let (cfg, secret) = match prerequisites(&state) {
+        Ok(p) => p,
+        Err(resp) => return resp,
+    };
+    let disc = match discovery(&state, cfg).await {
+        Ok(d) => d,
+        Err(e) => {
+            tracing::error!("oidc: {}", e);
+            return error(StatusCode::BAD_GATEWAY, "Identity provider unavailable");
+        }
+    };
+
+    let flow_state = uuid::Uuid::new_v4().simple().to_string();
+    let nonce = uuid::Uuid::new_v4().simple().to_string();
+    let verifier = format!(
+        "{}{}",
+        uuid::Uuid::new_v4().simple(),
+        uuid::Uuid::new_v4().simple()
+    );
+    let challenge = b64url(&Sha256::digest(verifier.as_bytes()));
+
+    let url = match url::Url::parse_with_params(
+        &disc.authorization_endpoint,
+        [
+            ("response_type", "code"),
+            ("client_id", cfg.client_id.as_str()),
+            ("redirect_uri", cfg.redirect_url.as_str()),
+            ("scope", "openid email profile"),
+            ("state", flow_state.as_str()),
+            ("nonce", nonce.as_str()),
+            ("code_challenge", challenge.as_str()),
+            ("code_challenge_method", "S256"),
+        ],
+    ) {
+        Ok(u) => u,
+        Err(e) => {
+            tracing::error!("oidc: bad authorization_endpoint: {}", e);
+            return error(StatusCode::BAD_GATEWAY, "Identity provider misconfigured");
+        }
+    };
+
+    let flow = sign_flow(&secret, &flow_state, &nonce, &verifier);
+    (
+        [flow_cookie(&flow, FLOW_MAX_AGE_SECS)],
+        Redirect::to(url.as_str()),
+    )
+        .into_response()
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  GET /api/auth/oidc/callback
+// ═══════════════════════════════════════════════════════════════════════
+
+#[derive(Debug, Deserialize)]
+pub struct CallbackQuery {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+    pub error_description: Option<String>,
+}
+
+/// Identity extracted from validated ID token claims.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Identity {
+    pub subject: String,
+    pub email: Option<String>,
+    pub name: Option<String>,
+}
+
+/// Decode the (unverified) JWT payload of an ID token.
+fn decode_id_token(token: &str) -> Option<Value> {
+    let payload = token.split('.').nth(1)?;
+    let bytes = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(payload.trim_end_matches('='))
+        .ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+/// Check issuer, audience, expiry, nonce and the email domain allow-list.
+fn validate_claims(
+    claims: &Value,
+    cfg: &OidcConfig,
+    issuer: &str,
+    nonce: &str,
+    now: i64,
+) -> Result<Identity, String> {
+    let str_claim = |k: &str| claims.get(k).and_then(|v| v.as_str());
+
+    if str_claim("iss").map(|i| i.trim_end_matches('/')) != Some(issuer.trim_end_matches('/')) {
+        return Err("ID token issuer mismatch".into());
+    }
+    let aud_ok = match claims.get("aud") {
+        Some(Value::String(a)) => *a == cfg.client_id,
+        Some(Value::Array(a)) => a.iter().any(|v| v.as_str() == Some(cfg.client_id.as_str())),
+        _ => false,
+    };
+    if !aud_ok {
+        return Err("ID token audience mismatch".into());
+    }
+    if claims.get("exp").and_then(|v| v.as_i64()).is_none_or(|exp| exp <= now) {
+        return Err("ID token expired".into());
+    }
+    if str_claim("nonce") != Some(nonce) {
+        return Err("ID token nonce mismatch".into());
+    }
+    let subject = str_claim("sub").ok_or("ID token has no subject")?.to_string();
+
+    // Only `email` is used for authorization (domains, OIDC_ADMIN_EMAILS).
+    // `preferred_username` is mutable and only shown as a name; Entra sends
+    // `email` once it is added as an optional claim.
+    let email = str_claim("email").map(|e| e.to_ascii_lowercase());
+    if claims.get("email_verified").and_then(|v| v.as_bool()) == Some(false) {
+        return Err("Email address is not verified".into());
+    }
+    if !cfg.allowed_domains.is_empty() {
+        let domain = email.as_deref().and_then(|e| e.rsplit_once('@')).map(|(_, d)| d);
+        if !domain.is_some_and(|d| cfg.allowed_domains.iter().any(|a| a == d)) {
+            return Err("Email domain is not allowed".into());
+        }
+    }
+
+    Ok(Identity {
+        subject,
+        email,
+        name: str_claim("name")
+            .or_else(|| str_claim("preferred_username"))
+            .map(str::to_string),
+    })
+}
+
+/// Map identity claims to a role (see module header for the precedence);
+/// `None` when the user isn't admitted at all.
+fn map_role(cfg: &OidcConfig, identity: &Identity, claims: &Value) -> Option<Role> {
+    let email = identity.email.as_ref();
+    if email.is_some_and(|e| cfg.admin_emails.contains(e)) {
+        return Some(Role::Admin);
+    }
+    let values: Vec<&str> = match claims.get(&cfg.role_claim) {
+        Some(Value::String(s)) => vec![s.as_str()],
+        Some(Value::Array(a)) => a.iter().filter_map(|v| v.as_str()).collect(),
+        _ => Vec::new(),
+    };
+    let has = |granting: &[String]| {
+        values
+            .iter()
+            .any(|v| granting.iter().any(|g| g.eq_ignore_ascii_case(v.trim())))
+    };
+    let claimed = if has(&cfg.admin_roles) {
+        Some(Role::Admin)
+    } else if has(&cfg.member_roles) {
+        Some(Role::Member)
+    } else if has(&cfg.viewer_roles) {
+        Some(Role::Viewer)
+    } else {
+        None
+    };
+    if claimed.is_some() {
+        return claimed;
+    }
+    // `validate_claims` already refused other domains when domains are set.
+    let allowed = email.is_some_and(|e| {
+        cfg.allowed_emails.contains(e)
+            || e.rsplit_once('@')
+                .is_some_and(|(_, d)| cfg.allowed_domains.iter().any(|a| a == d))
+    });
+    allowed.then_some(cfg.default_role)
+}
+
+/// `GET /api/auth/oidc/callback` — finish sign-in and start a web session
+pub async fn oidc_callback(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(q): Query<CallbackQuery>,
+) -> Response {
+    let (cfg, secret) = match prerequisites(&state) {
+        Ok(p) => p,
+        Err(resp) => return resp,
+    };
+    if let Some(err) = q.error {
+        tracing::warn!("oidc: provider returned error {}", err);
+        return error(
+            StatusCode::UNAUTHORIZED,
+            q.error_description.unwrap_or(err),
+        );
+    }
+    let Some(code) = q.code else {
+        return error(StatusCode::BAD_REQUEST, "Missing authorization code");
+    };
+    let flow = web_session::cookie(&headers, FLOW_COOKIE).and_then(|v| verify_flow(&secret, v));
+    let Some((expected_state, nonce, verifier)) = flow else {
+        return error(StatusCode::BAD_REQUEST, "Sign-in expired — start again");
+    };
+    if q.state.as_deref().is_none_or(|s| !crypto::ct_eq(s, &expected_state)) {
+        return error(StatusCode::BAD_REQUEST, "Sign-in state mismatch — start again");
+    }
+
+    let disc = match discovery(&state, cfg).await {
+        Ok(d) => d,
+        Err(e) => {
+            tracing::error!("oidc: {}", e);
+            return error(StatusCode::BAD_GATEWAY, "Identity provider unavailable");
+        }
+    };
+    let token_resp = state
+        .http_client
+        .post(&disc.token_endpoint)
+        .timeout(std::time::Duration::from_secs(15))
+        .form(&[
+            ("grant_type", "authorization_code"),
+            ("code", code.as_str()),
+            ("redirect_uri", cfg.redirect_url.as_str()),
+            ("client_id", cfg.client_id.as_str()),
+            ("client_secret", cfg.client_secret.as_str()),
+            ("code_verifier", verifier.as_str()),
+        ])
+        .send()
+        .await;
+    let tokens: Value = match token_resp {
+        Ok(r) if r.status().is_success() => r.json().await.unwrap_or_default(),
+        Ok(r) => {
+            tracing::warn!("oidc: token exchange returned {}", r.status());
+            return error(StatusCode::UNAUTHORIZED, "Token exchange failed");
+        }
+        Err(e) => {
+            tracing::error!("oidc: token exchange failed: {}", e);
+            return error(StatusCode::BAD_GATEWAY, "Identity provider unavailable");
+        }
+    };
+
+    let Some(claims) = tokens["id_token"].as_str().and_then(decode_id_token) else {
+        return error(StatusCode::UNAUTHORIZED, "Provider returned no ID token");
+    };
+    let now = chrono::Utc::now().timestamp();
+    let identity = match validate_claims(&claims, cfg, &disc.issuer, &nonce, now) {
+        Ok(i) => i,
+        Err(e) => {
+            tracing::warn!("oidc: rejected sign-in: {}", e);
+            return error(StatusCode::FORBIDDEN, e);
+        }
+    };
+    let Some(role) = map_role(cfg, &identity, &claims) else {
+        tracing::warn!(
+            "oidc: refused sign-in of {}: not in OIDC_ALLOWED_DOMAINS / OIDC_ALLOWED_EMAILS and no role claim",
+            identity.email.as_deref().unwrap_or(&identity.subject)
+        );
+        return error(StatusCode::FORBIDDEN, "This account is not allowed to sign in");
+    };
+
+    let user = sqlx::query_as::<_, (uuid::Uuid, bool)>(
+        "INSERT INTO ch_users (issuer, subject, email, name, role, last_login_at) \
+         VALUES ($1, $2, $3, $4, $5, NOW()) \
+         ON CONFLICT (issuer, subject) DO UPDATE SET \
+             email = EXCLUDED.email, name = EXCLUDED.name, \
+             role = EXCLUDED.role, last_login_at = NOW() \
+         RETURNING id, disabled",
+    )
+    .bind(&disc.issuer)
+    .bind(&identity.subject)
+    .bind(&identity.email)
+    .bind(&identity.name)
+    .bind(role.as_str())
+    .fetch_one(&state.db)
+    .await;
+    let user_id = match user {
+        Ok((_, true)) => return error(StatusCode::FORBIDDEN, "This account is disabled"),
+        Ok((id, false)) => id,
+        Err(e) => {
+            tracing::error!("oidc: failed to upsert user: {}", e);
+            return error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to record user");
+        }
+    };
+
+    tracing::info!(
+        "oidc: {} signed in as {}",
+        identity.email.as_deref().unwrap_or(&identity.subject),
+        role.as_str()
+    );
+    let (cookies, _) = web_session::start_session(&secret, user_id.simple().to_string(), role);
+    (
+        cookies,
+        [flow_cookie("", 0)],
+        Redirect::to(&cfg.post_login_redirect),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cfg() -> OidcConfig {
+        OidcConfig {
+            issuer: "https://login.example.com".into(),
+            client_id: "client-1".into(),
+            client_secret: "s".into(),
+            redirect_url: "https://app.example.com/api/auth/oidc/callback".into(),
+            allowed_domains: vec!["example.com".into()],
+            allowed_emails: Vec::new(),
+            admin_emails: vec!["boss@example.com".into()],
+            role_claim: "roles".into(),
+            admin_roles: vec!["claudehydra.admin".into(), "admin".into()],
+            member_roles: vec!["member".into()],
+            viewer_roles: vec!["claudehydra.viewer".into(), "viewer".into()],
+            default_role: Role::Member,
+            post_login_redirect: "/".into(),
+        }
+    }
+
+    fn claims(email: &str) -> Value {
+        json!({
+            "iss": "https://login.example.com/",
+            "aud": ["client-1"],
+            "exp": 2_000,
+            "nonce": "n1",
+            "sub": "user-1",
+            "email": email,
+            "name": "Geralt",
+        })
+    }
+
+    #[test]
+    fn id_token_payload_is_decoded() {
+        let payload = b64url(br#"{"sub":"abc"}"#.as_slice());
+        let token = format!("eyJhbGciOiJSUzI1NiJ9.{}.sig", payload);
+        assert_eq!(decode_id_token(&token).unwrap()["sub"], "abc");
+        assert!(decode_id_token("not-a-jwt").is_none());
+    }
+
+    #[test]
+    fn claims_are_validated() {
+        let cfg = cfg();
+        let issuer = "https://login.example.com";
+        let id = validate_claims(&claims("Dev@Example.com"), &cfg, issuer, "n1", 1_000).unwrap();
+        assert_eq!(id.email.as_deref(), Some("dev@example.com"));
+
+        assert!(validate_claims(&claims("dev@example.com"), &cfg, issuer, "n2", 1_000).is_err());
+        assert!(validate_claims(&claims("dev@example.com"), &cfg, issuer, "n1", 3_000).is_err());
+        assert!(validate_claims(&claims("dev@other.org"), &cfg, issuer, "n1", 1_000).is_err());
+        let mut wrong_aud = claims("dev@example.com");
+        wrong_aud["aud"] = json!("someone-else");
+        assert!(validate_claims(&wrong_aud, &cfg, issuer, "n1", 1_000).is_err());
+
+        // `preferred_username` is never taken as the email.
+        let mut upn_only = claims("dev@example.com");
+        upn_only.as_object_mut().unwrap().remove("email");
+        upn_only["preferred_username"] = json!("boss@example.com");
+        assert!(validate_claims(&upn_only, &cfg, issuer, "n1", 1_000).is_err());
+        let open = OidcConfig { allowed_domains: Vec::new(), ..cfg.clone() };
+        let id = validate_claims(&upn_only, &open, issuer, "n1", 1_000).unwrap();
+        assert_eq!(id.email, None);
+        assert_eq!(map_role(&open, &id, &upn_only), None);
+    }
+
+    #[test]
+    fn roles_are_mapped_from_claims() {
+        let cfg = cfg();
+        let identity = |email: &str| Identity {
+            subject: "s".into(),
+            email: Some(email.into()),
+            name: None,
+        };
+        let none = json!({});
+        assert_eq!(map_role(&cfg, &identity("boss@example.com"), &none), Some(Role::Admin));
+        assert_eq!(map_role(&cfg, &identity("dev@example.com"), &none), Some(Role::Member));
+        let entra = json!({ "roles": ["ClaudeHydra.Viewer", "ClaudeHydra.Admin"] });
+        assert_eq!(map_role(&cfg, &identity("dev@example.com"), &entra), Some(Role::Admin));
+        let viewer = json!({ "roles": "viewer" });
+        assert_eq!(map_role(&cfg, &identity("dev@example.com"), &viewer), Some(Role::Viewer));
+        // Only exact values count, not anything ending in a role name.
+        let lookalikes = json!({ "roles": ["contractors/admin", "app.Admin", "admins"] });
+        assert_eq!(map_role(&cfg, &identity("dev@example.com"), &lookalikes), Some(Role::Member));
+    }
+
+    #[test]
+    fn unlisted_users_are_refused_without_allowed_domains() {
+        let open = OidcConfig {
+            allowed_domains: Vec::new(),
+            allowed_emails: vec!["friend@gmail.com".into()],
+            ..cfg()
+        };
+        let identity = |email: &str| Identity {
+            subject: "s".into(),
+            email: Some(email.into()),
+            name: None,
+        };
+        let none = json!({});
+        assert_eq!(map_role(&open, &identity("stranger@gmail.com"), &none), None);
+        assert_eq!(map_role(&open, &identity("friend@gmail.com"), &none), Some(Role::Member));
+        assert_eq!(map_role(&open, &identity("boss@example.com"), &none), Some(Role::Admin));
+        let viewer = json!({ "roles": ["viewer"] });
+        assert_eq!(map_role(&open, &identity("stranger@gmail.com"), &viewer), Some(Role::Viewer));
+    }
+
+    #[test]
+    fn flow_cookie_is_signed() {
+        let value = sign_flow("secret", "st", "no", "ver");
+        assert_eq!(
+            verify_flow("secret", &value),
+            Some(("st".into(), "no".into(), "ver".into()))
+        );
+        assert_eq!(verify_flow("other", &value), None);
+    }
+}
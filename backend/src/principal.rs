@@ -0,0 +1,93 @@
+// ClaudeHydra v4 -- Request principal for usage attribution
+// Who made a request, as recorded in `ch_usage_events.user_id`:
+//   - `user:<subject>` for web sessions (the ch_users id for OIDC sign-ins,
+//     a per-sign-in nonce for AUTH_SECRET ones, see web_session),
+//   - `token:<id>` for `chk_…` API tokens (see api_tokens).
+// Bearer AUTH_SECRET callers stay anonymous (`None`), like single-user
+// deployments. The middleware runs the request with its principal set;
+// `handlers::prompt::resolve_request_scope` copies it into the request scope
+// so detached streams keep it after the handler has returned.
+
+use axum::extract::Request;
+use axum::middleware::Next;
+use axum::response::Response;
+
+use crate::web_session::Session;
+
+/// API token that authenticated the request (set by `api_tokens::token_auth`).
+#[derive(Debug, Clone)]
+pub struct TokenPrincipal(pub uuid::Uuid);
+
+tokio::task_local! {
+    static PRINCIPAL: String;
+}
+
+fn principal_of(session: Option<&Session>, token: Option<&TokenPrincipal>) -> Option<String> {
+    match (token, session) {
+        (Some(TokenPrincipal(id)), _) => Some(format!("token:{}", id)),
+        (None, Some(session)) => Some(format!("user:{}", session.subject)),
+        (None, None) => None,
+    }
+}
+
+/// Principal of the current request (middleware task), if any.
+pub fn requested() -> Option<String> {
+    PRINCIPAL.try_with(Clone::clone).ok()
+}
+
+/// Principal usage recorded now is attributed to.
+pub fn current() -> Option<String> {
+    crate::request_scope::current()
+        .and_then(|s| s.principal.clone())
+        .or_else(requested)
+}
+
+/// Run `fut` as `principal` — for work that outlives the request task, like
+/// a WebSocket connection after the upgrade.
+pub async fn scope<F: std::future::Future>(principal: Option<String>, fut: F) -> F::Output {
+    match principal {
+        Some(principal) => PRINCIPAL.scope(principal, fut).await,
+        None => fut.await,
+    }
+}
+
+/// Middleware: run the request with its session or API token as principal.
+pub async fn attach(req: Request, next: Next) -> Response {
+    let principal = principal_of(
+        req.extensions().get::<Session>(),
+        req.extensions().get::<TokenPrincipal>(),
+    );
+    match principal {
+        Some(principal) => PRINCIPAL.scope(principal, next.run(req)).await,
+        None => next.run(req).await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::web_session::Role;
+
+    #[test]
+    fn tokens_win_over_sessions() {
+        let session = Session {
+            expires_at: 0,
+            subject: "abc".into(),
+            role: Role::Member,
+        };
+        let token = TokenPrincipal(uuid::Uuid::nil());
+        assert_eq!(principal_of(Some(&session), None).as_deref(), Some("user:abc"));
+        assert_eq!(
+            principal_of(Some(&session), Some(&token)).as_deref(),
+            Some("token:00000000-0000-0000-0000-000000000000")
+        );
+        assert_eq!(principal_of(None, None), None);
+    }
+
+    #[tokio::test]
+    async fn principal_is_visible_inside_the_request() {
+        assert_eq!(current(), None);
+        let seen = PRINCIPAL.scope("user:abc".into(), async { current() }).await;
+        assert_eq!(seen.as_deref(), Some("user:abc"));
+    }
+}
@@ -0,0 +1,254 @@
+// ClaudeHydra v4 -- Request priority classes
+// Upstream Anthropic calls go through a concurrency gate
+// (ANTHROPIC_MAX_CONCURRENCY slots, 0 = unlimited). When every slot is busy,
+// waiting requests are admitted by priority: `high`, then `normal`, then `low`,
+// first come first served within a class. Interactive chats therefore jump
+// ahead of queued batch work (`low`); running requests are never interrupted.
+// A request waits at most PRIORITY_QUEUE_TIMEOUT_SECS and then fails with 503.
+//
+// A request holds one slot from its first upstream call until it finishes —
+// the whole tool loop and the streamed reply — via its request scope. Calls
+// made outside a request scope hold a slot for the call only.
+//
+// Priority comes from the request's `priority` field, else the API token's
+// `default_priority`, else `normal`.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::Json;
+use axum::extract::State;
+use axum::http::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tokio::sync::oneshot;
+use utoipa::ToSchema;
+
+use crate::state::AppState;
+
+const DEFAULT_QUEUE_TIMEOUT_SECS: u64 = 120;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    High,
+    #[default]
+    Normal,
+    Low,
+}
+
+impl Priority {
+    pub const ALL: [Priority; 3] = [Priority::High, Priority::Normal, Priority::Low];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Priority::High => "high",
+            Priority::Normal => "normal",
+            Priority::Low => "low",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|p| p.as_str() == s)
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Request extension: the default priority of the authenticating API token.
+#[derive(Debug, Clone, Copy)]
+pub struct DefaultPriority(pub Priority);
+
+struct GateState {
+    active: usize,
+    /// Waiters per priority (index = `Priority::index`).
+    queues: [VecDeque<oneshot::Sender<()>>; 3],
+}
+
+impl GateState {
+    /// Forget waiters that gave up.
+    fn prune(&mut self) {
+        for q in &mut self.queues {
+            q.retain(|tx| !tx.is_closed());
+        }
+    }
+}
+
+/// Priority-ordered concurrency limiter for upstream calls.
+pub struct PriorityGate {
+    max: usize,
+    queue_timeout: Duration,
+    state: Mutex<GateState>,
+}
+
+/// A held slot; released (or handed to the next waiter) on drop.
+pub struct Permit {
+    gate: Arc<PriorityGate>,
+}
+
+impl std::fmt::Debug for Permit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Permit")
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.gate.release();
+    }
+}
+
+impl PriorityGate {
+    pub fn new(max: usize, queue_timeout: Duration) -> Arc<Self> {
+        Arc::new(Self {
+            max,
+            queue_timeout,
+            state: Mutex::new(GateState {
+                active: 0,
+                queues: Default::default(),
+            }),
+        })
+    }
+
+    /// Gate configured from `ANTHROPIC_MAX_CONCURRENCY` and
+    /// `PRIORITY_QUEUE_TIMEOUT_SECS`.
+    pub fn from_env() -> Arc<Self> {
+        let env = |key: &str| std::env::var(key).ok().and_then(|v| v.parse::<u64>().ok());
+        Self::new(
+            env("ANTHROPIC_MAX_CONCURRENCY").unwrap_or(0) as usize,
+            Duration::from_secs(env("PRIORITY_QUEUE_TIMEOUT_SECS").unwrap_or(DEFAULT_QUEUE_TIMEOUT_SECS)),
+        )
+    }
+
+    /// Take a slot, waiting behind higher-priority requests when saturated.
+    /// `None` when the queue timeout passed.
+    pub async fn acquire(self: &Arc<Self>, priority: Priority) -> Option<Permit> {
+        let mut rx = {
+            let mut st = self.state.lock().ok()?;
+            if self.max == 0 || st.active < self.max {
+                st.active += 1;
+                return Some(Permit { gate: self.clone() });
+            }
+            st.prune();
+            let (tx, rx) = oneshot::channel();
+            st.queues[priority.index()].push_back(tx);
+            rx
+        };
+        match tokio::time::timeout(self.queue_timeout, &mut rx).await {
+            Ok(Ok(())) => Some(Permit { gate: self.clone() }),
+            Ok(Err(_)) => None,
+            Err(_) => {
+                // A slot may have been handed over just as we gave up.
+                rx.close();
+                rx.try_recv().ok().map(|()| Permit { gate: self.clone() })
+            }
+        }
+    }
+
+    fn release(&self) {
+        let Ok(mut st) = self.state.lock() else {
+            return;
+        };
+        for q in &mut st.queues {
+            while let Some(tx) = q.pop_front() {
+                // The slot moves to the waiter; `active` stays the same.
+                if tx.send(()).is_ok() {
+                    return;
+                }
+            }
+        }
+        st.active = st.active.saturating_sub(1);
+    }
+
+    pub fn stats(&self) -> Value {
+        let Ok(mut st) = self.state.lock() else {
+            return json!({});
+        };
+        st.prune();
+        let queued: serde_json::Map<String, Value> = Priority::ALL
+            .into_iter()
+            .map(|p| (p.as_str().to_string(), json!(st.queues[p.index()].len())))
+            .collect();
+        json!({
+            "max_concurrency": (self.max > 0).then_some(self.max),
+            "active": st.active,
+            "queued": queued,
+            "queue_timeout_secs": self.queue_timeout.as_secs(),
+        })
+    }
+}
+
+/// Hold a slot for the current upstream call. Inside a request scope the slot
+/// is taken once and kept by the scope; outside one it's returned for the
+/// caller to hold.
+pub async fn admit(state: &AppState) -> Result<Option<Permit>, (StatusCode, Json<Value>)> {
+    let scope = crate::request_scope::current();
+    if scope.as_ref().is_some_and(|s| s.permit.get().is_some()) {
+        return Ok(None);
+    }
+    let priority = scope.as_ref().map(|s| s.priority).unwrap_or_default();
+    let permit = state.priority_gate.acquire(priority).await.ok_or_else(|| {
+        tracing::warn!("priority: {} request timed out waiting for an upstream slot", priority.as_str());
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({
+                "error": "Upstream concurrency limit reached — retry later",
+                "priority": priority,
+            })),
+        )
+    })?;
+    match scope {
+        Some(scope) => {
+            let _ = scope.permit.set(Arc::new(permit));
+            Ok(None)
+        }
+        None => Ok(Some(permit)),
+    }
+}
+
+/// `GET /api/system/concurrency` — upstream slots in use and queued requests per priority
+pub async fn concurrency_status(State(state): State<AppState>) -> Json<Value> {
+    Json(state.priority_gate.stats())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn waiters_are_admitted_by_priority() {
+        let gate = PriorityGate::new(1, Duration::from_secs(5));
+        let first = gate.acquire(Priority::Normal).await.unwrap();
+
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let mut tasks = Vec::new();
+        for p in [Priority::Low, Priority::Normal, Priority::High] {
+            let (gate, order) = (gate.clone(), order.clone());
+            tasks.push(tokio::spawn(async move {
+                let permit = gate.acquire(p).await.unwrap();
+                order.lock().unwrap().push(p);
+                drop(permit);
+            }));
+            // Let each waiter enqueue before the next one.
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(gate.stats()["queued"]["low"], 1);
+        drop(first);
+        for t in tasks {
+            t.await.unwrap();
+        }
+        assert_eq!(*order.lock().unwrap(), vec![Priority::High, Priority::Normal, Priority::Low]);
+        assert_eq!(gate.stats()["active"], 0);
+    }
+
+    #[tokio::test]
+    async fn saturated_gate_times_out() {
+        let gate = PriorityGate::new(1, Duration::from_millis(20));
+        let _held = gate.acquire(Priority::High).await.unwrap();
+        assert!(gate.acquire(Priority::Low).await.is_none());
+        assert_eq!(gate.stats()["queued"]["low"], 0);
+    }
+}
@@ -0,0 +1,208 @@
+// ClaudeHydra v4 -- In-situ profiling (feature `pprof`)
+// Opt-in builds only: `cargo build --features pprof` (Unix; heap profiles on
+// Linux only). The whole process can then be profiled where it runs:
+//   - GET /api/debug/pprof/cpu  — samples every thread for `seconds`
+//     (default 10, max 120) at `frequency` Hz (default 99, max 1000),
+//   - GET /api/debug/pprof/heap — live allocations sampled by jemalloc
+//     (about one sample per 512 KiB allocated).
+// Both return a pprof protobuf (`go tool pprof`), or an SVG flame graph with
+// `?format=flamegraph`. The build alone is not enough: the endpoints answer
+// 404, and jemalloc does not sample, unless CH_PPROF=1 is set at startup.
+// `/api/debug/*` is admin-only, like `/api/admin/*`. One CPU profile runs at
+// a time; every capture is recorded in the audit log.
+
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use axum::Json;
+use axum::extract::{Path, Query, State};
+use axum::http::{StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::state::AppState;
+
+const DEFAULT_CPU_SECS: u64 = 10;
+const MAX_CPU_SECS: u64 = 120;
+const DEFAULT_FREQUENCY: i32 = 99;
+const MAX_FREQUENCY: i32 = 1000;
+/// Frames of these libraries are left out of CPU samples.
+const BLOCKLIST: &[&str] = &["libc", "libgcc", "pthread", "vdso"];
+
+type ApiError = (StatusCode, Json<Value>);
+
+static CPU_RUNNING: AtomicBool = AtomicBool::new(false);
+
+fn error(status: StatusCode, msg: impl Into<String>) -> ApiError {
+    (status, Json(json!({ "error": msg.into() })))
+}
+
+/// Whether profiling was opted into with CH_PPROF (read once).
+pub fn enabled() -> bool {
+    static ENABLED: OnceLock<bool> = OnceLock::new();
+    *ENABLED.get_or_init(|| {
+        std::env::var("CH_PPROF")
+            .map(|v| matches!(v.trim().to_lowercase().as_str(), "1" | "true" | "yes"))
+            .unwrap_or(false)
+    })
+}
+
+/// Start sampling allocations when profiling is enabled (startup).
+pub async fn init() {
+    if !enabled() {
+        return;
+    }
+    activate_heap().await;
+    tracing::warn!("profiling: /api/debug/pprof is enabled (CH_PPROF)");
+}
+
+#[cfg(target_os = "linux")]
+async fn activate_heap() {
+    let Some(ctl) = jemalloc_pprof::PROF_CTL.as_ref() else {
+        tracing::warn!("profiling: jemalloc profiling is unavailable, heap profiles are off");
+        return;
+    };
+    if let Err(e) = ctl.lock().await.activate() {
+        tracing::warn!("profiling: failed to activate heap sampling: {}", e);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+async fn activate_heap() {}
+
+/// Query of `GET /api/debug/pprof/{kind}`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ProfileQuery {
+    /// CPU sampling time (default 10, max 120).
+    pub seconds: Option<u64>,
+    /// CPU samples per second (default 99, max 1000).
+    pub frequency: Option<i32>,
+    /// `pprof` (default) or `flamegraph`.
+    pub format: Option<String>,
+}
+
+/// Resets `CPU_RUNNING` when the capture ends, also on panic.
+struct CpuSlot;
+
+impl Drop for CpuSlot {
+    fn drop(&mut self) {
+        CPU_RUNNING.store(false, Ordering::SeqCst);
+    }
+}
+
+async fn cpu_profile(seconds: u64, frequency: i32, flamegraph: bool) -> Result<Vec<u8>, ApiError> {
+    if CPU_RUNNING.swap(true, Ordering::SeqCst) {
+        return Err(error(StatusCode::CONFLICT, "A CPU profile is already running"));
+    }
+    let _slot = CpuSlot;
+    // The profiler guard is not `Send`; sample on a blocking thread.
+    tokio::task::spawn_blocking(move || -> Result<Vec<u8>, String> {
+        use pprof::protos::Message;
+
+        let guard = pprof::ProfilerGuardBuilder::default()
+            .frequency(frequency)
+            .blocklist(BLOCKLIST)
+            .build()
+            .map_err(|e| e.to_string())?;
+        std::thread::sleep(Duration::from_secs(seconds));
+        let report = guard.report().build().map_err(|e| e.to_string())?;
+        let mut body = Vec::new();
+        if flamegraph {
+            report.flamegraph(&mut body).map_err(|e| e.to_string())?;
+        } else {
+            let profile = report.pprof().map_err(|e| e.to_string())?;
+            profile.encode(&mut body).map_err(|e| e.to_string())?;
+        }
+        Ok(body)
+    })
+    .await
+    .map_err(|e| error(StatusCode::INTERNAL_SERVER_ERROR, format!("CPU profile failed: {}", e)))?
+    .map_err(|e| error(StatusCode::INTERNAL_SERVER_ERROR, format!("CPU profile failed: {}", e)))
+}
+
+#[cfg(target_os = "linux")]
+async fn heap_profile(flamegraph: bool) -> Result<Vec<u8>, ApiError> {
+    let Some(ctl) = jemalloc_pprof::PROF_CTL.as_ref() else {
+        return Err(error(StatusCode::SERVICE_UNAVAILABLE, "jemalloc profiling is unavailable"));
+    };
+    let mut ctl = ctl.lock().await;
+    if !ctl.activated() {
+        return Err(error(StatusCode::SERVICE_UNAVAILABLE, "Heap sampling is not active"));
+    }
+    let dumped = if flamegraph { ctl.dump_flamegraph() } else { ctl.dump_pprof() };
+    dumped.map_err(|e| error(StatusCode::INTERNAL_SERVER_ERROR, format!("Heap profile failed: {}", e)))
+}
+
+#[cfg(not(target_os = "linux"))]
+async fn heap_profile(_flamegraph: bool) -> Result<Vec<u8>, ApiError> {
+    Err(error(StatusCode::NOT_IMPLEMENTED, "Heap profiles are only available on Linux"))
+}
+
+/// `GET /api/debug/pprof/{kind}` — CPU or heap profile of the whole process
+#[utoipa::path(get, path = "/api/debug/pprof/{kind}", tag = "system",
+    params(
+        ("kind" = String, Path, description = "`cpu` or `heap`"),
+        ("seconds" = Option<u64>, Query, description = "CPU sampling time (default 10, max 120)"),
+        ("frequency" = Option<i32>, Query, description = "CPU samples per second (default 99, max 1000)"),
+        ("format" = Option<String>, Query, description = "`pprof` (default) or `flamegraph`")
+    ),
+    responses(
+        (status = 200, description = "pprof protobuf or SVG flame graph"),
+        (status = 400, description = "Unknown format"),
+        (status = 404, description = "Profiling not enabled, or unknown kind"),
+        (status = 409, description = "A CPU profile is already running")
+    ))]
+pub async fn profile(
+    State(state): State<AppState>,
+    Path(kind): Path<String>,
+    Query(query): Query<ProfileQuery>,
+) -> Result<Response, ApiError> {
+    if !enabled() {
+        return Err(error(StatusCode::NOT_FOUND, "Profiling is not enabled (set CH_PPROF=1)"));
+    }
+    let flamegraph = match query.format.as_deref().map(str::trim) {
+        None | Some("") | Some("pprof") => false,
+        Some("flamegraph") => true,
+        Some(other) => {
+            return Err(error(
+                StatusCode::BAD_REQUEST,
+                format!("Unknown format '{}' (use pprof or flamegraph)", other),
+            ));
+        }
+    };
+    let seconds = query.seconds.unwrap_or(DEFAULT_CPU_SECS).clamp(1, MAX_CPU_SECS);
+    let frequency = query.frequency.unwrap_or(DEFAULT_FREQUENCY).clamp(1, MAX_FREQUENCY);
+
+    let details = match kind.as_str() {
+        "cpu" => json!({ "kind": "cpu", "seconds": seconds, "frequency": frequency, "flamegraph": flamegraph }),
+        "heap" => json!({ "kind": "heap", "flamegraph": flamegraph }),
+        _ => return Err(error(StatusCode::NOT_FOUND, format!("Unknown profile '{}' (use cpu or heap)", kind))),
+    };
+    tracing::info!("profiling: {} profile requested", kind);
+    crate::audit::log_audit(&state.db, "pprof_profile", details, None).await;
+
+    let body = if kind == "cpu" {
+        cpu_profile(seconds, frequency, flamegraph).await?
+    } else {
+        heap_profile(flamegraph).await?
+    };
+    let (content_type, extension) = if flamegraph {
+        ("image/svg+xml", "svg")
+    } else {
+        ("application/octet-stream", "pb")
+    };
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}.{}\"", kind, extension),
+            ),
+        ],
+        body,
+    )
+        .into_response())
+}
@@ -0,0 +1,478 @@
+// ClaudeHydra v4 -- Prompt canaries
+// Edits to a shared prompt can be rolled out to a share of requests first:
+//   - `custom_instructions` — the custom instructions of the global layer,
+//   - `agent:<id>`          — an agent's persona (its description in the
+//                             agent layer).
+// While a canary runs, `percent` % of requests get the candidate text and the
+// rest the live one. The variant is recorded in the decision log and on the
+// request's usage events (`prompt_variants`), so `GET
+// /api/admin/prompt-canaries/{id}` can compare cost, reply length and refusal
+// rate of both. `promote` writes the candidate to the live setting,
+// `rollback` drops it; either ends the canary. One canary per target.
+
+use std::sync::RwLock;
+
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use utoipa::ToSchema;
+
+use crate::state::AppState;
+
+pub const INSTRUCTIONS: &str = "custom_instructions";
+const AGENT_PREFIX: &str = "agent:";
+
+type ApiError = (StatusCode, Json<Value>);
+
+pub fn agent_target(agent_id: &str) -> String {
+    format!("{}{}", AGENT_PREFIX, agent_id)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Variant {
+    /// The live text.
+    Control,
+    /// The canary's candidate text.
+    Candidate,
+}
+
+/// The variant of one canary a request was served with.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Arm {
+    pub canary_id: uuid::Uuid,
+    pub variant: Variant,
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+struct Canary {
+    id: uuid::Uuid,
+    target: String,
+    candidate: String,
+    percent: i16,
+}
+
+/// Running canaries (reloaded on every change, on all replicas).
+static RUNNING: RwLock<Vec<Canary>> = RwLock::new(Vec::new());
+
+/// Load the running canaries (startup and after changes).
+pub async fn load(db: &sqlx::PgPool) {
+    match sqlx::query_as::<_, Canary>(
+        "SELECT id, target, candidate, percent FROM ch_prompt_canaries WHERE status = 'running'",
+    )
+    .fetch_all(db)
+    .await
+    {
+        Ok(canaries) => {
+            if let Ok(mut running) = RUNNING.write() {
+                *running = canaries;
+            }
+        }
+        Err(e) => tracing::warn!("prompt_canary: failed to load canaries: {}", e),
+    }
+}
+
+/// Which variant a roll in [0, 1) lands on.
+fn variant_for(percent: i16, roll: f64) -> Variant {
+    if roll * 100.0 < percent as f64 {
+        Variant::Candidate
+    } else {
+        Variant::Control
+    }
+}
+
+/// Roll the running canary for `target`, if any. Returns the candidate text
+/// when the request is in the canary share (`None`: use the live text). The
+/// variant is recorded in the request scope either way.
+pub fn pick(target: &str) -> Option<String> {
+    let canary = RUNNING
+        .read()
+        .ok()?
+        .iter()
+        .find(|c| c.target == target)
+        .cloned()?;
+    let scope = crate::request_scope::current();
+    // A request resolving its prompt twice keeps the variant it rolled first.
+    let earlier = scope.as_ref().and_then(|s| {
+        s.prompt_variants
+            .entries()
+            .into_iter()
+            .find(|arm| arm.canary_id == canary.id)
+    });
+    let variant = match earlier {
+        Some(arm) => arm.variant,
+        None => {
+            let variant = variant_for(canary.percent, rand::random::<f64>());
+            crate::request_scope::decide(
+                "prompt_canary",
+                json!({ "canary_id": canary.id, "target": target, "variant": variant, "percent": canary.percent }),
+            );
+            if let Some(scope) = &scope {
+                scope.prompt_variants.push(Arm {
+                    canary_id: canary.id,
+                    variant,
+                });
+            }
+            variant
+        }
+    };
+    (variant == Variant::Candidate).then_some(canary.candidate)
+}
+
+/// Canary variants of the current request scope.
+pub fn current() -> Vec<Arm> {
+    crate::request_scope::current()
+        .map(|s| s.prompt_variants.entries())
+        .unwrap_or_default()
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  /api/admin/prompt-canaries
+// ═══════════════════════════════════════════════════════════════════════
+
+fn db_error(e: sqlx::Error) -> ApiError {
+    tracing::error!("prompt_canary: {}", e);
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(json!({ "error": "Prompt canary query failed" })),
+    )
+}
+
+fn not_found(id: uuid::Uuid) -> ApiError {
+    (
+        StatusCode::NOT_FOUND,
+        Json(json!({ "error": format!("Prompt canary '{}' not found", id) })),
+    )
+}
+
+/// The live text of `target`; `None` for an unknown target.
+async fn live_text(state: &AppState, target: &str) -> Result<Option<String>, ApiError> {
+    if target == INSTRUCTIONS {
+        let text: Option<String> =
+            sqlx::query_scalar("SELECT COALESCE(custom_instructions, '') FROM ch_settings WHERE id = 1")
+                .fetch_optional(&state.db)
+                .await
+                .map_err(db_error)?;
+        return Ok(Some(text.unwrap_or_default()));
+    }
+    let Some(agent_id) = target.strip_prefix(AGENT_PREFIX) else {
+        return Ok(None);
+    };
+    Ok(state
+        .agents
+        .read()
+        .await
+        .iter()
+        .find(|a| a.id == agent_id)
+        .map(|a| a.description.clone()))
+}
+
+/// Per-variant metrics from the usage events of a canary.
+async fn metrics(db: &sqlx::PgPool, id: uuid::Uuid) -> Result<Value, ApiError> {
+    let rows: Vec<(String, i64, f64, f64, f64, f64, f64)> = sqlx::query_as(
+        "SELECT v->>'variant', COUNT(*), \
+                COALESCE(AVG(e.input_tokens), 0)::FLOAT8, COALESCE(AVG(e.output_tokens), 0)::FLOAT8, \
+                COALESCE(AVG(e.cost_usd), 0)::FLOAT8, COALESCE(SUM(e.cost_usd), 0)::FLOAT8, \
+                COALESCE(AVG(e.refused::INT), 0)::FLOAT8 \
+         FROM ch_usage_events e, jsonb_array_elements(e.prompt_variants) v \
+         WHERE e.prompt_variants IS NOT NULL AND v->>'canary_id' = $1 \
+         GROUP BY 1",
+    )
+    .bind(id.to_string())
+    .fetch_all(db)
+    .await
+    .map_err(db_error)?;
+    let arm = |variant: &str| {
+        rows.iter().find(|r| r.0 == variant).map(|r| {
+            json!({
+                "requests": r.1,
+                "avg_input_tokens": r.2,
+                "avg_output_tokens": r.3,
+                "avg_cost_usd": r.4,
+                "total_cost_usd": r.5,
+                "refusal_rate": r.6,
+            })
+        })
+    };
+    let (control, candidate) = (arm("control"), arm("candidate"));
+    let delta = |field: &str| {
+        let c = control.as_ref()?.get(field)?.as_f64()?;
+        let n = candidate.as_ref()?.get(field)?.as_f64()?;
+        (c > 0.0).then(|| ((n - c) / c * 1000.0).round() / 10.0)
+    };
+    let comparison = json!({
+        "avg_cost_pct": delta("avg_cost_usd"),
+        "avg_output_tokens_pct": delta("avg_output_tokens"),
+        "refusal_rate_diff": match (&control, &candidate) {
+            (Some(c), Some(n)) => json!(n["refusal_rate"].as_f64().unwrap_or(0.0) - c["refusal_rate"].as_f64().unwrap_or(0.0)),
+            _ => Value::Null,
+        },
+    });
+    Ok(json!({ "control": control, "candidate": candidate, "comparison": comparison }))
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct CanaryRow {
+    id: uuid::Uuid,
+    target: String,
+    candidate: String,
+    baseline: String,
+    percent: i16,
+    status: String,
+    created_at: chrono::DateTime<chrono::Utc>,
+    ended_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+const CANARY_COLUMNS: &str = "id, target, candidate, baseline, percent, status, created_at, ended_at";
+
+async fn canary_json(state: &AppState, row: CanaryRow) -> Result<Value, ApiError> {
+    let metrics = metrics(crate::read_replica::pool(state), row.id).await?;
+    Ok(json!({
+        "id": row.id,
+        "target": row.target,
+        "candidate": row.candidate,
+        "baseline": row.baseline,
+        "percent": row.percent,
+        "status": row.status,
+        "created_at": row.created_at,
+        "ended_at": row.ended_at,
+        "metrics": metrics,
+    }))
+}
+
+async fn fetch(db: &sqlx::PgPool, id: uuid::Uuid) -> Result<CanaryRow, ApiError> {
+    sqlx::query_as::<_, CanaryRow>(&format!("SELECT {} FROM ch_prompt_canaries WHERE id = $1", CANARY_COLUMNS))
+        .bind(id)
+        .fetch_optional(db)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| not_found(id))
+}
+
+/// Reload the running set here and on the other replicas.
+async fn changed(state: &AppState) {
+    load(&state.db).await;
+    crate::cluster::publish(state, crate::cluster::ClusterEvent::PromptCanaries);
+}
+
+/// `GET /api/admin/prompt-canaries` — running and recent canaries with metrics
+#[utoipa::path(get, path = "/api/admin/prompt-canaries", tag = "system",
+    responses((status = 200, description = "Canaries, newest first, with per-variant metrics")))]
+pub async fn list_canaries(State(state): State<AppState>) -> Result<Json<Value>, ApiError> {
+    let rows = sqlx::query_as::<_, CanaryRow>(&format!(
+        "SELECT {} FROM ch_prompt_canaries ORDER BY created_at DESC LIMIT 50",
+        CANARY_COLUMNS
+    ))
+    .fetch_all(&state.db)
+    .await
+    .map_err(db_error)?;
+    let mut canaries = Vec::with_capacity(rows.len());
+    for row in rows {
+        canaries.push(canary_json(&state, row).await?);
+    }
+    Ok(Json(json!({ "canaries": canaries })))
+}
+
+/// `GET /api/admin/prompt-canaries/{id}` — one canary with metrics
+#[utoipa::path(get, path = "/api/admin/prompt-canaries/{id}", tag = "system",
+    params(("id" = String, Path, description = "Canary UUID")),
+    responses(
+        (status = 200, description = "The canary with per-variant metrics"),
+        (status = 404, description = "No such canary")
+    ))]
+pub async fn get_canary(
+    State(state): State<AppState>,
+    Path(id): Path<uuid::Uuid>,
+) -> Result<Json<Value>, ApiError> {
+    let row = fetch(&state.db, id).await?;
+    Ok(Json(canary_json(&state, row).await?))
+}
+
+/// Request body for `POST /api/admin/prompt-canaries`.
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct CreateCanaryRequest {
+    /// `custom_instructions` or `agent:<id>`.
+    pub target: String,
+    /// The edited text.
+    pub candidate: String,
+    /// Share of requests (1–99) that get the candidate.
+    pub percent: i16,
+}
+
+/// `POST /api/admin/prompt-canaries` — start a canary
+#[utoipa::path(post, path = "/api/admin/prompt-canaries", tag = "system",
+    request_body = CreateCanaryRequest,
+    responses(
+        (status = 201, description = "Canary started"),
+        (status = 400, description = "Unknown target, empty candidate or percent outside 1–99"),
+        (status = 409, description = "A canary is already running for the target")
+    ))]
+pub async fn create_canary(
+    State(state): State<AppState>,
+    Json(req): Json<CreateCanaryRequest>,
+) -> Result<(StatusCode, Json<Value>), ApiError> {
+    let bad = |msg: &str| (StatusCode::BAD_REQUEST, Json(json!({ "error": msg })));
+    if !(1..=99).contains(&req.percent) {
+        return Err(bad("percent must be between 1 and 99"));
+    }
+    if req.candidate.trim().is_empty() {
+        return Err(bad("candidate must not be empty"));
+    }
+    if req.target != INSTRUCTIONS && !req.target.starts_with(AGENT_PREFIX) {
+        return Err(bad("target must be custom_instructions or agent:<id>"));
+    }
+    let Some(baseline) = live_text(&state, &req.target).await? else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": format!("Unknown target '{}'", req.target) })),
+        ));
+    };
+    let row = sqlx::query_as::<_, CanaryRow>(&format!(
+        "INSERT INTO ch_prompt_canaries (target, candidate, baseline, percent) VALUES ($1, $2, $3, $4) \
+         RETURNING {}",
+        CANARY_COLUMNS
+    ))
+    .bind(&req.target)
+    .bind(&req.candidate)
+    .bind(&baseline)
+    .bind(req.percent)
+    .fetch_one(&state.db)
+    .await
+    .map_err(|e| {
+        if e.to_string().contains("idx_ch_prompt_canaries_running") {
+            (
+                StatusCode::CONFLICT,
+                Json(json!({ "error": format!("A canary is already running for '{}'", req.target) })),
+            )
+        } else {
+            db_error(e)
+        }
+    })?;
+    changed(&state).await;
+    crate::audit::log_audit(
+        &state.db,
+        "create_prompt_canary",
+        json!({ "id": row.id, "target": row.target, "percent": row.percent }),
+        None,
+    )
+    .await;
+    Ok((StatusCode::CREATED, Json(canary_json(&state, row).await?)))
+}
+
+/// End a running canary with `status`.
+async fn end(state: &AppState, id: uuid::Uuid, status: &str) -> Result<CanaryRow, ApiError> {
+    let row = fetch(&state.db, id).await?;
+    if row.status != "running" {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(json!({ "error": format!("The canary already ended ({})", row.status) })),
+        ));
+    }
+    sqlx::query("UPDATE ch_prompt_canaries SET status = $2, ended_at = NOW() WHERE id = $1 AND status = 'running'")
+        .bind(id)
+        .bind(status)
+        .execute(&state.db)
+        .await
+        .map_err(db_error)?;
+    fetch(&state.db, id).await
+}
+
+/// `POST /api/admin/prompt-canaries/{id}/promote` — make the candidate live
+#[utoipa::path(post, path = "/api/admin/prompt-canaries/{id}/promote", tag = "system",
+    params(("id" = String, Path, description = "Canary UUID")),
+    responses(
+        (status = 200, description = "Candidate written to the live setting; canary ended"),
+        (status = 404, description = "No such canary"),
+        (status = 409, description = "The canary already ended")
+    ))]
+pub async fn promote_canary(
+    State(state): State<AppState>,
+    Path(id): Path<uuid::Uuid>,
+) -> Result<Json<Value>, ApiError> {
+    let row = fetch(&state.db, id).await?;
+    if row.status == "running" {
+        if row.target == INSTRUCTIONS {
+            sqlx::query("UPDATE ch_settings SET custom_instructions = $1, updated_at = NOW() WHERE id = 1")
+                .bind(&row.candidate)
+                .execute(&state.db)
+                .await
+                .map_err(db_error)?;
+        } else if let Some(agent_id) = row.target.strip_prefix(AGENT_PREFIX) {
+            let updated = sqlx::query("UPDATE ch_agents_config SET description = $2, updated_at = NOW() WHERE id = $1")
+                .bind(agent_id)
+                .bind(&row.candidate)
+                .execute(&state.db)
+                .await
+                .map_err(db_error)?;
+            if updated.rows_affected() == 0 {
+                return Err((
+                    StatusCode::NOT_FOUND,
+                    Json(json!({ "error": format!("Agent '{}' no longer exists", agent_id) })),
+                ));
+            }
+            state.refresh_agents().await;
+            crate::cluster::publish(&state, crate::cluster::ClusterEvent::AgentsChanged);
+        }
+    }
+    let row = end(&state, id, "promoted").await?;
+    changed(&state).await;
+    crate::audit::log_audit(
+        &state.db,
+        "promote_prompt_canary",
+        json!({ "id": row.id, "target": row.target }),
+        None,
+    )
+    .await;
+    Ok(Json(canary_json(&state, row).await?))
+}
+
+/// `POST /api/admin/prompt-canaries/{id}/rollback` — drop the candidate
+#[utoipa::path(post, path = "/api/admin/prompt-canaries/{id}/rollback", tag = "system",
+    params(("id" = String, Path, description = "Canary UUID")),
+    responses(
+        (status = 200, description = "Canary ended; the live text is unchanged"),
+        (status = 404, description = "No such canary"),
+        (status = 409, description = "The canary already ended")
+    ))]
+pub async fn rollback_canary(
+    State(state): State<AppState>,
+    Path(id): Path<uuid::Uuid>,
+) -> Result<Json<Value>, ApiError> {
+    let row = end(&state, id, "rolled_back").await?;
+    changed(&state).await;
+    crate::audit::log_audit(
+        &state.db,
+        "rollback_prompt_canary",
+        json!({ "id": row.id, "target": row.target }),
+        None,
+    )
+    .await;
+    Ok(Json(canary_json(&state, row).await?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percent_sets_the_candidate_share() {
+        assert_eq!(variant_for(10, 0.05), Variant::Candidate);
+        assert_eq!(variant_for(10, 0.10), Variant::Control);
+        assert_eq!(variant_for(99, 0.985), Variant::Candidate);
+        assert_eq!(variant_for(1, 0.5), Variant::Control);
+    }
+
+    #[test]
+    fn arms_serialize_for_usage_events() {
+        let arm = Arm {
+            canary_id: uuid::Uuid::nil(),
+            variant: Variant::Candidate,
+        };
+        assert_eq!(
+            serde_json::to_value(&arm).unwrap(),
+            json!({ "canary_id": "00000000-0000-0000-0000-000000000000", "variant": "candidate" })
+        );
+    }
+}
@@ -0,0 +1,332 @@
+// ClaudeHydra v4 -- System prompt layering
+// The system prompt is assembled from up to four layers:
+//   - global  — built-in swarm prompt + custom instructions from settings,
+//   - project — the working directory and its `HYDRA.md` (if present),
+//   - agent   — identity of the agent picked with `agent_id`,
+//   - request — `system` messages sent by the caller.
+// `ch_settings.prompt_layering` decides the order (layers left out are not
+// used), the separator between layers and the total length limit. Later
+// layers take precedence: when the prompt is too long, the earliest layers
+// are cut from the end first.
+//
+// `POST /api/admin/prompt-preview` shows the prompt a chat request would get.
+
+use axum::Json;
+use axum::extract::State;
+use axum::http::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use utoipa::ToSchema;
+
+use crate::models::{ChatMessage, ChatRequest, WitcherAgent};
+use crate::state::AppState;
+
+/// Project prompt file looked up in the working directory.
+pub const PROJECT_PROMPT_FILE: &str = "HYDRA.md";
+/// Larger project files are cut (the layer still counts toward `max_chars`).
+const MAX_PROJECT_FILE_BYTES: u64 = 64 * 1024;
+const DEFAULT_MAX_CHARS: usize = 60_000;
+const MIN_MAX_CHARS: usize = 1_000;
+const MAX_MAX_CHARS: usize = 400_000;
+const MAX_SEPARATOR_CHARS: usize = 64;
+const TRUNCATION_MARKER: &str = "\n[… truncated]";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum PromptLayer {
+    Global,
+    Project,
+    Agent,
+    Request,
+}
+
+impl PromptLayer {
+    pub const ALL: [PromptLayer; 4] = [
+        PromptLayer::Global,
+        PromptLayer::Project,
+        PromptLayer::Agent,
+        PromptLayer::Request,
+    ];
+}
+
+/// Merge policy, stored in `ch_settings.prompt_layering` and edited through
+/// the `prompt_layering` field of `/api/settings`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(default)]
+pub struct PromptLayering {
+    /// Layers in prompt order; a layer left out is not used.
+    pub order: Vec<PromptLayer>,
+    /// Put between non-empty layers.
+    pub separator: String,
+    /// Upper bound for the assembled prompt, in characters.
+    pub max_chars: usize,
+}
+
+impl Default for PromptLayering {
+    fn default() -> Self {
+        Self {
+            order: PromptLayer::ALL.to_vec(),
+            separator: "\n\n".to_string(),
+            max_chars: DEFAULT_MAX_CHARS,
+        }
+    }
+}
+
+impl PromptLayering {
+    /// Drop duplicate layers and clamp the limits into their valid ranges.
+    pub fn normalized(mut self) -> Self {
+        let mut seen = Vec::new();
+        self.order.retain(|layer| {
+            let first = !seen.contains(layer);
+            seen.push(*layer);
+            first
+        });
+        if self.separator.chars().count() > MAX_SEPARATOR_CHARS {
+            self.separator = self.separator.chars().take(MAX_SEPARATOR_CHARS).collect();
+        }
+        self.max_chars = self.max_chars.clamp(MIN_MAX_CHARS, MAX_MAX_CHARS);
+        self
+    }
+
+    /// Parse the stored JSON; anything unreadable falls back to the default.
+    pub fn from_stored(value: Option<Value>) -> Self {
+        value
+            .and_then(|v| serde_json::from_value::<PromptLayering>(v).ok())
+            .unwrap_or_default()
+            .normalized()
+    }
+}
+
+/// Size of one layer in the assembled prompt.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct LayerReport {
+    pub layer: PromptLayer,
+    /// Characters of the layer before truncation.
+    pub chars: usize,
+    /// Characters cut to stay within `max_chars`.
+    pub truncated_chars: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AssembledPrompt {
+    pub prompt: String,
+    pub layers: Vec<LayerReport>,
+}
+
+/// Join the layer texts per policy. Empty layers are skipped (no separator).
+pub fn assemble(policy: &PromptLayering, layers: &[(PromptLayer, String)]) -> AssembledPrompt {
+    let mut parts: Vec<(PromptLayer, String, usize)> = policy
+        .order
+        .iter()
+        .filter_map(|layer| {
+            let text = layers.iter().find(|(l, _)| l == layer)?.1.trim();
+            (!text.is_empty()).then(|| (*layer, text.to_string(), text.chars().count()))
+        })
+        .collect();
+
+    let separators = policy.separator.chars().count() * parts.len().saturating_sub(1);
+    let total: usize = parts.iter().map(|(_, _, n)| n).sum::<usize>() + separators;
+    let mut excess = total.saturating_sub(policy.max_chars);
+    let mut truncated = vec![0usize; parts.len()];
+    let marker_len = TRUNCATION_MARKER.chars().count();
+
+    // Earliest layers give way first; a layer that would keep nothing useful
+    // beyond the marker is dropped entirely.
+    for (i, (_, text, chars)) in parts.iter_mut().enumerate() {
+        if excess == 0 {
+            break;
+        }
+        let keep = chars.saturating_sub(excess + marker_len);
+        if keep == 0 {
+            excess = excess.saturating_sub(*chars + policy.separator.chars().count());
+            truncated[i] = *chars;
+            text.clear();
+        } else {
+            let cut = *chars - keep;
+            excess = excess.saturating_sub(cut - marker_len);
+            truncated[i] = cut;
+            *text = text.chars().take(keep).collect::<String>() + TRUNCATION_MARKER;
+        }
+    }
+
+    let prompt = parts
+        .iter()
+        .filter(|(_, text, _)| !text.is_empty())
+        .map(|(_, text, _)| text.as_str())
+        .collect::<Vec<_>>()
+        .join(&policy.separator);
+    let layers = parts
+        .iter()
+        .zip(truncated)
+        .map(|((layer, _, chars), truncated_chars)| LayerReport {
+            layer: *layer,
+            chars: *chars,
+            truncated_chars,
+        })
+        .collect();
+    AssembledPrompt { prompt, layers }
+}
+
+/// Project layer: the working directory and its `HYDRA.md`.
+pub async fn project_layer(working_directory: &str) -> String {
+    if working_directory.is_empty() {
+        return String::new();
+    }
+    let mut lines = vec![
+        "## Working Directory".to_string(),
+        format!("**Current working directory**: `{}`", working_directory),
+        "You can use relative paths (e.g. `src/main.rs`) — they resolve against this directory.".to_string(),
+        "You do NOT need to specify absolute paths unless referencing files outside this folder.".to_string(),
+    ];
+    let path = std::path::Path::new(working_directory).join(PROJECT_PROMPT_FILE);
+    if let Ok(file) = tokio::fs::File::open(&path).await {
+        use tokio::io::AsyncReadExt;
+        let mut bytes = Vec::new();
+        if file.take(MAX_PROJECT_FILE_BYTES).read_to_end(&mut bytes).await.is_ok() {
+            let text = String::from_utf8_lossy(&bytes);
+            if !text.trim().is_empty() {
+                lines.extend([
+                    String::new(),
+                    format!("## Project Instructions ({})", PROJECT_PROMPT_FILE),
+                    text.trim().to_string(),
+                ]);
+            }
+        }
+    }
+    lines.join("\n")
+}
+
+/// Agent layer: who the reply is generated as.
+pub fn agent_layer(agent: &WitcherAgent) -> String {
+    let mut layer = format!(
+        "## Agent\nYou are answering as **{}** ({}, {} tier). {}",
+        agent.name, agent.role, agent.tier, agent.description
+    );
+    if !agent.system_prompt.trim().is_empty() {
+        layer.push_str("\n\n");
+        layer.push_str(agent.system_prompt.trim());
+    }
+    layer
+}
+
+/// Request layer: the caller's `system` messages, in order.
+pub fn request_layer(messages: &[ChatMessage]) -> String {
+    messages
+        .iter()
+        .filter(|m| m.role == "system" && !m.content.trim().is_empty())
+        .map(|m| m.content.trim())
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Request body for `POST /api/admin/prompt-preview`.
+#[derive(Debug, Clone, Default, Deserialize, ToSchema)]
+#[serde(default)]
+pub struct PromptPreviewRequest {
+    pub session_id: Option<String>,
+    pub agent_id: Option<String>,
+    pub model: Option<String>,
+    /// Only `system` messages matter (request layer); the last user message
+    /// feeds automatic model selection when `model` is not given.
+    pub messages: Vec<ChatMessage>,
+}
+
+/// `POST /api/admin/prompt-preview` — the system prompt a chat request with
+/// this session / agent / messages would be sent, with per-layer sizes.
+#[utoipa::path(post, path = "/api/admin/prompt-preview", tag = "system",
+    request_body = PromptPreviewRequest,
+    responses(
+        (status = 200, description = "Assembled system prompt and layer sizes"),
+        (status = 404, description = "Unknown agent_id")
+    ))]
+pub async fn preview_prompt(
+    State(state): State<AppState>,
+    Json(body): Json<PromptPreviewRequest>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    if let Some(id) = body.agent_id.as_deref().filter(|id| !id.is_empty())
+        && !state.agents.read().await.iter().any(|a| a.id == id)
+    {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": format!("Agent '{}' not found", id) })),
+        ));
+    }
+    let req = ChatRequest {
+        messages: body.messages,
+        model: body.model,
+        temperature: None,
+        max_tokens: None,
+        stream: None,
+        tools_enabled: None,
+        session_id: body.session_id,
+        agent_id: body.agent_id,
+        stop_sequences: None,
+        priority: None,
+    };
+    let ctx = crate::handlers::prompt::resolve_chat_context(&state, &req).await;
+    Ok(Json(json!({
+        "model": ctx.model,
+        "policy": ctx.prompt_layering,
+        "layers": ctx.prompt_layers,
+        "chars": ctx.system_prompt.chars().count(),
+        "prompt": ctx.system_prompt,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layers() -> Vec<(PromptLayer, String)> {
+        vec![
+            (PromptLayer::Global, "global".to_string()),
+            (PromptLayer::Project, String::new()),
+            (PromptLayer::Agent, "agent".to_string()),
+            (PromptLayer::Request, "request".to_string()),
+        ]
+    }
+
+    #[test]
+    fn layers_follow_the_configured_order_and_separator() {
+        let policy = PromptLayering {
+            order: vec![PromptLayer::Request, PromptLayer::Global, PromptLayer::Project],
+            separator: "\n---\n".to_string(),
+            ..Default::default()
+        };
+        let out = assemble(&policy, &layers());
+        assert_eq!(out.prompt, "request\n---\nglobal");
+        let used: Vec<PromptLayer> = out.layers.iter().map(|l| l.layer).collect();
+        assert_eq!(used, vec![PromptLayer::Request, PromptLayer::Global]);
+    }
+
+    #[test]
+    fn earliest_layers_are_cut_first() {
+        let policy = PromptLayering {
+            order: PromptLayer::ALL.to_vec(),
+            separator: "\n".to_string(),
+            max_chars: 40,
+        };
+        let long = vec![
+            (PromptLayer::Global, "g".repeat(50)),
+            (PromptLayer::Request, "keep me".to_string()),
+        ];
+        let out = assemble(&policy, &long);
+        assert!(out.prompt.chars().count() <= 40, "{}", out.prompt);
+        assert!(out.prompt.ends_with("\nkeep me"));
+        assert!(out.prompt.contains("[… truncated]"));
+        assert!(out.layers[0].truncated_chars > 0);
+        assert_eq!(out.layers[1].truncated_chars, 0);
+    }
+
+    #[test]
+    fn stored_policy_is_normalized() {
+        let policy = PromptLayering::from_stored(Some(json!({
+            "order": ["agent", "agent", "global"],
+            "max_chars": 5
+        })));
+        assert_eq!(policy.order, vec![PromptLayer::Agent, PromptLayer::Global]);
+        assert_eq!(policy.separator, "\n\n");
+        assert_eq!(policy.max_chars, MIN_MAX_CHARS);
+        assert_eq!(PromptLayering::from_stored(Some(json!("junk"))), PromptLayering::default());
+    }
+}
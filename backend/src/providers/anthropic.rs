@@ -0,0 +1,307 @@
+//! Anthropic Messages API provider.
+//!
+//! Requests go through `handlers::send_to_anthropic` (credential resolution,
+//! Vault delegation, retries with backoff (see `retry`), request-scope stop
+//! sequences); streaming uses the shared `anthropic_streaming` NDJSON handler
+//! and records a transcript.
+//! Image attachments are sent as `image` blocks ahead of the message text.
+
+use axum::Json;
+use axum::http::StatusCode;
+use axum::response::Response;
+use base64::Engine as _;
+use jaskier_core::handlers::anthropic_streaming::{self, AnthropicChatContext};
+use serde_json::{Value, json};
+
+use crate::handlers::{prepare_assistant_prefill, sanitize_json_strings, send_to_anthropic};
+use crate::models::{ChatMessage, ImageSource, UsageInfo};
+use crate::state::AppState;
+
+use super::retry::RetryAttempt;
+use super::{Completion, Provider, ProviderError, ProviderRequest};
+
+/// API default when the caller doesn't set one.
+const DEFAULT_TEMPERATURE: f64 = 1.0;
+/// Anthropic's per-image size limit.
+const MAX_IMAGE_BYTES: usize = 5 * 1024 * 1024;
+const IMAGE_MEDIA_TYPES: &[&str] = &["image/jpeg", "image/png", "image/gif", "image/webp"];
+
+/// Validate image attachments and inline uploads as base64.
+pub(crate) async fn prepare_attachments(state: &AppState, messages: &mut [ChatMessage]) -> Result<(), ProviderError> {
+    let invalid = |msg: String| (StatusCode::BAD_REQUEST, Json(json!({ "error": msg })));
+    for source in messages.iter_mut().flat_map(|m| m.attachments.iter_mut()) {
+        match source {
+            ImageSource::Base64 { media_type, .. } => {
+                if !IMAGE_MEDIA_TYPES.contains(&media_type.as_str()) {
+                    return Err(invalid(format!("Unsupported image type '{}'", media_type)));
+                }
+            }
+            ImageSource::Url { url } => {
+                if !(url.starts_with("https://") || url.starts_with("http://")) {
+                    return Err(invalid("Image URLs must be http(s)".into()));
+                }
+            }
+            ImageSource::Upload { upload_id } => {
+                let (mime, bytes) =
+                    crate::handlers::uploads::read_upload(state, *upload_id, MAX_IMAGE_BYTES).await?;
+                let media_type = mime.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+                if !IMAGE_MEDIA_TYPES.contains(&media_type.as_str()) {
+                    return Err(invalid(format!("Upload {} is not an image ({})", upload_id, mime)));
+                }
+                *source = ImageSource::Base64 {
+                    media_type,
+                    data: base64::engine::general_purpose::STANDARD.encode(&bytes),
+                };
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Anthropic `content` for a message: the plain text, or image blocks
+/// followed by the text when there are attachments.
+pub(crate) fn message_content(msg: &ChatMessage) -> Value {
+    if msg.attachments.is_empty() {
+        return json!(msg.content);
+    }
+    let mut blocks: Vec<Value> = msg
+        .attachments
+        .iter()
+        .map(|source| json!({ "type": "image", "source": source }))
+        .collect();
+    if !msg.content.is_empty() {
+        blocks.push(json!({ "type": "text", "text": msg.content }));
+    }
+    json!(blocks)
+}
+
+pub struct Anthropic;
+
+/// Reply body and the transient errors retried to get it.
+async fn send_chat(state: &AppState, body: &Value) -> Result<(Value, Vec<RetryAttempt>), ProviderError> {
+    let resp = send_to_anthropic(state, body, crate::endpoint_policies::Group::Chat).await?;
+    if !resp.status().is_success() {
+        return Err(super::upstream_error("anthropic chat", resp).await);
+    }
+    let retries = super::retry::of_response(&resp);
+    Ok((super::response_json("anthropic chat", resp).await?, retries))
+}
+
+/// Joined text blocks and token usage of a Messages API reply.
+fn reply_parts(resp_body: &Value) -> (String, Option<UsageInfo>) {
+    let content = resp_body
+        .get("content")
+        .and_then(|c| c.as_array())
+        .map(|blocks| {
+            blocks
+                .iter()
+                .filter_map(|b| b.get("text").and_then(|t| t.as_str()))
+                .collect::<Vec<&str>>()
+                .join("")
+        })
+        .unwrap_or_default();
+    let usage = resp_body.get("usage").map(|u| {
+        super::usage(
+            u.get("input_tokens").and_then(|v| v.as_u64()),
+            u.get("output_tokens").and_then(|v| v.as_u64()),
+            None,
+        )
+    });
+    (content, usage)
+}
+
+fn add_usage(a: Option<UsageInfo>, b: Option<UsageInfo>) -> Option<UsageInfo> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(super::usage(
+            Some(u64::from(a.prompt_tokens) + u64::from(b.prompt_tokens)),
+            Some(u64::from(a.completion_tokens) + u64::from(b.completion_tokens)),
+            None,
+        )),
+        (a, b) => a.or(b),
+    }
+}
+
+impl Provider for Anthropic {
+    fn name(&self) -> &'static str {
+        "anthropic"
+    }
+
+    async fn chat(&self, state: &AppState, req: &ProviderRequest) -> Result<Completion, ProviderError> {
+        let mut source = req.messages.clone();
+        prepare_attachments(state, &mut source).await?;
+        let mut messages: Vec<Value> = source
+            .iter()
+            .map(|m| json!({ "role": m.role, "content": message_content(m) }))
+            .collect();
+        // A trailing assistant message is a prefill — `content` below is the continuation only.
+        prepare_assistant_prefill(&mut messages)?;
+
+        let mut body = json!({
+            "model": req.model,
+            "max_tokens": req.max_tokens,
+            "messages": messages,
+        });
+        if !req.system_prompt.is_empty() {
+            body["system"] = json!(req.system_prompt);
+        }
+        if let Some(temp) = req.temperature {
+            body["temperature"] = json!(temp);
+        }
+        sanitize_json_strings(&mut body);
+
+        let (resp_body, mut retries) = send_chat(state, &body).await?;
+        let (mut content, mut usage) = reply_parts(&resp_body);
+        let mut stop_reason = resp_body["stop_reason"].as_str().map(str::to_string);
+        let mut refusal = crate::refusals::detect(stop_reason.as_deref(), &content);
+
+        // Reformulation retry: only for replies that read like a refusal —
+        // a provider safety stop is final.
+        if let Some(ref mut r) = refusal
+            && r.signal == crate::refusals::RefusalSignal::Heuristic
+            && crate::refusals::retry_enabled(&state.db).await
+        {
+            let system = body["system"].as_str().unwrap_or("").to_string();
+            body["system"] = json!(format!("{}\n\n{}", system, crate::refusals::RETRY_SYSTEM_NOTE).trim_start());
+            r.retried = true;
+            crate::request_scope::decide("refusal_retry", json!({ "signal": "heuristic" }));
+            match send_chat(state, &body).await {
+                Ok((retry_body, more_retries)) => {
+                    retries.extend(more_retries);
+                    let (retry_content, retry_usage) = reply_parts(&retry_body);
+                    usage = add_usage(usage, retry_usage);
+                    let retry_stop = retry_body["stop_reason"].as_str();
+                    if crate::refusals::detect(retry_stop, &retry_content).is_none() {
+                        r.resolved = true;
+                        content = retry_content;
+                        stop_reason = retry_stop.map(str::to_string);
+                    }
+                }
+                Err((status, _)) => tracing::warn!("anthropic chat: refusal retry failed ({})", status),
+            }
+        }
+
+        // max_tokens continuation: re-request with the reply so far as an
+        // assistant prefill and stitch the pieces, up to `max_continuations` times.
+        if stop_reason.as_deref() == Some("max_tokens") {
+            let limit = crate::handlers::max_continuations(&state.db).await;
+            let mut messages = body["messages"].as_array().cloned().unwrap_or_default();
+            let mut piece = content.clone();
+            let mut continuations = 0;
+            while stop_reason.as_deref() == Some("max_tokens") && continuations < limit && !piece.trim().is_empty() {
+                crate::handlers::continue_from(&mut messages, &piece);
+                crate::request_scope::decide(
+                    "continuation",
+                    json!({ "attempt": continuations + 1, "limit": limit, "reason": "max_tokens" }),
+                );
+                body["messages"] = json!(messages);
+                match send_chat(state, &body).await {
+                    Ok((next_body, more_retries)) => {
+                        retries.extend(more_retries);
+                        let (next, next_usage) = reply_parts(&next_body);
+                        usage = add_usage(usage, next_usage);
+                        stop_reason = next_body["stop_reason"].as_str().map(str::to_string);
+                        crate::handlers::stitch_continuation(&mut content, &next);
+                        piece = next;
+                        continuations += 1;
+                    }
+                    Err((status, _)) => {
+                        tracing::warn!("anthropic chat: max_tokens continuation failed ({})", status);
+                        break;
+                    }
+                }
+            }
+            if continuations > 0 {
+                tracing::info!(
+                    "anthropic chat: continued {} time(s) after max_tokens (final stop_reason: {:?})",
+                    continuations,
+                    stop_reason
+                );
+            }
+        }
+
+        Ok(Completion {
+            id: resp_body
+                .get("id")
+                .and_then(|v| v.as_str())
+                .unwrap_or("unknown")
+                .to_string(),
+            model: resp_body
+                .get("model")
+                .and_then(|m| m.as_str())
+                .unwrap_or(&req.model)
+                .to_string(),
+            content,
+            usage,
+            refusal,
+            retries,
+            warnings: crate::request_scope::warnings(),
+            prompt_variants: crate::prompt_canary::current(),
+        })
+    }
+
+    async fn chat_stream(&self, state: &AppState, mut req: ProviderRequest) -> Result<Response, ProviderError> {
+        prepare_attachments(state, &mut req.messages).await?;
+        let prompt_len = req.messages.iter().map(|m| m.content.len()).sum::<usize>();
+        let mut messages = crate::handlers::streaming::filter_client_system_prompt(&req.messages);
+        // The model continues the prefill; only the continuation is streamed back.
+        prepare_assistant_prefill(&mut messages)?;
+
+        let ctx = AnthropicChatContext {
+            model: req.model,
+            max_tokens: req.max_tokens,
+            temperature: req.temperature.unwrap_or(DEFAULT_TEMPERATURE),
+            // No tool loop on this path
+            max_iterations: 1,
+            working_directory: req.working_directory,
+            session_id: req.session_id,
+            system_prompt: req.system_prompt,
+        };
+
+        let transcript = crate::transcripts::start(state, "stream", &ctx, &messages, false).await;
+        if let Some(ref t) = transcript {
+            crate::transcripts::attach(t);
+        }
+
+        let response =
+            anthropic_streaming::anthropic_ndjson_stream_no_tools(state, &ctx, messages, prompt_len).await?;
+        Ok(crate::transcripts::tag_response(response, transcript.as_ref()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn content_blocks_split_into_text_and_attachments() {
+        let msg: ChatMessage = serde_json::from_value(json!({
+            "role": "user",
+            "content": [
+                { "type": "text", "text": "What is this?" },
+                { "type": "image", "source": { "type": "url", "url": "https://example.com/a.png" } },
+                { "type": "text", "text": "Be brief." }
+            ]
+        }))
+        .unwrap();
+        assert_eq!(msg.content, "What is this?\n\nBe brief.");
+        assert_eq!(msg.attachments, vec![ImageSource::Url { url: "https://example.com/a.png".into() }]);
+    }
+
+    #[test]
+    fn message_content_puts_images_before_text() {
+        let plain: ChatMessage = serde_json::from_value(json!({ "role": "user", "content": "hi" })).unwrap();
+        assert_eq!(message_content(&plain), json!("hi"));
+
+        let msg: ChatMessage = serde_json::from_value(json!({
+            "role": "user",
+            "content": "describe",
+            "attachments": [{ "type": "base64", "media_type": "image/png", "data": "iVBOR" }]
+        }))
+        .unwrap();
+        let content = message_content(&msg);
+        assert_eq!(content[0]["type"], "image");
+        assert_eq!(content[0]["source"]["type"], "base64");
+        assert_eq!(content[0]["source"]["media_type"], "image/png");
+        assert_eq!(content[1], json!({ "type": "text", "text": "describe" }));
+    }
+}
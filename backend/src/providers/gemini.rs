@@ -0,0 +1,316 @@
+//! Google Gemini provider (Generative Language API, `v1beta`).
+//!
+//! Chat messages map to `contents` (`assistant` → `model`), `system` messages
+//! are folded into the system instruction, request-scope stop sequences go to
+//! `generationConfig.stopSequences`. Streaming reads `streamGenerateContent`
+//! SSE and re-emits NDJSON token frames. `embed` wraps `batchEmbedContents`
+//! for retrieval (document Q&A).
+
+use axum::Json;
+use axum::body::Body;
+use axum::http::StatusCode;
+use axum::response::Response;
+use jaskier_core::handlers::anthropic_streaming::build_ndjson_response;
+use serde_json::{Value, json};
+
+use crate::endpoint_policies::Group;
+use crate::models::ChatMessage;
+use crate::state::AppState;
+
+use super::{Completion, Provider, ProviderError, ProviderRequest};
+
+const API_BASE: &str = "https://generativelanguage.googleapis.com/v1beta/models";
+const DEFAULT_TEMPERATURE: f64 = 1.0;
+/// Gemini accepts at most this many stop sequences.
+const MAX_STOP_SEQUENCES: usize = 5;
+/// `batchEmbedContents` accepts at most this many texts per call.
+const EMBED_BATCH: usize = 100;
+const DEFAULT_EMBEDDING_MODEL: &str = "gemini-embedding-001";
+
+pub struct Gemini;
+
+/// Translate chat messages into Gemini `contents`. `system` messages are
+/// folded into the system instruction.
+pub(crate) fn gemini_contents(messages: &[ChatMessage]) -> (Vec<Value>, Vec<&str>) {
+    let mut system = Vec::new();
+    let contents = messages
+        .iter()
+        .filter_map(|m| {
+            let role = match m.role.as_str() {
+                "system" => {
+                    system.push(m.content.as_str());
+                    return None;
+                }
+                "assistant" => "model",
+                _ => "user",
+            };
+            Some(json!({ "role": role, "parts": [{ "text": m.content }] }))
+        })
+        .collect();
+    (contents, system)
+}
+
+/// Build a `generateContent` body.
+pub(crate) fn gemini_body(req: &ProviderRequest) -> Value {
+    let (contents, extra_system) = gemini_contents(&req.messages);
+    let mut system: Vec<&str> = Vec::new();
+    if !req.system_prompt.is_empty() {
+        system.push(&req.system_prompt);
+    }
+    system.extend(extra_system);
+
+    let mut body = json!({
+        "contents": contents,
+        "generationConfig": {
+            "temperature": req.temperature.unwrap_or(DEFAULT_TEMPERATURE),
+            "maxOutputTokens": req.max_tokens,
+        }
+    });
+    if !system.is_empty() {
+        body["systemInstruction"] = json!({ "parts": [{ "text": system.join("\n\n") }] });
+    }
+    // Agent / caller stop sequences
+    if let Some(scope) = crate::request_scope::current()
+        && !scope.stop_sequences.is_empty()
+    {
+        let stops: Vec<&String> = scope.stop_sequences.iter().take(MAX_STOP_SEQUENCES).collect();
+        body["generationConfig"]["stopSequences"] = json!(stops);
+    }
+    body
+}
+
+/// One call with the timeout of the `group` policy (Gemini calls are not retried).
+async fn send(
+    state: &AppState,
+    url: &str,
+    body: &Value,
+    group: Group,
+) -> Result<reqwest::Response, ProviderError> {
+    let (api_key, is_oauth) = crate::key_environments::google_credential(state)
+        .await
+        .ok_or_else(|| {
+            (
+                StatusCode::UNAUTHORIZED,
+                Json(json!({ "error": "No Google API credential configured" })),
+            )
+        })?;
+    crate::circuit::admit(crate::circuit::GOOGLE)?;
+    let request = async {
+        jaskier_oauth::google::apply_google_auth(crate::http_client::client(state).post(url), &api_key, is_oauth)
+            .json(body)
+            .timeout(crate::endpoint_policies::get(group).timeout)
+            .send()
+            .await
+            .map_err(|e| super::request_failed("gemini", e))
+    };
+    // Test builds can inject upstream faults here (see `chaos`).
+    #[cfg(feature = "chaos")]
+    let sent = crate::chaos::around(crate::circuit::GOOGLE, request).await;
+    #[cfg(not(feature = "chaos"))]
+    let sent = request.await;
+    let resp = match sent {
+        Ok(resp) => resp,
+        Err(e) => {
+            crate::circuit::record_failure(state, crate::circuit::GOOGLE, "request failed").await;
+            return Err(e);
+        }
+    };
+    let status = resp.status().as_u16();
+    if crate::handlers::is_retryable_status(status) {
+        crate::circuit::record_failure(state, crate::circuit::GOOGLE, format!("HTTP {}", status)).await;
+    } else {
+        crate::circuit::record_success(state, crate::circuit::GOOGLE).await;
+    }
+    if !resp.status().is_success() {
+        return Err(super::upstream_error("gemini", resp).await);
+    }
+    Ok(resp)
+}
+
+/// Embedding model (`CH_EMBEDDING_MODEL`, default `gemini-embedding-001`).
+pub(crate) fn embedding_model() -> String {
+    std::env::var("CH_EMBEDDING_MODEL")
+        .ok()
+        .filter(|v| !v.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_EMBEDDING_MODEL.to_string())
+}
+
+/// Embed texts with `task_type` (`RETRIEVAL_DOCUMENT` / `RETRIEVAL_QUERY`),
+/// one vector per text, in order.
+pub(crate) async fn embed(
+    state: &AppState,
+    texts: &[String],
+    task_type: &str,
+) -> Result<Vec<Vec<f32>>, ProviderError> {
+    let model = embedding_model();
+    let url = format!("{}/{}:batchEmbedContents", API_BASE, model);
+    let mut vectors = Vec::with_capacity(texts.len());
+    for batch in texts.chunks(EMBED_BATCH) {
+        let requests: Vec<Value> = batch
+            .iter()
+            .map(|text| {
+                json!({
+                    "model": format!("models/{}", model),
+                    "content": { "parts": [{ "text": text }] },
+                    "taskType": task_type,
+                })
+            })
+            .collect();
+        let resp = send(state, &url, &json!({ "requests": requests }), Group::Batch).await?;
+        let body = super::response_json("gemini", resp).await?;
+        let embeddings = body["embeddings"].as_array().map(Vec::as_slice).unwrap_or(&[]);
+        if embeddings.len() != batch.len() {
+            return Err(super::request_failed("gemini", "embedding count mismatch"));
+        }
+        vectors.extend(embeddings.iter().map(|e| {
+            e["values"]
+                .as_array()
+                .map(|v| v.iter().filter_map(|x| x.as_f64()).map(|x| x as f32).collect())
+                .unwrap_or_default()
+        }));
+    }
+    Ok(vectors)
+}
+
+impl Provider for Gemini {
+    fn name(&self) -> &'static str {
+        "google"
+    }
+
+    async fn chat(&self, state: &AppState, req: &ProviderRequest) -> Result<Completion, ProviderError> {
+        let url = format!("{}/{}:generateContent", API_BASE, req.model);
+        let resp = send(state, &url, &gemini_body(req), Group::Chat).await?;
+        let resp_body = super::response_json("gemini", resp).await?;
+
+        let content = resp_body
+            .pointer("/candidates/0/content/parts")
+            .and_then(|p| p.as_array())
+            .map(|parts| {
+                parts
+                    .iter()
+                    .filter_map(|p| p.get("text").and_then(|t| t.as_str()))
+                    .collect::<Vec<&str>>()
+                    .join("")
+            })
+            .unwrap_or_default();
+        let usage = resp_body.get("usageMetadata").map(|u| {
+            super::usage(
+                u.get("promptTokenCount").and_then(|v| v.as_u64()),
+                u.get("candidatesTokenCount").and_then(|v| v.as_u64()),
+                u.get("totalTokenCount").and_then(|v| v.as_u64()),
+            )
+        });
+        let finish_reason = resp_body
+            .pointer("/candidates/0/finishReason")
+            .or_else(|| resp_body.pointer("/promptFeedback/blockReason"))
+            .and_then(|r| r.as_str());
+        let refusal = crate::refusals::detect(finish_reason, &content);
+
+        Ok(Completion {
+            id: resp_body
+                .get("responseId")
+                .and_then(|v| v.as_str())
+                .unwrap_or("unknown")
+                .to_string(),
+            model: resp_body
+                .get("modelVersion")
+                .and_then(|m| m.as_str())
+                .unwrap_or(&req.model)
+                .to_string(),
+            content,
+            usage,
+            refusal,
+            retries: Vec::new(),
+            warnings: crate::request_scope::warnings(),
+            prompt_variants: crate::prompt_canary::current(),
+        })
+    }
+
+    async fn chat_stream(&self, state: &AppState, req: ProviderRequest) -> Result<Response, ProviderError> {
+        let url = format!("{}/{}:streamGenerateContent?alt=sse", API_BASE, req.model);
+        let resp = send(state, &url, &gemini_body(&req), Group::Stream).await?;
+
+        let model_for_done = req.model;
+        let byte_stream = resp.bytes_stream();
+
+        let ndjson_stream = async_stream::stream! {
+            let mut sse_buffer = String::new();
+            let mut total_tokens: u32 = 0;
+            let mut stream = byte_stream;
+
+            while let Some(chunk_result) = futures_util::StreamExt::next(&mut stream).await {
+                let chunk = match chunk_result {
+                    Ok(b) => b,
+                    Err(e) => {
+                        tracing::error!("Google SSE stream error: {}", e);
+                        let err_line = serde_json::to_string(&json!({ "token": "\n[Stream interrupted]", "done": true, "model": &model_for_done })).unwrap_or_default();
+                        yield Ok::<_, std::io::Error>(axum::body::Bytes::from(format!("{}\n", err_line)));
+                        break;
+                    }
+                };
+                sse_buffer.push_str(&String::from_utf8_lossy(&chunk));
+
+                while let Some(nl) = sse_buffer.find('\n') {
+                    // Safety: '\n' is ASCII — find() returns byte pos at char boundary
+                    let line = sse_buffer[..nl].trim().to_string();
+                    sse_buffer = sse_buffer[nl + 1..].to_string();
+                    if line.is_empty() || line.starts_with(':') { continue; }
+                    if let Some(data) = line.strip_prefix("data: ")
+                        && let Ok(event) = serde_json::from_str::<Value>(data) {
+                            if let Some(text) = event.pointer("/candidates/0/content/parts/0/text").and_then(|t| t.as_str())
+                                && !text.is_empty() {
+                                    let ndjson_line = serde_json::to_string(&json!({ "token": text, "done": false })).unwrap_or_default();
+                                    yield Ok::<_, std::io::Error>(axum::body::Bytes::from(format!("{}\n", ndjson_line)));
+                                }
+                            if let Some(usage) = event.get("usageMetadata") {
+                                total_tokens = usage.get("totalTokenCount").and_then(|v| v.as_u64()).unwrap_or(0) as u32;
+                            }
+                        }
+                }
+            }
+            let done_line = serde_json::to_string(&json!({ "token": "", "done": true, "model": &model_for_done, "total_tokens": total_tokens })).unwrap_or_default();
+            yield Ok::<_, std::io::Error>(axum::body::Bytes::from(format!("{}\n", done_line)));
+        };
+
+        Ok(build_ndjson_response(Body::from_stream(ndjson_stream)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(role: &str, content: &str) -> ChatMessage {
+        ChatMessage {
+            role: role.into(),
+            content: content.into(),
+            attachments: Vec::new(),
+            model: None,
+            timestamp: None,
+        }
+    }
+
+    #[test]
+    fn body_maps_roles_and_folds_system_messages() {
+        let req = ProviderRequest {
+            model: "gemini-2.5-flash".into(),
+            messages: vec![
+                message("system", "Answer in Polish."),
+                message("user", "hi"),
+                message("assistant", "cześć"),
+            ],
+            system_prompt: "You are Geralt.".into(),
+            max_tokens: 256,
+            ..Default::default()
+        };
+        let body = gemini_body(&req);
+        assert_eq!(body["contents"].as_array().unwrap().len(), 2);
+        assert_eq!(body["contents"][1]["role"], "model");
+        assert_eq!(
+            body["systemInstruction"]["parts"][0]["text"],
+            "You are Geralt.\n\nAnswer in Polish."
+        );
+        assert_eq!(body["generationConfig"]["maxOutputTokens"], 256);
+        assert_eq!(body["generationConfig"]["temperature"], DEFAULT_TEMPERATURE);
+    }
+}
@@ -0,0 +1,213 @@
+// ClaudeHydra v4 -- Model providers
+// One `Provider` per upstream API. An implementation owns everything that is
+// specific to its API: URLs, auth headers, request and response shapes, SSE
+// parsing and upstream error mapping. Chat handlers build a `ProviderRequest`,
+// hand it to the provider serving the model and turn the `Completion` (or the
+// NDJSON response) into their own output, so a new provider is one module here
+// instead of another copy of the chat handlers.
+//
+// - `anthropic` — Messages API (direct, OAuth or Vault-delegated, see `handlers::send_to_anthropic`)
+// - `gemini` — Google Generative Language API (`gemini-*` models)
+
+pub mod anthropic;
+pub mod gemini;
+pub mod retry;
+
+pub use anthropic::Anthropic;
+pub use gemini::Gemini;
+
+use axum::Json;
+use axum::http::StatusCode;
+use axum::response::Response;
+use jaskier_core::handlers::anthropic_streaming::sanitize_api_error;
+use serde_json::{Value, json};
+
+use crate::handlers::prompt::ChatContext;
+use crate::models::{ChatMessage, ChatResponse, UsageInfo};
+use crate::state::AppState;
+
+pub type ProviderError = (StatusCode, Json<Value>);
+
+/// Provider-neutral chat request.
+#[derive(Debug, Clone, Default)]
+pub struct ProviderRequest {
+    pub model: String,
+    pub messages: Vec<ChatMessage>,
+    /// Resolved system prompt; empty = none.
+    pub system_prompt: String,
+    pub temperature: Option<f64>,
+    pub max_tokens: u32,
+    /// Session the streamed reply is persisted to.
+    pub session_id: Option<uuid::Uuid>,
+    pub working_directory: String,
+}
+
+impl ProviderRequest {
+    /// Request for a resolved chat context (streaming endpoints).
+    /// Caller `system` messages are dropped: `ctx.system_prompt` already
+    /// holds them as its request layer.
+    pub(crate) fn from_context(ctx: ChatContext, mut messages: Vec<ChatMessage>) -> Self {
+        messages.retain(|m| m.role != "system");
+        Self {
+            model: ctx.model,
+            messages,
+            system_prompt: ctx.system_prompt,
+            temperature: Some(ctx.temperature),
+            max_tokens: ctx.max_tokens,
+            session_id: ctx.session_id,
+            working_directory: ctx.working_directory,
+        }
+    }
+}
+
+/// A finished, non-streaming completion.
+#[derive(Debug, Clone)]
+pub struct Completion {
+    pub id: String,
+    /// Model that answered (as reported upstream).
+    pub model: String,
+    pub content: String,
+    pub usage: Option<UsageInfo>,
+    /// Set when the reply (or the one it replaced after a retry) was a refusal.
+    pub refusal: Option<crate::refusals::Refusal>,
+    /// Transient upstream errors retried on the way to this reply.
+    pub retries: Vec<retry::RetryAttempt>,
+    /// Soft limits crossed by the request (see soft_limits).
+    pub warnings: Vec<Value>,
+    /// Prompt canary variants the request was served with.
+    pub prompt_variants: Vec<crate::prompt_canary::Arm>,
+}
+
+impl Completion {
+    /// Record usage (and any refusal) and build the `ChatResponse` body.
+    pub fn into_response(self, state: &AppState, source: &'static str) -> ChatResponse {
+        self.into_attributed_response(state, source, None, None)
+    }
+
+    /// `into_response`, with usage attributed to a session and agent.
+    pub fn into_attributed_response(
+        self,
+        state: &AppState,
+        source: &'static str,
+        session_id: Option<uuid::Uuid>,
+        agent_id: Option<String>,
+    ) -> ChatResponse {
+        if let Some(ref refusal) = self.refusal {
+            crate::refusals::record(
+                &state.db,
+                refusal,
+                crate::refusals::RefusalContext {
+                    model: self.model.clone(),
+                    source,
+                    ..Default::default()
+                },
+            );
+        }
+        if let Some(ref u) = self.usage {
+            crate::usage::record_usage(
+                &state.db,
+                crate::usage::UsageEvent {
+                    model: self.model.clone(),
+                    input_tokens: u.prompt_tokens,
+                    output_tokens: u.completion_tokens,
+                    source,
+                    session_id,
+                    agent_id,
+                    prompt_variants: self.prompt_variants.clone(),
+                    refused: self.refusal.as_ref().is_some_and(|r| !r.resolved),
+                    ..Default::default()
+                },
+            );
+        }
+        ChatResponse {
+            id: self.id,
+            message: ChatMessage {
+                role: "assistant".to_string(),
+                content: self.content,
+                attachments: Vec::new(),
+                model: Some(self.model.clone()),
+                timestamp: Some(chrono::Utc::now().to_rfc3339()),
+            },
+            model: self.model,
+            usage: self.usage,
+            refusal: self
+                .refusal
+                .filter(|r| !r.resolved)
+                .map(|r| r.signal.as_str().to_string()),
+            retries: (!self.retries.is_empty()).then_some(self.retries),
+            warnings: self.warnings,
+        }
+    }
+}
+
+pub trait Provider: Send + Sync {
+    /// Provider id as used in model metadata (`anthropic`, `google`).
+    fn name(&self) -> &'static str;
+
+    /// Non-streaming completion.
+    fn chat(
+        &self,
+        state: &AppState,
+        req: &ProviderRequest,
+    ) -> impl Future<Output = Result<Completion, ProviderError>> + Send;
+
+    /// NDJSON stream (`{"token", "done"}` frames, final frame with `model` and
+    /// `total_tokens`), ready to be detached through `stream_relay`.
+    fn chat_stream(
+        &self,
+        state: &AppState,
+        req: ProviderRequest,
+    ) -> impl Future<Output = Result<Response, ProviderError>> + Send;
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  Shared upstream error mapping
+// ═══════════════════════════════════════════════════════════════════════
+
+/// Transport failure (connect, timeout) talking to a provider.
+pub(crate) fn request_failed(provider: &str, e: impl std::fmt::Display) -> ProviderError {
+    tracing::error!("{}: request failed: {}", provider, e);
+    (
+        StatusCode::BAD_GATEWAY,
+        Json(json!({ "error": "AI provider request failed" })),
+    )
+}
+
+/// Non-2xx answer: keep the upstream status, pass on a sanitized message
+/// (and the retries made before giving up).
+pub(crate) async fn upstream_error(provider: &str, resp: reqwest::Response) -> ProviderError {
+    let status = resp.status();
+    let retries = retry::of_response(&resp);
+    let body = resp.text().await.unwrap_or_default();
+    tracing::error!("{}: status={}, body={}", provider, status, body);
+    let mut error = json!({ "error": sanitize_api_error(&body) });
+    if !retries.is_empty() {
+        error["retries"] = json!(retries);
+    }
+    (
+        StatusCode::from_u16(status.as_u16()).unwrap_or(StatusCode::BAD_GATEWAY),
+        Json(error),
+    )
+}
+
+/// Parse a successful JSON body.
+pub(crate) async fn response_json(provider: &str, resp: reqwest::Response) -> Result<Value, ProviderError> {
+    resp.json().await.map_err(|e| {
+        tracing::error!("{}: invalid JSON response: {}", provider, e);
+        (
+            StatusCode::BAD_GATEWAY,
+            Json(json!({ "error": "AI provider returned invalid response" })),
+        )
+    })
+}
+
+/// Usage from prompt / completion counts (total computed when not reported).
+pub(crate) fn usage(prompt: Option<u64>, completion: Option<u64>, total: Option<u64>) -> UsageInfo {
+    let prompt = prompt.unwrap_or(0) as u32;
+    let completion = completion.unwrap_or(0) as u32;
+    UsageInfo {
+        prompt_tokens: prompt,
+        completion_tokens: completion,
+        total_tokens: total.map(|t| t as u32).unwrap_or(prompt + completion),
+    }
+}
@@ -0,0 +1,134 @@
+//! Retry policy for transient Anthropic errors.
+//!
+//! `handlers::send_to_anthropic` retries 429 (rate limited), 529 (overloaded)
+//! and other 5xx answers before anything is returned to the caller, so it
+//! covers streaming and non-streaming calls alike. Delays grow exponentially
+//! from `CH_RETRY_BASE_DELAY_MS` (default 1000) with jitter, capped at
+//! `CH_RETRY_MAX_DELAY_MS` (default 30000); a `retry-after` header is honored
+//! instead, and when it asks for longer than the cap the error is returned
+//! right away. `CH_RETRY_MAX_ATTEMPTS` (default 4, 1 = no retries) counts the
+//! first request. These are the defaults; the `chat`, `stream` and `batch`
+//! endpoint policies can override them (see `endpoint_policies`). Each retry
+//! is attached to the response (`Retries`) and, in streams, sent as a
+//! `{"type": "retry", …}` frame.
+
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+const DEFAULT_MAX_ATTEMPTS: u32 = 4;
+const DEFAULT_BASE_DELAY_MS: u64 = 1_000;
+const DEFAULT_MAX_DELAY_MS: u64 = 30_000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    /// Requests in total, the first one included.
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+fn env_u64(name: &str) -> Option<u64> {
+    std::env::var(name).ok().and_then(|v| v.trim().parse().ok())
+}
+
+impl RetryPolicy {
+    /// The policy from the env vars (read once).
+    pub fn get() -> RetryPolicy {
+        static POLICY: OnceLock<RetryPolicy> = OnceLock::new();
+        *POLICY.get_or_init(|| RetryPolicy {
+            max_attempts: env_u64("CH_RETRY_MAX_ATTEMPTS")
+                .map(|n| n.clamp(1, 10) as u32)
+                .unwrap_or(DEFAULT_MAX_ATTEMPTS),
+            base_delay: Duration::from_millis(env_u64("CH_RETRY_BASE_DELAY_MS").unwrap_or(DEFAULT_BASE_DELAY_MS)),
+            max_delay: Duration::from_millis(env_u64("CH_RETRY_MAX_DELAY_MS").unwrap_or(DEFAULT_MAX_DELAY_MS)),
+        })
+    }
+
+    /// How long to wait before `attempt` (2 = first retry), or `None` to give
+    /// up. `jitter` in [0, 1) picks a point in the upper half of the window.
+    pub fn delay(&self, attempt: u32, retry_after: Option<Duration>, jitter: f64) -> Option<Duration> {
+        if attempt > self.max_attempts {
+            return None;
+        }
+        if let Some(wait) = retry_after {
+            return (wait <= self.max_delay).then_some(wait);
+        }
+        let exponent = attempt.saturating_sub(2).min(16);
+        let window = self.base_delay.saturating_mul(1 << exponent).min(self.max_delay);
+        Some(window.mul_f64(0.5 + jitter.clamp(0.0, 1.0) / 2.0))
+    }
+}
+
+/// `retry-after` as seconds or an HTTP date.
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse::<f64>() {
+        return (secs.is_finite() && secs >= 0.0).then(|| Duration::from_secs_f64(secs));
+    }
+    let at = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    (at.with_timezone(&chrono::Utc) - chrono::Utc::now()).to_std().ok()
+}
+
+/// One retried upstream answer.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct RetryAttempt {
+    /// The request that got this answer (1 = the first).
+    pub attempt: u32,
+    pub status: u16,
+    /// Time waited before the next request.
+    pub delay_ms: u64,
+    /// Whether the wait came from the upstream `retry-after` header.
+    pub retry_after: bool,
+}
+
+/// Retries behind a response, stored in its extensions.
+#[derive(Debug, Clone, Default)]
+pub struct Retries(pub Vec<RetryAttempt>);
+
+/// Retries recorded on `resp` by `send_to_anthropic`.
+pub fn of_response(resp: &reqwest::Response) -> Vec<RetryAttempt> {
+    resp.extensions().get::<Retries>().map(|r| r.0.clone()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 4,
+            base_delay: Duration::from_millis(1_000),
+            max_delay: Duration::from_millis(5_000),
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let p = policy();
+        assert_eq!(p.delay(2, None, 1.0), Some(Duration::from_millis(1_000)));
+        assert_eq!(p.delay(3, None, 1.0), Some(Duration::from_millis(2_000)));
+        assert_eq!(p.delay(4, None, 1.0), Some(Duration::from_millis(4_000)));
+        assert_eq!(p.delay(4, None, 0.0), Some(Duration::from_millis(2_000)));
+        assert_eq!(p.delay(5, None, 1.0), None);
+    }
+
+    #[test]
+    fn retry_after_wins_unless_it_exceeds_the_cap() {
+        let p = policy();
+        assert_eq!(p.delay(2, Some(Duration::from_secs(3)), 0.5), Some(Duration::from_secs(3)));
+        assert_eq!(p.delay(2, Some(Duration::from_secs(60)), 0.5), None);
+    }
+
+    #[test]
+    fn retry_after_header_is_parsed() {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, "7".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(7)));
+        headers.insert(RETRY_AFTER, "soon".parse().unwrap());
+        assert_eq!(retry_after(&headers), None);
+    }
+}
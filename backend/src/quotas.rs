@@ -0,0 +1,542 @@
+// ClaudeHydra v4 -- Spend caps and per-model token quotas
+// Optional hard limits on what the deployment may spend, counted from
+// ch_usage_events across all replicas:
+//   - a daily and/or monthly cap on estimated spend (USD, UTC day / month),
+//   - a token budget (input + output) per model id, per UTC day or month.
+//
+// Once a limit is reached, chat requests are rejected with 429 and a
+// structured `quota_exceeded` error until the period resets: the `enforce`
+// middleware stops them before a stream is opened, and `send_to_anthropic`
+// checks again per call (agents, debates, background jobs, the model picked
+// after routing). `GET /api/health` gets a `quota` object saying whether any
+// limit is exceeded — amounts are only shown on `GET /api/admin/quotas`.
+//
+// Configured with `PUT /api/admin/quotas` (ch_settings.quotas, synced across
+// replicas).
+
+use std::collections::BTreeMap;
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use axum::Json;
+use axum::extract::{Request, State};
+use axum::http::{Method, StatusCode, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, Datelike, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use utoipa::ToSchema;
+
+use crate::state::AppState;
+
+/// How long the usage totals are reused before they are queried again.
+const USAGE_TTL: Duration = Duration::from_secs(30);
+
+type ApiError = (StatusCode, Json<Value>);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Period {
+    Day,
+    #[default]
+    Month,
+}
+
+impl Period {
+    /// Start of the current period (UTC) and of the next one.
+    fn window(self, now: DateTime<Utc>) -> (DateTime<Utc>, DateTime<Utc>) {
+        let today = now.date_naive();
+        let (start, next) = match self {
+            Period::Day => (today, today.succ_opt().unwrap_or(today)),
+            Period::Month => {
+                let first = today.with_day(1).unwrap_or(today);
+                (first, first.checked_add_months(chrono::Months::new(1)).unwrap_or(first))
+            }
+        };
+        let midnight = |d: chrono::NaiveDate| d.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc();
+        (midnight(start), midnight(next))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ModelQuota {
+    /// Input + output tokens per period.
+    pub tokens: u64,
+    #[serde(default)]
+    pub period: Period,
+}
+
+/// All limits; an omitted limit is unlimited.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Quotas {
+    /// Estimated spend cap in USD per UTC day.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daily_spend_usd: Option<f64>,
+    /// Estimated spend cap in USD per UTC calendar month.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub monthly_spend_usd: Option<f64>,
+    /// Token budget per model id (e.g. `claude-opus-4-6`).
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub models: BTreeMap<String, ModelQuota>,
+}
+
+impl Quotas {
+    fn is_empty(&self) -> bool {
+        self.daily_spend_usd.is_none() && self.monthly_spend_usd.is_none() && self.models.is_empty()
+    }
+}
+
+/// Spend so far and tokens per budgeted model in its own period.
+#[derive(Debug, Clone, Default)]
+struct Usage {
+    day_usd: f64,
+    month_usd: f64,
+    model_tokens: BTreeMap<String, u64>,
+}
+
+/// A limit that has been reached.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "quota", rename_all = "snake_case")]
+pub enum Breach {
+    DailySpend { used_usd: f64, limit_usd: f64 },
+    MonthlySpend { used_usd: f64, limit_usd: f64 },
+    ModelTokens { model: String, used_tokens: u64, limit_tokens: u64, period: Period },
+}
+
+impl Breach {
+    fn period(&self) -> Period {
+        match self {
+            Breach::DailySpend { .. } => Period::Day,
+            Breach::MonthlySpend { .. } => Period::Month,
+            Breach::ModelTokens { period, .. } => *period,
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            Breach::DailySpend { .. } => "Daily spend cap reached".to_string(),
+            Breach::MonthlySpend { .. } => "Monthly spend cap reached".to_string(),
+            Breach::ModelTokens { model, period, .. } => format!(
+                "{} token budget for {} is exhausted",
+                if *period == Period::Day { "Daily" } else { "Monthly" },
+                model
+            ),
+        }
+    }
+
+    /// Kind (and model) only — what the public health endpoint shows.
+    fn summary(&self) -> Value {
+        match self {
+            Breach::DailySpend { .. } => json!({ "quota": "daily_spend" }),
+            Breach::MonthlySpend { .. } => json!({ "quota": "monthly_spend" }),
+            Breach::ModelTokens { model, .. } => json!({ "quota": "model_tokens", "model": model }),
+        }
+    }
+}
+
+static QUOTAS: RwLock<Quotas> = RwLock::new(Quotas {
+    daily_spend_usd: None,
+    monthly_spend_usd: None,
+    models: BTreeMap::new(),
+});
+static USAGE: Mutex<Option<(Instant, Usage)>> = Mutex::new(None);
+
+pub fn set(quotas: Quotas) {
+    if let Ok(mut current) = QUOTAS.write() {
+        *current = quotas;
+    }
+    // The budgeted models may have changed.
+    if let Ok(mut cached) = USAGE.lock() {
+        *cached = None;
+    }
+}
+
+fn quotas() -> Quotas {
+    QUOTAS.read().map(|q| q.clone()).unwrap_or_default()
+}
+
+/// Load the quotas at startup.
+pub async fn load(db: &sqlx::PgPool) {
+    match sqlx::query_scalar::<_, Option<Value>>("SELECT quotas FROM ch_settings WHERE id = 1")
+        .fetch_optional(db)
+        .await
+    {
+        Ok(stored) => set(
+            stored
+                .flatten()
+                .and_then(|v| serde_json::from_value(v).ok())
+                .unwrap_or_default(),
+        ),
+        Err(e) => tracing::warn!("quotas: failed to load quotas: {}", e),
+    }
+}
+
+/// Usage counted against `quotas` (cached for `USAGE_TTL`). Read errors
+/// count as no usage — a broken query must not stop all chat.
+async fn usage(db: &sqlx::PgPool, quotas: &Quotas, fresh: bool) -> Usage {
+    if !fresh
+        && let Ok(cached) = USAGE.lock()
+        && let Some((at, usage)) = cached.as_ref()
+        && at.elapsed() < USAGE_TTL
+    {
+        return usage.clone();
+    }
+    let now = Utc::now();
+    let (day, _) = Period::Day.window(now);
+    let (month, _) = Period::Month.window(now);
+    let mut usage = Usage::default();
+    match sqlx::query_as::<_, (f64, f64)>(
+        "SELECT COALESCE(SUM(cost_usd) FILTER (WHERE created_at >= $1), 0)::FLOAT8, \
+                COALESCE(SUM(cost_usd), 0)::FLOAT8 \
+         FROM ch_usage_events WHERE created_at >= $2",
+    )
+    .bind(day)
+    .bind(month)
+    .fetch_one(db)
+    .await
+    {
+        Ok((day_usd, month_usd)) => {
+            usage.day_usd = day_usd;
+            usage.month_usd = month_usd;
+        }
+        Err(e) => tracing::warn!("quotas: failed to read spend: {}", e),
+    }
+    for (model, quota) in &quotas.models {
+        let used: Result<i64, sqlx::Error> = sqlx::query_scalar(
+            "SELECT COALESCE(SUM(total_tokens), 0)::BIGINT FROM ch_usage_events \
+             WHERE created_at >= $1 AND model = $2",
+        )
+        .bind(quota.period.window(now).0)
+        .bind(model)
+        .fetch_one(db)
+        .await;
+        match used {
+            Ok(used) => {
+                usage.model_tokens.insert(model.clone(), used.max(0) as u64);
+            }
+            Err(e) => tracing::warn!("quotas: failed to read usage of {}: {}", model, e),
+        }
+    }
+    if let Ok(mut cached) = USAGE.lock() {
+        *cached = Some((Instant::now(), usage.clone()));
+    }
+    usage
+}
+
+/// Spend caps reached by `usage`.
+fn spend_breaches(quotas: &Quotas, usage: &Usage) -> Vec<Breach> {
+    let mut out = Vec::new();
+    if let Some(limit) = quotas.daily_spend_usd
+        && usage.day_usd >= limit
+    {
+        out.push(Breach::DailySpend { used_usd: usage.day_usd, limit_usd: limit });
+    }
+    if let Some(limit) = quotas.monthly_spend_usd
+        && usage.month_usd >= limit
+    {
+        out.push(Breach::MonthlySpend { used_usd: usage.month_usd, limit_usd: limit });
+    }
+    out
+}
+
+/// `model`'s token budget, if it has one and it is used up.
+fn model_breach(quotas: &Quotas, usage: &Usage, model: &str) -> Option<Breach> {
+    let quota = quotas.models.get(model)?;
+    let used = usage.model_tokens.get(model).copied().unwrap_or(0);
+    (used >= quota.tokens).then(|| Breach::ModelTokens {
+        model: model.to_string(),
+        used_tokens: used,
+        limit_tokens: quota.tokens,
+        period: quota.period,
+    })
+}
+
+/// Limits a request for `model` would hit (spend caps only when the model
+/// is not known yet).
+fn breaches(quotas: &Quotas, usage: &Usage, model: Option<&str>) -> Vec<Breach> {
+    let mut out = spend_breaches(quotas, usage);
+    out.extend(model.and_then(|m| model_breach(quotas, usage, m)));
+    out
+}
+
+/// Every limit currently reached, including all model budgets.
+fn all_breaches(quotas: &Quotas, usage: &Usage) -> Vec<Breach> {
+    let mut out = spend_breaches(quotas, usage);
+    out.extend(quotas.models.keys().filter_map(|m| model_breach(quotas, usage, m)));
+    out
+}
+
+fn rejection(breach: &Breach) -> (StatusCode, u64, Value) {
+    let now = Utc::now();
+    let resets_at = breach.period().window(now).1;
+    let retry_after = (resets_at - now).num_seconds().max(1) as u64;
+    let body = json!({
+        "error": breach.message(),
+        "code": "quota_exceeded",
+        "quota": breach,
+        "resets_at": resets_at,
+    });
+    (StatusCode::TOO_MANY_REQUESTS, retry_after, body)
+}
+
+/// Check the spend caps and, with `model`, that model's token budget.
+pub async fn check(state: &AppState, model: Option<&str>) -> Result<(), ApiError> {
+    let quotas = quotas();
+    if quotas.is_empty() {
+        return Ok(());
+    }
+    let usage = usage(&state.db, &quotas, false).await;
+    match breaches(&quotas, &usage, model).first() {
+        None => {
+            warn_near_limits(&quotas, &usage, model);
+            Ok(())
+        }
+        Some(breach) => {
+            tracing::info!("quotas: rejecting request — {}", breach.message());
+            let (status, _, body) = rejection(breach);
+            Err((status, Json(body)))
+        }
+    }
+}
+
+/// Soft-limit warnings for limits that are close (see soft_limits).
+fn warn_near_limits(quotas: &Quotas, usage: &Usage, model: Option<&str>) {
+    use crate::soft_limits::{Kind, check};
+    if let Some(limit) = quotas.daily_spend_usd {
+        check(
+            Kind::Quota,
+            "daily_spend",
+            usage.day_usd,
+            limit,
+            "Daily spend cap is almost reached",
+            json!({ "quota": "daily_spend" }),
+        );
+    }
+    if let Some(limit) = quotas.monthly_spend_usd {
+        check(
+            Kind::Quota,
+            "monthly_spend",
+            usage.month_usd,
+            limit,
+            "Monthly spend cap is almost reached",
+            json!({ "quota": "monthly_spend" }),
+        );
+    }
+    if let Some(model) = model
+        && let Some(quota) = quotas.models.get(model)
+    {
+        check(
+            Kind::Quota,
+            &format!("model_tokens:{model}"),
+            usage.model_tokens.get(model).copied().unwrap_or(0) as f64,
+            quota.tokens as f64,
+            &format!("Token budget for {model} is almost used up"),
+            json!({ "quota": "model_tokens", "model": model, "period": quota.period }),
+        );
+    }
+}
+
+/// Requests that start a model call.
+fn is_chat_request(method: &Method, path: &str) -> bool {
+    if path == "/ws/chat" {
+        return true;
+    }
+    if *method != Method::POST {
+        return false;
+    }
+    matches!(
+        path,
+        "/api/claude/chat" | "/api/claude/chat/stream" | "/api/gemini/chat" | "/api/gemini/chat/stream" | "/api/debate"
+    ) || (path.starts_with("/api/agents/") && path.ends_with("/run"))
+        || (path.starts_with("/api/sessions/")
+            && (path.ends_with("/chat") || path.ends_with("/chat/stream") || path.ends_with("/regenerate")))
+}
+
+/// Middleware: reject chat requests with 429 once a spend cap is reached.
+pub async fn enforce(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    if !is_chat_request(req.method(), req.uri().path()) {
+        return next.run(req).await;
+    }
+    let quotas = quotas();
+    if quotas.is_empty() {
+        return next.run(req).await;
+    }
+    let usage = usage(&state.db, &quotas, false).await;
+    if let Some(breach) = breaches(&quotas, &usage, None).first() {
+        tracing::info!("quotas: rejecting {} — {}", req.uri().path(), breach.message());
+        let (status, retry_after, body) = rejection(breach);
+        return (status, [(header::RETRY_AFTER, retry_after.to_string())], Json(body)).into_response();
+    }
+    next.run(req).await
+}
+
+/// Quota status for the health endpoint: exceeded limits by kind, no amounts.
+pub(crate) async fn health_status(state: &AppState) -> Value {
+    let quotas = quotas();
+    if quotas.is_empty() {
+        return json!({ "configured": false, "status": "ok", "exceeded": [] });
+    }
+    let usage = usage(&state.db, &quotas, false).await;
+    let exceeded: Vec<Value> = all_breaches(&quotas, &usage).iter().map(Breach::summary).collect();
+    json!({
+        "configured": true,
+        "status": if exceeded.is_empty() { "ok" } else { "exceeded" },
+        "exceeded": exceeded,
+    })
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  /api/admin/quotas
+// ═══════════════════════════════════════════════════════════════════════
+
+/// `GET /api/admin/quotas` — limits with current usage
+#[utoipa::path(get, path = "/api/admin/quotas", tag = "system",
+    responses((status = 200, description = "Spend caps and model budgets with usage and reset times")))]
+pub async fn get_quotas(State(state): State<AppState>) -> Json<Value> {
+    let quotas = quotas();
+    let usage = usage(&state.db, &quotas, true).await;
+    let now = Utc::now();
+    let spend = |limit: Option<f64>, used: f64, period: Period| {
+        json!({
+            "limit_usd": limit,
+            "used_usd": used,
+            "remaining_usd": limit.map(|l| (l - used).max(0.0)),
+            "exceeded": limit.is_some_and(|l| used >= l),
+            "resets_at": period.window(now).1,
+        })
+    };
+    let models: Vec<Value> = quotas
+        .models
+        .iter()
+        .map(|(model, quota)| {
+            let used = usage.model_tokens.get(model).copied().unwrap_or(0);
+            json!({
+                "model": model,
+                "period": quota.period,
+                "limit_tokens": quota.tokens,
+                "used_tokens": used,
+                "remaining_tokens": quota.tokens.saturating_sub(used),
+                "exceeded": used >= quota.tokens,
+                "resets_at": quota.period.window(now).1,
+            })
+        })
+        .collect();
+    Json(json!({
+        "daily_spend": spend(quotas.daily_spend_usd, usage.day_usd, Period::Day),
+        "monthly_spend": spend(quotas.monthly_spend_usd, usage.month_usd, Period::Month),
+        "models": models,
+    }))
+}
+
+/// `PUT /api/admin/quotas` — replace all limits (`{}` removes them)
+#[utoipa::path(put, path = "/api/admin/quotas", tag = "system",
+    request_body = Quotas,
+    responses(
+        (status = 200, description = "Quotas updated"),
+        (status = 400, description = "A limit that is not positive")
+    ))]
+pub async fn set_quotas(
+    State(state): State<AppState>,
+    Json(quotas): Json<Quotas>,
+) -> Result<Json<Value>, ApiError> {
+    let bad_spend = [quotas.daily_spend_usd, quotas.monthly_spend_usd]
+        .into_iter()
+        .flatten()
+        .any(|usd| !usd.is_finite() || usd <= 0.0);
+    if bad_spend || quotas.models.values().any(|q| q.tokens == 0) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "Limits must be positive (leave a limit out for none)" })),
+        ));
+    }
+    if quotas.models.keys().any(|m| m.trim().is_empty()) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "Model ids must not be empty" })),
+        ));
+    }
+    let stored = serde_json::to_value(&quotas).unwrap_or_default();
+    sqlx::query("UPDATE ch_settings SET quotas = $1, updated_at = NOW() WHERE id = 1")
+        .bind(&stored)
+        .execute(&state.db)
+        .await
+        .map_err(|e| {
+            tracing::error!("quotas: failed to store quotas: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Failed to update quotas" })),
+            )
+        })?;
+    set(quotas);
+    crate::cluster::publish(
+        &state,
+        crate::cluster::ClusterEvent::Quotas {
+            quotas: stored.clone(),
+        },
+    );
+    crate::audit::log_audit(&state.db, "set_quotas", stored, None).await;
+    Ok(get_quotas(State(state)).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quotas() -> Quotas {
+        Quotas {
+            daily_spend_usd: Some(5.0),
+            monthly_spend_usd: Some(100.0),
+            models: BTreeMap::from([(
+                "claude-opus-4-6".to_string(),
+                ModelQuota { tokens: 1_000, period: Period::Day },
+            )]),
+        }
+    }
+
+    #[test]
+    fn spend_caps_apply_to_every_request() {
+        let usage = Usage { day_usd: 5.0, month_usd: 20.0, ..Default::default() };
+        assert_eq!(
+            breaches(&quotas(), &usage, None),
+            vec![Breach::DailySpend { used_usd: 5.0, limit_usd: 5.0 }]
+        );
+        let usage = Usage { day_usd: 1.0, month_usd: 20.0, ..Default::default() };
+        assert!(breaches(&quotas(), &usage, Some("claude-sonnet-4-6")).is_empty());
+    }
+
+    #[test]
+    fn model_budgets_apply_only_to_their_model() {
+        let usage = Usage {
+            model_tokens: BTreeMap::from([("claude-opus-4-6".to_string(), 1_000)]),
+            ..Default::default()
+        };
+        assert!(breaches(&quotas(), &usage, None).is_empty());
+        assert!(breaches(&quotas(), &usage, Some("claude-haiku-4-5-20251001")).is_empty());
+        assert!(matches!(
+            breaches(&quotas(), &usage, Some("claude-opus-4-6")).as_slice(),
+            [Breach::ModelTokens { used_tokens: 1_000, limit_tokens: 1_000, .. }]
+        ));
+        assert_eq!(all_breaches(&quotas(), &usage).len(), 1);
+    }
+
+    #[test]
+    fn periods_reset_at_utc_boundaries() {
+        let now = DateTime::parse_from_rfc3339("2026-12-31T15:30:00Z").unwrap().with_timezone(&Utc);
+        let (start, next) = Period::Day.window(now);
+        assert_eq!(start.to_rfc3339(), "2026-12-31T00:00:00+00:00");
+        assert_eq!(next.to_rfc3339(), "2027-01-01T00:00:00+00:00");
+        let (start, next) = Period::Month.window(now);
+        assert_eq!(start.to_rfc3339(), "2026-12-01T00:00:00+00:00");
+        assert_eq!(next.to_rfc3339(), "2027-01-01T00:00:00+00:00");
+    }
+
+    #[test]
+    fn chat_paths_are_recognised() {
+        assert!(is_chat_request(&Method::POST, "/api/claude/chat/stream"));
+        assert!(is_chat_request(&Method::POST, "/api/sessions/abc/chat"));
+        assert!(is_chat_request(&Method::GET, "/ws/chat"));
+        assert!(!is_chat_request(&Method::GET, "/api/sessions/abc"));
+        assert!(!is_chat_request(&Method::POST, "/api/token-count"));
+    }
+}
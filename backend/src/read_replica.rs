@@ -0,0 +1,134 @@
+// ClaudeHydra v4 -- Read replica
+// Heavy read-only queries (analytics, usage reports and export, session
+// search, prompt canary metrics) can run on a Postgres read replica so they
+// never contend with the primary serving live chats. Off unless
+// DATABASE_READ_URL is set.
+//
+// The replica is checked every 30 seconds. While it is unreachable or lags
+// the primary by more than CH_READ_REPLICA_MAX_LAG_SECS (default 300), the
+// queries go to the primary again. Its state is reported as `read_replica`
+// in `GET /api/health`.
+
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use serde_json::{Value, json};
+use sqlx::PgPool;
+
+use crate::state::AppState;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+const DEFAULT_MAX_LAG_SECS: i64 = 300;
+
+static REPLICA: OnceLock<PgPool> = OnceLock::new();
+static HEALTHY: AtomicBool = AtomicBool::new(false);
+/// Replication lag at the last check, in seconds (-1 = unknown).
+static LAG_SECS: AtomicI64 = AtomicI64::new(-1);
+
+fn max_lag_secs() -> i64 {
+    std::env::var("CH_READ_REPLICA_MAX_LAG_SECS")
+        .ok()
+        .and_then(|v| v.trim().parse::<i64>().ok())
+        .filter(|s| *s > 0)
+        .unwrap_or(DEFAULT_MAX_LAG_SECS)
+}
+
+/// Whether a replica that answered with `lag` seconds of lag may serve reads.
+/// `None`: not replaying (a promoted replica or a plain Postgres), no lag.
+fn usable(lag: Option<f64>, max_lag: i64) -> bool {
+    lag.is_none_or(|lag| lag <= max_lag as f64)
+}
+
+async fn check(pool: &PgPool) {
+    // Lag is 0 while the replica has replayed everything (no new writes).
+    let lag = sqlx::query_scalar::<_, Option<f64>>(
+        "SELECT CASE WHEN NOT pg_is_in_recovery() THEN NULL \
+                     WHEN pg_last_wal_receive_lsn() = pg_last_wal_replay_lsn() THEN 0 \
+                     ELSE EXTRACT(EPOCH FROM now() - pg_last_xact_replay_timestamp())::FLOAT8 END",
+    )
+    .fetch_one(pool)
+    .await;
+    let healthy = match lag {
+        Ok(lag) => {
+            LAG_SECS.store(lag.map(|l| l.round() as i64).unwrap_or(0), Ordering::Relaxed);
+            usable(lag, max_lag_secs())
+        }
+        Err(e) => {
+            tracing::debug!("read_replica: check failed: {}", e);
+            LAG_SECS.store(-1, Ordering::Relaxed);
+            false
+        }
+    };
+    if HEALTHY.swap(healthy, Ordering::Relaxed) != healthy {
+        if healthy {
+            tracing::info!("read_replica: serving analytics reads");
+        } else {
+            tracing::warn!("read_replica: unreachable or lagging — analytics reads go to the primary");
+        }
+    }
+}
+
+/// Connect to DATABASE_READ_URL (if set) and start the health checks.
+/// A replica that is down at startup is picked up by a later check.
+pub async fn init() {
+    let Some(url) = std::env::var("DATABASE_READ_URL").ok().filter(|u| !u.trim().is_empty()) else {
+        return;
+    };
+    let pool = match sqlx::postgres::PgPoolOptions::new()
+        .max_connections(5)
+        .acquire_timeout(Duration::from_secs(5))
+        .connect_lazy(url.trim())
+    {
+        Ok(pool) => pool,
+        Err(e) => {
+            tracing::error!("read_replica: invalid DATABASE_READ_URL: {}", e);
+            return;
+        }
+    };
+    if REPLICA.set(pool.clone()).is_err() {
+        return;
+    }
+    check(&pool).await;
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            check(&pool).await;
+        }
+    });
+}
+
+/// Pool for heavy read-only queries: the replica while it is healthy, else
+/// the primary.
+pub fn pool(state: &AppState) -> &PgPool {
+    match REPLICA.get() {
+        Some(replica) if HEALTHY.load(Ordering::Relaxed) => replica,
+        _ => &state.db,
+    }
+}
+
+/// Replica state for the health endpoint (`None` when not configured).
+pub fn health_status() -> Option<Value> {
+    REPLICA.get()?;
+    let lag = LAG_SECS.load(Ordering::Relaxed);
+    Some(json!({
+        "status": if HEALTHY.load(Ordering::Relaxed) { "ok" } else { "fallback" },
+        "lag_secs": (lag >= 0).then_some(lag),
+        "max_lag_secs": max_lag_secs(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lagging_replicas_are_not_used() {
+        assert!(usable(None, 300));
+        assert!(usable(Some(0.0), 300));
+        assert!(usable(Some(299.5), 300));
+        assert!(!usable(Some(301.0), 300));
+    }
+}
@@ -0,0 +1,379 @@
+// ClaudeHydra v4 -- Crash recovery journal
+// Long-running operations write a journal file when they start and remove it
+// when they end (however they end, panics included). A file that is still
+// there at the next startup belongs to an operation the process never
+// finished — a crash, OOM kill or power loss. `recover` moves those entries
+// into ch_interrupted_operations, where they are listed and can be retried:
+//
+//   - `stream`       — chat streams bound to a session (`/api/claude/chat/stream`)
+//   - `document_job` — document summaries (`/api/documents/{id}/summarize`)
+//   - `agent_run`    — server-side tool loops (`/api/agents/{id}/run`)
+//
+// `GET /api/recovery` lists them, `POST /api/recovery/{id}/retry` starts the
+// same operation again (its response is the new operation's response) and
+// `DELETE /api/recovery/{id}` dismisses one.
+//
+// The journal lives in CH_RECOVERY_DIR (default `~/.claudehydra/journal`);
+// an unwritable directory turns journaling off with a warning.
+
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use axum::Json;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::state::AppState;
+
+const DEFAULT_LIST_LIMIT: i64 = 100;
+const MAX_LIST_LIMIT: i64 = 500;
+/// Payloads above this are journaled without the request (not retryable).
+const MAX_PAYLOAD_BYTES: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationKind {
+    Stream,
+    DocumentJob,
+    AgentRun,
+}
+
+impl OperationKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            OperationKind::Stream => "stream",
+            OperationKind::DocumentJob => "document_job",
+            OperationKind::AgentRun => "agent_run",
+        }
+    }
+}
+
+/// What is written to disk while the operation runs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalRecord {
+    pub id: uuid::Uuid,
+    pub kind: OperationKind,
+    pub instance: String,
+    pub session_id: Option<uuid::Uuid>,
+    /// One line for humans ("chat stream in session …", "summary of …").
+    pub summary: String,
+    /// Everything needed to start the operation again (`null` = not retryable).
+    pub payload: Value,
+    pub started_at: DateTime<Utc>,
+}
+
+fn journal_dir() -> Option<&'static PathBuf> {
+    static DIR: OnceLock<Option<PathBuf>> = OnceLock::new();
+    DIR.get_or_init(|| {
+        let dir = std::env::var("CH_RECOVERY_DIR")
+            .ok()
+            .filter(|d| !d.trim().is_empty())
+            .map(PathBuf::from)
+            .or_else(|| dirs::home_dir().map(|h| h.join(".claudehydra").join("journal")))?;
+        match std::fs::create_dir_all(&dir) {
+            Ok(()) => Some(dir),
+            Err(e) => {
+                tracing::warn!("recovery: journal disabled, cannot create {}: {}", dir.display(), e);
+                None
+            }
+        }
+    })
+    .as_ref()
+}
+
+/// An open journal entry; dropping it marks the operation as finished.
+#[derive(Debug)]
+pub struct JournalEntry {
+    path: PathBuf,
+}
+
+impl Drop for JournalEntry {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path)
+            && e.kind() != std::io::ErrorKind::NotFound
+        {
+            tracing::warn!("recovery: failed to remove {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+/// Journal the start of an operation. `None` when journaling is off or the
+/// file could not be written (the operation runs anyway).
+pub fn begin(
+    kind: OperationKind,
+    session_id: Option<uuid::Uuid>,
+    summary: impl Into<String>,
+    payload: Value,
+) -> Option<JournalEntry> {
+    let dir = journal_dir()?;
+    let payload = if payload.to_string().len() > MAX_PAYLOAD_BYTES {
+        Value::Null
+    } else {
+        payload
+    };
+    let record = JournalRecord {
+        id: uuid::Uuid::new_v4(),
+        kind,
+        instance: crate::cluster::instance_id().to_string(),
+        session_id,
+        summary: summary.into(),
+        payload,
+        started_at: Utc::now(),
+    };
+    let path = dir.join(format!("{}.json", record.id));
+    let bytes = serde_json::to_vec(&record).ok()?;
+    // Write-then-rename so a crash mid-write never leaves a torn file.
+    let tmp = path.with_extension("tmp");
+    if let Err(e) = std::fs::write(&tmp, bytes).and_then(|_| std::fs::rename(&tmp, &path)) {
+        tracing::warn!("recovery: failed to journal {} operation: {}", kind.as_str(), e);
+        let _ = std::fs::remove_file(&tmp);
+        return None;
+    }
+    Some(JournalEntry { path })
+}
+
+/// Move journal files left by the previous process into the database.
+/// Call once at startup, before new operations start.
+pub async fn recover(db: &sqlx::PgPool) {
+    let Some(dir) = journal_dir() else {
+        return;
+    };
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    let mut recovered = 0;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => {}
+            // Torn writes from a crash during `begin`
+            Some("tmp") => {
+                let _ = std::fs::remove_file(&path);
+                continue;
+            }
+            _ => continue,
+        }
+        let record = match std::fs::read(&path)
+            .ok()
+            .and_then(|b| serde_json::from_slice::<JournalRecord>(&b).ok())
+        {
+            Some(r) => r,
+            None => {
+                tracing::warn!("recovery: unreadable journal file {}, removing", path.display());
+                let _ = std::fs::remove_file(&path);
+                continue;
+            }
+        };
+        let inserted = sqlx::query(
+            "INSERT INTO ch_interrupted_operations \
+             (id, kind, instance, session_id, summary, payload, started_at) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7) ON CONFLICT (id) DO NOTHING",
+        )
+        .bind(record.id)
+        .bind(record.kind.as_str())
+        .bind(&record.instance)
+        .bind(record.session_id)
+        .bind(&record.summary)
+        .bind(&record.payload)
+        .bind(record.started_at)
+        .execute(db)
+        .await;
+        match inserted {
+            Ok(_) => {
+                recovered += 1;
+                let _ = std::fs::remove_file(&path);
+            }
+            // Keep the file; the next start tries again.
+            Err(e) => tracing::warn!("recovery: failed to record {}: {}", record.id, e),
+        }
+    }
+    if recovered > 0 {
+        tracing::warn!("recovery: {} operation(s) interrupted by the last shutdown", recovered);
+    }
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  /api/recovery
+// ═══════════════════════════════════════════════════════════════════════
+
+type ApiError = (StatusCode, Json<Value>);
+
+fn api_error(status: StatusCode, msg: impl Into<String>) -> ApiError {
+    (status, Json(json!({ "error": msg.into() })))
+}
+
+fn db_error(e: sqlx::Error) -> ApiError {
+    tracing::error!("recovery: database error: {}", e);
+    api_error(StatusCode::INTERNAL_SERVER_ERROR, "Database error")
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct InterruptedOperation {
+    pub id: uuid::Uuid,
+    pub kind: String,
+    pub instance: String,
+    pub session_id: Option<uuid::Uuid>,
+    pub summary: String,
+    pub started_at: DateTime<Utc>,
+    pub interrupted_at: DateTime<Utc>,
+    /// `interrupted`, `retried` or `dismissed`.
+    pub status: String,
+    pub resolved_at: Option<DateTime<Utc>>,
+    pub retryable: bool,
+}
+
+const OPERATION_COLUMNS: &str = "id, kind, instance, session_id, summary, started_at, interrupted_at, \
+     status, resolved_at, payload <> 'null'::jsonb AS retryable";
+
+#[derive(Debug, Deserialize)]
+pub struct RecoveryQuery {
+    /// `interrupted` (default), `retried`, `dismissed` or `all`.
+    pub status: Option<String>,
+    pub session_id: Option<uuid::Uuid>,
+    pub limit: Option<i64>,
+}
+
+/// `GET /api/recovery` — operations interrupted by a crash or restart
+pub async fn list_interrupted(
+    State(state): State<AppState>,
+    Query(q): Query<RecoveryQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let status = q.status.as_deref().unwrap_or("interrupted");
+    if !matches!(status, "interrupted" | "retried" | "dismissed" | "all") {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            "status must be interrupted, retried, dismissed or all",
+        ));
+    }
+    let rows = sqlx::query_as::<_, InterruptedOperation>(&format!(
+        "SELECT {} FROM ch_interrupted_operations \
+         WHERE ($1 = 'all' OR status = $1) AND ($2::uuid IS NULL OR session_id = $2) \
+         ORDER BY started_at DESC LIMIT $3",
+        OPERATION_COLUMNS
+    ))
+    .bind(status)
+    .bind(q.session_id)
+    .bind(q.limit.unwrap_or(DEFAULT_LIST_LIMIT).clamp(1, MAX_LIST_LIMIT))
+    .fetch_all(&state.db)
+    .await
+    .map_err(db_error)?;
+    Ok(Json(json!({ "operations": rows })))
+}
+
+/// Claim an interrupted operation (`interrupted` → `status`); `None` when it
+/// does not exist or was already handled.
+async fn resolve(
+    db: &sqlx::PgPool,
+    id: uuid::Uuid,
+    status: &str,
+) -> Result<Option<(String, Value)>, sqlx::Error> {
+    sqlx::query_as::<_, (String, Value)>(
+        "UPDATE ch_interrupted_operations SET status = $2, resolved_at = NOW() \
+         WHERE id = $1 AND status = 'interrupted' RETURNING kind, payload",
+    )
+    .bind(id)
+    .bind(status)
+    .fetch_optional(db)
+    .await
+}
+
+fn parse_id(id: &str) -> Result<uuid::Uuid, ApiError> {
+    id.parse()
+        .map_err(|_| api_error(StatusCode::BAD_REQUEST, "Invalid operation id"))
+}
+
+/// `POST /api/recovery/{id}/retry` — start the operation again
+pub async fn retry_interrupted(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Response, ApiError> {
+    let id = parse_id(&id)?;
+    let retryable: Option<bool> = sqlx::query_scalar(
+        "SELECT payload <> 'null'::jsonb FROM ch_interrupted_operations \
+         WHERE id = $1 AND status = 'interrupted'",
+    )
+    .bind(id)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(db_error)?;
+    match retryable {
+        None => return Err(api_error(StatusCode::NOT_FOUND, "No interrupted operation with this id")),
+        Some(false) => {
+            return Err(api_error(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "The operation was journaled without its request and cannot be retried",
+            ));
+        }
+        Some(true) => {}
+    }
+    let Some((kind, payload)) = resolve(&state.db, id, "retried").await.map_err(db_error)? else {
+        return Err(api_error(StatusCode::NOT_FOUND, "No interrupted operation with this id"));
+    };
+    tracing::info!("recovery: retrying {} operation {}", kind, id);
+
+    let invalid = || api_error(StatusCode::UNPROCESSABLE_ENTITY, "Stored request is no longer valid");
+    match kind.as_str() {
+        "stream" => {
+            let req: crate::models::ChatRequest = serde_json::from_value(payload).map_err(|_| invalid())?;
+            crate::handlers::claude_chat_stream(State(state), None, Json(req)).await
+        }
+        "document_job" => {
+            let upload_id = payload["upload_id"].as_str().ok_or_else(invalid)?.to_string();
+            let body = crate::handlers::documents::SummarizeDocumentRequest {
+                instructions: payload["instructions"].as_str().map(str::to_string),
+            };
+            crate::handlers::summarize_document(State(state), Path(upload_id), Some(Json(body))).await
+        }
+        "agent_run" => {
+            let agent_id = payload["agent_id"].as_str().ok_or_else(invalid)?.to_string();
+            let req: crate::handlers::agent_run::AgentRunRequest =
+                serde_json::from_value(payload["request"].clone()).map_err(|_| invalid())?;
+            crate::handlers::agent_run::execute_run(&state, &agent_id, &req, Default::default())
+                .await
+                .map(|result| Json(result).into_response())
+        }
+        other => Err(api_error(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("Operations of kind '{}' cannot be retried", other),
+        )),
+    }
+}
+
+/// `DELETE /api/recovery/{id}` — dismiss an interrupted operation
+pub async fn dismiss_interrupted(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    let id = parse_id(&id)?;
+    match resolve(&state.db, id, "dismissed").await.map_err(db_error)? {
+        Some(_) => Ok(StatusCode::NO_CONTENT),
+        None => Err(api_error(StatusCode::NOT_FOUND, "No interrupted operation with this id")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn journal_records_round_trip() {
+        let record = JournalRecord {
+            id: uuid::Uuid::new_v4(),
+            kind: OperationKind::DocumentJob,
+            instance: "replica-1".into(),
+            session_id: None,
+            summary: "summary of report.pdf".into(),
+            payload: json!({ "upload_id": "00000000-0000-0000-0000-000000000000" }),
+            started_at: Utc::now(),
+        };
+        let encoded = serde_json::to_value(&record).unwrap();
+        assert_eq!(encoded["kind"], "document_job");
+        let decoded: JournalRecord = serde_json::from_value(encoded).unwrap();
+        assert_eq!(decoded.kind, OperationKind::DocumentJob);
+        assert_eq!(decoded.id, record.id);
+    }
+}
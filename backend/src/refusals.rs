@@ -0,0 +1,230 @@
+// ClaudeHydra v4 -- Model refusal and safety-stop logging
+// A reply counts as a refusal when the provider says so (Anthropic
+// `stop_reason: "refusal"`, Gemini `finishReason: SAFETY` and friends) or when
+// a short reply opens with a refusal phrase ("I can't help with …"). Each one
+// is logged with structured fields and stored in ch_refusals with its model,
+// agent, session and — when the reply was persisted — the message it tags.
+// `GET /api/usage/refusals` counts them per model / agent.
+//
+// Where it is detected:
+// - `/api/claude/chat` — stop reason + heuristic; with the `refusal_retry`
+//   setting on, a heuristic refusal is retried once with a system note asking
+//   for the answerable part. Provider safety stops are never retried.
+// - `/ws/chat` — heuristic, on the stored assistant message.
+// - NDJSON streams — heuristic, once the reply is persisted (message_context).
+// - agent runs — stop reason + heuristic.
+
+use serde::Serialize;
+
+/// Leading text scanned for refusal phrases.
+const HEURISTIC_WINDOW_CHARS: usize = 200;
+/// Longer replies that open with "I can't …" usually go on to help.
+const HEURISTIC_MAX_CHARS: usize = 1_500;
+const PREVIEW_CHARS: usize = 300;
+
+/// Appended to the system prompt for the one reformulation retry.
+pub const RETRY_SYSTEM_NOTE: &str = "Your previous reply declined this request. Reconsider it: if the request, \
+     or part of it, can be answered safely and helpfully, answer that part, and say briefly what you \
+     cannot help with and why.";
+
+const REFUSAL_PHRASES: &[&str] = &[
+    "i can't help with",
+    "i cannot help with",
+    "i can't assist with",
+    "i cannot assist with",
+    "i'm not able to help",
+    "i am not able to help",
+    "i'm unable to help",
+    "i am unable to help",
+    "i won't be able to help",
+    "i can't provide",
+    "i cannot provide",
+    "i can't comply",
+    "i cannot comply",
+    "i must decline",
+    "i'm not going to help",
+];
+
+/// Provider stop / finish reasons that mean the model refused or was stopped
+/// by a safety filter.
+const REFUSAL_STOP_REASONS: &[&str] = &["refusal", "SAFETY", "PROHIBITED_CONTENT", "BLOCKLIST", "SPII"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RefusalSignal {
+    /// The provider reported a refusal / safety stop.
+    StopReason,
+    /// The reply text reads like a refusal.
+    Heuristic,
+}
+
+impl RefusalSignal {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::StopReason => "stop_reason",
+            Self::Heuristic => "heuristic",
+        }
+    }
+}
+
+/// A detected refusal, carried with the reply until it is recorded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Refusal {
+    pub signal: RefusalSignal,
+    pub stop_reason: Option<String>,
+    /// A reformulation retry was made.
+    pub retried: bool,
+    /// The retry produced a non-refusal answer (which was returned instead).
+    pub resolved: bool,
+    /// Start of the refused reply.
+    pub preview: String,
+}
+
+/// Classify a reply by its stop reason first, then its text.
+pub fn detect(stop_reason: Option<&str>, text: &str) -> Option<Refusal> {
+    let signal = if stop_reason.is_some_and(|r| REFUSAL_STOP_REASONS.contains(&r)) {
+        RefusalSignal::StopReason
+    } else if looks_like_refusal(text) {
+        RefusalSignal::Heuristic
+    } else {
+        return None;
+    };
+    Some(Refusal {
+        signal,
+        stop_reason: stop_reason.map(str::to_string),
+        retried: false,
+        resolved: false,
+        preview: text.trim().chars().take(PREVIEW_CHARS).collect(),
+    })
+}
+
+fn looks_like_refusal(text: &str) -> bool {
+    let text = text.trim();
+    if text.is_empty() || text.chars().count() > HEURISTIC_MAX_CHARS {
+        return false;
+    }
+    let head: String = text
+        .chars()
+        .take(HEURISTIC_WINDOW_CHARS)
+        .collect::<String>()
+        .to_lowercase()
+        .replace('\u{2019}', "'");
+    REFUSAL_PHRASES.iter().any(|p| head.contains(p))
+}
+
+/// Where a refusal was seen and what it tags.
+#[derive(Debug, Clone, Default)]
+pub struct RefusalContext {
+    pub model: String,
+    pub agent_id: Option<String>,
+    pub session_id: Option<uuid::Uuid>,
+    pub message_id: Option<uuid::Uuid>,
+    /// Originating code path: `chat`, `ws`, `stream`, `agent_run`.
+    pub source: &'static str,
+}
+
+/// Whether heuristic refusals on `/api/claude/chat` get a reformulation retry.
+pub async fn retry_enabled(db: &sqlx::PgPool) -> bool {
+    sqlx::query_scalar::<_, bool>("SELECT refusal_retry FROM ch_settings WHERE id = 1")
+        .fetch_optional(db)
+        .await
+        .ok()
+        .flatten()
+        .unwrap_or(false)
+}
+
+/// Log and persist a refusal (fire-and-forget — never blocks or fails the caller).
+pub fn record(db: &sqlx::PgPool, refusal: &Refusal, ctx: RefusalContext) {
+    tracing::warn!(
+        target: "refusal",
+        model = %ctx.model,
+        agent_id = ?ctx.agent_id,
+        session_id = ?ctx.session_id,
+        message_id = ?ctx.message_id,
+        source = ctx.source,
+        signal = refusal.signal.as_str(),
+        stop_reason = ?refusal.stop_reason,
+        retried = refusal.retried,
+        resolved = refusal.resolved,
+        "model refusal"
+    );
+    let db = db.clone();
+    let refusal = refusal.clone();
+    tokio::spawn(async move {
+        if let Err(e) = sqlx::query(
+            "INSERT INTO ch_refusals \
+             (model, agent_id, session_id, message_id, source, signal, stop_reason, retried, resolved, preview) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
+        )
+        .bind(&ctx.model)
+        .bind(&ctx.agent_id)
+        .bind(ctx.session_id)
+        .bind(ctx.message_id)
+        .bind(ctx.source)
+        .bind(refusal.signal.as_str())
+        .bind(&refusal.stop_reason)
+        .bind(refusal.retried)
+        .bind(refusal.resolved)
+        .bind(&refusal.preview)
+        .execute(&db)
+        .await
+        {
+            tracing::warn!("refusals: failed to record refusal: {}", e);
+        }
+    });
+}
+
+/// Check a persisted assistant message (NDJSON replies, whose stop reason
+/// the shared streaming handler does not expose).
+pub async fn scan_message(db: &sqlx::PgPool, message_id: uuid::Uuid, source: &'static str) {
+    let row = sqlx::query_as::<_, (uuid::Uuid, String, Option<String>, Option<String>)>(
+        "SELECT session_id, content, model, agent FROM ch_messages WHERE id = $1 AND role = 'assistant'",
+    )
+    .bind(message_id)
+    .fetch_optional(db)
+    .await;
+    let Ok(Some((session_id, content, model, agent))) = row else {
+        return;
+    };
+    if let Some(refusal) = detect(None, &content) {
+        let ctx = RefusalContext {
+            model: model.unwrap_or_default(),
+            agent_id: agent,
+            session_id: Some(session_id),
+            message_id: Some(message_id),
+            source,
+        };
+        record(db, &refusal, ctx);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn provider_stop_reasons_win_over_text() {
+        let r = detect(Some("refusal"), "Here is the answer.").unwrap();
+        assert_eq!(r.signal, RefusalSignal::StopReason);
+        assert_eq!(r.stop_reason.as_deref(), Some("refusal"));
+        assert_eq!(detect(Some("SAFETY"), "").unwrap().signal, RefusalSignal::StopReason);
+        assert_eq!(detect(Some("end_turn"), "Here is the answer."), None);
+        assert_eq!(detect(Some("max_tokens"), ""), None);
+    }
+
+    #[test]
+    fn short_replies_opening_with_a_refusal_are_flagged() {
+        let r = detect(Some("end_turn"), "I’m sorry, but I can’t help with that request.").unwrap();
+        assert_eq!(r.signal, RefusalSignal::Heuristic);
+        assert!(detect(None, "Sorry — I cannot assist with creating malware.").is_some());
+        assert_eq!(detect(None, "Sure! Here's how to reverse a string in Rust."), None);
+    }
+
+    #[test]
+    fn long_answers_and_late_mentions_are_not_refusals() {
+        let long = format!("I can't provide live prices, but here is how to fetch them.\n{}", "step\n".repeat(400));
+        assert_eq!(detect(None, &long), None);
+        let late = format!("{} Note: I can't help with legal advice.", "Detailed answer. ".repeat(20));
+        assert_eq!(detect(None, &late), None);
+    }
+}
@@ -0,0 +1,283 @@
+// ClaudeHydra v4 -- Per-request generation overrides
+// Handlers resolve request-level settings (the selected agent's stop sequences,
+// the caller's own ones) into a `RequestScope` and run the request inside it.
+// `handlers::send_to_anthropic` applies the active scope to every outgoing
+// Messages API body, so the shared streaming / tool-loop code in jaskier-core
+// picks the settings up without knowing about them.
+// A detached stream (`stream_relay::detach`) also puts a side channel into
+// the scope, so code running inside the tool loop can `emit` extra frames.
+// The scope also holds the request's upstream concurrency slot (see
+// `priority`), taken on the first Anthropic call and released with the scope.
+// Prompt-assembly and routing code records what it decided (`decide`): model
+// routing, prompt layers and truncation, tier downgrades, retries. The log is
+// stored with the reply's generation context (see message_context).
+// Soft limits crossed on the way (see soft_limits) are collected as warnings
+// and attached to the reply. Prompt canaries (see prompt_canary) record the
+// variant the request was served with, for its usage events.
+
+use std::future::Future;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Instant;
+
+use serde_json::{Value, json};
+use tokio::sync::mpsc;
+
+/// Anthropic rejects requests with more stop sequences than this.
+pub const MAX_STOP_SEQUENCES: usize = 8;
+
+/// Out-of-band output for the stream the scope belongs to.
+#[derive(Debug)]
+pub enum StreamEvent {
+    /// A complete NDJSON frame (no trailing newline).
+    Frame(String),
+    /// Still working; resets the stall watchdog without writing anything.
+    KeepAlive,
+}
+
+/// Decisions taken while building and sending one request, in order, and
+/// the last upstream request they led to.
+#[derive(Debug, Clone, Default)]
+pub struct DecisionLog {
+    entries: Arc<Mutex<Vec<Value>>>,
+    /// Snapshot of the latest upstream request (see `message_context::snapshot`).
+    request: Arc<Mutex<Option<Value>>>,
+}
+
+impl DecisionLog {
+    /// Append `{"step": step, ...detail}` (`detail` is an object).
+    pub fn record(&self, step: &str, detail: Value) {
+        let mut entry = json!({ "step": step });
+        if let (Some(entry), Value::Object(detail)) = (entry.as_object_mut(), detail) {
+            entry.extend(detail);
+        }
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.push(entry);
+        }
+    }
+
+    pub fn entries(&self) -> Vec<Value> {
+        self.entries.lock().map(|e| e.clone()).unwrap_or_default()
+    }
+
+    pub fn set_request(&self, body: &Value) {
+        if let Ok(mut request) = self.request.lock() {
+            *request = Some(crate::message_context::snapshot(body));
+        }
+    }
+
+    pub fn request(&self) -> Option<Value> {
+        self.request.lock().ok().and_then(|r| r.clone())
+    }
+}
+
+/// Soft-limit warnings of one request, one per limit (the latest reading wins).
+#[derive(Debug, Clone, Default)]
+pub struct Warnings(Arc<Mutex<Vec<(String, Value)>>>);
+
+impl Warnings {
+    /// Add the warning for limit `key`, replacing an earlier one.
+    pub fn set(&self, key: String, warning: Value) {
+        if let Ok(mut warnings) = self.0.lock() {
+            match warnings.iter_mut().find(|(k, _)| *k == key) {
+                Some(existing) => existing.1 = warning,
+                None => warnings.push((key, warning)),
+            }
+        }
+    }
+
+    pub fn entries(&self) -> Vec<Value> {
+        self.0
+            .lock()
+            .map(|w| w.iter().map(|(_, v)| v.clone()).collect())
+            .unwrap_or_default()
+    }
+}
+
+/// Prompt canary variants a request was served with.
+#[derive(Debug, Clone, Default)]
+pub struct PromptVariants(Arc<Mutex<Vec<crate::prompt_canary::Arm>>>);
+
+impl PromptVariants {
+    pub fn push(&self, arm: crate::prompt_canary::Arm) {
+        if let Ok(mut arms) = self.0.lock()
+            && !arms.contains(&arm)
+        {
+            arms.push(arm);
+        }
+    }
+
+    pub fn entries(&self) -> Vec<crate::prompt_canary::Arm> {
+        self.0.lock().map(|a| a.clone()).unwrap_or_default()
+    }
+}
+
+/// Settings merged into every Anthropic request made while the scope is active.
+#[derive(Debug, Clone, Default)]
+pub struct RequestScope {
+    pub stop_sequences: Vec<String>,
+    /// Side channel into the response stream, when there is one.
+    pub events: Option<mpsc::UnboundedSender<StreamEvent>>,
+    /// Transcript recording this run; filled in once the handler has resolved
+    /// the context (see `transcripts::attach`).
+    pub transcript: OnceLock<Arc<crate::transcripts::Transcript>>,
+    /// When the request came in (stream setup time, see stream_throughput).
+    pub received_at: Option<Instant>,
+    /// Class used when waiting for an upstream slot.
+    pub priority: crate::priority::Priority,
+    /// Upstream slot held for the rest of the request.
+    pub permit: OnceLock<Arc<crate::priority::Permit>>,
+    /// Key environment picked by the request (see `key_environments`), kept
+    /// here so detached streams still use it.
+    pub key_environment: Option<String>,
+    /// Who the request is attributed to in usage events (see `principal`).
+    pub principal: Option<String>,
+    /// Crash-recovery journal entry of the operation, removed when the last
+    /// holder of the scope (e.g. a detached stream) is done.
+    pub journal: Option<Arc<crate::recovery::JournalEntry>>,
+    /// Keeps the selected agent busy while any holder of the scope is alive.
+    pub busy: Option<Arc<crate::agent_status::Busy>>,
+    /// Prompt-assembly / routing decisions of the request (see `decide`).
+    pub decisions: DecisionLog,
+    /// Soft limits the request crossed (see `warn`).
+    pub warnings: Warnings,
+    /// Prompt canary variants picked for the request (see `prompt_canary`).
+    pub prompt_variants: PromptVariants,
+}
+
+impl RequestScope {
+    pub fn is_empty(&self) -> bool {
+        self.stop_sequences.is_empty()
+    }
+}
+
+tokio::task_local! {
+    static SCOPE: Arc<RequestScope>;
+}
+
+/// The scope of the current task, if any.
+pub fn current() -> Option<Arc<RequestScope>> {
+    SCOPE.try_with(Arc::clone).ok()
+}
+
+/// Run `fut` with `scope` active.
+pub async fn run<F: Future>(scope: Arc<RequestScope>, fut: F) -> F::Output {
+    SCOPE.scope(scope, fut).await
+}
+
+/// Send `event` to the current scope's stream. Returns `false` when nothing
+/// is listening.
+pub fn emit(event: StreamEvent) -> bool {
+    current()
+        .and_then(|s| s.events.clone())
+        .is_some_and(|tx| tx.send(event).is_ok())
+}
+
+/// Record a decision in the current scope's log (no-op outside a scope).
+pub fn decide(step: &str, detail: Value) {
+    if let Some(scope) = current() {
+        scope.decisions.record(step, detail);
+    }
+}
+
+/// Record a soft-limit warning for limit `key` (no-op outside a scope).
+pub fn warn(key: String, warning: Value) {
+    if let Some(scope) = current() {
+        scope.warnings.set(key, warning);
+    }
+}
+
+/// The current scope's soft-limit warnings.
+pub fn warnings() -> Vec<Value> {
+    current().map(|s| s.warnings.entries()).unwrap_or_default()
+}
+
+/// The current scope's decision log as a JSON array.
+pub fn decisions() -> Value {
+    Value::Array(current().map(|s| s.decisions.entries()).unwrap_or_default())
+}
+
+/// Merge stop sequences from several sources (agent, template, caller) in
+/// priority order: blanks and duplicates are dropped and the result is capped
+/// at `MAX_STOP_SEQUENCES`.
+pub fn merge_stop_sequences<'a>(sources: impl IntoIterator<Item = &'a [String]>) -> Vec<String> {
+    let mut merged: Vec<String> = Vec::new();
+    for seq in sources.into_iter().flatten() {
+        if seq.trim().is_empty() || merged.contains(seq) {
+            continue;
+        }
+        if merged.len() == MAX_STOP_SEQUENCES {
+            break;
+        }
+        merged.push(seq.clone());
+    }
+    merged
+}
+
+/// Apply the active scope to a Messages API body. Returns `None` when there is
+/// nothing to change, so callers can keep borrowing the original.
+pub fn apply(body: &Value) -> Option<Value> {
+    let scope = current().filter(|s| !s.is_empty())?;
+    let existing: Vec<String> = body
+        .get("stop_sequences")
+        .and_then(|v| serde_json::from_value(v.clone()).ok())
+        .unwrap_or_default();
+    let mut body = body.clone();
+    body["stop_sequences"] = json!(merge_stop_sequences([
+        scope.stop_sequences.as_slice(),
+        existing.as_slice(),
+    ]));
+    Some(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(v: &[&str]) -> Vec<String> {
+        v.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn stop_sequences_are_deduped_and_capped() {
+        let agent = strings(&["###END###", "  "]);
+        let user = strings(&["###END###", "\n\nHuman:"]);
+        assert_eq!(
+            merge_stop_sequences([agent.as_slice(), user.as_slice()]),
+            strings(&["###END###", "\n\nHuman:"])
+        );
+
+        let many: Vec<String> = (0..20).map(|i| format!("stop-{i}")).collect();
+        assert_eq!(merge_stop_sequences([many.as_slice()]).len(), MAX_STOP_SEQUENCES);
+    }
+
+    #[tokio::test]
+    async fn scope_is_applied_to_bodies() {
+        let body = json!({ "model": "m", "stop_sequences": ["user-stop"] });
+        assert!(apply(&body).is_none());
+
+        let scope = Arc::new(RequestScope {
+            stop_sequences: strings(&["###END###"]),
+            ..Default::default()
+        });
+        let applied = run(scope, async { apply(&body) }).await.unwrap();
+        assert_eq!(applied["stop_sequences"], json!(["###END###", "user-stop"]));
+    }
+
+    #[tokio::test]
+    async fn decisions_are_logged_in_order() {
+        decide("ignored", json!({}));
+        let scope = Arc::new(RequestScope::default());
+        run(scope.clone(), async {
+            decide("model", json!({ "rule": "caller", "model": "m" }));
+            decide("retry", json!({ "status": 529 }));
+        })
+        .await;
+        assert_eq!(
+            scope.decisions.entries(),
+            vec![
+                json!({ "step": "model", "rule": "caller", "model": "m" }),
+                json!({ "step": "retry", "status": 529 }),
+            ]
+        );
+    }
+}
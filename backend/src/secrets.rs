@@ -0,0 +1,251 @@
+// ClaudeHydra v4 -- Encrypted provider API keys
+// Keys set with `POST /api/settings/api-key` are kept in ch_api_keys encrypted
+// with AES-256-GCM (the provider name is bound as associated data), and loaded
+// back into the runtime key map at startup. `DELETE /api/settings/api-key/{provider}`
+// removes them.
+//
+// The master key never goes to the database: it comes from
+// CLAUDEHYDRA_SECRETS_KEY (base64, 32 bytes) or a key file —
+// CLAUDEHYDRA_SECRETS_KEY_FILE, default `~/.claudehydra/secrets.key`, created
+// with owner-only permissions on first use. Each row records the master key's
+// fingerprint, so rows written under another key are skipped instead of
+// failing to decrypt. Without a usable master key, keys stay in memory only.
+
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use aes_gcm::aead::{Aead, Payload};
+use aes_gcm::{Aes256Gcm, KeyInit, Nonce};
+use base64::Engine as _;
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+
+struct MasterKey {
+    key: [u8; KEY_LEN],
+    fingerprint: String,
+}
+
+fn fingerprint(key: &[u8; KEY_LEN]) -> String {
+    format!("{:x}", Sha256::digest(key))[..16].to_string()
+}
+
+/// Master key file path (used only when CLAUDEHYDRA_SECRETS_KEY is unset).
+pub fn key_file() -> Option<PathBuf> {
+    std::env::var("CLAUDEHYDRA_SECRETS_KEY_FILE")
+        .ok()
+        .filter(|p| !p.trim().is_empty())
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|h| h.join(".claudehydra").join("secrets.key")))
+}
+
+fn decode_key(encoded: &str) -> Option<[u8; KEY_LEN]> {
+    base64::engine::general_purpose::STANDARD
+        .decode(encoded.trim())
+        .ok()?
+        .try_into()
+        .ok()
+}
+
+/// Read the key file, or create it with a fresh random key.
+fn load_or_create_key_file(path: &PathBuf) -> Result<[u8; KEY_LEN], String> {
+    if let Ok(existing) = std::fs::read_to_string(path) {
+        return decode_key(&existing).ok_or_else(|| format!("{} is not a base64 32-byte key", path.display()));
+    }
+    let key: [u8; KEY_LEN] = rand::random();
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| format!("cannot create {}: {}", dir.display(), e))?;
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(path)
+        .map_err(|e| format!("cannot create {}: {}", path.display(), e))?;
+    std::io::Write::write_all(
+        &mut file,
+        base64::engine::general_purpose::STANDARD.encode(key).as_bytes(),
+    )
+    .map_err(|e| format!("cannot write {}: {}", path.display(), e))?;
+    tracing::info!("secrets: created master key at {}", path.display());
+    Ok(key)
+}
+
+fn master_key() -> Option<&'static MasterKey> {
+    static KEY: OnceLock<Option<MasterKey>> = OnceLock::new();
+    KEY.get_or_init(|| {
+        let key = match std::env::var("CLAUDEHYDRA_SECRETS_KEY") {
+            Ok(encoded) if !encoded.trim().is_empty() => match decode_key(&encoded) {
+                Some(key) => key,
+                None => {
+                    tracing::error!("secrets: CLAUDEHYDRA_SECRETS_KEY is not a base64 32-byte key");
+                    return None;
+                }
+            },
+            _ => {
+                let path = key_file()?;
+                match load_or_create_key_file(&path) {
+                    Ok(key) => key,
+                    Err(e) => {
+                        tracing::error!("secrets: {} — API keys will not be persisted", e);
+                        return None;
+                    }
+                }
+            }
+        };
+        Some(MasterKey {
+            fingerprint: fingerprint(&key),
+            key,
+        })
+    })
+    .as_ref()
+}
+
+/// Whether keys can be stored (a master key is available).
+pub fn available() -> bool {
+    master_key().is_some()
+}
+
+fn encrypt(key: &[u8; KEY_LEN], provider: &str, plaintext: &str) -> Result<(Vec<u8>, Vec<u8>), String> {
+    let cipher = Aes256Gcm::new_from_slice(key).map_err(|e| e.to_string())?;
+    let nonce: [u8; NONCE_LEN] = rand::random();
+    let ciphertext = cipher
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: plaintext.as_bytes(),
+                aad: provider.as_bytes(),
+            },
+        )
+        .map_err(|_| "encryption failed".to_string())?;
+    Ok((nonce.to_vec(), ciphertext))
+}
+
+fn decrypt(key: &[u8; KEY_LEN], provider: &str, nonce: &[u8], ciphertext: &[u8]) -> Option<String> {
+    if nonce.len() != NONCE_LEN {
+        return None;
+    }
+    let cipher = Aes256Gcm::new_from_slice(key).ok()?;
+    let plaintext = cipher
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad: provider.as_bytes(),
+            },
+        )
+        .ok()?;
+    String::from_utf8(plaintext).ok()
+}
+
+/// Encrypt and store a provider key (replacing any previous one).
+pub async fn store(db: &sqlx::PgPool, provider: &str, value: &str) -> Result<(), String> {
+    let master = master_key().ok_or("no secrets master key available")?;
+    let (nonce, ciphertext) = encrypt(&master.key, provider, value)?;
+    sqlx::query(
+        "INSERT INTO ch_api_keys (provider, nonce, ciphertext, key_fingerprint, updated_at) \
+         VALUES ($1, $2, $3, $4, NOW()) \
+         ON CONFLICT (provider) DO UPDATE SET nonce = $2, ciphertext = $3, key_fingerprint = $4, \
+             updated_at = NOW()",
+    )
+    .bind(provider)
+    .bind(&nonce)
+    .bind(&ciphertext)
+    .bind(&master.fingerprint)
+    .execute(db)
+    .await
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Remove a stored provider key. Returns whether a row existed.
+pub async fn delete(db: &sqlx::PgPool, provider: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM ch_api_keys WHERE provider = $1")
+        .bind(provider)
+        .execute(db)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// `provider → (created_at, updated_at)` of the stored keys.
+pub async fn timestamps(
+    db: &sqlx::PgPool,
+) -> Result<std::collections::HashMap<String, (DateTime<Utc>, DateTime<Utc>)>, sqlx::Error> {
+    let rows = sqlx::query_as::<_, (String, DateTime<Utc>, DateTime<Utc>)>(
+        "SELECT provider, created_at, updated_at FROM ch_api_keys",
+    )
+    .fetch_all(db)
+    .await?;
+    Ok(rows.into_iter().map(|(p, c, u)| (p, (c, u))).collect())
+}
+
+/// Decrypt every stored key that was written under the current master key.
+pub async fn load_all(db: &sqlx::PgPool) -> Vec<(String, String)> {
+    let Some(master) = master_key() else {
+        return Vec::new();
+    };
+    let rows = match sqlx::query_as::<_, (String, Vec<u8>, Vec<u8>, String)>(
+        "SELECT provider, nonce, ciphertext, key_fingerprint FROM ch_api_keys",
+    )
+    .fetch_all(db)
+    .await
+    {
+        Ok(rows) => rows,
+        Err(e) => {
+            tracing::warn!("secrets: failed to load stored API keys: {}", e);
+            return Vec::new();
+        }
+    };
+    let mut keys = Vec::new();
+    for (provider, nonce, ciphertext, key_fingerprint) in rows {
+        if key_fingerprint != master.fingerprint {
+            tracing::warn!("secrets: key for '{}' was stored under another master key; skipped", provider);
+            continue;
+        }
+        match decrypt(&master.key, &provider, &nonce, &ciphertext) {
+            Some(value) => keys.push((provider, value)),
+            None => tracing::warn!("secrets: key for '{}' failed to decrypt; skipped", provider),
+        }
+    }
+    keys
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_round_trip_and_are_bound_to_the_provider() {
+        let key = [7u8; KEY_LEN];
+        let (nonce, ciphertext) = encrypt(&key, "anthropic", "sk-ant-123").unwrap();
+        assert!(!ciphertext.windows(10).any(|w| w == b"sk-ant-123"));
+        assert_eq!(
+            decrypt(&key, "anthropic", &nonce, &ciphertext).as_deref(),
+            Some("sk-ant-123")
+        );
+        assert_eq!(decrypt(&key, "google", &nonce, &ciphertext), None);
+        assert_eq!(decrypt(&[8u8; KEY_LEN], "anthropic", &nonce, &ciphertext), None);
+    }
+
+    #[test]
+    fn nonces_are_fresh_per_encryption() {
+        let key = [1u8; KEY_LEN];
+        let (a, _) = encrypt(&key, "github", "ghp_x").unwrap();
+        let (b, _) = encrypt(&key, "github", "ghp_x").unwrap();
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn master_keys_must_be_32_bytes() {
+        let encoded = base64::engine::general_purpose::STANDARD.encode([3u8; KEY_LEN]);
+        assert_eq!(decode_key(&encoded), Some([3u8; KEY_LEN]));
+        assert_eq!(decode_key("c2hvcnQ="), None);
+        assert_eq!(fingerprint(&[3u8; KEY_LEN]).len(), 16);
+    }
+}
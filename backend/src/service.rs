@@ -0,0 +1,425 @@
+// ClaudeHydra v4 -- Service manager integration
+// Lets the backend run as a managed service that reports its own health:
+//   - systemd (`Type=notify`, `NOTIFY_SOCKET` set): `READY=1` once startup
+//     finished (model sync + prompt cache, same as /api/health/ready),
+//     `WATCHDOG=1` every half `WatchdogSec` while the database answers, so a
+//     hung backend gets restarted; `STOPPING=1` when shutdown starts.
+//   - Windows service (started with `--service`): registers a control
+//     handler; Stop / Shutdown run the regular graceful shutdown. The status
+//     is StartPending until ready, then Running, StopPending, Stopped.
+// Outside a service manager every call here is a no-op.
+//
+// The `auto_start` setting maps onto the manager: `systemctl enable|disable`
+// or `sc.exe config start= auto|demand` for `CH_SERVICE_NAME` (default
+// `claudehydra`; `CH_SERVICE_SCOPE=user` for a systemd user unit).
+// `GET /api/system/service` shows what was detected and the last result.
+
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use axum::Json;
+use axum::extract::State;
+use serde_json::{Value, json};
+use tokio::sync::Notify;
+
+use crate::state::AppState;
+
+const DEFAULT_SERVICE_NAME: &str = "claudehydra";
+const READY_POLL: Duration = Duration::from_millis(500);
+const HEALTH_TIMEOUT: Duration = Duration::from_secs(5);
+/// Windows: how long the SCM waits for the next StartPending check-point.
+#[cfg(windows)]
+const START_HINT: Duration = Duration::from_secs(20);
+
+/// Stop requested by the service manager.
+static STOP: Notify = Notify::const_new();
+static READY_SENT: AtomicBool = AtomicBool::new(false);
+static LAST_HEALTHY: AtomicBool = AtomicBool::new(false);
+/// Last `auto_start` applied and the outcome.
+static AUTO_START: Mutex<Option<(bool, Result<String, String>)>> = Mutex::new(None);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Manager {
+    Systemd,
+    WindowsService,
+    None,
+}
+
+impl Manager {
+    fn as_str(self) -> &'static str {
+        match self {
+            Manager::Systemd => "systemd",
+            Manager::WindowsService => "windows_service",
+            Manager::None => "none",
+        }
+    }
+}
+
+pub fn manager() -> Manager {
+    #[cfg(windows)]
+    if windows::running() {
+        return Manager::WindowsService;
+    }
+    if std::env::var_os("NOTIFY_SOCKET").is_some() {
+        Manager::Systemd
+    } else {
+        Manager::None
+    }
+}
+
+fn service_name() -> String {
+    std::env::var("CH_SERVICE_NAME")
+        .ok()
+        .map(|n| n.trim().to_string())
+        .filter(|n| !n.is_empty())
+        .unwrap_or_else(|| DEFAULT_SERVICE_NAME.to_string())
+}
+
+/// Systemd watchdog interval (`WATCHDOG_USEC`), if it is meant for us.
+fn watchdog_interval() -> Option<Duration> {
+    if let Ok(pid) = std::env::var("WATCHDOG_PID")
+        && pid.trim() != std::process::id().to_string()
+    {
+        return None;
+    }
+    let usec: u64 = std::env::var("WATCHDOG_USEC").ok()?.trim().parse().ok()?;
+    (usec > 0).then(|| Duration::from_micros(usec))
+}
+
+/// Send one `sd_notify` message; `false` when there is no socket.
+#[cfg(unix)]
+fn sd_notify(message: &str) -> bool {
+    use std::os::unix::net::UnixDatagram;
+
+    let Some(path) = std::env::var_os("NOTIFY_SOCKET") else {
+        return false;
+    };
+    let Ok(socket) = UnixDatagram::unbound() else {
+        return false;
+    };
+    let path = path.to_string_lossy();
+    let sent = match path.strip_prefix('@') {
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            std::os::unix::net::SocketAddr::from_abstract_name(name.as_bytes())
+                .and_then(|addr| socket.send_to_addr(message.as_bytes(), &addr))
+        }
+        #[cfg(not(target_os = "linux"))]
+        Some(_) => return false,
+        None => socket.send_to(message.as_bytes(), path.as_ref()),
+    };
+    if let Err(e) = sent {
+        tracing::warn!("service: sd_notify failed: {}", e);
+        return false;
+    }
+    true
+}
+
+#[cfg(not(unix))]
+fn sd_notify(_message: &str) -> bool {
+    false
+}
+
+/// Ready and the database answers.
+async fn healthy(state: &AppState) -> bool {
+    state.is_ready()
+        && tokio::time::timeout(HEALTH_TIMEOUT, sqlx::query("SELECT 1").execute(&state.db))
+            .await
+            .is_ok_and(|r| r.is_ok())
+}
+
+/// Windows: connect to the service control manager when started with
+/// `--service`. Call first thing in `main`.
+pub fn init() {
+    #[cfg(windows)]
+    if std::env::args().any(|a| a == "--service") {
+        windows::start_dispatcher();
+    }
+}
+
+/// Report readiness once startup finishes, then keep the watchdog fed.
+pub fn spawn(state: AppState) {
+    let manager = manager();
+    if manager == Manager::None {
+        return;
+    }
+    tokio::spawn(async move {
+        while !state.is_ready() {
+            tokio::time::sleep(READY_POLL).await;
+            // Each check-point tells the SCM startup is still progressing.
+            #[cfg(windows)]
+            if manager == Manager::WindowsService {
+                windows::report(windows::Status::StartPending);
+            }
+        }
+        match manager {
+            Manager::Systemd => {
+                sd_notify(&format!("READY=1\nSTATUS=Listening\nMAINPID={}", std::process::id()));
+            }
+            #[cfg(windows)]
+            Manager::WindowsService => windows::report(windows::Status::Running),
+            _ => {}
+        }
+        READY_SENT.store(true, Ordering::Relaxed);
+        tracing::info!("service: reported ready to {}", manager.as_str());
+
+        let Some(interval) = watchdog_interval().filter(|_| manager == Manager::Systemd) else {
+            return;
+        };
+        let mut ticker = tokio::time::interval(interval / 2);
+        loop {
+            ticker.tick().await;
+            let ok = healthy(&state).await;
+            let was_ok = LAST_HEALTHY.swap(ok, Ordering::Relaxed);
+            if ok {
+                sd_notify("WATCHDOG=1");
+                if !was_ok {
+                    sd_notify("STATUS=Listening");
+                }
+            } else if was_ok {
+                // Withholding WATCHDOG=1 lets systemd restart us if it lasts.
+                tracing::warn!("service: health check failed; watchdog not fed");
+                sd_notify("STATUS=Degraded: database not responding");
+            }
+        }
+    });
+}
+
+/// Resolves when the service manager asks the backend to stop.
+pub async fn stop_requested() {
+    STOP.notified().await;
+}
+
+/// Graceful shutdown started.
+pub fn stopping() {
+    match manager() {
+        Manager::Systemd => {
+            sd_notify("STOPPING=1");
+        }
+        #[cfg(windows)]
+        Manager::WindowsService => windows::report(windows::Status::StopPending),
+        _ => {}
+    }
+}
+
+/// The server has shut down (Windows: report Stopped).
+pub fn stopped() {
+    #[cfg(windows)]
+    if manager() == Manager::WindowsService {
+        windows::report(windows::Status::Stopped);
+    }
+}
+
+fn run_command(program: &str, args: &[&str]) -> Result<String, String> {
+    let output = std::process::Command::new(program)
+        .args(args)
+        .output()
+        .map_err(|e| format!("{} failed to start: {}", program, e))?;
+    let text = String::from_utf8_lossy(if output.status.success() {
+        &output.stdout
+    } else {
+        &output.stderr
+    })
+    .trim()
+    .to_string();
+    if output.status.success() {
+        Ok(format!("{} {}", program, args.join(" ")))
+    } else {
+        Err(format!("{} {}: {}", program, args.join(" "), text))
+    }
+}
+
+/// Make the service start with the machine (or not). No-op outside a service
+/// manager or when `enabled` was already applied.
+pub fn apply_auto_start(enabled: bool) {
+    let manager = manager();
+    if manager == Manager::None {
+        return;
+    }
+    if let Ok(last) = AUTO_START.lock()
+        && matches!(&*last, Some((applied, Ok(_))) if *applied == enabled)
+    {
+        return;
+    }
+    tokio::task::spawn_blocking(move || {
+        let name = service_name();
+        let result = match manager {
+            Manager::Systemd => {
+                let unit = if name.contains('.') { name } else { format!("{}.service", name) };
+                let action = if enabled { "enable" } else { "disable" };
+                if std::env::var("CH_SERVICE_SCOPE").is_ok_and(|s| s.eq_ignore_ascii_case("user")) {
+                    run_command("systemctl", &["--user", action, &unit])
+                } else {
+                    run_command("systemctl", &[action, &unit])
+                }
+            }
+            Manager::WindowsService => {
+                let start = if enabled { "auto" } else { "demand" };
+                run_command("sc.exe", &["config", &name, "start=", start])
+            }
+            Manager::None => return,
+        };
+        match &result {
+            Ok(cmd) => tracing::info!("service: auto_start={} applied ({})", enabled, cmd),
+            Err(e) => tracing::warn!("service: failed to apply auto_start={}: {}", enabled, e),
+        }
+        if let Ok(mut last) = AUTO_START.lock() {
+            *last = Some((enabled, result));
+        }
+    });
+}
+
+/// `GET /api/system/service` — service manager integration status
+#[utoipa::path(get, path = "/api/system/service", tag = "system",
+    responses((status = 200, description = "Detected service manager, readiness and auto_start state")))]
+pub async fn service_status(State(state): State<AppState>) -> Json<Value> {
+    let auto_start_setting: Option<bool> =
+        sqlx::query_scalar("SELECT auto_start FROM ch_settings WHERE id = 1")
+            .fetch_optional(&state.db)
+            .await
+            .ok()
+            .flatten();
+    let applied = AUTO_START.lock().ok().and_then(|last| {
+        last.as_ref().map(|(enabled, result)| match result {
+            Ok(command) => json!({ "auto_start": enabled, "ok": true, "command": command }),
+            Err(error) => json!({ "auto_start": enabled, "ok": false, "error": error }),
+        })
+    });
+    let manager = manager();
+    Json(json!({
+        "manager": manager.as_str(),
+        "service_name": (manager != Manager::None).then(service_name),
+        "ready_reported": READY_SENT.load(Ordering::Relaxed),
+        "watchdog_interval_ms": watchdog_interval()
+            .filter(|_| manager == Manager::Systemd)
+            .map(|d| d.as_millis() as u64),
+        "healthy": LAST_HEALTHY.load(Ordering::Relaxed) || healthy(&state).await,
+        "auto_start": auto_start_setting,
+        "auto_start_applied": applied,
+    }))
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::ffi::OsString;
+    use std::sync::OnceLock;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::time::Duration;
+
+    use windows_service::service::{
+        ServiceControl, ServiceControlAccept, ServiceExitCode, ServiceState, ServiceStatus,
+        ServiceType,
+    };
+    use windows_service::service_control_handler::{self, ServiceControlHandlerResult, ServiceStatusHandle};
+    use windows_service::{define_windows_service, service_dispatcher};
+
+    pub enum Status {
+        StartPending,
+        Running,
+        StopPending,
+        Stopped,
+    }
+
+    static HANDLE: OnceLock<ServiceStatusHandle> = OnceLock::new();
+    static CHECKPOINT: AtomicU32 = AtomicU32::new(0);
+    /// Released when Stopped was reported, so `service_main` can return.
+    static DONE: OnceLock<std::sync::mpsc::SyncSender<()>> = OnceLock::new();
+
+    define_windows_service!(ffi_service_main, service_main);
+
+    pub fn running() -> bool {
+        HANDLE.get().is_some()
+    }
+
+    /// Run the dispatcher on a thread of its own (it blocks until the
+    /// service stops) and wait briefly for the control handler.
+    pub fn start_dispatcher() {
+        std::thread::spawn(|| {
+            if let Err(e) = service_dispatcher::start(super::service_name(), ffi_service_main) {
+                tracing::error!("service: not started by the service control manager: {}", e);
+            }
+        });
+        for _ in 0..50 {
+            if running() {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+    }
+
+    fn service_main(_args: Vec<OsString>) {
+        let handler = |control| match control {
+            ServiceControl::Stop | ServiceControl::Shutdown => {
+                super::STOP.notify_one();
+                ServiceControlHandlerResult::NoError
+            }
+            ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+            _ => ServiceControlHandlerResult::NotImplemented,
+        };
+        let handle = match service_control_handler::register(super::service_name(), handler) {
+            Ok(handle) => handle,
+            Err(e) => {
+                tracing::error!("service: failed to register control handler: {}", e);
+                return;
+            }
+        };
+        let (tx, rx) = std::sync::mpsc::sync_channel(1);
+        let _ = DONE.set(tx);
+        let _ = HANDLE.set(handle);
+        report(Status::StartPending);
+        let _ = rx.recv();
+    }
+
+    pub fn report(status: Status) {
+        let Some(handle) = HANDLE.get() else {
+            return;
+        };
+        let (current_state, controls_accepted, wait_hint) = match status {
+            Status::StartPending => (ServiceState::StartPending, ServiceControlAccept::empty(), super::START_HINT),
+            Status::Running => (
+                ServiceState::Running,
+                ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN,
+                Duration::ZERO,
+            ),
+            Status::StopPending => (ServiceState::StopPending, ServiceControlAccept::empty(), Duration::from_secs(30)),
+            Status::Stopped => (ServiceState::Stopped, ServiceControlAccept::empty(), Duration::ZERO),
+        };
+        let checkpoint = match current_state {
+            ServiceState::StartPending | ServiceState::StopPending => CHECKPOINT.fetch_add(1, Ordering::Relaxed) + 1,
+            _ => 0,
+        };
+        let result = handle.set_service_status(ServiceStatus {
+            service_type: ServiceType::OWN_PROCESS,
+            current_state,
+            controls_accepted,
+            exit_code: ServiceExitCode::Win32(0),
+            checkpoint,
+            wait_hint,
+            process_id: None,
+        });
+        if let Err(e) = result {
+            tracing::warn!("service: failed to report status: {}", e);
+        }
+        if matches!(current_state, ServiceState::Stopped)
+            && let Some(done) = DONE.get()
+        {
+            let _ = done.try_send(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_manager_without_a_notify_socket() {
+        // The test runner is not started by systemd or the Windows SCM.
+        if std::env::var_os("NOTIFY_SOCKET").is_none() {
+            assert_eq!(manager(), Manager::None);
+            assert!(!sd_notify("READY=1"));
+        }
+    }
+}
@@ -0,0 +1,464 @@
+// ClaudeHydra v4 -- Shared sessions: presence and live events
+// Several people can work in one session at once. Each open session view
+// connects to `GET /ws/sessions/{id}` and gets:
+//   - `presence` — who is connected and whether they are viewing or typing,
+//     re-sent on every change (clients report `typing` / `viewing`),
+//   - `message_added` — every message appended to the session, with author,
+//   - `comments_changed` — a message's comment threads changed.
+//
+// Contributors append through `POST /api/sessions/{id}/append`, which is
+// conflict-safe: appends to a session are serialized with a row lock, and a
+// client that passes `after` (the last message it saw) gets 409 with the
+// messages it missed instead of silently interleaving with someone else.
+//
+// Presence is per replica (it lives in memory next to the sockets); on
+// multi-machine deployments shared sessions should be routed to one instance.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use axum::extract::ws::{Message as WsMessage, WebSocket, WebSocketUpgrade};
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use chrono::{DateTime, Utc};
+use futures_util::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tokio::sync::broadcast;
+
+use crate::state::AppState;
+use crate::web_session::Session;
+
+const CHANNEL_CAPACITY: usize = 64;
+const MAX_NAME_CHARS: usize = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Activity {
+    Viewing,
+    Typing,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Participant {
+    pub connection_id: uuid::Uuid,
+    pub name: String,
+    pub activity: Activity,
+    pub since: DateTime<Utc>,
+}
+
+/// Events fanned out to everyone connected to a session.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SessionEvent {
+    Presence { participants: Vec<Participant> },
+    MessageAdded { message: Value },
+    /// Comments on a message changed; clients refetch that message's threads.
+    CommentsChanged { message_id: uuid::Uuid },
+    /// This message and every later one were removed (regenerate).
+    HistoryTruncated { from_message_id: uuid::Uuid },
+}
+
+struct Room {
+    tx: broadcast::Sender<SessionEvent>,
+    participants: HashMap<uuid::Uuid, Participant>,
+}
+
+/// Per-replica registry of session rooms.
+#[derive(Default)]
+pub struct PresenceHub {
+    rooms: Mutex<HashMap<uuid::Uuid, Room>>,
+}
+
+impl PresenceHub {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    pub fn participants(&self, session_id: uuid::Uuid) -> Vec<Participant> {
+        let Ok(rooms) = self.rooms.lock() else {
+            return Vec::new();
+        };
+        let mut list: Vec<Participant> = rooms
+            .get(&session_id)
+            .map(|r| r.participants.values().cloned().collect())
+            .unwrap_or_default();
+        list.sort_by_key(|p| p.since);
+        list
+    }
+
+    /// Publish an event to a session's room (no-op when nobody is connected).
+    pub fn publish(&self, session_id: uuid::Uuid, event: SessionEvent) {
+        if let Ok(rooms) = self.rooms.lock()
+            && let Some(room) = rooms.get(&session_id)
+        {
+            let _ = room.tx.send(event);
+        }
+    }
+
+    fn join(
+        &self,
+        session_id: uuid::Uuid,
+        participant: Participant,
+    ) -> Option<broadcast::Receiver<SessionEvent>> {
+        let rx = {
+            let mut rooms = self.rooms.lock().ok()?;
+            let room = rooms.entry(session_id).or_insert_with(|| Room {
+                tx: broadcast::channel(CHANNEL_CAPACITY).0,
+                participants: HashMap::new(),
+            });
+            room.participants.insert(participant.connection_id, participant);
+            room.tx.subscribe()
+        };
+        self.announce(session_id);
+        Some(rx)
+    }
+
+    fn set_activity(&self, session_id: uuid::Uuid, connection_id: uuid::Uuid, activity: Activity) {
+        let changed = self.rooms.lock().ok().is_some_and(|mut rooms| {
+            rooms
+                .get_mut(&session_id)
+                .and_then(|r| r.participants.get_mut(&connection_id))
+                .filter(|p| p.activity != activity)
+                .map(|p| {
+                    p.activity = activity;
+                    p.since = Utc::now();
+                })
+                .is_some()
+        });
+        if changed {
+            self.announce(session_id);
+        }
+    }
+
+    fn leave(&self, session_id: uuid::Uuid, connection_id: uuid::Uuid) {
+        if let Ok(mut rooms) = self.rooms.lock()
+            && let Some(room) = rooms.get_mut(&session_id)
+        {
+            room.participants.remove(&connection_id);
+            if room.participants.is_empty() {
+                rooms.remove(&session_id);
+                return;
+            }
+        }
+        self.announce(session_id);
+    }
+
+    fn announce(&self, session_id: uuid::Uuid) {
+        let participants = self.participants(session_id);
+        self.publish(session_id, SessionEvent::Presence { participants });
+    }
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  GET /ws/sessions/{id}
+// ═══════════════════════════════════════════════════════════════════════
+
+#[derive(Debug, Deserialize)]
+pub struct PresenceQuery {
+    pub token: Option<String>,
+    /// Display name shown to other participants.
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+    Typing,
+    Viewing,
+    Ping,
+}
+
+fn display_name(requested: Option<&str>, connection_id: uuid::Uuid) -> String {
+    requested
+        .map(|n| n.trim().chars().take(MAX_NAME_CHARS).collect::<String>())
+        .filter(|n| !n.is_empty())
+        .unwrap_or_else(|| format!("Guest-{}", &connection_id.simple().to_string()[..4]))
+}
+
+/// `GET /ws/sessions/{id}` — presence + live message events for a session
+/// (auth: `?token=<secret>` or the web UI session cookie)
+pub async fn ws_session_events(
+    ws: WebSocketUpgrade,
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    Query(q): Query<PresenceQuery>,
+) -> Response {
+    let Ok(session_id) = id.parse::<uuid::Uuid>() else {
+        return (StatusCode::BAD_REQUEST, "Invalid session id").into_response();
+    };
+    let secret = state.auth_secret.as_deref();
+    let query_token = q.token.as_deref().map(|t| format!("token={}", t)).unwrap_or_default();
+    if !jaskier_core::auth::validate_ws_token(&query_token, secret)
+        && !crate::web_session::has_valid_session(&headers, secret)
+    {
+        return (StatusCode::UNAUTHORIZED, "Invalid or missing auth token").into_response();
+    }
+
+    let name = q.name;
+    ws.on_upgrade(move |socket| handle_socket(socket, state, session_id, name))
+}
+
+async fn handle_socket(socket: WebSocket, state: AppState, session_id: uuid::Uuid, name: Option<String>) {
+    let connection_id = uuid::Uuid::new_v4();
+    let participant = Participant {
+        connection_id,
+        name: display_name(name.as_deref(), connection_id),
+        activity: Activity::Viewing,
+        since: Utc::now(),
+    };
+    let Some(mut events) = state.presence.join(session_id, participant) else {
+        return;
+    };
+    let (mut sender, mut receiver) = socket.split();
+
+    // Our own join announcement is already queued on `events`.
+    let welcome = json!({ "type": "welcome", "connection_id": connection_id });
+    if sender.send(WsMessage::Text(welcome.to_string().into())).await.is_ok() {
+        loop {
+            tokio::select! {
+                event = events.recv() => match event {
+                    Ok(event) => {
+                        let Ok(text) = serde_json::to_string(&event) else { continue };
+                        if sender.send(WsMessage::Text(text.into())).await.is_err() {
+                            break;
+                        }
+                    }
+                    // A slow client missed events; the next presence update resyncs it.
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                msg = receiver.next() => match msg {
+                    Some(Ok(WsMessage::Text(text))) => {
+                        match serde_json::from_str::<ClientMessage>(&text) {
+                            Ok(ClientMessage::Typing) => {
+                                state.presence.set_activity(session_id, connection_id, Activity::Typing)
+                            }
+                            Ok(ClientMessage::Viewing) => {
+                                state.presence.set_activity(session_id, connection_id, Activity::Viewing)
+                            }
+                            Ok(ClientMessage::Ping) => {
+                                let pong = json!({ "type": "pong" }).to_string();
+                                if sender.send(WsMessage::Text(pong.into())).await.is_err() {
+                                    break;
+                                }
+                            }
+                            Err(_) => {}
+                        }
+                    }
+                    Some(Ok(WsMessage::Close(_))) | None | Some(Err(_)) => break,
+                    Some(Ok(_)) => {}
+                },
+            }
+        }
+    }
+
+    state.presence.leave(session_id, connection_id);
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  GET /api/sessions/{id}/presence
+// ═══════════════════════════════════════════════════════════════════════
+
+/// `GET /api/sessions/{id}/presence` — who is currently connected to a session
+pub async fn get_presence(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, StatusCode> {
+    let session_id: uuid::Uuid = id.parse().map_err(|_| StatusCode::BAD_REQUEST)?;
+    Ok(Json(json!({
+        "session_id": session_id,
+        "participants": state.presence.participants(session_id),
+    })))
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  POST /api/sessions/{id}/append
+// ═══════════════════════════════════════════════════════════════════════
+
+#[derive(Debug, Deserialize)]
+pub struct AppendMessageRequest {
+    pub role: String,
+    pub content: String,
+    pub model: Option<String>,
+    pub agent: Option<String>,
+    /// Display name for callers without a web session.
+    pub author: Option<String>,
+    /// Last message id the client has seen; a newer message causes 409.
+    pub after: Option<uuid::Uuid>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct AttributedMessage {
+    pub id: uuid::Uuid,
+    pub role: String,
+    pub content: String,
+    pub model: Option<String>,
+    pub agent: Option<String>,
+    pub author: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+pub(crate) const MESSAGE_COLUMNS: &str = "id, role, content, model, agent, author, created_at";
+
+fn bad_request(msg: &str) -> (StatusCode, Json<Value>) {
+    (StatusCode::BAD_REQUEST, Json(json!({ "error": msg })))
+}
+
+/// `POST /api/sessions/{id}/append` — attributed, conflict-safe message append
+pub async fn append_message(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    session: Option<Extension<Session>>,
+    Json(req): Json<AppendMessageRequest>,
+) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)> {
+    let session_id: uuid::Uuid = id.parse().map_err(|_| bad_request("Invalid session id"))?;
+    if !matches!(req.role.as_str(), "user" | "assistant") {
+        return Err(bad_request("role must be 'user' or 'assistant'"));
+    }
+    if req.content.trim().is_empty() || req.content.len() > crate::handlers::MAX_MESSAGE_LENGTH {
+        return Err(bad_request("content is empty or too long"));
+    }
+    // A signed-in web session identifies the author; the body can't override it.
+    let author = match session {
+        Some(Extension(s)) => Some(s.subject),
+        None => req
+            .author
+            .as_deref()
+            .map(|a| a.trim().chars().take(MAX_NAME_CHARS).collect::<String>())
+            .filter(|a| !a.is_empty()),
+    };
+
+    let db_err = |e: sqlx::Error| {
+        tracing::error!("Failed to append message: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": "Failed to append message" })),
+        )
+    };
+    let mut tx = state.db.begin().await.map_err(db_err)?;
+
+    // Serializes concurrent appends to the same session.
+    let locked = sqlx::query("SELECT 1 FROM ch_sessions WHERE id = $1 FOR UPDATE")
+        .bind(session_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(db_err)?;
+    if locked.is_none() {
+        return Err((StatusCode::NOT_FOUND, Json(json!({ "error": "Session not found" }))));
+    }
+
+    if let Some(after) = req.after {
+        let missed = sqlx::query_as::<_, AttributedMessage>(&format!(
+            "SELECT {} FROM ch_messages WHERE session_id = $1 AND created_at > \
+             (SELECT created_at FROM ch_messages WHERE id = $2 AND session_id = $1) \
+             ORDER BY created_at",
+            MESSAGE_COLUMNS
+        ))
+        .bind(session_id)
+        .bind(after)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(db_err)?;
+        if !missed.is_empty() {
+            return Err((
+                StatusCode::CONFLICT,
+                Json(json!({
+                    "error": "Session has newer messages — merge them and retry",
+                    "missed": missed,
+                })),
+            ));
+        }
+    }
+
+    let message = sqlx::query_as::<_, AttributedMessage>(&format!(
+        "INSERT INTO ch_messages (session_id, role, content, model, agent, author) \
+         VALUES ($1, $2, $3, $4, $5, $6) RETURNING {}",
+        MESSAGE_COLUMNS
+    ))
+    .bind(session_id)
+    .bind(&req.role)
+    .bind(&req.content)
+    .bind(&req.model)
+    .bind(&req.agent)
+    .bind(&author)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(db_err)?;
+
+    sqlx::query("UPDATE ch_sessions SET updated_at = NOW() WHERE id = $1")
+        .bind(session_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_err)?;
+    tx.commit().await.map_err(db_err)?;
+    crate::slack::mirror(&state, session_id, &req.role, &req.content, author.as_deref());
+
+    let message = json!(message);
+    state.presence.publish(
+        session_id,
+        SessionEvent::MessageAdded {
+            message: message.clone(),
+        },
+    );
+    Ok((StatusCode::CREATED, Json(message)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn participant(name: &str) -> Participant {
+        Participant {
+            connection_id: uuid::Uuid::new_v4(),
+            name: name.into(),
+            activity: Activity::Viewing,
+            since: Utc::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn presence_tracks_joins_activity_and_leaves() {
+        let hub = PresenceHub::new();
+        let session = uuid::Uuid::new_v4();
+        let alice = participant("alice");
+        let alice_id = alice.connection_id;
+        let mut rx = hub.join(session, alice).unwrap();
+        let bob = participant("bob");
+        let bob_id = bob.connection_id;
+        hub.join(session, bob).unwrap();
+
+        hub.set_activity(session, bob_id, Activity::Typing);
+        let typing = hub
+            .participants(session)
+            .into_iter()
+            .find(|p| p.connection_id == bob_id)
+            .unwrap();
+        assert_eq!(typing.activity, Activity::Typing);
+
+        // alice saw: her own join, bob joining, bob typing
+        for expected in [1, 2, 2] {
+            match rx.recv().await {
+                Ok(SessionEvent::Presence { participants }) => assert_eq!(participants.len(), expected),
+                other => panic!("unexpected event: {:?}", other),
+            }
+        }
+
+        hub.leave(session, bob_id);
+        assert_eq!(hub.participants(session).len(), 1);
+        hub.leave(session, alice_id);
+        assert!(hub.participants(session).is_empty());
+        assert!(hub.rooms.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn display_names_are_trimmed_or_generated() {
+        let id = uuid::Uuid::new_v4();
+        assert_eq!(display_name(Some("  Yennefer "), id), "Yennefer");
+        assert!(display_name(Some("   "), id).starts_with("Guest-"));
+        assert_eq!(display_name(Some(&"x".repeat(100)), id).len(), MAX_NAME_CHARS);
+    }
+}
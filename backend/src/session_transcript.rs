@@ -0,0 +1,276 @@
+// ClaudeHydra v4 -- Session transcripts as Markdown / plain text
+// `GET /api/sessions/{id}` with `Accept: text/markdown` or `Accept: text/plain`
+// returns the conversation rendered as a transcript instead of JSON, so curl
+// users and scripts can read it directly. The route belongs to the shared
+// session router, so this is a middleware: the request goes through the normal
+// (authenticated) JSON handler and a successful JSON response is rendered.
+// `limit` / `offset` / `before` message pagination works as for the JSON
+// response.
+// The same rendering backs `GET /api/sessions/{id}/export?format=markdown`.
+
+use axum::extract::Request;
+use axum::http::{HeaderValue, Method, StatusCode, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use serde_json::Value;
+
+/// Session JSON larger than this is returned unrendered.
+const MAX_SESSION_JSON_BYTES: usize = 32 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TranscriptFormat {
+    Markdown,
+    Plain,
+}
+
+impl TranscriptFormat {
+    fn content_type(self) -> &'static str {
+        match self {
+            Self::Markdown => "text/markdown; charset=utf-8",
+            Self::Plain => "text/plain; charset=utf-8",
+        }
+    }
+}
+
+/// The transcript format the `Accept` header prefers over JSON, if any.
+/// Highest `q` wins; on a tie the earlier media range wins, and `*/*` or
+/// `application/*` count as JSON.
+pub fn negotiated_format(accept: &str) -> Option<TranscriptFormat> {
+    let mut best: Option<(Option<TranscriptFormat>, f32)> = None;
+    for range in accept.split(',') {
+        let mut parts = range.split(';').map(str::trim);
+        let media = parts.next().unwrap_or("").to_ascii_lowercase();
+        let q = parts
+            .find_map(|p| p.strip_prefix("q="))
+            .and_then(|q| q.parse::<f32>().ok())
+            .unwrap_or(1.0);
+        let format = match media.as_str() {
+            "text/markdown" | "text/x-markdown" => Some(TranscriptFormat::Markdown),
+            "text/plain" => Some(TranscriptFormat::Plain),
+            "application/json" | "application/*" | "*/*" => None,
+            _ => continue,
+        };
+        if q > 0.0 && best.is_none_or(|(_, best_q)| q > best_q) {
+            best = Some((format, q));
+        }
+    }
+    best.and_then(|(format, _)| format)
+}
+
+/// `/api/sessions/{uuid}` exactly — not `/list`, `/search` or sub-resources.
+fn is_session_detail(path: &str) -> bool {
+    path.strip_prefix("/api/sessions/")
+        .is_some_and(|id| id.parse::<uuid::Uuid>().is_ok())
+}
+
+/// Middleware: render session detail responses when the client asks for text.
+pub async fn negotiate(mut req: Request, next: Next) -> Response {
+    let format = (req.method() == Method::GET && is_session_detail(req.uri().path()))
+        .then(|| req.headers().get(header::ACCEPT)?.to_str().ok())
+        .flatten()
+        .and_then(negotiated_format);
+    let Some(format) = format else {
+        return next.run(req).await;
+    };
+    // Ask the inner handler for plain JSON — an encoded body cannot be rendered.
+    req.headers_mut().remove(header::ACCEPT_ENCODING);
+    req.headers_mut()
+        .insert(header::ACCEPT, HeaderValue::from_static("application/json"));
+
+    let response = next.run(req).await;
+    if response.status() != StatusCode::OK {
+        return response;
+    }
+    let (parts, body) = response.into_parts();
+    let bytes = match axum::body::to_bytes(body, MAX_SESSION_JSON_BYTES).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            tracing::warn!("session_transcript: failed to read session body: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    let Ok(session) = serde_json::from_slice::<Value>(&bytes) else {
+        return Response::from_parts(parts, axum::body::Body::from(bytes));
+    };
+    (
+        [
+            (header::CONTENT_TYPE, format.content_type()),
+            (header::VARY, "Accept"),
+        ],
+        render(&session, format),
+    )
+        .into_response()
+}
+
+fn role_label(role: &str) -> String {
+    let mut chars = role.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => "Unknown".to_string(),
+    }
+}
+
+/// Render a session detail JSON object (`title`, `messages[]`, `pagination`).
+pub fn render(session: &Value, format: TranscriptFormat) -> String {
+    let messages = session["messages"].as_array().map(Vec::as_slice).unwrap_or(&[]);
+    let mut out = render_header(session, format);
+    for msg in messages {
+        out.push_str(&render_message(msg, format));
+    }
+
+    let total = session["pagination"]["total"].as_u64();
+    if let Some(total) = total.filter(|t| *t > messages.len() as u64) {
+        let rest = match session["pagination"]["next_before"].as_str() {
+            Some(before) => format!("?before={} for older ones", before),
+            None => "?limit= and ?offset= for the rest".to_string(),
+        };
+        let note = format!("Showing {} of {} messages — use {}.", messages.len(), total, rest);
+        match format {
+            TranscriptFormat::Markdown => out.push_str(&format!("\n---\n\n_{}_\n", note)),
+            TranscriptFormat::Plain => out.push_str(&format!("\n{}\n", note)),
+        }
+    }
+    out
+}
+
+/// The transcript's title block (`title`, `id`, `created_at` of the session).
+pub fn render_header(session: &Value, format: TranscriptFormat) -> String {
+    let title = session["title"]
+        .as_str()
+        .filter(|t| !t.trim().is_empty())
+        .unwrap_or("Untitled session");
+    let id = session["id"].as_str().unwrap_or("");
+    let created = session["created_at"].as_str().unwrap_or("");
+    match format {
+        TranscriptFormat::Markdown => format!("# {}\n\n_Session {} · created {}_\n", title, id, created),
+        TranscriptFormat::Plain => {
+            let underline = "=".repeat(title.chars().count());
+            format!("{}\n{}\nSession {} · created {}\n", title, underline, id, created)
+        }
+    }
+}
+
+/// One message of the transcript.
+pub fn render_message(msg: &Value, format: TranscriptFormat) -> String {
+    let mut out = String::new();
+    let role = role_label(msg["role"].as_str().unwrap_or(""));
+    let mut meta: Vec<String> = Vec::new();
+    if let Some(model) = msg["model"].as_str().filter(|m| !m.is_empty()) {
+        meta.push(model.to_string());
+    }
+    if let Some(agent) = msg["agent"].as_str().filter(|a| !a.is_empty()) {
+        meta.push(format!("agent: {}", agent));
+    }
+    if let Some(ts) = msg["timestamp"].as_str() {
+        meta.push(ts.to_string());
+    }
+    let meta = if meta.is_empty() {
+        String::new()
+    } else {
+        format!(" ({})", meta.join(", "))
+    };
+    let tools: Vec<&str> = msg["tool_interactions"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|t| t["tool_name"].as_str())
+        .collect();
+    let content = msg["content"].as_str().unwrap_or("").trim_end();
+    // Rendered diagrams, attached by the export (see `artifacts`).
+    let artifacts: Vec<(&str, &str)> = msg["artifacts"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|a| Some((a["id"].as_str()?, a["kind"].as_str().unwrap_or("diagram"))))
+        .collect();
+
+    match format {
+        TranscriptFormat::Markdown => {
+            out.push_str(&format!("\n## {}{}\n\n", role, meta));
+            for tool in &tools {
+                out.push_str(&format!("> Tool: `{}`\n", tool));
+            }
+            if !tools.is_empty() {
+                out.push('\n');
+            }
+            out.push_str(content);
+            out.push('\n');
+            for (id, kind) in &artifacts {
+                out.push_str(&format!("\n![{}](/api/artifacts/{}/render)\n", kind, id));
+            }
+        }
+        TranscriptFormat::Plain => {
+            out.push_str(&format!("\n[{}]{}\n", role, meta));
+            for tool in &tools {
+                out.push_str(&format!("(tool: {})\n", tool));
+            }
+            out.push_str(content);
+            out.push('\n');
+            for (id, kind) in &artifacts {
+                out.push_str(&format!("({} rendered: /api/artifacts/{}/render)\n", kind, id));
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn accept_header_negotiation() {
+        assert_eq!(negotiated_format("text/markdown"), Some(TranscriptFormat::Markdown));
+        assert_eq!(negotiated_format("text/plain"), Some(TranscriptFormat::Plain));
+        assert_eq!(negotiated_format("application/json"), None);
+        assert_eq!(negotiated_format("*/*"), None);
+        assert_eq!(negotiated_format("application/json, text/markdown"), None);
+        assert_eq!(
+            negotiated_format("application/json;q=0.5, text/plain;q=0.9"),
+            Some(TranscriptFormat::Plain)
+        );
+        assert_eq!(negotiated_format("text/markdown;q=0"), None);
+        assert_eq!(negotiated_format("text/html"), None);
+    }
+
+    #[test]
+    fn only_session_detail_paths_are_rendered() {
+        assert!(is_session_detail("/api/sessions/7f8e2a1c-0b5d-4a57-9a43-3e1f0f5c2b11"));
+        assert!(!is_session_detail("/api/sessions/list"));
+        assert!(!is_session_detail("/api/sessions/7f8e2a1c-0b5d-4a57-9a43-3e1f0f5c2b11/messages"));
+    }
+
+    #[test]
+    fn renders_markdown_and_plain_transcripts() {
+        let session = json!({
+            "id": "s1",
+            "title": "Rust help",
+            "created_at": "2026-10-01T10:00:00Z",
+            "messages": [
+                { "role": "user", "content": "How do I read a file?", "timestamp": "2026-10-01T10:00:01Z" },
+                {
+                    "role": "assistant", "content": "Use `std::fs::read_to_string`.\n",
+                    "model": "claude-sonnet-4-6", "agent": "Geralt", "timestamp": "2026-10-01T10:00:05Z",
+                    "tool_interactions": [{ "tool_name": "read_file" }],
+                    "artifacts": [{ "id": "a1", "kind": "mermaid" }]
+                }
+            ],
+            "pagination": { "total": 3, "limit": 2, "offset": 0, "next_before": "m0" }
+        });
+        let md = render(&session, TranscriptFormat::Markdown);
+        assert!(md.starts_with("# Rust help\n"));
+        assert!(md.contains("\n## User (2026-10-01T10:00:01Z)\n\nHow do I read a file?\n"));
+        assert!(md.contains(
+            "## Assistant (claude-sonnet-4-6, agent: Geralt, 2026-10-01T10:00:05Z)\n\n> Tool: `read_file`\n"
+        ));
+        assert!(md.contains("\n![mermaid](/api/artifacts/a1/render)\n"));
+        assert!(md.contains("Showing 2 of 3 messages — use ?before=m0 for older ones."));
+
+        let plain = render(&session, TranscriptFormat::Plain);
+        assert!(plain.starts_with("Rust help\n=========\n"));
+        assert!(plain.contains("\n[User] (2026-10-01T10:00:01Z)\nHow do I read a file?\n"));
+        assert!(plain.contains("(tool: read_file)\n"));
+        assert!(plain.contains("(mermaid rendered: /api/artifacts/a1/render)\n"));
+    }
+}
@@ -0,0 +1,126 @@
+// ClaudeHydra v4 -- Cluster-wide chat rate limits
+// The per-endpoint governors (ch_rate_limits, applied by the shared router)
+// count in memory, so behind a load balancer each replica grants the full
+// limit. With CLUSTER_SYNC=1 this middleware also counts the chat groups in
+// Postgres (`ch_rate_windows`, one row per group, client and minute), so the
+// `requests_per_minute` of a group holds across all replicas:
+//   - chat_stream — POST …/chat/stream (Claude, Gemini, session chat)
+//   - chat        — POST /api/claude/chat, /api/gemini/chat,
+//                   /api/sessions/{id}/chat, /api/agents/{id}/chat
+// Clients are keyed like the governors: by peer IP (`Fly-Client-IP` behind
+// Fly's proxy). Counting fails open — a database error lets the request
+// through. Other groups stay per-replica.
+
+use std::net::SocketAddr;
+
+use axum::Json;
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::{HeaderValue, Method, StatusCode, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use serde_json::json;
+
+use crate::state::AppState;
+
+/// Rate limit group of a request, if it is one of the shared ones.
+fn group(method: &Method, path: &str) -> Option<&'static str> {
+    if method != Method::POST {
+        return None;
+    }
+    if path.starts_with("/api/") && path.ends_with("/chat/stream") {
+        return Some("chat_stream");
+    }
+    let entity_chat = |prefix: &str| {
+        path.strip_prefix(prefix)
+            .and_then(|rest| rest.strip_suffix("/chat"))
+            .is_some_and(|id| !id.is_empty() && !id.contains('/'))
+    };
+    (path == "/api/claude/chat"
+        || path == "/api/gemini/chat"
+        || entity_chat("/api/sessions/")
+        || entity_chat("/api/agents/"))
+    .then_some("chat")
+}
+
+fn client_key(req: &Request) -> Option<String> {
+    if let Some(ip) = req.headers().get("fly-client-ip").and_then(|v| v.to_str().ok()) {
+        return Some(ip.trim().to_string());
+    }
+    req.extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip().to_string())
+}
+
+/// Count the request in this minute's window; `Some(limit)` when it is over.
+async fn over_limit(db: &sqlx::PgPool, group: &str, client: &str) -> Option<i32> {
+    let counted = sqlx::query_as::<_, (i32, Option<i32>)>(
+        "INSERT INTO ch_rate_windows (endpoint_group, client, window_start, hits) \
+         VALUES ($1, $2, date_trunc('minute', NOW()), 1) \
+         ON CONFLICT (endpoint_group, client, window_start) \
+         DO UPDATE SET hits = ch_rate_windows.hits + 1 \
+         RETURNING hits, (SELECT requests_per_minute FROM ch_rate_limits \
+                          WHERE endpoint_group = $1 AND enabled IS NOT FALSE)",
+    )
+    .bind(group)
+    .bind(client)
+    .fetch_one(db)
+    .await;
+    match counted {
+        Ok((hits, Some(limit))) if hits > limit => Some(limit),
+        Ok(_) => None,
+        Err(e) => {
+            tracing::warn!("shared_rate_limits: counting failed, allowing request: {}", e);
+            None
+        }
+    }
+}
+
+/// Middleware: 429 once a client used up a chat group's limit on all replicas.
+pub async fn enforce(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    if !crate::cluster::enabled() {
+        return next.run(req).await;
+    }
+    let Some(group) = group(req.method(), req.uri().path()) else {
+        return next.run(req).await;
+    };
+    let Some(client) = client_key(&req) else {
+        return next.run(req).await;
+    };
+    let Some(limit) = over_limit(&state.db, group, &client).await else {
+        return next.run(req).await;
+    };
+
+    let retry_after = 60 - chrono::Utc::now().timestamp().rem_euclid(60);
+    tracing::info!("shared_rate_limits: {} over the {} limit ({}/min)", client, group, limit);
+    let mut response = (
+        StatusCode::TOO_MANY_REQUESTS,
+        Json(json!({
+            "error": format!("Rate limit of {} requests per minute exceeded", limit),
+            "code": "rate_limited",
+            "group": group,
+        })),
+    )
+        .into_response();
+    if let Ok(v) = HeaderValue::from_str(&retry_after.to_string()) {
+        response.headers_mut().insert(header::RETRY_AFTER, v);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chat_paths_map_to_groups() {
+        let post = Method::POST;
+        assert_eq!(group(&post, "/api/claude/chat/stream"), Some("chat_stream"));
+        assert_eq!(group(&post, "/api/sessions/abc/chat/stream"), Some("chat_stream"));
+        assert_eq!(group(&post, "/api/gemini/chat"), Some("chat"));
+        assert_eq!(group(&post, "/api/sessions/abc/chat"), Some("chat"));
+        assert_eq!(group(&post, "/api/agents/agent-001/chat"), Some("chat"));
+        assert_eq!(group(&post, "/api/sessions/abc/messages/x/chat"), None);
+        assert_eq!(group(&post, "/api/sessions"), None);
+        assert_eq!(group(&Method::GET, "/api/claude/chat/stream/abc"), None);
+    }
+}
@@ -0,0 +1,662 @@
+// ClaudeHydra v4 -- Slack connector
+// A ready-made Slack integration on top of the agent runs and outbound events:
+//
+// - Bot token + signing secret are set with `PUT /api/integrations/slack`
+//   (checked against `auth.test`), or come from SLACK_BOT_TOKEN /
+//   SLACK_SIGNING_SECRET when nothing is stored.
+// - A Slack channel maps to a session and/or an agent
+//   (`PUT /api/integrations/slack/channels/{channel}`).
+// - Slack → ClaudeHydra: the Events API posts to `/api/integrations/slack/events`
+//   (verified with the signing secret). Messages in a mapped channel are added
+//   to the session, and when an agent is mapped it runs the message as a task
+//   (see handlers/agent_run.rs) and answers in the message's thread.
+// - ClaudeHydra → Slack: messages appended to the mapped session from the web
+//   UI (`/api/sessions/{id}/append`) are mirrored to the channel, and events a
+//   channel follows (`agent.run_completed`, `usage.anomaly`, … — the outbound
+//   webhook events, see webhooks.rs) are posted as short summaries.
+//
+// Inbound events are rejected while the `webhooks` subsystem is paused.
+
+use axum::Json;
+use axum::body::Bytes;
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::crypto::{ct_eq, hmac_sha256};
+use crate::state::AppState;
+
+const SLACK_API: &str = "https://slack.com/api";
+/// Slack's own replay window for signed requests.
+const SIGNATURE_WINDOW_SECS: i64 = 300;
+/// Slack rejects longer `text`; longer content is cut.
+const MAX_TEXT_CHARS: usize = 3_900;
+
+// ═══════════════════════════════════════════════════════════════════════
+//  Configuration
+// ═══════════════════════════════════════════════════════════════════════
+
+#[derive(Debug, Clone)]
+struct SlackConfig {
+    bot_token: String,
+    signing_secret: Option<String>,
+    bot_user_id: Option<String>,
+    /// `database` or `env`.
+    source: &'static str,
+}
+
+async fn load_config(db: &sqlx::PgPool) -> Option<SlackConfig> {
+    let row = sqlx::query_as::<_, (String, Option<String>, Option<String>)>(
+        "SELECT bot_token, signing_secret, bot_user_id FROM ch_slack_config WHERE id = 1",
+    )
+    .fetch_optional(db)
+    .await
+    .ok()
+    .flatten();
+    if let Some((bot_token, signing_secret, bot_user_id)) = row {
+        return Some(SlackConfig {
+            bot_token,
+            signing_secret,
+            bot_user_id,
+            source: "database",
+        });
+    }
+    let env = |key: &str| std::env::var(key).ok().filter(|v| !v.trim().is_empty());
+    env("SLACK_BOT_TOKEN").map(|bot_token| SlackConfig {
+        bot_token,
+        signing_secret: env("SLACK_SIGNING_SECRET"),
+        bot_user_id: None,
+        source: "env",
+    })
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct ChannelMapping {
+    pub channel_id: String,
+    pub session_id: Option<uuid::Uuid>,
+    pub agent_id: Option<String>,
+    /// Outbound events posted to the channel (`*` = all).
+    pub events: Vec<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+const CHANNEL_COLUMNS: &str = "channel_id, session_id, agent_id, events, created_at";
+
+// ═══════════════════════════════════════════════════════════════════════
+//  Slack Web API
+// ═══════════════════════════════════════════════════════════════════════
+
+/// Call a Slack Web API method; Slack reports failures as `ok: false`.
+async fn call(state: &AppState, token: &str, method: &str, body: &Value) -> Result<Value, String> {
+    let resp = state
+        .http_client
+        .post(format!("{}/{}", SLACK_API, method))
+        .bearer_auth(token)
+        .json(body)
+        .timeout(std::time::Duration::from_secs(15))
+        .send()
+        .await
+        .map_err(|e| format!("Slack request failed: {}", e))?;
+    let json: Value = resp
+        .json()
+        .await
+        .map_err(|e| format!("Invalid Slack response: {}", e))?;
+    if json["ok"].as_bool() != Some(true) {
+        return Err(format!(
+            "Slack {} failed: {}",
+            method,
+            json["error"].as_str().unwrap_or("unknown error")
+        ));
+    }
+    Ok(json)
+}
+
+async fn post_message(
+    state: &AppState,
+    cfg: &SlackConfig,
+    channel: &str,
+    text: &str,
+    thread_ts: Option<&str>,
+) -> Result<(), String> {
+    let mut body = json!({ "channel": channel, "text": escape(&truncate(text)) });
+    if let Some(ts) = thread_ts {
+        body["thread_ts"] = json!(ts);
+    }
+    call(state, &cfg.bot_token, "chat.postMessage", &body).await.map(|_| ())
+}
+
+/// Escape the three characters Slack treats as markup.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+fn truncate(text: &str) -> String {
+    if text.chars().count() <= MAX_TEXT_CHARS {
+        return text.to_string();
+    }
+    let cut: String = text.chars().take(MAX_TEXT_CHARS).collect();
+    format!("{}… (truncated)", cut)
+}
+
+/// Check `X-Slack-Signature` (`v0=<hex HMAC-SHA256(secret, "v0:<ts>:<body>")>`).
+fn verify_signature(secret: &str, timestamp: &str, body: &[u8], signature: &str, now: i64) -> bool {
+    let Ok(ts) = timestamp.parse::<i64>() else {
+        return false;
+    };
+    if (now - ts).abs() > SIGNATURE_WINDOW_SECS {
+        return false;
+    }
+    let mut msg = format!("v0:{}:", timestamp).into_bytes();
+    msg.extend_from_slice(body);
+    ct_eq(signature, &format!("v0={}", hmac_sha256(secret.as_bytes(), &msg)))
+}
+
+/// One-line Slack summary of an outbound event.
+fn summarize_event(event: &str, data: &Value) -> String {
+    match event {
+        "agent.run_completed" => format!(
+            ":white_check_mark: *{}* finished a run ({}, {} iterations, {} tool calls, {:.1}s)\n{}",
+            data["agent"]["name"].as_str().unwrap_or("Agent"),
+            data["stop_reason"].as_str().unwrap_or("done"),
+            data["iterations"].as_u64().unwrap_or(0),
+            data["tool_calls"].as_u64().unwrap_or(0),
+            data["duration_ms"].as_u64().unwrap_or(0) as f64 / 1000.0,
+            data["answer_preview"].as_str().unwrap_or(""),
+        ),
+        "usage.anomaly" => format!(
+            ":warning: Usage anomaly: {} ({})",
+            data["kind"].as_str().unwrap_or("unknown"),
+            data["subject"].as_str().unwrap_or("-"),
+        ),
+        "health.probe_failed" => format!(
+            ":red_circle: Health check failed: {}{}",
+            data["component"].as_str().unwrap_or("unknown"),
+            data["detail"]
+                .as_str()
+                .map(|d| format!(" — {}", d))
+                .unwrap_or_default(),
+        ),
+        other => format!("ClaudeHydra event `{}`", other),
+    }
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  ClaudeHydra → Slack
+// ═══════════════════════════════════════════════════════════════════════
+
+/// Post `event` to the channels following it (called by `webhooks::dispatch`).
+pub async fn notify(state: &AppState, event: &str, data: &Value) {
+    let channels: Vec<String> = match sqlx::query_scalar(
+        "SELECT channel_id FROM ch_slack_channels \
+         WHERE events @> ARRAY[$1] OR events @> ARRAY['*']",
+    )
+    .bind(event)
+    .fetch_all(&state.db)
+    .await
+    {
+        Ok(c) => c,
+        Err(e) => {
+            tracing::debug!("slack: failed to load channels for {}: {}", event, e);
+            return;
+        }
+    };
+    if channels.is_empty() {
+        return;
+    }
+    let Some(cfg) = load_config(&state.db).await else {
+        return;
+    };
+    let text = summarize_event(event, data);
+    for channel in channels {
+        if let Err(e) = post_message(state, &cfg, &channel, &text, None).await {
+            tracing::warn!("slack: {} notification to {} failed: {}", event, channel, e);
+        }
+    }
+}
+
+/// Mirror a message appended to a session to the channels mapped to it.
+pub fn mirror(state: &AppState, session_id: uuid::Uuid, role: &str, content: &str, author: Option<&str>) {
+    let state = state.clone();
+    let speaker = match (role, author) {
+        ("assistant", _) => "Assistant".to_string(),
+        (_, Some(author)) => author.to_string(),
+        (role, None) => role.to_string(),
+    };
+    let text = format!("*{}*: {}", speaker, content);
+    tokio::spawn(async move {
+        let channels: Vec<String> =
+            sqlx::query_scalar("SELECT channel_id FROM ch_slack_channels WHERE session_id = $1")
+                .bind(session_id)
+                .fetch_all(&state.db)
+                .await
+                .unwrap_or_default();
+        if channels.is_empty() {
+            return;
+        }
+        let Some(cfg) = load_config(&state.db).await else {
+            return;
+        };
+        for channel in channels {
+            if let Err(e) = post_message(&state, &cfg, &channel, &text, None).await {
+                tracing::warn!("slack: mirroring to {} failed: {}", channel, e);
+            }
+        }
+    });
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  Slack → ClaudeHydra
+// ═══════════════════════════════════════════════════════════════════════
+
+#[derive(Debug, Deserialize)]
+struct MessageEvent {
+    #[serde(rename = "type")]
+    kind: String,
+    channel: Option<String>,
+    user: Option<String>,
+    text: Option<String>,
+    ts: Option<String>,
+    thread_ts: Option<String>,
+    bot_id: Option<String>,
+    subtype: Option<String>,
+}
+
+/// `POST /api/integrations/slack/events` — Slack Events API receiver
+pub async fn slack_events(State(state): State<AppState>, headers: HeaderMap, body: Bytes) -> Response {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).unwrap_or("");
+    let Some(cfg) = load_config(&state.db).await else {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({ "error": "Slack is not configured" })),
+        )
+            .into_response();
+    };
+    let Some(secret) = cfg.signing_secret.as_deref() else {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({ "error": "Slack signing secret is not configured" })),
+        )
+            .into_response();
+    };
+    if !verify_signature(
+        secret,
+        header("x-slack-request-timestamp"),
+        &body,
+        header("x-slack-signature"),
+        chrono::Utc::now().timestamp(),
+    ) {
+        return (StatusCode::UNAUTHORIZED, Json(json!({ "error": "Invalid Slack signature" }))).into_response();
+    }
+
+    let Ok(payload) = serde_json::from_slice::<Value>(&body) else {
+        return (StatusCode::BAD_REQUEST, Json(json!({ "error": "Invalid JSON" }))).into_response();
+    };
+    match payload["type"].as_str() {
+        Some("url_verification") => {
+            return Json(json!({ "challenge": payload["challenge"] })).into_response();
+        }
+        Some("event_callback") => {}
+        _ => return StatusCode::OK.into_response(),
+    }
+    // Slack retries when we don't ack within 3s; the first delivery is
+    // already being handled.
+    if !header("x-slack-retry-num").is_empty() {
+        return StatusCode::OK.into_response();
+    }
+
+    if let Ok(event) = serde_json::from_value::<MessageEvent>(payload["event"].clone())
+        && event.kind == "message"
+        && event.bot_id.is_none()
+        && event.subtype.is_none()
+        && event.user.is_some()
+        && event.user != cfg.bot_user_id
+    {
+        tokio::spawn(handle_message(state, cfg, event));
+    }
+    StatusCode::OK.into_response()
+}
+
+async fn handle_message(state: AppState, cfg: SlackConfig, event: MessageEvent) {
+    let (Some(channel), Some(text), Some(ts)) = (event.channel, event.text, event.ts) else {
+        return;
+    };
+    let text = text.trim();
+    if text.is_empty() {
+        return;
+    }
+    let mapping = match sqlx::query_as::<_, ChannelMapping>(&format!(
+        "SELECT {} FROM ch_slack_channels WHERE channel_id = $1",
+        CHANNEL_COLUMNS
+    ))
+    .bind(&channel)
+    .fetch_optional(&state.db)
+    .await
+    {
+        Ok(Some(m)) => m,
+        Ok(None) => return,
+        Err(e) => {
+            tracing::warn!("slack: failed to load mapping for {}: {}", channel, e);
+            return;
+        }
+    };
+    let author = format!("slack:{}", event.user.unwrap_or_default());
+    if let Some(session_id) = mapping.session_id {
+        store_message(&state, session_id, "user", text, None, None, Some(&author)).await;
+    }
+    let Some(agent_id) = mapping.agent_id else {
+        return;
+    };
+
+    let thread = event.thread_ts.unwrap_or(ts);
+    let req = crate::handlers::AgentRunRequest {
+        prompt: text.to_string(),
+        max_iterations: None,
+        tools: None,
+        working_directory: None,
+    };
+    match crate::handlers::agent_run::execute_run(&state, &agent_id, &req, Default::default()).await {
+        Ok(run) => {
+            let answer = run["answer"].as_str().unwrap_or("").trim();
+            let answer = if answer.is_empty() { "_(no answer)_" } else { answer };
+            if let Some(session_id) = mapping.session_id {
+                store_message(
+                    &state,
+                    session_id,
+                    "assistant",
+                    answer,
+                    run["model"].as_str(),
+                    run["agent"]["name"].as_str(),
+                    None,
+                )
+                .await;
+            }
+            if let Err(e) = post_message(&state, &cfg, &channel, answer, Some(&thread)).await {
+                tracing::warn!("slack: reply to {} failed: {}", channel, e);
+            }
+        }
+        Err((status, Json(err))) => {
+            let error = err["error"].as_str().unwrap_or("Agent run failed");
+            tracing::warn!("slack: agent run for {} failed ({}): {}", channel, status, error);
+            let _ = post_message(&state, &cfg, &channel, &format!(":x: {}", error), Some(&thread)).await;
+        }
+    }
+}
+
+async fn store_message(
+    state: &AppState,
+    session_id: uuid::Uuid,
+    role: &str,
+    content: &str,
+    model: Option<&str>,
+    agent: Option<&str>,
+    author: Option<&str>,
+) {
+    let result = sqlx::query(
+        "INSERT INTO ch_messages (session_id, role, content, model, agent, author) \
+         VALUES ($1, $2, $3, $4, $5, $6)",
+    )
+    .bind(session_id)
+    .bind(role)
+    .bind(content)
+    .bind(model)
+    .bind(agent)
+    .bind(author)
+    .execute(&state.db)
+    .await;
+    match result {
+        Ok(_) => {
+            let _ = sqlx::query("UPDATE ch_sessions SET updated_at = NOW() WHERE id = $1")
+                .bind(session_id)
+                .execute(&state.db)
+                .await;
+        }
+        Err(e) => tracing::error!("slack: failed to store message in {}: {}", session_id, e),
+    }
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  /api/integrations/slack
+// ═══════════════════════════════════════════════════════════════════════
+
+#[derive(Debug, Deserialize)]
+pub struct SlackConfigRequest {
+    /// Bot user OAuth token (`xoxb-…`).
+    pub bot_token: String,
+    /// Signing secret from the Slack app's Basic Information page.
+    #[serde(default)]
+    pub signing_secret: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ChannelMappingRequest {
+    #[serde(default)]
+    pub session_id: Option<uuid::Uuid>,
+    #[serde(default)]
+    pub agent_id: Option<String>,
+    #[serde(default)]
+    pub events: Vec<String>,
+}
+
+fn bad_request(msg: impl Into<String>) -> (StatusCode, Json<Value>) {
+    (StatusCode::BAD_REQUEST, Json(json!({ "error": msg.into() })))
+}
+
+fn db_error(e: sqlx::Error) -> (StatusCode, Json<Value>) {
+    tracing::error!("slack: query failed: {}", e);
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(json!({ "error": "Slack integration storage unavailable" })),
+    )
+}
+
+/// Slack conversation ids: `C…` (channel), `G…` (private), `D…` (DM).
+fn valid_channel_id(id: &str) -> bool {
+    (2..=32).contains(&id.len())
+        && id.starts_with(['C', 'G', 'D'])
+        && id.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+}
+
+fn validate_events(events: &[String]) -> Result<Vec<String>, String> {
+    let mut out: Vec<String> = Vec::new();
+    for e in events {
+        if e != "*" && !crate::webhooks::EVENTS.contains(&e.as_str()) {
+            return Err(format!("unknown event '{}'", e));
+        }
+        if !out.contains(e) {
+            out.push(e.clone());
+        }
+    }
+    Ok(out)
+}
+
+/// `GET /api/integrations/slack` — connection status and channel mappings (never the token)
+pub async fn slack_status(State(state): State<AppState>) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let cfg = load_config(&state.db).await;
+    let channels = sqlx::query_as::<_, ChannelMapping>(&format!(
+        "SELECT {} FROM ch_slack_channels ORDER BY created_at",
+        CHANNEL_COLUMNS
+    ))
+    .fetch_all(&state.db)
+    .await
+    .map_err(db_error)?;
+    let team: Option<String> = sqlx::query_scalar("SELECT team FROM ch_slack_config WHERE id = 1")
+        .fetch_optional(&state.db)
+        .await
+        .map_err(db_error)?
+        .flatten();
+    Ok(Json(json!({
+        "configured": cfg.is_some(),
+        "source": cfg.as_ref().map(|c| c.source),
+        "team": team,
+        "bot_user_id": cfg.as_ref().and_then(|c| c.bot_user_id.clone()),
+        "signing_secret_configured": cfg.as_ref().is_some_and(|c| c.signing_secret.is_some()),
+        "channels": channels,
+    })))
+}
+
+/// `PUT /api/integrations/slack` — store the bot token (verified with `auth.test`)
+pub async fn configure_slack(
+    State(state): State<AppState>,
+    Json(req): Json<SlackConfigRequest>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let token = req.bot_token.trim();
+    if !token.starts_with("xoxb-") {
+        return Err(bad_request("bot_token must be a bot token (xoxb-…)"));
+    }
+    let signing_secret = req
+        .signing_secret
+        .as_deref()
+        .map(str::trim)
+        .filter(|s| !s.is_empty());
+
+    let auth = call(&state, token, "auth.test", &json!({}))
+        .await
+        .map_err(|e| (StatusCode::BAD_GATEWAY, Json(json!({ "error": e }))))?;
+    let team = auth["team"].as_str();
+    let bot_user_id = auth["user_id"].as_str();
+
+    sqlx::query(
+        "INSERT INTO ch_slack_config (id, bot_token, signing_secret, team, bot_user_id, updated_at) \
+         VALUES (1, $1, $2, $3, $4, NOW()) \
+         ON CONFLICT (id) DO UPDATE SET bot_token = $1, \
+             signing_secret = COALESCE($2, ch_slack_config.signing_secret), \
+             team = $3, bot_user_id = $4, updated_at = NOW()",
+    )
+    .bind(token)
+    .bind(signing_secret)
+    .bind(team)
+    .bind(bot_user_id)
+    .execute(&state.db)
+    .await
+    .map_err(db_error)?;
+
+    crate::audit::log_audit(&state.db, "slack_configured", json!({ "team": team }), None).await;
+    Ok(Json(json!({ "configured": true, "team": team, "bot_user_id": bot_user_id })))
+}
+
+/// `DELETE /api/integrations/slack` — forget the stored token (mappings are kept)
+pub async fn disconnect_slack(State(state): State<AppState>) -> Result<StatusCode, (StatusCode, Json<Value>)> {
+    sqlx::query("DELETE FROM ch_slack_config WHERE id = 1")
+        .execute(&state.db)
+        .await
+        .map_err(db_error)?;
+    crate::audit::log_audit(&state.db, "slack_disconnected", json!({}), None).await;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// `PUT /api/integrations/slack/channels/{channel}` — map a channel to a session and/or agent
+pub async fn map_channel(
+    State(state): State<AppState>,
+    Path(channel): Path<String>,
+    Json(req): Json<ChannelMappingRequest>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    if !valid_channel_id(&channel) {
+        return Err(bad_request("Invalid Slack channel id"));
+    }
+    let events = validate_events(&req.events).map_err(bad_request)?;
+    let agent_id = req.agent_id.as_deref().map(str::trim).filter(|a| !a.is_empty());
+    if req.session_id.is_none() && agent_id.is_none() && events.is_empty() {
+        return Err(bad_request("Map the channel to a session, an agent, or at least one event"));
+    }
+    if let Some(agent_id) = agent_id
+        && !state.agents.read().await.iter().any(|a| a.id == agent_id)
+    {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": format!("Agent '{}' not found", agent_id) })),
+        ));
+    }
+    if let Some(session_id) = req.session_id {
+        let exists: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM ch_sessions WHERE id = $1)")
+            .bind(session_id)
+            .fetch_one(&state.db)
+            .await
+            .map_err(db_error)?;
+        if !exists {
+            return Err((StatusCode::NOT_FOUND, Json(json!({ "error": "Session not found" }))));
+        }
+    }
+
+    let mapping = sqlx::query_as::<_, ChannelMapping>(&format!(
+        "INSERT INTO ch_slack_channels (channel_id, session_id, agent_id, events) \
+         VALUES ($1, $2, $3, $4) \
+         ON CONFLICT (channel_id) DO UPDATE SET session_id = $2, agent_id = $3, events = $4 \
+         RETURNING {}",
+        CHANNEL_COLUMNS
+    ))
+    .bind(&channel)
+    .bind(req.session_id)
+    .bind(agent_id)
+    .bind(&events)
+    .fetch_one(&state.db)
+    .await
+    .map_err(db_error)?;
+    Ok(Json(json!(mapping)))
+}
+
+/// `DELETE /api/integrations/slack/channels/{channel}` — remove a channel mapping
+pub async fn unmap_channel(
+    State(state): State<AppState>,
+    Path(channel): Path<String>,
+) -> Result<StatusCode, (StatusCode, Json<Value>)> {
+    let result = sqlx::query("DELETE FROM ch_slack_channels WHERE channel_id = $1")
+        .bind(&channel)
+        .execute(&state.db)
+        .await
+        .map_err(db_error)?;
+    if result.rows_affected() == 0 {
+        return Err((StatusCode::NOT_FOUND, Json(json!({ "error": "Channel is not mapped" }))));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slack_signatures_are_checked_with_replay_window() {
+        let body = b"token=x&type=event_callback";
+        let msg = [b"v0:1700000000:".as_slice(), body].concat();
+        let sig = format!("v0={}", hmac_sha256(b"shh", &msg));
+        assert!(verify_signature("shh", "1700000000", body, &sig, 1_700_000_060));
+        assert!(!verify_signature("other", "1700000000", body, &sig, 1_700_000_060));
+        assert!(!verify_signature("shh", "1700000000", b"tampered", &sig, 1_700_000_060));
+        assert!(!verify_signature("shh", "1700000000", body, &sig, 1_700_000_000 + SIGNATURE_WINDOW_SECS + 1));
+        assert!(!verify_signature("shh", "soon", body, &sig, 1_700_000_000));
+    }
+
+    #[test]
+    fn channel_ids_and_events_are_validated() {
+        assert!(valid_channel_id("C0123ABCD"));
+        assert!(valid_channel_id("D9XYZ"));
+        assert!(!valid_channel_id("general"));
+        assert!(!valid_channel_id("C01-23"));
+        assert_eq!(
+            validate_events(&["agent.run_completed".into(), "agent.run_completed".into()]).unwrap(),
+            vec!["agent.run_completed"]
+        );
+        assert!(validate_events(&["chat.done".into()]).is_err());
+    }
+
+    #[test]
+    fn event_summaries_are_readable() {
+        let text = summarize_event(
+            "agent.run_completed",
+            &json!({
+                "agent": { "name": "Geralt" },
+                "stop_reason": "end_turn",
+                "iterations": 3,
+                "tool_calls": 2,
+                "duration_ms": 4200,
+                "answer_preview": "Done.",
+            }),
+        );
+        assert!(text.contains("*Geralt* finished a run (end_turn, 3 iterations, 2 tool calls, 4.2s)"));
+        assert!(text.ends_with("Done."));
+        assert_eq!(escape("a <b> & c"), "a &lt;b&gt; &amp; c");
+        assert!(truncate(&"x".repeat(MAX_TEXT_CHARS + 10)).ends_with("(truncated)"));
+    }
+}
@@ -0,0 +1,154 @@
+// ClaudeHydra v4 -- Soft limits
+// Warnings before a hard limit starts failing requests. A request that
+// crosses a configurable share of a limit is still served, but the reply
+// carries a `warnings` array — chat responses (`/api/claude/chat`, gemini,
+// session chat, regenerate), the final `done` frame of NDJSON streams and the
+// WebSocket `complete` message — so UIs can warn the user in time:
+//   - `tier_budget` — a tier's daily token budget (see tier_budgets),
+//     CH_SOFT_LIMIT_BUDGET_PCT (default 80),
+//   - `context`     — input plus `max_tokens` against the model's context
+//     window, CH_SOFT_LIMIT_CONTEXT_PCT (default 80),
+//   - `quota`       — spend caps and model token quotas (see quotas),
+//     CH_SOFT_LIMIT_QUOTA_PCT (default 80).
+// 0 turns a kind of warning off. Every Anthropic call is checked in
+// `send_to_anthropic`; warnings are collected in the request scope, one per
+// limit.
+
+use std::sync::OnceLock;
+
+use serde_json::{Value, json};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    TierBudget,
+    Context,
+    Quota,
+}
+
+impl Kind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Kind::TierBudget => "tier_budget",
+            Kind::Context => "context",
+            Kind::Quota => "quota",
+        }
+    }
+
+    fn env(self) -> &'static str {
+        match self {
+            Kind::TierBudget => "CH_SOFT_LIMIT_BUDGET_PCT",
+            Kind::Context => "CH_SOFT_LIMIT_CONTEXT_PCT",
+            Kind::Quota => "CH_SOFT_LIMIT_QUOTA_PCT",
+        }
+    }
+}
+
+const DEFAULT_PCT: f64 = 80.0;
+
+/// Share of a limit (0–1) above which `kind` warns; `None` when turned off.
+pub fn threshold(kind: Kind) -> Option<f64> {
+    static THRESHOLDS: OnceLock<[Option<f64>; 3]> = OnceLock::new();
+    let thresholds = THRESHOLDS.get_or_init(|| {
+        [Kind::TierBudget, Kind::Context, Kind::Quota].map(|k| {
+            let pct = std::env::var(k.env())
+                .ok()
+                .and_then(|v| v.trim().parse::<f64>().ok())
+                .filter(|p| p.is_finite())
+                .unwrap_or(DEFAULT_PCT)
+                .clamp(0.0, 100.0);
+            (pct > 0.0).then_some(pct / 100.0)
+        })
+    });
+    thresholds[kind as usize]
+}
+
+/// The warning for `used` of `limit`, if it is past the threshold (but not
+/// over the limit — that is the hard limit's business).
+fn warning(kind: Kind, threshold: Option<f64>, used: f64, limit: f64, message: &str, detail: Value) -> Option<Value> {
+    let threshold = threshold?;
+    if limit <= 0.0 || used < limit * threshold {
+        return None;
+    }
+    let mut warning = json!({
+        "kind": kind.as_str(),
+        "message": message,
+        "used": used,
+        "limit": limit,
+        "percent": (used * 1000.0 / limit).round() / 10.0,
+    });
+    if let (Some(warning), Value::Object(detail)) = (warning.as_object_mut(), detail) {
+        warning.extend(detail);
+    }
+    Some(warning)
+}
+
+/// Record a warning in the current request scope when `used` of `limit`
+/// crosses the `kind` threshold. `subject` tells limits of one kind apart
+/// (tier, model, quota name).
+pub fn check(kind: Kind, subject: &str, used: f64, limit: f64, message: &str, detail: Value) {
+    if let Some(warning) = warning(kind, threshold(kind), used, limit, message, detail) {
+        crate::request_scope::warn(format!("{}:{}", kind.as_str(), subject), warning);
+    }
+}
+
+/// Context window check of a Messages API body (local estimate).
+pub fn check_context(body: &Value) {
+    let Some(model) = body.get("model").and_then(|m| m.as_str()) else {
+        return;
+    };
+    let input = crate::token_count::estimate_body(body);
+    let max_tokens = body.get("max_tokens").and_then(|m| m.as_u64()).unwrap_or(0);
+    let window = crate::token_count::context_window(model);
+    check(
+        Kind::Context,
+        model,
+        (input + max_tokens) as f64,
+        window as f64,
+        "The conversation is close to the model's context window; consider compacting the session",
+        json!({ "model": model, "input_tokens": input, "max_tokens": max_tokens }),
+    );
+}
+
+/// NDJSON `done` frame with the current request's warnings added (`None`
+/// for any other line).
+pub fn done_frame_with_warnings(line: &[u8]) -> Option<Vec<u8>> {
+    let text = line.trim_ascii_end();
+    if !text.windows(11).any(|w| w == b"\"done\":true") {
+        return None;
+    }
+    let Ok(Value::Object(mut frame)) = serde_json::from_slice::<Value>(text) else {
+        return None;
+    };
+    if frame.get("done") != Some(&Value::Bool(true)) || frame.contains_key("warnings") {
+        return None;
+    }
+    frame.insert("warnings".to_string(), Value::Array(crate::request_scope::warnings()));
+    let mut out = Value::Object(frame).to_string().into_bytes();
+    out.extend_from_slice(&line[text.len()..]);
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn warnings_start_at_the_threshold() {
+        let w = |used| warning(Kind::Quota, Some(0.8), used, 100.0, "m", json!({ "quota": "daily_spend" }));
+        assert!(w(79.9).is_none());
+        let warned = w(84.0).unwrap();
+        assert_eq!(warned["kind"], "quota");
+        assert_eq!(warned["percent"], 84.0);
+        assert_eq!(warned["quota"], "daily_spend");
+        assert!(warning(Kind::Quota, None, 99.0, 100.0, "m", json!({})).is_none());
+    }
+
+    #[test]
+    fn only_done_frames_get_warnings() {
+        assert!(done_frame_with_warnings(br#"{"token":"a","done":false}"#).is_none());
+        let patched = done_frame_with_warnings(b"{\"token\":\"\",\"done\":true}\n").unwrap();
+        assert!(patched.ends_with(b"\n"));
+        let frame: Value = serde_json::from_slice(&patched).unwrap();
+        assert_eq!(frame["warnings"], json!([]));
+    }
+}
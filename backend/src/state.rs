@@ -27,11 +27,18 @@ use std::time::Instant;
 use crate::ai_gateway::{self, AiGatewayState, HasAiGateway};
 use crate::ai_gateway::vault_bridge::{HasVaultBridge, VaultClient};
 use crate::collab::CollabState;
+use crate::maintenance::MaintenanceState;
 use crate::memory_pruning::{HasMemoryPruning, MemoryPruningState};
 use crate::models::WitcherAgent;
+use crate::priority::PriorityGate;
 use crate::sandbox::{HasSandboxState, SandboxState};
 use crate::semantic_cache::{HasSemanticCache, SemanticCacheState};
+use crate::session_presence::PresenceHub;
+use crate::tool_confirmation::ToolConfirmations;
+use crate::stream_relay::StreamRelay;
+use crate::subsystems::SubsystemRegistry;
 use crate::swarm::SwarmState;
+use crate::tasks::TaskQueue;
 use crate::tools::ToolExecutor;
 
 // ── AppState ────────────────────────────────────────────────────────────────
@@ -82,6 +89,20 @@ pub struct AppState {
     pub sandbox: SandboxState,
     // ── Memory Pruning (Self-Reflection & Knowledge Graph cleanup) ──────
     pub memory_pruning: Arc<MemoryPruningState>,
+    // ── Maintenance (read-only mode toggled via /api/admin/read-only) ───
+    pub maintenance: Arc<MaintenanceState>,
+    // ── Subsystem pause/resume controls (/api/admin/subsystems) ─────────
+    pub subsystems: Arc<SubsystemRegistry>,
+    // ── Resumable chat streams (/api/claude/chat/stream/{stream_id}) ────
+    pub streams: Arc<StreamRelay>,
+    // ── Shared-session presence + live events (/ws/sessions/{id}) ───────
+    pub presence: Arc<PresenceHub>,
+    // ── Tool calls awaiting user confirmation (/api/tool-calls) ─────────
+    pub tool_confirmations: Arc<ToolConfirmations>,
+    // ── Upstream concurrency limit, queued by priority ──────────────────
+    pub priority_gate: Arc<PriorityGate>,
+    // ── Background agent runs and debates (/api/tasks) ──────────────────
+    pub tasks: Arc<TaskQueue>,
 }
 
 impl Deref for AppState {
@@ -106,11 +127,16 @@ impl AppState {
             mcp_tools_table: "ch_mcp_discovered_tools",
         }).await;
 
-        // ── Inject legacy key names for backward compatibility ──────
+        // ── Stored keys + legacy key names for backward compatibility ──
+        // Keys saved through the settings API (see secrets.rs) win over env.
         // BaseHydraState inserts as "anthropic" / "google", but CH handlers
         // look up "ANTHROPIC_API_KEY" / "GOOGLE_API_KEY" in runtime.api_keys.
         {
+            let stored = crate::secrets::load_all(&base.db).await;
             let mut rt = base.runtime.write().await;
+            for (provider, key) in stored {
+                rt.api_keys.insert(provider, key);
+            }
             if let Some(key) = rt.api_keys.get("anthropic").cloned() {
                 rt.api_keys.insert("ANTHROPIC_API_KEY".to_string(), key);
             }
@@ -177,6 +203,13 @@ impl AppState {
             semantic_cache,
             sandbox,
             memory_pruning: Arc::new(MemoryPruningState::new(&db).await),
+            maintenance: MaintenanceState::new(),
+            subsystems: SubsystemRegistry::new(),
+            streams: StreamRelay::new(),
+            presence: PresenceHub::new(),
+            tool_confirmations: ToolConfirmations::new(),
+            priority_gate: PriorityGate::from_env(),
+            tasks: TaskQueue::from_env(),
         }
     }
 
@@ -274,6 +307,13 @@ impl AppState {
             semantic_cache: Arc::new(SemanticCacheState::new_test()),
             sandbox: SandboxState::new(),
             memory_pruning: Arc::new(MemoryPruningState::new_test()),
+            maintenance: MaintenanceState::new(),
+            subsystems: SubsystemRegistry::new(),
+            streams: StreamRelay::new(),
+            presence: PresenceHub::new(),
+            tool_confirmations: ToolConfirmations::new(),
+            priority_gate: PriorityGate::from_env(),
+            tasks: TaskQueue::from_env(),
         }
     }
 }
@@ -391,6 +431,8 @@ impl jaskier_core::metrics::HasMetricsState for AppState {
         out.push_str(&self.semantic_cache.metrics.prometheus_output());
         // Memory pruning metrics
         out.push_str(&self.memory_pruning.metrics.prometheus_output());
+        // Chat stream throughput histograms
+        out.push_str(&crate::stream_throughput::prometheus_output());
         out
     }
 }
@@ -454,7 +496,7 @@ impl jaskier_core::sessions::HasSessionsState for AppState {
 
 impl jaskier_core::mcp::server::HasMcpServerState for AppState {
     fn mcp_server_name(&self) -> &'static str { "ClaudeHydra" }
-    fn mcp_server_version(&self) -> &'static str { "4.0.0" }
+    fn mcp_server_version(&self) -> &'static str { env!("CARGO_PKG_VERSION") }
     fn mcp_server_instructions(&self) -> &'static str {
         "ClaudeHydra AI Swarm Control Center — Anthropic Claude-powered multi-agent system"
     }
@@ -536,6 +578,9 @@ impl jaskier_core::sessions::HasAnthropicCredential for AppState {
     fn http_client(&self) -> &reqwest::Client { &self.http_client }
 
     async fn get_anthropic_credential(&self) -> Option<(String, bool)> {
+        if let Some(key) = crate::key_environments::environment_key(self, "anthropic").await {
+            return key.map(|k| (k, false));
+        }
         // 1. Try Vault first (ai_providers/anthropic_max)
         //    For this trait (title generation), we need the actual token.
         //    Vault.get() returns a masked credential — we can't use it directly.
@@ -590,7 +635,7 @@ impl jaskier_core::sessions::HasAnthropicCredential for AppState {
 /// when the table doesn't exist yet or is empty.
 async fn load_agents_from_db(db: &PgPool) -> Vec<WitcherAgent> {
     match sqlx::query_as::<_, crate::models::AgentConfigRow>(
-        "SELECT id, name, role, tier, status, description, model, created_at, updated_at \
+        "SELECT id, name, role, tier, status, description, model, stop_sequences, system_prompt, temperature, max_tokens, created_at, updated_at \
          FROM ch_agents_config ORDER BY id",
     )
     .fetch_all(db)
@@ -611,28 +656,23 @@ async fn load_agents_from_db(db: &PgPool) -> Vec<WitcherAgent> {
     }
 }
 
-fn model_for_tier(tier: &str) -> &'static str {
-    match tier {
-        "Commander" => "claude-opus-4-6",
-        "Coordinator" => "claude-sonnet-4-6",
-        "Executor" => "claude-haiku-4-5-20251001",
-        _ => "claude-sonnet-4-6",
-    }
-}
-
 /// Build default agent roster from shared jaskier-core list, converting to CH's
 /// local `WitcherAgent` type (which includes a `model` field based on tier).
 fn init_witcher_agents() -> Vec<WitcherAgent> {
     jaskier_core::models::default_agent_roster()
         .into_iter()
         .map(|shared| WitcherAgent {
-            model: model_for_tier(&shared.tier).to_string(),
+            model: crate::tier_models::agent_model(&shared.tier),
             id: shared.id,
             name: shared.name,
             role: shared.role,
             tier: shared.tier,
             status: shared.status,
             description: shared.description,
+            stop_sequences: Vec::new(),
+            system_prompt: String::new(),
+            temperature: None,
+            max_tokens: None,
         })
         .collect()
 }
@@ -0,0 +1,277 @@
+// ClaudeHydra v4 -- StatsD / DogStatsD metrics push exporter
+// For installs where nothing can scrape `/api/metrics` (a desktop app behind
+// NAT, a laptop), the same metrics are pushed over UDP to a StatsD server or
+// a Datadog agent. Off unless `STATSD_ADDR` is set; pausing the `statsd`
+// subsystem skips pushes until it is resumed.
+//
+// Every interval the Prometheus exposition text is converted line by line:
+// each sample becomes a gauge named `<prefix><metric>`, its labels become
+// DogStatsD tags next to the configured ones (`host`, `profile`, `STATSD_TAGS`).
+// Histogram buckets are skipped — `_sum` and `_count` are sent. Counters are
+// sent as their cumulative value; take the rate on the receiving side.
+//
+// Config:
+//   STATSD_ADDR=127.0.0.1:8125     target (unset = exporter off)
+//   STATSD_PREFIX=claudehydra.     metric name prefix
+//   STATSD_INTERVAL_SECS=10        push interval (min 1)
+//   STATSD_HOST=<hostname>         `host` tag (default HOSTNAME / COMPUTERNAME)
+//   STATSD_PROFILE=work            `profile` tag (omitted when unset)
+//   STATSD_TAGS=env:dev,team:ai    extra tags
+//   STATSD_PLAIN=1                 plain StatsD — no `|#tags` suffix
+
+use std::time::Duration;
+
+use jaskier_core::metrics::HasMetricsState;
+use tokio::net::UdpSocket;
+
+use crate::state::AppState;
+
+/// Keep datagrams under a typical MTU.
+const MAX_DATAGRAM_BYTES: usize = 1_400;
+
+#[derive(Debug, Clone)]
+pub struct StatsdConfig {
+    pub addr: String,
+    pub prefix: String,
+    pub interval: Duration,
+    /// `key:value` tags added to every metric.
+    pub tags: Vec<String>,
+    /// DogStatsD tag extension on (default) or plain StatsD.
+    pub dogstatsd: bool,
+}
+
+impl StatsdConfig {
+    /// `None` when `STATSD_ADDR` is unset.
+    pub fn from_env() -> Option<Self> {
+        fn var(key: &str) -> Option<String> {
+            std::env::var(key).ok().map(|v| v.trim().to_string()).filter(|v| !v.is_empty())
+        }
+        let addr = var("STATSD_ADDR")?;
+        let host = var("STATSD_HOST")
+            .or_else(|| var("HOSTNAME"))
+            .or_else(|| var("COMPUTERNAME"))
+            .unwrap_or_else(|| "localhost".to_string());
+        let mut tags = vec![format!("host:{}", sanitize_tag(&host))];
+        if let Some(profile) = var("STATSD_PROFILE") {
+            tags.push(format!("profile:{}", sanitize_tag(&profile)));
+        }
+        tags.extend(
+            var("STATSD_TAGS")
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|t| !t.is_empty())
+                .map(sanitize_tag),
+        );
+        Some(Self {
+            addr,
+            prefix: var("STATSD_PREFIX").unwrap_or_else(|| "claudehydra.".to_string()),
+            interval: Duration::from_secs(
+                var("STATSD_INTERVAL_SECS")
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(10u64)
+                    .max(1),
+            ),
+            tags,
+            dogstatsd: var("STATSD_PLAIN").is_none_or(|v| v == "0"),
+        })
+    }
+}
+
+/// DogStatsD tags cannot contain `|`, `,` or `#`; whitespace becomes `_`.
+fn sanitize_tag(tag: &str) -> String {
+    tag.chars()
+        .map(|c| match c {
+            '|' | ',' | '#' => '_',
+            c if c.is_whitespace() => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// One Prometheus sample line (`name{labels} value`) as a StatsD gauge.
+/// Comments, histogram buckets and non-finite values give `None`.
+pub fn sample_to_gauge(line: &str, cfg: &StatsdConfig) -> Option<String> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let (series, rest) = if line.contains('{') {
+        let close = line.rfind('}')?;
+        (&line[..close + 1], &line[close + 1..])
+    } else {
+        line.split_once(char::is_whitespace)?
+    };
+    // A trailing timestamp, if present, is ignored.
+    let value: f64 = rest.split_whitespace().next()?.parse().ok()?;
+    if !value.is_finite() {
+        return None;
+    }
+    let (name, labels) = match series.split_once('{') {
+        Some((name, labels)) => (name, labels.trim_end_matches('}')),
+        None => (series, ""),
+    };
+    if name.ends_with("_bucket") {
+        return None;
+    }
+
+    let mut out = format!("{}{}:{}|g", cfg.prefix, name, value);
+    if cfg.dogstatsd {
+        let mut tags = cfg.tags.clone();
+        tags.extend(parse_labels(labels).map(|(k, v)| sanitize_tag(&format!("{}:{}", k, v))));
+        if !tags.is_empty() {
+            out.push_str("|#");
+            out.push_str(&tags.join(","));
+        }
+    }
+    Some(out)
+}
+
+/// `a="x",b="y"` label pairs (escaped quotes inside values are kept as-is).
+fn parse_labels(labels: &str) -> impl Iterator<Item = (&str, &str)> {
+    let mut pairs = Vec::new();
+    let mut rest = labels;
+    while let Some((key, after)) = rest.split_once("=\"") {
+        let mut end = 0;
+        let bytes = after.as_bytes();
+        while end < bytes.len() && !(bytes[end] == b'"' && (end == 0 || bytes[end - 1] != b'\\')) {
+            end += 1;
+        }
+        pairs.push((key.trim_start_matches(',').trim(), &after[..end]));
+        rest = after.get(end + 1..).unwrap_or("");
+    }
+    pairs.into_iter()
+}
+
+/// Pack lines into newline-separated datagrams of at most `MAX_DATAGRAM_BYTES`.
+fn datagrams(lines: &[String]) -> Vec<String> {
+    let mut packets = Vec::new();
+    let mut current = String::new();
+    for line in lines {
+        if !current.is_empty() && current.len() + 1 + line.len() > MAX_DATAGRAM_BYTES {
+            packets.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push('\n');
+        }
+        current.push_str(line);
+    }
+    if !current.is_empty() {
+        packets.push(current);
+    }
+    packets
+}
+
+/// The Prometheus text CH contributes to `/api/metrics`, plus process gauges.
+async fn exposition(state: &AppState) -> String {
+    let snap = state.metrics_snapshot().await;
+    let mut out = format!(
+        concat!(
+            "process_uptime_seconds {}\n",
+            "system_cpu_usage_percent {}\n",
+            "system_memory_used_mb {}\n",
+            "system_memory_total_mb {}\n",
+        ),
+        state.metrics_start_time().elapsed().as_secs(),
+        snap.cpu_usage_percent,
+        snap.memory_used_mb,
+        snap.memory_total_mb,
+    );
+    out.push_str(&state.extra_metrics_lines().await);
+    out
+}
+
+/// Spawn the push loop when `STATSD_ADDR` is configured.
+pub fn spawn(state: AppState) -> Option<tokio::task::JoinHandle<()>> {
+    let cfg = StatsdConfig::from_env()?;
+    Some(tokio::spawn(async move {
+        let socket = match UdpSocket::bind("0.0.0.0:0").await {
+            Ok(s) => s,
+            Err(e) => {
+                tracing::error!("statsd: cannot open UDP socket: {}", e);
+                return;
+            }
+        };
+        if let Err(e) = socket.connect(&cfg.addr).await {
+            tracing::error!("statsd: cannot resolve {}: {}", cfg.addr, e);
+            return;
+        }
+        tracing::info!(
+            "statsd: pushing metrics to {} every {}s (tags: {})",
+            cfg.addr,
+            cfg.interval.as_secs(),
+            cfg.tags.join(",")
+        );
+        let mut interval = tokio::time::interval(cfg.interval);
+        let mut failing = false;
+        loop {
+            interval.tick().await;
+            if state.subsystems.is_paused(crate::subsystems::STATSD) {
+                tracing::debug!("statsd: push paused");
+                continue;
+            }
+            let text = exposition(&state).await;
+            let lines: Vec<String> = text
+                .lines()
+                .filter_map(|l| sample_to_gauge(l, &cfg))
+                .collect();
+            for packet in datagrams(&lines) {
+                match socket.send(packet.as_bytes()).await {
+                    Ok(_) => failing = false,
+                    // UDP errors (agent not running) are expected — log once per outage.
+                    Err(e) if !failing => {
+                        failing = true;
+                        tracing::warn!("statsd: send to {} failed: {}", cfg.addr, e);
+                        break;
+                    }
+                    Err(_) => break,
+                }
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cfg(dogstatsd: bool) -> StatsdConfig {
+        StatsdConfig {
+            addr: "127.0.0.1:8125".to_string(),
+            prefix: "claudehydra.".to_string(),
+            interval: Duration::from_secs(10),
+            tags: vec!["host:dev-box".to_string(), "profile:work".to_string()],
+            dogstatsd,
+        }
+    }
+
+    #[test]
+    fn samples_become_tagged_gauges() {
+        let line = r#"http_requests_total{method="GET",path="/api/health"} 42"#;
+        assert_eq!(
+            sample_to_gauge(line, &cfg(true)).as_deref(),
+            Some("claudehydra.http_requests_total:42|g|#host:dev-box,profile:work,method:GET,path:/api/health")
+        );
+        assert_eq!(
+            sample_to_gauge("process_uptime_seconds 12.5", &cfg(false)).as_deref(),
+            Some("claudehydra.process_uptime_seconds:12.5|g")
+        );
+    }
+
+    #[test]
+    fn comments_buckets_and_bad_values_are_skipped() {
+        assert_eq!(sample_to_gauge("# TYPE x counter", &cfg(true)), None);
+        assert_eq!(sample_to_gauge(r#"latency_bucket{le="0.5"} 3"#, &cfg(true)), None);
+        assert_eq!(sample_to_gauge("x NaN", &cfg(true)), None);
+        assert!(sample_to_gauge("latency_sum 1.25 1700000000000", &cfg(true)).is_some());
+    }
+
+    #[test]
+    fn datagrams_stay_under_the_mtu() {
+        let lines: Vec<String> = (0..100).map(|i| format!("claudehydra.metric_{}:1|g", i)).collect();
+        let packets = datagrams(&lines);
+        assert!(packets.len() > 1);
+        assert!(packets.iter().all(|p| p.len() <= MAX_DATAGRAM_BYTES));
+        assert_eq!(packets.iter().map(|p| p.lines().count()).sum::<usize>(), 100);
+    }
+}
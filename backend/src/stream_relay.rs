@@ -0,0 +1,860 @@
+// ClaudeHydra v4 -- Resumable NDJSON streams
+// Chat streams are detached from the HTTP connection: the generation keeps
+// running into an in-memory buffer, and the client reads from that buffer.
+// A dropped connection can reattach via
+// `GET /api/claude/chat/stream/{stream_id}?offset=<bytes received>`.
+//
+// Stream ids are `<uuid>.<instance id>`, so a reconnect that lands on another
+// replica knows who owns the buffer. With CLUSTER_SYNC that replica forwards
+// the resume to the owner's CLUSTER_INTERNAL_URL (see cluster) and relays the
+// stream, and a cancel is sent to the owner over the cluster event bus.
+// Otherwise, or when the owner is not registered, it answers 409 with a
+// `fly-replay: instance=<owner>` header (Fly's proxy transparently retries on
+// the owning machine) plus `X-Stream-Instance` for other load balancers.
+// Finished streams are kept for STREAM_RETENTION; after that the reply is
+// already persisted in the session history.
+//
+// Backpressure: every reader tracks how far it has consumed the buffer. When
+// the slowest live reader falls more than STREAM_BUFFER_HIGH_WATER_BYTES behind,
+// STREAM_BACKPRESSURE decides what happens:
+//   - `pause` (default): stop reading upstream until the reader catches up
+//     (TCP flow control slows the model down). A reader that makes no progress
+//     for the idle timeout is treated as stuck and the stream falls back to `drop`.
+//   - `drop`: keep reading, but coalesce consecutive token frames into one and
+//     flush it once the reader catches up, so frame overhead doesn't pile up.
+// Past STREAM_BUFFER_MAX_BYTES token text is dropped entirely; terminal and
+// non-token frames are always kept and a notice frame reports what was dropped.
+// The WebSocket path needs none of this: it awaits every send on the socket.
+//
+// `POST /api/claude/chat/stream/{stream_id}/cancel` aborts a running stream:
+// the upstream body is dropped (closing the Anthropic / Gemini connection and
+// any tool loop) and readers get a final `done` frame with `"cancelled": true`.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use axum::Json;
+use axum::body::{Body, Bytes};
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderMap, HeaderName, HeaderValue, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tokio::sync::{Notify, watch};
+use tokio_util::sync::CancellationToken;
+
+use crate::request_scope::StreamEvent;
+use crate::state::AppState;
+use crate::stream_throughput::{Meter, Outcome, StreamThroughput};
+use crate::stream_watchdog::{self, Next};
+
+const STREAM_RETENTION: Duration = Duration::from_secs(300);
+const DEFAULT_HIGH_WATER_BYTES: usize = 1024 * 1024;
+const DEFAULT_MAX_BUFFER_BYTES: usize = 8 * 1024 * 1024;
+/// How often a paused writer re-checks reader progress.
+const PAUSE_POLL: Duration = Duration::from_millis(500);
+
+pub const STREAM_ID_HEADER: &str = "x-stream-id";
+pub const STREAM_INSTANCE_HEADER: &str = "x-stream-instance";
+/// Set on resumes relayed by another replica.
+const FORWARDED_HEADER: &str = "x-cluster-forwarded";
+
+static TOTAL_PAUSED_MS: AtomicU64 = AtomicU64::new(0);
+static TOTAL_COALESCED_FRAMES: AtomicU64 = AtomicU64::new(0);
+static TOTAL_DROPPED_BYTES: AtomicU64 = AtomicU64::new(0);
+
+/// What to do when a live reader falls behind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackpressurePolicy {
+    Pause,
+    Drop,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct BufferLimits {
+    pub policy: BackpressurePolicy,
+    pub high_water_bytes: usize,
+    pub max_buffer_bytes: usize,
+}
+
+fn env_bytes(key: &str, default: usize) -> usize {
+    std::env::var(key)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(default)
+}
+
+/// Buffer limits from `STREAM_BACKPRESSURE`, `STREAM_BUFFER_HIGH_WATER_BYTES`
+/// and `STREAM_BUFFER_MAX_BYTES`.
+pub fn limits() -> BufferLimits {
+    static LIMITS: OnceLock<BufferLimits> = OnceLock::new();
+    *LIMITS.get_or_init(|| {
+        let policy = match std::env::var("STREAM_BACKPRESSURE").as_deref() {
+            Ok("drop") => BackpressurePolicy::Drop,
+            _ => BackpressurePolicy::Pause,
+        };
+        let max_buffer_bytes = env_bytes("STREAM_BUFFER_MAX_BYTES", DEFAULT_MAX_BUFFER_BYTES);
+        BufferLimits {
+            policy,
+            high_water_bytes: env_bytes("STREAM_BUFFER_HIGH_WATER_BYTES", DEFAULT_HIGH_WATER_BYTES)
+                .min(max_buffer_bytes),
+            max_buffer_bytes,
+        }
+    })
+}
+
+#[derive(Default)]
+struct StreamCounters {
+    paused_ms: AtomicU64,
+    coalesced_frames: AtomicU64,
+    dropped_bytes: AtomicU64,
+}
+
+struct BufferedStream {
+    data: Mutex<Vec<u8>>,
+    /// (bytes written, finished)
+    progress: watch::Sender<(usize, bool)>,
+    finished_at: Mutex<Option<Instant>>,
+    /// Consumed offset of every attached reader.
+    cursors: Mutex<HashMap<u64, usize>>,
+    next_cursor: AtomicU64,
+    /// Signalled whenever a reader consumes data or detaches.
+    consumed: Notify,
+    counters: StreamCounters,
+    /// Token throughput while the stream runs (see stream_throughput).
+    meter: Mutex<Option<Meter>>,
+    /// Cancelled by `cancel_stream`.
+    cancel: CancellationToken,
+}
+
+impl BufferedStream {
+    fn new() -> Self {
+        let (progress, _) = watch::channel((0usize, false));
+        Self {
+            data: Mutex::new(Vec::new()),
+            progress,
+            finished_at: Mutex::new(None),
+            cursors: Mutex::new(HashMap::new()),
+            next_cursor: AtomicU64::new(0),
+            consumed: Notify::new(),
+            counters: StreamCounters::default(),
+            meter: Mutex::new(None),
+            cancel: CancellationToken::new(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.data.lock().map(|d| d.len()).unwrap_or(0)
+    }
+
+    fn append(&self, bytes: &[u8]) -> usize {
+        match self.data.lock() {
+            Ok(mut data) => {
+                data.extend_from_slice(bytes);
+                data.len()
+            }
+            Err(_) => 0,
+        }
+    }
+
+    fn observe(&self, lines: &[u8]) {
+        if let Ok(mut meter) = self.meter.lock()
+            && let Some(m) = meter.as_mut()
+        {
+            m.observe(lines);
+        }
+    }
+
+    /// How far the slowest attached reader is behind, if anyone is attached.
+    fn lag(&self) -> Option<usize> {
+        let written = self.len();
+        let cursors = self.cursors.lock().ok()?;
+        cursors.values().min().map(|c| written.saturating_sub(*c))
+    }
+
+    fn attach(self: &Arc<Self>, offset: usize) -> Cursor {
+        let id = self.next_cursor.fetch_add(1, Ordering::Relaxed);
+        if let Ok(mut cursors) = self.cursors.lock() {
+            cursors.insert(id, offset);
+        }
+        Cursor {
+            stream: self.clone(),
+            id,
+        }
+    }
+
+    /// Wait (upstream reads paused) until the slowest reader is back under the
+    /// high-water mark. Returns false if readers stopped making progress.
+    async fn wait_for_readers(&self, high_water: usize) -> bool {
+        let started = Instant::now();
+        let mut last_progress = Instant::now();
+        let mut last_lag = usize::MAX;
+        let ok = loop {
+            let notified = self.consumed.notified();
+            let Some(lag) = self.lag().filter(|l| *l > high_water) else {
+                break true;
+            };
+            if lag < last_lag {
+                last_lag = lag;
+                last_progress = Instant::now();
+            } else if last_progress.elapsed() >= stream_watchdog::idle_timeout() {
+                break false;
+            }
+            let _ = tokio::time::timeout(PAUSE_POLL, notified).await;
+        };
+        let paused = started.elapsed().as_millis() as u64;
+        if paused > 0 {
+            self.counters.paused_ms.fetch_add(paused, Ordering::Relaxed);
+            TOTAL_PAUSED_MS.fetch_add(paused, Ordering::Relaxed);
+        }
+        ok
+    }
+}
+
+/// A reader's position in a buffered stream; detaches on drop.
+struct Cursor {
+    stream: Arc<BufferedStream>,
+    id: u64,
+}
+
+impl Cursor {
+    fn advance(&self, pos: usize) {
+        if let Ok(mut cursors) = self.stream.cursors.lock() {
+            cursors.insert(self.id, pos);
+        }
+        self.stream.consumed.notify_waiters();
+    }
+}
+
+impl Drop for Cursor {
+    fn drop(&mut self) {
+        if let Ok(mut cursors) = self.stream.cursors.lock() {
+            cursors.remove(&self.id);
+        }
+        self.stream.consumed.notify_waiters();
+    }
+}
+
+/// Token text held back while a reader is behind (`drop` policy) or the
+/// buffer is full.
+#[derive(Default)]
+struct Coalescer {
+    text: String,
+    frames: u64,
+    dropped_bytes: u64,
+}
+
+impl Coalescer {
+    /// Take a plain `{"token": "...", "done": false}` frame; anything else is
+    /// returned so the caller can flush and forward it in order.
+    fn absorb<'a>(&mut self, line: &'a [u8], keep_text: bool) -> Option<&'a [u8]> {
+        let token = serde_json::from_slice::<Value>(line).ok().and_then(|v| {
+            let obj = v.as_object()?;
+            let plain = obj.len() == 2 && obj.get("done") == Some(&Value::Bool(false));
+            plain.then(|| obj.get("token")?.as_str().map(str::to_string))?
+        });
+        let Some(token) = token else {
+            return Some(line);
+        };
+        self.frames += 1;
+        if keep_text {
+            self.text.push_str(&token);
+        } else {
+            self.dropped_bytes += line.len() as u64;
+        }
+        None
+    }
+
+    /// Frames replacing everything absorbed so far.
+    fn flush(&mut self, counters: &StreamCounters) -> Vec<u8> {
+        if self.is_empty() {
+            return Vec::new();
+        }
+        counters.coalesced_frames.fetch_add(self.frames, Ordering::Relaxed);
+        counters.dropped_bytes.fetch_add(self.dropped_bytes, Ordering::Relaxed);
+        TOTAL_COALESCED_FRAMES.fetch_add(self.frames, Ordering::Relaxed);
+        TOTAL_DROPPED_BYTES.fetch_add(self.dropped_bytes, Ordering::Relaxed);
+
+        let mut out = Vec::new();
+        if !self.text.is_empty() {
+            out.extend_from_slice(json!({ "token": self.text, "done": false }).to_string().as_bytes());
+            out.push(b'\n');
+        }
+        if self.dropped_bytes > 0 {
+            let notice = json!({
+                "token": "",
+                "done": false,
+                "notice": "Output exceeded the stream buffer — reload the session for the full reply",
+                "dropped_bytes": self.dropped_bytes,
+            });
+            out.extend_from_slice(notice.to_string().as_bytes());
+            out.push(b'\n');
+        }
+        *self = Self::default();
+        out
+    }
+
+    fn is_empty(&self) -> bool {
+        self.frames == 0
+    }
+}
+
+/// Per-replica registry of in-flight and recently finished streams.
+#[derive(Default)]
+pub struct StreamRelay {
+    streams: Mutex<HashMap<String, Arc<BufferedStream>>>,
+}
+
+impl StreamRelay {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    fn get(&self, id: &str) -> Option<Arc<BufferedStream>> {
+        self.streams.lock().ok()?.get(id).cloned()
+    }
+
+    fn insert(&self, id: String, stream: Arc<BufferedStream>) {
+        if let Ok(mut streams) = self.streams.lock() {
+            streams.retain(|_, s| {
+                s.finished_at
+                    .lock()
+                    .ok()
+                    .and_then(|f| *f)
+                    .is_none_or(|t| t.elapsed() < STREAM_RETENTION)
+            });
+            streams.insert(id, stream);
+        }
+    }
+
+    /// Streams still running (finished ones wait in the buffer for resumes).
+    pub fn active_count(&self) -> usize {
+        self.streams
+            .lock()
+            .map(|s| s.values().filter(|b| !b.progress.borrow().1).count())
+            .unwrap_or(0)
+    }
+
+    /// Buffer usage of every stream still held by this replica.
+    pub fn stats(&self) -> Vec<StreamBufferStats> {
+        let Ok(streams) = self.streams.lock() else {
+            return Vec::new();
+        };
+        streams
+            .iter()
+            .map(|(id, s)| StreamBufferStats {
+                id: id.clone(),
+                buffered_bytes: s.len(),
+                readers: s.cursors.lock().map(|c| c.len()).unwrap_or(0),
+                lag_bytes: s.lag(),
+                finished: s.progress.borrow().1,
+                paused_ms: s.counters.paused_ms.load(Ordering::Relaxed),
+                coalesced_frames: s.counters.coalesced_frames.load(Ordering::Relaxed),
+                dropped_bytes: s.counters.dropped_bytes.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+
+    /// Cancel a running stream. `None` if the id is unknown, `Some(false)` if
+    /// it had already finished.
+    pub fn cancel(&self, id: &str) -> Option<bool> {
+        let stream = self.get(id)?;
+        if stream.progress.borrow().1 {
+            return Some(false);
+        }
+        stream.cancel.cancel();
+        Some(true)
+    }
+
+    /// Throughput so far of streams that are still running.
+    pub fn throughput(&self) -> Vec<StreamThroughput> {
+        let Ok(streams) = self.streams.lock() else {
+            return Vec::new();
+        };
+        streams
+            .values()
+            .filter(|s| !s.progress.borrow().1)
+            .filter_map(|s| {
+                let paused = s.counters.paused_ms.load(Ordering::Relaxed);
+                s.meter.lock().ok()?.as_ref().map(|m| m.snapshot(paused, None))
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamBufferStats {
+    pub id: String,
+    pub buffered_bytes: usize,
+    pub readers: usize,
+    /// How far the slowest attached reader is behind (null when none attached).
+    pub lag_bytes: Option<usize>,
+    pub finished: bool,
+    pub paused_ms: u64,
+    pub coalesced_frames: u64,
+    pub dropped_bytes: u64,
+}
+
+/// Split a stream id into `(uuid, owning instance)`.
+pub fn parse_stream_id(id: &str) -> Option<(uuid::Uuid, &str)> {
+    let (uuid, instance) = id.split_once('.')?;
+    let uuid = uuid::Uuid::parse_str(uuid).ok()?;
+    (!instance.is_empty()).then_some((uuid, instance))
+}
+
+/// Detach a successful streaming response from its connection and make it
+/// resumable. Non-2xx responses are passed through untouched.
+pub fn detach(state: &AppState, response: Response) -> Response {
+    if !response.status().is_success() {
+        return response;
+    }
+    let id = format!("{}.{}", uuid::Uuid::new_v4(), crate::cluster::instance_id());
+    let (parts, body) = response.into_parts();
+    let buffered = Arc::new(BufferedStream::new());
+    let received_at = crate::request_scope::current().and_then(|s| s.received_at);
+    if let Ok(mut meter) = buffered.meter.lock() {
+        *meter = Some(Meter::start(&id, received_at));
+    }
+    state.streams.insert(id.clone(), buffered.clone());
+    // Attach the live reader before any output exists so the writer sees it.
+    let live = buffered.attach(0);
+
+    let writer = buffered.clone();
+    // Keep the request scope (stop sequences etc.) for upstream calls made
+    // while the body is being produced, e.g. later tool-loop iterations, and
+    // give code running there a side channel into this stream.
+    let (events_tx, mut events) = tokio::sync::mpsc::unbounded_channel();
+    let scope = Arc::new(crate::request_scope::RequestScope {
+        events: Some(events_tx),
+        ..(*crate::request_scope::current().unwrap_or_default()).clone()
+    });
+    let state = state.clone();
+    tokio::spawn(crate::request_scope::run(scope, async move {
+        let limits = limits();
+        let mut policy = limits.policy;
+        let mut upstream = body.into_data_stream();
+        let mut partial: Vec<u8> = Vec::new();
+        let mut held = Coalescer::default();
+        // Side-channel frames wait here until the upstream is at a line boundary.
+        let mut side: Vec<u8> = Vec::new();
+        let mut outcome = Outcome::Completed;
+        loop {
+            let next = tokio::select! {
+                biased;
+                _ = writer.cancel.cancelled() => {
+                    outcome = Outcome::Cancelled;
+                    // Dropping `upstream` below closes the upstream request.
+                    if !partial.is_empty() {
+                        partial.push(b'\n');
+                    }
+                    partial.extend_from_slice(cancelled_ndjson_frame().as_bytes());
+                    break;
+                }
+                next = stream_watchdog::next_chunk(&mut upstream) => next,
+                Some(event) = events.recv() => {
+                    // Any event (including a keep-alive) restarts the watchdog.
+                    if let StreamEvent::Frame(frame) = event {
+                        side.extend_from_slice(frame.as_bytes());
+                        side.push(b'\n');
+                    }
+                    if partial.is_empty() && !side.is_empty() {
+                        let mut out = held.flush(&writer.counters);
+                        out.append(&mut side);
+                        let written = writer.append(&out);
+                        writer.progress.send_replace((written, false));
+                    }
+                    continue;
+                }
+            };
+            let chunk = match next {
+                Next::Item(Ok(chunk)) => chunk,
+                Next::Item(Err(_)) => {
+                    outcome = Outcome::Interrupted;
+                    break;
+                }
+                Next::End => break,
+                Next::Stalled => {
+                    outcome = Outcome::Stalled;
+                    // Dropping `upstream` below aborts the stalled request.
+                    stream_watchdog::record_stall(&state, "ndjson", writer.len()).await;
+                    if !partial.is_empty() {
+                        partial.push(b'\n');
+                    }
+                    partial.extend_from_slice(stream_watchdog::stalled_ndjson_frame().as_bytes());
+                    break;
+                }
+            };
+            partial.extend_from_slice(&chunk);
+            // Only whole NDJSON lines are forwarded, so frames are never split
+            // by coalescing.
+            let Some(cut) = partial.iter().rposition(|b| *b == b'\n') else {
+                continue;
+            };
+            let lines: Vec<u8> = partial.drain(..=cut).collect();
+            writer.observe(&lines);
+
+            let lag = writer.lag().unwrap_or(0);
+            let high = limits.high_water_bytes;
+            let full = writer.len() + lines.len() > limits.max_buffer_bytes;
+            // Start holding tokens back above the high-water mark and keep doing
+            // so until the reader is below half of it.
+            let hold = full
+                || (policy == BackpressurePolicy::Drop
+                    && (lag > high || (!held.is_empty() && lag > high / 2)));
+            let mut out = Vec::with_capacity(lines.len());
+            for line in lines.split_inclusive(|b| *b == b'\n') {
+                let with_warnings = crate::soft_limits::done_frame_with_warnings(line);
+                let line = with_warnings.as_deref().unwrap_or(line);
+                if hold && held.absorb(line.trim_ascii_end(), !full).is_none() {
+                    continue;
+                }
+                out.extend(held.flush(&writer.counters));
+                out.extend_from_slice(line);
+            }
+            if partial.is_empty() && !side.is_empty() {
+                out.extend(held.flush(&writer.counters));
+                out.append(&mut side);
+            }
+            if !out.is_empty() {
+                let written = writer.append(&out);
+                writer.progress.send_replace((written, false));
+            }
+
+            if policy == BackpressurePolicy::Pause
+                && !writer.wait_for_readers(limits.high_water_bytes).await
+            {
+                tracing::warn!("stream_relay: reader stopped consuming, switching to drop policy");
+                policy = BackpressurePolicy::Drop;
+            }
+        }
+
+        // Flush held tokens, any unterminated trailing frame and the stall frame.
+        writer.observe(&partial);
+        let mut tail = held.flush(&writer.counters);
+        tail.append(&mut side);
+        tail.extend_from_slice(&partial);
+        let written = writer.append(&tail);
+        if let Ok(mut f) = writer.finished_at.lock() {
+            *f = Some(Instant::now());
+        }
+        writer.progress.send_replace((written, true));
+
+        let meter = writer.meter.lock().ok().and_then(|mut m| m.take());
+        if let Some(meter) = meter {
+            let paused = writer.counters.paused_ms.load(Ordering::Relaxed);
+            crate::stream_throughput::record(meter.snapshot(paused, Some(outcome)));
+        }
+    }));
+
+    let mut response = Response::from_parts(parts, follow(live, 0));
+    let headers = response.headers_mut();
+    if let Ok(v) = HeaderValue::from_str(&id) {
+        headers.insert(HeaderName::from_static(STREAM_ID_HEADER), v);
+    }
+    if let Ok(v) = HeaderValue::from_str(crate::cluster::instance_id()) {
+        headers.insert(HeaderName::from_static(STREAM_INSTANCE_HEADER), v);
+    }
+    response
+}
+
+/// Final frame of a cancelled stream.
+fn cancelled_ndjson_frame() -> String {
+    format!("{}\n", json!({ "token": "", "done": true, "cancelled": true }))
+}
+
+/// Body that replays the buffer from the cursor's offset and then follows
+/// live output. The cursor advances once the client asks for the next chunk.
+fn follow(cursor: Cursor, offset: usize) -> Body {
+    let stream = cursor.stream.clone();
+    let mut rx = stream.progress.subscribe();
+    let body = async_stream::stream! {
+        let mut pos = offset;
+        loop {
+            let (len, done) = *rx.borrow_and_update();
+            if len > pos {
+                let chunk = stream
+                    .data
+                    .lock()
+                    .map(|d| Bytes::copy_from_slice(&d[pos..len]))
+                    .unwrap_or_default();
+                pos = len;
+                yield Ok::<_, std::io::Error>(chunk);
+                cursor.advance(pos);
+            }
+            if done || rx.changed().await.is_err() {
+                break;
+            }
+        }
+    };
+    Body::from_stream(body)
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  GET /api/claude/chat/stream/{stream_id}
+// ═══════════════════════════════════════════════════════════════════════
+
+#[derive(Debug, Deserialize)]
+pub struct ResumeQuery {
+    /// Bytes of the NDJSON body the client already received.
+    #[serde(default)]
+    pub offset: usize,
+}
+
+/// `GET /api/claude/chat/stream/{stream_id}` — reattach to a running or recently finished stream
+pub async fn resume_stream(
+    State(state): State<AppState>,
+    Path(stream_id): Path<String>,
+    Query(q): Query<ResumeQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let Some((_, owner)) = parse_stream_id(&stream_id) else {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "Invalid stream id" })),
+        )
+            .into_response();
+    };
+
+    if owner != crate::cluster::instance_id() {
+        // A forwarded request is never forwarded again.
+        if !headers.contains_key(FORWARDED_HEADER)
+            && let Some(response) = forward_resume(&state, owner, &stream_id, q.offset, &headers).await
+        {
+            return response;
+        }
+        return misrouted(owner);
+    }
+
+    let Some(stream) = state.streams.get(&stream_id) else {
+        return (
+            StatusCode::GONE,
+            Json(json!({
+                "error": "Stream expired — reload the session history instead",
+            })),
+        )
+            .into_response();
+    };
+
+    let available = stream.progress.borrow().0;
+    if q.offset > available {
+        return (
+            StatusCode::RANGE_NOT_SATISFIABLE,
+            Json(json!({ "error": "Offset beyond buffered output", "available": available })),
+        )
+            .into_response();
+    }
+
+    jaskier_core::handlers::anthropic_streaming::build_ndjson_response(follow(stream.attach(q.offset), q.offset))
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  POST /api/claude/chat/stream/{stream_id}/cancel
+// ═══════════════════════════════════════════════════════════════════════
+
+/// `POST /api/claude/chat/stream/{stream_id}/cancel` — abort a running stream
+pub async fn cancel_stream(
+    State(state): State<AppState>,
+    Path(stream_id): Path<String>,
+) -> Response {
+    let Some((_, owner)) = parse_stream_id(&stream_id) else {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "Invalid stream id" })),
+        )
+            .into_response();
+    };
+    if owner != crate::cluster::instance_id() {
+        if !crate::cluster::enabled() {
+            return misrouted(owner);
+        }
+        // The owner cancels it when the event arrives.
+        crate::cluster::publish(
+            &state,
+            crate::cluster::ClusterEvent::CancelStream { stream_id: stream_id.clone() },
+        );
+        return (
+            StatusCode::ACCEPTED,
+            Json(json!({ "stream_id": stream_id, "forwarded_to": owner })),
+        )
+            .into_response();
+    }
+    match state.streams.cancel(&stream_id) {
+        Some(cancelled) => {
+            if cancelled {
+                tracing::info!("stream_relay: stream {} cancelled by client", stream_id);
+            }
+            Json(json!({ "stream_id": stream_id, "cancelled": cancelled })).into_response()
+        }
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": "Unknown or expired stream" })),
+        )
+            .into_response(),
+    }
+}
+
+/// `GET /api/system/stream-buffers` — buffer limits, per-stream usage and backpressure totals
+pub async fn stream_buffers(State(state): State<AppState>) -> Json<Value> {
+    let mut streams = state.streams.stats();
+    streams.sort_by(|a, b| b.buffered_bytes.cmp(&a.buffered_bytes));
+    Json(json!({
+        "limits": limits(),
+        "buffered_bytes": streams.iter().map(|s| s.buffered_bytes).sum::<usize>(),
+        "totals": {
+            "paused_ms": TOTAL_PAUSED_MS.load(Ordering::Relaxed),
+            "coalesced_frames": TOTAL_COALESCED_FRAMES.load(Ordering::Relaxed),
+            "dropped_bytes": TOTAL_DROPPED_BYTES.load(Ordering::Relaxed),
+        },
+        "streams": streams,
+    }))
+}
+
+/// Relay the resume from the owning replica, if it is registered and answers.
+async fn forward_resume(
+    state: &AppState,
+    owner: &str,
+    stream_id: &str,
+    offset: usize,
+    headers: &HeaderMap,
+) -> Option<Response> {
+    let base = crate::cluster::instance_url(&state.db, owner).await?;
+    let mut request = state
+        .http_client
+        .get(format!("{}/api/claude/chat/stream/{}?offset={}", base, stream_id, offset))
+        .timeout(Duration::from_secs(crate::http_client::stream_timeout()))
+        .header(FORWARDED_HEADER, crate::cluster::instance_id());
+    // The owner authenticates the client again.
+    for name in [header::AUTHORIZATION, header::COOKIE] {
+        if let Some(value) = headers.get(&name) {
+            request = request.header(name, value.clone());
+        }
+    }
+    let upstream = match request.send().await {
+        Ok(r) => r,
+        Err(e) => {
+            tracing::warn!("stream_relay: forwarding resume of {} to {} failed: {}", stream_id, owner, e);
+            return None;
+        }
+    };
+    let status = StatusCode::from_u16(upstream.status().as_u16()).unwrap_or(StatusCode::BAD_GATEWAY);
+    let content_type = upstream.headers().get(header::CONTENT_TYPE).cloned();
+    let mut response = Response::new(Body::from_stream(upstream.bytes_stream()));
+    *response.status_mut() = status;
+    if let Some(v) = content_type {
+        response.headers_mut().insert(header::CONTENT_TYPE, v);
+    }
+    if let Ok(v) = HeaderValue::from_str(owner) {
+        response
+            .headers_mut()
+            .insert(HeaderName::from_static(STREAM_INSTANCE_HEADER), v);
+    }
+    Some(response)
+}
+
+/// Reply for a resume / cancel that reached the wrong replica.
+fn misrouted(owner: &str) -> Response {
+    owned_elsewhere(owner, "Stream is owned by another instance")
+}
+
+/// 409 sending the client to replica `owner` (`fly-replay`, `X-Stream-Instance`).
+pub(crate) fn owned_elsewhere(owner: &str, error: &str) -> Response {
+    let body: Value = json!({
+        "error": error,
+        "instance": owner,
+    });
+    let mut response = (StatusCode::CONFLICT, Json(body)).into_response();
+    if let Ok(v) = HeaderValue::from_str(&format!("instance={}", owner)) {
+        response
+            .headers_mut()
+            .insert(HeaderName::from_static("fly-replay"), v);
+    }
+    if let Ok(v) = HeaderValue::from_str(owner) {
+        response
+            .headers_mut()
+            .insert(HeaderName::from_static(STREAM_INSTANCE_HEADER), v);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stream_ids_carry_the_owner() {
+        let id = uuid::Uuid::new_v4();
+        let raw = format!("{}.machine-42", id);
+        assert_eq!(parse_stream_id(&raw), Some((id, "machine-42")));
+        assert_eq!(parse_stream_id(&format!("{}.", id)), None);
+        assert_eq!(parse_stream_id("not-a-uuid.machine-42"), None);
+        assert_eq!(parse_stream_id("no-separator"), None);
+    }
+
+    #[test]
+    fn token_frames_are_coalesced_in_order() {
+        let counters = StreamCounters::default();
+        let mut held = Coalescer::default();
+        assert!(held.absorb(br#"{"token":"Hel","done":false}"#, true).is_none());
+        assert!(held.absorb(br#"{"token":"lo","done":false}"#, true).is_none());
+        let done = br#"{"token":"","done":true,"model":"m"}"#;
+        assert_eq!(held.absorb(done, true), Some(&done[..]));
+
+        let flushed = String::from_utf8(held.flush(&counters)).unwrap();
+        assert_eq!(flushed.matches('\n').count(), 1);
+        let frame: Value = serde_json::from_str(flushed.trim_end()).unwrap();
+        assert_eq!(frame, json!({ "token": "Hello", "done": false }));
+        assert!(held.is_empty());
+        assert_eq!(counters.coalesced_frames.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn dropped_tokens_leave_a_notice() {
+        let counters = StreamCounters::default();
+        let mut held = Coalescer::default();
+        assert!(held.absorb(br#"{"token":"x","done":false}"#, false).is_none());
+        let flushed = String::from_utf8(held.flush(&counters)).unwrap();
+        let notice: Value = serde_json::from_str(flushed.trim_end()).unwrap();
+        assert_eq!(notice["dropped_bytes"], 26);
+        assert_eq!(notice["done"], false);
+    }
+
+    #[tokio::test]
+    async fn lag_follows_the_slowest_reader() {
+        let stream = Arc::new(BufferedStream::new());
+        assert_eq!(stream.lag(), None);
+        stream.append(&[0u8; 100]);
+        let fast = stream.attach(0);
+        let slow = stream.attach(0);
+        fast.advance(100);
+        slow.advance(40);
+        assert_eq!(stream.lag(), Some(60));
+        drop(slow);
+        assert_eq!(stream.lag(), Some(0));
+        assert!(stream.wait_for_readers(10).await);
+    }
+
+    #[tokio::test]
+    async fn cancelled_stream_ends_with_done_frame() {
+        let state = AppState::new_test();
+        let first = Bytes::from_static(b"{\"token\":\"a\",\"done\":false}\n");
+        let upstream = futures_util::StreamExt::chain(
+            futures_util::stream::once(async move { Ok::<_, std::io::Error>(first) }),
+            futures_util::stream::pending(),
+        );
+        let response = detach(&state, Response::new(Body::from_stream(upstream)));
+        let id = response.headers()[STREAM_ID_HEADER].to_str().unwrap().to_string();
+
+        assert_eq!(state.streams.cancel(&id), Some(true));
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let last = body.trim_ascii_end().split(|b| *b == b'\n').next_back().unwrap();
+        let frame: Value = serde_json::from_slice(last).unwrap();
+        assert_eq!(frame["done"], true);
+        assert_eq!(frame["cancelled"], true);
+        assert_eq!(state.streams.cancel(&id), Some(false));
+        assert_eq!(state.streams.cancel("unknown"), None);
+    }
+}
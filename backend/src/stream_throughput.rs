@@ -0,0 +1,370 @@
+// ClaudeHydra v4 -- Per-stream token throughput
+// Every detached NDJSON stream (see stream_relay) is metered as its frames
+// arrive from the provider handler, which splits the time a user waits into
+// our share and the provider's share:
+//   - `setup_ms` — request received → upstream response headers (context
+//     resolution, DB, credential lookup, upstream connect and queueing),
+//   - `first_token_ms` — upstream headers → first token,
+//   - `tokens_per_sec` plus one sample per second of generation, and the
+//     longest gap between tokens,
+//   - `paused_ms` — time the relay itself held upstream reads back because a
+//     reader was slow (backpressure).
+// Token counts are estimated from the streamed text (~4 chars per token); they
+// are for rates, not billing (see usage).
+//
+// Finished streams go into a small in-memory ring (`GET /api/streams/recent`)
+// and into the `ch_stream_*` histograms on `/metrics`.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use axum::Json;
+use axum::extract::{Query, State};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::state::AppState;
+
+const MAX_RECENT: usize = 200;
+/// Per-second samples kept per stream (10 minutes).
+const MAX_SAMPLES: usize = 600;
+const CHARS_PER_TOKEN: f64 = 4.0;
+
+const TPS_BUCKETS: [f64; 9] = [5.0, 10.0, 20.0, 40.0, 60.0, 80.0, 100.0, 150.0, 200.0];
+const LATENCY_BUCKETS: [f64; 8] = [0.1, 0.25, 0.5, 1.0, 2.0, 4.0, 8.0, 16.0];
+
+/// How a stream ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Completed,
+    /// Aborted by the stall watchdog.
+    Stalled,
+    /// Upstream body errored.
+    Interrupted,
+    /// Aborted by the client (`POST …/stream/{id}/cancel`).
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamThroughput {
+    pub stream_id: String,
+    pub model: Option<String>,
+    pub started_at: DateTime<Utc>,
+    /// `None` while the stream is still running.
+    pub outcome: Option<Outcome>,
+    pub setup_ms: Option<u64>,
+    pub first_token_ms: Option<u64>,
+    /// First token → last token.
+    pub generation_ms: u64,
+    pub tokens: u64,
+    pub tokens_per_sec: Option<f64>,
+    pub max_gap_ms: u64,
+    pub paused_ms: u64,
+    /// Tokens/second for each second since the first token.
+    pub samples: Vec<f64>,
+}
+
+/// Meter for one running stream.
+#[derive(Debug)]
+pub struct Meter {
+    stream_id: String,
+    model: Option<String>,
+    started_at: DateTime<Utc>,
+    started: Instant,
+    setup: Option<Duration>,
+    first_token: Option<Instant>,
+    last_token: Option<Instant>,
+    chars: usize,
+    max_gap: Duration,
+    /// Characters received in each second since the first token.
+    buckets: Vec<usize>,
+}
+
+impl Meter {
+    /// Start metering at the upstream response; `received_at` is when the
+    /// request came in (from the request scope), if known.
+    pub fn start(stream_id: &str, received_at: Option<Instant>) -> Self {
+        let started = Instant::now();
+        Self {
+            stream_id: stream_id.to_string(),
+            model: None,
+            started_at: Utc::now(),
+            started,
+            setup: received_at.map(|r| started.saturating_duration_since(r)),
+            first_token: None,
+            last_token: None,
+            chars: 0,
+            max_gap: Duration::ZERO,
+            buckets: Vec::new(),
+        }
+    }
+
+    /// Observe complete NDJSON lines as they arrive from upstream.
+    pub fn observe(&mut self, lines: &[u8]) {
+        let now = Instant::now();
+        for line in lines.split(|b| *b == b'\n').filter(|l| !l.trim_ascii().is_empty()) {
+            let Ok(frame) = serde_json::from_slice::<Value>(line) else {
+                continue;
+            };
+            if let Some(model) = frame.get("model").and_then(|m| m.as_str()) {
+                self.model = Some(model.to_string());
+            }
+            let text = frame.get("token").and_then(|t| t.as_str()).unwrap_or_default();
+            if !text.is_empty() {
+                self.token(text.chars().count(), now);
+            }
+        }
+    }
+
+    fn token(&mut self, chars: usize, now: Instant) {
+        let first = *self.first_token.get_or_insert(now);
+        if let Some(last) = self.last_token {
+            self.max_gap = self.max_gap.max(now.saturating_duration_since(last));
+        }
+        self.last_token = Some(now);
+        self.chars += chars;
+        let second = now.saturating_duration_since(first).as_secs() as usize;
+        if second < MAX_SAMPLES {
+            if self.buckets.len() <= second {
+                self.buckets.resize(second + 1, 0);
+            }
+            self.buckets[second] += chars;
+        }
+    }
+
+    pub fn snapshot(&self, paused_ms: u64, outcome: Option<Outcome>) -> StreamThroughput {
+        let tokens = |chars: usize| chars as f64 / CHARS_PER_TOKEN;
+        let generation = match (self.first_token, self.last_token) {
+            (Some(first), Some(last)) => last.saturating_duration_since(first),
+            _ => Duration::ZERO,
+        };
+        let mut samples: Vec<f64> = self.buckets.iter().map(|c| round1(tokens(*c))).collect();
+        // The last second is usually partial; scale it to a rate.
+        if let Some(last) = samples.last_mut() {
+            let covered = (generation.as_secs_f64() - (self.buckets.len() - 1) as f64).clamp(0.25, 1.0);
+            *last = round1(*last / covered);
+        }
+        StreamThroughput {
+            stream_id: self.stream_id.clone(),
+            model: self.model.clone(),
+            started_at: self.started_at,
+            outcome,
+            setup_ms: self.setup.map(|d| d.as_millis() as u64),
+            first_token_ms: self
+                .first_token
+                .map(|t| t.saturating_duration_since(self.started).as_millis() as u64),
+            generation_ms: generation.as_millis() as u64,
+            tokens: tokens(self.chars).round() as u64,
+            tokens_per_sec: (generation >= Duration::from_millis(500))
+                .then(|| round1(tokens(self.chars) / generation.as_secs_f64())),
+            max_gap_ms: self.max_gap.as_millis() as u64,
+            paused_ms,
+            samples,
+        }
+    }
+}
+
+fn round1(v: f64) -> f64 {
+    (v * 10.0).round() / 10.0
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  Recent streams + Prometheus histograms
+// ═══════════════════════════════════════════════════════════════════════
+
+struct Histogram {
+    bounds: &'static [f64],
+    counts: Vec<AtomicU64>,
+    /// Sum in thousandths, so it fits an atomic integer.
+    sum_milli: AtomicU64,
+    total: AtomicU64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Self {
+        Self {
+            bounds,
+            counts: bounds.iter().map(|_| AtomicU64::new(0)).collect(),
+            sum_milli: AtomicU64::new(0),
+            total: AtomicU64::new(0),
+        }
+    }
+
+    fn observe(&self, value: f64) {
+        for (bound, count) in self.bounds.iter().zip(&self.counts) {
+            if value <= *bound {
+                count.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.sum_milli.fetch_add((value * 1000.0) as u64, Ordering::Relaxed);
+        self.total.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str) {
+        out.push_str(&format!("# HELP {} {}\n# TYPE {} histogram\n", name, help, name));
+        for (bound, count) in self.bounds.iter().zip(&self.counts) {
+            out.push_str(&format!("{}_bucket{{le=\"{}\"}} {}\n", name, bound, count.load(Ordering::Relaxed)));
+        }
+        let total = self.total.load(Ordering::Relaxed);
+        out.push_str(&format!("{}_bucket{{le=\"+Inf\"}} {}\n", name, total));
+        out.push_str(&format!(
+            "{}_sum {}\n{}_count {}\n",
+            name,
+            self.sum_milli.load(Ordering::Relaxed) as f64 / 1000.0,
+            name,
+            total
+        ));
+    }
+}
+
+struct Registry {
+    recent: Mutex<VecDeque<StreamThroughput>>,
+    tokens_per_sec: Histogram,
+    first_token: Histogram,
+    setup: Histogram,
+}
+
+fn registry() -> &'static Registry {
+    static REGISTRY: OnceLock<Registry> = OnceLock::new();
+    REGISTRY.get_or_init(|| Registry {
+        recent: Mutex::new(VecDeque::with_capacity(MAX_RECENT)),
+        tokens_per_sec: Histogram::new(&TPS_BUCKETS),
+        first_token: Histogram::new(&LATENCY_BUCKETS),
+        setup: Histogram::new(&LATENCY_BUCKETS),
+    })
+}
+
+/// Record a finished stream.
+pub fn record(stream: StreamThroughput) {
+    let reg = registry();
+    if let Some(tps) = stream.tokens_per_sec {
+        reg.tokens_per_sec.observe(tps);
+    }
+    if let Some(ms) = stream.first_token_ms {
+        reg.first_token.observe(ms as f64 / 1000.0);
+    }
+    if let Some(ms) = stream.setup_ms {
+        reg.setup.observe(ms as f64 / 1000.0);
+    }
+    if let Ok(mut recent) = reg.recent.lock() {
+        if recent.len() == MAX_RECENT {
+            recent.pop_front();
+        }
+        recent.push_back(stream);
+    }
+}
+
+/// Finished streams, newest first.
+pub fn recent() -> Vec<StreamThroughput> {
+    registry()
+        .recent
+        .lock()
+        .map(|r| r.iter().rev().cloned().collect())
+        .unwrap_or_default()
+}
+
+/// `ch_stream_*` histograms for `/metrics`.
+pub fn prometheus_output() -> String {
+    let reg = registry();
+    let mut out = String::new();
+    reg.tokens_per_sec.render(
+        &mut out,
+        "ch_stream_tokens_per_second",
+        "Estimated output tokens per second of finished chat streams",
+    );
+    reg.first_token.render(
+        &mut out,
+        "ch_stream_first_token_seconds",
+        "Upstream response headers to first token",
+    );
+    reg.setup.render(
+        &mut out,
+        "ch_stream_setup_seconds",
+        "Request received to upstream response headers",
+    );
+    out
+}
+
+/// `(p50, p95)` of the present values.
+fn percentiles(values: impl Iterator<Item = Option<f64>>) -> Value {
+    let mut v: Vec<f64> = values.flatten().collect();
+    if v.is_empty() {
+        return json!({ "p50": null, "p95": null });
+    }
+    v.sort_by(f64::total_cmp);
+    let at = |q: f64| v[((v.len() - 1) as f64 * q).round() as usize];
+    json!({ "p50": round1(at(0.5)), "p95": round1(at(0.95)) })
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  GET /api/streams/recent
+// ═══════════════════════════════════════════════════════════════════════
+
+#[derive(Debug, Deserialize)]
+pub struct RecentQuery {
+    pub limit: Option<usize>,
+    /// Include per-second samples (default true).
+    pub samples: Option<bool>,
+}
+
+/// `GET /api/streams/recent` — throughput of running and recently finished streams, with p50/p95
+pub async fn recent_streams(
+    State(state): State<AppState>,
+    Query(q): Query<RecentQuery>,
+) -> Json<Value> {
+    let limit = q.limit.unwrap_or(50).clamp(1, MAX_RECENT);
+    let mut finished = recent();
+    finished.truncate(limit);
+    let mut active = state.streams.throughput();
+    if !q.samples.unwrap_or(true) {
+        for s in finished.iter_mut().chain(active.iter_mut()) {
+            s.samples.clear();
+        }
+    }
+
+    let ms = |v: Option<u64>| v.map(|v| v as f64);
+    Json(json!({
+        "summary": {
+            "streams": finished.len(),
+            "tokens_per_sec": percentiles(finished.iter().map(|s| s.tokens_per_sec)),
+            "first_token_ms": percentiles(finished.iter().map(|s| ms(s.first_token_ms))),
+            "setup_ms": percentiles(finished.iter().map(|s| ms(s.setup_ms))),
+            "max_gap_ms": percentiles(finished.iter().map(|s| Some(s.max_gap_ms as f64))),
+            "paused_ms": percentiles(finished.iter().map(|s| Some(s.paused_ms as f64))),
+        },
+        "active": active,
+        "recent": finished,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn meter_counts_tokens_and_model() {
+        let mut meter = Meter::start("s.local", Some(Instant::now() - Duration::from_millis(300)));
+        meter.observe(b"{\"token\":\"Hello wo\",\"done\":false}\n{\"token\":\"rld!\",\"done\":false}\n");
+        meter.observe(b"{\"token\":\"\",\"done\":true,\"model\":\"claude-sonnet-4-6\"}\n");
+        let snap = meter.snapshot(0, Some(Outcome::Completed));
+        assert_eq!(snap.tokens, 3);
+        assert_eq!(snap.model.as_deref(), Some("claude-sonnet-4-6"));
+        assert!(snap.setup_ms.unwrap() >= 300);
+        assert!(snap.first_token_ms.is_some());
+        assert_eq!(snap.samples.len(), 1);
+        // Too short to give a meaningful rate
+        assert_eq!(snap.tokens_per_sec, None);
+    }
+
+    #[test]
+    fn percentiles_skip_missing_values() {
+        let p = percentiles([Some(10.0), None, Some(30.0), Some(20.0)].into_iter());
+        assert_eq!(p["p50"], 20.0);
+        assert_eq!(p["p95"], 30.0);
+        assert_eq!(percentiles(std::iter::empty())["p50"], Value::Null);
+    }
+}
@@ -0,0 +1,143 @@
+// ClaudeHydra v4 -- Hung stream watchdog
+// Upstream SSE streams occasionally stall with the connection still open. Every
+// chat stream reads upstream chunks through `next_chunk`, which gives up after
+// STREAM_IDLE_TIMEOUT_SECS without data. A stall:
+//   - drops the upstream response (aborting the HTTP request),
+//   - counts as a provider failure on the Anthropic circuit breaker (see
+//     circuit.rs), so repeated stalls open the breaker and new requests fail
+//     fast / take the fallback chain,
+//   - is kept in a small in-memory incident log (`GET /api/system/stream-incidents`).
+
+use std::collections::VecDeque;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use axum::Json;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::{Value, json};
+
+use crate::state::AppState;
+
+const DEFAULT_IDLE_TIMEOUT_SECS: u64 = 120;
+const MAX_INCIDENTS: usize = 100;
+
+/// Error code sent to clients when a stream is aborted for inactivity.
+pub const STALLED_CODE: &str = "STREAM_STALLED";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamIncident {
+    pub at: DateTime<Utc>,
+    /// Which pipeline stalled (`ndjson`, `ws`, `ws_tools`).
+    pub source: &'static str,
+    pub idle_secs: u64,
+    /// Bytes / tokens received before the stall.
+    pub received: usize,
+}
+
+fn incidents() -> &'static Mutex<VecDeque<StreamIncident>> {
+    static INCIDENTS: OnceLock<Mutex<VecDeque<StreamIncident>>> = OnceLock::new();
+    INCIDENTS.get_or_init(|| Mutex::new(VecDeque::with_capacity(MAX_INCIDENTS)))
+}
+
+/// Inactivity limit per stream (`STREAM_IDLE_TIMEOUT_SECS`, default 120s, min 5s).
+/// Kept above the tool timeout so a slow tool call isn't mistaken for a stall.
+pub fn idle_timeout() -> Duration {
+    static TIMEOUT: OnceLock<Duration> = OnceLock::new();
+    *TIMEOUT.get_or_init(|| {
+        let secs = std::env::var("STREAM_IDLE_TIMEOUT_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_IDLE_TIMEOUT_SECS);
+        Duration::from_secs(secs.max(5))
+    })
+}
+
+/// Outcome of waiting for the next upstream chunk.
+pub enum Next<T> {
+    Item(T),
+    End,
+    Stalled,
+}
+
+/// Await the next stream item, giving up after `idle_timeout()`.
+pub async fn next_chunk<S>(stream: &mut S) -> Next<S::Item>
+where
+    S: futures_util::Stream + Unpin,
+{
+    match tokio::time::timeout(idle_timeout(), futures_util::StreamExt::next(stream)).await {
+        Ok(Some(item)) => Next::Item(item),
+        Ok(None) => Next::End,
+        Err(_) => Next::Stalled,
+    }
+}
+
+/// Record a stall: circuit breaker failure + incident log.
+pub async fn record_stall(state: &AppState, source: &'static str, received: usize) {
+    let idle_secs = idle_timeout().as_secs();
+    tracing::warn!(
+        source,
+        received,
+        "stream_watchdog: upstream stalled for {}s, aborting",
+        idle_secs
+    );
+    crate::circuit::record_failure(state, crate::circuit::ANTHROPIC, format!("{} stream stalled", source)).await;
+    if let Ok(mut log) = incidents().lock() {
+        if log.len() == MAX_INCIDENTS {
+            log.pop_front();
+        }
+        log.push_back(StreamIncident {
+            at: Utc::now(),
+            source,
+            idle_secs,
+            received,
+        });
+    }
+}
+
+/// NDJSON error frame appended to a stalled stream.
+pub fn stalled_ndjson_frame() -> String {
+    format!(
+        "{}\n",
+        json!({
+            "token": "",
+            "done": true,
+            "error": format!("Upstream stalled — no data for {}s", idle_timeout().as_secs()),
+            "code": STALLED_CODE,
+        })
+    )
+}
+
+/// `GET /api/system/stream-incidents` — recent stalled-stream incidents (newest first)
+pub async fn stream_incidents() -> Json<Value> {
+    let list: Vec<StreamIncident> = incidents()
+        .lock()
+        .map(|l| l.iter().rev().cloned().collect())
+        .unwrap_or_default();
+    Json(json!({
+        "idle_timeout_secs": idle_timeout().as_secs(),
+        "incidents": list,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stalled_frame_is_one_terminal_ndjson_line() {
+        let frame = stalled_ndjson_frame();
+        assert!(frame.ends_with('\n'));
+        assert_eq!(frame.matches('\n').count(), 1);
+        let v: Value = serde_json::from_str(frame.trim_end()).unwrap();
+        assert_eq!(v["done"], true);
+        assert_eq!(v["code"], STALLED_CODE);
+    }
+
+    #[tokio::test]
+    async fn finished_stream_is_not_a_stall() {
+        let mut one = futures_util::stream::iter([1u8]);
+        assert!(matches!(next_chunk(&mut one).await, Next::Item(1)));
+        assert!(matches!(next_chunk(&mut one).await, Next::End));
+    }
+}
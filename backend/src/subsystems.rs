@@ -0,0 +1,174 @@
+// ClaudeHydra v4 -- Runtime subsystem controls
+// Background loops and inbound webhook handlers consult this registry so an
+// operator can pause a misbehaving subsystem via /api/admin/subsystems without
+// restarting the server (and without touching active chats).
+// Not registered: the update check (/api/system/update-check) only runs when
+// that endpoint is called, and is already opt-in via CH_UPDATE_CHECK.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use axum::Json;
+use axum::extract::{Path, Request, State};
+use axum::http::StatusCode;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use serde_json::{Value, json};
+
+use crate::state::AppState;
+
+pub const ANTHROPIC_WATCHDOG: &str = "anthropic_watchdog";
+pub const USAGE_ANOMALY: &str = "usage_anomaly";
+pub const SWARM_DISCOVERY: &str = "swarm_discovery";
+pub const WEBHOOKS: &str = "webhooks";
+pub const AUTO_COMPACTION: &str = "auto_compaction";
+pub const COLD_STORAGE: &str = "cold_storage";
+pub const OUTBOUND_WEBHOOKS: &str = "outbound_webhooks";
+pub const TASK_QUEUE: &str = "task_queue";
+pub const STATSD: &str = "statsd";
+
+/// Pausable subsystems and what pausing them does.
+const SUBSYSTEMS: &[(&str, &str)] = &[
+    (ANTHROPIC_WATCHDOG, "Periodic Anthropic / Google / database probes (health history)"),
+    (USAGE_ANOMALY, "Background usage anomaly detector"),
+    (SWARM_DISCOVERY, "Swarm IPC peer discovery loop"),
+    (
+        WEBHOOKS,
+        "Inbound webhooks (/api/webhooks/*, /api/integrations/{slack,github}/events, \
+         /api/integrations/email/inbound) — rejected with 503 while paused",
+    ),
+    (AUTO_COMPACTION, "Automatic session compaction after session chat turns (manual /compact still works)"),
+    (COLD_STORAGE, "Background compression of idle sessions (access still thaws them)"),
+    (OUTBOUND_WEBHOOKS, "Slack notifications and outbound webhook deliveries (events are still recorded)"),
+    (TASK_QUEUE, "Background task workers — queued tasks wait, running ones finish"),
+    (STATSD, "StatsD metrics push (STATSD_ADDR)"),
+];
+
+struct Subsystem {
+    description: &'static str,
+    paused: AtomicBool,
+}
+
+/// Registry of pausable subsystems. Names are fixed at startup.
+pub struct SubsystemRegistry {
+    entries: BTreeMap<&'static str, Subsystem>,
+}
+
+impl SubsystemRegistry {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            entries: SUBSYSTEMS
+                .iter()
+                .map(|(name, description)| {
+                    (
+                        *name,
+                        Subsystem {
+                            description: *description,
+                            paused: AtomicBool::new(false),
+                        },
+                    )
+                })
+                .collect(),
+        })
+    }
+
+    pub fn is_paused(&self, name: &str) -> bool {
+        self.entries
+            .get(name)
+            .is_some_and(|s| s.paused.load(Ordering::Relaxed))
+    }
+
+    /// Pause or resume a subsystem. Returns `false` for unknown names.
+    pub fn set_paused(&self, name: &str, paused: bool) -> bool {
+        match self.entries.get(name) {
+            Some(s) => {
+                s.paused.store(paused, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn snapshot(&self) -> Vec<Value> {
+        self.entries
+            .iter()
+            .map(|(name, s)| {
+                json!({
+                    "name": name,
+                    "description": s.description,
+                    "paused": s.paused.load(Ordering::Relaxed),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Route layer for webhook routes — 503 while the `webhooks` subsystem is paused.
+pub async fn webhook_guard(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    if state.subsystems.is_paused(WEBHOOKS) {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({ "error": "Webhook processing is paused" })),
+        )
+            .into_response();
+    }
+    next.run(req).await
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  /api/admin/subsystems
+// ═══════════════════════════════════════════════════════════════════════
+
+/// `GET /api/admin/subsystems` — list subsystems and their pause state
+pub async fn list_subsystems(State(state): State<AppState>) -> Json<Value> {
+    Json(json!({ "subsystems": state.subsystems.snapshot() }))
+}
+
+/// `POST /api/admin/subsystems/{name}/pause`
+pub async fn pause_subsystem(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    toggle(&state, &name, true).await
+}
+
+/// `POST /api/admin/subsystems/{name}/resume`
+pub async fn resume_subsystem(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    toggle(&state, &name, false).await
+}
+
+async fn toggle(
+    state: &AppState,
+    name: &str,
+    paused: bool,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    if !state.subsystems.set_paused(name, paused) {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": format!("Unknown subsystem: {}", name) })),
+        ));
+    }
+    tracing::warn!("Subsystem '{}' {}", name, if paused { "paused" } else { "resumed" });
+    crate::cluster::publish(
+        state,
+        crate::cluster::ClusterEvent::Subsystem { name: name.to_string(), paused },
+    );
+
+    let db = state.db.clone();
+    let name_owned = name.to_string();
+    tokio::spawn(async move {
+        crate::audit::log_audit(
+            &db,
+            if paused { "pause_subsystem" } else { "resume_subsystem" },
+            json!({ "subsystem": name_owned }),
+            None,
+        )
+        .await;
+    });
+
+    Ok(Json(json!({ "name": name, "paused": paused })))
+}
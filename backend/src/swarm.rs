@@ -43,7 +43,8 @@ impl SwarmState {
     }
 
     /// Start background discovery loop (probes peers every 30s).
-    pub fn start_discovery(&self) {
+    /// Skips probing while the `swarm_discovery` subsystem is paused.
+    pub fn start_discovery(&self, subsystems: Arc<crate::subsystems::SubsystemRegistry>) {
         let registry = self.registry.clone();
         let event_tx = self.event_tx.clone();
 
@@ -51,6 +52,9 @@ impl SwarmState {
             let mut interval = tokio::time::interval(Duration::from_secs(30));
             loop {
                 interval.tick().await;
+                if subsystems.is_paused(crate::subsystems::SWARM_DISCOVERY) {
+                    continue;
+                }
                 let peers = registry.discover().await;
                 let online = peers
                     .iter()
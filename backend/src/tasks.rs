@@ -0,0 +1,586 @@
+// ClaudeHydra v4 -- Background tasks
+// Long agent runs and debates don't have to hold an HTTP request open:
+// `POST /api/tasks` queues one and answers 202 with its id right away,
+// `GET /api/tasks/{id}` reports its status (queued → running → completed,
+// failed or cancelled) with the output produced so far, `GET /api/tasks`
+// lists them and `DELETE /api/tasks/{id}` cancels a queued or running task.
+// Kinds:
+//   - agent_run — `POST /api/agents/{id}/run` (`agent_id` plus its body);
+//                 output: a `text` frame per model turn, a `tool_call` frame
+//                 per tool call and `retry` frames,
+//   - debate    — `POST /api/debate` (its body); output: the debate's frames.
+// A pool of CH_TASK_CONCURRENCY workers (default 2, max 32) runs them in
+// submission order; at most MAX_PENDING_TASKS wait or run at once. Tasks ask
+// for upstream slots at `low` priority unless the request or the API token
+// says otherwise (see `priority`).
+//
+// Tasks live in memory on the replica that accepted them: ids are
+// `<uuid>.<instance id>` and other replicas redirect like resumable streams
+// (see stream_relay). Finished tasks are kept for TASK_RETENTION. Agent runs
+// are journaled (see recovery), so a crash leaves them retryable.
+// Pausing the `task_queue` subsystem keeps queued tasks waiting; running ones
+// finish normally.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tokio::sync::{Semaphore, mpsc};
+use tokio::task::AbortHandle;
+use utoipa::ToSchema;
+
+use crate::handlers::agent_run::AgentRunRequest;
+use crate::handlers::debate::DebateRequest;
+use crate::priority::{DefaultPriority, Priority};
+use crate::request_scope::{RequestScope, StreamEvent};
+use crate::state::AppState;
+
+const DEFAULT_CONCURRENCY: usize = 2;
+const MAX_CONCURRENCY: usize = 32;
+const MAX_PENDING_TASKS: usize = 100;
+const TASK_RETENTION: Duration = Duration::from_secs(60 * 60);
+/// How often a worker checks whether the `task_queue` subsystem was resumed.
+const PAUSE_POLL: Duration = Duration::from_secs(1);
+/// Output frames kept per task; older ones are dropped first.
+const MAX_OUTPUT_FRAMES: usize = 1000;
+
+type ApiError = (StatusCode, Json<Value>);
+
+fn error(status: StatusCode, msg: impl Into<String>) -> ApiError {
+    (status, Json(json!({ "error": msg.into() })))
+}
+
+/// What a task runs.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TaskKind {
+    /// An agent run (`POST /api/agents/{id}/run`).
+    AgentRun {
+        agent_id: String,
+        #[serde(flatten)]
+        run: AgentRunRequest,
+    },
+    /// A debate (`POST /api/debate`).
+    Debate {
+        #[serde(flatten)]
+        debate: DebateRequest,
+    },
+}
+
+impl TaskKind {
+    fn name(&self) -> &'static str {
+        match self {
+            TaskKind::AgentRun { .. } => "agent_run",
+            TaskKind::Debate { .. } => "debate",
+        }
+    }
+
+    fn summary(&self) -> String {
+        match self {
+            TaskKind::AgentRun { agent_id, run } => {
+                format!("agent run as {}: {}", agent_id, run.prompt.chars().take(80).collect::<String>())
+            }
+            TaskKind::Debate { debate } => format!("debate: {}", debate.topic.chars().take(80).collect::<String>()),
+        }
+    }
+}
+
+/// Request body for `POST /api/tasks`.
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct CreateTaskRequest {
+    #[serde(flatten)]
+    pub task: TaskKind,
+    /// Upstream priority (default: the API token's, else `low`).
+    #[serde(default)]
+    pub priority: Option<Priority>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskStatus {
+    Queued,
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl TaskStatus {
+    fn is_finished(self) -> bool {
+        matches!(self, TaskStatus::Completed | TaskStatus::Failed | TaskStatus::Cancelled)
+    }
+}
+
+struct Progress {
+    status: TaskStatus,
+    started_at: Option<DateTime<Utc>>,
+    finished_at: Option<DateTime<Utc>>,
+    /// When the task finished, for retention.
+    finished: Option<Instant>,
+    output: VecDeque<Value>,
+    /// Frames dropped from the front of `output`.
+    dropped: usize,
+    result: Option<Value>,
+    error: Option<String>,
+}
+
+struct Task {
+    id: String,
+    kind: &'static str,
+    summary: String,
+    created_at: DateTime<Utc>,
+    progress: Mutex<Progress>,
+    abort: Mutex<Option<AbortHandle>>,
+}
+
+impl Task {
+    fn new(id: String, kind: &TaskKind) -> Self {
+        Self {
+            id,
+            kind: kind.name(),
+            summary: kind.summary(),
+            created_at: Utc::now(),
+            progress: Mutex::new(Progress {
+                status: TaskStatus::Queued,
+                started_at: None,
+                finished_at: None,
+                finished: None,
+                output: VecDeque::new(),
+                dropped: 0,
+                result: None,
+                error: None,
+            }),
+            abort: Mutex::new(None),
+        }
+    }
+
+    fn status(&self) -> TaskStatus {
+        self.progress.lock().map(|p| p.status).unwrap_or(TaskStatus::Failed)
+    }
+
+    fn expired(&self) -> bool {
+        self.progress
+            .lock()
+            .ok()
+            .and_then(|p| p.finished)
+            .is_some_and(|t| t.elapsed() >= TASK_RETENTION)
+    }
+
+    fn push(&self, frame: Value) {
+        if let Ok(mut p) = self.progress.lock() {
+            if p.output.len() == MAX_OUTPUT_FRAMES {
+                p.output.pop_front();
+                p.dropped += 1;
+            }
+            p.output.push_back(frame);
+        }
+    }
+
+    /// Mark the task running; false when it was cancelled while queued.
+    fn start(&self) -> bool {
+        let Ok(mut p) = self.progress.lock() else {
+            return false;
+        };
+        if p.status != TaskStatus::Queued {
+            return false;
+        }
+        p.status = TaskStatus::Running;
+        p.started_at = Some(Utc::now());
+        true
+    }
+
+    /// Record the outcome, unless the task already ended (cancelled).
+    fn finish(&self, status: TaskStatus, result: Option<Value>, error: Option<String>) -> bool {
+        let Ok(mut p) = self.progress.lock() else {
+            return false;
+        };
+        if p.status.is_finished() {
+            return false;
+        }
+        p.status = status;
+        p.result = result;
+        p.error = error;
+        p.finished_at = Some(Utc::now());
+        p.finished = Some(Instant::now());
+        true
+    }
+
+    /// Cancel a queued or running task. False when it had already ended.
+    fn cancel(&self) -> bool {
+        if !self.finish(TaskStatus::Cancelled, None, None) {
+            return false;
+        }
+        if let Some(handle) = self.abort.lock().ok().and_then(|mut a| a.take()) {
+            handle.abort();
+        }
+        true
+    }
+
+    /// Status and output from frame `since` on (`null` output for listings).
+    fn view(&self, since: Option<usize>) -> Value {
+        let Ok(p) = self.progress.lock() else {
+            return json!({ "id": self.id });
+        };
+        let mut view = json!({
+            "id": self.id,
+            "kind": self.kind,
+            "summary": self.summary,
+            "status": p.status,
+            "created_at": self.created_at,
+            "started_at": p.started_at,
+            "finished_at": p.finished_at,
+            "error": p.error,
+        });
+        if let Some(since) = since {
+            let skip = since.saturating_sub(p.dropped);
+            view["output"] = json!(p.output.iter().skip(skip).collect::<Vec<_>>());
+            view["next"] = json!(p.dropped + p.output.len());
+            view["dropped_frames"] = json!(p.dropped);
+            view["result"] = json!(p.result);
+        }
+        view
+    }
+}
+
+/// Per-replica registry and worker pool of background tasks.
+pub struct TaskQueue {
+    tasks: Mutex<HashMap<String, Arc<Task>>>,
+    workers: Arc<Semaphore>,
+    concurrency: usize,
+}
+
+impl TaskQueue {
+    /// Pool of CH_TASK_CONCURRENCY workers.
+    pub fn from_env() -> Arc<Self> {
+        let concurrency = std::env::var("CH_TASK_CONCURRENCY")
+            .ok()
+            .and_then(|v| v.trim().parse::<usize>().ok())
+            .unwrap_or(DEFAULT_CONCURRENCY)
+            .clamp(1, MAX_CONCURRENCY);
+        Arc::new(Self {
+            tasks: Mutex::new(HashMap::new()),
+            workers: Arc::new(Semaphore::new(concurrency)),
+            concurrency,
+        })
+    }
+
+    fn get(&self, id: &str) -> Option<Arc<Task>> {
+        self.tasks.lock().ok()?.get(id).cloned()
+    }
+
+    /// Add `task` unless MAX_PENDING_TASKS are already queued or running.
+    fn insert(&self, task: Arc<Task>) -> bool {
+        let Ok(mut tasks) = self.tasks.lock() else {
+            return false;
+        };
+        tasks.retain(|_, t| !t.expired());
+        if tasks.values().filter(|t| !t.status().is_finished()).count() >= MAX_PENDING_TASKS {
+            return false;
+        }
+        tasks.insert(task.id.clone(), task);
+        true
+    }
+
+    fn list(&self) -> Vec<Arc<Task>> {
+        let Ok(mut tasks) = self.tasks.lock() else {
+            return Vec::new();
+        };
+        tasks.retain(|_, t| !t.expired());
+        let mut list: Vec<Arc<Task>> = tasks.values().cloned().collect();
+        list.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+        list
+    }
+}
+
+/// Error text of a handler error.
+fn error_message((_, Json(err)): ApiError) -> String {
+    err["error"].as_str().unwrap_or("Task failed").to_string()
+}
+
+/// Run an agent run, forwarding its progress frames to the task.
+async fn run_agent(
+    state: &AppState,
+    task: &Arc<Task>,
+    agent_id: &str,
+    run: &AgentRunRequest,
+    priority: Priority,
+) -> Result<Value, String> {
+    let _journal = crate::recovery::begin(
+        crate::recovery::OperationKind::AgentRun,
+        None,
+        format!("agent run as {} (task {})", agent_id, task.id),
+        json!({ "agent_id": agent_id, "request": run }),
+    );
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let sink = task.clone();
+    let forward = tokio::spawn(async move {
+        while let Some(event) = rx.recv().await {
+            if let StreamEvent::Frame(frame) = event
+                && let Ok(frame) = serde_json::from_str::<Value>(&frame)
+            {
+                sink.push(frame);
+            }
+        }
+    });
+    let scope = Arc::new(RequestScope {
+        events: Some(tx),
+        priority,
+        ..Default::default()
+    });
+    let outcome = crate::request_scope::run(
+        scope,
+        crate::handlers::agent_run::execute_run(state, agent_id, run, priority),
+    )
+    .await;
+    // The scope (and with it the sender) is gone; drain what is left.
+    let _ = forward.await;
+    outcome.map_err(error_message)
+}
+
+/// Run a debate, collecting its NDJSON frames.
+async fn run_debate(state: &AppState, task: &Task, debate: DebateRequest) -> Result<Value, String> {
+    let response = crate::handlers::debate::start_debate(State(state.clone()), Json(debate))
+        .await
+        .map_err(error_message)?;
+    let mut body = response.into_body().into_data_stream();
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut last = None;
+    while let Some(chunk) = body.next().await {
+        buffer.extend_from_slice(&chunk.map_err(|e| e.to_string())?);
+        while let Some(end) = buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=end).collect();
+            let Ok(frame) = serde_json::from_slice::<Value>(&line) else {
+                continue;
+            };
+            let failed = (frame["type"] == "error").then(|| frame["error"].as_str().unwrap_or("Debate failed").to_string());
+            if frame["done"] == true {
+                last = Some(frame.clone());
+            }
+            task.push(frame);
+            if let Some(failed) = failed {
+                return Err(failed);
+            }
+        }
+    }
+    last.ok_or_else(|| "Debate ended without a result".to_string())
+}
+
+/// Queue `task` on the worker pool.
+fn spawn(state: &AppState, task: Arc<Task>, kind: TaskKind, priority: Priority) {
+    let workers = state.tasks.workers.clone();
+    let worker_state = state.clone();
+    let worker_task = task.clone();
+    let handle = tokio::spawn(async move {
+        let Ok(_worker) = workers.acquire_owned().await else {
+            return;
+        };
+        while worker_state.subsystems.is_paused(crate::subsystems::TASK_QUEUE) {
+            tokio::time::sleep(PAUSE_POLL).await;
+        }
+        if !worker_task.start() {
+            return;
+        }
+        tracing::info!("tasks: {} started ({})", worker_task.id, worker_task.summary);
+        let outcome = match kind {
+            TaskKind::AgentRun { agent_id, run } => {
+                run_agent(&worker_state, &worker_task, &agent_id, &run, priority).await
+            }
+            TaskKind::Debate { debate } => run_debate(&worker_state, &worker_task, debate).await,
+        };
+        let finished = match outcome {
+            Ok(result) => worker_task.finish(TaskStatus::Completed, Some(result), None),
+            Err(e) => {
+                tracing::warn!("tasks: {} failed: {}", worker_task.id, e);
+                worker_task.finish(TaskStatus::Failed, None, Some(e))
+            }
+        };
+        if finished {
+            tracing::info!("tasks: {} finished ({:?})", worker_task.id, worker_task.status());
+        }
+    });
+    if let Ok(mut abort) = task.abort.lock() {
+        *abort = Some(handle.abort_handle());
+    }
+}
+
+/// Cheap checks up front, so a bad request fails with 4xx instead of as a task.
+async fn validate(state: &AppState, kind: &TaskKind) -> Result<(), ApiError> {
+    match kind {
+        TaskKind::AgentRun { agent_id, run } => {
+            if agent_id.trim().is_empty() {
+                return Err(error(StatusCode::BAD_REQUEST, "agent_id must not be empty"));
+            }
+            if run.prompt.trim().is_empty() {
+                return Err(error(StatusCode::BAD_REQUEST, "prompt must not be empty"));
+            }
+            let agent = state.agents.read().await.iter().find(|a| a.id == *agent_id).cloned();
+            let Some(agent) = agent else {
+                return Err(error(StatusCode::NOT_FOUND, format!("Agent '{}' not found", agent_id)));
+            };
+            crate::agent_status::ensure_usable(&agent)
+        }
+        TaskKind::Debate { debate } => {
+            if debate.topic.trim().is_empty() {
+                return Err(error(StatusCode::BAD_REQUEST, "topic must not be empty"));
+            }
+            Ok(())
+        }
+    }
+}
+
+/// The task `id` on this replica, or the response to send instead.
+fn lookup(state: &AppState, id: &str) -> Result<Arc<Task>, Response> {
+    let Some((_, owner)) = crate::stream_relay::parse_stream_id(id) else {
+        return Err(error(StatusCode::BAD_REQUEST, "Invalid task id").into_response());
+    };
+    if owner != crate::cluster::instance_id() {
+        return Err(crate::stream_relay::owned_elsewhere(owner, "Task is owned by another instance"));
+    }
+    state
+        .tasks
+        .get(id)
+        .ok_or_else(|| error(StatusCode::NOT_FOUND, "Unknown or expired task").into_response())
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  POST /api/tasks
+// ═══════════════════════════════════════════════════════════════════════
+
+/// `POST /api/tasks` — queue an agent run or debate in the background
+#[utoipa::path(post, path = "/api/tasks", tag = "agents",
+    request_body = CreateTaskRequest,
+    responses(
+        (status = 202, description = "Task queued"),
+        (status = 400, description = "Invalid task"),
+        (status = 404, description = "Unknown agent"),
+        (status = 409, description = "Agent is disabled"),
+        (status = 429, description = "Too many tasks pending")
+    ))]
+pub async fn create_task(
+    State(state): State<AppState>,
+    token_priority: Option<Extension<DefaultPriority>>,
+    Json(req): Json<CreateTaskRequest>,
+) -> Result<Response, ApiError> {
+    validate(&state, &req.task).await?;
+    let priority = req
+        .priority
+        .or_else(|| token_priority.map(|Extension(DefaultPriority(p))| p))
+        .unwrap_or(Priority::Low);
+
+    let id = format!("{}.{}", uuid::Uuid::new_v4(), crate::cluster::instance_id());
+    let task = Arc::new(Task::new(id, &req.task));
+    if !state.tasks.insert(task.clone()) {
+        return Err(error(
+            StatusCode::TOO_MANY_REQUESTS,
+            format!("{} tasks are already pending", MAX_PENDING_TASKS),
+        ));
+    }
+    tracing::info!("tasks: {} queued ({})", task.id, task.summary);
+    spawn(&state, task.clone(), req.task, priority);
+    Ok((StatusCode::ACCEPTED, Json(task.view(None))).into_response())
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  GET /api/tasks
+// ═══════════════════════════════════════════════════════════════════════
+
+/// `GET /api/tasks` — tasks of this replica, newest first
+#[utoipa::path(get, path = "/api/tasks", tag = "agents",
+    responses((status = 200, description = "Worker pool and tasks (without output)")))]
+pub async fn list_tasks(State(state): State<AppState>) -> Json<Value> {
+    let tasks = state.tasks.list();
+    let count = |status: TaskStatus| tasks.iter().filter(|t| t.status() == status).count();
+    Json(json!({
+        "concurrency": state.tasks.concurrency,
+        "running": count(TaskStatus::Running),
+        "queued": count(TaskStatus::Queued),
+        "tasks": tasks.iter().map(|t| t.view(None)).collect::<Vec<_>>(),
+    }))
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  GET /api/tasks/{id}
+// ═══════════════════════════════════════════════════════════════════════
+
+#[derive(Debug, Deserialize)]
+pub struct TaskQuery {
+    /// Output frames the client already has (`next` of the previous poll).
+    #[serde(default)]
+    pub since: usize,
+}
+
+/// `GET /api/tasks/{id}` — status, output so far and result of a task
+#[utoipa::path(get, path = "/api/tasks/{id}", tag = "agents",
+    params(
+        ("id" = String, Path, description = "Task ID"),
+        ("since" = Option<usize>, Query, description = "Skip the output frames before this index")
+    ),
+    responses(
+        (status = 200, description = "Task with its output from `since` on"),
+        (status = 404, description = "Unknown or expired task"),
+        (status = 409, description = "Task is owned by another instance")
+    ))]
+pub async fn get_task(State(state): State<AppState>, Path(id): Path<String>, Query(q): Query<TaskQuery>) -> Response {
+    match lookup(&state, &id) {
+        Ok(task) => Json(task.view(Some(q.since))).into_response(),
+        Err(response) => response,
+    }
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  DELETE /api/tasks/{id}
+// ═══════════════════════════════════════════════════════════════════════
+
+/// `DELETE /api/tasks/{id}` — cancel a queued or running task
+#[utoipa::path(delete, path = "/api/tasks/{id}", tag = "agents",
+    params(("id" = String, Path, description = "Task ID")),
+    responses(
+        (status = 200, description = "Task cancelled"),
+        (status = 404, description = "Unknown or expired task"),
+        (status = 409, description = "Task already finished, or owned by another instance")
+    ))]
+pub async fn cancel_task(State(state): State<AppState>, Path(id): Path<String>) -> Response {
+    let task = match lookup(&state, &id) {
+        Ok(task) => task,
+        Err(response) => return response,
+    };
+    if !task.cancel() {
+        return error(StatusCode::CONFLICT, "Task already finished").into_response();
+    }
+    tracing::info!("tasks: {} cancelled", task.id);
+    Json(task.view(None)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_is_capped_and_paged() {
+        let kind = TaskKind::Debate {
+            debate: serde_json::from_value(json!({ "topic": "t", "agent_a": "a", "agent_b": "b" })).unwrap(),
+        };
+        let task = Task::new("t.local".into(), &kind);
+        assert!(task.start());
+        for i in 0..MAX_OUTPUT_FRAMES + 5 {
+            task.push(json!({ "n": i }));
+        }
+        let view = task.view(Some(MAX_OUTPUT_FRAMES + 3));
+        assert_eq!(view["dropped_frames"], 5);
+        assert_eq!(view["next"], MAX_OUTPUT_FRAMES + 5);
+        assert_eq!(view["output"].as_array().unwrap().len(), 2);
+        assert_eq!(view["output"][0]["n"], MAX_OUTPUT_FRAMES + 3);
+
+        assert!(task.cancel());
+        assert!(!task.finish(TaskStatus::Completed, Some(json!({})), None));
+        assert_eq!(task.status(), TaskStatus::Cancelled);
+    }
+}
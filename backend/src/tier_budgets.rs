@@ -0,0 +1,394 @@
+// ClaudeHydra v4 -- Daily token budgets per agent tier
+// Each tier (Commander = Opus, Coordinator = Sonnet, Executor = Haiku) can
+// get a daily token budget, counted from ch_usage_events since 00:00 UTC
+// across all replicas. Every Anthropic request is checked in
+// `send_to_anthropic`, whatever started it (chat, streams, agents, debates):
+//   - `downgrade` (default): the request goes to the next tier down that
+//     still has budget; streams get a `{"type": "tier_downgrade", …}` frame
+//     and the reply's `model` shows the model actually used,
+//   - `reject`: the request fails with 429 until the budget resets.
+// Executor has no tier below it, so an exhausted Executor budget rejects.
+//
+// Configured with `PUT /api/admin/tier-budgets` (ch_settings.tier_budgets,
+// synced across replicas); `GET` shows usage and what is left today.
+
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+use axum::Json;
+use axum::extract::State;
+use axum::http::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use utoipa::ToSchema;
+
+use crate::state::AppState;
+
+/// How long today's usage per tier is reused before it is queried again.
+const USAGE_TTL: Duration = Duration::from_secs(30);
+
+type ApiError = (StatusCode, Json<Value>);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, ToSchema)]
+pub enum Tier {
+    Commander,
+    Coordinator,
+    Executor,
+}
+
+impl Tier {
+    pub const ALL: [Tier; 3] = [Tier::Commander, Tier::Coordinator, Tier::Executor];
+
+    /// Tier of a Claude model (by family); `None` for other providers.
+    pub fn of_model(model: &str) -> Option<Tier> {
+        let lower = model.to_lowercase();
+        if lower.contains("opus") {
+            Some(Tier::Commander)
+        } else if lower.contains("sonnet") {
+            Some(Tier::Coordinator)
+        } else if lower.contains("haiku") {
+            Some(Tier::Executor)
+        } else {
+            None
+        }
+    }
+
+    fn below(self) -> Option<Tier> {
+        match self {
+            Tier::Commander => Some(Tier::Coordinator),
+            Tier::Coordinator => Some(Tier::Executor),
+            Tier::Executor => None,
+        }
+    }
+
+    /// Model-registry use case that picks this tier's model.
+    pub(crate) fn use_case(self) -> &'static str {
+        match self {
+            Tier::Commander => "commander",
+            Tier::Coordinator => "coordinator",
+            Tier::Executor => "executor",
+        }
+    }
+
+    /// SQL `LIKE` pattern matching this tier's models.
+    fn model_pattern(self) -> &'static str {
+        match self {
+            Tier::Commander => "%opus%",
+            Tier::Coordinator => "%sonnet%",
+            Tier::Executor => "%haiku%",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum OnExhausted {
+    #[default]
+    Downgrade,
+    Reject,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TierBudget {
+    /// Input + output tokens per UTC day.
+    pub daily_tokens: u64,
+    #[serde(default)]
+    pub on_exhausted: OnExhausted,
+}
+
+/// Budgets by tier; a tier without an entry is unlimited.
+pub type TierBudgets = BTreeMap<Tier, TierBudget>;
+
+static BUDGETS: RwLock<TierBudgets> = RwLock::new(BTreeMap::new());
+static USAGE: std::sync::Mutex<Option<(Instant, BTreeMap<Tier, u64>)>> = std::sync::Mutex::new(None);
+
+pub fn set(budgets: TierBudgets) {
+    if let Ok(mut current) = BUDGETS.write() {
+        *current = budgets;
+    }
+}
+
+fn budgets() -> TierBudgets {
+    BUDGETS.read().map(|b| b.clone()).unwrap_or_default()
+}
+
+/// Load the budgets at startup.
+pub async fn load(db: &sqlx::PgPool) {
+    match sqlx::query_scalar::<_, Option<Value>>("SELECT tier_budgets FROM ch_settings WHERE id = 1")
+        .fetch_optional(db)
+        .await
+    {
+        Ok(stored) => set(
+            stored
+                .flatten()
+                .and_then(|v| serde_json::from_value(v).ok())
+                .unwrap_or_default(),
+        ),
+        Err(e) => tracing::warn!("tier_budgets: failed to load budgets: {}", e),
+    }
+}
+
+/// Start of the current budget day (UTC midnight) and of the next one.
+fn budget_day() -> (chrono::DateTime<chrono::Utc>, chrono::DateTime<chrono::Utc>) {
+    let start = chrono::Utc::now()
+        .date_naive()
+        .and_hms_opt(0, 0, 0)
+        .unwrap_or_default()
+        .and_utc();
+    (start, start + chrono::Duration::days(1))
+}
+
+/// Tokens used today per tier (cached for `USAGE_TTL`).
+async fn usage_today(db: &sqlx::PgPool, fresh: bool) -> BTreeMap<Tier, u64> {
+    if !fresh
+        && let Ok(cached) = USAGE.lock()
+        && let Some((at, usage)) = cached.as_ref()
+        && at.elapsed() < USAGE_TTL
+    {
+        return usage.clone();
+    }
+    let (since, _) = budget_day();
+    let mut usage = BTreeMap::new();
+    for tier in Tier::ALL {
+        let used: Result<i64, sqlx::Error> = sqlx::query_scalar(
+            "SELECT COALESCE(SUM(total_tokens), 0)::BIGINT FROM ch_usage_events \
+             WHERE created_at >= $1 AND model ILIKE $2",
+        )
+        .bind(since)
+        .bind(tier.model_pattern())
+        .fetch_one(db)
+        .await;
+        match used {
+            Ok(used) => {
+                usage.insert(tier, used.max(0) as u64);
+            }
+            Err(e) => tracing::warn!("tier_budgets: failed to read usage: {}", e),
+        }
+    }
+    if let Ok(mut cached) = USAGE.lock() {
+        *cached = Some((Instant::now(), usage.clone()));
+    }
+    usage
+}
+
+/// What to do with a request for `tier`, given today's usage.
+#[derive(Debug, PartialEq)]
+enum Decision {
+    Allow,
+    /// Use this lower tier instead.
+    Downgrade(Tier),
+    Reject { tier: Tier, used: u64, budget: u64 },
+}
+
+fn decide(tier: Tier, budgets: &TierBudgets, usage: &BTreeMap<Tier, u64>) -> Decision {
+    let exhausted = |t: Tier| {
+        budgets
+            .get(&t)
+            .filter(|b| usage.get(&t).copied().unwrap_or(0) >= b.daily_tokens)
+    };
+    let Some(budget) = exhausted(tier) else {
+        return Decision::Allow;
+    };
+    let reject = Decision::Reject {
+        tier,
+        used: usage.get(&tier).copied().unwrap_or(0),
+        budget: budget.daily_tokens,
+    };
+    if budget.on_exhausted == OnExhausted::Reject {
+        return reject;
+    }
+    let mut next = tier.below();
+    while let Some(lower) = next {
+        match exhausted(lower) {
+            None => return Decision::Downgrade(lower),
+            Some(b) if b.on_exhausted == OnExhausted::Reject => break,
+            Some(_) => next = lower.below(),
+        }
+    }
+    reject
+}
+
+/// Check a Messages API body against the budgets. `Ok(None)` leaves it as
+/// is, `Ok(Some(body))` is the body with a lower tier's model.
+pub async fn apply(state: &AppState, body: &Value) -> Result<Option<Value>, ApiError> {
+    let budgets = budgets();
+    if budgets.is_empty() {
+        return Ok(None);
+    }
+    let Some(model) = body.get("model").and_then(|m| m.as_str()) else {
+        return Ok(None);
+    };
+    let Some(tier) = Tier::of_model(model) else {
+        return Ok(None);
+    };
+    if !budgets.contains_key(&tier) {
+        return Ok(None);
+    }
+    let usage = usage_today(&state.db, false).await;
+    match decide(tier, &budgets, &usage) {
+        Decision::Allow => {
+            if let Some(budget) = budgets.get(&tier) {
+                crate::soft_limits::check(
+                    crate::soft_limits::Kind::TierBudget,
+                    &format!("{:?}", tier),
+                    usage.get(&tier).copied().unwrap_or(0) as f64,
+                    budget.daily_tokens as f64,
+                    &format!("Daily token budget for the {:?} tier is almost used up", tier),
+                    json!({ "tier": tier, "on_exhausted": budget.on_exhausted, "resets_at": budget_day().1 }),
+                );
+            }
+            Ok(None)
+        }
+        Decision::Downgrade(lower) => {
+            let replacement = crate::model_registry::get_model_id(state, lower.use_case()).await;
+            tracing::info!(
+                "tier_budgets: {:?} budget exhausted — {} downgraded to {}",
+                tier,
+                model,
+                replacement
+            );
+            let downgrade = json!({
+                "tier": tier,
+                "to_tier": lower,
+                "from": model,
+                "to": replacement,
+                "reason": "daily token budget exhausted",
+            });
+            crate::request_scope::decide("tier_downgrade", downgrade.clone());
+            let mut frame = json!({ "type": "tier_downgrade" });
+            if let (Some(frame), Value::Object(detail)) = (frame.as_object_mut(), downgrade) {
+                frame.extend(detail);
+            }
+            crate::request_scope::emit(crate::request_scope::StreamEvent::Frame(frame.to_string()));
+            let mut body = body.clone();
+            body["model"] = json!(replacement);
+            Ok(Some(body))
+        }
+        Decision::Reject { tier, used, budget } => Err((
+            StatusCode::TOO_MANY_REQUESTS,
+            Json(json!({
+                "error": format!("Daily token budget for the {:?} tier is exhausted", tier),
+                "tier": tier,
+                "used_tokens": used,
+                "daily_tokens": budget,
+                "resets_at": budget_day().1,
+            })),
+        )),
+    }
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  /api/admin/tier-budgets
+// ═══════════════════════════════════════════════════════════════════════
+
+/// `GET /api/admin/tier-budgets` — budgets with today's usage
+#[utoipa::path(get, path = "/api/admin/tier-budgets", tag = "system",
+    responses((status = 200, description = "Budget, usage and remaining tokens per tier")))]
+pub async fn get_tier_budgets(State(state): State<AppState>) -> Json<Value> {
+    let budgets = budgets();
+    let usage = usage_today(&state.db, true).await;
+    let tiers: Vec<Value> = Tier::ALL
+        .iter()
+        .map(|tier| {
+            let used = usage.get(tier).copied().unwrap_or(0);
+            let budget = budgets.get(tier);
+            json!({
+                "tier": tier,
+                "daily_tokens": budget.map(|b| b.daily_tokens),
+                "on_exhausted": budget.map(|b| b.on_exhausted),
+                "used_tokens": used,
+                "remaining_tokens": budget.map(|b| b.daily_tokens.saturating_sub(used)),
+                "exhausted": budget.is_some_and(|b| used >= b.daily_tokens),
+            })
+        })
+        .collect();
+    Json(json!({ "tiers": tiers, "resets_at": budget_day().1 }))
+}
+
+/// `PUT /api/admin/tier-budgets` — replace all budgets (`{}` removes them)
+#[utoipa::path(put, path = "/api/admin/tier-budgets", tag = "system",
+    request_body(content = Object, description = "Budget per tier, e.g. `{\"Commander\": {\"daily_tokens\": 200000, \"on_exhausted\": \"downgrade\"}}`"),
+    responses(
+        (status = 200, description = "Budgets updated"),
+        (status = 400, description = "A budget of 0 tokens")
+    ))]
+pub async fn set_tier_budgets(
+    State(state): State<AppState>,
+    Json(budgets): Json<TierBudgets>,
+) -> Result<Json<Value>, ApiError> {
+    if budgets.values().any(|b| b.daily_tokens == 0) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "daily_tokens must be at least 1 (leave the tier out for no limit)" })),
+        ));
+    }
+    let stored = serde_json::to_value(&budgets).unwrap_or_default();
+    sqlx::query("UPDATE ch_settings SET tier_budgets = $1, updated_at = NOW() WHERE id = 1")
+        .bind(&stored)
+        .execute(&state.db)
+        .await
+        .map_err(|e| {
+            tracing::error!("tier_budgets: failed to store budgets: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Failed to update tier budgets" })),
+            )
+        })?;
+    set(budgets);
+    crate::cluster::publish(
+        &state,
+        crate::cluster::ClusterEvent::TierBudgets {
+            budgets: stored.clone(),
+        },
+    );
+    crate::audit::log_audit(&state.db, "set_tier_budgets", stored, None).await;
+    Ok(get_tier_budgets(State(state)).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn budget(daily_tokens: u64, on_exhausted: OnExhausted) -> TierBudget {
+        TierBudget {
+            daily_tokens,
+            on_exhausted,
+        }
+    }
+
+    #[test]
+    fn models_map_to_tiers() {
+        assert_eq!(Tier::of_model("claude-opus-4-6"), Some(Tier::Commander));
+        assert_eq!(Tier::of_model("claude-haiku-4-5-20251001"), Some(Tier::Executor));
+        assert_eq!(Tier::of_model("gemini-2.5-pro"), None);
+    }
+
+    #[test]
+    fn exhausted_tiers_downgrade_or_reject() {
+        let mut budgets = TierBudgets::new();
+        budgets.insert(Tier::Commander, budget(1_000, OnExhausted::Downgrade));
+        budgets.insert(Tier::Coordinator, budget(5_000, OnExhausted::Downgrade));
+        let mut usage = BTreeMap::from([(Tier::Commander, 1_000), (Tier::Coordinator, 10)]);
+
+        assert_eq!(decide(Tier::Coordinator, &budgets, &usage), Decision::Allow);
+        assert_eq!(decide(Tier::Commander, &budgets, &usage), Decision::Downgrade(Tier::Coordinator));
+
+        // Both spent: Opus falls through to Haiku (no Executor budget).
+        usage.insert(Tier::Coordinator, 5_000);
+        assert_eq!(decide(Tier::Commander, &budgets, &usage), Decision::Downgrade(Tier::Executor));
+
+        budgets.insert(Tier::Commander, budget(1_000, OnExhausted::Reject));
+        assert!(matches!(
+            decide(Tier::Commander, &budgets, &usage),
+            Decision::Reject { tier: Tier::Commander, used: 1_000, budget: 1_000 }
+        ));
+    }
+
+    #[test]
+    fn executor_has_nothing_below() {
+        let budgets = TierBudgets::from([(Tier::Executor, budget(10, OnExhausted::Downgrade))]);
+        let usage = BTreeMap::from([(Tier::Executor, 11)]);
+        assert!(matches!(decide(Tier::Executor, &budgets, &usage), Decision::Reject { .. }));
+    }
+}
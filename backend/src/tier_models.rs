@@ -0,0 +1,203 @@
+// ClaudeHydra v4 -- Model per agent tier
+// Which model each agent tier (Commander, Coordinator, Executor) runs on.
+// A tier left out follows the model registry: its pinned model, else the
+// newest model of its family, else the built-in default below.
+//
+// Stored in ch_settings.tier_models (synced across replicas), shown as
+// `tier_models` in GET /api/settings and changed with PUT /api/agents/tiers.
+// When a tier's model changes, its agents still on the previous model move
+// to the new one; agents given a model of their own keep it.
+
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+use axum::Json;
+use axum::extract::State;
+use axum::http::StatusCode;
+use serde_json::{Value, json};
+
+use crate::state::AppState;
+use crate::tier_budgets::Tier;
+
+type ApiError = (StatusCode, Json<Value>);
+
+/// Model id per tier; a tier left out follows the model registry.
+pub type TierModels = BTreeMap<Tier, String>;
+
+static TIER_MODELS: RwLock<TierModels> = RwLock::new(BTreeMap::new());
+
+pub fn set(models: TierModels) {
+    if let Ok(mut current) = TIER_MODELS.write() {
+        *current = models;
+    }
+}
+
+pub fn all() -> TierModels {
+    TIER_MODELS.read().map(|m| m.clone()).unwrap_or_default()
+}
+
+/// Model configured for `tier`, if any.
+pub fn configured(tier: Tier) -> Option<String> {
+    TIER_MODELS.read().ok()?.get(&tier).cloned()
+}
+
+/// Read the mapping from the DB (at startup and on other replicas' changes).
+pub async fn load(db: &sqlx::PgPool) {
+    match sqlx::query_scalar::<_, Option<Value>>("SELECT tier_models FROM ch_settings WHERE id = 1")
+        .fetch_optional(db)
+        .await
+    {
+        Ok(stored) => set(
+            stored
+                .flatten()
+                .and_then(|v| serde_json::from_value(v).ok())
+                .unwrap_or_default(),
+        ),
+        Err(e) => tracing::warn!("tier_models: failed to load tier models: {}", e),
+    }
+}
+
+/// Tier by agent tier name or registry use case, in any case.
+pub fn parse_tier(name: &str) -> Option<Tier> {
+    Tier::ALL
+        .into_iter()
+        .find(|t| t.use_case().eq_ignore_ascii_case(name.trim()))
+}
+
+/// Model used when nothing is configured or resolved for `tier`.
+pub fn builtin_default(tier: Tier) -> &'static str {
+    match tier {
+        Tier::Commander => "claude-opus-4-6",
+        Tier::Coordinator => "claude-sonnet-4-6",
+        Tier::Executor => "claude-haiku-4-5-20251001",
+    }
+}
+
+/// Model of a new agent in tier `tier` (unknown tiers get the Coordinator's).
+pub fn agent_model(tier: &str) -> String {
+    let tier = parse_tier(tier).unwrap_or(Tier::Coordinator);
+    configured(tier).unwrap_or_else(|| builtin_default(tier).to_string())
+}
+
+/// Effective model and configured model of every tier.
+async fn tiers_json(state: &AppState) -> Value {
+    let mut tiers = serde_json::Map::new();
+    for tier in Tier::ALL {
+        tiers.insert(
+            format!("{:?}", tier),
+            json!({
+                "model": crate::model_registry::get_model_id(state, tier.use_case()).await,
+                "configured": configured(tier),
+            }),
+        );
+    }
+    Value::Object(tiers)
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  /api/agents/tiers
+// ═══════════════════════════════════════════════════════════════════════
+
+/// `GET /api/agents/tiers` — model used by each tier
+#[utoipa::path(get, path = "/api/agents/tiers", tag = "agents",
+    responses((status = 200, description = "Effective and configured model per tier")))]
+pub async fn get_tier_models(State(state): State<AppState>) -> Json<Value> {
+    Json(json!({ "tiers": tiers_json(&state).await }))
+}
+
+/// `PUT /api/agents/tiers` — replace the tier → model mapping (`{}` resets it)
+#[utoipa::path(put, path = "/api/agents/tiers", tag = "agents",
+    request_body = Value,
+    responses(
+        (status = 200, description = "Mapping updated, agents moved to the new models"),
+        (status = 400, description = "Empty model id")
+    ))]
+pub async fn set_tier_models(
+    State(state): State<AppState>,
+    Json(models): Json<TierModels>,
+) -> Result<Json<Value>, ApiError> {
+    if models.values().any(|m| m.trim().is_empty()) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "Model ids must not be empty (leave a tier out to follow the registry)" })),
+        ));
+    }
+    let models: TierModels = models.into_iter().map(|(t, m)| (t, m.trim().to_string())).collect();
+
+    let mut before = BTreeMap::new();
+    for tier in Tier::ALL {
+        before.insert(tier, crate::model_registry::get_model_id(&state, tier.use_case()).await);
+    }
+    let stored = serde_json::to_value(&models).unwrap_or_default();
+    sqlx::query("UPDATE ch_settings SET tier_models = $1, updated_at = NOW() WHERE id = 1")
+        .bind(&stored)
+        .execute(&state.db)
+        .await
+        .map_err(|e| {
+            tracing::error!("tier_models: failed to store tier models: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Failed to update tier models" })),
+            )
+        })?;
+    set(models);
+    crate::cluster::publish(&state, crate::cluster::ClusterEvent::TierModels { models: stored.clone() });
+
+    // Agents that followed the tier (on its previous or built-in model) move along.
+    let mut agents_updated = 0;
+    for (tier, previous) in before {
+        let model = crate::model_registry::get_model_id(&state, tier.use_case()).await;
+        if model == previous {
+            continue;
+        }
+        let followed = vec![previous, builtin_default(tier).to_string()];
+        match sqlx::query(
+            "UPDATE ch_agents_config SET model = $1, updated_at = now() \
+             WHERE tier = $2 AND model = ANY($3) AND model <> $1",
+        )
+        .bind(&model)
+        .bind(format!("{:?}", tier))
+        .bind(&followed)
+        .execute(&state.db)
+        .await
+        {
+            Ok(r) => agents_updated += r.rows_affected(),
+            Err(e) => tracing::error!("tier_models: failed to move {:?} agents to {}: {}", tier, model, e),
+        }
+    }
+    if agents_updated > 0 {
+        state.refresh_agents().await;
+        crate::cluster::publish(&state, crate::cluster::ClusterEvent::AgentsChanged);
+    }
+
+    crate::audit::log_audit(
+        &state.db,
+        "set_tier_models",
+        json!({ "tier_models": stored, "agents_updated": agents_updated }),
+        None,
+    )
+    .await;
+    Ok(Json(json!({
+        "tiers": tiers_json(&state).await,
+        "agents_updated": agents_updated,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tiers_parse_from_agent_tiers_and_use_cases() {
+        assert_eq!(parse_tier("Executor"), Some(Tier::Executor));
+        assert_eq!(parse_tier("commander"), Some(Tier::Commander));
+        assert_eq!(parse_tier("flash"), None);
+    }
+
+    #[test]
+    fn mapping_uses_tier_names_as_keys() {
+        let models: TierModels = serde_json::from_value(json!({ "Executor": "claude-haiku-4-5" })).unwrap();
+        assert_eq!(models.get(&Tier::Executor).map(String::as_str), Some("claude-haiku-4-5"));
+        assert!(serde_json::from_value::<TierModels>(json!({ "Intern": "x" })).is_err());
+    }
+}
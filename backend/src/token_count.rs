@@ -0,0 +1,286 @@
+// ClaudeHydra v4 -- Token counting and context pre-flight
+// `POST /api/token-count` counts the input tokens of a message list: through
+// Anthropic's `/v1/messages/count_tokens` for Claude models when a direct
+// credential is available, otherwise with the local estimate (see tokenizer,
+// plus a small per-message overhead). `/api/tokens*` belongs to the shared
+// token routes, hence the path.
+//
+// Session chat runs the same check (local estimate, no extra upstream call)
+// before sending: a context that leaves no room for `max_tokens` within the
+// model's window is rejected with 413, and one above the soft limit
+// (CH_SOFT_LIMIT_CONTEXT_PCT, see soft_limits) is answered with a warning
+// (`context_warning` / `X-Context-Warning`).
+
+use axum::Json;
+use axum::extract::State;
+use axum::http::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use utoipa::ToSchema;
+
+use crate::models::{ChatMessage, ChatRequest};
+use crate::state::AppState;
+
+const CLAUDE_CONTEXT_WINDOW: u64 = 200_000;
+const GEMINI_CONTEXT_WINDOW: u64 = 1_048_576;
+/// Role markers and message framing, per message.
+const MESSAGE_OVERHEAD_TOKENS: u64 = 4;
+/// Response header carrying the warning on streaming endpoints.
+pub const WARNING_HEADER: &str = "x-context-warning";
+
+type ApiError = (StatusCode, Json<Value>);
+
+/// Input window of `model`, in tokens.
+pub fn context_window(model: &str) -> u64 {
+    if model.starts_with("gemini-") {
+        GEMINI_CONTEXT_WINDOW
+    } else {
+        CLAUDE_CONTEXT_WINDOW
+    }
+}
+
+/// Local estimate for `text`.
+pub fn estimate_text(text: &str) -> u64 {
+    crate::tokenizer::count(text)
+}
+
+/// Local estimate for a system prompt plus messages (attachments excluded).
+pub fn estimate(system: &str, messages: &[ChatMessage]) -> u64 {
+    estimate_text(system)
+        + messages
+            .iter()
+            .map(|m| estimate_text(&m.content) + MESSAGE_OVERHEAD_TOKENS)
+            .sum::<u64>()
+}
+
+/// Local estimate for a Messages API body (`system` plus text blocks).
+pub fn estimate_body(body: &Value) -> u64 {
+    fn text(v: &Value) -> u64 {
+        match v {
+            Value::String(s) => estimate_text(s),
+            Value::Array(blocks) => blocks
+                .iter()
+                .filter_map(|b| b.get("text").and_then(|t| t.as_str()))
+                .map(estimate_text)
+                .sum(),
+            _ => 0,
+        }
+    }
+    let system = body.get("system").map(text).unwrap_or(0);
+    let messages = body
+        .get("messages")
+        .and_then(|m| m.as_array())
+        .map(|msgs| {
+            msgs.iter()
+                .map(|m| m.get("content").map(text).unwrap_or(0) + MESSAGE_OVERHEAD_TOKENS)
+                .sum::<u64>()
+        })
+        .unwrap_or(0);
+    system + messages
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ContextCheck {
+    pub model: String,
+    pub input_tokens: u64,
+    pub max_tokens: u64,
+    pub context_window: u64,
+    /// `anthropic` (counted upstream) or `estimate`.
+    pub method: &'static str,
+}
+
+impl ContextCheck {
+    pub fn fits(&self) -> bool {
+        self.input_tokens + self.max_tokens <= self.context_window
+    }
+
+    pub fn near_limit(&self) -> bool {
+        crate::soft_limits::threshold(crate::soft_limits::Kind::Context).is_some_and(|ratio| {
+            (self.input_tokens + self.max_tokens) as f64 > self.context_window as f64 * ratio
+        })
+    }
+
+    pub fn warning(&self) -> Option<String> {
+        self.near_limit().then(|| {
+            format!(
+                "Context uses ~{} of {} tokens for {} (with max_tokens {}); consider compacting the session",
+                self.input_tokens, self.context_window, self.model, self.max_tokens
+            )
+        })
+    }
+}
+
+/// Pre-flight check of a chat request as it would be sent (system prompt
+/// resolved, caller `system` messages moved into it). 413 when it cannot fit.
+pub async fn preflight(state: &AppState, req: &ChatRequest) -> Result<ContextCheck, ApiError> {
+    let ctx = crate::handlers::prompt::resolve_chat_context(state, req).await;
+    let messages: Vec<ChatMessage> = req.messages.iter().filter(|m| m.role != "system").cloned().collect();
+    let check = ContextCheck {
+        input_tokens: estimate(&ctx.system_prompt, &messages),
+        max_tokens: ctx.max_tokens as u64,
+        context_window: context_window(&ctx.model),
+        model: ctx.model,
+        method: "estimate",
+    };
+    if !check.fits() {
+        return Err((
+            StatusCode::PAYLOAD_TOO_LARGE,
+            Json(json!({
+                "error": "The conversation no longer fits the model's context window; compact the session or lower max_tokens",
+                "context": check,
+            })),
+        ));
+    }
+    Ok(check)
+}
+
+/// Ask Anthropic to count; `None` when no direct credential is configured
+/// (Vault-delegated calls only go to `/v1/messages`) or the call fails.
+async fn count_upstream(state: &AppState, body: &Value) -> Option<u64> {
+    let (credential, is_oauth) = crate::handlers::get_anthropic_credential(state).await?;
+    if credential == "__vault_managed__" {
+        return None;
+    }
+    let mut req = crate::http_client::client(state)
+        .post(format!("{}/v1/messages/count_tokens", crate::handlers::anthropic_api_url()))
+        .timeout(crate::endpoint_policies::get(crate::endpoint_policies::Group::Models).timeout)
+        .header("anthropic-version", "2023-06-01");
+    req = if is_oauth {
+        req.header("authorization", format!("Bearer {}", credential))
+    } else {
+        req.header("x-api-key", credential)
+    };
+    let resp = req.json(body).send().await.ok()?;
+    if !resp.status().is_success() {
+        tracing::warn!("token_count: count_tokens returned {}", resp.status());
+        return None;
+    }
+    resp.json::<Value>().await.ok()?.get("input_tokens")?.as_u64()
+}
+
+/// Request body for `POST /api/token-count`.
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct TokenCountRequest {
+    pub messages: Vec<ChatMessage>,
+    /// Defaults to the configured default model.
+    #[serde(default)]
+    pub model: Option<String>,
+    /// System prompt to count as given; when absent, the prompt a chat
+    /// request would get (layers) is counted.
+    #[serde(default)]
+    pub system: Option<String>,
+    #[serde(default)]
+    pub agent_id: Option<String>,
+    #[serde(default)]
+    pub session_id: Option<String>,
+    /// Output budget to check against the window.
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+    /// `false` skips the upstream count and only estimates.
+    #[serde(default = "default_true")]
+    pub upstream: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// `POST /api/token-count` — input tokens of a message list
+#[utoipa::path(post, path = "/api/token-count", tag = "chat",
+    request_body = TokenCountRequest,
+    responses(
+        (status = 200, description = "Token count and whether it fits the model's window"),
+        (status = 400, description = "No messages")
+    ))]
+pub async fn count_tokens(
+    State(state): State<AppState>,
+    Json(body): Json<TokenCountRequest>,
+) -> Result<Json<Value>, ApiError> {
+    if body.messages.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, Json(json!({ "error": "messages must not be empty" }))));
+    }
+    let req = ChatRequest {
+        messages: body.messages,
+        model: body.model,
+        temperature: None,
+        max_tokens: body.max_tokens,
+        stream: None,
+        tools_enabled: None,
+        session_id: body.session_id,
+        agent_id: body.agent_id,
+        stop_sequences: None,
+        priority: None,
+    };
+    let ctx = crate::handlers::prompt::resolve_chat_context(&state, &req).await;
+    let system = body.system.unwrap_or(ctx.system_prompt);
+    let messages: Vec<ChatMessage> = req.messages.into_iter().filter(|m| m.role != "system").collect();
+
+    let mut check = ContextCheck {
+        input_tokens: estimate(&system, &messages),
+        max_tokens: ctx.max_tokens as u64,
+        context_window: context_window(&ctx.model),
+        model: ctx.model.clone(),
+        method: "estimate",
+    };
+    if body.upstream && !ctx.model.starts_with("gemini-") {
+        let api_messages: Vec<Value> = messages
+            .iter()
+            .map(|m| json!({ "role": m.role, "content": m.content }))
+            .collect();
+        let mut count_body = json!({ "model": ctx.model, "messages": api_messages });
+        if !system.is_empty() {
+            count_body["system"] = json!(system);
+        }
+        if let Some(tokens) = count_upstream(&state, &count_body).await {
+            check.input_tokens = tokens;
+            check.method = "anthropic";
+        }
+    }
+
+    Ok(Json(json!({
+        "model": check.model,
+        "input_tokens": check.input_tokens,
+        "method": check.method,
+        "tokenizer": (check.method == "estimate").then(|| crate::tokenizer::active().name()),
+        "max_tokens": check.max_tokens,
+        "context_window": check.context_window,
+        "fits": check.fits(),
+        "warning": check.warning(),
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(input_tokens: u64, max_tokens: u64) -> ContextCheck {
+        ContextCheck {
+            model: "claude-sonnet-4-6".to_string(),
+            input_tokens,
+            max_tokens,
+            context_window: context_window("claude-sonnet-4-6"),
+            method: "estimate",
+        }
+    }
+
+    #[test]
+    fn estimate_counts_characters_and_framing() {
+        let messages = vec![ChatMessage {
+            role: "user".to_string(),
+            content: "abcdefgh".to_string(),
+            attachments: Vec::new(),
+            model: None,
+            timestamp: None,
+        }];
+        assert_eq!(estimate("abc", &messages), 1 + 2 + MESSAGE_OVERHEAD_TOKENS);
+    }
+
+    #[test]
+    fn limits_include_the_output_budget() {
+        assert!(check(150_000, 4_096).fits());
+        assert!(check(150_000, 4_096).warning().is_none());
+        assert!(check(170_000, 4_096).warning().is_some());
+        assert!(!check(197_000, 4_096).fits());
+        assert_eq!(context_window("gemini-2.5-pro"), GEMINI_CONTEXT_WINDOW);
+    }
+}
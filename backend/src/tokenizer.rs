@@ -0,0 +1,139 @@
+// ClaudeHydra v4 -- Local token estimates
+// Context pre-flight, soft limits, compaction and `POST /api/token-count`
+// (unless it counts upstream) estimate tokens locally, without a call to
+// Anthropic, with the `Tokenizer` chosen by CH_TOKENIZER:
+//   - approx (default) — splits text roughly like Claude's BPE does: words
+//     (long ones in several pieces), groups of up to 3 digits, single
+//     punctuation marks, newlines, runs of indentation and CJK characters,
+//   - chars            — ~4 characters per token (the earlier estimate).
+// Exact counts still come from `/v1/messages/count_tokens` (token_count).
+
+use std::sync::OnceLock;
+
+/// Bytes of a word (letters only) covered by one token.
+const WORD_BYTES_PER_TOKEN: u64 = 7;
+const DIGITS_PER_TOKEN: u64 = 3;
+const CHARS_PER_TOKEN: u64 = 4;
+
+/// Counts tokens of text locally.
+pub trait Tokenizer: Send + Sync {
+    /// Name reported with estimates.
+    fn name(&self) -> &'static str;
+    /// Estimated tokens of `text`.
+    fn count(&self, text: &str) -> u64;
+}
+
+/// ~4 characters per token.
+pub struct CharEstimate;
+
+impl Tokenizer for CharEstimate {
+    fn name(&self) -> &'static str {
+        "chars"
+    }
+
+    fn count(&self, text: &str) -> u64 {
+        (text.chars().count() as u64).div_ceil(CHARS_PER_TOKEN)
+    }
+}
+
+/// Word / digit / punctuation split approximating Claude's tokenizer.
+pub struct Approx;
+
+/// A letter that stays inside a word (Latin, Greek, Cyrillic, …).
+fn is_word_letter(c: char) -> bool {
+    c.is_alphabetic() && c.len_utf8() < 3
+}
+
+impl Tokenizer for Approx {
+    fn name(&self) -> &'static str {
+        "approx"
+    }
+
+    fn count(&self, text: &str) -> u64 {
+        let mut tokens = 0u64;
+        let mut word_bytes = 0u64;
+        let mut digits = 0u64;
+        let mut spaces = 0u32;
+        for c in text.chars() {
+            if word_bytes > 0 && !is_word_letter(c) {
+                tokens += word_bytes.div_ceil(WORD_BYTES_PER_TOKEN);
+                word_bytes = 0;
+            }
+            if digits > 0 && !c.is_ascii_digit() {
+                tokens += digits.div_ceil(DIGITS_PER_TOKEN);
+                digits = 0;
+            }
+            if c != ' ' {
+                // One space joins the next token; longer runs are a token.
+                tokens += u64::from(spaces > 1);
+                spaces = 0;
+            }
+            match c {
+                ' ' => spaces += 1,
+                c if is_word_letter(c) => word_bytes += c.len_utf8() as u64,
+                c if c.is_ascii_digit() => digits += 1,
+                '\r' => {}
+                _ => tokens += 1,
+            }
+        }
+        tokens
+            + word_bytes.div_ceil(WORD_BYTES_PER_TOKEN)
+            + digits.div_ceil(DIGITS_PER_TOKEN)
+            + u64::from(spaces > 1)
+    }
+}
+
+/// Tokenizer called `name`, if there is one.
+pub fn by_name(name: &str) -> Option<Box<dyn Tokenizer>> {
+    match name.trim().to_ascii_lowercase().as_str() {
+        "approx" => Some(Box::new(Approx)),
+        "chars" => Some(Box::new(CharEstimate)),
+        _ => None,
+    }
+}
+
+static ACTIVE: OnceLock<Box<dyn Tokenizer>> = OnceLock::new();
+
+/// The tokenizer chosen by CH_TOKENIZER (default `approx`).
+pub fn active() -> &'static dyn Tokenizer {
+    ACTIVE
+        .get_or_init(|| {
+            let name = std::env::var("CH_TOKENIZER").unwrap_or_default();
+            if name.trim().is_empty() {
+                return Box::new(Approx);
+            }
+            by_name(&name).unwrap_or_else(|| {
+                tracing::warn!("tokenizer: unknown CH_TOKENIZER '{}', using approx", name);
+                Box::new(Approx)
+            })
+        })
+        .as_ref()
+}
+
+/// Estimated tokens of `text` with the active tokenizer.
+pub fn count(text: &str) -> u64 {
+    active().count(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn approx_splits_words_digits_and_symbols() {
+        assert_eq!(Approx.count(""), 0);
+        assert_eq!(Approx.count("Hello, world!"), 4);
+        assert_eq!(Approx.count("1234567"), 3);
+        assert_eq!(Approx.count("internationalization"), 3);
+        assert_eq!(Approx.count("你好世界"), 4);
+        assert_eq!(Approx.count("fn main() {\r\n    x\n}"), 10);
+    }
+
+    #[test]
+    fn tokenizers_are_picked_by_name() {
+        assert_eq!(by_name("CHARS").map(|t| t.name()), Some("chars"));
+        assert_eq!(by_name("approx").map(|t| t.count("abcdefgh")), Some(2));
+        assert!(by_name("tiktoken").is_none());
+        assert_eq!(CharEstimate.count("abcdefgh"), 2);
+    }
+}
@@ -0,0 +1,392 @@
+// ClaudeHydra v4 -- Human-in-the-loop tool confirmation
+// Tools can be flagged "require confirmation" (ch_tool_policies, managed via
+// `/api/admin/tool-policies`). When the model asks for such a tool the agent
+// loop pauses and emits a confirmation-required event:
+//   - NDJSON streams get a `{"type":"tool_confirmation", ...}` frame,
+//   - WebSocket clients get a `tool_confirmation` message.
+// The call runs only after `POST /api/tool-calls/{id}/approve`;
+// `POST /api/tool-calls/{id}/reject` (or the confirmation timeout) hands the
+// model an error result instead, so it can change course.
+//
+// Pending calls live in memory on the replica running the agent loop.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tokio::sync::{RwLock, oneshot};
+
+use crate::request_scope::{self, StreamEvent};
+use crate::state::AppState;
+
+/// How often a waiting NDJSON stream is told the loop is still alive.
+const KEEPALIVE_SECS: u64 = 15;
+const MAX_REASON_CHARS: usize = 500;
+
+/// How long a call waits for a decision before it is rejected.
+fn confirmation_timeout() -> Duration {
+    let secs = std::env::var("TOOL_CONFIRMATION_TIMEOUT_SECS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .filter(|s| *s > 0)
+        .unwrap_or(600);
+    Duration::from_secs(secs)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PendingToolCall {
+    pub id: uuid::Uuid,
+    pub tool: String,
+    pub input: Value,
+    pub requested_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl PendingToolCall {
+    /// NDJSON frame announcing the call to the streaming client.
+    pub fn ndjson_frame(&self) -> String {
+        json!({
+            "token": "",
+            "done": false,
+            "type": "tool_confirmation",
+            "tool_call_id": self.id,
+            "tool_name": self.tool,
+            "tool_input": self.input,
+            "expires_at": self.expires_at,
+        })
+        .to_string()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Decision {
+    Approved,
+    Rejected(Option<String>),
+    TimedOut,
+}
+
+struct Pending {
+    call: PendingToolCall,
+    tx: oneshot::Sender<Decision>,
+}
+
+/// Confirmation policies plus the calls currently waiting for a decision.
+#[derive(Default)]
+pub struct ToolConfirmations {
+    pending: Mutex<HashMap<uuid::Uuid, Pending>>,
+    /// Tools that need confirmation; loaded from the DB on first use.
+    required: RwLock<Option<HashSet<String>>>,
+}
+
+impl ToolConfirmations {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    pub async fn requires(&self, db: &sqlx::PgPool, tool: &str) -> bool {
+        if let Some(required) = self.required.read().await.as_ref() {
+            return required.contains(tool);
+        }
+        let mut guard = self.required.write().await;
+        if guard.is_none() {
+            let loaded = sqlx::query_scalar::<_, String>(
+                "SELECT tool_name FROM ch_tool_policies WHERE require_confirmation",
+            )
+            .fetch_all(db)
+            .await;
+            match loaded {
+                Ok(names) => *guard = Some(names.into_iter().collect()),
+                Err(e) => {
+                    // Not cached, so the next call retries the load.
+                    tracing::warn!("tool_confirmation: failed to load policies: {}", e);
+                    return false;
+                }
+            }
+        }
+        guard.as_ref().is_some_and(|required| required.contains(tool))
+    }
+
+    /// Update the cached policy after a write.
+    async fn set_required(&self, tool: &str, required: bool) {
+        if let Some(set) = self.required.write().await.as_mut() {
+            if required {
+                set.insert(tool.to_string());
+            } else {
+                set.remove(tool);
+            }
+        }
+    }
+
+    /// Register a call that needs a decision.
+    pub fn open(&self, tool: &str, input: &Value) -> (PendingToolCall, oneshot::Receiver<Decision>) {
+        let now = Utc::now();
+        let call = PendingToolCall {
+            id: uuid::Uuid::new_v4(),
+            tool: tool.to_string(),
+            input: input.clone(),
+            requested_at: now,
+            expires_at: now
+                + chrono::Duration::from_std(confirmation_timeout()).unwrap_or_default(),
+        };
+        let (tx, rx) = oneshot::channel();
+        if let Ok(mut pending) = self.pending.lock() {
+            pending.insert(call.id, Pending { call: call.clone(), tx });
+        }
+        (call, rx)
+    }
+
+    /// Deliver a decision. Returns `false` when the call is not waiting.
+    pub fn resolve(&self, id: uuid::Uuid, decision: Decision) -> bool {
+        let entry = self.pending.lock().ok().and_then(|mut p| p.remove(&id));
+        entry.is_some_and(|p| p.tx.send(decision).is_ok())
+    }
+
+    pub fn list(&self) -> Vec<PendingToolCall> {
+        let mut calls: Vec<PendingToolCall> = self
+            .pending
+            .lock()
+            .map(|p| p.values().map(|p| p.call.clone()).collect())
+            .unwrap_or_default();
+        calls.sort_by_key(|c| c.requested_at);
+        calls
+    }
+
+    /// Wait for the decision on `id`, calling `keepalive` periodically.
+    pub async fn wait(
+        &self,
+        id: uuid::Uuid,
+        mut rx: oneshot::Receiver<Decision>,
+        keepalive: impl Fn(),
+    ) -> Decision {
+        let deadline = tokio::time::sleep(confirmation_timeout());
+        tokio::pin!(deadline);
+        let mut tick = tokio::time::interval(Duration::from_secs(KEEPALIVE_SECS));
+        tick.tick().await;
+        let decision = loop {
+            tokio::select! {
+                decision = &mut rx => break decision.unwrap_or(Decision::TimedOut),
+                _ = &mut deadline => break Decision::TimedOut,
+                _ = tick.tick() => keepalive(),
+            }
+        };
+        if let Ok(mut pending) = self.pending.lock() {
+            pending.remove(&id);
+        }
+        decision
+    }
+}
+
+/// Tool result handed to the model when a call is not approved.
+pub fn refusal(tool: &str, decision: &Decision) -> String {
+    match decision {
+        Decision::Approved => String::new(),
+        Decision::Rejected(Some(reason)) => {
+            format!("The user rejected the '{}' tool call: {}", tool, reason)
+        }
+        Decision::Rejected(None) => format!("The user rejected the '{}' tool call.", tool),
+        Decision::TimedOut => format!(
+            "The '{}' tool call was not confirmed within {}s and was not executed.",
+            tool,
+            confirmation_timeout().as_secs()
+        ),
+    }
+}
+
+/// Pause until the user approves `tool`, if it requires confirmation.
+/// The confirmation request goes to the active stream (if any); `Err` carries
+/// the tool result to return instead of executing.
+pub async fn gate(state: &AppState, tool: &str, input: &Value) -> Result<(), String> {
+    let hub = &state.tool_confirmations;
+    if !hub.requires(&state.db, tool).await {
+        return Ok(());
+    }
+    let (call, rx) = hub.open(tool, input);
+    tracing::info!("tool_confirmation: '{}' awaiting approval ({})", tool, call.id);
+    request_scope::emit(StreamEvent::Frame(call.ndjson_frame()));
+    let decision = hub
+        .wait(call.id, rx, || {
+            request_scope::emit(StreamEvent::KeepAlive);
+        })
+        .await;
+    match decision {
+        Decision::Approved => Ok(()),
+        other => Err(refusal(tool, &other)),
+    }
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  /api/tool-calls + /api/admin/tool-policies
+// ═══════════════════════════════════════════════════════════════════════
+
+fn parse_call_id(id: &str) -> Result<uuid::Uuid, (StatusCode, Json<Value>)> {
+    uuid::Uuid::parse_str(id).map_err(|_| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "Invalid tool call id" })),
+        )
+    })
+}
+
+fn not_waiting() -> (StatusCode, Json<Value>) {
+    (
+        StatusCode::NOT_FOUND,
+        Json(json!({ "error": "Tool call is not awaiting confirmation" })),
+    )
+}
+
+/// `GET /api/tool-calls/pending` — tool calls waiting for a decision
+pub async fn list_pending(State(state): State<AppState>) -> Json<Value> {
+    Json(json!({ "pending": state.tool_confirmations.list() }))
+}
+
+/// `POST /api/tool-calls/{id}/approve` — let a paused tool call run
+pub async fn approve_tool_call(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let id = parse_call_id(&id)?;
+    if !state.tool_confirmations.resolve(id, Decision::Approved) {
+        return Err(not_waiting());
+    }
+    Ok(Json(json!({ "id": id, "status": "approved" })))
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct RejectRequest {
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+/// `POST /api/tool-calls/{id}/reject` — refuse a paused tool call (optional `reason` is shown to the model)
+pub async fn reject_tool_call(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    body: Option<Json<RejectRequest>>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let id = parse_call_id(&id)?;
+    let reason = body
+        .and_then(|Json(b)| b.reason)
+        .map(|r| r.trim().chars().take(MAX_REASON_CHARS).collect::<String>())
+        .filter(|r| !r.is_empty());
+    if !state.tool_confirmations.resolve(id, Decision::Rejected(reason)) {
+        return Err(not_waiting());
+    }
+    Ok(Json(json!({ "id": id, "status": "rejected" })))
+}
+
+/// `GET /api/admin/tool-policies` — tools flagged as requiring confirmation
+pub async fn list_policies(
+    State(state): State<AppState>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let rows = sqlx::query_as::<_, (String, bool, DateTime<Utc>)>(
+        "SELECT tool_name, require_confirmation, updated_at FROM ch_tool_policies ORDER BY tool_name",
+    )
+    .fetch_all(&state.db)
+    .await
+    .map_err(|e| {
+        tracing::error!("tool_confirmation: failed to list policies: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": "Failed to load tool policies" })),
+        )
+    })?;
+    let policies: Vec<Value> = rows
+        .into_iter()
+        .map(|(tool, require_confirmation, updated_at)| {
+            json!({
+                "tool": tool,
+                "require_confirmation": require_confirmation,
+                "updated_at": updated_at,
+            })
+        })
+        .collect();
+    Ok(Json(json!({ "policies": policies })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ToolPolicyRequest {
+    pub require_confirmation: bool,
+}
+
+/// `PUT /api/admin/tool-policies/{tool}` — set a tool's `require_confirmation` flag
+pub async fn set_policy(
+    State(state): State<AppState>,
+    Path(tool): Path<String>,
+    Json(req): Json<ToolPolicyRequest>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let tool = tool.trim();
+    let valid = !tool.is_empty()
+        && tool.len() <= 128
+        && tool
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | ':'));
+    if !valid {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "Invalid tool name" })),
+        ));
+    }
+
+    sqlx::query(
+        "INSERT INTO ch_tool_policies (tool_name, require_confirmation) VALUES ($1, $2) \
+         ON CONFLICT (tool_name) DO UPDATE \
+         SET require_confirmation = EXCLUDED.require_confirmation, updated_at = NOW()",
+    )
+    .bind(tool)
+    .bind(req.require_confirmation)
+    .execute(&state.db)
+    .await
+    .map_err(|e| {
+        tracing::error!("tool_confirmation: failed to save policy for {}: {}", tool, e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": "Failed to save tool policy" })),
+        )
+    })?;
+    state
+        .tool_confirmations
+        .set_required(tool, req.require_confirmation)
+        .await;
+
+    Ok(Json(json!({
+        "tool": tool,
+        "require_confirmation": req.require_confirmation,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn decisions_reach_the_waiting_call() {
+        let hub = ToolConfirmations::new();
+        let (call, rx) = hub.open("write_file", &json!({ "path": "notes.md" }));
+        assert_eq!(hub.list().len(), 1);
+
+        let waiter = {
+            let hub = hub.clone();
+            tokio::spawn(async move { hub.wait(call.id, rx, || {}).await })
+        };
+        assert!(hub.resolve(call.id, Decision::Rejected(Some("not now".into()))));
+        assert_eq!(
+            waiter.await.unwrap(),
+            Decision::Rejected(Some("not now".into()))
+        );
+        assert!(hub.list().is_empty());
+        assert!(!hub.resolve(call.id, Decision::Approved));
+    }
+
+    #[test]
+    fn refusals_explain_themselves() {
+        let msg = refusal("write_file", &Decision::Rejected(None));
+        assert!(msg.contains("rejected") && msg.contains("write_file"));
+        assert!(refusal("write_file", &Decision::TimedOut).contains("not confirmed"));
+    }
+}
@@ -0,0 +1,575 @@
+// ClaudeHydra v4 -- Agent-loop transcripts with deterministic replay
+// Every NDJSON run (`/api/claude/chat/stream`) and every tool-enabled `/ws/chat`
+// run is recorded as a transcript: the resolved context (model, params, system
+// prompt, initial messages), each upstream request (only the messages added
+// since the previous one) and every tool call with its input and output.
+// The streaming response carries the id in `X-Transcript-Id`. When the run
+// ends, its final request becomes the reply's context (see message_context).
+//
+// `POST /api/transcripts/{id}/replay` re-runs the loop from the recorded
+// context, but tools are never executed: each call is answered with the
+// recorded result for the same tool + input (no live side effects). A model
+// that diverges from the recording gets an error result for the unknown call.
+// The replay is itself recorded (`source = "replay"`, `replay_of = id`), so
+// the two runs can be diffed step by step.
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::atomic::{AtomicI32, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Instant;
+
+use axum::Json;
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderName, HeaderValue, StatusCode};
+use axum::response::Response;
+use chrono::{DateTime, Utc};
+use jaskier_core::handlers::anthropic_streaming::{self, AnthropicChatContext};
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::request_scope::{self, RequestScope};
+use crate::state::AppState;
+
+pub const TRANSCRIPT_ID_HEADER: &str = "x-transcript-id";
+
+/// A tool result taken from a recorded transcript.
+#[derive(Debug, Clone)]
+struct RecordedTool {
+    name: String,
+    input: Value,
+    output: String,
+    is_error: bool,
+}
+
+#[derive(Debug)]
+pub struct Transcript {
+    pub id: uuid::Uuid,
+    db: sqlx::PgPool,
+    session_id: Option<uuid::Uuid>,
+    started_at: DateTime<Utc>,
+    /// Snapshot of the latest upstream request (see `message_context::snapshot`).
+    last_request: Mutex<Option<Value>>,
+    seq: AtomicI32,
+    /// Messages already recorded, so request steps only store the new ones.
+    recorded_messages: AtomicUsize,
+    /// Recorded tool results when this run is a replay.
+    tape: Option<Mutex<VecDeque<RecordedTool>>>,
+    /// Decision log of the request running this transcript (see `attach`).
+    decisions: OnceLock<request_scope::DecisionLog>,
+}
+
+impl Transcript {
+    /// Append a step. Writes are fire-and-forget; a failed write never
+    /// affects the run being recorded.
+    fn record(&self, kind: &'static str, data: Value) {
+        let seq = self.seq.fetch_add(1, Ordering::Relaxed);
+        let db = self.db.clone();
+        let id = self.id;
+        tokio::spawn(async move {
+            if let Err(e) = sqlx::query(
+                "INSERT INTO ch_transcript_steps (transcript_id, seq, kind, data) \
+                 VALUES ($1, $2, $3, $4)",
+            )
+            .bind(id)
+            .bind(seq)
+            .bind(kind)
+            .bind(&data)
+            .execute(&db)
+            .await
+            {
+                tracing::warn!("transcripts: failed to record {} step for {}: {}", kind, id, e);
+            }
+        });
+    }
+
+    /// Record an upstream Messages API request.
+    pub fn record_request(&self, body: &Value) {
+        let messages = body
+            .get("messages")
+            .and_then(|m| m.as_array())
+            .map(|m| m.as_slice())
+            .unwrap_or_default();
+        if let Ok(mut last) = self.last_request.lock() {
+            *last = Some(crate::message_context::snapshot(body));
+        }
+        let seen = self.recorded_messages.swap(messages.len(), Ordering::Relaxed);
+        // A shorter conversation is a different loop (e.g. a delegated agent)
+        let offset = if seen <= messages.len() { seen } else { 0 };
+        self.record(
+            "request",
+            json!({
+                "model": body.get("model"),
+                "max_tokens": body.get("max_tokens"),
+                "temperature": body.get("temperature"),
+                "stop_sequences": body.get("stop_sequences"),
+                "message_offset": offset,
+                "messages": &messages[offset..],
+            }),
+        );
+    }
+
+    pub fn last_request(&self) -> Option<Value> {
+        self.last_request.lock().ok().and_then(|l| l.clone())
+    }
+
+    /// Answer a tool call from the tape: the first unused recording of the
+    /// same tool with the same input.
+    fn replay_tool(&self, name: &str, input: &Value) -> (String, bool) {
+        let found = self.tape.as_ref().and_then(|tape| {
+            let mut tape = tape.lock().ok()?;
+            let pos = tape.iter().position(|t| t.name == name && t.input == *input)?;
+            tape.remove(pos)
+        });
+        match found {
+            Some(t) => (t.output, t.is_error),
+            None => (
+                format!(
+                    "[replay] No recorded result for '{}' with this input — the run diverged from the transcript.",
+                    name
+                ),
+                true,
+            ),
+        }
+    }
+}
+
+impl Drop for Transcript {
+    fn drop(&mut self) {
+        // The last scope holding the transcript is gone, so the run is over.
+        let Ok(handle) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let db = self.db.clone();
+        let id = self.id;
+        let reply = self.session_id.zip(self.last_request());
+        let decisions = Value::Array(self.decisions.get().map(|d| d.entries()).unwrap_or_default());
+        let since = self.started_at;
+        handle.spawn(async move {
+            let _ = sqlx::query("UPDATE ch_transcripts SET finished_at = NOW() WHERE id = $1")
+                .bind(id)
+                .execute(&db)
+                .await;
+            // No-op when the reply already has its context (WebSocket runs)
+            if let Some((session_id, request)) = reply {
+                crate::message_context::link_latest(db, session_id, since, Some(id), request, decisions);
+            }
+        });
+    }
+}
+
+/// Begin recording a run. Returns `None` (and the run goes unrecorded) when
+/// the transcript row cannot be written.
+pub async fn start(
+    state: &AppState,
+    source: &str,
+    ctx: &AnthropicChatContext,
+    messages: &[Value],
+    tools: bool,
+) -> Option<Arc<Transcript>> {
+    begin(state, source, ctx, messages, tools, None).await
+}
+
+async fn begin(
+    state: &AppState,
+    source: &str,
+    ctx: &AnthropicChatContext,
+    messages: &[Value],
+    tools: bool,
+    replay: Option<(uuid::Uuid, Vec<RecordedTool>)>,
+) -> Option<Arc<Transcript>> {
+    let stop_sequences = request_scope::current()
+        .map(|s| s.stop_sequences.clone())
+        .unwrap_or_default();
+    let context = json!({
+        "model": ctx.model,
+        "max_tokens": ctx.max_tokens,
+        "temperature": ctx.temperature,
+        "max_iterations": ctx.max_iterations,
+        "working_directory": ctx.working_directory,
+        "system_prompt": ctx.system_prompt,
+        "stop_sequences": stop_sequences,
+        "tools": tools,
+        "messages": messages,
+    });
+    let (replay_of, tape) = match replay {
+        Some((of, tape)) => (Some(of), Some(Mutex::new(tape.into()))),
+        None => (None, None),
+    };
+    let id = uuid::Uuid::new_v4();
+    let inserted = sqlx::query(
+        "INSERT INTO ch_transcripts (id, session_id, source, replay_of, model, context) \
+         VALUES ($1, $2, $3, $4, $5, $6)",
+    )
+    .bind(id)
+    .bind(ctx.session_id)
+    .bind(source)
+    .bind(replay_of)
+    .bind(&ctx.model)
+    .bind(&context)
+    .execute(&state.db)
+    .await;
+    if let Err(e) = inserted {
+        tracing::warn!("transcripts: failed to start transcript: {}", e);
+        return None;
+    }
+    Some(Arc::new(Transcript {
+        id,
+        db: state.db.clone(),
+        session_id: ctx.session_id,
+        started_at: Utc::now(),
+        last_request: Mutex::new(None),
+        seq: AtomicI32::new(0),
+        recorded_messages: AtomicUsize::new(messages.len()),
+        tape,
+        decisions: OnceLock::new(),
+    }))
+}
+
+/// The transcript recording the current request, if any.
+pub fn current() -> Option<Arc<Transcript>> {
+    request_scope::current().and_then(|s| s.transcript.get().cloned())
+}
+
+/// Put `transcript` into the current request scope, so upstream requests and
+/// tool calls made while the response streams are recorded.
+pub fn attach(transcript: &Arc<Transcript>) {
+    if let Some(scope) = request_scope::current() {
+        let _ = scope.transcript.set(transcript.clone());
+        let _ = transcript.decisions.set(scope.decisions.clone());
+    }
+}
+
+/// Add `X-Transcript-Id` to a streaming response.
+pub fn tag_response(mut response: Response, transcript: Option<&Arc<Transcript>>) -> Response {
+    if let Some(t) = transcript
+        && let Ok(v) = HeaderValue::from_str(&t.id.to_string())
+    {
+        response
+            .headers_mut()
+            .insert(HeaderName::from_static(TRANSCRIPT_ID_HEADER), v);
+    }
+    response
+}
+
+/// Run a tool call through `transcript`: recorded live, or answered from the
+/// tape without running `exec` when the transcript is a replay.
+pub async fn run_tool<F>(
+    transcript: Option<Arc<Transcript>>,
+    name: &str,
+    input: &Value,
+    exec: F,
+) -> (String, bool)
+where
+    F: Future<Output = (String, bool)>,
+{
+    let Some(t) = transcript else {
+        return exec.await;
+    };
+    let started = Instant::now();
+    let replayed = t.tape.is_some();
+    let (output, is_error) = if replayed {
+        t.replay_tool(name, input)
+    } else {
+        exec.await
+    };
+    t.record(
+        "tool",
+        json!({
+            "name": name,
+            "input": input,
+            "output": &output,
+            "is_error": is_error,
+            "duration_ms": started.elapsed().as_millis() as u64,
+            "replayed": replayed,
+        }),
+    );
+    (output, is_error)
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  /api/transcripts
+// ═══════════════════════════════════════════════════════════════════════
+
+fn parse_id(id: &str) -> Result<uuid::Uuid, (StatusCode, Json<Value>)> {
+    uuid::Uuid::parse_str(id).map_err(|_| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "Invalid transcript id" })),
+        )
+    })
+}
+
+fn db_error(e: sqlx::Error) -> (StatusCode, Json<Value>) {
+    tracing::error!("transcripts: query failed: {}", e);
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(json!({ "error": "Failed to load transcript" })),
+    )
+}
+
+fn not_found() -> (StatusCode, Json<Value>) {
+    (
+        StatusCode::NOT_FOUND,
+        Json(json!({ "error": "Transcript not found" })),
+    )
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TranscriptListQuery {
+    #[serde(default)]
+    pub session_id: Option<uuid::Uuid>,
+    #[serde(default)]
+    pub limit: Option<i64>,
+}
+
+type TranscriptRow = (
+    uuid::Uuid,
+    Option<uuid::Uuid>,
+    String,
+    Option<uuid::Uuid>,
+    String,
+    DateTime<Utc>,
+    Option<DateTime<Utc>>,
+);
+
+/// `TranscriptRow` preceded by the recorded context.
+type DetailRow = (
+    Value,
+    uuid::Uuid,
+    Option<uuid::Uuid>,
+    String,
+    Option<uuid::Uuid>,
+    String,
+    DateTime<Utc>,
+    Option<DateTime<Utc>>,
+);
+
+fn transcript_json(row: TranscriptRow) -> Value {
+    let (id, session_id, source, replay_of, model, started_at, finished_at) = row;
+    json!({
+        "id": id,
+        "session_id": session_id,
+        "source": source,
+        "replay_of": replay_of,
+        "model": model,
+        "started_at": started_at,
+        "finished_at": finished_at,
+    })
+}
+
+/// `GET /api/transcripts` — recent runs, newest first (`?session_id=&limit=`)
+pub async fn list_transcripts(
+    State(state): State<AppState>,
+    Query(q): Query<TranscriptListQuery>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let limit = q.limit.unwrap_or(50).clamp(1, 200);
+    let rows = sqlx::query_as::<_, TranscriptRow>(
+        "SELECT id, session_id, source, replay_of, model, started_at, finished_at \
+         FROM ch_transcripts \
+         WHERE ($1::uuid IS NULL OR session_id = $1) \
+         ORDER BY started_at DESC LIMIT $2",
+    )
+    .bind(q.session_id)
+    .bind(limit)
+    .fetch_all(&state.db)
+    .await
+    .map_err(db_error)?;
+    let transcripts: Vec<Value> = rows.into_iter().map(transcript_json).collect();
+    Ok(Json(json!({ "transcripts": transcripts })))
+}
+
+/// `GET /api/transcripts/{id}` — context and every recorded step
+pub async fn get_transcript(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let id = parse_id(&id)?;
+    let (context, row) = sqlx::query_as::<_, DetailRow>(
+        "SELECT context, id, session_id, source, replay_of, model, started_at, finished_at \
+         FROM ch_transcripts WHERE id = $1",
+    )
+    .bind(id)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(db_error)?
+    .map(|(context, a, b, c, d, e, f, g)| (context, (a, b, c, d, e, f, g)))
+    .ok_or_else(not_found)?;
+    let steps = sqlx::query_as::<_, (i32, String, Value, DateTime<Utc>)>(
+        "SELECT seq, kind, data, created_at FROM ch_transcript_steps \
+         WHERE transcript_id = $1 ORDER BY seq",
+    )
+    .bind(id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(db_error)?;
+
+    let mut transcript = transcript_json(row);
+    transcript["context"] = context;
+    transcript["steps"] = steps
+        .into_iter()
+        .map(|(seq, kind, data, created_at)| {
+            json!({ "seq": seq, "kind": kind, "data": data, "at": created_at })
+        })
+        .collect();
+    Ok(Json(transcript))
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ReplayRequest {
+    /// Re-run with another model (e.g. to see if it decides differently).
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub temperature: Option<f64>,
+}
+
+/// `POST /api/transcripts/{id}/replay` — re-run a recorded loop with recorded tool results (NDJSON)
+pub async fn replay_transcript(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    body: Option<Json<ReplayRequest>>,
+) -> Result<Response, (StatusCode, Json<Value>)> {
+    let id = parse_id(&id)?;
+    let req = body.map(|Json(b)| b).unwrap_or_default();
+    if let Some(t) = req.temperature
+        && !(0.0..=1.0).contains(&t)
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "temperature must be between 0 and 1" })),
+        ));
+    }
+
+    let (context,): (Value,) =
+        sqlx::query_as("SELECT context FROM ch_transcripts WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&state.db)
+            .await
+            .map_err(db_error)?
+            .ok_or_else(not_found)?;
+    let tools = sqlx::query_scalar::<_, Value>(
+        "SELECT data FROM ch_transcript_steps \
+         WHERE transcript_id = $1 AND kind = 'tool' ORDER BY seq",
+    )
+    .bind(id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(db_error)?;
+    let tape: Vec<RecordedTool> = tools
+        .into_iter()
+        .map(|t| RecordedTool {
+            name: t["name"].as_str().unwrap_or_default().to_string(),
+            input: t["input"].clone(),
+            output: t["output"].as_str().unwrap_or_default().to_string(),
+            is_error: t["is_error"].as_bool().unwrap_or(false),
+        })
+        .collect();
+
+    let Some(messages) = context["messages"].as_array().cloned() else {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(json!({ "error": "Transcript has no recorded context" })),
+        ));
+    };
+    let ctx = AnthropicChatContext {
+        model: req
+            .model
+            .unwrap_or_else(|| context["model"].as_str().unwrap_or_default().to_string()),
+        max_tokens: context["max_tokens"].as_u64().unwrap_or(4096) as u32,
+        temperature: req
+            .temperature
+            .or_else(|| context["temperature"].as_f64())
+            .unwrap_or(0.7),
+        max_iterations: context["max_iterations"].as_u64().unwrap_or(15) as usize,
+        working_directory: context["working_directory"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+        // Never write the replay into the original session
+        session_id: None,
+        system_prompt: context["system_prompt"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+    };
+    let stop_sequences: Vec<String> =
+        serde_json::from_value(context["stop_sequences"].clone()).unwrap_or_default();
+    let tools = context["tools"].as_bool().unwrap_or(true);
+
+    let scope = Arc::new(RequestScope {
+        stop_sequences,
+        ..Default::default()
+    });
+    request_scope::run(scope, async move {
+        let Some(transcript) =
+            begin(&state, "replay", &ctx, &messages, tools, Some((id, tape))).await
+        else {
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Failed to start replay transcript" })),
+            ));
+        };
+        attach(&transcript);
+        let response = if tools {
+            anthropic_streaming::anthropic_ndjson_stream_with_tools(&state, ctx, messages).await?
+        } else {
+            let prompt_len = messages
+                .iter()
+                .map(|m| m["content"].as_str().map_or(0, str::len))
+                .sum::<usize>();
+            anthropic_streaming::anthropic_ndjson_stream_no_tools(&state, &ctx, messages, prompt_len)
+                .await?
+        };
+        let response = tag_response(response, Some(&transcript));
+        Ok(crate::stream_relay::detach(&state, response))
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recorded(name: &str, input: Value, output: &str) -> RecordedTool {
+        RecordedTool {
+            name: name.to_string(),
+            input,
+            output: output.to_string(),
+            is_error: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn replay_answers_from_the_tape_by_tool_and_input() {
+        let transcript = Transcript {
+            id: uuid::Uuid::new_v4(),
+            db: sqlx::PgPool::connect_lazy("postgres://test@localhost:19999/test").unwrap(),
+            session_id: None,
+            started_at: Utc::now(),
+            last_request: Mutex::new(None),
+            seq: AtomicI32::new(0),
+            recorded_messages: AtomicUsize::new(0),
+            tape: Some(Mutex::new(
+                vec![
+                    recorded("read_file", json!({ "path": "a.rs" }), "fn a() {}"),
+                    recorded("read_file", json!({ "path": "b.rs" }), "fn b() {}"),
+                ]
+                .into(),
+            )),
+        };
+
+        // Out of order is fine as long as tool and input match
+        assert_eq!(
+            transcript.replay_tool("read_file", &json!({ "path": "b.rs" })),
+            ("fn b() {}".to_string(), false)
+        );
+        assert_eq!(
+            transcript.replay_tool("read_file", &json!({ "path": "a.rs" })),
+            ("fn a() {}".to_string(), false)
+        );
+        // Each recording is used once; anything else is a divergence
+        let (msg, is_error) = transcript.replay_tool("read_file", &json!({ "path": "a.rs" }));
+        assert!(is_error && msg.starts_with("[replay]"));
+    }
+}
@@ -0,0 +1,147 @@
+// ClaudeHydra v4 -- Update check
+// `GET /api/system/update-check` compares the running version with the latest
+// GitHub release, so the desktop app can offer an upgrade. Off unless
+// `CH_UPDATE_CHECK=true`; the release is fetched at most once a day (failed
+// lookups are not cached). `CH_UPDATE_REPO` points at a fork's releases.
+// Drafts and pre-releases are never offered (GitHub's `releases/latest`).
+
+use std::time::{Duration, Instant};
+
+use axum::Json;
+use axum::extract::State;
+use axum::http::StatusCode;
+use serde_json::{Value, json};
+use tokio::sync::Mutex;
+
+use crate::state::AppState;
+
+const DEFAULT_REPO: &str = "pawelserkowski-lang/ClaudeHydra-v4";
+const CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+/// Release notes are cut to this many characters.
+const MAX_NOTES_CHARS: usize = 20_000;
+
+/// Latest release as last fetched.
+static LATEST: Mutex<Option<(Instant, Value)>> = Mutex::const_new(None);
+
+pub fn enabled() -> bool {
+    std::env::var("CH_UPDATE_CHECK")
+        .map(|v| matches!(v.trim().to_ascii_lowercase().as_str(), "1" | "true" | "yes" | "on"))
+        .unwrap_or(false)
+}
+
+fn repo() -> String {
+    std::env::var("CH_UPDATE_REPO")
+        .ok()
+        .map(|r| r.trim().to_string())
+        .filter(|r| r.split('/').count() == 2)
+        .unwrap_or_else(|| DEFAULT_REPO.to_string())
+}
+
+/// Numeric parts of a `v4.2.0` style tag (a pre-release suffix is ignored).
+fn version_parts(version: &str) -> Vec<u64> {
+    version
+        .trim()
+        .trim_start_matches(['v', 'V'])
+        .split(['-', '+'])
+        .next()
+        .unwrap_or_default()
+        .split('.')
+        .map(|p| p.parse().unwrap_or(0))
+        .collect()
+}
+
+/// `latest` is a newer version than `current` (missing parts count as 0).
+pub fn is_newer(latest: &str, current: &str) -> bool {
+    let (mut a, mut b) = (version_parts(latest), version_parts(current));
+    let len = a.len().max(b.len());
+    a.resize(len, 0);
+    b.resize(len, 0);
+    a > b
+}
+
+async fn fetch_latest(client: &reqwest::Client) -> Result<Value, String> {
+    let url = format!("https://api.github.com/repos/{}/releases/latest", repo());
+    let resp = client
+        .get(&url)
+        .header(reqwest::header::USER_AGENT, "ClaudeHydra")
+        .header(reqwest::header::ACCEPT, "application/vnd.github+json")
+        .timeout(Duration::from_secs(10))
+        .send()
+        .await
+        .map_err(|e| format!("request failed: {}", e))?;
+    if !resp.status().is_success() {
+        return Err(format!("GitHub returned {}", resp.status()));
+    }
+    let release: Value = resp.json().await.map_err(|e| format!("invalid response: {}", e))?;
+    let notes: String = release["body"]
+        .as_str()
+        .unwrap_or_default()
+        .chars()
+        .take(MAX_NOTES_CHARS)
+        .collect();
+    Ok(json!({
+        "version": release["tag_name"].as_str().unwrap_or_default().trim_start_matches(['v', 'V']),
+        "name": release["name"],
+        "url": release["html_url"],
+        "published_at": release["published_at"],
+        "notes": notes,
+    }))
+}
+
+/// `GET /api/system/update-check` — is a newer release available
+#[utoipa::path(get, path = "/api/system/update-check", tag = "system",
+    responses(
+        (status = 200, description = "Current and latest version (enabled: false when opted out)"),
+        (status = 502, description = "GitHub could not be reached")
+    ))]
+pub async fn update_check(
+    State(state): State<AppState>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let current = env!("CARGO_PKG_VERSION");
+    if !enabled() {
+        return Ok(Json(json!({ "enabled": false, "current_version": current })));
+    }
+
+    let mut cached = LATEST.lock().await;
+    let (checked, release) = match cached.as_ref() {
+        Some((at, release)) if at.elapsed() < CACHE_TTL => (*at, release.clone()),
+        _ => {
+            let release = fetch_latest(&state.http_client).await.map_err(|e| {
+                tracing::warn!("update_check: {}", e);
+                (
+                    StatusCode::BAD_GATEWAY,
+                    Json(json!({ "error": "Failed to fetch the latest release", "details": e })),
+                )
+            })?;
+            *cached = Some((Instant::now(), release.clone()));
+            (Instant::now(), release)
+        }
+    };
+    drop(cached);
+
+    let latest = release["version"].as_str().unwrap_or_default();
+    let checked_at = chrono::Utc::now()
+        - chrono::Duration::from_std(checked.elapsed()).unwrap_or_default();
+    Ok(Json(json!({
+        "enabled": true,
+        "current_version": current,
+        "latest_version": latest,
+        "update_available": !latest.is_empty() && is_newer(latest, current),
+        "release": release,
+        "checked_at": checked_at,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions_compare_numerically() {
+        assert!(is_newer("v4.10.0", "4.9.3"));
+        assert!(is_newer("4.0.1", "4.0"));
+        assert!(!is_newer("v4.0.0", "4.0.0"));
+        assert!(!is_newer("4.1.0-rc.1", "4.1.0"));
+        assert!(!is_newer("3.9.9", "4.0.0"));
+    }
+}
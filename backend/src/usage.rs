@@ -0,0 +1,207 @@
+//! Durable usage events — one `ch_usage_events` row per completed provider call.
+//!
+//! Unlike `ch_agent_usage` (aggregated for the analytics dashboard), these rows
+//! carry the session / agent / user attribution and the computed cost at the
+//! time of the call, so they can be exported and reconciled against the
+//! Anthropic invoice (`GET /api/usage/export`).
+
+use crate::handlers::usage::{ModelPrice, list_cost, load_prices};
+
+/// A single usage event ready to be persisted.
+#[derive(Debug, Clone, Default)]
+pub struct UsageEvent {
+    pub model: String,
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+    /// `true` when token counts were estimated from character length
+    /// (streaming paths that never see the provider's `usage` block).
+    pub estimated: bool,
+    /// Originating code path: `chat`, `stream`, `ws`, `debate`, ...
+    pub source: &'static str,
+    pub session_id: Option<uuid::Uuid>,
+    pub agent_id: Option<String>,
+    /// Authenticated principal (`user:…` / `token:…`, see principal); the
+    /// current request's when left empty, `None` in single-user deployments.
+    pub user_id: Option<String>,
+    /// Prompt canary variants the reply was generated with (see
+    /// prompt_canary); the current request scope's when left empty.
+    pub prompt_variants: Vec<crate::prompt_canary::Arm>,
+    /// The reply was an unresolved refusal.
+    pub refused: bool,
+}
+
+/// Cost in USD for the given token counts at list prices: the model's
+/// `ch_model_prices` entry, else its tier's built-in price.
+pub fn estimate_cost_usd(model: &str, input_tokens: u32, output_tokens: u32, prices: &[ModelPrice]) -> f64 {
+    list_cost(model, input_tokens as i64, output_tokens as i64, prices)
+}
+
+/// Adds one event to its session's running totals (`ch_sessions`).
+const ADD_SESSION_TOTALS_SQL: &str = "UPDATE ch_sessions SET input_tokens = input_tokens + $2, \
+     output_tokens = output_tokens + $3, cost_usd = cost_usd + $4 WHERE id = $1";
+
+/// What one event adds to its session's totals, as bound to
+/// `ADD_SESSION_TOTALS_SQL`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct SessionTotals {
+    input_tokens: i64,
+    output_tokens: i64,
+    cost_usd: f64,
+}
+
+impl SessionTotals {
+    fn of(event: &UsageEvent, cost_usd: f64) -> Self {
+        Self {
+            input_tokens: event.input_tokens as i64,
+            output_tokens: event.output_tokens as i64,
+            cost_usd,
+        }
+    }
+}
+
+impl std::ops::AddAssign for SessionTotals {
+    fn add_assign(&mut self, other: Self) {
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.cost_usd += other.cost_usd;
+    }
+}
+
+/// Fill in what the caller left empty from the current request: its prompt
+/// canary variants and principal.
+fn attribute(event: &mut UsageEvent) {
+    if event.prompt_variants.is_empty() {
+        event.prompt_variants = crate::prompt_canary::current();
+    }
+    if event.user_id.is_none() {
+        event.user_id = crate::principal::current();
+    }
+}
+
+/// Persist a usage event and add it to the session's running totals
+/// (fire-and-forget — never blocks or fails the caller).
+pub fn record_usage(db: &sqlx::PgPool, mut event: UsageEvent) {
+    let db = db.clone();
+    attribute(&mut event);
+    tokio::spawn(async move {
+        // Same prices as `/api/usage`, so spend caps and session totals agree.
+        let prices = load_prices(&db).await.unwrap_or_else(|e| {
+            tracing::warn!("Failed to load model prices, using tier pricing: {}", e);
+            Vec::new()
+        });
+        let cost = estimate_cost_usd(&event.model, event.input_tokens, event.output_tokens, &prices);
+        let total = event.input_tokens.saturating_add(event.output_tokens);
+        if let Err(e) = sqlx::query(
+            "INSERT INTO ch_usage_events \
+             (model, input_tokens, output_tokens, total_tokens, cost_usd, estimated, source, session_id, agent_id, user_id, \
+              prompt_variants, refused) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)",
+        )
+        .bind(&event.model)
+        .bind(event.input_tokens.min(i32::MAX as u32) as i32)
+        .bind(event.output_tokens.min(i32::MAX as u32) as i32)
+        .bind(total.min(i32::MAX as u32) as i32)
+        .bind(cost)
+        .bind(event.estimated)
+        .bind(event.source)
+        .bind(event.session_id)
+        .bind(&event.agent_id)
+        .bind(&event.user_id)
+        .bind((!event.prompt_variants.is_empty()).then(|| serde_json::json!(event.prompt_variants)))
+        .bind(event.refused)
+        .execute(&db)
+        .await
+        {
+            tracing::warn!("Failed to record usage event: {}", e);
+        }
+        let added = SessionTotals::of(&event, cost);
+        if let Some(session_id) = event.session_id
+            && let Err(e) = sqlx::query(ADD_SESSION_TOTALS_SQL)
+                .bind(session_id)
+                .bind(added.input_tokens)
+                .bind(added.output_tokens)
+                .bind(added.cost_usd)
+                .execute(&db)
+                .await
+        {
+            tracing::warn!("Failed to update session usage totals: {}", e);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cost_uses_tier_pricing() {
+        // Sonnet: $3 / Mtok in, $15 / Mtok out
+        let cost = estimate_cost_usd("claude-sonnet-4-6", 1_000_000, 1_000_000, &[]);
+        assert!((cost - 18.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn cost_uses_the_price_table_first() {
+        let prices = [ModelPrice {
+            model_pattern: "sonnet-4-6".into(),
+            input_usd_per_mtok: 2.0,
+            output_usd_per_mtok: 10.0,
+        }];
+        let cost = estimate_cost_usd("claude-sonnet-4-6", 1_000_000, 1_000_000, &prices);
+        assert!((cost - 12.0).abs() < 1e-9);
+        // Models without an entry keep their tier price.
+        let cost = estimate_cost_usd("claude-haiku-4-5", 1_000_000, 0, &prices);
+        assert!((cost - crate::handlers::analytics::tier_pricing("haiku").0).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn events_are_attributed_to_the_request_principal() {
+        let mut anonymous = UsageEvent::default();
+        attribute(&mut anonymous);
+        assert_eq!(anonymous.user_id, None);
+
+        let scope = std::sync::Arc::new(crate::request_scope::RequestScope {
+            principal: Some("token:abc".into()),
+            ..Default::default()
+        });
+        let event = crate::request_scope::run(scope, async {
+            let mut event = UsageEvent::default();
+            attribute(&mut event);
+            event
+        })
+        .await;
+        assert_eq!(event.user_id.as_deref(), Some("token:abc"));
+
+        // An explicit user is kept.
+        let mut explicit = UsageEvent { user_id: Some("user:x".into()), ..Default::default() };
+        attribute(&mut explicit);
+        assert_eq!(explicit.user_id.as_deref(), Some("user:x"));
+    }
+
+    #[test]
+    fn cost_is_zero_without_tokens() {
+        assert_eq!(estimate_cost_usd("claude-opus-4-6", 0, 0, &[]), 0.0);
+    }
+
+    #[test]
+    fn session_totals_accumulate_across_events() {
+        // Every column is incremented in place, never overwritten.
+        for column in ["input_tokens", "output_tokens", "cost_usd"] {
+            assert!(ADD_SESSION_TOTALS_SQL.contains(&format!("{0} = {0} + $", column)), "{}", column);
+        }
+
+        let events = [
+            UsageEvent { model: "claude-sonnet-4-6".into(), input_tokens: 1_000_000, output_tokens: 200_000, ..Default::default() },
+            UsageEvent { model: "claude-sonnet-4-6".into(), input_tokens: 500_000, output_tokens: 100_000, ..Default::default() },
+        ];
+        let mut session = SessionTotals::default();
+        for event in &events {
+            let cost = estimate_cost_usd(&event.model, event.input_tokens, event.output_tokens, &[]);
+            session += SessionTotals::of(event, cost);
+        }
+        assert_eq!(session.input_tokens, 1_500_000);
+        assert_eq!(session.output_tokens, 300_000);
+        // Sonnet: 1.5 Mtok in at $3 plus 0.3 Mtok out at $15
+        assert!((session.cost_usd - 9.0).abs() < 1e-9);
+    }
+}
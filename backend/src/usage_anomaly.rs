@@ -0,0 +1,300 @@
+// ClaudeHydra v4 -- Usage anomaly detector
+// Periodically scans `ch_usage_events` for patterns that suggest a leaked key
+// or a runaway loop, records them in `ch_usage_anomalies`, writes an audit
+// entry and raises a desktop notification via the ai-swarm-notifier MCP server.
+//
+// Checks:
+// - daily_spike   — tokens in the last 24h exceed N x the trailing 7-day daily average
+// - session_heavy — a single session consumed more than X tokens in the last 24h
+// - odd_hours     — a principal made requests during configured quiet hours (UTC);
+//                   principals are the `user_id` of the events (`user:…` for
+//                   web sessions, `token:…` for API tokens, see principal),
+//                   unattributed AUTH_SECRET calls count as `default`
+
+use std::time::Duration;
+
+use serde_json::{Value, json};
+
+use crate::state::AppState;
+
+/// Detector thresholds, read from the environment at startup.
+#[derive(Debug, Clone)]
+pub struct AnomalyConfig {
+    pub interval: Duration,
+    /// `USAGE_ANOMALY_SPIKE_FACTOR` (default 10)
+    pub spike_factor: f64,
+    /// Minimum 24h volume before a spike is reported (avoids 0 → 1k noise).
+    pub spike_min_tokens: i64,
+    /// `USAGE_ANOMALY_SESSION_TOKENS` (default 500k)
+    pub session_tokens: i64,
+    /// `USAGE_ANOMALY_QUIET_HOURS` as `start-end` in UTC, e.g. `1-6` (default off)
+    pub quiet_hours: Option<(u32, u32)>,
+    /// Requests during quiet hours before a principal is flagged.
+    pub quiet_hours_min_requests: i64,
+}
+
+impl AnomalyConfig {
+    pub fn from_env() -> Self {
+        fn env<T: std::str::FromStr>(key: &str, default: T) -> T {
+            std::env::var(key)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default)
+        }
+        Self {
+            interval: Duration::from_secs(env("USAGE_ANOMALY_INTERVAL_SECS", 900u64).max(60)),
+            spike_factor: env("USAGE_ANOMALY_SPIKE_FACTOR", 10.0),
+            spike_min_tokens: env("USAGE_ANOMALY_SPIKE_MIN_TOKENS", 100_000),
+            session_tokens: env("USAGE_ANOMALY_SESSION_TOKENS", 500_000),
+            quiet_hours: std::env::var("USAGE_ANOMALY_QUIET_HOURS")
+                .ok()
+                .and_then(|v| parse_hour_range(&v)),
+            quiet_hours_min_requests: env("USAGE_ANOMALY_QUIET_MIN_REQUESTS", 5),
+        }
+    }
+}
+
+/// Parse `"start-end"` (UTC hours, end exclusive). Ranges may wrap midnight (`22-5`).
+pub fn parse_hour_range(raw: &str) -> Option<(u32, u32)> {
+    let (start, end) = raw.trim().split_once('-')?;
+    let start: u32 = start.trim().parse().ok()?;
+    let end: u32 = end.trim().parse().ok()?;
+    (start < 24 && end <= 24 && start != end).then_some((start, end))
+}
+
+/// Whether `hour` falls inside a (possibly midnight-wrapping) range.
+pub fn hour_in_range(hour: u32, (start, end): (u32, u32)) -> bool {
+    if start < end {
+        hour >= start && hour < end
+    } else {
+        hour >= start || hour < end
+    }
+}
+
+/// Principals with at least `min_requests` requests inside the quiet hours,
+/// from `(principal, hour, requests)` rows.
+pub fn odd_hour_principals(
+    rows: Vec<(String, i32, i64)>,
+    quiet_hours: (u32, u32),
+    min_requests: i64,
+) -> Vec<(String, i64)> {
+    let mut per_principal: std::collections::BTreeMap<String, i64> = Default::default();
+    for (principal, hour, count) in rows {
+        if hour_in_range(hour.max(0) as u32, quiet_hours) {
+            *per_principal.entry(principal).or_default() += count;
+        }
+    }
+    per_principal
+        .into_iter()
+        .filter(|(_, count)| *count >= min_requests)
+        .collect()
+}
+
+/// Whether the last 24h volume is a spike against the trailing daily average.
+pub fn is_daily_spike(last_24h: i64, baseline_daily_avg: f64, cfg: &AnomalyConfig) -> bool {
+    if last_24h < cfg.spike_min_tokens {
+        return false;
+    }
+    // No history yet: anything above the floor on day one is not a "spike".
+    baseline_daily_avg > 0.0 && last_24h as f64 > baseline_daily_avg * cfg.spike_factor
+}
+
+/// Spawn the background detector loop.
+pub fn spawn(state: AppState) -> tokio::task::JoinHandle<()> {
+    let cfg = AnomalyConfig::from_env();
+    tokio::spawn(async move {
+        tracing::info!(
+            "usage_anomaly: detector started (interval={}s, spike={}x, session>{} tokens, quiet_hours={:?})",
+            cfg.interval.as_secs(),
+            cfg.spike_factor,
+            cfg.session_tokens,
+            cfg.quiet_hours
+        );
+        let mut interval = tokio::time::interval(cfg.interval);
+        loop {
+            interval.tick().await;
+            if state.subsystems.is_paused(crate::subsystems::USAGE_ANOMALY) {
+                tracing::debug!("usage_anomaly: detector paused");
+                continue;
+            }
+            if let Err(e) = run_checks(&state, &cfg).await {
+                tracing::warn!("usage_anomaly: check failed: {}", e);
+            }
+        }
+    })
+}
+
+/// Run all checks once. Returns the number of newly raised anomalies.
+pub async fn run_checks(state: &AppState, cfg: &AnomalyConfig) -> Result<usize, sqlx::Error> {
+    let mut raised = 0;
+
+    // ── daily_spike ──────────────────────────────────────────────────────
+    let (last_24h, prev_7d): (i64, i64) = sqlx::query_as(
+        "SELECT \
+            COALESCE(SUM(total_tokens) FILTER (WHERE created_at >= NOW() - INTERVAL '1 day'), 0)::BIGINT, \
+            COALESCE(SUM(total_tokens) FILTER (WHERE created_at < NOW() - INTERVAL '1 day'), 0)::BIGINT \
+         FROM ch_usage_events WHERE created_at >= NOW() - INTERVAL '8 days'",
+    )
+    .fetch_one(&state.db)
+    .await?;
+    let baseline = prev_7d as f64 / 7.0;
+    if is_daily_spike(last_24h, baseline, cfg) {
+        raised += raise(
+            state,
+            "daily_spike",
+            "global",
+            json!({ "last_24h_tokens": last_24h, "baseline_daily_avg": baseline.round(), "factor": cfg.spike_factor }),
+        )
+        .await?;
+    }
+
+    // ── session_heavy ────────────────────────────────────────────────────
+    let heavy: Vec<(uuid::Uuid, i64)> = sqlx::query_as(
+        "SELECT session_id, SUM(total_tokens)::BIGINT AS tokens FROM ch_usage_events \
+         WHERE session_id IS NOT NULL AND created_at >= NOW() - INTERVAL '1 day' \
+         GROUP BY session_id HAVING SUM(total_tokens) > $1",
+    )
+    .bind(cfg.session_tokens)
+    .fetch_all(&state.db)
+    .await?;
+    for (session_id, tokens) in heavy {
+        raised += raise(
+            state,
+            "session_heavy",
+            &session_id.to_string(),
+            json!({ "session_id": session_id, "tokens_24h": tokens, "threshold": cfg.session_tokens }),
+        )
+        .await?;
+    }
+
+    // ── odd_hours ────────────────────────────────────────────────────────
+    if let Some((start, end)) = cfg.quiet_hours {
+        let rows: Vec<(String, i32, i64)> = sqlx::query_as(
+            "SELECT COALESCE(user_id, 'default') AS principal, \
+                    EXTRACT(HOUR FROM created_at AT TIME ZONE 'UTC')::INT AS hour, COUNT(*) \
+             FROM ch_usage_events WHERE created_at >= NOW() - INTERVAL '1 day' \
+             GROUP BY 1, 2",
+        )
+        .fetch_all(&state.db)
+        .await?;
+
+        for (principal, count) in odd_hour_principals(rows, (start, end), cfg.quiet_hours_min_requests) {
+            raised += raise(
+                state,
+                "odd_hours",
+                &principal,
+                json!({ "principal": principal, "requests": count, "quiet_hours_utc": format!("{}-{}", start, end) }),
+            )
+            .await?;
+        }
+    }
+
+    Ok(raised)
+}
+
+/// Record an anomaly (once per kind/subject/day) and notify. Returns 1 if new.
+async fn raise(
+    state: &AppState,
+    kind: &str,
+    subject: &str,
+    details: Value,
+) -> Result<usize, sqlx::Error> {
+    let inserted: Option<i64> = sqlx::query_scalar(
+        "INSERT INTO ch_usage_anomalies (kind, subject, window_day, details) \
+         VALUES ($1, $2, (NOW() AT TIME ZONE 'UTC')::date, $3) \
+         ON CONFLICT (kind, subject, window_day) DO NOTHING RETURNING id",
+    )
+    .bind(kind)
+    .bind(subject)
+    .bind(&details)
+    .fetch_optional(&state.db)
+    .await?;
+
+    if inserted.is_none() {
+        return Ok(0);
+    }
+
+    tracing::warn!(kind, subject, %details, "usage_anomaly: anomaly detected");
+    crate::audit::log_audit(
+        &state.db,
+        "usage_anomaly",
+        json!({ "kind": kind, "subject": subject, "details": details }),
+        None,
+    )
+    .await;
+    crate::webhooks::dispatch(
+        state,
+        "usage.anomaly",
+        json!({ "kind": kind, "subject": subject, "details": details }),
+    );
+
+    let args = json!({
+        "status": "warning",
+        "agent": "ClaudeHydra",
+        "message": format!("Usage anomaly: {} ({})", kind, subject),
+    });
+    if let Err(e) = state
+        .mcp_client
+        .call_tool("mcp_ai_swarm_notifier_show_notification", &args)
+        .await
+    {
+        tracing::debug!("usage_anomaly: MCP notification not sent: {}", e);
+    }
+
+    Ok(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cfg() -> AnomalyConfig {
+        AnomalyConfig {
+            interval: Duration::from_secs(900),
+            spike_factor: 10.0,
+            spike_min_tokens: 1_000,
+            session_tokens: 500_000,
+            quiet_hours: None,
+            quiet_hours_min_requests: 5,
+        }
+    }
+
+    #[test]
+    fn hour_ranges_parse_and_wrap() {
+        assert_eq!(parse_hour_range("1-6"), Some((1, 6)));
+        assert_eq!(parse_hour_range(" 22 - 5 "), Some((22, 5)));
+        assert_eq!(parse_hour_range("5-5"), None);
+        assert_eq!(parse_hour_range("25-3"), None);
+
+        assert!(hour_in_range(3, (1, 6)));
+        assert!(!hour_in_range(6, (1, 6)));
+        assert!(hour_in_range(23, (22, 5)));
+        assert!(hour_in_range(2, (22, 5)));
+        assert!(!hour_in_range(12, (22, 5)));
+    }
+
+    #[test]
+    fn spike_requires_baseline_and_floor() {
+        let c = cfg();
+        assert!(is_daily_spike(50_000, 2_000.0, &c));
+        assert!(!is_daily_spike(15_000, 2_000.0, &c));
+        assert!(!is_daily_spike(500, 10.0, &c)); // below floor
+        assert!(!is_daily_spike(50_000, 0.0, &c)); // no history
+    }
+
+    #[test]
+    fn odd_hours_are_counted_per_principal() {
+        let rows = vec![
+            // A CI token busy at night, split over two quiet hours
+            ("token:ci".to_string(), 2, 4),
+            ("token:ci".to_string(), 3, 3),
+            // A user with a few night requests and many daytime ones
+            ("user:dev".to_string(), 3, 2),
+            ("user:dev".to_string(), 14, 40),
+        ];
+        assert_eq!(
+            odd_hour_principals(rows, (1, 6), 5),
+            vec![("token:ci".to_string(), 7)]
+        );
+    }
+}
@@ -1,32 +1,62 @@
 // ClaudeHydra v4 -- Background watchdog
 // Core watchdog logic (DB ping, model cache refresh, browser proxy monitoring)
 // is provided by the shared `jaskier-browser` crate.
-// This module adds a ClaudeHydra-specific Anthropic API reachability check.
+// This module adds ClaudeHydra-specific provider probes (Anthropic, Google)
+// and a database probe; every result is stored in the health history.
 
 pub use jaskier_browser::watchdog::HasWatchdogState;
 
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use crate::ai_gateway::vault_bridge::HasVaultBridge;
+use crate::health_history::{self, Probe};
 use crate::state::AppState;
 
 const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Prune the health history once per this many checks (hourly).
+const PRUNE_EVERY: u64 = 60;
 
 /// Spawn the shared watchdog + an additional Anthropic API health check task.
 pub fn spawn(state: AppState) -> tokio::task::JoinHandle<()> {
     // Spawn shared watchdog (DB ping, model cache refresh, browser proxy monitoring)
     let shared_handle = jaskier_browser::watchdog::spawn(state.clone());
 
-    // Spawn ClaudeHydra-specific Anthropic API check on the same interval
+    // Spawn ClaudeHydra-specific provider probes on the same interval
     tokio::spawn(async move {
-        tracing::info!("watchdog: Anthropic API health check started (interval={}s)", CHECK_INTERVAL.as_secs());
+        tracing::info!("watchdog: provider health checks started (interval={}s)", CHECK_INTERVAL.as_secs());
 
+        let mut ticks: u64 = 0;
         loop {
             tokio::time::sleep(CHECK_INTERVAL).await;
-            let api_ok = check_anthropic_api(&state).await;
-            if !api_ok {
+            if state.subsystems.is_paused(crate::subsystems::ANTHROPIC_WATCHDOG) {
+                tracing::debug!("watchdog: provider checks paused");
+                continue;
+            }
+            let (anthropic, google, database) =
+                tokio::join!(check_anthropic_api(&state), check_google_api(&state), check_database(&state));
+            if anthropic.as_ref().is_some_and(|p| !p.ok) {
                 tracing::warn!("watchdog: Anthropic API check failed");
             }
+            for probe in [anthropic, google, Some(database)].into_iter().flatten() {
+                health_history::record(&state.db, &probe).await;
+                if !probe.ok {
+                    crate::webhooks::dispatch(
+                        &state,
+                        "health.probe_failed",
+                        serde_json::json!({
+                            "component": probe.component,
+                            "latency_ms": probe.latency.as_millis() as u64,
+                            "detail": probe.detail,
+                        }),
+                    );
+                }
+            }
+
+            ticks += 1;
+            if ticks % PRUNE_EVERY == 0 {
+                health_history::prune(&state.db).await;
+            }
         }
     });
 
@@ -35,8 +65,8 @@ pub fn spawn(state: AppState) -> tokio::task::JoinHandle<()> {
 
 /// Check Anthropic API reachability.
 /// Uses a lightweight HEAD request to api.anthropic.com (no tokens consumed).
-/// Skips if no credential is available (Vault, OAuth token, or API key).
-async fn check_anthropic_api(state: &AppState) -> bool {
+/// Skips (`None`) if no credential is available (Vault, OAuth token, or API key).
+async fn check_anthropic_api(state: &AppState) -> Option<Probe> {
     // Check if we have a credential configured from ANY source:
     // 1. Vault (ai_providers/anthropic_max)
     let has_vault = match state.vault_client().get("ai_providers", "anthropic_max").await {
@@ -48,7 +78,12 @@ async fn check_anthropic_api(state: &AppState) -> bool {
         }
         Err(crate::ai_gateway::vault_bridge::VaultError::AnomalyDetected(msg)) => {
             tracing::error!("watchdog: ANOMALY DETECTED from Vault: {}", msg);
-            return false;
+            return Some(Probe {
+                component: "anthropic",
+                ok: false,
+                latency: Duration::ZERO,
+                detail: Some(format!("Vault anomaly: {}", msg)),
+            });
         }
         Err(_) => false,
     };
@@ -64,37 +99,66 @@ async fn check_anthropic_api(state: &AppState) -> bool {
 
     if !has_vault && !has_oauth && !has_key {
         // No credential from any source -- skip check (not an error)
-        return true;
+        return None;
     }
 
-    let result = tokio::time::timeout(
-        Duration::from_secs(5),
-        state
-            .http_client
-            .head("https://api.anthropic.com/v1/messages")
-            .header("anthropic-version", "2023-06-01")
-            .send(),
-    )
-    .await;
-
-    match result {
+    let request = state
+        .http_client
+        .head("https://api.anthropic.com/v1/messages")
+        .header("anthropic-version", "2023-06-01");
+    Some(reachability("anthropic", request).await)
+}
+
+/// Check Google Generative Language API reachability (skipped without a key).
+async fn check_google_api(state: &AppState) -> Option<Probe> {
+    jaskier_oauth::google::get_google_credential(state).await?;
+    let request = state
+        .http_client
+        .head("https://generativelanguage.googleapis.com/v1beta/models");
+    Some(reachability("google", request).await)
+}
+
+/// Time a HEAD request. Any answer below 500 (even 401/405) means the host is
+/// reachable.
+async fn reachability(component: &'static str, request: reqwest::RequestBuilder) -> Probe {
+    let started = Instant::now();
+    let result = tokio::time::timeout(PROBE_TIMEOUT, request.send()).await;
+    let latency = started.elapsed();
+    let (ok, detail) = match result {
         Ok(Ok(resp)) => {
-            // Any HTTP response (even 401/405) means the host is reachable
             let status = resp.status().as_u16();
             if status >= 500 {
-                tracing::warn!("watchdog: Anthropic API returned server error {}", status);
-                false
+                tracing::warn!("watchdog: {} API returned server error {}", component, status);
+                (false, Some(format!("HTTP {}", status)))
             } else {
-                true
+                (true, None)
             }
         }
         Ok(Err(e)) => {
-            tracing::error!("watchdog: Anthropic API unreachable: {}", e);
-            false
+            tracing::error!("watchdog: {} API unreachable: {}", component, e);
+            (false, Some(e.to_string()))
         }
         Err(_) => {
-            tracing::error!("watchdog: Anthropic API check timed out after 5s");
-            false
+            tracing::error!("watchdog: {} API check timed out after {}s", component, PROBE_TIMEOUT.as_secs());
+            (false, Some(format!("timed out after {}s", PROBE_TIMEOUT.as_secs())))
         }
+    };
+    Probe { component, ok, latency, detail }
+}
+
+/// Check database connectivity.
+async fn check_database(state: &AppState) -> Probe {
+    let started = Instant::now();
+    let result = tokio::time::timeout(PROBE_TIMEOUT, sqlx::query("SELECT 1").execute(&state.db)).await;
+    let (ok, detail) = match result {
+        Ok(Ok(_)) => (true, None),
+        Ok(Err(e)) => (false, Some(e.to_string())),
+        Err(_) => (false, Some(format!("timed out after {}s", PROBE_TIMEOUT.as_secs()))),
+    };
+    Probe {
+        component: "database",
+        ok,
+        latency: started.elapsed(),
+        detail,
     }
 }
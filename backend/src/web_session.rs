@@ -0,0 +1,454 @@
+// ClaudeHydra v4 -- Cookie sessions for the bundled web UI
+// The served frontend can trade AUTH_SECRET for an HttpOnly, SameSite=Strict
+// session cookie once (`POST /api/auth/session`) instead of keeping the secret
+// in browser storage. The cookie carries its expiry, subject and role, signed
+// with HMAC-SHA256 keyed by AUTH_SECRET (rotating the secret revokes every
+// session). OIDC sessions are re-checked against `ch_users` on every request:
+// disabling a user ends their sessions and a changed role applies right away.
+//
+// `cookie_auth` runs in front of every route. Requests with an Authorization
+// header are left alone. A request carrying a valid session cookie is
+// translated into the bearer credential the shared `require_auth` expects;
+// mutating methods additionally need `X-CSRF-Token` — a double-submit token
+// derived from the session, readable by the UI through the `ch_csrf` cookie.
+//
+// Every session carries a role. Signing in with AUTH_SECRET gives `admin`;
+// OIDC sign-ins (see `oidc.rs`) get the role mapped from their claims.
+// `member` sessions can't reach `/api/admin/*`, `/api/debug/*` or the other
+// admin-only endpoints in `ADMIN_ONLY` (provider keys, prices, tier budgets,
+// integrations, service tokens); `viewer` sessions can't mutate or open a
+// chat WebSocket.
+
+use axum::Json;
+use axum::extract::{Request, State};
+use axum::http::{HeaderMap, HeaderValue, Method, StatusCode, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::crypto::{ct_eq, hmac_sha256};
+use crate::state::AppState;
+
+pub const SESSION_COOKIE: &str = "ch_session";
+pub const CSRF_COOKIE: &str = "ch_csrf";
+pub const CSRF_HEADER: &str = "x-csrf-token";
+const SESSION_PATH: &str = "/api/auth/session";
+const DEFAULT_TTL_HOURS: i64 = 12;
+
+fn ttl_hours() -> i64 {
+    std::env::var("WEB_SESSION_TTL_HOURS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|h: &i64| *h > 0)
+        .unwrap_or(DEFAULT_TTL_HOURS)
+}
+
+/// `Secure` is on unless `COOKIE_SECURE=0` (plain-HTTP local development).
+pub(crate) fn secure_cookies() -> bool {
+    !matches!(
+        std::env::var("COOKIE_SECURE").as_deref(),
+        Ok("0") | Ok("false")
+    )
+}
+
+/// What a cookie session may do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Admin,
+    Member,
+    Viewer,
+}
+
+impl Role {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::Admin => "admin",
+            Role::Member => "member",
+            Role::Viewer => "viewer",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "admin" => Some(Role::Admin),
+            "member" => Some(Role::Member),
+            "viewer" => Some(Role::Viewer),
+            _ => None,
+        }
+    }
+}
+
+/// A verified session cookie.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Session {
+    pub expires_at: i64,
+    /// User id for OIDC sessions, a random nonce for AUTH_SECRET sign-ins.
+    pub subject: String,
+    pub role: Role,
+}
+
+/// New session cookie value: `<expires unix>.<subject>.<role>.<signature>`.
+fn issue(secret: &str, session: &Session) -> String {
+    let payload = format!(
+        "{}.{}.{}",
+        session.expires_at,
+        session.subject,
+        session.role.as_str()
+    );
+    let sig = hmac_sha256(secret.as_bytes(), format!("session|{}", payload).as_bytes());
+    format!("{}.{}", payload, sig)
+}
+
+/// A correctly signed, unexpired session cookie.
+fn verify(secret: &str, value: &str) -> Option<Session> {
+    let (payload, sig) = value.rsplit_once('.')?;
+    let expected = hmac_sha256(secret.as_bytes(), format!("session|{}", payload).as_bytes());
+    if !ct_eq(sig, &expected) {
+        return None;
+    }
+    let mut parts = payload.splitn(3, '.');
+    let expires_at: i64 = parts.next()?.parse().ok()?;
+    let subject = parts.next()?.to_string();
+    let role = Role::parse(parts.next()?)?;
+    (expires_at > chrono::Utc::now().timestamp()).then_some(Session {
+        expires_at,
+        subject,
+        role,
+    })
+}
+
+/// CSRF token bound to one session cookie.
+fn csrf_token(secret: &str, session: &str) -> String {
+    hmac_sha256(secret.as_bytes(), format!("csrf|{}", session).as_bytes())
+}
+
+pub(crate) fn cookie<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(k, _)| *k == name)
+        .map(|(_, v)| v)
+}
+
+/// Whether the request carries a valid session cookie (used by the WebSocket
+/// handshake, which can't send custom headers).
+pub fn has_valid_session(headers: &HeaderMap, secret: Option<&str>) -> bool {
+    match (secret, cookie(headers, SESSION_COOKIE)) {
+        (Some(secret), Some(value)) => verify(secret, value).is_some(),
+        _ => false,
+    }
+}
+
+fn is_mutating(method: &Method) -> bool {
+    !matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+/// Admin-only endpoints outside `/api/admin` and `/api/debug`: path prefix
+/// and whether reading stays open to everyone.
+const ADMIN_ONLY: &[(&str, bool)] = &[
+    // Provider keys: `api-key`, `api-key/{provider}`, `api-keys`
+    ("/api/settings/api-key", false),
+    ("/api/settings/key-environments", true),
+    ("/api/usage/prices", true),
+    ("/api/agents/tiers", true),
+    ("/api/integrations/", false),
+    // Shared service tokens
+    ("/api/tokens", false),
+];
+
+/// Requests only admins may make.
+pub(crate) fn is_admin_request(method: &Method, path: &str) -> bool {
+    path.starts_with("/api/admin")
+        || path.starts_with("/api/debug")
+        || ADMIN_ONLY
+            .iter()
+            .any(|(prefix, reads_open)| path.starts_with(prefix) && !(*reads_open && !is_mutating(method)))
+}
+
+/// Whether `role` may make this request at all.
+fn role_allows(role: Role, method: &Method, path: &str) -> bool {
+    match role {
+        Role::Admin => true,
+        Role::Member => !is_admin_request(method, path),
+        Role::Viewer => {
+            !is_admin_request(method, path) && !is_mutating(method) && !crate::ws::is_chat_socket(path)
+        }
+    }
+}
+
+/// Re-check a session against `ch_users`: `None` once its user is disabled,
+/// otherwise the session carrying the user's current role. Subjects that are
+/// no user id (AUTH_SECRET sign-ins) pass unchanged.
+async fn revalidate(db: &sqlx::PgPool, mut session: Session) -> Result<Option<Session>, sqlx::Error> {
+    let Ok(user_id) = uuid::Uuid::parse_str(&session.subject) else {
+        return Ok(Some(session));
+    };
+    let user = sqlx::query_as::<_, (String, bool)>("SELECT role, disabled FROM ch_users WHERE id = $1")
+        .bind(user_id)
+        .fetch_optional(db)
+        .await?;
+    match user {
+        Some((_, true)) => Ok(None),
+        Some((role, false)) => {
+            if let Some(role) = Role::parse(&role) {
+                session.role = role;
+            }
+            Ok(Some(session))
+        }
+        None => Ok(Some(session)),
+    }
+}
+
+fn forbidden(msg: &str) -> Response {
+    (StatusCode::FORBIDDEN, Json(json!({ "error": msg }))).into_response()
+}
+
+/// Middleware: accept the session cookie as an alternative to a bearer token.
+pub async fn cookie_auth(State(state): State<AppState>, mut req: Request, next: Next) -> Response {
+    let Some(secret) = state.auth_secret.clone() else {
+        return next.run(req).await;
+    };
+    if req.headers().contains_key(header::AUTHORIZATION) {
+        return next.run(req).await;
+    }
+    let Some(session) = cookie(req.headers(), SESSION_COOKIE).map(str::to_string) else {
+        return next.run(req).await;
+    };
+    let Some(verified) = verify(&secret, &session) else {
+        // Expired or forged — the route's own auth decides (usually 401).
+        return next.run(req).await;
+    };
+    let verified = match revalidate(&state.db, verified).await {
+        Ok(Some(session)) => session,
+        Ok(None) => {
+            return (
+                StatusCode::UNAUTHORIZED,
+                [
+                    set_cookie(SESSION_COOKIE, "", 0, true),
+                    set_cookie(CSRF_COOKIE, "", 0, false),
+                ],
+                Json(json!({ "error": "This account is disabled" })),
+            )
+                .into_response();
+        }
+        Err(e) => {
+            tracing::error!("web_session: failed to check session user: {}", e);
+            return (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(json!({ "error": "Could not verify session" })),
+            )
+                .into_response();
+        }
+    };
+
+    // Signing in or out is always allowed; everything else is checked against
+    // the session's role.
+    let path = req.uri().path();
+    let login = req.method() == Method::POST && path == SESSION_PATH;
+    if path != SESSION_PATH && !role_allows(verified.role, req.method(), path) {
+        return forbidden(&format!(
+            "The {} role can't access this endpoint",
+            verified.role.as_str()
+        ));
+    }
+    if is_mutating(req.method()) && !login {
+        let sent = req.headers().get(CSRF_HEADER).and_then(|v| v.to_str().ok());
+        match sent {
+            Some(token) if ct_eq(token, &csrf_token(&secret, &session)) => {}
+            Some(_) => return forbidden("Invalid CSRF token"),
+            None => return forbidden("Missing X-CSRF-Token header"),
+        }
+    }
+
+    // Handlers behind the shared `require_auth` only understand bearer tokens.
+    if let Ok(v) = HeaderValue::from_str(&format!("Bearer {}", secret)) {
+        req.headers_mut().insert(header::AUTHORIZATION, v);
+    }
+    req.extensions_mut().insert(verified);
+    next.run(req).await
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  /api/auth/session
+// ═══════════════════════════════════════════════════════════════════════
+
+#[derive(Debug, Deserialize)]
+pub struct CreateSessionRequest {
+    /// The deployment's AUTH_SECRET.
+    pub token: String,
+}
+
+fn set_cookie(name: &str, value: &str, max_age: i64, http_only: bool) -> (header::HeaderName, String) {
+    let mut cookie = format!(
+        "{}={}; Path=/; Max-Age={}; SameSite=Strict",
+        name, value, max_age
+    );
+    if http_only {
+        cookie.push_str("; HttpOnly");
+    }
+    if secure_cookies() {
+        cookie.push_str("; Secure");
+    }
+    (header::SET_COOKIE, cookie)
+}
+
+/// Session + CSRF `Set-Cookie` headers and the JSON body describing them.
+pub(crate) fn start_session(
+    secret: &str,
+    subject: String,
+    role: Role,
+) -> ([(header::HeaderName, String); 2], serde_json::Value) {
+    let max_age = ttl_hours() * 3600;
+    let session = Session {
+        expires_at: chrono::Utc::now().timestamp() + max_age,
+        subject,
+        role,
+    };
+    let value = issue(secret, &session);
+    let csrf = csrf_token(secret, &value);
+    let cookies = [
+        set_cookie(SESSION_COOKIE, &value, max_age, true),
+        set_cookie(CSRF_COOKIE, &csrf, max_age, false),
+    ];
+    (cookies, session_json(&session, &csrf))
+}
+
+fn session_json(session: &Session, csrf: &str) -> serde_json::Value {
+    json!({
+        "authenticated": true,
+        "role": session.role,
+        "subject": session.subject,
+        "csrf_token": csrf,
+        "expires_at": chrono::DateTime::from_timestamp(session.expires_at, 0),
+    })
+}
+
+/// `POST /api/auth/session` — exchange AUTH_SECRET for session + CSRF cookies
+pub async fn create_session(
+    State(state): State<AppState>,
+    Json(req): Json<CreateSessionRequest>,
+) -> Response {
+    let Some(secret) = state.auth_secret.as_deref() else {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "Authentication is disabled (AUTH_SECRET not set)" })),
+        )
+            .into_response();
+    };
+    if !ct_eq(req.token.trim(), secret) {
+        tracing::warn!("web_session: rejected sign-in with invalid token");
+        return (StatusCode::UNAUTHORIZED, Json(json!({ "error": "Invalid token" })))
+            .into_response();
+    }
+
+    let nonce = uuid::Uuid::new_v4().simple().to_string();
+    let (cookies, body) = start_session(secret, nonce, Role::Admin);
+    (cookies, Json(body)).into_response()
+}
+
+/// `GET /api/auth/session` — whether the session cookie is valid, plus its CSRF token
+pub async fn session_status(State(state): State<AppState>, headers: HeaderMap) -> Json<serde_json::Value> {
+    let secret = state.auth_secret.as_deref();
+    let session = cookie(&headers, SESSION_COOKIE);
+    match (secret, session) {
+        (Some(secret), Some(value)) => {
+            let session = match verify(secret, value) {
+                Some(session) => revalidate(&state.db, session).await.ok().flatten(),
+                None => None,
+            };
+            match session {
+                Some(session) => Json(session_json(&session, &csrf_token(secret, value))),
+                None => Json(json!({ "authenticated": false, "oidc": crate::oidc::enabled() })),
+            }
+        }
+        (None, _) => Json(json!({ "authenticated": true, "auth_required": false })),
+        (Some(_), None) => Json(json!({ "authenticated": false, "oidc": crate::oidc::enabled() })),
+    }
+}
+
+/// `DELETE /api/auth/session` — sign out (clears both cookies)
+pub async fn delete_session() -> Response {
+    (
+        [
+            set_cookie(SESSION_COOKIE, "", 0, true),
+            set_cookie(CSRF_COOKIE, "", 0, false),
+        ],
+        StatusCode::NO_CONTENT,
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(expires_in: i64, role: Role) -> Session {
+        Session {
+            expires_at: chrono::Utc::now().timestamp() + expires_in,
+            subject: "nonce".into(),
+            role,
+        }
+    }
+
+    #[test]
+    fn sessions_are_signed_and_expire() {
+        let valid = session(60, Role::Member);
+        let value = issue("secret", &valid);
+        assert_eq!(verify("secret", &value), Some(valid));
+        assert_eq!(verify("other-secret", &value), None);
+
+        let tampered = value.replacen(".member.", ".admin.", 1);
+        assert_eq!(verify("secret", &tampered), None);
+
+        let expired = issue("secret", &session(-1, Role::Admin));
+        assert_eq!(verify("secret", &expired), None);
+    }
+
+    #[test]
+    fn roles_limit_reach() {
+        assert!(role_allows(Role::Admin, &Method::POST, "/api/admin/read-only"));
+        assert!(!role_allows(Role::Member, &Method::GET, "/api/admin/read-only"));
+        assert!(!role_allows(Role::Member, &Method::GET, "/api/debug/pprof/cpu"));
+        assert!(role_allows(Role::Member, &Method::POST, "/api/sessions"));
+        assert!(role_allows(Role::Viewer, &Method::GET, "/api/sessions"));
+        assert!(!role_allows(Role::Viewer, &Method::POST, "/api/sessions"));
+        assert!(!role_allows(Role::Viewer, &Method::GET, "/ws/chat"));
+        assert!(!role_allows(Role::Viewer, &Method::GET, "/api/ws"));
+        assert!(role_allows(Role::Member, &Method::GET, "/api/ws"));
+    }
+
+    #[test]
+    fn admin_only_endpoints_are_kept_from_members() {
+        let member = |method: Method, path: &str| role_allows(Role::Member, &method, path);
+        assert!(!member(Method::POST, "/api/settings/api-key"));
+        assert!(!member(Method::DELETE, "/api/settings/api-key/anthropic"));
+        assert!(!member(Method::GET, "/api/settings/api-keys"));
+        assert!(!member(Method::PUT, "/api/settings/key-environments"));
+        assert!(!member(Method::PUT, "/api/usage/prices/claude-opus-*"));
+        assert!(!member(Method::PUT, "/api/agents/tiers"));
+        assert!(!member(Method::PUT, "/api/integrations/slack"));
+        assert!(!member(Method::GET, "/api/integrations/github/repos"));
+        assert!(!member(Method::GET, "/api/tokens"));
+        // Reading prices and tiers stays open; token counting is no token endpoint
+        assert!(member(Method::GET, "/api/usage/prices"));
+        assert!(member(Method::GET, "/api/agents/tiers"));
+        assert!(member(Method::POST, "/api/token-count"));
+        assert!(member(Method::GET, "/api/settings/policies"));
+    }
+
+    #[test]
+    fn cookies_are_parsed_from_the_header() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::COOKIE,
+            HeaderValue::from_static("theme=dark; ch_session=abc.def; ch_csrf=x"),
+        );
+        assert_eq!(cookie(&headers, SESSION_COOKIE), Some("abc.def"));
+        assert_eq!(cookie(&headers, "missing"), None);
+    }
+}
@@ -0,0 +1,323 @@
+// ClaudeHydra v4 -- Signed outbound webhooks
+// Subscribers registered at `/api/admin/webhooks` receive JSON event POSTs
+// (`usage.anomaly`, `health.probe_failed`, `agent.run_completed`,
+// `webhook.ping`; `*` = everything). Slack channels can follow the same
+// events (see slack.rs).
+// Every delivery is signed with the subscriber's own secret (`whsec_…`,
+// shown once at creation) so receivers can verify it came from this instance:
+//
+//   X-ClaudeHydra-Event:      event name
+//   X-ClaudeHydra-Delivery:   unique delivery id (dedupe retries on it)
+//   X-ClaudeHydra-Timestamp:  unix seconds when the delivery was signed
+//   X-ClaudeHydra-Signature:  v1=<hex HMAC-SHA256(secret, "<timestamp>.<raw body>")>
+//
+// Receivers recompute the HMAC over the timestamp, a dot and the raw request
+// body, compare in constant time, and reject timestamps more than
+// WEBHOOK_REPLAY_WINDOW_SECS (300) away from their clock — see `verify`, and
+// "Outbound Webhooks" in docs/API.md for a receiver example. Inbound webhooks
+// (`/api/webhooks/*`) are unrelated.
+
+use std::time::Duration;
+
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::crypto::{ct_eq, hmac_sha256};
+use crate::state::AppState;
+
+pub const SECRET_PREFIX: &str = "whsec_";
+pub const SIGNATURE_VERSION: &str = "v1";
+/// Receivers should reject deliveries signed longer ago than this.
+pub const WEBHOOK_REPLAY_WINDOW_SECS: i64 = 300;
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Events a subscription can ask for.
+pub const EVENTS: &[&str] = &[
+    "usage.anomaly",
+    "health.probe_failed",
+    "agent.run_completed",
+    "webhook.ping",
+];
+
+/// `v1=<hex>` signature of a payload signed at `timestamp`.
+pub fn sign(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut msg = format!("{}.", timestamp).into_bytes();
+    msg.extend_from_slice(body);
+    format!("{}={}", SIGNATURE_VERSION, hmac_sha256(secret.as_bytes(), &msg))
+}
+
+/// Receiver-side check: signature matches and the timestamp is inside the
+/// replay window around `now`.
+pub fn verify(secret: &str, timestamp: i64, body: &[u8], signature: &str, now: i64) -> bool {
+    (now - timestamp).abs() <= WEBHOOK_REPLAY_WINDOW_SECS && ct_eq(signature, &sign(secret, timestamp, body))
+}
+
+fn generate_secret() -> String {
+    format!(
+        "{}{}{}",
+        SECRET_PREFIX,
+        uuid::Uuid::new_v4().simple(),
+        uuid::Uuid::new_v4().simple()
+    )
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+struct Target {
+    id: uuid::Uuid,
+    url: String,
+    secret: String,
+}
+
+/// Deliver `event` to every enabled subscriber (and Slack channel following
+/// it) in the background, and store it in the event history. Nothing is
+/// delivered while the `outbound_webhooks` subsystem is paused.
+pub fn dispatch(state: &AppState, event: &'static str, data: Value) {
+    crate::event_history::record(&state.db, event.to_string(), data.clone());
+    if state.subsystems.is_paused(crate::subsystems::OUTBOUND_WEBHOOKS) {
+        tracing::debug!("webhooks: delivery paused, skipping {}", event);
+        return;
+    }
+    let state = state.clone();
+    tokio::spawn(async move {
+        crate::slack::notify(&state, event, &data).await;
+        let targets = sqlx::query_as::<_, Target>(
+            "SELECT id, url, secret FROM ch_outbound_webhooks \
+             WHERE enabled AND (events @> ARRAY[$1] OR events @> ARRAY['*'])",
+        )
+        .bind(event)
+        .fetch_all(&state.db)
+        .await;
+        match targets {
+            Ok(targets) => {
+                for target in targets {
+                    let _ = deliver(&state, &target, event, &data).await;
+                }
+            }
+            Err(e) => tracing::warn!("webhooks: failed to load subscribers: {}", e),
+        }
+    });
+}
+
+/// Sign and POST one delivery; the outcome is stored on the subscription.
+async fn deliver(state: &AppState, target: &Target, event: &str, data: &Value) -> Result<u16, String> {
+    let delivery_id = uuid::Uuid::new_v4();
+    let body = json!({
+        "id": delivery_id,
+        "event": event,
+        "created_at": chrono::Utc::now(),
+        "instance": crate::cluster::instance_id(),
+        "data": data,
+    })
+    .to_string();
+    let timestamp = chrono::Utc::now().timestamp();
+
+    let result = state
+        .http_client
+        .post(&target.url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header("X-ClaudeHydra-Event", event)
+        .header("X-ClaudeHydra-Delivery", delivery_id.to_string())
+        .header("X-ClaudeHydra-Timestamp", timestamp.to_string())
+        .header("X-ClaudeHydra-Signature", sign(&target.secret, timestamp, body.as_bytes()))
+        .body(body)
+        .timeout(DELIVERY_TIMEOUT)
+        .send()
+        .await;
+    let outcome = match result {
+        Ok(resp) if resp.status().is_success() => Ok(resp.status().as_u16()),
+        Ok(resp) => Err(format!("HTTP {}", resp.status().as_u16())),
+        Err(e) => Err(e.to_string()),
+    };
+    if let Err(ref e) = outcome {
+        tracing::warn!("webhooks: delivery {} of {} to {} failed: {}", delivery_id, event, target.id, e);
+    }
+
+    let _ = sqlx::query(
+        "UPDATE ch_outbound_webhooks SET last_delivery_at = NOW(), last_status = $2 WHERE id = $1",
+    )
+    .bind(target.id)
+    .bind(match &outcome {
+        Ok(status) => status.to_string(),
+        Err(e) => e.chars().take(200).collect(),
+    })
+    .execute(&state.db)
+    .await;
+    outcome
+}
+
+// ═══════════════════════════════════════════════════════════════════════
+//  /api/admin/webhooks
+// ═══════════════════════════════════════════════════════════════════════
+
+#[derive(Debug, Deserialize)]
+pub struct CreateWebhookRequest {
+    pub url: String,
+    /// Event names, or `["*"]` for all.
+    pub events: Vec<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct WebhookRow {
+    pub id: uuid::Uuid,
+    pub url: String,
+    pub events: Vec<String>,
+    pub description: Option<String>,
+    pub enabled: bool,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub last_delivery_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub last_status: Option<String>,
+}
+
+const WEBHOOK_COLUMNS: &str =
+    "id, url, events, description, enabled, created_at, last_delivery_at, last_status";
+
+fn bad_request(msg: impl Into<String>) -> (StatusCode, Json<Value>) {
+    (StatusCode::BAD_REQUEST, Json(json!({ "error": msg.into() })))
+}
+
+fn db_error(e: sqlx::Error) -> (StatusCode, Json<Value>) {
+    tracing::error!("webhooks: query failed: {}", e);
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(json!({ "error": "Webhook storage unavailable" })),
+    )
+}
+
+/// Validate a create request; returns the normalized URL and events.
+fn validate(req: &CreateWebhookRequest) -> Result<(String, Vec<String>), String> {
+    let url = url::Url::parse(req.url.trim()).map_err(|_| "url must be an absolute URL".to_string())?;
+    if !matches!(url.scheme(), "https" | "http") {
+        return Err("url must use http or https".into());
+    }
+    if req.events.is_empty() {
+        return Err(format!("at least one event is required ({} or *)", EVENTS.join(", ")));
+    }
+    let mut events: Vec<String> = Vec::new();
+    for e in &req.events {
+        if e != "*" && !EVENTS.contains(&e.as_str()) {
+            return Err(format!("unknown event '{}'", e));
+        }
+        if !events.contains(e) {
+            events.push(e.clone());
+        }
+    }
+    Ok((url.to_string(), events))
+}
+
+/// `GET /api/admin/webhooks` — list outbound webhook subscriptions (never the secrets)
+pub async fn list_webhooks(State(state): State<AppState>) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let rows = sqlx::query_as::<_, WebhookRow>(&format!(
+        "SELECT {} FROM ch_outbound_webhooks ORDER BY created_at DESC",
+        WEBHOOK_COLUMNS
+    ))
+    .fetch_all(&state.db)
+    .await
+    .map_err(db_error)?;
+    Ok(Json(json!({ "webhooks": rows, "events": EVENTS })))
+}
+
+/// `POST /api/admin/webhooks` — subscribe a URL; the signing secret is only in this response
+pub async fn create_webhook(
+    State(state): State<AppState>,
+    Json(req): Json<CreateWebhookRequest>,
+) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)> {
+    let (url, events) = validate(&req).map_err(bad_request)?;
+    let secret = generate_secret();
+    let row = sqlx::query_as::<_, WebhookRow>(&format!(
+        "INSERT INTO ch_outbound_webhooks (url, events, description, secret) \
+         VALUES ($1, $2, $3, $4) RETURNING {}",
+        WEBHOOK_COLUMNS
+    ))
+    .bind(&url)
+    .bind(&events)
+    .bind(req.description.as_deref().map(str::trim).filter(|d| !d.is_empty()))
+    .bind(&secret)
+    .fetch_one(&state.db)
+    .await
+    .map_err(db_error)?;
+
+    tracing::info!("webhooks: subscription {} created for {:?}", row.id, row.events);
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({
+            "secret": secret,
+            "warning": "Store this secret now — it cannot be shown again",
+            "details": row,
+        })),
+    ))
+}
+
+/// `DELETE /api/admin/webhooks/{id}` — remove a subscription
+pub async fn delete_webhook(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, (StatusCode, Json<Value>)> {
+    let id: uuid::Uuid = id.parse().map_err(|_| bad_request("Invalid webhook id"))?;
+    let result = sqlx::query("DELETE FROM ch_outbound_webhooks WHERE id = $1")
+        .bind(id)
+        .execute(&state.db)
+        .await
+        .map_err(db_error)?;
+    if result.rows_affected() == 0 {
+        return Err((StatusCode::NOT_FOUND, Json(json!({ "error": "Webhook not found" }))));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// `POST /api/admin/webhooks/{id}/test` — send a signed `webhook.ping` and report the result
+pub async fn test_webhook(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let id: uuid::Uuid = id.parse().map_err(|_| bad_request("Invalid webhook id"))?;
+    let target = sqlx::query_as::<_, Target>("SELECT id, url, secret FROM ch_outbound_webhooks WHERE id = $1")
+        .bind(id)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, Json(json!({ "error": "Webhook not found" }))))?;
+
+    let outcome = deliver(&state, &target, "webhook.ping", &json!({ "message": "ping" })).await;
+    Ok(Json(match outcome {
+        Ok(status) => json!({ "delivered": true, "status": status }),
+        Err(error) => json!({ "delivered": false, "error": error }),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signatures_verify_within_the_replay_window() {
+        let body = br#"{"event":"webhook.ping"}"#;
+        let sig = sign("whsec_test", 1_700_000_000, body);
+        assert!(sig.starts_with("v1="));
+        assert!(verify("whsec_test", 1_700_000_000, body, &sig, 1_700_000_100));
+        // Tampered body, wrong secret, replayed too late
+        assert!(!verify("whsec_test", 1_700_000_000, b"{}", &sig, 1_700_000_100));
+        assert!(!verify("whsec_other", 1_700_000_000, body, &sig, 1_700_000_100));
+        assert!(!verify("whsec_test", 1_700_000_000, body, &sig, 1_700_000_000 + WEBHOOK_REPLAY_WINDOW_SECS + 1));
+    }
+
+    #[test]
+    fn subscriptions_are_validated() {
+        let req = |url: &str, events: &[&str]| CreateWebhookRequest {
+            url: url.into(),
+            events: events.iter().map(|e| e.to_string()).collect(),
+            description: None,
+        };
+        let (url, events) = validate(&req("https://hooks.example.com/ch", &["usage.anomaly", "usage.anomaly"])).unwrap();
+        assert_eq!(url, "https://hooks.example.com/ch");
+        assert_eq!(events, vec!["usage.anomaly"]);
+        assert!(validate(&req("ftp://example.com", &["*"])).is_err());
+        assert!(validate(&req("not a url", &["*"])).is_err());
+        assert!(validate(&req("https://example.com", &[])).is_err());
+        assert!(validate(&req("https://example.com", &["chat.done"])).is_err());
+    }
+}
@@ -0,0 +1,287 @@
+// ClaudeHydra v4 -- Multiplexed chat WebSocket
+// `GET /api/ws` carries any number of concurrent chat requests over one
+// connection. Messages about a request carry the client-chosen correlation
+// `id`, so frames of different streams can interleave freely.
+//
+// Client → server:
+//   {"type":"chat","id","request":<ChatRequest>}   start a stream
+//   {"type":"cancel","id"}                          abort it
+//   {"type":"ping"}
+// Server → client:
+//   {"type":"started","id","stream_id"}      streaming; `stream_id` is resumable over HTTP
+//   {"type":"frame","id","frame":{…}}         one NDJSON frame of the stream, as-is
+//   {"type":"done","id"}                      the stream ended (after its final frame)
+//   {"type":"error","id","status","error"}    rejected or failed before streaming
+//   {"type":"pong"}
+//
+// Requests run through the same handler as `POST /api/claude/chat/stream`
+// (`gemini-*` models included), detached by stream_relay, so a dropped socket
+// doesn't abort them; `cancel` works like `POST …/stream/{id}/cancel`.
+// Auth: `?token=<secret>` or the web UI session cookie.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use axum::Json;
+use axum::extract::ws::{Message as WsMessage, WebSocket, WebSocketUpgrade};
+use axum::extract::{Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use futures_util::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tokio::sync::mpsc;
+use tokio::task::AbortHandle;
+
+use crate::models::ChatRequest;
+use crate::state::AppState;
+use crate::stream_relay::STREAM_ID_HEADER;
+
+/// Concurrent streams one connection may run.
+const MAX_STREAMS_PER_CONNECTION: usize = 8;
+const MAX_ID_CHARS: usize = 128;
+const OUTBOX_CAPACITY: usize = 256;
+
+#[derive(Debug, Deserialize)]
+pub struct WsQuery {
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+    Chat { id: String, request: ChatRequest },
+    Cancel { id: String },
+    Ping,
+}
+
+/// A request in flight on this connection.
+struct InFlight {
+    /// Set once the stream is running.
+    stream_id: Option<String>,
+    task: AbortHandle,
+}
+
+type Requests = Arc<Mutex<HashMap<String, InFlight>>>;
+
+/// WebSocket upgrades that start chat generations (this socket and the
+/// single-stream `/ws/chat`). They are GET requests, so method-based checks
+/// (read-only mode, viewer sessions, `read` tokens) have to name them.
+pub fn is_chat_socket(path: &str) -> bool {
+    path == "/api/ws" || path == "/ws/chat"
+}
+
+fn error_message(id: &str, status: StatusCode, error: &str) -> String {
+    json!({ "type": "error", "id": id, "status": status.as_u16(), "error": error }).to_string()
+}
+
+/// `GET /api/ws` — multiplexed chat streams over one WebSocket
+pub async fn ws_handler(
+    ws: WebSocketUpgrade,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(q): Query<WsQuery>,
+) -> Response {
+    let secret = state.auth_secret.as_deref();
+    let query_token = q.token.as_deref().map(|t| format!("token={}", t)).unwrap_or_default();
+    if !jaskier_core::auth::validate_ws_token(&query_token, secret)
+        && !crate::web_session::has_valid_session(&headers, secret)
+    {
+        return (StatusCode::UNAUTHORIZED, "Invalid or missing auth token").into_response();
+    }
+    let principal = crate::principal::requested();
+    ws.on_upgrade(move |socket| crate::principal::scope(principal, handle_socket(socket, state)))
+}
+
+async fn handle_socket(socket: WebSocket, state: AppState) {
+    let (mut sender, mut receiver) = socket.split();
+    // Request tasks write here; this loop is the only writer to the socket.
+    let (out_tx, mut out_rx) = mpsc::channel::<String>(OUTBOX_CAPACITY);
+    let requests: Requests = Arc::new(Mutex::new(HashMap::new()));
+
+    loop {
+        tokio::select! {
+            Some(text) = out_rx.recv() => {
+                if sender.send(WsMessage::Text(text.into())).await.is_err() {
+                    break;
+                }
+            }
+            msg = receiver.next() => match msg {
+                Some(Ok(WsMessage::Text(text))) => {
+                    let reply = match serde_json::from_str::<ClientMessage>(&text) {
+                        Ok(ClientMessage::Chat { id, request }) => {
+                            start(&state, &requests, &out_tx, id, request)
+                        }
+                        Ok(ClientMessage::Cancel { id }) => cancel(&state, &requests, &id),
+                        Ok(ClientMessage::Ping) => Some(json!({ "type": "pong" }).to_string()),
+                        Err(e) => Some(
+                            json!({ "type": "error", "status": 400, "error": format!("Invalid message: {}", e) })
+                                .to_string(),
+                        ),
+                    };
+                    if let Some(reply) = reply
+                        && sender.send(WsMessage::Text(reply.into())).await.is_err()
+                    {
+                        break;
+                    }
+                }
+                Some(Ok(WsMessage::Close(_))) | None | Some(Err(_)) => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+
+    // Streams keep running (and stay resumable); only stop forwarding them.
+    if let Ok(mut requests) = requests.lock() {
+        for (_, r) in requests.drain() {
+            r.task.abort();
+        }
+    }
+}
+
+/// Start a chat request; returns an immediate reply when it is rejected.
+fn start(
+    state: &AppState,
+    requests: &Requests,
+    out: &mpsc::Sender<String>,
+    id: String,
+    request: ChatRequest,
+) -> Option<String> {
+    if id.is_empty() || id.chars().count() > MAX_ID_CHARS {
+        return Some(error_message(&id, StatusCode::BAD_REQUEST, "id must be 1-128 characters"));
+    }
+    let Ok(mut map) = requests.lock() else {
+        return Some(error_message(&id, StatusCode::INTERNAL_SERVER_ERROR, "Connection state unavailable"));
+    };
+    if map.contains_key(&id) {
+        return Some(error_message(&id, StatusCode::CONFLICT, "A request with this id is already running"));
+    }
+    if map.len() >= MAX_STREAMS_PER_CONNECTION {
+        return Some(error_message(
+            &id,
+            StatusCode::TOO_MANY_REQUESTS,
+            &format!("At most {} concurrent streams per connection", MAX_STREAMS_PER_CONNECTION),
+        ));
+    }
+
+    let task = tokio::spawn(crate::principal::scope(
+        crate::principal::requested(),
+        forward(state.clone(), requests.clone(), out.clone(), id.clone(), request),
+    ));
+    map.insert(
+        id,
+        InFlight {
+            stream_id: None,
+            task: task.abort_handle(),
+        },
+    );
+    None
+}
+
+/// Run one request and forward its NDJSON frames to the socket.
+async fn forward(state: AppState, requests: Requests, out: mpsc::Sender<String>, id: String, request: ChatRequest) {
+    let finish = |msg: String| {
+        if let Ok(mut map) = requests.lock() {
+            map.remove(&id);
+        }
+        msg
+    };
+
+    let response = match crate::handlers::claude_chat_stream(State(state.clone()), None, Json(request)).await {
+        Ok(r) if r.status().is_success() => r,
+        Ok(r) => {
+            let status = r.status();
+            let body = axum::body::to_bytes(r.into_body(), 64 * 1024).await.unwrap_or_default();
+            let error = serde_json::from_slice::<Value>(&body)
+                .ok()
+                .and_then(|v| v.get("error").and_then(|e| e.as_str()).map(str::to_string))
+                .unwrap_or_else(|| "Request failed".to_string());
+            let _ = out.send(finish(error_message(&id, status, &error))).await;
+            return;
+        }
+        Err((status, Json(err))) => {
+            let error = err.get("error").and_then(|e| e.as_str()).unwrap_or("Request failed");
+            let _ = out.send(finish(error_message(&id, status, error))).await;
+            return;
+        }
+    };
+
+    let stream_id = response
+        .headers()
+        .get(STREAM_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    if let Ok(mut map) = requests.lock()
+        && let Some(r) = map.get_mut(&id)
+    {
+        r.stream_id = stream_id.clone();
+    }
+    let started = json!({ "type": "started", "id": &id, "stream_id": stream_id });
+    if out.send(started.to_string()).await.is_err() {
+        return;
+    }
+
+    let mut body = response.into_body().into_data_stream();
+    let mut partial: Vec<u8> = Vec::new();
+    while let Some(Ok(chunk)) = body.next().await {
+        partial.extend_from_slice(&chunk);
+        while let Some(nl) = partial.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = partial.drain(..=nl).collect();
+            let Ok(frame) = serde_json::from_slice::<Value>(line.trim_ascii()) else {
+                continue;
+            };
+            let msg = json!({ "type": "frame", "id": &id, "frame": frame });
+            if out.send(msg.to_string()).await.is_err() {
+                return;
+            }
+        }
+    }
+    let _ = out.send(finish(json!({ "type": "done", "id": &id }).to_string())).await;
+}
+
+/// Cancel a request. A running stream gets its final frame through the relay;
+/// one still waiting to start is dropped and reported done right away.
+fn cancel(state: &AppState, requests: &Requests, id: &str) -> Option<String> {
+    let Ok(mut map) = requests.lock() else {
+        return None;
+    };
+    let Some(request) = map.get(id) else {
+        return Some(error_message(id, StatusCode::NOT_FOUND, "No running request with this id"));
+    };
+    match request.stream_id.clone() {
+        Some(stream_id) => {
+            state.streams.cancel(&stream_id);
+            None
+        }
+        None => {
+            if let Some(r) = map.remove(id) {
+                r.task.abort();
+            }
+            Some(json!({ "type": "done", "id": id, "cancelled": true }).to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn client_messages_carry_correlation_ids() {
+        let chat: ClientMessage = serde_json::from_value(json!({
+            "type": "chat",
+            "id": "r1",
+            "request": { "messages": [{ "role": "user", "content": "hi" }], "priority": "high" },
+        }))
+        .unwrap();
+        let ClientMessage::Chat { id, request } = chat else {
+            panic!("expected chat");
+        };
+        assert_eq!(id, "r1");
+        assert_eq!(request.priority, Some(crate::priority::Priority::High));
+
+        let cancel: ClientMessage = serde_json::from_value(json!({ "type": "cancel", "id": "r1" })).unwrap();
+        assert!(matches!(cancel, ClientMessage::Cancel { id } if id == "r1"));
+        assert!(serde_json::from_value::<ClientMessage>(json!({ "type": "cancel" })).is_err());
+    }
+}
@@ -8,6 +8,20 @@ import { toast } from 'sonner';
 import { apiDelete, apiGet, apiPatch, apiPost } from '@/shared/api/client';
 import type { Session, SessionSummary, SessionsList } from '@/shared/api/schemas';
 
+/** Structured content of a stored message (mirrors backend MessagePart) */
+export type MessagePart =
+  | { type: 'text'; text: string }
+  | {
+      type: 'image';
+      source:
+        | { type: 'base64'; media_type: string; data: string }
+        | { type: 'url'; url: string }
+        | { type: 'upload'; upload_id: string };
+    }
+  | { type: 'tool_call'; id: string; name: string; input: Record<string, unknown> }
+  | { type: 'tool_result'; tool_use_id: string; content: string; is_error: boolean }
+  | { type: 'thinking'; thinking: string; signature?: string };
+
 /** Shape returned by GET /api/sessions/:id */
 export interface SessionDetail {
   id: string;
@@ -18,6 +32,8 @@ export interface SessionDetail {
     id: string;
     role: string;
     content: string;
+    /** Present for messages stored with structured parts */
+    parts?: MessagePart[];
     model?: string | null;
     agent?: string | null;
     timestamp: string;
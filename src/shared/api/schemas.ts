@@ -51,6 +51,9 @@ export const agentSchema = z.object({
   status: z.string(),
   description: z.string(),
   model: z.string().optional(),
+  /** A request generated as the agent is in flight. */
+  busy: z.boolean().optional(),
+  in_flight: z.number().optional(),
 });
 
 export type Agent = z.infer<typeof agentSchema>;
@@ -115,6 +118,44 @@ export const settingsSchema = z.object({
   compaction_threshold: z.number().optional().default(25),
   /** Message compaction keep — keep this many recent messages after compaction */
   compaction_keep: z.number().optional().default(15),
+  /** Retry replies that read like a refusal once, asking for the answerable part */
+  refusal_retry: z.boolean().optional().default(false),
+  /** Continue replies cut off at max_tokens this many times (0 = off) */
+  max_continuations: z.number().optional().default(2),
+  /** System prompt layer order, separator and total length limit */
+  prompt_layering: z
+    .object({
+      order: z.array(z.enum(['global', 'project', 'agent', 'request'])),
+      separator: z.string(),
+      max_chars: z.number(),
+    })
+    .optional(),
+  /** Provider call timeouts and connection pooling (unset = server env / defaults) */
+  http: z
+    .object({
+      request_timeout_secs: z.number().nullish(),
+      stream_timeout_secs: z.number().nullish(),
+      connect_timeout_secs: z.number().nullish(),
+      pool_max_idle_per_host: z.number().nullish(),
+      pool_idle_timeout_secs: z.number().nullish(),
+      tcp_keepalive_secs: z.number().nullish(),
+      proxy_url: z.string().nullish(),
+    })
+    .optional(),
+  /** Timeout and retries per endpoint group (unset = defaults) */
+  policies: z
+    .record(
+      z.enum(['chat', 'stream', 'models', 'batch', 'tools']),
+      z.object({
+        timeout_secs: z.number().nullish(),
+        max_attempts: z.number().nullish(),
+        base_delay_ms: z.number().nullish(),
+        max_delay_ms: z.number().nullish(),
+      }),
+    )
+    .optional(),
+  /** Model per agent tier (read-only here — set with PUT /api/agents/tiers) */
+  tier_models: z.record(z.enum(['Commander', 'Coordinator', 'Executor']), z.string()).optional(),
 });
 
 export type Settings = z.infer<typeof settingsSchema>;
@@ -171,6 +212,8 @@ export const sessionSummarySchema = z.object({
   message_count: z.number(),
   preview: z.string().optional(),
   working_directory: z.string().optional(),
+  pinned: z.boolean().optional(),
+  archived: z.boolean().optional(),
 });
 
 export type SessionSummary = z.infer<typeof sessionSummarySchema>;
@@ -186,6 +229,10 @@ export const sessionSchema = z.object({
   updated_at: z.string(),
   message_count: z.number(),
   messages: z.array(messageSchema),
+  messages_truncated: z.boolean().optional(),
+  tags: z.array(z.string()).optional(),
+  pinned: z.boolean().optional(),
+  archived: z.boolean().optional(),
 });
 
 export type Session = z.infer<typeof sessionSchema>;